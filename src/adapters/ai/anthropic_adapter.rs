@@ -0,0 +1,282 @@
+//! Anthropic (Claude) adapter for AI analysis.
+//!
+//! Talks to the Messages API: the system prompt is a top-level field, the reply is an array of
+//! content blocks, and auth uses `x-api-key` plus `anthropic-version` headers. Prompts, JSON
+//! post-processing and retries are shared with the OpenAI adapter.
+
+use super::{PromptTemplates, llm};
+use crate::domain::{AiReply, AiUsage, AnalysisResult, DomainError, WeekGroup};
+use crate::ports::AiPort;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::info;
+
+/// Messages API version sent in the `anthropic-version` header.
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Output token limit per request (required by the Messages API).
+const MAX_TOKENS: u32 = 4096;
+
+/// Anthropic Messages API adapter.
+pub struct AnthropicAdapter {
+    client: reqwest::Client,
+    api_url: String,
+    api_key: String,
+    model: String,
+    /// Retries per request after the first attempt.
+    max_retries: u32,
+    base_backoff: Duration,
+    prompts: PromptTemplates,
+}
+
+impl AnthropicAdapter {
+    /// Create a new Anthropic adapter.
+    ///
+    /// # Arguments
+    /// * `api_url` - API endpoint (e.g., "https://api.anthropic.com/v1/messages")
+    /// * `api_key` - Anthropic API key
+    /// * `model` - Model name (e.g., "claude-3-5-haiku-latest")
+    /// * `max_retries` - Retries per request on 429, 5xx and timeouts (TG_SYNC_AI_MAX_RETRIES)
+    /// * `prompts` - Analysis and summarization prompts (validated when loaded)
+    pub fn new(
+        api_url: String,
+        api_key: String,
+        model: String,
+        max_retries: u32,
+        prompts: PromptTemplates,
+    ) -> Self {
+        Self {
+            client: llm::http_client(None),
+            api_url,
+            api_key,
+            model,
+            max_retries,
+            base_backoff: llm::BASE_BACKOFF,
+            prompts,
+        }
+    }
+
+    /// Send requests through `proxy` (TG_SYNC_HTTP_PROXY).
+    pub fn with_proxy(mut self, proxy: reqwest::Proxy) -> Self {
+        self.client = llm::http_client(Some(proxy));
+        self
+    }
+
+    /// POST a Messages request and return the concatenated text blocks of the reply.
+    async fn send_messages(
+        &self,
+        request: &MessagesRequest,
+    ) -> Result<AiReply<String>, DomainError> {
+        let response: MessagesResponse = llm::send_with_retry(
+            || {
+                self.client
+                    .post(&self.api_url)
+                    .header("x-api-key", &self.api_key)
+                    .header("anthropic-version", ANTHROPIC_VERSION)
+                    .header("Content-Type", "application/json")
+                    .json(request)
+            },
+            self.max_retries,
+            self.base_backoff,
+        )
+        .await?;
+        let usage = response.usage.as_ref().map(|u| AiUsage {
+            model: self.model.clone(),
+            prompt_tokens: u.input_tokens,
+            completion_tokens: u.output_tokens,
+        });
+        Ok(AiReply {
+            value: response.text()?,
+            usage,
+        })
+    }
+}
+
+/// Messages API request structure.
+#[derive(Serialize)]
+struct MessagesRequest {
+    model: String,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    messages: Vec<InputMessage>,
+    temperature: f32,
+}
+
+#[derive(Serialize)]
+struct InputMessage {
+    role: String,
+    content: String,
+}
+
+/// Messages API response structure (only the parts we read).
+#[derive(Deserialize)]
+struct MessagesResponse {
+    content: Vec<ContentBlock>,
+    #[serde(default)]
+    usage: Option<TokenUsage>,
+}
+
+#[derive(Deserialize)]
+struct TokenUsage {
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
+}
+
+#[derive(Deserialize)]
+struct ContentBlock {
+    #[serde(rename = "type")]
+    block_type: String,
+    #[serde(default)]
+    text: String,
+}
+
+impl MessagesResponse {
+    /// Text of all `text` blocks, in order. Errors if the reply has none.
+    fn text(self) -> Result<String, DomainError> {
+        let text: String = self
+            .content
+            .into_iter()
+            .filter(|b| b.block_type == "text")
+            .map(|b| b.text)
+            .collect();
+        if text.trim().is_empty() {
+            return Err(DomainError::Ai("No text content returned".to_string()));
+        }
+        Ok(text)
+    }
+}
+
+#[async_trait::async_trait]
+impl AiPort for AnthropicAdapter {
+    async fn analyze(
+        &self,
+        chat_id: i64,
+        week_group: &WeekGroup,
+        context_csv: &str,
+    ) -> Result<AiReply<AnalysisResult>, DomainError> {
+        info!(
+            chat_id,
+            week = %week_group,
+            csv_len = context_csv.len(),
+            "sending context to AI for analysis"
+        );
+
+        let (system, user) = self.prompts.analysis(context_csv);
+        let request = MessagesRequest {
+            model: self.model.clone(),
+            max_tokens: MAX_TOKENS,
+            system: Some(system),
+            messages: vec![InputMessage {
+                role: "user".to_string(),
+                content: user,
+            }],
+            temperature: 0.3,
+        };
+
+        let reply = self.send_messages(&request).await?;
+        Ok(AiReply {
+            value: llm::parse_analysis(chat_id, week_group, &reply.value)?,
+            usage: reply.usage,
+        })
+    }
+
+    async fn summarize(&self, context: &str) -> Result<AiReply<String>, DomainError> {
+        info!(
+            context_len = context.len(),
+            "sending context to AI for summarization"
+        );
+
+        let request = MessagesRequest {
+            model: self.model.clone(),
+            max_tokens: MAX_TOKENS,
+            system: None,
+            messages: vec![InputMessage {
+                role: "user".to_string(),
+                content: self.prompts.summarize(context),
+            }],
+            temperature: 0.3,
+        };
+
+        let reply = self.send_messages(&request).await?;
+        let summary = reply.value.trim().to_string();
+
+        info!(summary_len = summary.len(), "summarization complete");
+
+        Ok(AiReply {
+            value: summary,
+            usage: reply.usage,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Messages API reply captured from claude-3-5-haiku: JSON wrapped in a markdown fence.
+    const ANALYSIS_FIXTURE: &str = r#"{
+  "id": "msg_01XFDUDYJgAACzvnptvVoYEL",
+  "type": "message",
+  "role": "assistant",
+  "model": "claude-3-5-haiku-20241022",
+  "content": [
+    {
+      "type": "text",
+      "text": "```json\n{\n  \"summary\": \"The team agreed to ship v2 on Friday.\",\n  \"key_topics\": [\"release\", \"budget\"],\n  \"action_items\": [\n    {\"description\": \"Reply to Alex regarding budget approval\", \"owner\": \"Alex\", \"deadline\": \"Friday\", \"priority\": \"high\"},\n    {\"description\": \"Reply to unknown regarding invoice\", \"owner\": null, \"deadline\": null, \"priority\": null}\n  ]\n}\n```"
+    }
+  ],
+  "stop_reason": "end_turn",
+  "stop_sequence": null,
+  "usage": {"input_tokens": 1834, "output_tokens": 121}
+}"#;
+
+    #[test]
+    fn test_parse_analysis_from_fixture() {
+        let response: MessagesResponse = serde_json::from_str(ANALYSIS_FIXTURE).unwrap();
+        let usage = response.usage.as_ref().unwrap();
+        assert_eq!((usage.input_tokens, usage.output_tokens), (1834, 121));
+        let week = WeekGroup::new("2025-07");
+        let result = llm::parse_analysis(42, &week, &response.text().unwrap()).unwrap();
+
+        assert_eq!(result.chat_id, 42);
+        assert_eq!(result.week_group, week);
+        assert_eq!(result.summary, "The team agreed to ship v2 on Friday.");
+        assert_eq!(result.key_topics, vec!["release", "budget"]);
+        // The "unknown" person item is dropped like with the OpenAI adapter
+        assert_eq!(result.action_items.len(), 1);
+        assert_eq!(result.action_items[0].owner.as_deref(), Some("Alex"));
+    }
+
+    #[test]
+    fn test_text_joins_blocks_and_rejects_empty() {
+        let response: MessagesResponse = serde_json::from_str(
+            r#"{"content":[{"type":"text","text":"Part one. "},{"type":"tool_use","id":"t1"},{"type":"text","text":"Part two."}]}"#,
+        )
+        .unwrap();
+        assert_eq!(response.text().unwrap(), "Part one. Part two.");
+
+        let empty: MessagesResponse = serde_json::from_str(r#"{"content":[]}"#).unwrap();
+        assert!(matches!(empty.text(), Err(DomainError::Ai(_))));
+    }
+
+    #[test]
+    fn test_request_shape() {
+        let request = MessagesRequest {
+            model: "claude-3-5-haiku-latest".to_string(),
+            max_tokens: MAX_TOKENS,
+            system: Some("be brief".to_string()),
+            messages: vec![InputMessage {
+                role: "user".to_string(),
+                content: "hi".to_string(),
+            }],
+            temperature: 0.3,
+        };
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["system"], "be brief");
+        assert_eq!(json["messages"][0]["role"], "user");
+        assert_eq!(json["max_tokens"], MAX_TOKENS);
+    }
+}
@@ -2,12 +2,26 @@
 //!
 //! Converts domain messages to CSV format suitable for LLM context input.
 
-use crate::domain::Message;
+use super::tokens::ChunkBudget;
+use crate::domain::{Message, album_label};
+use crate::shared::anonymize::Anonymizer;
 use chrono::{DateTime, Utc};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// Messages the rows of a CSV reply to, by id (see `RepoPort::get_messages_map`). Passing them
+/// adds a `ReplyTo` column, so the LLM sees which question a message answers.
+pub type ReplyTargets = HashMap<i32, Message>;
+
+/// Quoted messages in the `ReplyTo` column are cut to this many characters.
+const REPLY_QUOTE_CHARS: usize = 80;
 
 /// Convert messages to a CSV string for LLM context.
 ///
-/// Format: `Date;User;Message` (semicolon-delimited for LLM token efficiency)
+/// Format: `Date;User;Message` (semicolon-delimited for LLM token efficiency). `User` is the
+/// sender's display name when known, else the user id. Messages of a forum topic start with
+/// the topic name in brackets, and forwarded messages with `[Forwarded from X]` so their
+/// content isn't attributed to the forwarder. An album is one row (see [`collapse_albums`]).
 ///
 /// # Arguments
 /// * `messages` - Slice of messages to convert (should be pre-filtered)
@@ -23,21 +37,17 @@ pub fn messages_to_csv(messages: &[Message]) -> Result<String, csv::Error> {
     // Write header
     wtr.write_record(["Date", "User", "Message"])?;
 
-    for msg in messages {
+    for msg in collapse_albums(messages).iter() {
         // Convert Unix timestamp to readable ISO format
         let date_str = DateTime::<Utc>::from_timestamp(msg.date, 0)
             .map(|dt| dt.format("%Y-%m-%d %H:%M").to_string())
             .unwrap_or_else(|| msg.date.to_string());
 
-        // User ID as string (could be enhanced with user lookup later)
-        let user_str = msg
-            .from_user_id
-            .map(|id| id.to_string())
-            .unwrap_or_else(|| "unknown".to_string());
+        let user_str = sender_label(msg);
 
         // Clean text: replace newlines with spaces for LLM readability
         // The csv crate handles proper quoting/escaping of special characters
-        let clean_text = msg.text.replace('\n', " ").replace('\r', "");
+        let clean_text = clean_text(msg, &msg.text);
 
         wtr.write_record([&date_str, &user_str, &clean_text])?;
     }
@@ -58,37 +68,70 @@ pub fn messages_to_csv(messages: &[Message]) -> Result<String, csv::Error> {
     })
 }
 
-/// Convert messages to CSV chunks, each under `max_chunk_size` characters.
+/// Convert messages to CSV chunks, each within `budget.max_tokens` tokens of `budget.model`.
 ///
-/// Avoids allocating the entire week's messages as a single string (memory bomb).
-/// Each chunk includes the header row. Chunks are split when the current string
-/// would exceed `max_chunk_size` (conservative for LLM token limits).
+/// Avoids allocating the entire period's messages as a single string (memory bomb).
+/// Each chunk includes the header row. Chunks are split when the next row would push the
+/// chunk over the budget; a single row larger than the budget gets a chunk of its own.
 ///
 /// # Arguments
 /// * `messages` - Slice of messages to convert
-/// * `max_chunk_size` - Maximum characters per chunk (e.g., 50_000 for ~15k tokens)
+/// * `budget` - Token limit per chunk and the model whose tokenizer counts it
+/// * `replies` - Messages replied to, for a fourth `ReplyTo` column ("Name: first 80
+///   characters", `#id` when not archived). None leaves the column out.
 pub fn messages_to_csv_chunked(
     messages: &[Message],
-    max_chunk_size: usize,
+    budget: &ChunkBudget,
+    replies: Option<&ReplyTargets>,
 ) -> Result<Vec<String>, csv::Error> {
-    const HEADER: &str = "Date;User;Message\n";
+    chunk_rows(messages, budget, replies, None)
+}
+
+/// Like [`messages_to_csv_chunked`], but user ids become pseudonyms (`User-A`, ...) and
+/// emails, phone numbers, card numbers and @mentions in the text are redacted.
+/// Used when `TG_SYNC_AI_ANONYMIZE=true`.
+pub fn messages_to_csv_chunked_anonymized(
+    messages: &[Message],
+    budget: &ChunkBudget,
+    replies: Option<&ReplyTargets>,
+    anonymizer: &mut Anonymizer,
+) -> Result<Vec<String>, csv::Error> {
+    chunk_rows(messages, budget, replies, Some(anonymizer))
+}
+
+fn chunk_rows(
+    messages: &[Message],
+    budget: &ChunkBudget,
+    replies: Option<&ReplyTargets>,
+    mut anonymizer: Option<&mut Anonymizer>,
+) -> Result<Vec<String>, csv::Error> {
+    let header = match replies {
+        Some(_) => "Date;User;Message;ReplyTo\n",
+        None => "Date;User;Message\n",
+    };
 
     if messages.is_empty() {
         return Ok(vec![]);
     }
 
+    let counter = budget.counter();
+    let header_tokens = counter.count(header);
     let mut chunks = Vec::new();
-    let mut current = String::with_capacity(max_chunk_size.min(4096));
-    current.push_str(HEADER);
+    let mut current = String::with_capacity(4096);
+    current.push_str(header);
+    let mut current_tokens = header_tokens;
 
-    for msg in messages {
-        let row = format_message_row(msg)?;
-        if current.len() + row.len() > max_chunk_size && current.len() > HEADER.len() {
+    for msg in collapse_albums(messages).iter() {
+        let row = format_message_row(msg, replies, anonymizer.as_deref_mut())?;
+        let row_tokens = counter.count(&row);
+        if current_tokens + row_tokens > budget.max_tokens && current.len() > header.len() {
             chunks.push(std::mem::take(&mut current));
-            current = String::with_capacity(max_chunk_size.min(4096));
-            current.push_str(HEADER);
+            current = String::with_capacity(4096);
+            current.push_str(header);
+            current_tokens = header_tokens;
         }
         current.push_str(&row);
+        current_tokens += row_tokens;
     }
 
     if !current.is_empty() {
@@ -98,24 +141,118 @@ pub fn messages_to_csv_chunked(
     Ok(chunks)
 }
 
-fn format_message_row(msg: &Message) -> Result<String, csv::Error> {
+/// Messages with each album (consecutive parts sharing a `grouped_id`) collapsed into its
+/// captioned part, the caption prefixed with `[album of 5 photos]`, so the parts don't cost a
+/// row each. Captionless parts left on their own are skipped like other empty messages.
+fn collapse_albums(messages: &[Message]) -> Vec<Cow<'_, Message>> {
+    let mut rows = Vec::with_capacity(messages.len());
+    for run in messages.chunk_by(|a, b| a.same_album(b)) {
+        match run {
+            [single] if single.grouped_id.is_some() && single.text.is_empty() => {}
+            [single] => rows.push(Cow::Borrowed(single)),
+            parts => {
+                let Some(lead) = parts.iter().find(|m| !m.text.is_empty()) else {
+                    continue;
+                };
+                let captions: Vec<&str> = parts
+                    .iter()
+                    .map(|m| m.text.as_str())
+                    .filter(|text| !text.is_empty())
+                    .collect();
+                rows.push(Cow::Owned(Message {
+                    text: format!("[{}] {}", album_label(parts), captions.join(" ")),
+                    ..lead.clone()
+                }));
+            }
+        }
+    }
+    rows
+}
+
+/// Sender display name, else user id, else "unknown".
+fn sender_label(msg: &Message) -> String {
+    match (&msg.sender_name, msg.from_user_id) {
+        (Some(name), _) => name.clone(),
+        (None, Some(id)) => id.to_string(),
+        (None, None) => "unknown".to_string(),
+    }
+}
+
+/// `text` on one line, prefixed with `[topic] ` when the message belongs to a forum topic and
+/// `[Forwarded from X] ` when it was forwarded.
+fn clean_text(msg: &Message, text: &str) -> String {
+    let mut text = text.replace('\n', " ").replace('\r', "");
+    if let Some(origin) = msg.forwarded_from() {
+        text = format!("[Forwarded from {}] {}", origin, text);
+    }
+    match &msg.topic_title {
+        Some(topic) => format!("[{}] {}", topic, text),
+        None => text,
+    }
+}
+
+/// `ReplyTo` cell: "Name: text" of the message replied to, cut to [`REPLY_QUOTE_CHARS`];
+/// `#id` when it is not in the archive (deleted, or older than the backup); empty for
+/// messages that reply to nothing.
+fn reply_cell(
+    msg: &Message,
+    targets: &ReplyTargets,
+    anonymizer: Option<&mut Anonymizer>,
+) -> String {
+    let Some(id) = msg.reply_to_msg_id else {
+        return String::new();
+    };
+    let Some(target) = targets.get(&id) else {
+        return format!("#{}", id);
+    };
+    let (name, text) = match anonymizer {
+        Some(a) => (
+            target
+                .from_user_id
+                .map(|id| a.user(id, None))
+                .unwrap_or_else(|| "unknown".to_string()),
+            a.redact_text(&target.text),
+        ),
+        None => (sender_label(target), target.text.clone()),
+    };
+    let text = text.replace('\n', " ").replace('\r', "");
+    let mut quote: String = text.chars().take(REPLY_QUOTE_CHARS).collect();
+    if text.chars().count() > REPLY_QUOTE_CHARS {
+        quote = format!("{}…", quote.trim_end());
+    }
+    format!("{}: {}", name, quote)
+}
+
+fn format_message_row(
+    msg: &Message,
+    replies: Option<&ReplyTargets>,
+    mut anonymizer: Option<&mut Anonymizer>,
+) -> Result<String, csv::Error> {
     let date_str = DateTime::<Utc>::from_timestamp(msg.date, 0)
         .map(|dt| dt.format("%Y-%m-%d %H:%M").to_string())
         .unwrap_or_else(|| msg.date.to_string());
 
-    let user_str = msg
-        .from_user_id
-        .map(|id| id.to_string())
-        .unwrap_or_else(|| "unknown".to_string());
+    let (user_str, text) = match anonymizer.as_deref_mut() {
+        Some(a) => (
+            msg.from_user_id
+                .map(|id| a.user(id, None))
+                .unwrap_or_else(|| "unknown".to_string()),
+            a.redact_text(&msg.text),
+        ),
+        None => (sender_label(msg), msg.text.clone()),
+    };
 
-    let clean_text = msg.text.replace('\n', " ").replace('\r', "");
+    let mut record = vec![date_str, user_str, clean_text(msg, &text)];
+    if let Some(targets) = replies {
+        record.push(reply_cell(msg, targets, anonymizer));
+    }
 
     let mut wtr = csv::WriterBuilder::new()
         .delimiter(b';')
         .has_headers(false)
         .from_writer(Vec::new());
 
-    wtr.write_record([&date_str, &user_str, &clean_text])?;
+    wtr.write_record(&record)?;
     wtr.flush()?;
 
     let bytes = wtr.into_inner().map_err(|e| {
@@ -140,18 +277,51 @@ fn format_message_row(msg: &Message) -> Result<String, csv::Error> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::fake_tg::text_message;
+
+    /// ~50k characters with the chars/4 estimate.
+    fn budget() -> ChunkBudget {
+        ChunkBudget::new(12_500, "unknown")
+    }
+
+    fn corpus(text: &str) -> Vec<Message> {
+        (0..200)
+            .map(|i| Message {
+                from_user_id: Some(456),
+                ..text_message(123, i, 1704067200, text)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_cyrillic_needs_more_chunks_than_ascii() {
+        let ascii = "Let us meet tomorrow to discuss the release plan and budget. ";
+        let cyrillic = "Давайте встретимся завтра и обсудим план релиза и бюджет, ок ";
+        assert_eq!(ascii.chars().count(), cyrillic.chars().count());
+
+        let budget = ChunkBudget::new(2_000, "gpt-4o-mini");
+        let counter = budget.counter();
+        let ascii_chunks =
+            messages_to_csv_chunked(&corpus(&ascii.repeat(4)), &budget, None).unwrap();
+        let cyrillic_chunks =
+            messages_to_csv_chunked(&corpus(&cyrillic.repeat(4)), &budget, None).unwrap();
+        assert!(
+            cyrillic_chunks.len() > ascii_chunks.len(),
+            "cyrillic {} vs ascii {}",
+            cyrillic_chunks.len(),
+            ascii_chunks.len()
+        );
+        for chunk in ascii_chunks.iter().chain(&cyrillic_chunks) {
+            assert!(counter.count(chunk) <= 2_000 + 8); // row boundaries may merge a token or two
+        }
+    }
 
     #[test]
     fn test_messages_to_csv_basic() {
         let messages = vec![Message {
-            id: 1,
-            chat_id: 123,
-            date: 1704067200, // 2024-01-01 00:00:00 UTC
-            text: "Hello world".to_string(),
-            media: None,
             from_user_id: Some(456),
-            reply_to_msg_id: None,
-            edit_history: None,
+            // 2024-01-01 00:00:00 UTC
+            ..text_message(123, 1, 1704067200, "Hello world")
         }];
 
         let csv = messages_to_csv(&messages).unwrap();
@@ -161,17 +331,75 @@ mod tests {
         assert!(csv.contains("Hello world"));
     }
 
+    #[test]
+    fn test_topic_title_prefixes_message() {
+        let mut in_topic = text_message(123, 1, 1704067200, "Hello world");
+        in_topic.topic_title = Some("Releases".to_string());
+
+        let csv = messages_to_csv(std::slice::from_ref(&in_topic)).unwrap();
+        assert!(csv.contains(";[Releases] Hello world"));
+        let chunks = messages_to_csv_chunked(&[in_topic], &budget(), None).unwrap();
+        assert!(chunks[0].contains(";[Releases] Hello world"));
+    }
+
+    #[test]
+    fn test_forwarded_message_names_its_origin() {
+        let mut forwarded = text_message(123, 1, 1704067200, "Hello world");
+        forwarded.forwarded_from_name = Some("Daily News".to_string());
+        forwarded.forwarded_date = Some(1704000000);
+
+        let csv = messages_to_csv(std::slice::from_ref(&forwarded)).unwrap();
+        assert!(csv.contains(";[Forwarded from Daily News] Hello world"));
+
+        // Inside a forum topic, the topic label comes first.
+        forwarded.topic_title = Some("Releases".to_string());
+        let csv = messages_to_csv(&[forwarded]).unwrap();
+        assert!(csv.contains(";[Releases] [Forwarded from Daily News] Hello world"));
+    }
+
+    #[test]
+    fn test_album_is_one_row_with_its_caption() {
+        use crate::domain::{MediaReference, MediaType};
+        let part = |id: i32, text: &str, grouped_id: i64, media_type: MediaType| {
+            let mut m = text_message(123, id, 1704067200, text);
+            m.grouped_id = Some(grouped_id);
+            m.media = Some(MediaReference {
+                message_id: id,
+                chat_id: m.chat_id,
+                media_type,
+                opaque_ref: String::new(),
+                size_bytes: None,
+                filename: None,
+                mime_type: None,
+            });
+            m
+        };
+        let mut messages: Vec<Message> = (1..=5)
+            .map(|id| {
+                let caption = if id == 3 { "Sunset at the lake" } else { "" };
+                part(id, caption, 900, MediaType::Photo)
+            })
+            .collect();
+        messages.push(text_message(123, 6, 1704067300, "after the trip"));
+        messages.push(part(7, "Clip and notes", 901, MediaType::Video));
+        messages.push(part(8, "", 901, MediaType::Document));
+
+        let csv = messages_to_csv(&messages).unwrap();
+        let rows: Vec<&str> = csv.lines().skip(1).collect();
+        assert_eq!(rows.len(), 3, "{}", csv);
+        assert!(rows[0].ends_with(";[album of 5 photos] Sunset at the lake"));
+        assert!(rows[1].ends_with(";after the trip"));
+        assert!(rows[2].ends_with(";[album of 2 items] Clip and notes"));
+
+        let chunks = messages_to_csv_chunked(&messages, &budget(), None).unwrap();
+        assert_eq!(chunks[0].lines().count(), 4);
+    }
+
     #[test]
     fn test_messages_to_csv_special_chars() {
         let messages = vec![Message {
-            id: 1,
-            chat_id: 123,
-            date: 1704067200,
-            text: "Hello; with \"quotes\" and\nnewlines".to_string(),
-            media: None,
             from_user_id: Some(456),
-            reply_to_msg_id: None,
-            edit_history: None,
+            ..text_message(123, 1, 1704067200, "Hello; with \"quotes\" and\nnewlines")
         }];
 
         let csv = messages_to_csv(&messages).unwrap();
@@ -184,17 +412,11 @@ mod tests {
     #[test]
     fn test_messages_to_csv_chunked_single() {
         let messages = vec![Message {
-            id: 1,
-            chat_id: 123,
-            date: 1704067200,
-            text: "Hello world".to_string(),
-            media: None,
             from_user_id: Some(456),
-            reply_to_msg_id: None,
-            edit_history: None,
+            ..text_message(123, 1, 1704067200, "Hello world")
         }];
 
-        let chunks = messages_to_csv_chunked(&messages, 50_000).unwrap();
+        let chunks = messages_to_csv_chunked(&messages, &budget(), None).unwrap();
         assert_eq!(chunks.len(), 1);
         assert!(chunks[0].contains("Date;User;Message"));
         assert!(chunks[0].contains("Hello world"));
@@ -205,22 +427,113 @@ mod tests {
         let mut messages = Vec::new();
         for i in 0..100 {
             messages.push(Message {
-                id: i,
-                chat_id: 123,
-                date: 1704067200,
-                text: "x".repeat(600), // ~620 chars per row with header overhead
-                media: None,
                 from_user_id: Some(456),
-                reply_to_msg_id: None,
-                edit_history: None,
+                // ~620 chars per row with header overhead
+                ..text_message(123, i, 1704067200, &"x".repeat(600))
             });
         }
 
-        let chunks = messages_to_csv_chunked(&messages, 50_000).unwrap();
+        let chunks = messages_to_csv_chunked(&messages, &budget(), None).unwrap();
         assert!(chunks.len() > 1);
         for chunk in &chunks {
             assert!(chunk.len() <= 52_000); // Allow small overshoot for last row
             assert!(chunk.starts_with("Date;User;Message"));
         }
     }
+
+    #[test]
+    fn test_sender_name_with_id_fallback() {
+        let msg = |from: i64, name: Option<&str>| Message {
+            from_user_id: Some(from),
+            sender_name: name.map(String::from),
+            ..text_message(123, 1, 1704067200, "budget?")
+        };
+        let messages = vec![msg(582331907, Some("Alice Smith")), msg(42, None)];
+
+        let chunks = messages_to_csv_chunked(&messages, &budget(), None).unwrap();
+        assert_eq!(
+            chunks[0],
+            "Date;User;Message\n\
+             2024-01-01 00:00;Alice Smith;budget?\n\
+             2024-01-01 00:00;42;budget?\n"
+        );
+        assert!(messages_to_csv(&messages).unwrap().contains("Alice Smith"));
+
+        // Anonymized output never carries the real name.
+        let chunks =
+            messages_to_csv_chunked_anonymized(&messages, &budget(), None, &mut Anonymizer::new())
+                .unwrap();
+        assert!(!chunks[0].contains("Alice"));
+    }
+
+    #[test]
+    fn test_reply_to_column_quotes_the_replied_message() {
+        let mut question = text_message(123, 1, 1704067200, "");
+        question.text = format!("Can you send the signed contract{}?", " today".repeat(12));
+        question.from_user_id = Some(456);
+        question.sender_name = Some("Alice".to_string());
+        let reply = |id: i32, to: Option<i32>, text: &str| Message {
+            reply_to_msg_id: to,
+            from_user_id: Some(789),
+            ..text_message(123, id, 1704067260, text)
+        };
+        let messages = vec![
+            question.clone(),
+            reply(2, Some(1), "sent"),
+            reply(3, Some(99), "as discussed"),
+            reply(4, None, "thanks"),
+        ];
+        let targets = ReplyTargets::from([(1, question)]);
+
+        let chunks = messages_to_csv_chunked(&messages, &budget(), Some(&targets)).unwrap();
+        let rows: Vec<&str> = chunks[0].lines().collect();
+        assert_eq!(rows[0], "Date;User;Message;ReplyTo");
+        assert!(rows[1].ends_with("today today?;"), "{}", rows[1]);
+        assert_eq!(
+            rows[2],
+            "2024-01-01 00:01;789;sent;Alice: Can you send the signed contract today today today today today today today today…"
+        );
+        assert_eq!(rows[3], "2024-01-01 00:01;789;as discussed;#99");
+        assert_eq!(rows[4], "2024-01-01 00:01;789;thanks;");
+
+        let chunks = messages_to_csv_chunked_anonymized(
+            &messages,
+            &budget(),
+            Some(&targets),
+            &mut Anonymizer::new(),
+        )
+        .unwrap();
+        assert!(
+            chunks[0].contains(";sent;User-A: Can you send"),
+            "{}",
+            chunks[0]
+        );
+        assert!(!chunks[0].contains("Alice"));
+    }
+
+    #[test]
+    fn test_messages_to_csv_chunked_anonymized() {
+        let msg = |id: i32, from: i64, text: &str| Message {
+            from_user_id: Some(from),
+            ..text_message(123, id, 1704067200, text)
+        };
+        let messages = vec![
+            msg(1, 456, "mail me at a.b@example.com"),
+            msg(2, 789, "call +1 (555) 123-4567"),
+            msg(3, 456, "ok"),
+        ];
+
+        let mut anonymizer = Anonymizer::new();
+        let chunks =
+            messages_to_csv_chunked_anonymized(&messages, &budget(), None, &mut anonymizer)
+                .unwrap();
+        assert_eq!(
+            chunks[0],
+            "Date;User;Message\n\
+             2024-01-01 00:00;User-A;mail me at [email]\n\
+             2024-01-01 00:00;User-B;call [phone]\n\
+             2024-01-01 00:00;User-A;ok\n"
+        );
+        assert!(!chunks[0].contains("456"));
+    }
 }
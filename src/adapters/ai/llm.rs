@@ -0,0 +1,335 @@
+//! Provider-independent pieces of the LLM adapters: response post-processing and the HTTP
+//! retry policy (prompts live in [`super::prompts`]). [`OpenAiAdapter`](super::OpenAiAdapter) and
+//! [`AnthropicAdapter`](super::AnthropicAdapter) differ only in request/response shape.
+
+use crate::domain::{ActionItem, AnalysisResult, DomainError, Granularity, WeekGroup};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, warn};
+
+/// Upper bound for one request, so a hung connection can't stall the analysis forever.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// First backoff after a 5xx or timeout; doubles with every further attempt.
+pub(crate) const BASE_BACKOFF: Duration = Duration::from_secs(1);
+
+/// No single wait (backoff or `Retry-After`) is longer than this.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Sanitize JSON response from LLM.
+///
+/// LLMs sometimes wrap JSON in markdown code blocks. This strips them.
+pub(crate) fn sanitize_json(raw_text: &str) -> String {
+    let trimmed = raw_text.trim();
+
+    // Handle markdown code blocks: ```json ... ``` or ``` ... ```
+    if trimmed.starts_with("```") {
+        let without_prefix = if trimmed.starts_with("```json") {
+            trimmed.strip_prefix("```json").unwrap_or(trimmed)
+        } else {
+            trimmed.strip_prefix("```").unwrap_or(trimmed)
+        };
+
+        // Find closing backticks
+        if let Some(end_idx) = without_prefix.rfind("```") {
+            return without_prefix[..end_idx].trim().to_string();
+        }
+        return without_prefix.trim().to_string();
+    }
+
+    // Handle cases where JSON might be wrapped in other markdown
+    if let Some(start) = trimmed.find('{') {
+        if let Some(end) = trimmed.rfind('}') {
+            if start < end {
+                return trimmed[start..=end].to_string();
+            }
+        }
+    }
+
+    trimmed.to_string()
+}
+
+/// Parsed LLM response (matches our JSON schema).
+#[derive(Deserialize)]
+struct LlmAnalysis {
+    summary: String,
+    key_topics: Vec<String>,
+    action_items: Vec<LlmActionItem>,
+}
+
+#[derive(Deserialize)]
+struct LlmActionItem {
+    description: String,
+    owner: Option<String>,
+    deadline: Option<String>,
+    priority: Option<String>,
+}
+
+/// Turn the model's answer to the analysis prompt into an [`AnalysisResult`].
+///
+/// Strips markdown around the JSON and drops action items that name "unknown" people.
+pub(crate) fn parse_analysis(
+    chat_id: i64,
+    week_group: &WeekGroup,
+    raw_content: &str,
+) -> Result<AnalysisResult, DomainError> {
+    debug!(raw_len = raw_content.len(), "received AI response");
+
+    // Sanitize and parse JSON
+    let clean_json = sanitize_json(raw_content);
+    let analysis: LlmAnalysis = serde_json::from_str(&clean_json).map_err(|e| {
+        warn!(error = %e, json = %clean_json.chars().take(200).collect::<String>(), "JSON parse failed");
+        DomainError::Ai(format!("Failed to parse LLM JSON: {}", e))
+    })?;
+
+    // Convert to domain entity
+    let analyzed_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+
+    let action_items: Vec<ActionItem> = analysis
+        .action_items
+        .into_iter()
+        .filter_map(|item| {
+            if item.description.to_lowercase().contains("unknown") {
+                warn!(
+                    description = %item.description,
+                    "dropping action item: description must not contain 'unknown' for a person; use User ID or contextual role"
+                );
+                None
+            } else {
+                Some(ActionItem {
+                    description: item.description,
+                    owner: item.owner,
+                    deadline: item.deadline,
+                    priority: item.priority,
+                })
+            }
+        })
+        .collect();
+
+    info!(
+        chat_id,
+        week = %week_group,
+        topics = analysis.key_topics.len(),
+        actions = action_items.len(),
+        "AI analysis complete"
+    );
+
+    Ok(AnalysisResult {
+        week_group: week_group.clone(),
+        // The grouping is the caller's; AnalysisService overwrites it
+        granularity: Granularity::default(),
+        chat_id,
+        summary: analysis.summary,
+        key_topics: analysis.key_topics,
+        action_items,
+        analyzed_at,
+    })
+}
+
+/// HTTP client for LLM calls, with [`REQUEST_TIMEOUT`] per request. Without `proxy`, the
+/// HTTP_PROXY / HTTPS_PROXY variables apply.
+pub(crate) fn http_client(proxy: Option<reqwest::Proxy>) -> reqwest::Client {
+    let mut builder = reqwest::Client::builder().timeout(REQUEST_TIMEOUT);
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(proxy);
+    }
+    builder.build().unwrap_or_else(|e| {
+        warn!(error = %e, "failed to build AI HTTP client with timeout, using defaults");
+        reqwest::Client::new()
+    })
+}
+
+/// Send the request built by `build` and decode the JSON reply, retrying transient failures
+/// up to `max_retries` times (see [`retry_delay`]). Other errors fail at once.
+pub(crate) async fn send_with_retry<T: DeserializeOwned>(
+    build: impl Fn() -> reqwest::RequestBuilder,
+    max_retries: u32,
+    base_backoff: Duration,
+) -> Result<T, DomainError> {
+    let mut attempt = 0u32;
+    loop {
+        attempt += 1;
+        let failure = match build().send().await {
+            Ok(response) if response.status().is_success() => {
+                return response
+                    .json()
+                    .await
+                    .map_err(|e| DomainError::Ai(format!("Failed to parse API response: {}", e)));
+            }
+            Ok(response) => {
+                let status = response.status();
+                let retry_after = response
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(parse_retry_after);
+                let text = response.text().await.unwrap_or_default();
+                warn!(status = %status, body = %text, attempt, "AI API returned error");
+                let error = DomainError::Ai(format!(
+                    "API error {}: {}",
+                    status,
+                    text.chars().take(200).collect::<String>()
+                ));
+                (
+                    error,
+                    retry_delay(Some(status), retry_after, attempt, base_backoff),
+                )
+            }
+            Err(e) => {
+                let delay = (e.is_timeout() || e.is_connect())
+                    .then(|| retry_delay(None, None, attempt, base_backoff))
+                    .flatten();
+                (
+                    DomainError::Ai(format!("HTTP request failed: {}", e)),
+                    delay,
+                )
+            }
+        };
+
+        match failure {
+            (error, Some(delay)) if attempt <= max_retries => {
+                warn!(
+                    attempt,
+                    max_retries,
+                    delay_ms = delay.as_millis() as u64,
+                    error = %error,
+                    "retrying AI request"
+                );
+                tokio::time::sleep(delay).await;
+            }
+            (error, _) => return Err(error),
+        }
+    }
+}
+
+/// How long to wait before retrying a failed request, or `None` if it must not be retried.
+///
+/// `status` is `None` for transport failures the caller already judged transient (timeouts,
+/// refused connections). 429 waits for `retry_after` when the server sent one; 429 without it,
+/// 5xx and transport failures back off exponentially from `base` with jitter. Other statuses
+/// (401, 400 schema errors, ...) are permanent.
+fn retry_delay(
+    status: Option<StatusCode>,
+    retry_after: Option<Duration>,
+    attempt: u32,
+    base: Duration,
+) -> Option<Duration> {
+    match status {
+        Some(StatusCode::TOO_MANY_REQUESTS) => {
+            Some(retry_after.map_or_else(|| backoff(attempt, base), |d| d.min(MAX_BACKOFF)))
+        }
+        Some(s) if !s.is_server_error() => None,
+        _ => Some(backoff(attempt, base)),
+    }
+}
+
+/// `base * 2^(attempt - 1)` plus up to 50% jitter, capped at [`MAX_BACKOFF`].
+fn backoff(attempt: u32, base: Duration) -> Duration {
+    let exp = base.saturating_mul(1 << attempt.saturating_sub(1).min(16));
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos();
+    let jitter = exp.mul_f64(f64::from(nanos % 1000) / 2000.0);
+    (exp + jitter).min(MAX_BACKOFF)
+}
+
+/// `Retry-After` in delta-seconds form. HTTP dates are rare for this API and fall back to backoff.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    value.trim().parse::<u64>().ok().map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_json_clean() {
+        let input = r#"{"summary": "test"}"#;
+        assert_eq!(sanitize_json(input), input);
+    }
+
+    #[test]
+    fn test_sanitize_json_markdown() {
+        let input = r#"```json
+{"summary": "test"}
+```"#;
+        assert_eq!(sanitize_json(input), r#"{"summary": "test"}"#);
+    }
+
+    #[test]
+    fn test_sanitize_json_markdown_no_lang() {
+        let input = r#"```
+{"summary": "test"}
+```"#;
+        assert_eq!(sanitize_json(input), r#"{"summary": "test"}"#);
+    }
+
+    #[test]
+    fn test_sanitize_json_with_text() {
+        let input = r#"Here is the analysis:
+{"summary": "test", "key_topics": []}"#;
+        assert_eq!(
+            sanitize_json(input),
+            r#"{"summary": "test", "key_topics": []}"#
+        );
+    }
+
+    #[test]
+    fn test_retry_delay_decisions() {
+        let base = Duration::from_millis(100);
+        // 429 honours Retry-After (capped), or backs off without it
+        assert_eq!(
+            retry_delay(
+                Some(StatusCode::TOO_MANY_REQUESTS),
+                Some(Duration::from_secs(7)),
+                1,
+                base
+            ),
+            Some(Duration::from_secs(7))
+        );
+        assert_eq!(
+            retry_delay(
+                Some(StatusCode::TOO_MANY_REQUESTS),
+                Some(Duration::from_secs(3600)),
+                1,
+                base
+            ),
+            Some(MAX_BACKOFF)
+        );
+        assert!(retry_delay(Some(StatusCode::TOO_MANY_REQUESTS), None, 1, base).is_some());
+        // 5xx and transport failures back off
+        assert!(retry_delay(Some(StatusCode::BAD_GATEWAY), None, 1, base).is_some());
+        assert!(retry_delay(None, None, 1, base).is_some());
+        // Permanent client errors are not retried
+        assert_eq!(
+            retry_delay(Some(StatusCode::UNAUTHORIZED), None, 1, base),
+            None
+        );
+        assert_eq!(
+            retry_delay(Some(StatusCode::BAD_REQUEST), None, 1, base),
+            None
+        );
+    }
+
+    #[test]
+    fn test_backoff_grows_with_jitter_and_cap() {
+        let base = Duration::from_millis(100);
+        for attempt in 1..=4 {
+            let exp = base * (1 << (attempt - 1));
+            let delay = backoff(attempt, base);
+            assert!(
+                delay >= exp && delay <= exp.mul_f64(1.5),
+                "{attempt}: {delay:?}"
+            );
+        }
+        assert_eq!(backoff(30, base), MAX_BACKOFF);
+        assert_eq!(parse_retry_after(" 12 "), Some(Duration::from_secs(12)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), None);
+    }
+}
@@ -1,12 +1,34 @@
 //! Mock AI adapter for testing without API calls.
 //!
-//! Returns hardcoded responses for development and testing purposes.
+//! Returns hardcoded responses for development and testing purposes. Tests can script
+//! failures, malformed replies and the analysis returned, and inspect what was sent; the
+//! defaults (used when no API key is set) always succeed.
 
-use crate::domain::{ActionItem, AnalysisResult, DomainError, WeekGroup};
+use super::llm::parse_analysis;
+use crate::domain::{ActionItem, AiReply, AnalysisResult, DomainError, Granularity, WeekGroup};
 use crate::ports::AiPort;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tracing::info;
 
+/// Analysis reply that is not valid JSON, as a model might send it.
+const MALFORMED_REPLY: &str =
+    "```json\n{\"summary\": \"cut off mid-answer\", \"key_topics\": [\n```";
+
+/// One request received by a [`MockAiAdapter`] with [`MockAiAdapter::record_calls`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MockCall {
+    /// Final analysis (Reduce phase, or the whole period when it fits one request).
+    Analyze {
+        chat_id: i64,
+        week_group: WeekGroup,
+        context: String,
+    },
+    /// Chunk summary (Map phase).
+    Summarize { context: String },
+}
+
 /// Mock AI adapter for testing.
 ///
 /// Returns predetermined responses without making API calls.
@@ -14,17 +36,103 @@ use tracing::info;
 pub struct MockAiAdapter {
     /// Simulated network delay in milliseconds.
     delay_ms: u64,
+    /// Calls (of either kind) that fail before the first success.
+    fail_first: usize,
+    /// Summarize call (0-based, in call order) that fails once.
+    fail_on_chunk: Option<usize>,
+    /// Answer analysis requests with invalid JSON.
+    malformed_json: bool,
+    /// Analysis returned instead of the canned one.
+    response: Option<AnalysisResult>,
+    /// Requests received, when recording.
+    calls: Option<Mutex<Vec<MockCall>>>,
+    /// Requests received so far, of either kind.
+    call_count: AtomicUsize,
+    /// Summarize requests received so far.
+    summarize_count: AtomicUsize,
 }
 
 impl MockAiAdapter {
     /// Create a new mock adapter with default delay (100ms).
     pub fn new() -> Self {
-        Self { delay_ms: 100 }
+        Self::with_delay(100)
     }
 
     /// Create a mock adapter with custom delay.
     pub fn with_delay(delay_ms: u64) -> Self {
-        Self { delay_ms }
+        Self {
+            delay_ms,
+            fail_first: 0,
+            fail_on_chunk: None,
+            malformed_json: false,
+            response: None,
+            calls: None,
+            call_count: AtomicUsize::new(0),
+            summarize_count: AtomicUsize::new(0),
+        }
+    }
+
+    /// Fail the first `n` requests (analysis or summary) with an API error.
+    pub fn fail_first_n(mut self, n: usize) -> Self {
+        self.fail_first = n;
+        self
+    }
+
+    /// Fail the summary request of Map-phase chunk `index` (0-based), once. Chunks are counted
+    /// in the order their summaries are requested, across periods and runs: with one period
+    /// and no stored summaries, that is chunk `index` of the period.
+    pub fn fail_on_chunk(mut self, index: usize) -> Self {
+        self.fail_on_chunk = Some(index);
+        self
+    }
+
+    /// Answer analysis requests with JSON the real adapters cannot parse.
+    pub fn return_malformed_json(mut self) -> Self {
+        self.malformed_json = true;
+        self
+    }
+
+    /// Return `result` from analysis requests, with the chat and period of the request.
+    pub fn respond_with(mut self, result: AnalysisResult) -> Self {
+        self.response = Some(result);
+        self
+    }
+
+    /// Keep every request for [`Self::calls`].
+    pub fn record_calls(mut self) -> Self {
+        self.calls = Some(Mutex::new(Vec::new()));
+        self
+    }
+
+    /// Requests received so far, in order. Empty unless [`Self::record_calls`] was set.
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.calls
+            .as_ref()
+            .map(|calls| calls.lock().unwrap_or_else(|e| e.into_inner()).clone())
+            .unwrap_or_default()
+    }
+
+    /// Count and record `call`; an error when the scenario fails it.
+    fn receive(&self, call: MockCall) -> Result<(), DomainError> {
+        let n = self.call_count.fetch_add(1, Ordering::SeqCst);
+        let chunk = matches!(call, MockCall::Summarize { .. })
+            .then(|| self.summarize_count.fetch_add(1, Ordering::SeqCst));
+        if let Some(calls) = &self.calls {
+            calls.lock().unwrap_or_else(|e| e.into_inner()).push(call);
+        }
+        if n < self.fail_first {
+            return Err(DomainError::Ai(format!(
+                "[MOCK] API error 503: simulated failure of request {}",
+                n + 1
+            )));
+        }
+        if chunk.is_some() && chunk == self.fail_on_chunk {
+            return Err(DomainError::Ai(format!(
+                "[MOCK] API error 503: simulated failure of chunk {}",
+                chunk.unwrap_or_default() + 1
+            )));
+        }
+        Ok(())
     }
 }
 
@@ -41,7 +149,7 @@ impl AiPort for MockAiAdapter {
         chat_id: i64,
         week_group: &WeekGroup,
         context_csv: &str,
-    ) -> Result<AnalysisResult, DomainError> {
+    ) -> Result<AiReply<AnalysisResult>, DomainError> {
         info!(
             chat_id,
             week = %week_group,
@@ -52,6 +160,22 @@ impl AiPort for MockAiAdapter {
         // Simulate network delay
         tokio::time::sleep(Duration::from_millis(self.delay_ms)).await;
 
+        self.receive(MockCall::Analyze {
+            chat_id,
+            week_group: week_group.clone(),
+            context: context_csv.to_string(),
+        })?;
+        if self.malformed_json {
+            return parse_analysis(chat_id, week_group, MALFORMED_REPLY).map(AiReply::unmetered);
+        }
+        if let Some(response) = &self.response {
+            return Ok(AiReply::unmetered(AnalysisResult {
+                week_group: week_group.clone(),
+                chat_id,
+                ..response.clone()
+            }));
+        }
+
         let analyzed_at = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap_or_default()
@@ -60,11 +184,12 @@ impl AiPort for MockAiAdapter {
         // Count lines in CSV (excluding header) to make summary somewhat realistic
         let message_count = context_csv.lines().count().saturating_sub(1);
 
-        Ok(AnalysisResult {
+        Ok(AiReply::unmetered(AnalysisResult {
             week_group: week_group.clone(),
+            granularity: Granularity::default(),
             chat_id,
             summary: format!(
-                "[MOCK] This is a simulated analysis of {} messages for week {}. \
+                "[MOCK] This is a simulated analysis of {} messages for period {}. \
                  In a real scenario, this would contain a comprehensive summary \
                  of the discussions, key decisions made, and overall context. \
                  The mock adapter is useful for testing the analysis pipeline \
@@ -91,10 +216,10 @@ impl AiPort for MockAiAdapter {
                 },
             ],
             analyzed_at,
-        })
+        }))
     }
 
-    async fn summarize(&self, context: &str) -> Result<String, DomainError> {
+    async fn summarize(&self, context: &str) -> Result<AiReply<String>, DomainError> {
         info!(
             context_len = context.len(),
             "[MOCK] Simulating AI summarization"
@@ -102,13 +227,17 @@ impl AiPort for MockAiAdapter {
 
         tokio::time::sleep(Duration::from_millis(self.delay_ms)).await;
 
+        self.receive(MockCall::Summarize {
+            context: context.to_string(),
+        })?;
+
         let line_count = context.lines().count().saturating_sub(1).max(0);
-        Ok(format!(
+        Ok(AiReply::unmetered(format!(
             "[MOCK] Intermediate summary of {} lines of chat logs. \
              Key events and topics from this chunk. In production, the LLM would \
              extract salient points for the reduce phase.",
             line_count
-        ))
+        )))
     }
 }
 
@@ -122,7 +251,7 @@ mod tests {
         let week = WeekGroup::new("2024-01");
         let csv = "Date;User;Message\n2024-01-01;123;Hello";
 
-        let result = adapter.analyze(123, &week, csv).await.unwrap();
+        let result = adapter.analyze(123, &week, csv).await.unwrap().value;
 
         assert_eq!(result.chat_id, 123);
         assert_eq!(result.week_group, week);
@@ -130,4 +259,46 @@ mod tests {
         assert_eq!(result.key_topics.len(), 3);
         assert_eq!(result.action_items.len(), 2);
     }
+
+    #[tokio::test]
+    async fn test_scripted_failures_and_recorded_calls() {
+        let week = WeekGroup::new("2024-01");
+        let adapter = MockAiAdapter::with_delay(0)
+            .fail_first_n(1)
+            .fail_on_chunk(1)
+            .record_calls();
+
+        assert!(adapter.summarize("first").await.is_err());
+        assert!(adapter.summarize("second").await.is_err());
+        assert!(adapter.summarize("third").await.is_ok());
+        assert!(adapter.analyze(5, &week, "csv").await.is_ok());
+        assert_eq!(
+            adapter.calls(),
+            vec![
+                MockCall::Summarize {
+                    context: "first".to_string()
+                },
+                MockCall::Summarize {
+                    context: "second".to_string()
+                },
+                MockCall::Summarize {
+                    context: "third".to_string()
+                },
+                MockCall::Analyze {
+                    chat_id: 5,
+                    week_group: week.clone(),
+                    context: "csv".to_string()
+                },
+            ]
+        );
+
+        let malformed = MockAiAdapter::with_delay(0).return_malformed_json();
+        let err = malformed.analyze(5, &week, "csv").await.unwrap_err();
+        assert!(
+            err.to_string().contains("Failed to parse LLM JSON"),
+            "{}",
+            err
+        );
+        assert!(malformed.calls().is_empty());
+    }
 }
@@ -1,11 +1,22 @@
 //! AI adapter module. Implements AiPort for LLM integration.
 //!
-//! Provides OpenAI-compatible adapter and mock adapter for testing.
+//! Provides OpenAI-compatible and Anthropic adapters, and a mock adapter for testing.
+//! Both real adapters take their prompts from [`PromptTemplates`].
 
+pub mod anthropic_adapter;
 pub mod csv_utils;
+mod llm;
 pub mod mock_adapter;
 pub mod openai_adapter;
+pub mod prompts;
+pub mod tokens;
 
-pub use csv_utils::{messages_to_csv, messages_to_csv_chunked};
-pub use mock_adapter::MockAiAdapter;
+pub use anthropic_adapter::AnthropicAdapter;
+
+pub use csv_utils::{
+    ReplyTargets, messages_to_csv, messages_to_csv_chunked, messages_to_csv_chunked_anonymized,
+};
+pub use mock_adapter::{MockAiAdapter, MockCall};
 pub use openai_adapter::OpenAiAdapter;
+pub use prompts::PromptTemplates;
+pub use tokens::{ChunkBudget, TokenCounter};
@@ -2,12 +2,16 @@
 //!
 //! Supports OpenAI API, Azure OpenAI, and local Ollama instances.
 //! Implements `AiPort` with robust JSON parsing and markdown stripping.
+//!
+//! Every request is retried on rate limits (429, honouring `Retry-After`), server errors (5xx)
+//! and timeouts, so one flaky response doesn't cost a whole week's Map-Reduce run.
 
-use crate::domain::{ActionItem, AnalysisResult, DomainError, WeekGroup};
+use super::{PromptTemplates, llm};
+use crate::domain::{AiReply, AiUsage, AnalysisResult, DomainError, WeekGroup};
 use crate::ports::AiPort;
 use serde::{Deserialize, Serialize};
-use std::time::{SystemTime, UNIX_EPOCH};
-use tracing::{debug, info, warn};
+use std::time::Duration;
+use tracing::info;
 
 /// OpenAI-compatible AI adapter.
 ///
@@ -21,6 +25,10 @@ pub struct OpenAiAdapter {
     api_url: String,
     api_key: String,
     model: String,
+    /// Retries per request after the first attempt.
+    max_retries: u32,
+    base_backoff: Duration,
+    prompts: PromptTemplates,
 }
 
 impl OpenAiAdapter {
@@ -30,122 +38,55 @@ impl OpenAiAdapter {
     /// * `api_url` - API endpoint (e.g., "https://api.openai.com/v1/chat/completions")
     /// * `api_key` - API key (can be empty for local Ollama)
     /// * `model` - Model name (e.g., "gpt-4o-mini", "llama3.2")
-    pub fn new(api_url: String, api_key: String, model: String) -> Self {
+    /// * `max_retries` - Retries per request on 429, 5xx and timeouts (TG_SYNC_AI_MAX_RETRIES)
+    /// * `prompts` - Analysis and summarization prompts (validated when loaded)
+    pub fn new(
+        api_url: String,
+        api_key: String,
+        model: String,
+        max_retries: u32,
+        prompts: PromptTemplates,
+    ) -> Self {
         Self {
-            client: reqwest::Client::new(),
+            client: llm::http_client(None),
             api_url,
             api_key,
             model,
+            max_retries,
+            base_backoff: llm::BASE_BACKOFF,
+            prompts,
         }
     }
 
-    /// Build the system prompt with JSON schema instructions.
-    fn system_prompt() -> &'static str {
-        r#"You are an expert personal assistant analyzing Telegram chat logs for the chat owner.
-
-## Your Task
-1. Summarize the key discussions and themes (2-3 concise paragraphs).
-2. Extract Action Items (see rules below), with owner and deadline if mentioned.
-3. List 3-5 key topics discussed.
-
-## Action Items: What to Extract
-
-### Explicit tasks
-- Commitments, promises, or stated to-dos (e.g., "I need to do X", "We should schedule Y", "Let me send you Z").
-- Include owner and deadline when present in the thread.
-
-### Unanswered messages (implicit tasks)
-- **Identify questions or requests directed at the chat owner** that have no visible reply in the provided chunk.
-- Look for: direct questions (@ or by name), "can you...", "could you...", "when will you...", "did you...", requests for input or approval, or follow-ups that were never answered.
-- **Format each unanswered item as a single actionable task:** "Reply to [Name] regarding [Topic]".
-  - [Name] = the person who asked (or their display name/identifier from the log).
-  - [Topic] = a short, clear summary of what they asked (e.g., "meeting time", "approval for X", "status on Y").
-- Only include an unanswered item if the chat owner appears to be the addressee and no answer is present in the log.
-
-### Strict Identity Resolution (required)
-- **Never use the term "unknown" as a placeholder for a person's name** in any Action Item.
-- When generating tasks (e.g. "Reply to...", "Send CV to..."), resolve the identity using this hierarchy:
-  1. **Explicit Name:** Use the name if mentioned in the message text or signature.
-  2. **User Identifier:** If no name is textually present, you MUST use the value from the "User" column of the provided CSV (e.g. "Reply to User 12345 regarding ...").
-  3. **Contextual Role:** If the User column is missing or not an identifier, infer a specific role from the conversation (e.g. "Recruiter", "Client", "Hiring Manager") and use that (e.g. "Reply to Recruiter regarding ...").
-- **Constraint:** Any Action Item description that contains the word "unknown" referring to a person is forbidden. Use the User ID or a contextual role instead.
-
-### Validation (before output)
-- Review every action item you generated. Each must be:
-  - **Actionable:** Someone could do it without guessing (e.g., "Reply to Alex regarding budget approval" not "Follow up on thing").
-  - **Clear:** No vague references; include enough context (name/topic) so the task is unambiguous.
-  - **No "unknown" for people:** No action item may use "unknown" as a person's name; use User column value or a contextual role instead.
-- Remove or rewrite any item that fails this check. Prefer fewer, clear tasks over many vague ones.
-
-## Output Format
-You MUST respond with valid JSON only. No markdown, no explanations outside JSON.
-
-```json
-{
-  "summary": "Concise summary of discussions...",
-  "key_topics": ["topic1", "topic2", "topic3"],
-  "action_items": [
-    {
-      "description": "What needs to be done (e.g. 'Reply to [Name] regarding [Topic]' for unanswered items)",
-      "owner": "Person responsible (or null)",
-      "deadline": "Due date if mentioned (or null)",
-      "priority": "high|medium|low (or null)"
-    }
-  ]
-}
-```
-
-If there are no action items, return an empty array for action_items.
-Keep summaries factual and concise. Focus on actionable information."#
+    /// Send requests through `proxy` (TG_SYNC_HTTP_PROXY).
+    pub fn with_proxy(mut self, proxy: reqwest::Proxy) -> Self {
+        self.client = llm::http_client(Some(proxy));
+        self
     }
 
-    /// Build the user prompt with CSV data or combined summaries (reduce phase).
-    fn user_prompt(context_csv: &str) -> String {
-        format!(
-            "Analyze the following chat log context for the week. It may be CSV format (Date;User;Message) or combined summaries from multiple chunks.\n\n{}",
-            context_csv
-        )
+    /// Usage reported in `response`, attributed to the configured model.
+    fn usage(&self, response: &ChatResponse) -> Option<AiUsage> {
+        response.usage.as_ref().map(|u| AiUsage {
+            model: self.model.clone(),
+            prompt_tokens: u.prompt_tokens,
+            completion_tokens: u.completion_tokens,
+        })
     }
 
-    /// Build the summarization prompt for the Map phase.
-    fn summarize_prompt(context: &str) -> String {
-        format!(
-            "Summarize the following chat logs, highlighting key events and topics.\n\n{}",
-            context
+    /// POST a chat completion, retrying transient failures.
+    async fn send_chat(&self, request: &ChatRequest) -> Result<ChatResponse, DomainError> {
+        llm::send_with_retry(
+            || {
+                self.client
+                    .post(&self.api_url)
+                    .header("Authorization", format!("Bearer {}", self.api_key))
+                    .header("Content-Type", "application/json")
+                    .json(request)
+            },
+            self.max_retries,
+            self.base_backoff,
         )
-    }
-
-    /// Sanitize JSON response from LLM.
-    ///
-    /// LLMs sometimes wrap JSON in markdown code blocks. This strips them.
-    fn sanitize_json(raw_text: &str) -> String {
-        let trimmed = raw_text.trim();
-
-        // Handle markdown code blocks: ```json ... ``` or ``` ... ```
-        if trimmed.starts_with("```") {
-            let without_prefix = if trimmed.starts_with("```json") {
-                trimmed.strip_prefix("```json").unwrap_or(trimmed)
-            } else {
-                trimmed.strip_prefix("```").unwrap_or(trimmed)
-            };
-
-            // Find closing backticks
-            if let Some(end_idx) = without_prefix.rfind("```") {
-                return without_prefix[..end_idx].trim().to_string();
-            }
-            return without_prefix.trim().to_string();
-        }
-
-        // Handle cases where JSON might be wrapped in other markdown
-        if let Some(start) = trimmed.find('{') {
-            if let Some(end) = trimmed.rfind('}') {
-                if start < end {
-                    return trimmed[start..=end].to_string();
-                }
-            }
-        }
-
-        trimmed.to_string()
+        .await
     }
 }
 
@@ -175,6 +116,17 @@ struct ResponseFormat {
 #[derive(Deserialize)]
 struct ChatResponse {
     choices: Vec<Choice>,
+    /// Token counts; some compatible servers omit it.
+    #[serde(default)]
+    usage: Option<TokenUsage>,
+}
+
+#[derive(Deserialize)]
+struct TokenUsage {
+    #[serde(default)]
+    prompt_tokens: u64,
+    #[serde(default)]
+    completion_tokens: u64,
 }
 
 #[derive(Deserialize)]
@@ -187,22 +139,6 @@ struct MessageContent {
     content: String,
 }
 
-/// Parsed LLM response (matches our JSON schema).
-#[derive(Deserialize)]
-struct LlmAnalysis {
-    summary: String,
-    key_topics: Vec<String>,
-    action_items: Vec<LlmActionItem>,
-}
-
-#[derive(Deserialize)]
-struct LlmActionItem {
-    description: String,
-    owner: Option<String>,
-    deadline: Option<String>,
-    priority: Option<String>,
-}
-
 #[async_trait::async_trait]
 impl AiPort for OpenAiAdapter {
     async fn analyze(
@@ -210,7 +146,7 @@ impl AiPort for OpenAiAdapter {
         chat_id: i64,
         week_group: &WeekGroup,
         context_csv: &str,
-    ) -> Result<AnalysisResult, DomainError> {
+    ) -> Result<AiReply<AnalysisResult>, DomainError> {
         info!(
             chat_id,
             week = %week_group,
@@ -219,16 +155,17 @@ impl AiPort for OpenAiAdapter {
         );
 
         // Build request
+        let (system, user) = self.prompts.analysis(context_csv);
         let request = ChatRequest {
             model: self.model.clone(),
             messages: vec![
                 ChatMessage {
                     role: "system".to_string(),
-                    content: Self::system_prompt().to_string(),
+                    content: system,
                 },
                 ChatMessage {
                     role: "user".to_string(),
-                    content: Self::user_prompt(context_csv),
+                    content: user,
                 },
             ],
             temperature: 0.3,
@@ -237,34 +174,7 @@ impl AiPort for OpenAiAdapter {
             }),
         };
 
-        // Send request
-        let response = self
-            .client
-            .post(&self.api_url)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| DomainError::Ai(format!("HTTP request failed: {}", e)))?;
-
-        // Check status
-        if !response.status().is_success() {
-            let status = response.status();
-            let text = response.text().await.unwrap_or_default();
-            warn!(status = %status, body = %text, "AI API returned error");
-            return Err(DomainError::Ai(format!(
-                "API error {}: {}",
-                status,
-                text.chars().take(200).collect::<String>()
-            )));
-        }
-
-        // Parse response
-        let chat_response: ChatResponse = response
-            .json()
-            .await
-            .map_err(|e| DomainError::Ai(format!("Failed to parse API response: {}", e)))?;
+        let chat_response = self.send_chat(&request).await?;
 
         let raw_content = chat_response
             .choices
@@ -272,61 +182,13 @@ impl AiPort for OpenAiAdapter {
             .map(|c| c.message.content.clone())
             .ok_or_else(|| DomainError::Ai("No response choices returned".to_string()))?;
 
-        debug!(raw_len = raw_content.len(), "received AI response");
-
-        // Sanitize and parse JSON
-        let clean_json = Self::sanitize_json(&raw_content);
-        let analysis: LlmAnalysis = serde_json::from_str(&clean_json).map_err(|e| {
-            warn!(error = %e, json = %clean_json.chars().take(200).collect::<String>(), "JSON parse failed");
-            DomainError::Ai(format!("Failed to parse LLM JSON: {}", e))
-        })?;
-
-        // Convert to domain entity
-        let analyzed_at = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs() as i64;
-
-        let action_items: Vec<ActionItem> = analysis
-            .action_items
-            .into_iter()
-            .filter_map(|item| {
-                if item.description.to_lowercase().contains("unknown") {
-                    warn!(
-                        description = %item.description,
-                        "dropping action item: description must not contain 'unknown' for a person; use User ID or contextual role"
-                    );
-                    None
-                } else {
-                    Some(ActionItem {
-                        description: item.description,
-                        owner: item.owner,
-                        deadline: item.deadline,
-                        priority: item.priority,
-                    })
-                }
-            })
-            .collect();
-
-        info!(
-            chat_id,
-            week = %week_group,
-            topics = analysis.key_topics.len(),
-            actions = action_items.len(),
-            "AI analysis complete"
-        );
-
-        Ok(AnalysisResult {
-            week_group: week_group.clone(),
-            chat_id,
-            summary: analysis.summary,
-            key_topics: analysis.key_topics,
-            action_items,
-            analyzed_at,
+        Ok(AiReply {
+            value: llm::parse_analysis(chat_id, week_group, &raw_content)?,
+            usage: self.usage(&chat_response),
         })
     }
 
-    async fn summarize(&self, context: &str) -> Result<String, DomainError> {
+    async fn summarize(&self, context: &str) -> Result<AiReply<String>, DomainError> {
         info!(
             context_len = context.len(),
             "sending context to AI for summarization"
@@ -336,37 +198,13 @@ impl AiPort for OpenAiAdapter {
             model: self.model.clone(),
             messages: vec![ChatMessage {
                 role: "user".to_string(),
-                content: Self::summarize_prompt(context),
+                content: self.prompts.summarize(context),
             }],
             temperature: 0.3,
             response_format: None, // Plain text, no JSON
         };
 
-        let response = self
-            .client
-            .post(&self.api_url)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| DomainError::Ai(format!("HTTP request failed: {}", e)))?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let text = response.text().await.unwrap_or_default();
-            warn!(status = %status, body = %text, "AI API returned error");
-            return Err(DomainError::Ai(format!(
-                "API error {}: {}",
-                status,
-                text.chars().take(200).collect::<String>()
-            )));
-        }
-
-        let chat_response: ChatResponse = response
-            .json()
-            .await
-            .map_err(|e| DomainError::Ai(format!("Failed to parse API response: {}", e)))?;
+        let chat_response = self.send_chat(&request).await?;
 
         let summary = chat_response
             .choices
@@ -376,49 +214,82 @@ impl AiPort for OpenAiAdapter {
 
         info!(summary_len = summary.len(), "summarization complete");
 
-        Ok(summary)
+        Ok(AiReply {
+            value: summary,
+            usage: self.usage(&chat_response),
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-
-    #[test]
-    fn test_sanitize_json_clean() {
-        let input = r#"{"summary": "test"}"#;
-        assert_eq!(OpenAiAdapter::sanitize_json(input), input);
+    use crate::testing::http_stub;
+
+    const OK_SUMMARY: &str = r#"{"choices":[{"message":{"content":"weekly summary"}}],"usage":{"prompt_tokens":120,"completion_tokens":30,"total_tokens":150}}"#;
+
+    /// Canned chat completions API; returns its endpoint URL and the number of requests
+    /// served once all `replies` went out.
+    async fn mock_api(
+        replies: Vec<(&'static str, &'static str)>,
+    ) -> (String, tokio::task::JoinHandle<usize>) {
+        let (url, server) = http_stub::serve(replies).await;
+        let served = tokio::spawn(async move { server.await.unwrap().len() });
+        (format!("{}/v1/chat/completions", url), served)
     }
 
-    #[test]
-    fn test_sanitize_json_markdown() {
-        let input = r#"```json
-{"summary": "test"}
-```"#;
-        assert_eq!(
-            OpenAiAdapter::sanitize_json(input),
-            r#"{"summary": "test"}"#
+    fn adapter(url: String, max_retries: u32) -> OpenAiAdapter {
+        let mut adapter = OpenAiAdapter::new(
+            url,
+            "key".to_string(),
+            "model".to_string(),
+            max_retries,
+            PromptTemplates::default(),
         );
+        adapter.base_backoff = Duration::from_millis(1);
+        adapter
+    }
+
+    #[tokio::test]
+    async fn test_retries_rate_limit_and_server_error() {
+        let (url, served) = mock_api(vec![
+            ("429 Too Many Requests\r\nRetry-After: 0", "{}"),
+            ("502 Bad Gateway", "upstream"),
+            ("200 OK", OK_SUMMARY),
+        ])
+        .await;
+        let summary = adapter(url, 3).summarize("chunk").await.unwrap();
+        assert_eq!(summary.value, "weekly summary");
+        let usage = summary.usage.unwrap();
+        assert_eq!(usage.model, "model");
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens), (120, 30));
+        assert_eq!(served.await.unwrap(), 3);
     }
 
-    #[test]
-    fn test_sanitize_json_markdown_no_lang() {
-        let input = r#"```
-{"summary": "test"}
-```"#;
-        assert_eq!(
-            OpenAiAdapter::sanitize_json(input),
-            r#"{"summary": "test"}"#
+    #[tokio::test]
+    async fn test_client_error_fails_immediately() {
+        let (url, served) = mock_api(vec![("401 Unauthorized", r#"{"error":"bad key"}"#)]).await;
+        let err = adapter(url, 3).summarize("chunk").await.unwrap_err();
+        assert!(
+            matches!(err, DomainError::Ai(ref m) if m.contains("401")),
+            "{err}"
         );
+        assert_eq!(served.await.unwrap(), 1);
     }
 
-    #[test]
-    fn test_sanitize_json_with_text() {
-        let input = r#"Here is the analysis:
-{"summary": "test", "key_topics": []}"#;
-        assert_eq!(
-            OpenAiAdapter::sanitize_json(input),
-            r#"{"summary": "test", "key_topics": []}"#
+    #[tokio::test]
+    async fn test_gives_up_after_max_retries() {
+        let (url, served) = mock_api(vec![
+            ("503 Service Unavailable", "down"),
+            ("503 Service Unavailable", "down"),
+        ])
+        .await;
+        let err = adapter(url, 1).summarize("chunk").await.unwrap_err();
+        assert!(
+            matches!(err, DomainError::Ai(ref m) if m.contains("503")),
+            "{err}"
         );
+        // First attempt + one retry
+        assert_eq!(served.await.unwrap(), 2);
     }
 }
@@ -0,0 +1,288 @@
+//! Prompt templates for the LLM adapters.
+//!
+//! The analysis instructions and the Map-phase summarization prompt can be replaced by
+//! `analyze.md` and `summarize.md` in the prompts directory (`data/prompts/`); missing files
+//! fall back to the embedded defaults. Whatever the template says, the analysis prompt always
+//! ends with the JSON output format, so [`parse_analysis`](super::llm::parse_analysis) keeps
+//! working.
+//!
+//! Placeholders: `{context}` is replaced by the chat log (CSV or combined chunk summaries).
+//! It is required in `summarize.md`. In `analyze.md` it is optional: without it the template
+//! is sent as the system prompt and the chat log follows as the user message; with it the
+//! filled-in template becomes the user message.
+
+use crate::domain::DomainError;
+use std::path::Path;
+
+/// Template file with the analysis instructions.
+pub const ANALYZE_FILE: &str = "analyze.md";
+
+/// Template file with the Map-phase summarization prompt.
+pub const SUMMARIZE_FILE: &str = "summarize.md";
+
+/// Replaced by the chat log when a template is rendered.
+const CONTEXT: &str = "{context}";
+
+/// Embedded analysis instructions (system prompt).
+const DEFAULT_ANALYZE: &str = r#"You are an expert personal assistant analyzing Telegram chat logs for the chat owner.
+
+## Your Task
+1. Summarize the key discussions and themes (2-3 concise paragraphs).
+2. Extract Action Items (see rules below), with owner and deadline if mentioned.
+3. List 3-5 key topics discussed.
+
+## Action Items: What to Extract
+
+### Explicit tasks
+- Commitments, promises, or stated to-dos (e.g., "I need to do X", "We should schedule Y", "Let me send you Z").
+- Include owner and deadline when present in the thread.
+
+### Unanswered messages (implicit tasks)
+- **Identify questions or requests directed at the chat owner** that have no visible reply in the provided chunk.
+- Look for: direct questions (@ or by name), "can you...", "could you...", "when will you...", "did you...", requests for input or approval, or follow-ups that were never answered.
+- **Format each unanswered item as a single actionable task:** "Reply to [Name] regarding [Topic]".
+  - [Name] = the person who asked (or their display name/identifier from the log).
+  - [Topic] = a short, clear summary of what they asked (e.g., "meeting time", "approval for X", "status on Y").
+- Only include an unanswered item if the chat owner appears to be the addressee and no answer is present in the log.
+- When the CSV has a ReplyTo column, a row quoting a question in it is an answer to that question.
+
+### Strict Identity Resolution (required)
+- **Never use the term "unknown" as a placeholder for a person's name** in any Action Item.
+- When generating tasks (e.g. "Reply to...", "Send CV to..."), resolve the identity using this hierarchy:
+  1. **Explicit Name:** Use the name if mentioned in the message text or signature.
+  2. **User Identifier:** If no name is textually present, you MUST use the value from the "User" column of the provided CSV (e.g. "Reply to User 12345 regarding ...").
+  3. **Contextual Role:** If the User column is missing or not an identifier, infer a specific role from the conversation (e.g. "Recruiter", "Client", "Hiring Manager") and use that (e.g. "Reply to Recruiter regarding ...").
+- **Constraint:** Any Action Item description that contains the word "unknown" referring to a person is forbidden. Use the User ID or a contextual role instead.
+
+### Validation (before output)
+- Review every action item you generated. Each must be:
+  - **Actionable:** Someone could do it without guessing (e.g., "Reply to Alex regarding budget approval" not "Follow up on thing").
+  - **Clear:** No vague references; include enough context (name/topic) so the task is unambiguous.
+  - **No "unknown" for people:** No action item may use "unknown" as a person's name; use User column value or a contextual role instead.
+- Remove or rewrite any item that fails this check. Prefer fewer, clear tasks over many vague ones.
+"#;
+
+/// Embedded Map-phase prompt.
+const DEFAULT_SUMMARIZE: &str =
+    "Summarize the following chat logs, highlighting key events and topics.\n\n{context}";
+
+/// User message wrapping the chat log when the analysis template has no `{context}`.
+const DEFAULT_ANALYZE_USER: &str = "Analyze the following chat log context for the period. It may be CSV format (Date;User;Message, plus ReplyTo quoting the message a row answers) or combined summaries from multiple chunks.\n\n{context}";
+
+/// JSON schema requirement appended to every analysis system prompt.
+const OUTPUT_FORMAT: &str = r#"## Output Format
+You MUST respond with valid JSON only. No markdown, no explanations outside JSON.
+
+```json
+{
+  "summary": "Concise summary of discussions...",
+  "key_topics": ["topic1", "topic2", "topic3"],
+  "action_items": [
+    {
+      "description": "What needs to be done (e.g. 'Reply to [Name] regarding [Topic]' for unanswered items)",
+      "owner": "Person responsible (or null)",
+      "deadline": "Due date if mentioned (or null)",
+      "priority": "high|medium|low (or null)"
+    }
+  ]
+}
+```
+
+If there are no action items, return an empty array for action_items.
+Keep summaries factual and concise. Focus on actionable information."#;
+
+/// Analysis and summarization prompts, plus an optional response language
+/// (TG_SYNC_AI_LANGUAGE). `Default` is the embedded English prompts.
+#[derive(Debug, Clone)]
+pub struct PromptTemplates {
+    analyze: String,
+    summarize: String,
+    language: Option<String>,
+}
+
+impl Default for PromptTemplates {
+    fn default() -> Self {
+        Self {
+            analyze: DEFAULT_ANALYZE.to_string(),
+            summarize: DEFAULT_SUMMARIZE.to_string(),
+            language: None,
+        }
+    }
+}
+
+impl PromptTemplates {
+    /// Load `analyze.md` and `summarize.md` from `dir`, using the defaults for missing files.
+    /// Fails on unreadable or malformed templates (blank, unknown placeholder, `summarize.md`
+    /// without `{context}`) so a bad template stops startup instead of an analysis run.
+    pub fn load(dir: &Path, language: Option<String>) -> Result<Self, DomainError> {
+        let analyze = read_template(dir, ANALYZE_FILE)?.unwrap_or_else(|| DEFAULT_ANALYZE.into());
+        let summarize =
+            read_template(dir, SUMMARIZE_FILE)?.unwrap_or_else(|| DEFAULT_SUMMARIZE.into());
+        Self::new(analyze, summarize, language)
+    }
+
+    /// Validate and build from template text.
+    pub fn new(
+        analyze: String,
+        summarize: String,
+        language: Option<String>,
+    ) -> Result<Self, DomainError> {
+        validate(ANALYZE_FILE, &analyze, false)?;
+        validate(SUMMARIZE_FILE, &summarize, true)?;
+        Ok(Self {
+            analyze,
+            summarize,
+            language: language
+                .map(|l| l.trim().to_string())
+                .filter(|l| !l.is_empty()),
+        })
+    }
+
+    /// System prompt and user message for the analysis (reduce) request.
+    pub fn analysis(&self, context: &str) -> (String, String) {
+        let system = |instructions: &str| {
+            let mut prompt = String::new();
+            if !instructions.is_empty() {
+                prompt.push_str(instructions.trim_end());
+                prompt.push_str("\n\n");
+            }
+            prompt.push_str(OUTPUT_FORMAT);
+            if let Some(language) = &self.language {
+                prompt.push_str(&format!(
+                    "\n\nRespond in {}. Keep the JSON field names exactly as shown above.",
+                    language
+                ));
+            }
+            prompt
+        };
+        if self.analyze.contains(CONTEXT) {
+            (system(""), self.analyze.replace(CONTEXT, context))
+        } else {
+            (
+                system(&self.analyze),
+                DEFAULT_ANALYZE_USER.replace(CONTEXT, context),
+            )
+        }
+    }
+
+    /// Prompt for summarizing one chunk (Map phase).
+    pub fn summarize(&self, context: &str) -> String {
+        let mut prompt = self.summarize.replace(CONTEXT, context);
+        if let Some(language) = &self.language {
+            prompt.push_str(&format!("\n\nRespond in {}.", language));
+        }
+        prompt
+    }
+}
+
+/// Contents of `dir/name`, or None when the file doesn't exist.
+fn read_template(dir: &Path, name: &str) -> Result<Option<String>, DomainError> {
+    let path = dir.join(name);
+    match std::fs::read_to_string(&path) {
+        Ok(text) => Ok(Some(text)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(DomainError::Ai(format!(
+            "cannot read prompt template {}: {}",
+            path.display(),
+            e
+        ))),
+    }
+}
+
+fn validate(name: &str, template: &str, needs_context: bool) -> Result<(), DomainError> {
+    if template.trim().is_empty() {
+        return Err(DomainError::Ai(format!(
+            "prompt template {} is empty",
+            name
+        )));
+    }
+    if let Some(unknown) = placeholders(template).find(|p| *p != CONTEXT) {
+        return Err(DomainError::Ai(format!(
+            "prompt template {}: unknown placeholder {} (only {} is supported)",
+            name, unknown, CONTEXT
+        )));
+    }
+    if needs_context && !template.contains(CONTEXT) {
+        return Err(DomainError::Ai(format!(
+            "prompt template {} must contain {}",
+            name, CONTEXT
+        )));
+    }
+    Ok(())
+}
+
+/// `{word}` tokens in `text` (lowercase letters and `_` only, so JSON examples don't count).
+fn placeholders(text: &str) -> impl Iterator<Item = &str> {
+    text.match_indices('{').filter_map(move |(start, _)| {
+        let rest = &text[start + 1..];
+        let len = rest
+            .find(|c: char| !(c.is_ascii_lowercase() || c == '_'))
+            .unwrap_or(rest.len());
+        (len > 0 && rest[len..].starts_with('}')).then(|| &text[start..start + len + 2])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_prompts_keep_output_format() {
+        let prompts = PromptTemplates::default();
+        let (system, user) = prompts.analysis("Date;User;Message");
+        assert!(system.starts_with(DEFAULT_ANALYZE.trim_end()));
+        assert!(system.ends_with(OUTPUT_FORMAT));
+        assert!(user.ends_with("\n\nDate;User;Message"));
+        assert_eq!(
+            prompts.summarize("log"),
+            "Summarize the following chat logs, highlighting key events and topics.\n\nlog"
+        );
+    }
+
+    #[test]
+    fn test_custom_templates_and_language() {
+        let prompts = PromptTemplates::new(
+            "Проанализируй чат:\n{context}".to_string(),
+            "Кратко: {context}".to_string(),
+            Some(" Russian ".to_string()),
+        )
+        .unwrap();
+        let (system, user) = prompts.analysis("csv");
+        // The JSON requirement survives a template that doesn't mention it.
+        assert!(system.starts_with("## Output Format"));
+        assert!(
+            system
+                .ends_with("Respond in Russian. Keep the JSON field names exactly as shown above.")
+        );
+        assert_eq!(user, "Проанализируй чат:\ncsv");
+        assert_eq!(prompts.summarize("x"), "Кратко: x\n\nRespond in Russian.");
+    }
+
+    #[test]
+    fn test_malformed_templates_are_rejected() {
+        let new = |a: &str, s: &str| PromptTemplates::new(a.into(), s.into(), None);
+        assert!(new("  \n", DEFAULT_SUMMARIZE).is_err());
+        assert!(new(DEFAULT_ANALYZE, "Summarize the logs").is_err());
+        assert!(new("Analyze {contxt}", DEFAULT_SUMMARIZE).is_err());
+        // JSON examples in the instructions are not placeholders.
+        assert!(new(r#"Reply like {"summary": "..."} or {}"#, DEFAULT_SUMMARIZE).is_ok());
+    }
+
+    #[test]
+    fn test_load_falls_back_per_file() {
+        let dir = std::path::PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("target")
+            .join("test_prompt_templates");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(SUMMARIZE_FILE), "Short summary please.\n{context}").unwrap();
+
+        let prompts = PromptTemplates::load(&dir, None).unwrap();
+        assert_eq!(prompts.analyze, DEFAULT_ANALYZE);
+        assert_eq!(prompts.summarize("log"), "Short summary please.\nlog");
+
+        std::fs::write(dir.join(ANALYZE_FILE), "").unwrap();
+        assert!(PromptTemplates::load(&dir, None).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,80 @@
+//! Token estimates for chunking LLM context.
+//!
+//! OpenAI models are counted with their real BPE (tiktoken); other models (Anthropic, Ollama,
+//! ...) fall back to one token per four characters.
+
+use tiktoken_rs::CoreBPE;
+use tiktoken_rs::tokenizer::{Tokenizer, get_tokenizer};
+
+/// Size limit of one CSV chunk sent to the LLM: a token budget for a given model.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkBudget {
+    /// Tokens per chunk (TG_SYNC_AI_MAX_CONTEXT_TOKENS).
+    pub max_tokens: usize,
+    /// Model the chunks are sent to; picks the tokenizer.
+    pub model: String,
+}
+
+impl ChunkBudget {
+    pub fn new(max_tokens: usize, model: impl Into<String>) -> Self {
+        Self {
+            max_tokens,
+            model: model.into(),
+        }
+    }
+
+    /// Token counter for [`Self::model`].
+    pub fn counter(&self) -> TokenCounter {
+        TokenCounter {
+            bpe: get_tokenizer(&self.model).map(bpe_for),
+        }
+    }
+}
+
+/// Counts tokens in text, exactly (tiktoken) or as an estimate (chars / 4).
+pub struct TokenCounter {
+    bpe: Option<&'static CoreBPE>,
+}
+
+impl TokenCounter {
+    pub fn count(&self, text: &str) -> usize {
+        match self.bpe {
+            Some(bpe) => bpe.encode_ordinary(text).len(),
+            None => text.chars().count().div_ceil(4),
+        }
+    }
+
+    /// True when counts come from the model's tokenizer rather than the chars/4 estimate.
+    pub fn is_exact(&self) -> bool {
+        self.bpe.is_some()
+    }
+}
+
+/// Shared tokenizer instance (loaded once per process).
+fn bpe_for(tokenizer: Tokenizer) -> &'static CoreBPE {
+    match tokenizer {
+        Tokenizer::O200kBase => tiktoken_rs::o200k_base_singleton(),
+        Tokenizer::Cl100kBase => tiktoken_rs::cl100k_base_singleton(),
+        Tokenizer::P50kBase => tiktoken_rs::p50k_base_singleton(),
+        Tokenizer::P50kEdit => tiktoken_rs::p50k_edit_singleton(),
+        Tokenizer::R50kBase | Tokenizer::Gpt2 => tiktoken_rs::r50k_base_singleton(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counter_per_model() {
+        let openai = ChunkBudget::new(1000, "gpt-4o-mini").counter();
+        assert!(openai.is_exact());
+        assert_eq!(openai.count("hello world"), 2);
+
+        let fallback = ChunkBudget::new(1000, "claude-3-5-haiku-latest").counter();
+        assert!(!fallback.is_exact());
+        assert_eq!(fallback.count("hello world"), 3);
+        // Characters, not bytes.
+        assert_eq!(fallback.count("привет"), 2);
+    }
+}
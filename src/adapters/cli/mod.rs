@@ -0,0 +1,787 @@
+//! Non-interactive command line. Inbound adapter next to the TUI.
+//!
+//! `tg-sync` with no arguments starts the interactive menu; `tg-sync <command> [flags]`
+//! runs a single maintenance/export action and exits. `watch` is the exception: it logs in
+//! and runs the Watcher without the menu. Hand-rolled parser: the command
+//! set is small and flags are `--name value`, `--name=value` or bare `--switch`.
+
+use crate::domain::ExportFormat;
+use chrono::NaiveDate;
+use std::path::PathBuf;
+
+/// Usage text printed for `--help` and on parse errors.
+pub const USAGE: &str = "\
+Usage: tg-sync [COMMAND] [OPTIONS]
+
+Without a command, starts the interactive menu.
+
+Commands:
+  media-manifest [--only-chat <ID>]   Write data/media/manifest.jsonl (downloaded media index)
+  export --chat <ID> [--format csv|md|html|json] [--from <DATE>] [--to <DATE>]
+         [--out <PATH>] [--anonymize]
+                                      Export a chat's stored messages (default data/exports/):
+                                      csv rows, or a Markdown transcript, HTML page or Telegram
+                                      Desktop JSON; --from/--to (YYYY-MM-DD, inclusive) limit
+                                      the rendered formats to those days
+  export --analysis [--chat <ID>] [--format csv] [--out <PATH>] [--anonymize]
+                                      Export saved AI analyses, one row per action item
+  ingest --file <PATH> --chat-name <NAME>
+                                      Import a Telegram Desktop JSON export or '[date] Name: text'
+                                      log into a local chat named NAME
+  import --file <PATH> [--chat <ID>] [--media]
+                                      Import a Telegram Desktop export (result.json) into the
+                                      chat it was exported from (--chat: into chat ID), keeping
+                                      message ids; the next sync continues after it; --media:
+                                      copy the exported media files into data/media/
+  analyze --chat <ID> [--latest] [--auto-push]
+                                      Analyze a chat's unanalyzed periods with the configured AI
+                                      (reports in data/reports/); --latest: only the most recent
+                                      one; --auto-push: send every action item to the task tracker
+  audit [--fix]                       Check archive consistency (DB, media files, state);
+                                      --fix re-queues media, clamps checkpoints, rebuilds FTS
+  mirror-rebuild [--chat <ID>]        Rebuild missing data/mirror/<chat_id>.jsonl files from the
+                                      database (--chat: rewrite that chat's mirror)
+  rekey [--batch <N>]                 Encrypt a plaintext archive and its media with
+                                      TG_SYNC_ENCRYPTION_KEY (or a passphrase prompt),
+                                      N messages per transaction (default 1000)
+  watch [--detach | --status]         Run the Watcher without the menu until Ctrl+C/SIGTERM
+                                      (logs in to Telegram; PID in data/watcher.pid);
+                                      --detach: run it in the background (log in
+                                      data/watcher.log); --status: show the Watcher status
+  config validate                     Check configuration (timezone, SMTP URL/TLS/credentials,
+                                      addresses, S3 storage, unknown keys) without connecting
+                                      to Telegram
+  config check                        Print the effective configuration (secrets masked) with
+                                      where each value was set, then check it like validate
+  help                                Show this message
+
+Options:
+  --force                             Run even if data/.lock is held by another instance
+  --profile <NAME>                    Use profile NAME (own data/profiles/NAME/ directory,
+                                      session and [profiles.NAME] config; default:
+                                      TG_SYNC_PROFILE, else the default profile)
+";
+
+/// A command requested on the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CliCommand {
+    /// Regenerate `media/manifest.jsonl`, optionally for a single chat.
+    MediaManifest { only_chat: Option<i64> },
+    /// Export stored data to a file.
+    Export {
+        target: ExportTarget,
+        out: Option<PathBuf>,
+        /// Pseudonymize users and redact contact data (mapping written alongside).
+        anonymize: bool,
+    },
+    /// Import a chat fragment file into a synthetic chat.
+    Ingest { file: PathBuf, chat_name: String },
+    /// Import a Telegram Desktop export into its own chat (or `chat_id`), keeping message ids;
+    /// `media` copies the exported files.
+    Import {
+        file: PathBuf,
+        chat_id: Option<i64>,
+        media: bool,
+    },
+    /// AI analysis of a chat's unanalyzed periods (only the latest with `latest`). Action items
+    /// go to the task tracker only with `auto_push`; the menu asks for each item instead.
+    Analyze {
+        chat_id: i64,
+        latest: bool,
+        auto_push: bool,
+    },
+    /// Check archive consistency; `fix` repairs what can be repaired.
+    Audit { fix: bool },
+    /// Rebuild JSONL mirror files from the database: one chat, or every chat missing one.
+    MirrorRebuild { chat_id: Option<i64> },
+    /// Encrypt messages and media stored in plaintext, `batch` messages per transaction.
+    Rekey { batch: u32 },
+    /// Check the configuration and exit non-zero on problems.
+    ConfigValidate,
+    /// Print the effective configuration, then check it like `ConfigValidate`.
+    ConfigCheck,
+    /// Run the Watcher without the menu, start it as a background process, or show its status.
+    Watch { mode: WatchMode },
+    /// Persistence load benchmark on a scratch database (hidden; not in `USAGE`).
+    Bench {
+        messages: u32,
+        max_text_len: usize,
+        /// Share of messages with media, in percent.
+        media_percent: u32,
+        /// Scratch directory; kept after the run. A temp dir (removed afterwards) when None.
+        dir: Option<PathBuf>,
+    },
+    /// Print usage.
+    Help,
+}
+
+impl CliCommand {
+    /// Commands that write to the archive and must hold the data-directory lock.
+    pub fn needs_lock(&self) -> bool {
+        matches!(
+            self,
+            CliCommand::Ingest { .. }
+                | CliCommand::Import { .. }
+                | CliCommand::Analyze { .. }
+                | CliCommand::Audit { fix: true }
+                | CliCommand::MirrorRebuild { .. }
+                | CliCommand::Rekey { .. }
+        )
+    }
+}
+
+/// What `watch` does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchMode {
+    /// Log in and run the Watcher in this process until Ctrl+C or SIGTERM.
+    Foreground,
+    /// Start `tg-sync watch` as a background process and return.
+    Detach,
+    /// Print the stored Watcher status (no login).
+    Status,
+}
+
+/// Options valid with or without a command.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GlobalFlags {
+    /// Break an existing data-directory lock (`--force`).
+    pub force: bool,
+    /// Profile to use (`--profile <name>`).
+    pub profile: Option<String>,
+}
+
+/// Split global flags (anywhere on the line) from the command arguments.
+pub fn split_global_flags<I, S>(args: I) -> Result<(GlobalFlags, Vec<String>), String>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    let mut globals = GlobalFlags::default();
+    let mut rest = Vec::new();
+    let mut args = args.into_iter().map(Into::into);
+    while let Some(arg) = args.next() {
+        if arg == "--force" {
+            globals.force = true;
+        } else if arg == "--profile" {
+            let name = args
+                .next()
+                .filter(|name| !name.starts_with("--"))
+                .ok_or("--profile requires a name")?;
+            globals.profile = Some(name);
+        } else if let Some(name) = arg.strip_prefix("--profile=") {
+            globals.profile = Some(name.to_string());
+        } else {
+            rest.push(arg);
+        }
+    }
+    Ok((globals, rest))
+}
+
+/// What `export` writes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExportTarget {
+    /// All stored messages of one chat.
+    Messages { chat_id: i64, format: ExportFormat },
+    /// Saved weekly analyses, optionally for one chat.
+    Analysis {
+        chat_id: Option<i64>,
+        format: ExportFormat,
+    },
+    /// One chat rendered by the exporter writing `format` files (one of [`CHAT_FORMATS`]),
+    /// limited to the local days `from..=to` when given.
+    Chat {
+        chat_id: i64,
+        format: String,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    },
+}
+
+/// File extensions of the rendered chat exports (`export::default_exporters`).
+pub const CHAT_FORMATS: &[&str] = &["md", "html", "json"];
+
+/// Parse process arguments (without the program name).
+/// Returns `Ok(None)` when no command was given (interactive mode).
+pub fn parse_args<I, S>(args: I) -> Result<Option<CliCommand>, String>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    let mut args = args.into_iter().map(Into::into).peekable();
+    let Some(command) = args.next() else {
+        return Ok(None);
+    };
+    // `config <action>`: the action is positional.
+    let action = if command == "config" {
+        args.next_if(|a| !a.starts_with("--"))
+    } else {
+        None
+    };
+    let flags = Flags::parse(args)?;
+
+    let cmd = match command.as_str() {
+        "media-manifest" => {
+            let only_chat = flags.parse_opt::<i64>("only-chat")?;
+            flags.finish(&["only-chat"])?;
+            CliCommand::MediaManifest { only_chat }
+        }
+        "export" => {
+            let name = flags.value("format")?.unwrap_or("csv");
+            let format = ExportFormat::from_name(name);
+            let chat_id = flags.parse_opt::<i64>("chat")?;
+            let from = flags.parse_opt::<NaiveDate>("from")?;
+            let to = flags.parse_opt::<NaiveDate>("to")?;
+            let unsupported = || format!("unsupported export format: {}", name);
+            let target = if flags.switch("analysis")? {
+                let format = format.ok_or_else(unsupported)?;
+                ExportTarget::Analysis { chat_id, format }
+            } else {
+                let chat_id = chat_id.ok_or("export requires --chat <ID> (or --analysis)")?;
+                match format {
+                    Some(format) => ExportTarget::Messages { chat_id, format },
+                    None => {
+                        let format = name.to_lowercase();
+                        if !CHAT_FORMATS.contains(&format.as_str()) {
+                            return Err(unsupported());
+                        }
+                        ExportTarget::Chat {
+                            chat_id,
+                            format,
+                            from,
+                            to,
+                        }
+                    }
+                }
+            };
+            if (from.is_some() || to.is_some()) && !matches!(target, ExportTarget::Chat { .. }) {
+                return Err("--from/--to need --format md, html or json".to_string());
+            }
+            if matches!((from, to), (Some(from), Some(to)) if from > to) {
+                return Err("--from is after --to".to_string());
+            }
+            let out = flags.value("out")?.map(PathBuf::from);
+            let anonymize = flags.switch("anonymize")?;
+            flags.finish(&[
+                "format",
+                "chat",
+                "analysis",
+                "from",
+                "to",
+                "out",
+                "anonymize",
+            ])?;
+            CliCommand::Export {
+                target,
+                out,
+                anonymize,
+            }
+        }
+        "ingest" => {
+            let file = flags
+                .value("file")?
+                .map(PathBuf::from)
+                .ok_or("ingest requires --file <PATH>")?;
+            let chat_name = flags
+                .value("chat-name")?
+                .map(String::from)
+                .ok_or("ingest requires --chat-name <NAME>")?;
+            flags.finish(&["file", "chat-name"])?;
+            CliCommand::Ingest { file, chat_name }
+        }
+        "import" => {
+            let file = flags
+                .value("file")?
+                .map(PathBuf::from)
+                .ok_or("import requires --file <PATH>")?;
+            let chat_id = flags.parse_opt::<i64>("chat")?;
+            let media = flags.switch("media")?;
+            flags.finish(&["file", "chat", "media"])?;
+            CliCommand::Import {
+                file,
+                chat_id,
+                media,
+            }
+        }
+        "analyze" => {
+            let chat_id = flags
+                .parse_opt::<i64>("chat")?
+                .ok_or("analyze requires --chat <ID>")?;
+            let latest = flags.switch("latest")?;
+            let auto_push = flags.switch("auto-push")?;
+            flags.finish(&["chat", "latest", "auto-push"])?;
+            CliCommand::Analyze {
+                chat_id,
+                latest,
+                auto_push,
+            }
+        }
+        "audit" => {
+            let fix = flags.switch("fix")?;
+            flags.finish(&["fix"])?;
+            CliCommand::Audit { fix }
+        }
+        "mirror-rebuild" => {
+            let chat_id = flags.parse_opt::<i64>("chat")?;
+            flags.finish(&["chat"])?;
+            CliCommand::MirrorRebuild { chat_id }
+        }
+        "rekey" => {
+            let batch = flags.parse_opt::<u32>("batch")?.unwrap_or(1000);
+            flags.finish(&["batch"])?;
+            if batch == 0 {
+                return Err("--batch must be at least 1".to_string());
+            }
+            CliCommand::Rekey { batch }
+        }
+        "watch" => {
+            let detach = flags.switch("detach")?;
+            let status = flags.switch("status")?;
+            flags.finish(&["detach", "status"])?;
+            let mode = match (detach, status) {
+                (true, true) => return Err("--detach and --status exclude each other".to_string()),
+                (true, false) => WatchMode::Detach,
+                (false, true) => WatchMode::Status,
+                (false, false) => WatchMode::Foreground,
+            };
+            CliCommand::Watch { mode }
+        }
+        "config" => {
+            flags.finish(&[])?;
+            match action.as_deref() {
+                Some("validate") => CliCommand::ConfigValidate,
+                Some("check") => CliCommand::ConfigCheck,
+                Some(other) => return Err(format!("unknown config action: {}", other)),
+                None => return Err("config requires an action: validate or check".to_string()),
+            }
+        }
+        "bench" => {
+            let messages = flags.parse_opt::<u32>("messages")?.unwrap_or(100_000);
+            let max_text_len = flags.parse_opt::<usize>("max-text")?.unwrap_or(400);
+            let media_percent = flags.parse_opt::<u32>("media-percent")?.unwrap_or(15);
+            if media_percent > 100 {
+                return Err("--media-percent must be between 0 and 100".to_string());
+            }
+            let dir = flags.value("dir")?.map(PathBuf::from);
+            flags.finish(&["messages", "max-text", "media-percent", "dir"])?;
+            CliCommand::Bench {
+                messages,
+                max_text_len,
+                media_percent,
+                dir,
+            }
+        }
+        "help" | "--help" | "-h" => CliCommand::Help,
+        other => return Err(format!("unknown command: {}", other)),
+    };
+    Ok(Some(cmd))
+}
+
+/// `--name value` / `--name=value` pairs and bare `--switch`es, in order of appearance.
+struct Flags {
+    pairs: Vec<(String, Option<String>)>,
+}
+
+impl Flags {
+    fn parse(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut pairs: Vec<(String, Option<String>)> = Vec::new();
+        for arg in args {
+            if let Some(name) = arg.strip_prefix("--") {
+                match name.split_once('=') {
+                    Some((n, v)) => pairs.push((n.to_string(), Some(v.to_string()))),
+                    None => pairs.push((name.to_string(), None)),
+                }
+            } else {
+                match pairs.last_mut() {
+                    Some((_, value @ None)) => *value = Some(arg),
+                    _ => return Err(format!("unexpected argument: {}", arg)),
+                }
+            }
+        }
+        Ok(Self { pairs })
+    }
+
+    fn value(&self, name: &str) -> Result<Option<&str>, String> {
+        match self.pairs.iter().rev().find(|(n, _)| n == name) {
+            None => Ok(None),
+            Some((_, Some(v))) => Ok(Some(v.as_str())),
+            Some((_, None)) => Err(format!("--{} requires a value", name)),
+        }
+    }
+
+    fn parse_opt<T: std::str::FromStr>(&self, name: &str) -> Result<Option<T>, String> {
+        self.value(name)?
+            .map(|v| {
+                v.parse::<T>()
+                    .map_err(|_| format!("invalid value for --{}: {}", name, v))
+            })
+            .transpose()
+    }
+
+    /// Bare `--name` switch. Present with a value is an error.
+    fn switch(&self, name: &str) -> Result<bool, String> {
+        match self.pairs.iter().rev().find(|(n, _)| n == name) {
+            None => Ok(false),
+            Some((_, None)) => Ok(true),
+            Some((_, Some(_))) => Err(format!("--{} takes no value", name)),
+        }
+    }
+
+    /// Reject flags the command does not know.
+    fn finish(&self, known: &[&str]) -> Result<(), String> {
+        match self
+            .pairs
+            .iter()
+            .find(|(n, _)| !known.contains(&n.as_str()))
+        {
+            Some((n, _)) => Err(format!("unknown option: --{}", n)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// (arguments, expected parse result or error message)
+    type Case = (
+        &'static [&'static str],
+        Result<Option<CliCommand>, &'static str>,
+    );
+
+    #[test]
+    fn test_parse_args_commands() {
+        let cases: &[Case] = &[
+            (&[], Ok(None)),
+            (&["help"], Ok(Some(CliCommand::Help))),
+            (&["--help"], Ok(Some(CliCommand::Help))),
+            (
+                &["media-manifest"],
+                Ok(Some(CliCommand::MediaManifest { only_chat: None })),
+            ),
+            (
+                &["media-manifest", "--only-chat", "-100123"],
+                Ok(Some(CliCommand::MediaManifest {
+                    only_chat: Some(-100123),
+                })),
+            ),
+            (
+                &["media-manifest", "--only-chat=42"],
+                Ok(Some(CliCommand::MediaManifest {
+                    only_chat: Some(42),
+                })),
+            ),
+            (&["nope"], Err("unknown command: nope")),
+            (
+                &["media-manifest", "--only-chat"],
+                Err("--only-chat requires a value"),
+            ),
+            (
+                &["media-manifest", "--only-chat", "abc"],
+                Err("invalid value for --only-chat: abc"),
+            ),
+            (
+                &["media-manifest", "--bogus", "1"],
+                Err("unknown option: --bogus"),
+            ),
+            (
+                &["media-manifest", "stray"],
+                Err("unexpected argument: stray"),
+            ),
+            (
+                &["export", "--chat", "5"],
+                Ok(Some(CliCommand::Export {
+                    target: ExportTarget::Messages {
+                        chat_id: 5,
+                        format: ExportFormat::Csv,
+                    },
+                    out: None,
+                    anonymize: false,
+                })),
+            ),
+            (
+                &["export", "--format", "CSV", "--chat=5", "--out", "x.csv"],
+                Ok(Some(CliCommand::Export {
+                    target: ExportTarget::Messages {
+                        chat_id: 5,
+                        format: ExportFormat::Csv,
+                    },
+                    out: Some(PathBuf::from("x.csv")),
+                    anonymize: false,
+                })),
+            ),
+            (
+                &["export", "--analysis", "--anonymize"],
+                Ok(Some(CliCommand::Export {
+                    target: ExportTarget::Analysis {
+                        chat_id: None,
+                        format: ExportFormat::Csv,
+                    },
+                    out: None,
+                    anonymize: true,
+                })),
+            ),
+            (
+                &[
+                    "export",
+                    "--chat",
+                    "5",
+                    "--format",
+                    "MD",
+                    "--from",
+                    "2024-03-01",
+                ],
+                Ok(Some(CliCommand::Export {
+                    target: ExportTarget::Chat {
+                        chat_id: 5,
+                        format: "md".to_string(),
+                        from: NaiveDate::from_ymd_opt(2024, 3, 1),
+                        to: None,
+                    },
+                    out: None,
+                    anonymize: false,
+                })),
+            ),
+            (
+                &[
+                    "export",
+                    "--chat",
+                    "5",
+                    "--format",
+                    "html",
+                    "--to=2024-03-31",
+                ],
+                Ok(Some(CliCommand::Export {
+                    target: ExportTarget::Chat {
+                        chat_id: 5,
+                        format: "html".to_string(),
+                        from: None,
+                        to: NaiveDate::from_ymd_opt(2024, 3, 31),
+                    },
+                    out: None,
+                    anonymize: false,
+                })),
+            ),
+            (
+                &["export", "--chat", "5", "--from", "2024-03-01"],
+                Err("--from/--to need --format md, html or json"),
+            ),
+            (
+                &["export", "--analysis", "--format", "md"],
+                Err("unsupported export format: md"),
+            ),
+            (
+                &[
+                    "export",
+                    "--chat",
+                    "5",
+                    "--format",
+                    "md",
+                    "--from",
+                    "2024-03-02",
+                    "--to",
+                    "2024-03-01",
+                ],
+                Err("--from is after --to"),
+            ),
+            (
+                &["export", "--chat", "5", "--format", "md", "--from", "March"],
+                Err("invalid value for --from: March"),
+            ),
+            (
+                &["export"],
+                Err("export requires --chat <ID> (or --analysis)"),
+            ),
+            (
+                &["export", "--chat", "5", "--format", "xls"],
+                Err("unsupported export format: xls"),
+            ),
+            (
+                &["export", "--analysis", "yes"],
+                Err("--analysis takes no value"),
+            ),
+            (
+                &["ingest", "--file", "chat.txt", "--chat-name", "Project X"],
+                Ok(Some(CliCommand::Ingest {
+                    file: PathBuf::from("chat.txt"),
+                    chat_name: "Project X".to_string(),
+                })),
+            ),
+            (
+                &["ingest", "--chat-name=X"],
+                Err("ingest requires --file <PATH>"),
+            ),
+            (
+                &["ingest", "--file", "chat.txt"],
+                Err("ingest requires --chat-name <NAME>"),
+            ),
+            (
+                &["import", "--file", "export/result.json", "--media"],
+                Ok(Some(CliCommand::Import {
+                    file: PathBuf::from("export/result.json"),
+                    chat_id: None,
+                    media: true,
+                })),
+            ),
+            (
+                &["import", "--file=result.json", "--chat", "-1001234"],
+                Ok(Some(CliCommand::Import {
+                    file: PathBuf::from("result.json"),
+                    chat_id: Some(-1001234),
+                    media: false,
+                })),
+            ),
+            (&["import", "--media"], Err("import requires --file <PATH>")),
+            (
+                &["analyze", "--chat", "42", "--auto-push"],
+                Ok(Some(CliCommand::Analyze {
+                    chat_id: 42,
+                    latest: false,
+                    auto_push: true,
+                })),
+            ),
+            (
+                &["analyze", "--chat=-1001", "--latest"],
+                Ok(Some(CliCommand::Analyze {
+                    chat_id: -1001,
+                    latest: true,
+                    auto_push: false,
+                })),
+            ),
+            (&["analyze"], Err("analyze requires --chat <ID>")),
+            (&["audit"], Ok(Some(CliCommand::Audit { fix: false }))),
+            (
+                &["audit", "--fix"],
+                Ok(Some(CliCommand::Audit { fix: true })),
+            ),
+            (&["audit", "--fix=1"], Err("--fix takes no value")),
+            (
+                &["mirror-rebuild"],
+                Ok(Some(CliCommand::MirrorRebuild { chat_id: None })),
+            ),
+            (
+                &["mirror-rebuild", "--chat", "-1001"],
+                Ok(Some(CliCommand::MirrorRebuild {
+                    chat_id: Some(-1001),
+                })),
+            ),
+            (&["rekey"], Ok(Some(CliCommand::Rekey { batch: 1000 }))),
+            (
+                &["rekey", "--batch=200"],
+                Ok(Some(CliCommand::Rekey { batch: 200 })),
+            ),
+            (
+                &["rekey", "--batch", "0"],
+                Err("--batch must be at least 1"),
+            ),
+            (
+                &["config", "validate"],
+                Ok(Some(CliCommand::ConfigValidate)),
+            ),
+            (&["config", "check"], Ok(Some(CliCommand::ConfigCheck))),
+            (
+                &["config"],
+                Err("config requires an action: validate or check"),
+            ),
+            (
+                &["watch"],
+                Ok(Some(CliCommand::Watch {
+                    mode: WatchMode::Foreground,
+                })),
+            ),
+            (
+                &["watch", "--detach"],
+                Ok(Some(CliCommand::Watch {
+                    mode: WatchMode::Detach,
+                })),
+            ),
+            (
+                &["watch", "--status"],
+                Ok(Some(CliCommand::Watch {
+                    mode: WatchMode::Status,
+                })),
+            ),
+            (
+                &["watch", "--status", "--detach"],
+                Err("--detach and --status exclude each other"),
+            ),
+            (&["config", "show"], Err("unknown config action: show")),
+            (
+                &["config", "validate", "--fix"],
+                Err("unknown option: --fix"),
+            ),
+            (
+                &["bench"],
+                Ok(Some(CliCommand::Bench {
+                    messages: 100_000,
+                    max_text_len: 400,
+                    media_percent: 15,
+                    dir: None,
+                })),
+            ),
+            (
+                &[
+                    "bench",
+                    "--messages",
+                    "2000000",
+                    "--media-percent=40",
+                    "--dir",
+                    "/tmp/b",
+                ],
+                Ok(Some(CliCommand::Bench {
+                    messages: 2_000_000,
+                    max_text_len: 400,
+                    media_percent: 40,
+                    dir: Some(PathBuf::from("/tmp/b")),
+                })),
+            ),
+            (
+                &["bench", "--media-percent", "150"],
+                Err("--media-percent must be between 0 and 100"),
+            ),
+        ];
+        for (args, expected) in cases {
+            let got = parse_args(args.iter().copied());
+            let expected = expected.clone().map_err(String::from);
+            assert_eq!(got, expected, "parse_args({:?})", args);
+        }
+    }
+
+    #[test]
+    fn test_split_global_flags() {
+        let (globals, rest) = split_global_flags(["--force"]).unwrap();
+        assert!(globals.force);
+        assert!(rest.is_empty());
+
+        let (globals, rest) = split_global_flags(["audit", "--force", "--fix"]).unwrap();
+        assert!(globals.force);
+        assert_eq!(rest, vec!["audit", "--fix"]);
+        assert_eq!(parse_args(rest), Ok(Some(CliCommand::Audit { fix: true })));
+
+        let (globals, _) = split_global_flags(["export", "--chat", "1"]).unwrap();
+        assert_eq!(globals, GlobalFlags::default());
+
+        let (globals, rest) = split_global_flags(["--profile", "work", "watch"]).unwrap();
+        assert_eq!(globals.profile.as_deref(), Some("work"));
+        assert_eq!(rest, vec!["watch"]);
+        let (globals, _) = split_global_flags(["audit", "--profile=home"]).unwrap();
+        assert_eq!(globals.profile.as_deref(), Some("home"));
+        assert_eq!(
+            split_global_flags(["--profile", "--force"]),
+            Err("--profile requires a name".to_string())
+        );
+        assert!(split_global_flags(["--profile"]).is_err());
+        assert!(CliCommand::Audit { fix: true }.needs_lock());
+        assert!(!CliCommand::Audit { fix: false }.needs_lock());
+    }
+
+    #[test]
+    fn test_chat_formats_have_exporters() {
+        let exporters = crate::adapters::export::default_exporters(0);
+        let mut extensions: Vec<&str> = exporters.iter().map(|e| e.extension()).collect();
+        extensions.sort_unstable();
+        let mut formats = CHAT_FORMATS.to_vec();
+        formats.sort_unstable();
+        assert_eq!(extensions, formats);
+    }
+}
@@ -0,0 +1,582 @@
+//! Telegram Desktop "Export chat history" JSON (`result.json`).
+//!
+//! Writes the subset of the official schema that downstream tools read: chat `name`, `type`
+//! and `id`, then one `"type": "message"` entry per message with local `date` and
+//! `date_unixtime`, `from`/`from_id`, `text` plus `text_entities`, `reply_to_message_id`, and
+//! media as `photo` or `file` (+ `media_type`). Media that was not downloaded is written as
+//! [`FILE_NOT_INCLUDED`], like Desktop does when files are excluded from an export. Messages of
+//! a forum topic also carry a `topic` name, which Desktop does not write; readers of the
+//! official schema ignore it. Forwards carry Desktop's `forwarded_from` (the original author's
+//! name, null when unknown). Chat events are written between the messages as Desktop's
+//! `"type": "service"` entries with `actor`/`actor_id` and an `action`. Channel posts with
+//! archived comments carry them as a `comments` array of message entries (not in Desktop's
+//! schema either; their ids and replies are the discussion group's).
+
+use crate::domain::{
+    ChatEventKind, ChatType, DomainError, ExportChat, ExportEvent, ExportMessage, MediaType,
+};
+use crate::ports::{ChatExportWriter, ExporterPort};
+use chrono::{DateTime, FixedOffset};
+use serde::Serialize;
+use std::io::Write;
+
+/// Placeholder path for media that is not in the export.
+pub const FILE_NOT_INCLUDED: &str = "(File not included)";
+
+/// Writes a chat as Telegram Desktop `result.json`. Local dates use `utc_offset_secs`.
+pub struct JsonExporter {
+    utc_offset_secs: i32,
+}
+
+impl JsonExporter {
+    pub fn new(utc_offset_secs: i32) -> Self {
+        Self { utc_offset_secs }
+    }
+}
+
+impl ExporterPort for JsonExporter {
+    fn name(&self) -> &'static str {
+        "Telegram Desktop JSON"
+    }
+
+    fn extension(&self) -> &'static str {
+        "json"
+    }
+
+    fn begin(
+        &self,
+        chat: &ExportChat,
+        mut out: Box<dyn Write + Send>,
+    ) -> Result<Box<dyn ChatExportWriter>, DomainError> {
+        write!(
+            out,
+            "{{\n \"name\": {},\n \"type\": \"{}\",\n \"id\": {},\n \"messages\": [",
+            serde_json::to_string(&chat.title).map_err(json_err)?,
+            chat_type_name(chat.kind),
+            chat.id
+        )
+        .map_err(write_err)?;
+        Ok(Box::new(JsonChatWriter {
+            out,
+            chat_id: chat.id,
+            chat_title: chat.title.clone(),
+            is_channel: chat.kind == ChatType::Channel,
+            utc_offset_secs: self.utc_offset_secs,
+            count: 0,
+        }))
+    }
+}
+
+struct JsonChatWriter {
+    out: Box<dyn Write + Send>,
+    chat_id: i64,
+    /// `from` of channel posts.
+    chat_title: String,
+    is_channel: bool,
+    utc_offset_secs: i32,
+    count: u64,
+}
+
+/// One entry of `messages`, in Desktop's field order.
+#[derive(Serialize)]
+struct DesktopMessage<'a> {
+    id: i32,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    date: String,
+    date_unixtime: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    from: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    from_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    forwarded_from: Option<Option<&'a str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_to_message_id: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    topic: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    photo: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_name: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    media_type: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mime_type: Option<&'a str>,
+    text: &'a str,
+    text_entities: Vec<TextEntity<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    comments: Vec<DesktopMessage<'a>>,
+}
+
+/// A `"type": "service"` entry of `messages`.
+#[derive(Serialize)]
+struct DesktopService<'a> {
+    id: i32,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    date: String,
+    date_unixtime: String,
+    actor: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    actor_id: Option<String>,
+    action: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message_id: Option<i32>,
+    text: &'a str,
+    text_entities: Vec<TextEntity<'a>>,
+}
+
+#[derive(Serialize)]
+struct TextEntity<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    text: &'a str,
+}
+
+impl JsonChatWriter {
+    /// Entry of `m`; `in_thread` for comments, which are never posted as the channel.
+    fn entry<'a>(&self, m: &'a ExportMessage, in_thread: bool) -> DesktopMessage<'a> {
+        let msg = &m.message;
+        let (from, from_id) = match msg.from_user_id {
+            Some(id) => (Some(m.sender_name.clone()), Some(format!("user{}", id))),
+            None if self.is_channel && !in_thread => (
+                Some(Some(self.chat_title.clone())),
+                Some(format!("channel{}", self.chat_id)),
+            ),
+            None => (None, None),
+        };
+        let path = m.media_path.as_deref().unwrap_or(FILE_NOT_INCLUDED);
+        let (photo, file, media_type) = match msg.media.as_ref().map(|r| r.media_type) {
+            None => (None, None, None),
+            Some(MediaType::Photo) => (Some(path), None, None),
+            Some(t) => (None, Some(path), desktop_media_type(t)),
+        };
+        DesktopMessage {
+            id: msg.id,
+            kind: "message",
+            date: local_date(msg.date, self.utc_offset_secs),
+            date_unixtime: msg.date.to_string(),
+            from,
+            from_id,
+            forwarded_from: msg
+                .forwarded_date
+                .map(|_| msg.forwarded_from_name.as_deref()),
+            reply_to_message_id: msg.reply_to_msg_id,
+            topic: msg.topic_title.as_deref(),
+            photo,
+            file,
+            file_name: msg.media.as_ref().and_then(|r| r.filename.as_deref()),
+            media_type,
+            mime_type: msg.media.as_ref().and_then(|r| r.mime_type.as_deref()),
+            text: &msg.text,
+            text_entities: if msg.text.is_empty() {
+                Vec::new()
+            } else {
+                vec![TextEntity {
+                    kind: "plain",
+                    text: &msg.text,
+                }]
+            },
+            comments: m.comments.iter().map(|c| self.entry(c, true)).collect(),
+        }
+    }
+}
+
+impl ChatExportWriter for JsonChatWriter {
+    fn write_batch(&mut self, messages: &[ExportMessage]) -> Result<(), DomainError> {
+        let mut json = String::new();
+        for m in messages {
+            json.push_str(if self.count == 0 { "\n  " } else { ",\n  " });
+            json.push_str(&serde_json::to_string(&self.entry(m, false)).map_err(json_err)?);
+            self.count += 1;
+        }
+        self.out.write_all(json.as_bytes()).map_err(write_err)?;
+        self.out.flush().map_err(write_err)
+    }
+
+    fn write_event(&mut self, event: &ExportEvent) -> Result<(), DomainError> {
+        let e = &event.event;
+        let entry = DesktopService {
+            id: e.id,
+            kind: "service",
+            date: local_date(e.date, self.utc_offset_secs),
+            date_unixtime: e.date.to_string(),
+            actor: event.actor_name.as_deref(),
+            actor_id: e.actor_id.map(|id| format!("user{}", id)),
+            action: desktop_action(e.kind, e.payload.is_some()),
+            title: (e.kind == ChatEventKind::TitleChanged)
+                .then_some(e.payload.as_deref())
+                .flatten(),
+            message_id: (e.kind == ChatEventKind::MessagePinned)
+                .then(|| e.payload.as_deref()?.parse().ok())
+                .flatten(),
+            text: "",
+            text_entities: Vec::new(),
+        };
+        let sep = if self.count == 0 { "\n  " } else { ",\n  " };
+        let json = serde_json::to_string(&entry).map_err(json_err)?;
+        self.count += 1;
+        write!(self.out, "{}{}", sep, json).map_err(write_err)
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), DomainError> {
+        let close = if self.count == 0 {
+            "]\n}\n"
+        } else {
+            "\n ]\n}\n"
+        };
+        self.out.write_all(close.as_bytes()).map_err(write_err)?;
+        self.out.flush().map_err(write_err)
+    }
+}
+
+/// Desktop's chat `type` for a chat kind (public/private is not known here).
+fn chat_type_name(kind: ChatType) -> &'static str {
+    match kind {
+        ChatType::Private => "personal_chat",
+        ChatType::Group => "private_group",
+        ChatType::Supergroup => "private_supergroup",
+        ChatType::Channel => "private_channel",
+    }
+}
+
+/// Desktop's `action` of a service entry. A join without added users is a join by link.
+fn desktop_action(kind: ChatEventKind, has_payload: bool) -> &'static str {
+    match kind {
+        ChatEventKind::UserJoined if has_payload => "invite_members",
+        ChatEventKind::UserJoined => "join_group_by_link",
+        ChatEventKind::UserLeft => "remove_members",
+        ChatEventKind::TitleChanged => "edit_group_title",
+        ChatEventKind::MessagePinned => "pin_message",
+        ChatEventKind::CallStarted => "group_call",
+        ChatEventKind::Other => "unknown",
+    }
+}
+
+/// Desktop's `media_type` of a `file` entry. Plain documents have none.
+fn desktop_media_type(media_type: MediaType) -> Option<&'static str> {
+    match media_type {
+        MediaType::Video => Some("video_file"),
+        MediaType::Voice => Some("voice_message"),
+        MediaType::Audio => Some("audio_file"),
+        MediaType::Sticker => Some("sticker"),
+        MediaType::Animation => Some("animation"),
+        MediaType::VideoNote => Some("video_message"),
+        MediaType::Photo | MediaType::Document | MediaType::Other => None,
+    }
+}
+
+/// Zone-less local time, e.g. `2024-01-05T10:01:00`.
+fn local_date(ts: i64, utc_offset_secs: i32) -> String {
+    FixedOffset::east_opt(utc_offset_secs)
+        .zip(DateTime::from_timestamp(ts, 0))
+        .map(|(tz, dt)| {
+            dt.with_timezone(&tz)
+                .format("%Y-%m-%dT%H:%M:%S")
+                .to_string()
+        })
+        .unwrap_or_else(|| ts.to_string())
+}
+
+fn json_err(e: serde_json::Error) -> DomainError {
+    DomainError::Repo(format!("Failed to encode JSON export: {}", e))
+}
+
+fn write_err(e: std::io::Error) -> DomainError {
+    DomainError::Repo(format!("Failed to write JSON export: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::ingest::DesktopJsonParser;
+    use crate::domain::{ChatEvent, MediaReference, Message};
+    use crate::ports::FragmentParser;
+    use crate::testing::fake_tg::text_message;
+    use serde::Deserialize;
+    use std::sync::{Arc, Mutex};
+
+    /// Mirror of the official `result.json` fields this exporter writes.
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct DesktopExport {
+        name: String,
+        #[serde(rename = "type")]
+        kind: String,
+        id: i64,
+        messages: Vec<DesktopEntry>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct DesktopEntry {
+        id: i64,
+        #[serde(rename = "type")]
+        kind: String,
+        date: String,
+        date_unixtime: String,
+        #[serde(default)]
+        from: Option<String>,
+        from_id: Option<String>,
+        #[serde(default)]
+        forwarded_from: Option<String>,
+        reply_to_message_id: Option<i64>,
+        photo: Option<String>,
+        file: Option<String>,
+        media_type: Option<String>,
+        text: DesktopText,
+        text_entities: Vec<DesktopEntity>,
+        #[serde(default)]
+        comments: Vec<DesktopEntry>,
+    }
+
+    /// `text` is a string or an array of strings and entities.
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum DesktopText {
+        Plain(String),
+        Parts(Vec<serde_json::Value>),
+    }
+
+    impl DesktopText {
+        fn flatten(&self) -> String {
+            match self {
+                DesktopText::Plain(s) => s.clone(),
+                DesktopText::Parts(parts) => parts
+                    .iter()
+                    .filter_map(|p| p.as_str().or_else(|| p.get("text")?.as_str()))
+                    .collect(),
+            }
+        }
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct DesktopEntity {
+        #[serde(rename = "type")]
+        kind: String,
+        text: String,
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn export_message(id: i32, from: Option<i64>, text: &str) -> ExportMessage {
+        ExportMessage {
+            message: Message {
+                from_user_id: from,
+                ..text_message(42, id, 1704448860 + id as i64, text)
+            },
+            sender_name: from.map(|_| "alice".to_string()),
+            is_outgoing: false,
+            media_path: None,
+            reply_to: None,
+            comments: Vec::new(),
+        }
+    }
+
+    fn with_media(
+        mut m: ExportMessage,
+        media_type: MediaType,
+        path: Option<&str>,
+    ) -> ExportMessage {
+        m.message.media = Some(MediaReference {
+            message_id: m.message.id,
+            chat_id: 42,
+            media_type,
+            opaque_ref: String::new(),
+            size_bytes: None,
+            filename: None,
+            mime_type: None,
+        });
+        m.media_path = path.map(String::from);
+        m
+    }
+
+    fn export(kind: ChatType, batches: &[Vec<ExportMessage>]) -> String {
+        let chat = ExportChat {
+            id: 42,
+            title: "Project \"X\"".to_string(),
+            kind,
+            range: None,
+            exported_at: 0,
+        };
+        let buf = SharedBuf::default();
+        let mut writer = JsonExporter::new(3600)
+            .begin(&chat, Box::new(buf.clone()))
+            .unwrap();
+        for batch in batches {
+            writer.write_batch(batch).unwrap();
+        }
+        writer.finish().unwrap();
+        let bytes = buf.0.lock().unwrap().clone();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_output_matches_desktop_schema() {
+        let mut reply = export_message(3, Some(7), "line \"one\"\nline two");
+        reply.message.reply_to_msg_id = Some(1);
+        reply.message.forwarded_from_name = Some("Daily News".to_string());
+        reply.message.forwarded_date = Some(1704000000);
+        let json = export(
+            ChatType::Group,
+            &[
+                vec![
+                    export_message(1, Some(7), "Hello team"),
+                    with_media(
+                        export_message(2, Some(8), ""),
+                        MediaType::Photo,
+                        Some("../media/42_2.jpg"),
+                    ),
+                ],
+                vec![
+                    reply,
+                    with_media(export_message(4, Some(7), "clip"), MediaType::Video, None),
+                ],
+            ],
+        );
+
+        let parsed: DesktopExport = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.name, "Project \"X\"");
+        assert_eq!(parsed.kind, "private_group");
+        assert_eq!(parsed.id, 42);
+        assert_eq!(parsed.messages.len(), 4);
+
+        let first = &parsed.messages[0];
+        assert_eq!(first.id, 1);
+        assert_eq!(first.kind, "message");
+        // UTC+1: 10:01:01 UTC is 11:01:01 local.
+        assert_eq!(first.date, "2024-01-05T11:01:01");
+        assert_eq!(first.date_unixtime, "1704448861");
+        assert_eq!(first.from.as_deref(), Some("alice"));
+        assert_eq!(first.from_id.as_deref(), Some("user7"));
+        assert!(matches!(&first.text, DesktopText::Plain(_)));
+        assert_eq!(first.text.flatten(), "Hello team");
+        assert_eq!(first.text_entities.len(), 1);
+        assert_eq!(first.text_entities[0].kind, "plain");
+        assert_eq!(first.text_entities[0].text, "Hello team");
+
+        let photo = &parsed.messages[1];
+        assert_eq!(photo.photo.as_deref(), Some("../media/42_2.jpg"));
+        assert!(photo.file.is_none());
+        assert!(photo.text_entities.is_empty());
+
+        assert_eq!(parsed.messages[2].reply_to_message_id, Some(1));
+        assert_eq!(
+            parsed.messages[2].forwarded_from.as_deref(),
+            Some("Daily News")
+        );
+        assert!(parsed.messages[1].forwarded_from.is_none());
+        assert_eq!(parsed.messages[2].text.flatten(), "line \"one\"\nline two");
+        let video = &parsed.messages[3];
+        assert_eq!(video.file.as_deref(), Some(FILE_NOT_INCLUDED));
+        assert_eq!(video.media_type.as_deref(), Some("video_file"));
+        assert!(video.photo.is_none());
+
+        // Our own importer reads it back.
+        let fragment = DesktopJsonParser::new(3600).parse(&json).unwrap();
+        let texts: Vec<&str> = fragment.messages.iter().map(|m| m.text.as_str()).collect();
+        assert_eq!(texts, vec!["Hello team", "line \"one\"\nline two", "clip"]);
+        assert_eq!(fragment.messages[0].date, 1704448861);
+    }
+
+    #[test]
+    fn test_channel_posts_and_empty_export() {
+        let mut news = export_message(1, None, "news");
+        let mut reply = export_message(8, Some(7), "thanks");
+        reply.message.reply_to_msg_id = Some(3);
+        news.comments = vec![export_message(3, None, "first"), reply];
+        let json = export(ChatType::Channel, &[vec![news]]);
+        let parsed: DesktopExport = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.kind, "private_channel");
+        let post = &parsed.messages[0];
+        assert_eq!(post.from.as_deref(), Some("Project \"X\""));
+        assert_eq!(post.from_id.as_deref(), Some("channel42"));
+        let comments: Vec<(i64, &str)> = post
+            .comments
+            .iter()
+            .map(|c| (c.id, c.text_entities[0].text.as_str()))
+            .collect();
+        assert_eq!(comments, vec![(3, "first"), (8, "thanks")]);
+        // An anonymous comment is not attributed to the channel.
+        assert!(post.comments[0].from_id.is_none());
+        assert_eq!(post.comments[1].reply_to_message_id, Some(3));
+        assert!(!json.contains("\"comments\":[]"));
+
+        let empty: DesktopExport = serde_json::from_str(&export(ChatType::Private, &[])).unwrap();
+        assert_eq!(empty.kind, "personal_chat");
+        assert!(empty.messages.is_empty());
+    }
+
+    #[test]
+    fn test_events_are_service_entries() {
+        let chat = ExportChat {
+            id: 42,
+            title: "Team".to_string(),
+            kind: ChatType::Group,
+            range: None,
+            exported_at: 0,
+        };
+        let event = |id, kind, payload: Option<&str>| ExportEvent {
+            event: ChatEvent {
+                id,
+                chat_id: 42,
+                date: 1704448860 + id as i64,
+                kind,
+                actor_id: Some(7),
+                payload: payload.map(String::from),
+            },
+            actor_name: Some("alice".to_string()),
+            text: String::new(),
+        };
+        let buf = SharedBuf::default();
+        let mut writer = JsonExporter::new(0)
+            .begin(&chat, Box::new(buf.clone()))
+            .unwrap();
+        writer
+            .write_event(&event(1, ChatEventKind::TitleChanged, Some("Team")))
+            .unwrap();
+        writer
+            .write_batch(&[export_message(2, Some(7), "hi")])
+            .unwrap();
+        writer
+            .write_event(&event(3, ChatEventKind::MessagePinned, Some("2")))
+            .unwrap();
+        writer.finish().unwrap();
+        let json = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let entries = parsed["messages"].as_array().unwrap();
+        let kinds: Vec<&str> = entries
+            .iter()
+            .map(|e| e["type"].as_str().unwrap())
+            .collect();
+        assert_eq!(kinds, vec!["service", "message", "service"]);
+        assert_eq!(entries[0]["action"], "edit_group_title");
+        assert_eq!(entries[0]["title"], "Team");
+        assert_eq!(entries[0]["actor"], "alice");
+        assert_eq!(entries[0]["actor_id"], "user7");
+        assert_eq!(entries[2]["action"], "pin_message");
+        assert_eq!(entries[2]["message_id"], 2);
+
+        // The importer skips service entries.
+        let fragment = DesktopJsonParser::new(0).parse(&json).unwrap();
+        assert_eq!(fragment.messages.len(), 1);
+    }
+}
@@ -0,0 +1,615 @@
+//! Standalone HTML chat export.
+//!
+//! One self-contained file (inline CSS, no scripts): messages in chronological order with
+//! day separators, sender, local time and forum topic, the original author of forwards, reply
+//! quotes linking to the quoted message, and downloaded media linked by relative path (images shown as lazy-loaded
+//! thumbnails). An album is one message with its media side by side and the shared caption.
+//! Service events (joins, leaves, renames, pins, calls) sit between the messages as
+//! centered notes. Channel posts are followed by their archived comments, indented.
+
+use crate::domain::{ChatType, DomainError, ExportChat, ExportEvent, ExportMessage, MediaType};
+use crate::ports::{ChatExportWriter, ExporterPort};
+use crate::shared::activity;
+use crate::shared::markdown::escape;
+use std::io::Write;
+
+/// Quoted reply text is cut to this many characters.
+const QUOTE_CHARS: usize = 120;
+
+const STYLE: &str = "\
+body{margin:0;background:#f4f4f7;color:#1c1c1e;font:15px/1.45 -apple-system,'Segoe UI',Roboto,sans-serif}
+header{background:#2b2d42;color:#fff;padding:16px 24px}
+header h1{margin:0;font-size:20px}
+header p{margin:4px 0 0;font-size:13px;opacity:.75}
+main{max-width:760px;margin:0 auto;padding:12px 16px 32px}
+.day{text-align:center;margin:18px 0 8px;font-size:13px;color:#6e6e73}
+.event{text-align:center;margin:6px 0;font-size:13px;color:#6e6e73;font-style:italic}
+.event .time{font-style:normal}
+.msg{background:#fff;border-radius:10px;padding:8px 12px;margin:6px 48px 6px 0;box-shadow:0 1px 1px rgba(0,0,0,.06)}
+.msg.out{background:#e3f2dc;margin:6px 0 6px 48px}
+.head{font-size:13px;margin-bottom:2px}
+.from{font-weight:600;color:#3a5ba0}
+.time{color:#8e8e93;text-decoration:none;margin-left:6px}
+.topic{font-size:12px;color:#6e6e73;margin-left:6px}
+.reply{border-left:3px solid #3a5ba0;margin:4px 0;padding:2px 8px;font-size:13px;color:#555}
+.reply a{color:#3a5ba0;text-decoration:none;font-weight:600}
+.fwd{font-size:13px;color:#3a5ba0;margin:2px 0}
+.text{white-space:pre-wrap;word-wrap:break-word}
+.media img{max-width:320px;max-height:320px;border-radius:6px;display:block;margin:4px 0}
+.album{display:flex;flex-wrap:wrap;gap:4px;align-items:flex-start}
+.album img{max-width:156px;max-height:156px}
+.file{font-size:13px}
+.missing{color:#8e8e93;font-style:italic}
+.comments{margin:0 48px 10px 24px;padding-left:10px;border-left:2px solid #d0d4e4}
+.comments .count{font-size:12px;color:#6e6e73}
+.comments .msg{margin:4px 0;background:#fafafc}
+footer{text-align:center;font-size:12px;color:#8e8e93;padding:16px}
+";
+
+/// Writes a chat as a single HTML page. Times are shown at `utc_offset_secs`.
+pub struct HtmlExporter {
+    utc_offset_secs: i32,
+}
+
+impl HtmlExporter {
+    pub fn new(utc_offset_secs: i32) -> Self {
+        Self { utc_offset_secs }
+    }
+}
+
+impl ExporterPort for HtmlExporter {
+    fn name(&self) -> &'static str {
+        "HTML"
+    }
+
+    fn extension(&self) -> &'static str {
+        "html"
+    }
+
+    fn begin(
+        &self,
+        chat: &ExportChat,
+        mut out: Box<dyn Write + Send>,
+    ) -> Result<Box<dyn ChatExportWriter>, DomainError> {
+        let offset = self.utc_offset_secs;
+        let mut meta = format!(
+            "{} · id {} · exported {}",
+            kind_label(chat.kind),
+            chat.id,
+            activity::time_label(chat.exported_at, offset)
+        );
+        if let Some(range) = chat.range {
+            meta.push_str(&format!(
+                " · {} – {}",
+                range_bound(range.from, offset),
+                range_bound(range.to.saturating_sub(1), offset)
+            ));
+        }
+        write!(
+            out,
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+             <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+             <title>{title}</title>\n<style>\n{STYLE}</style>\n</head>\n<body>\n\
+             <header><h1>{title}</h1><p>{meta}</p></header>\n<main>\n",
+            title = escape(&chat.title),
+            meta = escape(&meta),
+        )
+        .map_err(write_err)?;
+        Ok(Box::new(HtmlChatWriter {
+            out,
+            chat_title: chat.title.clone(),
+            utc_offset_secs: offset,
+            last_day: None,
+            count: 0,
+            album: PendingAlbum::default(),
+        }))
+    }
+}
+
+struct HtmlChatWriter {
+    out: Box<dyn Write + Send>,
+    /// Shown as the sender of channel posts.
+    chat_title: String,
+    utc_offset_secs: i32,
+    /// Label of the last day separator written.
+    last_day: Option<String>,
+    count: u64,
+    album: PendingAlbum,
+}
+
+impl HtmlChatWriter {
+    fn sender(&self, m: &ExportMessage) -> String {
+        match (&m.sender_name, m.message.from_user_id) {
+            (Some(name), _) => name.clone(),
+            (None, Some(id)) => format!("user {}", id),
+            (None, None) => self.chat_title.clone(),
+        }
+    }
+
+    /// Day separator before the first entry of a new local day.
+    fn day_separator(&mut self, date: i64, html: &mut String) {
+        let day = activity::day_label(date, self.utc_offset_secs);
+        if self.last_day.as_ref() != Some(&day) {
+            html.push_str(&format!("<div class=\"day\">{}</div>\n", escape(&day)));
+            self.last_day = Some(day);
+        }
+    }
+
+    /// Render a message, or the parts of an album as one; `in_thread` for comments, which get
+    /// anchors of their own (their ids are the discussion group's, not the channel's).
+    fn render(&self, parts: &[ExportMessage], in_thread: bool, html: &mut String) {
+        let Some(first) = parts.first() else {
+            return;
+        };
+        // Forward and reply headers come from the captioned part, like Telegram shows them.
+        let m = parts
+            .iter()
+            .find(|p| !p.message.text.is_empty())
+            .unwrap_or(first);
+        let msg = &m.message;
+        let anchor = |id: i32| match in_thread {
+            true => format!("c{}_{}", msg.chat_id.unsigned_abs(), id),
+            false => format!("m{}", id),
+        };
+        let topic = msg
+            .topic_title
+            .as_deref()
+            .map(|t| format!("<span class=\"topic\"># {}</span>", escape(t)))
+            .unwrap_or_default();
+        html.push_str(&format!(
+            "<div class=\"msg{}\" id=\"{}\">\n<div class=\"head\"><span class=\"from\">{}</span>\
+             <a class=\"time\" href=\"#{}\">{}</a>{}</div>\n",
+            if first.is_outgoing { " out" } else { "" },
+            anchor(first.message.id),
+            escape(&self.sender(first)),
+            anchor(first.message.id),
+            activity::time_label(first.message.date, self.utc_offset_secs),
+            topic
+        ));
+        // Links to the other parts of an album land on the album.
+        for part in &parts[1..] {
+            html.push_str(&format!(
+                "<span id=\"{}\"></span>\n",
+                anchor(part.message.id)
+            ));
+        }
+        if let Some(origin) = msg.forwarded_from() {
+            html.push_str(&format!(
+                "<div class=\"fwd\">Forwarded from {}</div>\n",
+                escape(&origin)
+            ));
+        }
+        if let Some(reply_id) = msg.reply_to_msg_id {
+            match &m.reply_to {
+                Some(q) => html.push_str(&format!(
+                    "<div class=\"reply\"><a href=\"#{}\">{}</a> {}</div>\n",
+                    anchor(q.message_id),
+                    escape(q.sender_name.as_deref().unwrap_or("Reply")),
+                    escape(&excerpt(&q.text))
+                )),
+                // A comment without a quote answers the post itself.
+                None if in_thread => {}
+                None => html.push_str(&format!(
+                    "<div class=\"reply missing\">In reply to message #{} (not in archive)</div>\n",
+                    reply_id
+                )),
+            }
+        }
+        let media: String = parts.iter().filter_map(media_html).collect();
+        if !media.is_empty() {
+            let class = if parts.len() > 1 {
+                "media album"
+            } else {
+                "media"
+            };
+            html.push_str(&format!("<div class=\"{}\">{}</div>\n", class, media));
+        }
+        let text: Vec<&str> = parts
+            .iter()
+            .map(|p| p.message.text.as_str())
+            .filter(|text| !text.is_empty())
+            .collect();
+        if !text.is_empty() {
+            html.push_str(&format!(
+                "<div class=\"text\">{}</div>\n",
+                escape(&text.join("\n"))
+            ));
+        }
+        html.push_str("</div>\n");
+        let comments: Vec<&ExportMessage> = parts.iter().flat_map(|p| &p.comments).collect();
+        if !comments.is_empty() {
+            html.push_str(&format!(
+                "<div class=\"comments\">\n<div class=\"count\">{} comment(s)</div>\n",
+                comments.len()
+            ));
+            for comment in comments {
+                self.render(std::slice::from_ref(comment), true, html);
+            }
+            html.push_str("</div>\n");
+        }
+    }
+
+    /// Render a message or the parts of an album, after a day separator when due.
+    fn write_entry(&mut self, parts: &[ExportMessage], html: &mut String) {
+        if let Some(first) = parts.first() {
+            self.day_separator(first.message.date, html);
+            self.render(parts, false, html);
+            self.count += parts.len() as u64;
+        }
+    }
+}
+
+/// Downloaded media of a message as a thumbnail or file link, or a note that it wasn't.
+fn media_html(m: &ExportMessage) -> Option<String> {
+    let media = m.message.media.as_ref()?;
+    let kind = media.media_type.as_str();
+    Some(match &m.media_path {
+        Some(path) if shows_inline(media.media_type) => {
+            let href = escape(&url_path(path));
+            format!("<a href=\"{href}\"><img src=\"{href}\" loading=\"lazy\" alt=\"{kind}\"></a>")
+        }
+        Some(path) => format!(
+            "<a class=\"file\" href=\"{}\">📎 {}: {}</a>",
+            escape(&url_path(path)),
+            kind,
+            escape(path.rsplit('/').next().unwrap_or(path))
+        ),
+        None => format!(
+            "<span class=\"file missing\">[{} not downloaded]</span>",
+            kind
+        ),
+    })
+}
+
+/// Parts of the album being written, held back until a message outside it arrives: an album
+/// can span two batches and still renders as one block.
+#[derive(Default)]
+pub(super) struct PendingAlbum(Vec<ExportMessage>);
+
+impl PendingAlbum {
+    /// Take in the next message. Returns the album it ends (empty when it continues the album
+    /// or none was pending) and the message itself when it is not part of an album.
+    pub(super) fn push<'a>(
+        &mut self,
+        m: &'a ExportMessage,
+    ) -> (Vec<ExportMessage>, Option<&'a ExportMessage>) {
+        let continues = self
+            .0
+            .last()
+            .is_some_and(|last| last.message.same_album(&m.message));
+        let done = match continues {
+            true => Vec::new(),
+            false => self.take(),
+        };
+        if m.message.grouped_id.is_some() {
+            self.0.push(m.clone());
+            (done, None)
+        } else {
+            (done, Some(m))
+        }
+    }
+
+    /// The parts held back, leaving none.
+    pub(super) fn take(&mut self) -> Vec<ExportMessage> {
+        std::mem::take(&mut self.0)
+    }
+}
+
+impl ChatExportWriter for HtmlChatWriter {
+    fn write_batch(&mut self, messages: &[ExportMessage]) -> Result<(), DomainError> {
+        let mut html = String::new();
+        for m in messages {
+            let (album, single) = self.album.push(m);
+            self.write_entry(&album, &mut html);
+            if let Some(m) = single {
+                self.write_entry(std::slice::from_ref(m), &mut html);
+            }
+        }
+        self.out.write_all(html.as_bytes()).map_err(write_err)?;
+        self.out.flush().map_err(write_err)
+    }
+
+    fn write_event(&mut self, event: &ExportEvent) -> Result<(), DomainError> {
+        let mut html = String::new();
+        let album = self.album.take();
+        self.write_entry(&album, &mut html);
+        self.day_separator(event.event.date, &mut html);
+        html.push_str(&format!(
+            "<div class=\"event\" id=\"m{}\">{}<a class=\"time\" href=\"#m{}\">{}</a></div>\n",
+            event.event.id,
+            escape(&event.text),
+            event.event.id,
+            activity::time_label(event.event.date, self.utc_offset_secs)
+        ));
+        self.out.write_all(html.as_bytes()).map_err(write_err)
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), DomainError> {
+        let mut html = String::new();
+        let album = self.album.take();
+        self.write_entry(&album, &mut html);
+        write!(
+            self.out,
+            "{}</main>\n<footer>{} message(s) · tg-sync</footer>\n</body>\n</html>\n",
+            html, self.count
+        )
+        .map_err(write_err)?;
+        self.out.flush().map_err(write_err)
+    }
+}
+
+pub(super) fn kind_label(kind: ChatType) -> &'static str {
+    match kind {
+        ChatType::Private => "Private chat",
+        ChatType::Group => "Group",
+        ChatType::Supergroup => "Supergroup",
+        ChatType::Channel => "Channel",
+    }
+}
+
+/// Date of a range bound; open bounds (i64::MIN/MAX) are shown as "…".
+pub(super) fn range_bound(ts: i64, utc_offset_secs: i32) -> String {
+    if ts <= i64::MIN + 1 || ts >= i64::MAX - 1 {
+        return "…".to_string();
+    }
+    activity::time_label(ts, utc_offset_secs)
+        .split(' ')
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Media shown as a thumbnail rather than a file link.
+pub(super) fn shows_inline(media_type: MediaType) -> bool {
+    matches!(media_type, MediaType::Photo | MediaType::Sticker)
+}
+
+/// First line of a quoted message, cut to [`QUOTE_CHARS`].
+pub(super) fn excerpt(text: &str) -> String {
+    let line = text.lines().next().unwrap_or_default();
+    if line.chars().count() > QUOTE_CHARS || text.lines().nth(1).is_some() {
+        let cut: String = line.chars().take(QUOTE_CHARS).collect();
+        format!("{}…", cut.trim_end())
+    } else {
+        line.to_string()
+    }
+}
+
+/// Percent-encode the characters that break a relative URL (space, `#`, `?`, `%`).
+pub(super) fn url_path(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    for c in path.chars() {
+        match c {
+            ' ' => out.push_str("%20"),
+            '#' => out.push_str("%23"),
+            '?' => out.push_str("%3F"),
+            '%' => out.push_str("%25"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn write_err(e: std::io::Error) -> DomainError {
+    DomainError::Repo(format!("Failed to write HTML export: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{MediaReference, Message, ReplyQuote};
+    use crate::testing::fake_tg::text_message;
+    use std::sync::{Arc, Mutex};
+
+    /// Write target that can be read back after the writer is consumed.
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn export_message(id: i32, date: i64, text: &str) -> ExportMessage {
+        ExportMessage {
+            message: Message {
+                from_user_id: Some(7),
+                ..text_message(100, id, date, text)
+            },
+            sender_name: Some("alice".to_string()),
+            is_outgoing: false,
+            media_path: None,
+            reply_to: None,
+            comments: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_renders_escaped_messages_with_replies_media_and_days() {
+        let chat = ExportChat {
+            id: 100,
+            title: "Team <dev>".to_string(),
+            kind: ChatType::Group,
+            range: None,
+            exported_at: 1704153600,
+        };
+        let buf = SharedBuf::default();
+        let mut writer = HtmlExporter::new(0)
+            .begin(&chat, Box::new(buf.clone()))
+            .unwrap();
+
+        let first = export_message(1, 1704067200, "<script>alert(1)</script> & more");
+        let mut photo = export_message(2, 1704070800, "");
+        photo.message.media = Some(MediaReference {
+            message_id: 2,
+            chat_id: 100,
+            media_type: MediaType::Photo,
+            opaque_ref: String::new(),
+            size_bytes: None,
+            filename: None,
+            mime_type: None,
+        });
+        photo.media_path = Some("../media/100_2.jpg".to_string());
+        photo.is_outgoing = true;
+        writer.write_batch(&[first, photo]).unwrap();
+
+        let mut reply = export_message(3, 1704160000, "agreed");
+        reply.message.reply_to_msg_id = Some(1);
+        reply.reply_to = Some(ReplyQuote {
+            message_id: 1,
+            sender_name: Some("alice".to_string()),
+            text: "first line\nsecond line".to_string(),
+        });
+        let mut video = export_message(4, 1704160100, "clip");
+        video.message.media = Some(MediaReference {
+            message_id: 4,
+            chat_id: 100,
+            media_type: MediaType::Video,
+            opaque_ref: String::new(),
+            size_bytes: None,
+            filename: None,
+            mime_type: None,
+        });
+        video.message.reply_to_msg_id = Some(-5);
+        video.message.topic_title = Some("Launch & QA".to_string());
+        video.message.forwarded_from_id = Some(-1000000000042);
+        video.message.forwarded_date = Some(1704000000);
+        writer.write_batch(&[reply, video]).unwrap();
+        writer.finish().unwrap();
+
+        let html = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>Team &lt;dev&gt;</title>"));
+        assert!(html.contains("&lt;script&gt;alert(1)&lt;/script&gt; &amp; more"));
+        assert!(!html.contains("<script>"));
+        assert!(html.contains(
+            "<a href=\"../media/100_2.jpg\"><img src=\"../media/100_2.jpg\" loading=\"lazy\" alt=\"photo\"></a>"
+        ));
+        assert!(html.contains("<div class=\"msg out\" id=\"m2\">"));
+        assert!(html.contains("<a href=\"#m1\">alice</a> first line…"));
+        assert!(html.contains("In reply to message #-5 (not in archive)"));
+        assert!(html.contains("[video not downloaded]"));
+        assert!(html.contains("<span class=\"topic\"># Launch &amp; QA</span>"));
+        assert!(html.contains("<div class=\"fwd\">Forwarded from -1000000000042</div>"));
+        assert!(html.contains("<footer>4 message(s)"));
+        assert!(html.ends_with("</html>\n"));
+
+        // One separator per local day, in order.
+        let days: Vec<&str> = html
+            .match_indices("<div class=\"day\">")
+            .map(|(i, _)| &html[i + 17..i + 31])
+            .collect();
+        assert_eq!(days, vec!["Mon 2024-01-01", "Tue 2024-01-02"]);
+        assert!(html.find("id=\"m2\"").unwrap() < html.find("Tue 2024-01-02").unwrap());
+    }
+
+    /// Part `id` of album 900, a downloaded photo.
+    fn album_photo(id: i32, caption: &str) -> ExportMessage {
+        let mut part = export_message(id, 1704067200, caption);
+        part.message.grouped_id = Some(900);
+        part.message.media = Some(MediaReference {
+            message_id: id,
+            chat_id: 100,
+            media_type: MediaType::Photo,
+            opaque_ref: String::new(),
+            size_bytes: None,
+            filename: None,
+            mime_type: None,
+        });
+        part.media_path = Some(format!("../media/100/{}.jpg", id));
+        part
+    }
+
+    #[test]
+    fn test_album_is_one_message_even_across_batches() {
+        let chat = ExportChat {
+            id: 100,
+            title: "Trip".to_string(),
+            kind: ChatType::Group,
+            range: None,
+            exported_at: 1704153600,
+        };
+        let buf = SharedBuf::default();
+        let mut writer = HtmlExporter::new(0)
+            .begin(&chat, Box::new(buf.clone()))
+            .unwrap();
+        writer
+            .write_batch(&[album_photo(10, ""), album_photo(11, "Sunset at the lake")])
+            .unwrap();
+        writer
+            .write_batch(&[album_photo(12, ""), export_message(13, 1704067300, "wow")])
+            .unwrap();
+        let mut next = album_photo(14, "");
+        next.message.grouped_id = Some(901);
+        writer.write_batch(&[next]).unwrap();
+        writer.finish().unwrap();
+
+        let html = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(html.matches("<div class=\"msg\"").count(), 3);
+        assert_eq!(html.matches("<div class=\"media album\">").count(), 1);
+        assert_eq!(html.matches("Sunset at the lake").count(), 1);
+        assert!(html.contains("<div class=\"msg\" id=\"m10\">"));
+        assert!(html.contains("<span id=\"m11\"></span>\n<span id=\"m12\"></span>"));
+        let album = &html[html.find("id=\"m10\"").unwrap()..html.find("id=\"m13\"").unwrap()];
+        assert_eq!(album.matches("<img ").count(), 3);
+        // The lone part of the next album is written on finish, as a plain message.
+        assert!(html.contains("<div class=\"msg\" id=\"m14\">"));
+        assert!(html.find("id=\"m13\"").unwrap() < html.find("id=\"m14\"").unwrap());
+        assert!(html.contains("<footer>5 message(s)"));
+    }
+
+    #[test]
+    fn test_channel_post_is_followed_by_comments() {
+        let chat = ExportChat {
+            id: -1000000000042,
+            title: "News".to_string(),
+            kind: ChatType::Channel,
+            range: None,
+            exported_at: 1704153600,
+        };
+        let buf = SharedBuf::default();
+        let mut writer = HtmlExporter::new(0)
+            .begin(&chat, Box::new(buf.clone()))
+            .unwrap();
+
+        let mut post = export_message(5, 1704067200, "release notes");
+        let mut first = export_message(5, 1704067300, "nice");
+        first.message.chat_id = -1000000000077;
+        first.message.reply_to_msg_id = Some(4);
+        let mut second = export_message(6, 1704067400, "agreed");
+        second.message.chat_id = -1000000000077;
+        second.message.reply_to_msg_id = Some(5);
+        second.reply_to = Some(ReplyQuote {
+            message_id: 5,
+            sender_name: Some("alice".to_string()),
+            text: "nice".to_string(),
+        });
+        post.comments = vec![first, second];
+        writer.write_batch(&[post]).unwrap();
+        writer.finish().unwrap();
+
+        let html = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        assert!(html.contains("<div class=\"msg\" id=\"m5\">"));
+        assert!(html.contains("<div class=\"count\">2 comment(s)</div>"));
+        // Comment anchors do not collide with the post sharing their id.
+        assert!(html.contains("<div class=\"msg\" id=\"c1000000000077_5\">"));
+        assert!(html.contains("<a href=\"#c1000000000077_5\">alice</a> nice"));
+        // The reply to the post copy is not reported as missing.
+        assert!(!html.contains("not in archive"));
+        assert!(html.find("release notes").unwrap() < html.find("comment(s)").unwrap());
+        assert!(html.contains("<footer>1 message(s)"));
+    }
+
+    #[test]
+    fn test_excerpt_and_url_path() {
+        assert_eq!(excerpt("short"), "short");
+        assert_eq!(excerpt("a\nb"), "a…");
+        assert_eq!(excerpt(&"x".repeat(200)).chars().count(), QUOTE_CHARS + 1);
+        assert_eq!(url_path("../my media/1#2.jpg"), "../my%20media/1%232.jpg");
+    }
+}
@@ -0,0 +1,467 @@
+//! Markdown chat transcript, readable as plain text.
+//!
+//! One line per message, `**Name** (2024-03-02 14:11): text`, under a `## ` header per local
+//! day. Further lines of a message follow as hard line breaks, then downloaded media as a link
+//! to the local file (images embedded) and the replied-to message as a quote. An album is one
+//! entry with the shared caption and every part's media. Service events are italic lines
+//! between the messages; comments on channel posts follow the post as a list.
+//! Message text is escaped, so it shows as written rather than as markup.
+
+use super::html::{PendingAlbum, excerpt, kind_label, range_bound, shows_inline, url_path};
+use crate::domain::{DomainError, ExportChat, ExportEvent, ExportMessage, album_label};
+use crate::ports::{ChatExportWriter, ExporterPort};
+use crate::shared::activity;
+use std::io::Write;
+
+/// Writes a chat as a Markdown transcript. Times are shown at `utc_offset_secs`.
+pub struct MarkdownExporter {
+    utc_offset_secs: i32,
+}
+
+impl MarkdownExporter {
+    pub fn new(utc_offset_secs: i32) -> Self {
+        Self { utc_offset_secs }
+    }
+}
+
+impl ExporterPort for MarkdownExporter {
+    fn name(&self) -> &'static str {
+        "Markdown"
+    }
+
+    fn extension(&self) -> &'static str {
+        "md"
+    }
+
+    fn begin(
+        &self,
+        chat: &ExportChat,
+        mut out: Box<dyn Write + Send>,
+    ) -> Result<Box<dyn ChatExportWriter>, DomainError> {
+        let offset = self.utc_offset_secs;
+        let mut meta = format!(
+            "{} · id {} · exported {}",
+            kind_label(chat.kind),
+            chat.id,
+            activity::time_label(chat.exported_at, offset)
+        );
+        if let Some(range) = chat.range {
+            meta.push_str(&format!(
+                " · {} – {}",
+                range_bound(range.from, offset),
+                range_bound(range.to.saturating_sub(1), offset)
+            ));
+        }
+        write!(out, "# {}\n\n{}\n\n", escape(&chat.title), meta).map_err(write_err)?;
+        Ok(Box::new(MarkdownChatWriter {
+            out,
+            chat_title: chat.title.clone(),
+            utc_offset_secs: offset,
+            last_day: None,
+            count: 0,
+            album: PendingAlbum::default(),
+        }))
+    }
+}
+
+struct MarkdownChatWriter {
+    out: Box<dyn Write + Send>,
+    /// Shown as the sender of channel posts.
+    chat_title: String,
+    utc_offset_secs: i32,
+    /// Label of the last day header written.
+    last_day: Option<String>,
+    count: u64,
+    album: PendingAlbum,
+}
+
+impl MarkdownChatWriter {
+    fn sender(&self, m: &ExportMessage) -> String {
+        match (&m.sender_name, m.message.from_user_id) {
+            (Some(name), _) => name.clone(),
+            (None, Some(id)) => format!("user {}", id),
+            (None, None) => self.chat_title.clone(),
+        }
+    }
+
+    /// Day header before the first entry of a new local day.
+    fn day_header(&mut self, date: i64, md: &mut String) {
+        let day = activity::day_label(date, self.utc_offset_secs);
+        if self.last_day.as_ref() != Some(&day) {
+            md.push_str(&format!("## {}\n\n", day));
+            self.last_day = Some(day);
+        }
+    }
+
+    /// Render a message or the parts of an album, every line after the first prefixed with
+    /// `indent`. Comments are list items (`in_thread`); a comment without a quote answers the
+    /// post itself.
+    fn render(&self, parts: &[ExportMessage], in_thread: bool, indent: &str, md: &mut String) {
+        let Some(first) = parts.first() else {
+            return;
+        };
+        // Forward and reply headers come from the captioned part, like Telegram shows them.
+        let m = parts
+            .iter()
+            .find(|p| !p.message.text.is_empty())
+            .unwrap_or(first);
+        let msg = &m.message;
+        let mut head = format!(
+            "**{}** ({}",
+            escape(&self.sender(first)),
+            activity::time_label(first.message.date, self.utc_offset_secs)
+        );
+        if parts.len() > 1 {
+            head.push_str(&format!(
+                ", {}",
+                album_label(parts.iter().map(|p| &p.message))
+            ));
+        }
+        if let Some(topic) = &msg.topic_title {
+            head.push_str(&format!(", # {}", escape(topic)));
+        }
+        if let Some(origin) = msg.forwarded_from() {
+            head.push_str(&format!(", forwarded from {}", escape(&origin)));
+        }
+        head.push(')');
+
+        let mut lines: Vec<String> = parts
+            .iter()
+            .flat_map(|p| p.message.text.lines())
+            .map(escape)
+            .collect();
+        for part in parts {
+            let Some(media) = &part.message.media else {
+                continue;
+            };
+            let kind = media.media_type.as_str();
+            lines.push(match &part.media_path {
+                Some(path) if shows_inline(media.media_type) => {
+                    format!("![{}]({})", kind, url_path(path))
+                }
+                Some(path) => format!(
+                    "[📎 {}: {}]({})",
+                    kind,
+                    escape(path.rsplit('/').next().unwrap_or(path)),
+                    url_path(path)
+                ),
+                None => format!("*\\[{} not downloaded\\]*", kind),
+            });
+        }
+        match lines.split_first() {
+            Some((first, rest)) => {
+                md.push_str(&format!("{}: {}", head, first));
+                for line in rest {
+                    md.push_str(&format!("  \n{}{}", indent, line));
+                }
+            }
+            None => md.push_str(&head),
+        }
+        md.push('\n');
+
+        if let Some(reply_id) = msg.reply_to_msg_id {
+            match &m.reply_to {
+                Some(q) => md.push_str(&format!(
+                    "{}> **{}**: {}\n",
+                    indent,
+                    escape(q.sender_name.as_deref().unwrap_or("Reply")),
+                    escape(&excerpt(&q.text))
+                )),
+                None if in_thread => {}
+                None => md.push_str(&format!(
+                    "{}> In reply to message #{} (not in archive)\n",
+                    indent, reply_id
+                )),
+            }
+        }
+        md.push('\n');
+
+        let comments: Vec<&ExportMessage> = parts.iter().flat_map(|p| &p.comments).collect();
+        if !comments.is_empty() {
+            md.push_str(&format!("*{} comment(s):*\n\n", comments.len()));
+            for comment in comments {
+                md.push_str("- ");
+                self.render(std::slice::from_ref(comment), true, "  ", md);
+            }
+        }
+    }
+
+    /// Render a message or the parts of an album, after a day header when due.
+    fn write_entry(&mut self, parts: &[ExportMessage], md: &mut String) {
+        if let Some(first) = parts.first() {
+            self.day_header(first.message.date, md);
+            self.render(parts, false, "", md);
+            self.count += parts.len() as u64;
+        }
+    }
+}
+
+impl ChatExportWriter for MarkdownChatWriter {
+    fn write_batch(&mut self, messages: &[ExportMessage]) -> Result<(), DomainError> {
+        let mut md = String::new();
+        for m in messages {
+            let (album, single) = self.album.push(m);
+            self.write_entry(&album, &mut md);
+            if let Some(m) = single {
+                self.write_entry(std::slice::from_ref(m), &mut md);
+            }
+        }
+        self.out.write_all(md.as_bytes()).map_err(write_err)?;
+        self.out.flush().map_err(write_err)
+    }
+
+    fn write_event(&mut self, event: &ExportEvent) -> Result<(), DomainError> {
+        let mut md = String::new();
+        let album = self.album.take();
+        self.write_entry(&album, &mut md);
+        self.day_header(event.event.date, &mut md);
+        md.push_str(&format!(
+            "*{}* ({})\n\n",
+            escape(&event.text),
+            activity::time_label(event.event.date, self.utc_offset_secs)
+        ));
+        self.out.write_all(md.as_bytes()).map_err(write_err)
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), DomainError> {
+        let mut md = String::new();
+        let album = self.album.take();
+        self.write_entry(&album, &mut md);
+        write!(
+            self.out,
+            "{}---\n\n{} message(s) · tg-sync\n",
+            md, self.count
+        )
+        .map_err(write_err)?;
+        self.out.flush().map_err(write_err)
+    }
+}
+
+/// Backslash-escape markup so `text` shows literally: inline markers anywhere, block markers
+/// (headings, quotes, list bullets) at the start of a line.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let body = text.trim_start();
+    out.push_str(&text[..text.len() - body.len()]);
+    if body.starts_with(['#', '>', '-', '+']) {
+        out.push('\\');
+    }
+    for c in body.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']' | '<') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn write_err(e: std::io::Error) -> DomainError {
+    DomainError::Repo(format!("Failed to write Markdown export: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{ChatType, MediaReference, MediaType, Message, ReplyQuote};
+    use crate::testing::fake_tg::text_message;
+    use std::sync::{Arc, Mutex};
+
+    /// Write target that can be read back after the writer is consumed.
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn export_message(id: i32, date: i64, text: &str) -> ExportMessage {
+        ExportMessage {
+            message: Message {
+                from_user_id: Some(7),
+                ..text_message(100, id, date, text)
+            },
+            sender_name: Some("alice".to_string()),
+            is_outgoing: false,
+            media_path: None,
+            reply_to: None,
+            comments: Vec::new(),
+        }
+    }
+
+    fn media(id: i32, media_type: MediaType) -> Option<MediaReference> {
+        Some(MediaReference {
+            message_id: id,
+            chat_id: 100,
+            media_type,
+            opaque_ref: String::new(),
+            size_bytes: None,
+            filename: None,
+            mime_type: None,
+        })
+    }
+
+    #[test]
+    fn test_renders_transcript_with_days_replies_and_media() {
+        let chat = ExportChat {
+            id: 100,
+            title: "Team *dev*".to_string(),
+            kind: ChatType::Group,
+            range: None,
+            exported_at: 1704153600,
+        };
+        let buf = SharedBuf::default();
+        let mut writer = MarkdownExporter::new(0)
+            .begin(&chat, Box::new(buf.clone()))
+            .unwrap();
+
+        let first = export_message(1, 1704067200, "hello *all*\n# not a heading");
+        let mut photo = export_message(2, 1704070800, "");
+        photo.message.media = media(2, MediaType::Photo);
+        photo.media_path = Some("../media/100 2.jpg".to_string());
+        photo.sender_name = None;
+        writer.write_batch(&[first, photo]).unwrap();
+
+        let mut reply = export_message(3, 1704160000, "agreed");
+        reply.message.reply_to_msg_id = Some(1);
+        reply.reply_to = Some(ReplyQuote {
+            message_id: 1,
+            sender_name: Some("alice".to_string()),
+            text: "hello *all*\nsecond line".to_string(),
+        });
+        let mut video = export_message(4, 1704160100, "clip");
+        video.message.media = media(4, MediaType::Video);
+        video.message.reply_to_msg_id = Some(-5);
+        video.message.forwarded_from_name = Some("Carol".to_string());
+        video.message.forwarded_date = Some(1704000000);
+        let mut doc = export_message(5, 1704160200, "");
+        doc.message.media = media(5, MediaType::Document);
+        doc.media_path = Some("../media/100_5.pdf".to_string());
+        writer.write_batch(&[reply, video, doc]).unwrap();
+        writer.finish().unwrap();
+
+        let md = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        assert!(
+            md.starts_with("# Team \\*dev\\*\n\nGroup · id 100 · exported 2024-01-02 00:00\n\n")
+        );
+        assert!(md.contains(
+            "## Mon 2024-01-01\n\n**alice** (2024-01-01 00:00): hello \\*all\\*  \n\\# not a heading\n\n"
+        ));
+        assert!(md.contains("**user 7** (2024-01-01 01:00): ![photo](../media/100%202.jpg)\n"));
+        assert!(
+            md.contains("**alice** (2024-01-02 01:46): agreed\n> **alice**: hello \\*all\\*…\n\n")
+        );
+        assert!(md.contains(
+            "**alice** (2024-01-02 01:48, forwarded from Carol): clip  \n*\\[video not downloaded\\]*\n\
+             > In reply to message #-5 (not in archive)\n"
+        ));
+        assert!(md.contains("[📎 document: 100\\_5.pdf](../media/100_5.pdf)"));
+        assert!(md.ends_with("---\n\n5 message(s) · tg-sync\n"));
+
+        let days: Vec<&str> = md.lines().filter(|l| l.starts_with("## ")).collect();
+        assert_eq!(days, vec!["## Mon 2024-01-01", "## Tue 2024-01-02"]);
+    }
+
+    #[test]
+    fn test_album_is_one_entry_with_its_caption() {
+        let chat = ExportChat {
+            id: 100,
+            title: "Trip".to_string(),
+            kind: ChatType::Group,
+            range: None,
+            exported_at: 1704153600,
+        };
+        let buf = SharedBuf::default();
+        let mut writer = MarkdownExporter::new(0)
+            .begin(&chat, Box::new(buf.clone()))
+            .unwrap();
+        let part = |id: i32, caption: &str| {
+            let mut part = export_message(id, 1704067200, caption);
+            part.message.grouped_id = Some(900);
+            part.message.media = media(id, MediaType::Photo);
+            part.media_path = Some(format!("../media/100/{}.jpg", id));
+            part
+        };
+        writer
+            .write_batch(&[part(10, ""), part(11, "Sunset at the lake")])
+            .unwrap();
+        writer.write_batch(&[part(12, "")]).unwrap();
+        writer
+            .write_event(&ExportEvent {
+                event: crate::domain::ChatEvent {
+                    id: 13,
+                    chat_id: 100,
+                    date: 1704067300,
+                    kind: crate::domain::ChatEventKind::UserJoined,
+                    actor_id: Some(8),
+                    payload: None,
+                },
+                actor_name: Some("bob".to_string()),
+                text: "bob joined".to_string(),
+            })
+            .unwrap();
+        writer.finish().unwrap();
+
+        let md = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        assert!(md.contains(
+            "**alice** (2024-01-01 00:00, album of 3 photos): Sunset at the lake  \n\
+             ![photo](../media/100/10.jpg)  \n![photo](../media/100/11.jpg)  \n\
+             ![photo](../media/100/12.jpg)\n\n*bob joined*"
+        ));
+        assert!(md.ends_with("3 message(s) · tg-sync\n"));
+    }
+
+    #[test]
+    fn test_channel_post_is_followed_by_comments() {
+        let chat = ExportChat {
+            id: -1000000000042,
+            title: "News".to_string(),
+            kind: ChatType::Channel,
+            range: None,
+            exported_at: 1704153600,
+        };
+        let buf = SharedBuf::default();
+        let mut writer = MarkdownExporter::new(0)
+            .begin(&chat, Box::new(buf.clone()))
+            .unwrap();
+
+        let mut post = export_message(5, 1704067200, "release notes");
+        post.sender_name = None;
+        post.message.from_user_id = None;
+        let mut first = export_message(5, 1704067300, "nice\nreally");
+        first.message.reply_to_msg_id = Some(4);
+        let mut second = export_message(6, 1704067400, "agreed");
+        second.message.reply_to_msg_id = Some(5);
+        second.reply_to = Some(ReplyQuote {
+            message_id: 5,
+            sender_name: Some("bob".to_string()),
+            text: "nice".to_string(),
+        });
+        post.comments = vec![first, second];
+        writer.write_batch(&[post]).unwrap();
+        writer.finish().unwrap();
+
+        let md = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        assert!(md.contains(
+            "**News** (2024-01-01 00:00): release notes\n\n*2 comment(s):*\n\n\
+             - **alice** (2024-01-01 00:01): nice  \n  really\n\n\
+             - **alice** (2024-01-01 00:03): agreed\n  > **bob**: nice\n\n"
+        ));
+        assert!(!md.contains("not in archive"));
+        assert!(md.ends_with("1 message(s) · tg-sync\n"));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("snake_case [x] `y`"), "snake\\_case \\[x\\] \\`y\\`");
+        assert_eq!(escape("> quoted"), "\\> quoted");
+        assert_eq!(escape("  - item"), "  \\- item");
+        assert_eq!(escape("a - b # c"), "a - b # c");
+        assert_eq!(escape("<b>"), "\\<b>");
+    }
+}
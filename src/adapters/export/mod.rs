@@ -0,0 +1,25 @@
+//! Chat exporters for rendered exports (HTML, Telegram Desktop JSON, Markdown transcript).
+//! Implement ExporterPort.
+//!
+//! Exporters only turn prepared messages into a document; reading the archive in batches,
+//! sender lookup, media paths and atomic file replacement are handled by ExportService.
+
+pub mod desktop_json;
+pub mod html;
+pub mod markdown;
+
+pub use desktop_json::JsonExporter;
+pub use html::HtmlExporter;
+pub use markdown::MarkdownExporter;
+
+use crate::ports::ExporterPort;
+use std::sync::Arc;
+
+/// Built-in exporters in menu order. Times are rendered at `utc_offset_secs`.
+pub fn default_exporters(utc_offset_secs: i32) -> Vec<Arc<dyn ExporterPort>> {
+    vec![
+        Arc::new(HtmlExporter::new(utc_offset_secs)),
+        Arc::new(JsonExporter::new(utc_offset_secs)),
+        Arc::new(MarkdownExporter::new(utc_offset_secs)),
+    ]
+}
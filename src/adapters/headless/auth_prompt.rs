@@ -0,0 +1,343 @@
+//! Implements AuthPromptPort without a TTY.
+//!
+//! The phone number (and optionally the 2FA password) come from configuration
+//! (`TG_SYNC_PHONE`, `TG_SYNC_PASSWORD`). The login code, which only exists after Telegram
+//! sends it, is waited for: the first of a file dropped into the data directory
+//! (`login_code.txt`) or a one-shot HTTP request to `TG_SYNC_LOGIN_HTTP`
+//! (`curl 'http://127.0.0.1:8765/?code=12345'` or a POST with the code as body). A missing
+//! 2FA password is waited for the same way (`login_password.txt`). Files are deleted once read.
+//!
+//! With QR login (`TG_SYNC_LOGIN_METHOD=qr`, the default without a phone number) the QR code
+//! is printed to stdout and its URL logged, so it can be scanned from `docker logs`.
+
+use crate::domain::{DomainError, LoginMethod, QrToken};
+use crate::ports::AuthPromptPort;
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+/// Login code file name inside the data directory.
+pub const LOGIN_CODE_FILE: &str = "login_code.txt";
+
+/// 2FA password file name inside the data directory.
+pub const LOGIN_PASSWORD_FILE: &str = "login_password.txt";
+
+/// How long to wait for a code or password before giving up (codes expire anyway).
+const WAIT_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+/// File poll interval.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Largest HTTP request read from the login endpoint.
+const MAX_REQUEST_BYTES: usize = 8 * 1024;
+
+/// Headless login input: configuration values plus a code file or HTTP endpoint.
+pub struct EnvAuthPrompt {
+    phone: Option<String>,
+    password: Option<String>,
+    login_method: LoginMethod,
+    /// Directory watched for the code and password files.
+    data_dir: PathBuf,
+    /// Address of the one-shot HTTP endpoint, when enabled.
+    http_addr: Option<SocketAddr>,
+    timeout: Duration,
+}
+
+impl EnvAuthPrompt {
+    pub fn new(
+        phone: Option<String>,
+        password: Option<String>,
+        login_method: LoginMethod,
+        data_dir: PathBuf,
+        http_addr: Option<SocketAddr>,
+    ) -> Self {
+        Self {
+            phone,
+            password,
+            login_method,
+            data_dir,
+            http_addr,
+            timeout: WAIT_TIMEOUT,
+        }
+    }
+
+    /// Wait until `file` appears (non-empty) or the HTTP endpoint receives a value.
+    async fn wait_for(&self, file_name: &str, what: &str) -> Result<String, DomainError> {
+        let file = self.data_dir.join(file_name);
+        let listener =
+            match self.http_addr {
+                Some(addr) => Some(TcpListener::bind(addr).await.map_err(|e| {
+                    DomainError::Auth(format!("bind login endpoint {}: {}", addr, e))
+                })?),
+                None => None,
+            };
+        match self.http_addr {
+            Some(addr) => info!(
+                file = %file.display(),
+                "Waiting for the {}: write it to the file or send it to http://{}/?code=...",
+                what,
+                addr
+            ),
+            None => info!(file = %file.display(), "Waiting for the {}: write it to the file", what),
+        }
+
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if let Some(value) = take_file(&file).await? {
+                return Ok(value);
+            }
+            if Instant::now() >= deadline {
+                return Err(DomainError::Auth(format!(
+                    "timed out after {:?} waiting for the {}",
+                    self.timeout, what
+                )));
+            }
+            match &listener {
+                Some(listener) => tokio::select! {
+                    accepted = listener.accept() => {
+                        if let Ok((stream, peer)) = accepted {
+                            if let Some(value) = serve_once(stream).await {
+                                info!(%peer, "received the {} over HTTP", what);
+                                return Ok(value);
+                            }
+                        }
+                    }
+                    _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                },
+                None => tokio::time::sleep(POLL_INTERVAL).await,
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl AuthPromptPort for EnvAuthPrompt {
+    async fn login_method(&self) -> Result<LoginMethod, DomainError> {
+        Ok(self.login_method)
+    }
+
+    async fn show_qr(&self, token: &QrToken, qr: &str) -> Result<(), DomainError> {
+        println!("{}", qr);
+        info!(
+            url = %token.url,
+            "Scan the QR code with Telegram (Settings → Devices → Link Desktop Device)"
+        );
+        Ok(())
+    }
+
+    async fn phone(&self) -> Result<String, DomainError> {
+        self.phone
+            .clone()
+            .filter(|p| !p.trim().is_empty())
+            .ok_or_else(|| {
+                DomainError::Auth(
+                    "no terminal for the login prompt: set TG_SYNC_PHONE for headless login".into(),
+                )
+            })
+    }
+
+    async fn login_code(&self) -> Result<String, DomainError> {
+        self.wait_for(LOGIN_CODE_FILE, "login code").await
+    }
+
+    async fn password(&self, hint: Option<&str>) -> Result<String, DomainError> {
+        if let Some(password) = self.password.clone() {
+            return Ok(password);
+        }
+        warn!(
+            hint = hint.unwrap_or("(no hint)"),
+            "2FA password required and TG_SYNC_PASSWORD is not set"
+        );
+        self.wait_for(LOGIN_PASSWORD_FILE, "2FA password").await
+    }
+}
+
+/// Read and delete `path`. None while it is missing or still empty.
+async fn take_file(path: &Path) -> Result<Option<String>, DomainError> {
+    let content = match tokio::fs::read_to_string(path).await {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(DomainError::Auth(format!("read {}: {}", path.display(), e)));
+        }
+    };
+    let value = content.trim();
+    if value.is_empty() {
+        return Ok(None);
+    }
+    if let Err(e) = tokio::fs::remove_file(path).await {
+        warn!(path = %path.display(), error = %e, "could not delete login file");
+    }
+    Ok(Some(value.to_string()))
+}
+
+/// Answer one HTTP request. Returns the value from `?code=` or the request body, if any.
+async fn serve_once(mut stream: TcpStream) -> Option<String> {
+    let mut buf = vec![0u8; MAX_REQUEST_BYTES];
+    let mut len = 0;
+    // Read until the headers and the announced body are in (or the buffer is full).
+    loop {
+        let n = stream.read(&mut buf[len..]).await.ok()?;
+        len += n;
+        let request = String::from_utf8_lossy(&buf[..len]);
+        let complete = request
+            .find("\r\n\r\n")
+            .is_some_and(|end| len >= end + 4 + content_length(&request[..end]).unwrap_or(0));
+        if n == 0 || complete || len == buf.len() {
+            break;
+        }
+    }
+    let value = parse_request(&String::from_utf8_lossy(&buf[..len]));
+    let (status, body) = match value {
+        Some(_) => ("200 OK", "ok\n"),
+        None => (
+            "400 Bad Request",
+            "expected ?code=<value> or the value as request body\n",
+        ),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+    value
+}
+
+fn content_length(headers: &str) -> Option<usize> {
+    headers.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("content-length")
+            .then(|| value.trim().parse().ok())?
+    })
+}
+
+/// Value of a request: `code` query parameter, else the (form or plain) body.
+fn parse_request(request: &str) -> Option<String> {
+    let (head, body) = request.split_once("\r\n\r\n").unwrap_or((request, ""));
+    let target = head.lines().next()?.split_whitespace().nth(1)?;
+    let from_query = target
+        .split_once('?')
+        .and_then(|(_, query)| form_value(query));
+    let value = from_query.or_else(|| {
+        let body = body.trim();
+        form_value(body).or_else(|| (!body.contains('=')).then(|| body.to_string()))
+    })?;
+    let value = value.trim().to_string();
+    (!value.is_empty()).then_some(value)
+}
+
+/// `code` or `password` from `a=b&c=d` (values are digits or simple text; `+` means space).
+fn form_value(query: &str) -> Option<String> {
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        matches!(key, "code" | "password").then(|| value.replace('+', " "))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_parse_request() {
+        assert_eq!(
+            parse_request("GET /?code=12345 HTTP/1.1\r\nHost: x\r\n\r\n").as_deref(),
+            Some("12345")
+        );
+        assert_eq!(
+            parse_request("POST / HTTP/1.1\r\nContent-Length: 6\r\n\r\n54321\n").as_deref(),
+            Some("54321")
+        );
+        assert_eq!(
+            parse_request("POST / HTTP/1.1\r\n\r\npassword=my+secret").as_deref(),
+            Some("my secret")
+        );
+        assert_eq!(parse_request("GET /favicon.ico HTTP/1.1\r\n\r\n"), None);
+        assert_eq!(parse_request("GET /?code= HTTP/1.1\r\n\r\n"), None);
+    }
+
+    #[tokio::test]
+    async fn test_code_from_file_and_missing_phone() {
+        let dir = temp_dir("test_headless_auth_file");
+        let prompt = EnvAuthPrompt::new(
+            None,
+            Some("pw".into()),
+            LoginMethod::PhoneCode,
+            dir.clone(),
+            None,
+        );
+        assert!(prompt.phone().await.is_err());
+        assert_eq!(prompt.password(None).await.unwrap(), "pw");
+
+        let code_path = dir.join(LOGIN_CODE_FILE);
+        let writer = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            std::fs::write(&code_path, " 24680\n").unwrap();
+        });
+        assert_eq!(prompt.login_code().await.unwrap(), "24680");
+        writer.await.unwrap();
+        assert!(!dir.join(LOGIN_CODE_FILE).exists());
+
+        let mut quick =
+            EnvAuthPrompt::new(Some("+100".into()), None, LoginMethod::PhoneCode, dir, None);
+        quick.timeout = Duration::ZERO;
+        assert_eq!(quick.phone().await.unwrap(), "+100");
+        assert!(quick.login_code().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_code_from_http_endpoint() {
+        let dir = temp_dir("test_headless_auth_http");
+        // Reserve a free port, then let the prompt bind it.
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let prompt = EnvAuthPrompt::new(
+            Some("+100".into()),
+            None,
+            LoginMethod::PhoneCode,
+            dir,
+            Some(addr),
+        );
+
+        let client = tokio::spawn(async move {
+            let send = |request: &'static str| async move {
+                loop {
+                    if let Ok(mut stream) = TcpStream::connect(addr).await {
+                        stream.write_all(request.as_bytes()).await.unwrap();
+                        let mut response = String::new();
+                        stream.read_to_string(&mut response).await.unwrap();
+                        return response;
+                    }
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                }
+            };
+            let bad = send("GET / HTTP/1.1\r\n\r\n").await;
+            let good = send("GET /?code=13579 HTTP/1.1\r\n\r\n").await;
+            (bad, good)
+        });
+        assert_eq!(prompt.login_code().await.unwrap(), "13579");
+        let (bad, good) = client.await.unwrap();
+        assert!(bad.starts_with("HTTP/1.1 400"));
+        assert!(good.starts_with("HTTP/1.1 200"));
+    }
+}
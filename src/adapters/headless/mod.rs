@@ -0,0 +1,5 @@
+//! Headless adapters: run without a terminal (containers, servers, systemd).
+
+pub mod auth_prompt;
+
+pub use auth_prompt::EnvAuthPrompt;
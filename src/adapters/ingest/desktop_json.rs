@@ -0,0 +1,488 @@
+//! Telegram Desktop "Export chat history" JSON (`result.json`).
+//!
+//! As a fragment (`ingest`) only `"type": "message"` entries with text are used; service
+//! entries (joins, pins) are ignored. As a history (`import`) every entry keeps its id:
+//! messages with their sender (`from_id` `user<id>`), reply, forward origin and media file,
+//! service entries as chat events. `text` is either a string or an array of strings and
+//! entity objects with a `text` field.
+
+use super::local_to_unix;
+use crate::domain::{
+    ChatEvent, ChatEventKind, ChatType, DomainError, FragmentMessage, ImportedHistory,
+    ImportedMessage, MediaReference, MediaType, Message, ParsedFragment, User,
+};
+use crate::ports::{FragmentParser, HistoryParser};
+use chrono::NaiveDateTime;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Bot API dialog ids of channels and supergroups are `-100<channel_id>`.
+const CHANNEL_ID_OFFSET: i64 = 1_000_000_000_000;
+
+/// Start of the `file`/`photo` value Desktop writes for files left out of the export.
+const FILE_NOT_INCLUDED_PREFIX: &str = "(File not included";
+
+pub struct DesktopJsonParser {
+    utc_offset_secs: i32,
+}
+
+impl DesktopJsonParser {
+    pub fn new(utc_offset_secs: i32) -> Self {
+        Self { utc_offset_secs }
+    }
+
+    /// Prefer `date_unixtime` (UTC); fall back to the local `date` string.
+    fn entry_date(&self, entry: &Value) -> Option<i64> {
+        if let Some(ts) = entry.get("date_unixtime").and_then(Value::as_str) {
+            return ts.parse().ok();
+        }
+        let date = entry.get("date").and_then(Value::as_str)?;
+        NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S")
+            .ok()
+            .map(|dt| local_to_unix(dt, self.utc_offset_secs))
+    }
+}
+
+/// Flatten `text`: plain string, or array of strings / `{"type": ..., "text": ...}` entities.
+fn entry_text(text: Option<&Value>) -> String {
+    match text {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .filter_map(|p| match p {
+                Value::String(s) => Some(s.as_str()),
+                other => other.get("text").and_then(Value::as_str),
+            })
+            .collect(),
+        _ => String::new(),
+    }
+}
+
+impl FragmentParser for DesktopJsonParser {
+    fn name(&self) -> &'static str {
+        "desktop-json"
+    }
+
+    fn detect(&self, content: &str) -> bool {
+        content.trim_start().starts_with('{') && content.contains("\"messages\"")
+    }
+
+    fn parse(&self, content: &str) -> Result<ParsedFragment, DomainError> {
+        let root: Value = serde_json::from_str(content)
+            .map_err(|e| DomainError::Ingest(format!("invalid JSON export: {}", e)))?;
+        let entries = root
+            .get("messages")
+            .and_then(Value::as_array)
+            .ok_or_else(|| DomainError::Ingest("JSON export has no \"messages\" array".into()))?;
+
+        let mut parsed = ParsedFragment::default();
+        for (i, entry) in entries.iter().enumerate() {
+            let number = i + 1;
+            match entry.get("type").and_then(Value::as_str) {
+                Some("message") => {}
+                Some(_) => continue,
+                None => {
+                    parsed.skipped.push((number, "entry without type".into()));
+                    continue;
+                }
+            }
+            let Some(date) = self.entry_date(entry) else {
+                parsed
+                    .skipped
+                    .push((number, "missing or invalid date".into()));
+                continue;
+            };
+            let text = entry_text(entry.get("text"));
+            if text.trim().is_empty() {
+                parsed.skipped.push((number, "no text (media only)".into()));
+                continue;
+            }
+            let sender = entry
+                .get("from")
+                .and_then(Value::as_str)
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(String::from);
+            parsed.messages.push(FragmentMessage { date, sender, text });
+        }
+        Ok(parsed)
+    }
+}
+
+impl HistoryParser for DesktopJsonParser {
+    fn parse_history(&self, content: &str) -> Result<ImportedHistory, DomainError> {
+        let root: Value = serde_json::from_str(content)
+            .map_err(|e| DomainError::Ingest(format!("invalid JSON export: {}", e)))?;
+        let entries = root
+            .get("messages")
+            .and_then(Value::as_array)
+            .ok_or_else(|| DomainError::Ingest("JSON export has no \"messages\" array".into()))?;
+        let kind_name = root.get("type").and_then(Value::as_str).unwrap_or_default();
+        let chat_id = root
+            .get("id")
+            .and_then(Value::as_i64)
+            .map(|id| bot_api_chat_id(kind_name, id));
+        let title = root
+            .get("name")
+            .and_then(Value::as_str)
+            .map(String::from)
+            .unwrap_or_else(|| match kind_name {
+                "saved_messages" => "Saved Messages".to_string(),
+                _ => chat_id.map(|id| id.to_string()).unwrap_or_default(),
+            });
+
+        let mut history = ImportedHistory {
+            chat_id,
+            title,
+            kind: chat_type(kind_name),
+            messages: Vec::new(),
+            events: Vec::new(),
+            users: Vec::new(),
+            skipped: Vec::new(),
+        };
+        let mut users: BTreeMap<i64, String> = BTreeMap::new();
+        for (i, entry) in entries.iter().enumerate() {
+            let number = i + 1;
+            let kind = entry.get("type").and_then(Value::as_str);
+            let Some(id) = entry
+                .get("id")
+                .and_then(Value::as_i64)
+                .and_then(|id| i32::try_from(id).ok())
+            else {
+                history
+                    .skipped
+                    .push((number, "missing or invalid id".into()));
+                continue;
+            };
+            let Some(date) = self.entry_date(entry) else {
+                history
+                    .skipped
+                    .push((number, "missing or invalid date".into()));
+                continue;
+            };
+            match kind {
+                Some("message") => {
+                    let from_user_id = peer_user_id(entry.get("from_id"));
+                    if let (Some(user_id), Some(name)) =
+                        (from_user_id, entry.get("from").and_then(Value::as_str))
+                    {
+                        users.insert(user_id, name.to_string());
+                    }
+                    history
+                        .messages
+                        .push(imported_message(entry, id, date, from_user_id));
+                }
+                Some("service") => history.events.push(chat_event(entry, id, date)),
+                Some(other) => history
+                    .skipped
+                    .push((number, format!("unknown entry type '{}'", other))),
+                None => history.skipped.push((number, "entry without type".into())),
+            }
+        }
+        history.users = users
+            .into_iter()
+            .map(|(id, name)| User {
+                id,
+                first_name: Some(name),
+                last_name: None,
+                username: None,
+            })
+            .collect();
+        Ok(history)
+    }
+}
+
+/// Bot API style id of an export's chat: Desktop writes bare ids. Negative ids are already
+/// in Bot API style (tg-sync's own exports) and kept.
+fn bot_api_chat_id(kind: &str, id: i64) -> i64 {
+    if id < 0 {
+        return id;
+    }
+    match kind {
+        "private_group" => -id,
+        "private_supergroup" | "public_supergroup" | "private_channel" | "public_channel" => {
+            -(CHANNEL_ID_OFFSET + id)
+        }
+        _ => id,
+    }
+}
+
+fn chat_type(kind: &str) -> ChatType {
+    match kind {
+        "personal_chat" | "bot_chat" | "saved_messages" => ChatType::Private,
+        "private_supergroup" | "public_supergroup" => ChatType::Supergroup,
+        "private_channel" | "public_channel" => ChatType::Channel,
+        _ => ChatType::Group,
+    }
+}
+
+/// User id of a `from_id`/`actor_id` such as `user123`. Channels posting (`channel123`) are
+/// not users.
+fn peer_user_id(value: Option<&Value>) -> Option<i64> {
+    value?.as_str()?.strip_prefix("user")?.parse().ok()
+}
+
+fn imported_message(
+    entry: &Value,
+    id: i32,
+    date: i64,
+    from_user_id: Option<i64>,
+) -> ImportedMessage {
+    let forwarded = entry.get("forwarded_from");
+    let (media, media_file) = match entry_media(entry, id) {
+        Some((media, file)) => (Some(media), file),
+        None => (None, None),
+    };
+    ImportedMessage {
+        message: Message {
+            id,
+            chat_id: 0,
+            date,
+            text: entry_text(entry.get("text")),
+            media,
+            from_user_id,
+            reply_to_msg_id: entry
+                .get("reply_to_message_id")
+                .and_then(Value::as_i64)
+                .and_then(|id| i32::try_from(id).ok()),
+            edit_history: None,
+            sender_name: None,
+            topic_id: None,
+            topic_title: None,
+            forwarded_from_name: forwarded.and_then(Value::as_str).map(String::from),
+            forwarded_from_id: None,
+            // Desktop does not export the original date; the message's own stands in.
+            forwarded_date: forwarded.map(|_| date),
+            poll: None,
+            linked_channel_post: None,
+            grouped_id: None,
+            mentioned: false,
+            mentioned_user_ids: Vec::new(),
+        },
+        media_file,
+    }
+}
+
+/// Media of a message entry and its exported file (None when left out).
+fn entry_media(entry: &Value, id: i32) -> Option<(MediaReference, Option<String>)> {
+    let (media_type, file, size) = match entry.get("photo").and_then(Value::as_str) {
+        Some(photo) => (MediaType::Photo, photo, entry.get("photo_file_size")),
+        None => {
+            let file = entry.get("file").and_then(Value::as_str)?;
+            let media_type = match entry.get("media_type").and_then(Value::as_str) {
+                Some("video_file") => MediaType::Video,
+                Some("video_message") => MediaType::VideoNote,
+                Some("voice_message") => MediaType::Voice,
+                Some("audio_file") => MediaType::Audio,
+                Some("sticker") => MediaType::Sticker,
+                Some("animation") => MediaType::Animation,
+                _ => MediaType::Document,
+            };
+            (media_type, file, entry.get("file_size"))
+        }
+    };
+    let included = !file.starts_with(FILE_NOT_INCLUDED_PREFIX);
+    // Desktop renames photos and voice notes; documents and audio keep the sender's name.
+    let filename = match entry.get("file_name").and_then(Value::as_str) {
+        Some(name) => Some(name.to_string()),
+        None if included && matches!(media_type, MediaType::Document | MediaType::Audio) => file
+            .rsplit('/')
+            .next()
+            .filter(|name| !name.is_empty())
+            .map(String::from),
+        None => None,
+    };
+    let media = MediaReference {
+        message_id: id,
+        chat_id: 0,
+        media_type,
+        opaque_ref: String::new(),
+        size_bytes: size.and_then(Value::as_i64),
+        filename,
+        mime_type: entry
+            .get("mime_type")
+            .and_then(Value::as_str)
+            .map(String::from),
+    };
+    Some((media, included.then(|| file.to_string())))
+}
+
+/// Service entry as a chat event. Actions without an event kind, or whose members are only
+/// known by name (`invite_members`, `remove_members`), are kept as `Other` with the action.
+fn chat_event(entry: &Value, id: i32, date: i64) -> ChatEvent {
+    let action = entry
+        .get("action")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let (kind, payload) = match action {
+        "join_group_by_link" | "join_group_by_request" => (ChatEventKind::UserJoined, None),
+        "edit_group_title" => (
+            ChatEventKind::TitleChanged,
+            entry.get("title").and_then(Value::as_str).map(String::from),
+        ),
+        "pin_message" => (
+            ChatEventKind::MessagePinned,
+            entry
+                .get("message_id")
+                .and_then(Value::as_i64)
+                .map(|id| id.to_string()),
+        ),
+        "group_call" => (ChatEventKind::CallStarted, None),
+        other => (ChatEventKind::Other, Some(other.to_string())),
+    };
+    ChatEvent {
+        id,
+        chat_id: 0,
+        date,
+        kind,
+        actor_id: peer_user_id(entry.get("actor_id")),
+        payload,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = r#"{
+        "name": "Project X",
+        "type": "private_group",
+        "id": 42,
+        "messages": [
+            {"id": 1, "type": "service", "date": "2024-01-05T10:00:00", "actor": "Alice", "action": "create_group"},
+            {"id": 2, "type": "message", "date": "2024-01-05T10:01:00", "date_unixtime": "1704448860", "from": "Alice", "text": "Hello team"},
+            {"id": 3, "type": "message", "date": "2024-01-05T10:02:00", "from": "Bob",
+             "text": ["See ", {"type": "link", "text": "https://example.com"}, " please"]},
+            {"id": 4, "type": "message", "date": "2024-01-05T10:03:00", "from": "Bob", "photo": "photos/1.jpg", "text": ""},
+            {"id": 5, "type": "message", "from": "Carol", "text": "no date"},
+            {"id": 6, "date": "2024-01-05T10:04:00", "text": "no type"},
+            {"id": 7, "type": "message", "date": "2024-01-05T10:05:00", "from": null, "text": "deleted account"}
+        ]
+    }"#;
+
+    #[test]
+    fn test_parse_desktop_export() {
+        // UTC+1: local 10:02 is 09:02 UTC.
+        let parser = DesktopJsonParser::new(3600);
+        assert!(parser.detect(FIXTURE));
+
+        let parsed = parser.parse(FIXTURE).unwrap();
+        assert_eq!(
+            parsed.messages,
+            vec![
+                FragmentMessage {
+                    date: 1704448860,
+                    sender: Some("Alice".into()),
+                    text: "Hello team".into(),
+                },
+                FragmentMessage {
+                    date: 1704445320,
+                    sender: Some("Bob".into()),
+                    text: "See https://example.com please".into(),
+                },
+                FragmentMessage {
+                    date: 1704445500,
+                    sender: None,
+                    text: "deleted account".into(),
+                },
+            ]
+        );
+        assert_eq!(
+            parsed.skipped,
+            vec![
+                (4, "no text (media only)".to_string()),
+                (5, "missing or invalid date".to_string()),
+                (6, "entry without type".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_history_keeps_ids_media_and_events() {
+        let export = r#"{
+            "name": "Dev", "type": "private_supergroup", "id": 1234,
+            "messages": [
+                {"id": 10, "type": "service", "date": "2024-01-05T10:00:00", "actor_id": "user7", "action": "edit_group_title", "title": "Dev team"},
+                {"id": 11, "type": "message", "date": "2024-01-05T10:01:00", "from": "Alice", "from_id": "user7",
+                 "text": ["Hi ", {"type": "bold", "text": "all"}], "reply_to_message_id": 9},
+                {"id": 12, "type": "message", "date": "2024-01-05T10:02:00", "from": "News", "from_id": "channel5",
+                 "forwarded_from": "Ann", "photo": "photos/photo_1.jpg", "photo_file_size": 2048, "text": ""},
+                {"id": 13, "type": "message", "date": "2024-01-05T10:03:00", "from": "Alice", "from_id": "user7",
+                 "file": "(File not included. Change data exporting settings to download.)", "media_type": "voice_message", "text": ""},
+                {"id": 14, "type": "sticker_set", "date": "2024-01-05T10:04:00"},
+                {"type": "message", "date": "2024-01-05T10:05:00", "text": "no id"},
+                {"id": 15, "type": "message", "date": "2024-01-05T10:06:00", "from": "Alice", "from_id": "user7",
+                 "file": "files/report_final.pdf", "mime_type": "application/pdf", "text": ""},
+                {"id": 16, "type": "message", "date": "2024-01-05T10:07:00", "from": "Alice", "from_id": "user7",
+                 "file": "round_video_messages/file_1.mp4", "media_type": "video_message", "text": ""}
+            ]
+        }"#;
+        let history = DesktopJsonParser::new(0).parse_history(export).unwrap();
+
+        assert_eq!(history.chat_id, Some(-1_000_000_001_234));
+        assert_eq!(history.kind, ChatType::Supergroup);
+        assert_eq!(history.title, "Dev");
+        let ids: Vec<i32> = history.messages.iter().map(|m| m.message.id).collect();
+        assert_eq!(ids, vec![11, 12, 13, 15, 16]);
+
+        let hi = &history.messages[0].message;
+        assert_eq!(hi.text, "Hi all");
+        assert_eq!(hi.date, 1704448860);
+        assert_eq!((hi.from_user_id, hi.reply_to_msg_id), (Some(7), Some(9)));
+
+        let photo = &history.messages[1];
+        assert_eq!(photo.media_file.as_deref(), Some("photos/photo_1.jpg"));
+        let media = photo.message.media.as_ref().unwrap();
+        assert_eq!(
+            (media.media_type, media.size_bytes),
+            (MediaType::Photo, Some(2048))
+        );
+        assert!(media.opaque_ref.is_empty());
+        assert_eq!(media.filename, None);
+        assert_eq!(photo.message.from_user_id, None);
+        assert_eq!(photo.message.forwarded_from_name.as_deref(), Some("Ann"));
+
+        let voice = &history.messages[2];
+        assert_eq!(voice.media_file, None);
+        assert_eq!(
+            voice.message.media.as_ref().map(|m| m.media_type),
+            Some(MediaType::Voice)
+        );
+
+        let document = history.messages[3].message.media.as_ref().unwrap();
+        assert_eq!(
+            (document.filename.as_deref(), document.mime_type.as_deref()),
+            (Some("report_final.pdf"), Some("application/pdf"))
+        );
+        let round = history.messages[4].message.media.as_ref().unwrap();
+        assert_eq!(
+            (round.media_type, round.filename.as_deref()),
+            (MediaType::VideoNote, None)
+        );
+
+        assert_eq!(history.events.len(), 1);
+        let event = &history.events[0];
+        assert_eq!((event.id, event.kind), (10, ChatEventKind::TitleChanged));
+        assert_eq!(
+            (event.actor_id, event.payload.as_deref()),
+            (Some(7), Some("Dev team"))
+        );
+        assert_eq!(history.users.len(), 1);
+        assert_eq!(history.users[0].first_name.as_deref(), Some("Alice"));
+        assert_eq!(
+            history.skipped,
+            vec![
+                (5, "unknown entry type 'sticker_set'".to_string()),
+                (6, "missing or invalid id".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reject_malformed_files() {
+        let parser = DesktopJsonParser::new(0);
+        assert!(!parser.detect("[2024-01-05 10:00] Alice: {\"messages\"}"));
+        assert!(parser.parse("{\"messages\": [").is_err());
+        assert!(parser.parse("{\"name\": \"x\"}").is_err());
+    }
+}
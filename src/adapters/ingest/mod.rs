@@ -0,0 +1,27 @@
+//! Chat fragment parsers for `tg-sync ingest`. Implement FragmentParser.
+//!
+//! Fragments are chat logs received from elsewhere: Telegram Desktop JSON exports and
+//! plain "[date] Name: text" logs. Parsers only turn content into FragmentMessages; ids,
+//! deduplication and storage are handled by IngestService.
+
+pub mod desktop_json;
+pub mod plain_text;
+
+pub use desktop_json::DesktopJsonParser;
+pub use plain_text::PlainTextParser;
+
+use crate::ports::FragmentParser;
+
+/// Built-in parsers in detection order (most specific first). Timestamps without a zone are
+/// read as local time at `utc_offset_secs`.
+pub fn default_parsers(utc_offset_secs: i32) -> Vec<Box<dyn FragmentParser>> {
+    vec![
+        Box::new(DesktopJsonParser::new(utc_offset_secs)),
+        Box::new(PlainTextParser::new(utc_offset_secs)),
+    ]
+}
+
+/// Read a zone-less timestamp as local time at `utc_offset_secs` and return Unix seconds.
+fn local_to_unix(dt: chrono::NaiveDateTime, utc_offset_secs: i32) -> i64 {
+    dt.and_utc().timestamp() - utc_offset_secs as i64
+}
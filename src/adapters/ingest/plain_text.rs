@@ -0,0 +1,197 @@
+//! Plain text logs: one message per `[date] Name: text` line.
+//!
+//! Lines that do not start with `[` continue the previous message (multi-line text).
+//! Accepted dates: `2024-01-05 14:03[:22]`, `2024-01-05T14:03:22` and `05.01.2024[,] 14:03[:22]`,
+//! read as local time at the configured UTC offset. Blank lines are ignored.
+
+use super::local_to_unix;
+use crate::domain::{DomainError, FragmentMessage, ParsedFragment};
+use crate::ports::FragmentParser;
+use chrono::NaiveDateTime;
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// `[date] rest`
+static LINE_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\[([^\]]+)\]\s*(.*)$").expect("line regex"));
+
+const DATE_FORMATS: &[&str] = &[
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M:%S",
+    "%d.%m.%Y, %H:%M:%S",
+    "%d.%m.%Y, %H:%M",
+    "%d.%m.%Y %H:%M:%S",
+    "%d.%m.%Y %H:%M",
+];
+
+pub struct PlainTextParser {
+    utc_offset_secs: i32,
+}
+
+impl PlainTextParser {
+    pub fn new(utc_offset_secs: i32) -> Self {
+        Self { utc_offset_secs }
+    }
+
+    fn parse_date(&self, s: &str) -> Option<i64> {
+        let s = s.trim();
+        DATE_FORMATS
+            .iter()
+            .find_map(|f| NaiveDateTime::parse_from_str(s, f).ok())
+            .map(|dt| local_to_unix(dt, self.utc_offset_secs))
+    }
+}
+
+impl FragmentParser for PlainTextParser {
+    fn name(&self) -> &'static str {
+        "plain-text"
+    }
+
+    fn detect(&self, content: &str) -> bool {
+        content
+            .lines()
+            .find(|l| !l.trim().is_empty())
+            .and_then(|l| LINE_RE.captures(l.trim_start_matches('\u{feff}')))
+            .is_some_and(|c| self.parse_date(&c[1]).is_some())
+    }
+
+    fn parse(&self, content: &str) -> Result<ParsedFragment, DomainError> {
+        let mut parsed = ParsedFragment::default();
+        // Line number of each accepted message, to report the ones that end up empty.
+        let mut starts: Vec<usize> = Vec::new();
+        // Whether the last message line was accepted (continuations of skipped lines are dropped).
+        let mut in_message = false;
+
+        for (i, raw) in content.lines().enumerate() {
+            let number = i + 1;
+            let line = raw.trim_start_matches('\u{feff}').trim_end();
+            if line.trim().is_empty() {
+                continue;
+            }
+            let Some(caps) = LINE_RE.captures(line) else {
+                match parsed.messages.last_mut() {
+                    Some(last) if in_message => {
+                        last.text.push('\n');
+                        last.text.push_str(line);
+                    }
+                    _ => parsed
+                        .skipped
+                        .push((number, "text outside a message".into())),
+                }
+                continue;
+            };
+            in_message = false;
+
+            let Some(date) = self.parse_date(&caps[1]) else {
+                parsed
+                    .skipped
+                    .push((number, format!("unrecognized date '{}'", &caps[1])));
+                continue;
+            };
+            let Some((sender, text)) = caps[2].split_once(':') else {
+                parsed
+                    .skipped
+                    .push((number, "missing 'Name:' before the text".into()));
+                continue;
+            };
+            let sender = sender.trim();
+            if sender.is_empty() {
+                parsed.skipped.push((number, "empty sender name".into()));
+                continue;
+            }
+            parsed.messages.push(FragmentMessage {
+                date,
+                sender: Some(sender.to_string()),
+                text: text.trim().to_string(),
+            });
+            starts.push(number);
+            in_message = true;
+        }
+
+        let mut kept = Vec::with_capacity(parsed.messages.len());
+        for (msg, number) in parsed.messages.into_iter().zip(starts) {
+            if msg.text.is_empty() {
+                parsed.skipped.push((number, "empty message".into()));
+            } else {
+                kept.push(msg);
+            }
+        }
+        parsed.messages = kept;
+        parsed.skipped.sort_by_key(|(n, _)| *n);
+
+        if parsed.messages.is_empty() && !parsed.skipped.is_empty() {
+            return Err(DomainError::Ingest(format!(
+                "no parsable lines ({} skipped, first at line {}: {})",
+                parsed.skipped.len(),
+                parsed.skipped[0].0,
+                parsed.skipped[0].1
+            )));
+        }
+        Ok(parsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = "\u{feff}[2024-01-05 14:03] Alice: Morning!
+[2024-01-05 14:04:30] Bob: Two lines:
+second line
+
+[05.01.2024, 14:05] Alice: ok: see you
+[yesterday] Carol: lost date
+  orphan continuation
+[2024-01-05 14:06] no sender here
+[2024-01-05 14:07] : empty name
+[05.01.2024 14:08:00] Bob:
+";
+
+    #[test]
+    fn test_parse_plain_text_log() {
+        let parser = PlainTextParser::new(0);
+        assert!(parser.detect(FIXTURE));
+
+        let parsed = parser.parse(FIXTURE).unwrap();
+        let day = 1704412800; // 2024-01-05 00:00 UTC
+        let got: Vec<(i64, Option<&str>, &str)> = parsed
+            .messages
+            .iter()
+            .map(|m| (m.date - day, m.sender.as_deref(), m.text.as_str()))
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                (14 * 3600 + 3 * 60, Some("Alice"), "Morning!"),
+                (
+                    14 * 3600 + 4 * 60 + 30,
+                    Some("Bob"),
+                    "Two lines:\nsecond line"
+                ),
+                (14 * 3600 + 5 * 60, Some("Alice"), "ok: see you"),
+            ]
+        );
+        assert_eq!(
+            parsed.skipped,
+            vec![
+                (6, "unrecognized date 'yesterday'".to_string()),
+                (7, "text outside a message".to_string()),
+                (8, "missing 'Name:' before the text".to_string()),
+                (9, "empty sender name".to_string()),
+                (10, "empty message".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_utc_offset_and_rejection() {
+        let parser = PlainTextParser::new(2 * 3600);
+        let parsed = parser.parse("[2024-01-05 02:00] A: x").unwrap();
+        assert_eq!(parsed.messages[0].date, 1704412800);
+
+        assert!(!parser.detect("hello\n[2024-01-05 02:00] A: x"));
+        assert!(!parser.detect("{\"messages\": []}"));
+        assert!(parser.parse("just some notes\nwithout dates").is_err());
+    }
+}
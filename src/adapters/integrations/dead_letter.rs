@@ -0,0 +1,262 @@
+//! Dead-letter queue for the task tracker.
+//!
+//! Wraps a `TaskTrackerPort`: a task whose creation fails is appended to a JSONL file
+//! (`data/tracker_dead_letters.jsonl`) before the error is returned, so action items are not
+//! lost when Trello is down. `replay_dead_letters` retries them (run by startup recovery).
+
+use crate::domain::{DomainError, TaskSpec};
+use crate::ports::{TaskDeadLetterPort, TaskTrackerPort};
+use serde::{Deserialize, Serialize};
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tracing::warn;
+
+/// Dead-letter file name inside the data directory.
+pub const DEAD_LETTER_FILE: &str = "tracker_dead_letters.jsonl";
+
+/// A task creation that failed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct DeadLetter {
+    #[serde(flatten)]
+    task: TaskSpec,
+    /// Unix timestamp of the first failure.
+    failed_at: i64,
+    error: String,
+}
+
+/// Task tracker decorator that parks failed task creations for a later retry.
+pub struct DeadLetterTracker {
+    inner: Arc<dyn TaskTrackerPort>,
+    path: PathBuf,
+    /// Serializes appends and replays.
+    file_lock: Mutex<()>,
+}
+
+impl DeadLetterTracker {
+    /// Wrap `inner`; dead letters go to `path`.
+    pub fn new(inner: Arc<dyn TaskTrackerPort>, path: PathBuf) -> Self {
+        Self {
+            inner,
+            path,
+            file_lock: Mutex::new(()),
+        }
+    }
+
+    /// Park `task`, which failed with `error`. Logged, not returned: the caller reports the
+    /// task's own error.
+    async fn park(&self, task: &TaskSpec, error: &DomainError) {
+        let letter = DeadLetter {
+            task: task.clone(),
+            failed_at: chrono::Utc::now().timestamp(),
+            error: error.to_string(),
+        };
+        let _guard = self.file_lock.lock().await;
+        if let Err(park_err) = self.append(&letter).await {
+            warn!(title = %task.title, error = %park_err, "failed to park task in dead-letter file");
+        }
+    }
+
+    async fn append(&self, letter: &DeadLetter) -> Result<(), DomainError> {
+        let mut line =
+            serde_json::to_string(letter).map_err(|e| DomainError::TaskTracker(e.to_string()))?;
+        line.push('\n');
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .map_err(|e| DomainError::TaskTracker(format!("open dead-letter file: {}", e)))?;
+        file.write_all(line.as_bytes())
+            .await
+            .map_err(|e| DomainError::TaskTracker(format!("write dead-letter file: {}", e)))?;
+        // tokio completes file writes in the background; flush so the line is on disk.
+        file.flush()
+            .await
+            .map_err(|e| DomainError::TaskTracker(format!("flush dead-letter file: {}", e)))
+    }
+
+    async fn load(&self) -> Result<Vec<DeadLetter>, DomainError> {
+        let content = match fs::read_to_string(&self.path).await {
+            Ok(s) => s,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(DomainError::TaskTracker(format!(
+                    "read dead-letter file: {}",
+                    e
+                )));
+            }
+        };
+        let mut letters = Vec::new();
+        for (i, line) in content.lines().enumerate() {
+            match serde_json::from_str(line) {
+                Ok(letter) => letters.push(letter),
+                Err(e) if !line.trim().is_empty() => {
+                    warn!(line = i + 1, error = %e, "dropping unreadable tracker dead letter")
+                }
+                Err(_) => {}
+            }
+        }
+        Ok(letters)
+    }
+
+    /// Replace the file with `letters` (write-replace); remove it when empty.
+    async fn store(&self, letters: &[DeadLetter]) -> Result<(), DomainError> {
+        if letters.is_empty() {
+            return match fs::remove_file(&self.path).await {
+                Err(e) if e.kind() != ErrorKind::NotFound => Err(DomainError::TaskTracker(
+                    format!("remove dead-letter file: {}", e),
+                )),
+                _ => Ok(()),
+            };
+        }
+        let mut content = String::new();
+        for letter in letters {
+            content.push_str(
+                &serde_json::to_string(letter)
+                    .map_err(|e| DomainError::TaskTracker(e.to_string()))?,
+            );
+            content.push('\n');
+        }
+        let tmp = self.path.with_extension("jsonl.tmp");
+        fs::write(&tmp, content)
+            .await
+            .map_err(|e| DomainError::TaskTracker(format!("write dead-letter file: {}", e)))?;
+        fs::rename(&tmp, &self.path)
+            .await
+            .map_err(|e| DomainError::TaskTracker(format!("replace dead-letter file: {}", e)))
+    }
+}
+
+#[async_trait::async_trait]
+impl TaskTrackerPort for DeadLetterTracker {
+    async fn create_task(&self, task: &TaskSpec) -> Result<String, DomainError> {
+        let result = self.inner.create_task(task).await;
+        if let Err(e) = &result {
+            self.park(task, e).await;
+        }
+        result
+    }
+
+    /// Passed on as one batch, so the inner tracker can pace it; failures are parked.
+    async fn create_tasks(&self, tasks: &[TaskSpec]) -> Vec<Result<String, DomainError>> {
+        let results = self.inner.create_tasks(tasks).await;
+        for (task, result) in tasks.iter().zip(&results) {
+            if let Err(e) = result {
+                self.park(task, e).await;
+            }
+        }
+        results
+    }
+}
+
+#[async_trait::async_trait]
+impl TaskDeadLetterPort for DeadLetterTracker {
+    async fn replay_dead_letters(&self) -> Result<(u64, u64), DomainError> {
+        let _guard = self.file_lock.lock().await;
+        let letters = self.load().await?;
+        if letters.is_empty() {
+            return Ok((0, 0));
+        }
+        let tasks: Vec<TaskSpec> = letters.iter().map(|l| l.task.clone()).collect();
+        let results = self.inner.create_tasks(&tasks).await;
+        let mut delivered = 0u64;
+        let mut remaining = Vec::new();
+        for (letter, result) in letters.into_iter().zip(results) {
+            match result {
+                Ok(_) => delivered += 1,
+                Err(e) => remaining.push(DeadLetter {
+                    error: e.to_string(),
+                    ..letter
+                }),
+            }
+        }
+        self.store(&remaining).await?;
+        Ok((delivered, remaining.len() as u64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// Tracker that fails while `down` is set and records created titles.
+    #[derive(Default)]
+    struct FlakyTracker {
+        down: AtomicBool,
+        created: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait::async_trait]
+    impl TaskTrackerPort for FlakyTracker {
+        async fn create_task(&self, task: &TaskSpec) -> Result<String, DomainError> {
+            if self.down.load(Ordering::SeqCst) {
+                return Err(DomainError::TaskTracker("503 unavailable".into()));
+            }
+            let mut created = self.created.lock().unwrap();
+            created.push(task.title.clone());
+            Ok(format!("card-{}", created.len()))
+        }
+    }
+
+    fn task(title: &str, due: Option<&str>) -> TaskSpec {
+        TaskSpec {
+            title: title.to_string(),
+            description: String::new(),
+            due: due.map(str::to_string),
+            priority: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_tasks_are_parked_and_replayed() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join("test_tracker_dead_letters");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(DEAD_LETTER_FILE);
+
+        let inner = Arc::new(FlakyTracker::default());
+        let tracker = DeadLetterTracker::new(inner.clone(), path.clone());
+        assert_eq!(
+            tracker.create_task(&task("ok", None)).await.unwrap(),
+            "card-1"
+        );
+
+        inner.down.store(true, Ordering::SeqCst);
+        assert!(tracker.create_task(&task("a", None)).await.is_err());
+        let results = tracker
+            .create_tasks(&[task("b", Some("2024-06-01")), task("c", None)])
+            .await;
+        assert!(results.iter().all(|r| r.is_err()));
+        // A letter parked before tasks had a priority.
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        std::io::Write::write_all(
+            &mut file,
+            br#"{"title":"old","description":"","due":null,"failed_at":1,"error":"x"}"#,
+        )
+        .unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 4);
+
+        // Still down: nothing delivered, all stay parked.
+        assert_eq!(tracker.replay_dead_letters().await.unwrap(), (0, 4));
+
+        inner.down.store(false, Ordering::SeqCst);
+        assert_eq!(tracker.replay_dead_letters().await.unwrap(), (4, 0));
+        assert_eq!(
+            *inner.created.lock().unwrap(),
+            vec!["ok", "a", "b", "c", "old"]
+        );
+        assert!(!path.exists());
+        assert_eq!(tracker.replay_dead_letters().await.unwrap(), (0, 0));
+    }
+}
@@ -1,3 +1,4 @@
 //! External service integrations (e.g. Trello task tracker).
 
+pub mod dead_letter;
 pub mod trello;
@@ -1,23 +1,102 @@
 //! Trello adapter. Implements TaskTrackerPort by creating cards via Trello REST API.
+//!
+//! The API key and token travel in the `Authorization` header, never in the URL, and are
+//! masked in error texts, so they don't end up in logs or in the dead-letter file.
+//!
+//! Trello allows 100 requests per 10 seconds per token: a batch of cards is paced by the
+//! request delay, and a 429 is retried after its `Retry-After`.
 
-use crate::domain::DomainError;
+use crate::domain::{DomainError, TaskSpec};
 use crate::ports::TaskTrackerPort;
-use reqwest::Client;
-use std::sync::Arc;
+use reqwest::header::{AUTHORIZATION, RETRY_AFTER};
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tracing::{info, warn};
 
-const TRELLO_CARDS_URL: &str = "https://api.trello.com/1/cards";
+const TRELLO_API_URL: &str = "https://api.trello.com/1";
+
+/// Pause between the cards of a batch unless configured (TG_SYNC_TRELLO_REQUEST_DELAY_MS).
+pub const DEFAULT_REQUEST_DELAY: Duration = Duration::from_millis(150);
+
+/// Retries of a request Trello rate-limited (429).
+const MAX_RATE_LIMIT_RETRIES: u32 = 3;
+
+/// Wait after a 429 without `Retry-After`: Trello's rate limit window.
+const RATE_LIMIT_WAIT: Duration = Duration::from_secs(10);
+
+/// No `Retry-After` wait is longer than this.
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+
+/// Label color given to cards of high-priority action items.
+const HIGH_PRIORITY_COLOR: &str = "red";
+
+/// Card creation response (only the parts we read).
+#[derive(Deserialize)]
+struct CreatedCard {
+    id: String,
+}
+
+/// A list (only the parts we read).
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TrelloList {
+    id_board: String,
+}
+
+/// A board label.
+#[derive(Deserialize)]
+struct Label {
+    id: String,
+    #[serde(default)]
+    color: Option<String>,
+}
+
+/// A failed Trello call; the message has the credentials masked.
+#[derive(Debug)]
+enum CallError {
+    /// Trello could not be reached (network, proxy, timeout).
+    Unreachable(String),
+    /// Trello answered with an error or with something unreadable.
+    Rejected(String),
+}
+
+impl CallError {
+    /// Prefix the message with what was being fetched.
+    fn context(self, what: &str) -> Self {
+        match self {
+            Self::Unreachable(m) => Self::Unreachable(format!("{}: {}", what, m)),
+            Self::Rejected(m) => Self::Rejected(format!("{}: {}", what, m)),
+        }
+    }
+}
+
+impl From<CallError> for DomainError {
+    fn from(e: CallError) -> Self {
+        match e {
+            CallError::Unreachable(m) | CallError::Rejected(m) => DomainError::TaskTracker(m),
+        }
+    }
+}
 
 /// Trello API adapter for creating cards (tasks).
 ///
 /// Requires API key and token from https://trello.com/app-key.
-/// Cards are created in the list specified by `list_id`. `board_id` is stored for reference.
+/// Cards are created in the list specified by `list_id`. Cards of high-priority action items
+/// get the board's red label; `with_label` adds a fixed label to every card.
 pub struct TrelloAdapter {
     client: Arc<Client>,
+    base_url: String,
     api_key: String,
     token: String,
-    #[allow(dead_code)] // reserved for future use (e.g. card labels by board)
-    board_id: String,
+    board_id: Option<String>,
     list_id: String,
+    label_id: Option<String>,
+    request_delay: Duration,
+    /// The board's red label, looked up once (None: the board has none).
+    red_label: OnceLock<Option<String>>,
 }
 
 impl TrelloAdapter {
@@ -26,59 +105,393 @@ impl TrelloAdapter {
     /// # Arguments
     /// * `api_key` - Trello API key (from app key page)
     /// * `token` - Trello API token (from OAuth or token generation)
-    /// * `board_id` - ID of the board (for reference; card creation uses `list_id`)
+    /// * `board_id` - ID of the board; when None, the board of `list_id` is used
     /// * `list_id` - ID of the list where cards will be created
-    pub fn new(api_key: String, token: String, board_id: String, list_id: String) -> Self {
+    pub fn new(api_key: String, token: String, board_id: Option<String>, list_id: String) -> Self {
         Self {
             client: Arc::new(Client::new()),
+            base_url: TRELLO_API_URL.to_string(),
             api_key,
             token,
             board_id,
             list_id,
+            label_id: None,
+            request_delay: DEFAULT_REQUEST_DELAY,
+            red_label: OnceLock::new(),
         }
     }
-}
 
-#[async_trait::async_trait]
-impl TaskTrackerPort for TrelloAdapter {
-    async fn create_task(
+    /// Send requests through `proxy` (TG_SYNC_HTTP_PROXY).
+    pub fn with_proxy(mut self, proxy: reqwest::Proxy) -> Self {
+        match Client::builder().proxy(proxy).build() {
+            Ok(client) => self.client = Arc::new(client),
+            Err(e) => {
+                warn!(error = %e, "failed to build Trello HTTP client with proxy, using defaults")
+            }
+        }
+        self
+    }
+
+    /// Add label `label_id` to every card (TRELLO_LABEL_ID).
+    pub fn with_label(mut self, label_id: String) -> Self {
+        self.label_id = Some(label_id);
+        self
+    }
+
+    /// Pause `delay` between the cards of a batch (TG_SYNC_TRELLO_REQUEST_DELAY_MS).
+    pub fn with_request_delay(mut self, delay: Duration) -> Self {
+        self.request_delay = delay;
+        self
+    }
+
+    /// Check that the list and the board exist and the key and token can read them, and look
+    /// up the labels. Fails on Trello's answer (bad ids or credentials, a list on another
+    /// board, an unknown label); when Trello can't be reached it only warns, as failed cards
+    /// are parked and retried anyway.
+    pub async fn validate(&self) -> Result<(), DomainError> {
+        match self.check().await {
+            Err(CallError::Unreachable(e)) => {
+                warn!(error = %e, "Trello unreachable; list and board not checked");
+                Ok(())
+            }
+            result => result.map_err(DomainError::from),
+        }
+    }
+
+    async fn check(&self) -> Result<(), CallError> {
+        let list: TrelloList = self
+            .get(&format!("lists/{}", self.list_id))
+            .await
+            .map_err(|e| e.context(&format!("list {}", self.list_id)))?;
+        let board = self.board_id.as_deref().unwrap_or(&list.id_board);
+        let labels = self.labels(board).await?;
+        if board != list.id_board {
+            return Err(CallError::Rejected(format!(
+                "list {} is not on board {}",
+                self.list_id, board
+            )));
+        }
+        if let Some(label) = &self.label_id {
+            if !labels.iter().any(|l| l.id == *label) {
+                return Err(CallError::Rejected(format!(
+                    "label {} is not on board {}",
+                    label, board
+                )));
+            }
+        }
+        let red = red_label(&labels);
+        if red.is_none() {
+            info!("the Trello board has no red label; high-priority cards are not labelled");
+        }
+        let _ = self.red_label.set(red);
+        Ok(())
+    }
+
+    async fn labels(&self, board: &str) -> Result<Vec<Label>, CallError> {
+        self.get(&format!("boards/{}/labels", board))
+            .await
+            .map_err(|e| e.context(&format!("board {}", board)))
+    }
+
+    /// The board's red label, looked up on first use unless `validate` did. A failed lookup
+    /// is retried with the next high-priority card.
+    async fn high_priority_label(&self) -> Option<String> {
+        if let Some(label) = self.red_label.get() {
+            return label.clone();
+        }
+        let lookup = async {
+            let board = match &self.board_id {
+                Some(board) => board.clone(),
+                None => {
+                    self.get::<TrelloList>(&format!("lists/{}", self.list_id))
+                        .await?
+                        .id_board
+                }
+            };
+            self.labels(&board).await
+        };
+        match lookup.await {
+            Ok(labels) => {
+                let red = red_label(&labels);
+                let _ = self.red_label.set(red.clone());
+                red
+            }
+            Err(e) => {
+                warn!(error = ?e, "failed to look up the Trello red label");
+                None
+            }
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url, path)
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, CallError> {
+        let url = self.url(path);
+        self.send(|| self.client.get(&url)).await
+    }
+
+    /// Send the request built by `build` with the credentials and read the JSON answer. A 429
+    /// is retried up to [`MAX_RATE_LIMIT_RETRIES`] times, after its `Retry-After`.
+    async fn send<T: DeserializeOwned>(
         &self,
-        title: &str,
-        description: &str,
-        due: Option<String>,
-    ) -> Result<(), DomainError> {
-        let url = format!(
-            "{}?key={}&token={}",
-            TRELLO_CARDS_URL, self.api_key, self.token
+        build: impl Fn() -> RequestBuilder,
+    ) -> Result<T, CallError> {
+        let authorization = format!(
+            "OAuth oauth_consumer_key=\"{}\", oauth_token=\"{}\"",
+            self.api_key, self.token
         );
+        let mut attempt = 0u32;
+        let res = loop {
+            attempt += 1;
+            let res = build()
+                .header(AUTHORIZATION, &authorization)
+                .send()
+                .await
+                .map_err(|e| {
+                    CallError::Unreachable(
+                        self.redact(&format!("Request failed: {}", e.without_url())),
+                    )
+                })?;
+            if res.status() != StatusCode::TOO_MANY_REQUESTS || attempt > MAX_RATE_LIMIT_RETRIES {
+                break res;
+            }
+            let wait = res
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .map_or(RATE_LIMIT_WAIT, Duration::from_secs)
+                .min(MAX_RATE_LIMIT_WAIT);
+            warn!(
+                attempt,
+                wait_ms = wait.as_millis() as u64,
+                "Trello rate limit hit; retrying"
+            );
+            tokio::time::sleep(wait).await;
+        };
+
+        if !res.status().is_success() {
+            let status = res.status();
+            let text = res.text().await.unwrap_or_else(|_| "unknown".to_string());
+            return Err(CallError::Rejected(
+                self.redact(&format!("Trello API error {}: {}", status, text)),
+            ));
+        }
+
+        res.json().await.map_err(|e| {
+            CallError::Rejected(
+                self.redact(&format!("Invalid Trello response: {}", e.without_url())),
+            )
+        })
+    }
+
+    /// `text` with the API key and token masked.
+    fn redact(&self, text: &str) -> String {
+        [&self.api_key, &self.token]
+            .into_iter()
+            .filter(|secret| !secret.is_empty())
+            .fold(text.to_string(), |text, secret| {
+                text.replace(secret.as_str(), "****")
+            })
+    }
+}
 
+/// Id of the first red label.
+fn red_label(labels: &[Label]) -> Option<String> {
+    labels
+        .iter()
+        .find(|l| l.color.as_deref() == Some(HIGH_PRIORITY_COLOR))
+        .map(|l| l.id.clone())
+}
+
+#[async_trait::async_trait]
+impl TaskTrackerPort for TrelloAdapter {
+    async fn create_task(&self, task: &TaskSpec) -> Result<String, DomainError> {
         let mut body = serde_json::json!({
             "idList": self.list_id,
-            "name": title,
-            "desc": description,
+            "name": task.title,
+            "desc": task.description,
         });
 
-        if let Some(d) = due {
-            body["due"] = serde_json::Value::String(d);
+        if let Some(d) = &task.due {
+            body["due"] = serde_json::Value::String(d.clone());
+        }
+
+        let mut labels: Vec<String> = self.label_id.iter().cloned().collect();
+        if task
+            .priority
+            .as_deref()
+            .is_some_and(|p| p.eq_ignore_ascii_case("high"))
+        {
+            if let Some(red) = self.high_priority_label().await {
+                if !labels.contains(&red) {
+                    labels.push(red);
+                }
+            }
+        }
+        if !labels.is_empty() {
+            body["idLabels"] = serde_json::Value::String(labels.join(","));
+        }
+
+        let url = self.url("cards");
+        let card: CreatedCard = self.send(|| self.client.post(&url).json(&body)).await?;
+        Ok(card.id)
+    }
+
+    /// Paced: [`Self::with_request_delay`] between cards.
+    async fn create_tasks(&self, tasks: &[TaskSpec]) -> Vec<Result<String, DomainError>> {
+        let mut results = Vec::with_capacity(tasks.len());
+        for (i, task) in tasks.iter().enumerate() {
+            if i > 0 {
+                tokio::time::sleep(self.request_delay).await;
+            }
+            results.push(self.create_task(task).await);
+        }
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::http_stub;
+
+    /// Canned Trello API; returns its `/1` base URL and the requests received.
+    async fn serve(
+        replies: Vec<(&'static str, &'static str)>,
+    ) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+        let (url, server) = http_stub::serve(replies).await;
+        (format!("{}/1", url), server)
+    }
+
+    fn adapter(url: &str, board_id: Option<&str>) -> TrelloAdapter {
+        let mut trello = TrelloAdapter::new(
+            "k3y".to_string(),
+            "t0ken".to_string(),
+            board_id.map(str::to_string),
+            "list1".to_string(),
+        );
+        trello.base_url = url.to_string();
+        trello
+    }
+
+    fn task(title: &str, due: Option<&str>, priority: Option<&str>) -> TaskSpec {
+        TaskSpec {
+            title: title.to_string(),
+            description: String::new(),
+            due: due.map(str::to_string),
+            priority: priority.map(str::to_string),
         }
+    }
 
-        let res = self
-            .client
-            .post(&url)
-            .json(&body)
-            .send()
+    #[tokio::test]
+    async fn test_cards_carry_labels_and_credentials_stay_out_of_the_url() {
+        let (url, server) = serve(vec![
+            ("200 OK", r#"{"id":"list1","idBoard":"b1"}"#),
+            (
+                "200 OK",
+                r#"[{"id":"green1","color":"green"},{"id":"red1","color":"red"}]"#,
+            ),
+            ("200 OK", r#"{"id":"card1"}"#),
+            ("200 OK", r#"{"id":"card2"}"#),
+        ])
+        .await;
+        let trello = adapter(&url, None).with_label("green1".to_string());
+        trello.validate().await.unwrap();
+        let high = trello
+            .create_task(&task("Ship v2", Some("2024-06-01"), Some("High")))
             .await
-            .map_err(|e| DomainError::TaskTracker(format!("Request failed: {}", e)))?;
+            .unwrap();
+        assert_eq!(high, "card1");
+        let low = trello
+            .create_task(&task("Tidy up", None, Some("low")))
+            .await
+            .unwrap();
+        assert_eq!(low, "card2");
 
-        if !res.status().is_success() {
-            let status = res.status();
-            let text = res.text().await.unwrap_or_else(|_| "unknown".to_string());
-            return Err(DomainError::TaskTracker(format!(
-                "Trello API error {}: {}",
-                status, text
-            )));
+        let requests = server.await.unwrap();
+        assert!(requests[0].starts_with("GET /1/lists/list1 HTTP/1.1"));
+        assert!(requests[1].starts_with("GET /1/boards/b1/labels HTTP/1.1"));
+        assert!(requests[2].starts_with("POST /1/cards HTTP/1.1"));
+        for request in &requests {
+            let request_line = request.lines().next().unwrap();
+            assert!(!request_line.contains("k3y") && !request_line.contains("t0ken"));
+            assert!(request.to_lowercase().contains(
+                "authorization: oauth oauth_consumer_key=\"k3y\", oauth_token=\"t0ken\""
+            ));
         }
+        assert!(requests[2].contains("\"idList\":\"list1\""));
+        assert!(requests[2].contains("\"due\":\"2024-06-01\""));
+        assert!(requests[2].contains("\"idLabels\":\"green1,red1\""));
+        assert!(requests[3].contains("\"idLabels\":\"green1\""));
+    }
 
-        Ok(())
+    #[tokio::test]
+    async fn test_bad_ids_fail_validation_and_errors_mask_credentials() {
+        let (url, server) = serve(vec![
+            ("404 Not Found", "model not found"),
+            ("200 OK", r#"{"id":"list1","idBoard":"b1"}"#),
+            ("200 OK", "[]"),
+            ("401 Unauthorized", "invalid token t0ken for key k3y"),
+        ])
+        .await;
+        let err = adapter(&url, None).validate().await.unwrap_err();
+        assert!(err.to_string().contains("list list1"));
+        assert!(err.to_string().contains("404"));
+
+        let err = adapter(&url, Some("other")).validate().await.unwrap_err();
+        assert!(err.to_string().contains("not on board other"));
+
+        let err = adapter(&url, None)
+            .create_task(&task("t", None, None))
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("401"));
+        assert!(!err.contains("k3y") && !err.contains("t0ken"));
+        assert!(err.contains("invalid token **** for key ****"));
+        server.await.unwrap();
+
+        // Nothing listens any more: unreachable is a warning at startup, an error for cards.
+        let trello = adapter(&url, None);
+        trello.validate().await.unwrap();
+        let err = trello
+            .create_task(&task("t", None, None))
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Request failed"));
+        assert!(!err.contains("127.0.0.1"));
+    }
+
+    #[tokio::test]
+    async fn test_batch_is_paced_and_retries_rate_limits() {
+        let (url, server) = serve(vec![
+            ("200 OK", r#"{"id":"card1"}"#),
+            ("429 Too Many Requests\r\nretry-after: 0", "{}"),
+            ("200 OK", r#"{"id":"card2"}"#),
+            ("400 Bad Request", "invalid value for due"),
+        ])
+        .await;
+        let trello = adapter(&url, None).with_request_delay(Duration::from_millis(30));
+        let started = std::time::Instant::now();
+        let results = trello
+            .create_tasks(&[
+                task("a", None, None),
+                task("b", None, None),
+                task("c", Some("soon"), None),
+            ])
+            .await;
+        assert!(started.elapsed() >= Duration::from_millis(60));
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_deref().unwrap(), "card1");
+        assert_eq!(results[1].as_deref().unwrap(), "card2");
+        assert!(results[2].as_ref().unwrap_err().to_string().contains("400"));
+
+        let requests = server.await.unwrap();
+        assert!(requests[1].contains("\"name\":\"b\""));
+        assert!(requests[2].contains("\"name\":\"b\""));
     }
 }
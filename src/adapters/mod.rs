@@ -3,8 +3,15 @@
 //! Telegram, filesystem, external tools. Map errors to DomainError.
 
 pub mod ai;
+pub mod cli;
+pub mod export;
+pub mod headless;
+pub mod ingest;
 pub mod integrations;
+pub mod notify;
 pub mod persistence;
+pub mod recording;
+pub mod storage;
 pub mod telegram;
 pub mod tools;
 pub mod ui;
@@ -0,0 +1,112 @@
+//! HTTP heartbeat. Implements `HeartbeatPort` by requesting a URL, healthchecks.io style.
+//!
+//! Settings: `TG_SYNC_HEARTBEAT_URL` and optionally `TG_SYNC_HEARTBEAT_METHOD` (`get`, default,
+//! or `post`, which sends the cycle summary as a plain-text body). Any 2xx answer counts.
+
+use crate::domain::DomainError;
+use crate::ports::HeartbeatPort;
+use crate::shared::config::AppConfig;
+use reqwest::{Client, Url};
+use std::time::Duration;
+
+/// Timeout of one ping; a hanging monitor must not hold up the watcher for long.
+const PING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Pings a URL over HTTP.
+pub struct HttpHeartbeat {
+    client: Client,
+    url: Url,
+    post: bool,
+}
+
+impl HttpHeartbeat {
+    /// Create for an http or https `url`; `post` sends the summary as body instead of a GET.
+    pub fn new(url: &str, post: bool) -> Result<Self, DomainError> {
+        let url = Url::parse(url.trim())
+            .ok()
+            .filter(|u| matches!(u.scheme(), "http" | "https"))
+            .ok_or_else(|| {
+                DomainError::Notify(format!(
+                    "invalid TG_SYNC_HEARTBEAT_URL '{}' (expected an http or https URL)",
+                    url
+                ))
+            })?;
+        let client = Client::builder()
+            .timeout(PING_TIMEOUT)
+            .build()
+            .map_err(|e| DomainError::Notify(format!("HTTP client: {}", e)))?;
+        Ok(Self { client, url, post })
+    }
+
+    /// Build from config. `Ok(None)` when no TG_SYNC_HEARTBEAT_URL is set.
+    pub fn from_config(cfg: &AppConfig) -> Result<Option<Self>, DomainError> {
+        let Some(url) = cfg.heartbeat_url.as_deref() else {
+            return Ok(None);
+        };
+        let post = match cfg
+            .heartbeat_method
+            .as_deref()
+            .map(|m| m.trim().to_ascii_lowercase())
+            .as_deref()
+        {
+            None | Some("get") => false,
+            Some("post") => true,
+            Some(other) => {
+                return Err(DomainError::Notify(format!(
+                    "TG_SYNC_HEARTBEAT_METHOD: unknown method '{}' (expected get or post)",
+                    other
+                )));
+            }
+        };
+        Self::new(url, post).map(Some)
+    }
+}
+
+#[async_trait::async_trait]
+impl HeartbeatPort for HttpHeartbeat {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    async fn ping(&self, summary: &str) -> Result<(), DomainError> {
+        let request = if self.post {
+            self.client.post(self.url.clone()).body(summary.to_string())
+        } else {
+            self.client.get(self.url.clone())
+        };
+        // The URL usually carries the check's secret: log only the host.
+        let host = self.url.host_str().unwrap_or_default();
+        let res = request.send().await.map_err(|e| {
+            DomainError::Notify(format!("heartbeat to {}: {}", host, e.without_url()))
+        })?;
+        if !res.status().is_success() {
+            return Err(DomainError::Notify(format!(
+                "heartbeat to {}: {}",
+                host,
+                res.status()
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heartbeat_config() {
+        let mut cfg = AppConfig::default();
+        assert!(HttpHeartbeat::from_config(&cfg).unwrap().is_none());
+        cfg.heartbeat_url = Some(" https://hc-ping.com/abc ".to_string());
+        let heartbeat = HttpHeartbeat::from_config(&cfg).unwrap().unwrap();
+        assert!(!heartbeat.post);
+        assert_eq!(heartbeat.url.as_str(), "https://hc-ping.com/abc");
+        cfg.heartbeat_method = Some("POST".to_string());
+        assert!(HttpHeartbeat::from_config(&cfg).unwrap().unwrap().post);
+        cfg.heartbeat_method = Some("put".to_string());
+        assert!(HttpHeartbeat::from_config(&cfg).is_err());
+        assert!(HttpHeartbeat::new("ftp://host/x", false).is_err());
+        assert!(HttpHeartbeat::new("not a url", false).is_err());
+    }
+}
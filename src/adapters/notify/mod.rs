@@ -0,0 +1,32 @@
+//! Notification adapters. Implement `NotifierPort` (email digests, webhooks, Telegram
+//! messages) and `HeartbeatPort` (watcher liveness pings).
+
+pub mod email;
+pub mod heartbeat;
+pub mod telegram;
+pub mod webhook;
+
+pub use email::EmailNotifier;
+pub use heartbeat::HttpHeartbeat;
+pub use telegram::TelegramNotifier;
+pub use webhook::WebhookNotifier;
+
+use crate::domain::DomainError;
+use crate::ports::NotifierPort;
+use crate::shared::config::AppConfig;
+use std::sync::Arc;
+
+/// Build every notifier enabled in the config.
+///
+/// Misconfiguration (bad SMTP URL, TLS or credentials setup, addresses, webhook URL or
+/// format) is an error here, so it surfaces at startup and in `tg-sync config validate` rather than on first send.
+pub fn notifiers_from_config(cfg: &AppConfig) -> Result<Vec<Arc<dyn NotifierPort>>, DomainError> {
+    let mut notifiers: Vec<Arc<dyn NotifierPort>> = Vec::new();
+    if let Some(email) = EmailNotifier::from_config(cfg)? {
+        notifiers.push(Arc::new(email));
+    }
+    if let Some(webhook) = WebhookNotifier::from_config(cfg)? {
+        notifiers.push(Arc::new(webhook));
+    }
+    Ok(notifiers)
+}
@@ -0,0 +1,215 @@
+//! Telegram notifier. Implements `NotifierPort` by sending messages through `TgGateway`.
+//!
+//! Keyword alerts are sent as they are; other events as their Markdown rendered to plain text,
+//! split to Telegram's length limit. With a fallback chat (Saved Messages), the destination
+//! switches to it for good after [`FAILURES_BEFORE_FALLBACK`] failed sends in a row.
+
+use crate::domain::{DomainError, NotificationEvent};
+use crate::ports::{NotifierPort, TgGateway};
+use crate::shared::markdown;
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+/// Longest Telegram text message, in UTF-16 code units.
+const TELEGRAM_TEXT_LIMIT: usize = 4096;
+
+/// Consecutive failed sends after which the fallback chat is used.
+pub const FAILURES_BEFORE_FALLBACK: u32 = 2;
+
+/// Sends events as messages to one chat.
+pub struct TelegramNotifier {
+    tg: Arc<dyn TgGateway>,
+    target: Mutex<Target>,
+}
+
+/// Current destination and its failure streak.
+#[derive(Debug)]
+struct Target {
+    chat_id: i64,
+    fallback: Option<i64>,
+    /// Consecutive failed sends to `chat_id`.
+    failures: u32,
+}
+
+impl Target {
+    fn record_success(&mut self) {
+        self.failures = 0;
+    }
+
+    /// Count a failed send. Returns the fallback chat when this switched to it.
+    fn record_failure(&mut self) -> Option<i64> {
+        let fallback = self.fallback.filter(|&id| id != self.chat_id)?;
+        self.failures += 1;
+        if self.failures < FAILURES_BEFORE_FALLBACK {
+            return None;
+        }
+        self.chat_id = fallback;
+        self.failures = 0;
+        Some(fallback)
+    }
+}
+
+impl TelegramNotifier {
+    pub fn new(tg: Arc<dyn TgGateway>, chat_id: i64) -> Self {
+        Self {
+            tg,
+            target: Mutex::new(Target {
+                chat_id,
+                fallback: None,
+                failures: 0,
+            }),
+        }
+    }
+
+    /// Fall back to `chat_id` (Saved Messages) once the destination keeps failing.
+    pub fn with_fallback(self, chat_id: i64) -> Self {
+        self.target.lock().expect("target lock").fallback = Some(chat_id);
+        self
+    }
+
+    /// Chat messages currently go to.
+    pub fn chat_id(&self) -> i64 {
+        self.target.lock().expect("target lock").chat_id
+    }
+
+    /// Send one message, switching to the fallback chat when the destination keeps failing.
+    async fn send(&self, text: &str) -> Result<(), DomainError> {
+        let result = self.tg.send_message(self.chat_id(), text).await;
+        let Err(e) = &result else {
+            self.target.lock().expect("target lock").record_success();
+            return result;
+        };
+        let switched = self.target.lock().expect("target lock").record_failure();
+        match switched {
+            Some(fallback) => {
+                warn!(error = %e, "destination chat failed repeatedly; falling back to Saved Messages");
+                self.tg.send_message(fallback, text).await
+            }
+            None => result,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl NotifierPort for TelegramNotifier {
+    fn name(&self) -> &'static str {
+        "telegram"
+    }
+
+    async fn notify(&self, event: &NotificationEvent) -> Result<(), DomainError> {
+        let text = match event {
+            NotificationEvent::KeywordAlert { text, .. } => text.clone(),
+            other => markdown::to_plain_text(&other.markdown()),
+        };
+        for part in markdown::split_text(&text, TELEGRAM_TEXT_LIMIT) {
+            self.send(&part).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{Chat, MediaReference, Message};
+    use std::path::Path;
+
+    /// Telegram where sending to chat -100 fails.
+    #[derive(Default)]
+    struct FlakyTg {
+        sent: Mutex<Vec<(i64, String)>>,
+    }
+
+    #[async_trait::async_trait]
+    impl TgGateway for FlakyTg {
+        async fn get_dialogs(&self) -> Result<Vec<Chat>, DomainError> {
+            Ok(Vec::new())
+        }
+
+        async fn get_messages(
+            &self,
+            _chat_id: i64,
+            _min_id: i32,
+            _max_id: i32,
+            _limit: i32,
+        ) -> Result<Vec<Message>, DomainError> {
+            Ok(Vec::new())
+        }
+
+        async fn download_media(
+            &self,
+            _media_ref: &MediaReference,
+            _dest_path: &Path,
+        ) -> Result<(), DomainError> {
+            Ok(())
+        }
+
+        async fn get_me_id(&self) -> Result<i64, DomainError> {
+            Ok(1)
+        }
+
+        async fn send_message(&self, chat_id: i64, text: &str) -> Result<(), DomainError> {
+            if chat_id == -100 {
+                return Err(DomainError::TgGateway("CHAT_WRITE_FORBIDDEN".to_string()));
+            }
+            self.sent.lock().unwrap().push((chat_id, text.to_string()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_target_falls_back_after_two_failures() {
+        let mut target = Target {
+            chat_id: -100,
+            fallback: Some(1),
+            failures: 0,
+        };
+        assert_eq!(target.record_failure(), None);
+        target.record_success();
+        assert_eq!(target.record_failure(), None);
+        assert_eq!(target.chat_id, -100);
+        assert_eq!(target.record_failure(), Some(1));
+        assert_eq!(target.chat_id, 1);
+        // Saved Messages itself never "falls back".
+        assert_eq!(target.record_failure(), None);
+        assert_eq!(target.record_failure(), None);
+        assert_eq!(target.chat_id, 1);
+    }
+
+    fn alert(text: &str) -> NotificationEvent {
+        NotificationEvent::KeywordAlert {
+            chat_id: 5,
+            chat_title: "Ops".to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_to_saved_messages_after_two_failures() {
+        let tg = Arc::new(FlakyTg::default());
+        let notifier = TelegramNotifier::new(tg.clone(), -100).with_fallback(1);
+        assert!(notifier.notify(&alert("first")).await.is_err());
+        assert_eq!(notifier.chat_id(), -100);
+        // The second failure switches and delivers to Saved Messages.
+        notifier.notify(&alert("*second*")).await.unwrap();
+        assert_eq!(notifier.chat_id(), 1);
+        notifier
+            .notify(&NotificationEvent::Error {
+                context: "watcher".to_string(),
+                message: "**disk** full".to_string(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            *tg.sent.lock().unwrap(),
+            vec![(1, "*second*".to_string()), (1, "disk full".to_string())]
+        );
+
+        // Without a fallback the destination never changes.
+        let plain = TelegramNotifier::new(tg, -100);
+        for _ in 0..3 {
+            assert!(plain.notify(&alert("x")).await.is_err());
+        }
+        assert_eq!(plain.chat_id(), -100);
+    }
+}
@@ -0,0 +1,218 @@
+//! Webhook notifier. Implements `NotifierPort` by POSTing every event as JSON.
+//!
+//! Settings: `TG_SYNC_WEBHOOK_URL` and optionally `TG_SYNC_WEBHOOK_FORMAT`: `json` (default,
+//! `{"event", "subject", "text", ...event fields}`) or `slack` (`{"text": ...}` with the subject
+//! in bold), which Slack, Mattermost and Discord (`.../slack` webhook URLs) accept.
+
+use crate::domain::{DomainError, NotificationEvent};
+use crate::ports::NotifierPort;
+use crate::shared::config::AppConfig;
+use reqwest::{Client, Url};
+use serde_json::{Value, json};
+use std::time::Duration;
+
+/// Timeout of one request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Longest Slack message text; longer bodies are cut.
+const SLACK_TEXT_LIMIT: usize = 3500;
+
+/// Shape of the request body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookFormat {
+    Json,
+    Slack,
+}
+
+/// POSTs events to one URL.
+pub struct WebhookNotifier {
+    client: Client,
+    url: Url,
+    format: WebhookFormat,
+}
+
+impl WebhookNotifier {
+    /// Create for an http or https `url`.
+    pub fn new(url: &str, format: WebhookFormat) -> Result<Self, DomainError> {
+        let url = Url::parse(url.trim())
+            .ok()
+            .filter(|u| matches!(u.scheme(), "http" | "https"))
+            .ok_or_else(|| {
+                DomainError::Notify(
+                    "invalid TG_SYNC_WEBHOOK_URL (expected an http or https URL)".to_string(),
+                )
+            })?;
+        let client = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| DomainError::Notify(format!("HTTP client: {}", e)))?;
+        Ok(Self {
+            client,
+            url,
+            format,
+        })
+    }
+
+    /// Build from config. `Ok(None)` when no TG_SYNC_WEBHOOK_URL is set.
+    pub fn from_config(cfg: &AppConfig) -> Result<Option<Self>, DomainError> {
+        let Some(url) = cfg.webhook_url.as_deref() else {
+            return Ok(None);
+        };
+        let format = match cfg
+            .webhook_format
+            .as_deref()
+            .map(|f| f.trim().to_ascii_lowercase())
+            .as_deref()
+        {
+            None | Some("json") => WebhookFormat::Json,
+            Some("slack") => WebhookFormat::Slack,
+            Some(other) => {
+                return Err(DomainError::Notify(format!(
+                    "TG_SYNC_WEBHOOK_FORMAT: unknown format '{}' (expected json or slack)",
+                    other
+                )));
+            }
+        };
+        Self::new(url, format).map(Some)
+    }
+
+    /// Request body for `event`.
+    pub fn payload(&self, event: &NotificationEvent) -> Value {
+        let text = event.markdown();
+        match self.format {
+            WebhookFormat::Slack => {
+                let mut text = format!("*{}*\n{}", event.subject(), text);
+                if let Some((cut, _)) = text.char_indices().nth(SLACK_TEXT_LIMIT) {
+                    text.truncate(cut);
+                    text.push('…');
+                }
+                json!({ "text": text })
+            }
+            WebhookFormat::Json => {
+                let mut body = json!({
+                    "event": event.kind(),
+                    "subject": event.subject(),
+                    "text": text,
+                });
+                let fields = match event {
+                    NotificationEvent::KeywordAlert {
+                        chat_id,
+                        chat_title,
+                        ..
+                    } => json!({ "chat_id": chat_id, "chat_title": chat_title }),
+                    NotificationEvent::SyncCompleted {
+                        chats,
+                        messages,
+                        failed,
+                    } => json!({ "chats": chats, "messages": messages, "failed": failed }),
+                    NotificationEvent::AnalysisCompleted {
+                        chat_id,
+                        granularity,
+                        week_group,
+                        ..
+                    } => json!({
+                        "chat_id": chat_id,
+                        "granularity": granularity.label(),
+                        "period": week_group.to_string(),
+                    }),
+                    NotificationEvent::Digest { .. } => json!({}),
+                    NotificationEvent::Error { context, .. } => json!({ "context": context }),
+                };
+                if let (Some(body), Value::Object(fields)) = (body.as_object_mut(), fields) {
+                    body.extend(fields);
+                }
+                body
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl NotifierPort for WebhookNotifier {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    async fn notify(&self, event: &NotificationEvent) -> Result<(), DomainError> {
+        // Webhook URLs carry their secret: errors name the host only.
+        let host = self.url.host_str().unwrap_or_default();
+        let res = self
+            .client
+            .post(self.url.clone())
+            .json(&self.payload(event))
+            .send()
+            .await
+            .map_err(|e| {
+                DomainError::Notify(format!("webhook to {}: {}", host, e.without_url()))
+            })?;
+        if !res.status().is_success() {
+            return Err(DomainError::Notify(format!(
+                "webhook to {}: {}",
+                host,
+                res.status()
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::http_stub;
+
+    #[test]
+    fn test_payload_formats() {
+        let event = NotificationEvent::KeywordAlert {
+            chat_id: -100,
+            chat_title: "Ops".to_string(),
+            text: "[ALERT] 'error' in 'Ops'".to_string(),
+        };
+        let json = WebhookNotifier::new("https://hooks.example.com/x", WebhookFormat::Json)
+            .unwrap()
+            .payload(&event);
+        assert_eq!(
+            json,
+            json!({
+                "event": "keyword_alert",
+                "subject": "Keyword alert in 'Ops'",
+                "text": "[ALERT] 'error' in 'Ops'",
+                "chat_id": -100,
+                "chat_title": "Ops",
+            })
+        );
+        let slack = WebhookNotifier::new("https://hooks.example.com/x", WebhookFormat::Slack)
+            .unwrap()
+            .payload(&event);
+        assert_eq!(
+            slack,
+            json!({ "text": "*Keyword alert in 'Ops'*\n[ALERT] 'error' in 'Ops'" })
+        );
+
+        let mut cfg = AppConfig::default();
+        assert!(WebhookNotifier::from_config(&cfg).unwrap().is_none());
+        cfg.webhook_url = Some("https://hooks.example.com/x".to_string());
+        cfg.webhook_format = Some("teams".to_string());
+        assert!(WebhookNotifier::from_config(&cfg).is_err());
+        assert!(WebhookNotifier::new("mailto:me@example.com", WebhookFormat::Json).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_posts_json_and_reports_http_errors() {
+        let (url, server) =
+            http_stub::serve(vec![("200 OK", ""), ("500 Internal Server Error", "")]).await;
+        let notifier = WebhookNotifier::new(&format!("{}/hook", url), WebhookFormat::Json).unwrap();
+        let event = NotificationEvent::Error {
+            context: "watcher".to_string(),
+            message: "database is locked".to_string(),
+        };
+        notifier.notify(&event).await.unwrap();
+        let err = notifier.notify(&event).await.unwrap_err();
+        assert!(err.to_string().contains("500"));
+
+        let requests = server.await.unwrap();
+        assert!(requests[0].starts_with("POST /hook HTTP/1.1"));
+        assert!(requests[0].contains("\"event\":\"error\""));
+        assert!(requests[0].contains("\"context\":\"watcher\""));
+    }
+}
@@ -0,0 +1,792 @@
+//! Plain-text archive: one append-only JSONL file per chat (`<base>/<chat_id>.jsonl`) plus
+//! small JSON files for everything else a `RepoPort` stores.
+//!
+//! Greppable archive format, one serialized `Message` per line. Saves only append, so a
+//! message saved twice (e.g. after an edit) appears twice; readers keep the last line per id.
+//! Service events go to `<chat_id>.events.jsonl` the same way. Users, chats, forum topics,
+//! blacklist, targets, per-chat settings and the entity registry live in one JSON file each,
+//! replaced atomically
+//! (temp file, fsync, rename) like `StateJson`.
+//!
+//! Used as the JSONL mirror next to SQLite (see `MirrorRepo`), and as the message repository
+//! itself with TG_SYNC_REPO_BACKEND=jsonl (`data/jsonl/`). Queries read the files and
+//! aggregate in memory: there are no indexes, so search and statistics scan the chats.
+
+use super::scan_search::{matches_all, search_terms, snippet};
+use super::sqlite_repo::MAX_COMMENT_DEPTH;
+use crate::domain::{
+    ActivityBin, ActivityBucket, ArchiveStats, Chat, ChatEvent, ChatPurge, ChatSettings, ChatStats,
+    DomainError, ForumTopic, Message, MessageEdit, SearchHit, TimeRange, User,
+};
+use crate::ports::{EntityRegistry, RepoPort};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+const USERS_FILE: &str = "users.json";
+const CHATS_FILE: &str = "chats.json";
+const FORUM_TOPICS_FILE: &str = "forum_topics.json";
+const BLACKLIST_FILE: &str = "blacklist.json";
+const TARGETS_FILE: &str = "targets.json";
+const CHAT_SETTINGS_FILE: &str = "chat_settings.json";
+const ENTITIES_FILE: &str = "entities.json";
+
+/// Cached peer of the entity registry (`entities.json`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredEntity {
+    access_hash: i64,
+    peer_type: String,
+    username: Option<String>,
+}
+
+/// JSONL file-based message store.
+pub struct FsRepo {
+    base_dir: PathBuf,
+    /// Serializes read-modify-write cycles (JSON files, merged message saves).
+    write_lock: Mutex<()>,
+}
+
+impl FsRepo {
+    pub fn new(base_dir: impl AsRef<Path>) -> Self {
+        Self {
+            base_dir: base_dir.as_ref().to_path_buf(),
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    /// Path of a chat's JSONL file.
+    pub fn chat_path(&self, chat_id: i64) -> PathBuf {
+        self.base_dir.join(format!("{}.jsonl", chat_id))
+    }
+
+    /// Path of a chat's service event file.
+    fn events_path(&self, chat_id: i64) -> PathBuf {
+        self.base_dir.join(format!("{}.events.jsonl", chat_id))
+    }
+
+    /// Append messages to the chat's file (created on first save) as they are, without
+    /// merging them with stored versions. Used by the mirror.
+    pub async fn append_messages(
+        &self,
+        chat_id: i64,
+        messages: &[Message],
+    ) -> Result<(), DomainError> {
+        self.create_base_dir().await?;
+        append_jsonl(&self.chat_path(chat_id), messages).await
+    }
+
+    async fn create_base_dir(&self) -> Result<(), DomainError> {
+        fs::create_dir_all(&self.base_dir)
+            .await
+            .map_err(|e| DomainError::Repo(format!("create {}: {}", self.base_dir.display(), e)))
+    }
+
+    /// Stored messages of a chat as saved (last line per id), oldest first by id.
+    async fn load_chat(&self, chat_id: i64) -> Result<Vec<Message>, DomainError> {
+        let mut by_id: BTreeMap<i32, Message> = BTreeMap::new();
+        for msg in read_jsonl::<Message>(&self.chat_path(chat_id)).await? {
+            by_id.insert(msg.id, msg);
+        }
+        Ok(by_id.into_values().collect())
+    }
+
+    /// [`Self::load_chat`] with sender names and topic titles filled in, as reads return them.
+    async fn read_chat(&self, chat_id: i64) -> Result<Vec<Message>, DomainError> {
+        let mut messages = self.load_chat(chat_id).await?;
+        let users: BTreeMap<i64, User> = self.read_json(USERS_FILE).await?;
+        let topics: BTreeMap<i64, Vec<ForumTopic>> = self.read_json(FORUM_TOPICS_FILE).await?;
+        let topics = topics.get(&chat_id);
+        for msg in &mut messages {
+            msg.sender_name = msg
+                .from_user_id
+                .and_then(|id| users.get(&id))
+                .and_then(display_name);
+            msg.topic_title = msg.topic_id.and_then(|id| {
+                topics
+                    .and_then(|t| t.iter().find(|t| t.id == id))
+                    .map(|t| t.title.clone())
+            });
+        }
+        Ok(messages)
+    }
+
+    /// Ids of the chats with a message file, ascending.
+    async fn chat_ids(&self) -> Result<Vec<i64>, DomainError> {
+        let mut entries = match fs::read_dir(&self.base_dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(DomainError::Repo(format!(
+                    "read {}: {}",
+                    self.base_dir.display(),
+                    e
+                )));
+            }
+        };
+        let mut ids = Vec::new();
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            // `<id>.events.jsonl` does not parse as an id.
+            if let Some(id) = name.strip_suffix(".jsonl").and_then(|s| s.parse().ok()) {
+                ids.push(id);
+            }
+        }
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    /// Contents of a JSON file of the base dir; the default when it does not exist yet.
+    async fn read_json<T: DeserializeOwned + Default>(&self, name: &str) -> Result<T, DomainError> {
+        let path = self.base_dir.join(name);
+        match fs::read_to_string(&path).await {
+            Ok(s) => serde_json::from_str(&s)
+                .map_err(|e| DomainError::Repo(format!("{}: {}", path.display(), e))),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(T::default()),
+            Err(e) => Err(DomainError::Repo(format!("read {}: {}", path.display(), e))),
+        }
+    }
+
+    /// Replace a JSON file of the base dir: temp file, fsync, rename.
+    async fn write_json<T: Serialize>(&self, name: &str, value: &T) -> Result<(), DomainError> {
+        self.create_base_dir().await?;
+        let path = self.base_dir.join(name);
+        let json =
+            serde_json::to_string_pretty(value).map_err(|e| DomainError::Repo(e.to_string()))?;
+        let temp_path = path.with_extension("json.tmp");
+        let mut f = fs::File::create(&temp_path)
+            .await
+            .map_err(|e| DomainError::Repo(format!("create {}: {}", temp_path.display(), e)))?;
+        f.write_all(json.as_bytes())
+            .await
+            .map_err(|e| DomainError::Repo(format!("write {}: {}", temp_path.display(), e)))?;
+        f.sync_all()
+            .await
+            .map_err(|e| DomainError::Repo(format!("sync {}: {}", temp_path.display(), e)))?;
+        drop(f);
+        fs::rename(&temp_path, &path)
+            .await
+            .map_err(|e| DomainError::Repo(format!("rename {}: {}", temp_path.display(), e)))
+    }
+
+    /// Read, change and write back one JSON file under the write lock.
+    async fn update_json<T, R>(
+        &self,
+        name: &str,
+        change: impl FnOnce(&mut T) -> R,
+    ) -> Result<R, DomainError>
+    where
+        T: DeserializeOwned + Serialize + Default,
+    {
+        let _guard = self.write_lock.lock().await;
+        let mut value: T = self.read_json(name).await?;
+        let result = change(&mut value);
+        self.write_json(name, &value).await?;
+        Ok(result)
+    }
+
+    async fn collect_chat_stats(&self, chat_id: i64) -> Result<Option<ChatStats>, DomainError> {
+        let messages = self.load_chat(chat_id).await?;
+        if messages.is_empty() {
+            return Ok(None);
+        }
+        let mut stats = ChatStats {
+            chat_id,
+            messages: messages.len() as u64,
+            oldest: messages.iter().map(|m| m.date).min(),
+            newest: messages.iter().map(|m| m.date).max(),
+            ..Default::default()
+        };
+        for media in messages.iter().filter_map(|m| m.media.as_ref()) {
+            match stats
+                .media_by_type
+                .iter_mut()
+                .find(|(k, _)| *k == media.media_type)
+            {
+                Some((_, n)) => *n += 1,
+                None => stats.media_by_type.push((media.media_type, 1)),
+            }
+        }
+        stats
+            .media_by_type
+            .sort_by_key(|(_, n)| std::cmp::Reverse(*n));
+        // Downloaded files are indexed in SQLite only (media_files).
+        Ok(Some(stats))
+    }
+
+    /// Total size of the files in the base dir, in bytes.
+    async fn size_bytes(&self) -> u64 {
+        let Ok(mut entries) = fs::read_dir(&self.base_dir).await else {
+            return 0;
+        };
+        let mut total = 0;
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            if let Ok(meta) = entry.metadata().await {
+                if meta.is_file() {
+                    total += meta.len();
+                }
+            }
+        }
+        total
+    }
+}
+
+/// Display name of a sender: first and last name, else `@username` (as the SQLite join).
+fn display_name(user: &User) -> Option<String> {
+    let name = format!(
+        "{} {}",
+        user.first_name.as_deref().unwrap_or(""),
+        user.last_name.as_deref().unwrap_or("")
+    );
+    let name = name.trim();
+    if !name.is_empty() {
+        Some(name.to_string())
+    } else {
+        user.username.as_ref().map(|u| format!("@{}", u))
+    }
+}
+
+/// Every line of a JSONL file, in file order. Missing file means no lines.
+async fn read_jsonl<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>, DomainError> {
+    let content = match fs::read_to_string(path).await {
+        Ok(s) => s,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(DomainError::Repo(format!("read {}: {}", path.display(), e))),
+    };
+    let mut items = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        items.push(
+            serde_json::from_str(line).map_err(|e| {
+                DomainError::Repo(format!("{} line {}: {}", path.display(), i + 1, e))
+            })?,
+        );
+    }
+    Ok(items)
+}
+
+/// Append items as JSON lines to `path` in a single write.
+pub(crate) async fn append_jsonl<T: Serialize>(
+    path: &Path,
+    items: &[T],
+) -> Result<(), DomainError> {
+    if items.is_empty() {
+        return Ok(());
+    }
+    let mut buf = String::new();
+    for item in items {
+        let line = serde_json::to_string(item).map_err(|e| DomainError::Repo(e.to_string()))?;
+        buf.push_str(&line);
+        buf.push('\n');
+    }
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+        .map_err(|e| DomainError::Repo(format!("open {}: {}", path.display(), e)))?;
+    file.write_all(buf.as_bytes())
+        .await
+        .map_err(|e| DomainError::Repo(format!("append {}: {}", path.display(), e)))?;
+    file.flush()
+        .await
+        .map_err(|e| DomainError::Repo(format!("flush {}: {}", path.display(), e)))
+}
+
+/// Remove a file, returning its size; None when it did not exist.
+async fn remove_counted(path: &Path) -> Result<Option<u64>, DomainError> {
+    let Ok(meta) = fs::metadata(path).await else {
+        return Ok(None);
+    };
+    fs::remove_file(path)
+        .await
+        .map_err(|e| DomainError::Repo(format!("remove {}: {}", path.display(), e)))?;
+    Ok(Some(meta.len()))
+}
+
+#[async_trait::async_trait]
+impl RepoPort for FsRepo {
+    /// Appends each message merged with its stored version, like the SQLite upsert: a changed
+    /// text moves the old one into the edit history, a known comment link is kept.
+    async fn save_messages(&self, chat_id: i64, messages: &[Message]) -> Result<(), DomainError> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+        let _guard = self.write_lock.lock().await;
+        let mut stored: HashMap<i32, Message> = self
+            .load_chat(chat_id)
+            .await?
+            .into_iter()
+            .map(|m| (m.id, m))
+            .collect();
+        let mut lines = Vec::with_capacity(messages.len());
+        for m in messages {
+            let mut line = Message {
+                chat_id,
+                sender_name: None,
+                topic_title: None,
+                edit_history: None,
+                ..m.clone()
+            };
+            if let Some(prev) = stored.get(&m.id) {
+                let mut history = prev.edit_history.clone().unwrap_or_default();
+                if prev.text != m.text && m.poll.is_none() {
+                    history.push(MessageEdit {
+                        date: prev.date,
+                        text: prev.text.clone(),
+                    });
+                }
+                line.edit_history = (!history.is_empty()).then_some(history);
+                line.linked_channel_post = m.linked_channel_post.or(prev.linked_channel_post);
+            }
+            stored.insert(m.id, line.clone());
+            lines.push(line);
+        }
+        self.append_messages(chat_id, &lines).await
+    }
+
+    async fn save_users(&self, users: &[User]) -> Result<(), DomainError> {
+        if users.is_empty() {
+            return Ok(());
+        }
+        self.update_json(USERS_FILE, |stored: &mut BTreeMap<i64, User>| {
+            for u in users {
+                stored.insert(u.id, u.clone());
+            }
+        })
+        .await
+    }
+
+    /// Load messages for a chat, newest first (by id). Missing file means no messages.
+    async fn get_messages(
+        &self,
+        chat_id: i64,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<Message>, DomainError> {
+        Ok(self
+            .read_chat(chat_id)
+            .await?
+            .into_iter()
+            .rev()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .collect())
+    }
+
+    async fn get_messages_after(
+        &self,
+        chat_id: i64,
+        after_id: i32,
+        limit: u32,
+    ) -> Result<Vec<Message>, DomainError> {
+        Ok(self
+            .read_chat(chat_id)
+            .await?
+            .into_iter()
+            .filter(|m| m.id > after_id)
+            .take(limit as usize)
+            .collect())
+    }
+
+    async fn get_messages_in_range(
+        &self,
+        chat_id: i64,
+        range: TimeRange,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<Message>, DomainError> {
+        let mut messages: Vec<Message> = self
+            .read_chat(chat_id)
+            .await?
+            .into_iter()
+            .filter(|m| range.from <= m.date && m.date < range.to)
+            .collect();
+        messages.sort_by_key(|m| (m.date, m.id));
+        Ok(messages
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .collect())
+    }
+
+    async fn get_messages_by_ids(
+        &self,
+        chat_id: i64,
+        ids: &[i32],
+    ) -> Result<Vec<Message>, DomainError> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let ids: HashSet<i32> = ids.iter().copied().collect();
+        Ok(self
+            .read_chat(chat_id)
+            .await?
+            .into_iter()
+            .filter(|m| ids.contains(&m.id))
+            .collect())
+    }
+
+    /// Scans the stored text: every query word must occur in it. Newest first; no ranking.
+    async fn search_messages(
+        &self,
+        query: &str,
+        chat_id: Option<i64>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<SearchHit>, DomainError> {
+        let terms = search_terms(query);
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+        let chat_ids = match chat_id {
+            Some(id) => vec![id],
+            None => self.chat_ids().await?,
+        };
+        let mut matches = Vec::new();
+        for id in chat_ids {
+            matches.extend(
+                self.read_chat(id)
+                    .await?
+                    .into_iter()
+                    .filter(|m| !m.text.is_empty() && matches_all(&m.text, &terms)),
+            );
+        }
+        matches.sort_by_key(|m| (std::cmp::Reverse(m.date), m.chat_id, m.id));
+        let chats: BTreeMap<i64, Chat> = self.read_json(CHATS_FILE).await?;
+        Ok(matches
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .map(|message| SearchHit {
+                snippet: snippet(&message.text, &terms),
+                chat_title: chats.get(&message.chat_id).map(|c| c.title.clone()),
+                message,
+            })
+            .collect())
+    }
+
+    async fn get_blacklisted_ids(&self) -> Result<HashSet<i64>, DomainError> {
+        let ids: BTreeSet<i64> = self.read_json(BLACKLIST_FILE).await?;
+        Ok(ids.into_iter().collect())
+    }
+
+    async fn update_blacklist(&self, ids: HashSet<i64>) -> Result<(), DomainError> {
+        self.update_json(BLACKLIST_FILE, |stored: &mut BTreeSet<i64>| {
+            *stored = ids.into_iter().collect();
+        })
+        .await
+    }
+
+    async fn get_target_ids(&self) -> Result<HashSet<i64>, DomainError> {
+        let ids: BTreeSet<i64> = self.read_json(TARGETS_FILE).await?;
+        Ok(ids.into_iter().collect())
+    }
+
+    async fn update_targets(&self, ids: HashSet<i64>) -> Result<(), DomainError> {
+        self.update_json(TARGETS_FILE, |stored: &mut BTreeSet<i64>| {
+            *stored = ids.into_iter().collect();
+        })
+        .await
+    }
+
+    async fn get_chat_settings(&self, chat_id: i64) -> Result<ChatSettings, DomainError> {
+        let mut stored: BTreeMap<i64, ChatSettings> = self.read_json(CHAT_SETTINGS_FILE).await?;
+        Ok(stored
+            .remove(&chat_id)
+            .unwrap_or_else(|| ChatSettings::new(chat_id)))
+    }
+
+    async fn list_chat_settings(&self) -> Result<Vec<ChatSettings>, DomainError> {
+        let stored: BTreeMap<i64, ChatSettings> = self.read_json(CHAT_SETTINGS_FILE).await?;
+        Ok(stored.into_values().collect())
+    }
+
+    async fn set_chat_settings(&self, settings: &ChatSettings) -> Result<(), DomainError> {
+        self.update_json(
+            CHAT_SETTINGS_FILE,
+            |stored: &mut BTreeMap<i64, ChatSettings>| {
+                if settings.is_default() {
+                    stored.remove(&settings.chat_id);
+                } else {
+                    stored.insert(settings.chat_id, settings.clone());
+                }
+            },
+        )
+        .await
+    }
+
+    async fn get_activity_histogram(
+        &self,
+        chat_id: i64,
+        bucket: ActivityBucket,
+        range: TimeRange,
+        utc_offset_secs: i32,
+    ) -> Result<Vec<ActivityBin>, DomainError> {
+        let offset = utc_offset_secs as i64;
+        let mut counts: BTreeMap<i64, u64> = BTreeMap::new();
+        for msg in self.load_chat(chat_id).await? {
+            if msg.date < range.from || msg.date >= range.to {
+                continue;
+            }
+            // Same bucketing as the SQL aggregate: local time, days shifted back to UTC.
+            let local = msg.date + offset;
+            let key = match bucket {
+                ActivityBucket::Day => (local / 86400) * 86400 - offset,
+                ActivityBucket::HourOfDay => (local % 86400) / 3600,
+            };
+            *counts.entry(key).or_default() += 1;
+        }
+        Ok(counts
+            .into_iter()
+            .map(|(bucket_start, count)| ActivityBin {
+                bucket_start,
+                count,
+            })
+            .collect())
+    }
+
+    async fn register_synthetic_chat(&self, chat: &Chat) -> Result<(), DomainError> {
+        self.upsert_chats(std::slice::from_ref(chat)).await
+    }
+
+    async fn upsert_chats(&self, chats: &[Chat]) -> Result<(), DomainError> {
+        if chats.is_empty() {
+            return Ok(());
+        }
+        self.update_json(CHATS_FILE, |stored: &mut BTreeMap<i64, Chat>| {
+            for c in chats {
+                stored.insert(c.id, c.clone());
+            }
+        })
+        .await
+    }
+
+    async fn save_forum_topics(
+        &self,
+        chat_id: i64,
+        topics: &[ForumTopic],
+    ) -> Result<(), DomainError> {
+        self.update_json(
+            FORUM_TOPICS_FILE,
+            |stored: &mut BTreeMap<i64, Vec<ForumTopic>>| {
+                if topics.is_empty() {
+                    stored.remove(&chat_id);
+                } else {
+                    stored.insert(chat_id, topics.to_vec());
+                }
+            },
+        )
+        .await
+    }
+
+    async fn save_events(&self, events: &[ChatEvent]) -> Result<(), DomainError> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        self.create_base_dir().await?;
+        let mut by_chat: BTreeMap<i64, Vec<ChatEvent>> = BTreeMap::new();
+        for event in events {
+            by_chat
+                .entry(event.chat_id)
+                .or_default()
+                .push(event.clone());
+        }
+        for (chat_id, events) in by_chat {
+            append_jsonl(&self.events_path(chat_id), &events).await?;
+        }
+        Ok(())
+    }
+
+    async fn get_events(&self, chat_id: i64) -> Result<Vec<ChatEvent>, DomainError> {
+        let mut by_id: BTreeMap<i32, ChatEvent> = BTreeMap::new();
+        for event in read_jsonl::<ChatEvent>(&self.events_path(chat_id)).await? {
+            by_id.insert(event.id, event);
+        }
+        Ok(by_id.into_values().collect())
+    }
+
+    async fn link_channel_comments(&self, chat_id: i64) -> Result<u64, DomainError> {
+        let _guard = self.write_lock.lock().await;
+        let mut messages = self.load_chat(chat_id).await?;
+        let mut changed: BTreeSet<usize> = BTreeSet::new();
+        for _ in 0..MAX_COMMENT_DEPTH {
+            let posts: HashMap<i32, i32> = messages
+                .iter()
+                .filter_map(|m| m.linked_channel_post.map(|p| (m.id, p)))
+                .collect();
+            let mut linked = 0;
+            for (i, msg) in messages.iter_mut().enumerate() {
+                if msg.linked_channel_post.is_some() {
+                    continue;
+                }
+                if let Some(post) = msg.reply_to_msg_id.and_then(|r| posts.get(&r)) {
+                    msg.linked_channel_post = Some(*post);
+                    changed.insert(i);
+                    linked += 1;
+                }
+            }
+            if linked == 0 {
+                break;
+            }
+        }
+        let lines: Vec<Message> = changed.iter().map(|&i| messages[i].clone()).collect();
+        append_jsonl(&self.chat_path(chat_id), &lines).await?;
+        Ok(lines.len() as u64)
+    }
+
+    async fn get_channel_comments(
+        &self,
+        channel_id: i64,
+        post_ids: &[i32],
+    ) -> Result<Vec<Message>, DomainError> {
+        if post_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let posts: HashSet<i32> = post_ids.iter().copied().collect();
+        let mut comments = Vec::new();
+        for chat_id in self.chat_ids().await? {
+            let messages = self.read_chat(chat_id).await?;
+            // Discussion groups hold the automatic copies of the channel's posts; the copies
+            // themselves are not comments.
+            let is_discussion = messages.iter().any(|m| {
+                m.linked_channel_post.is_some() && m.forwarded_from_id == Some(channel_id)
+            });
+            if !is_discussion {
+                continue;
+            }
+            comments.extend(messages.into_iter().filter(|m| {
+                m.linked_channel_post.is_some_and(|p| posts.contains(&p))
+                    && m.forwarded_from_id != Some(channel_id)
+            }));
+        }
+        comments.sort_by_key(|m| (m.linked_channel_post, m.id));
+        Ok(comments)
+    }
+
+    async fn get_known_chats(&self) -> Result<Vec<Chat>, DomainError> {
+        let chats: BTreeMap<i64, Chat> = self.read_json(CHATS_FILE).await?;
+        let mut chats: Vec<Chat> = chats.into_values().collect();
+        chats.sort_by_cached_key(|c| (c.title.to_lowercase(), c.id));
+        Ok(chats)
+    }
+
+    async fn get_chat_stats(&self, chat_id: i64) -> Result<ChatStats, DomainError> {
+        Ok(self
+            .collect_chat_stats(chat_id)
+            .await?
+            .unwrap_or(ChatStats {
+                chat_id,
+                ..Default::default()
+            }))
+    }
+
+    async fn get_global_stats(&self) -> Result<ArchiveStats, DomainError> {
+        let mut chats = Vec::new();
+        for chat_id in self.chat_ids().await? {
+            chats.extend(self.collect_chat_stats(chat_id).await?);
+        }
+        Ok(ArchiveStats {
+            chats,
+            db_size_bytes: self.size_bytes().await,
+        })
+    }
+
+    /// Removes the chat's message and event files (counted as files) and its entries in the
+    /// JSON files. There is no media index here, so no media paths are returned.
+    async fn delete_chat_data(&self, chat_id: i64) -> Result<ChatPurge, DomainError> {
+        let messages = self.load_chat(chat_id).await?.len() as u64;
+        let events = self.get_events(chat_id).await?.len() as u64;
+        let _guard = self.write_lock.lock().await;
+        let mut purge = ChatPurge::default();
+        for path in [self.chat_path(chat_id), self.events_path(chat_id)] {
+            if let Some(bytes) = remove_counted(&path).await? {
+                purge.files += 1;
+                purge.bytes += bytes;
+            }
+        }
+        let mut topics: BTreeMap<i64, Vec<ForumTopic>> = self.read_json(FORUM_TOPICS_FILE).await?;
+        let topic_rows = topics.remove(&chat_id).map_or(0, |t| t.len() as u64);
+        if topic_rows > 0 {
+            self.write_json(FORUM_TOPICS_FILE, &topics).await?;
+        }
+        let mut targets: BTreeSet<i64> = self.read_json(TARGETS_FILE).await?;
+        let target_rows = targets.remove(&chat_id) as u64;
+        if target_rows > 0 {
+            self.write_json(TARGETS_FILE, &targets).await?;
+        }
+        let mut settings: BTreeMap<i64, ChatSettings> = self.read_json(CHAT_SETTINGS_FILE).await?;
+        let settings_rows = settings.remove(&chat_id).is_some() as u64;
+        if settings_rows > 0 {
+            self.write_json(CHAT_SETTINGS_FILE, &settings).await?;
+        }
+        let mut chats: BTreeMap<i64, Chat> = self.read_json(CHATS_FILE).await?;
+        let chat_rows = chats.remove(&chat_id).is_some() as u64;
+        if chat_rows > 0 {
+            self.write_json(CHATS_FILE, &chats).await?;
+        }
+        // Same table names and order as the SQLite purge.
+        for (table, rows) in [
+            ("messages", messages),
+            ("chat_events", events),
+            ("forum_topics", topic_rows),
+            ("targets", target_rows),
+            ("chat_settings", settings_rows),
+            ("chats", chat_rows),
+        ] {
+            if rows > 0 {
+                purge.rows.push((table, rows));
+            }
+        }
+        Ok(purge)
+    }
+}
+
+#[async_trait::async_trait]
+impl EntityRegistry for FsRepo {
+    async fn get_access_hash(&self, peer_id: i64) -> Result<Option<i64>, DomainError> {
+        Ok(self.get_entity(peer_id).await?.map(|(hash, _)| hash))
+    }
+
+    async fn get_entity(&self, peer_id: i64) -> Result<Option<(i64, String)>, DomainError> {
+        let entities: BTreeMap<i64, StoredEntity> = self.read_json(ENTITIES_FILE).await?;
+        Ok(entities
+            .get(&peer_id)
+            .map(|e| (e.access_hash, e.peer_type.clone())))
+    }
+
+    async fn save_entity(
+        &self,
+        peer_id: i64,
+        access_hash: i64,
+        peer_type: &str,
+        username: Option<&str>,
+    ) -> Result<(), DomainError> {
+        self.update_json(ENTITIES_FILE, |stored: &mut BTreeMap<i64, StoredEntity>| {
+            stored.insert(
+                peer_id,
+                StoredEntity {
+                    access_hash,
+                    peer_type: peer_type.to_string(),
+                    username: username.map(str::to_string),
+                },
+            );
+        })
+        .await
+    }
+
+    async fn get_username(&self, peer_id: i64) -> Result<Option<String>, DomainError> {
+        let entities: BTreeMap<i64, StoredEntity> = self.read_json(ENTITIES_FILE).await?;
+        Ok(entities.get(&peer_id).and_then(|e| e.username.clone()))
+    }
+}
@@ -0,0 +1,205 @@
+//! Versioned schema migrations for the SQLite archive.
+//!
+//! The applied versions are recorded in `schema_version` (one row per migration, with its
+//! name and when it ran). [`migrate`] applies every migration newer than the database, in
+//! order, each in its own transaction together with its `schema_version` row: a failing
+//! step rolls back completely and leaves the database at the previous version. Databases
+//! created before this table existed are at version 0.
+//!
+//! New schema changes are appended to the migration list of the repository (see
+//! `SqliteRepo`) with the next version number; applied migrations are never edited.
+
+use crate::domain::DomainError;
+use futures::future::BoxFuture;
+use libsql::{Connection, params};
+use std::time::Instant;
+use tracing::info;
+
+const SCHEMA_VERSION_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS schema_version (
+    version INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    applied_at INTEGER NOT NULL
+)"#;
+
+/// What a migration does: plain statements, or code for changes that depend on the data
+/// or the current layout. Both run inside the migration's transaction and must not open
+/// one of their own.
+pub(crate) enum MigrationStep {
+    Sql(&'static [&'static str]),
+    Rust(for<'a> fn(&'a Connection) -> BoxFuture<'a, Result<(), DomainError>>),
+}
+
+/// One schema version.
+pub(crate) struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    pub step: MigrationStep,
+}
+
+/// Highest version recorded in the database (0 when none is).
+pub(crate) async fn current_version(conn: &Connection) -> Result<u32, DomainError> {
+    conn.execute(SCHEMA_VERSION_TABLE, ())
+        .await
+        .map_err(|e| DomainError::Repo(e.to_string()))?;
+    let mut rows = conn
+        .query("SELECT COALESCE(MAX(version), 0) FROM schema_version", ())
+        .await
+        .map_err(|e| DomainError::Repo(e.to_string()))?;
+    match rows
+        .next()
+        .await
+        .map_err(|e| DomainError::Repo(e.to_string()))?
+    {
+        Some(row) => Ok(row
+            .get::<i64>(0)
+            .map_err(|e| DomainError::Repo(e.to_string()))? as u32),
+        None => Ok(0),
+    }
+}
+
+/// Bring the database up to the last of `migrations` (ordered by version). Returns the
+/// version reached. Fails for a database written by a newer version of the app.
+pub(crate) async fn migrate(
+    conn: &Connection,
+    migrations: &[Migration],
+) -> Result<u32, DomainError> {
+    debug_assert!(migrations.windows(2).all(|w| w[0].version < w[1].version));
+    let start = current_version(conn).await?;
+    let latest = migrations.last().map_or(0, |m| m.version);
+    if start > latest {
+        return Err(DomainError::Repo(format!(
+            "database schema version {} is newer than this build supports ({}); update tg-sync",
+            start, latest
+        )));
+    }
+    let mut version = start;
+    for migration in migrations.iter().filter(|m| m.version > start) {
+        info!(
+            version = migration.version,
+            name = migration.name,
+            "applying schema migration"
+        );
+        let started = Instant::now();
+        apply(conn, migration).await.map_err(|e| {
+            DomainError::Repo(format!(
+                "schema migration {} ({}) failed, database left at version {}: {}",
+                migration.version, migration.name, version, e
+            ))
+        })?;
+        version = migration.version;
+        info!(
+            version,
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "schema migration applied"
+        );
+    }
+    Ok(version)
+}
+
+/// Run one migration and record it, all or nothing.
+async fn apply(conn: &Connection, migration: &Migration) -> Result<(), DomainError> {
+    let tx = conn
+        .transaction()
+        .await
+        .map_err(|e| DomainError::Repo(e.to_string()))?;
+    let result = async {
+        match &migration.step {
+            MigrationStep::Sql(statements) => {
+                for sql in *statements {
+                    tx.execute(sql, ())
+                        .await
+                        .map_err(|e| DomainError::Repo(e.to_string()))?;
+                }
+            }
+            MigrationStep::Rust(step) => step(&tx).await?,
+        }
+        tx.execute(
+            "INSERT INTO schema_version (version, name, applied_at) VALUES (?1, ?2, ?3)",
+            params![
+                migration.version,
+                migration.name,
+                chrono::Utc::now().timestamp()
+            ],
+        )
+        .await
+        .map_err(|e| DomainError::Repo(e.to_string()))?;
+        Ok(())
+    }
+    .await;
+    match result {
+        Ok(()) => tx
+            .commit()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string())),
+        Err(e) => {
+            let _ = tx.rollback().await;
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_notes(conn: &Connection) -> BoxFuture<'_, Result<(), DomainError>> {
+        Box::pin(async move {
+            conn.execute("INSERT INTO notes (body) VALUES ('from code')", ())
+                .await
+                .map_err(|e| DomainError::Repo(e.to_string()))?;
+            Ok(())
+        })
+    }
+
+    const MIGRATIONS: [Migration; 3] = [
+        Migration {
+            version: 1,
+            name: "notes",
+            step: MigrationStep::Sql(&["CREATE TABLE notes (body TEXT NOT NULL)"]),
+        },
+        Migration {
+            version: 2,
+            name: "seed",
+            step: MigrationStep::Rust(create_notes),
+        },
+        Migration {
+            version: 3,
+            name: "broken",
+            step: MigrationStep::Sql(&[
+                "ALTER TABLE notes ADD COLUMN author TEXT",
+                "INSERT INTO missing_table VALUES (1)",
+            ]),
+        },
+    ];
+
+    async fn memory_db() -> Connection {
+        libsql::Builder::new_local(":memory:")
+            .build()
+            .await
+            .unwrap()
+            .connect()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_failed_migration_leaves_previous_version() {
+        let conn = memory_db().await;
+        assert_eq!(current_version(&conn).await.unwrap(), 0);
+        assert_eq!(migrate(&conn, &MIGRATIONS[..2]).await.unwrap(), 2);
+
+        let err = migrate(&conn, &MIGRATIONS).await.unwrap_err();
+        assert!(err.to_string().contains("left at version 2"));
+        assert_eq!(current_version(&conn).await.unwrap(), 2);
+        // The column added before the failing statement was rolled back too.
+        assert!(conn.query("SELECT author FROM notes", ()).await.is_err());
+        // Already applied steps don't run again.
+        assert_eq!(migrate(&conn, &MIGRATIONS[..2]).await.unwrap(), 2);
+        let mut rows = conn.query("SELECT COUNT(*) FROM notes", ()).await.unwrap();
+        let row = rows.next().await.unwrap().unwrap();
+        assert_eq!(row.get::<i64>(0).unwrap(), 1);
+
+        // A database from a newer build is refused.
+        assert!(migrate(&conn, &MIGRATIONS[..1]).await.is_err());
+    }
+}
@@ -0,0 +1,375 @@
+//! `RepoPort` decorator that mirrors saved messages into per-chat JSONL files.
+//!
+//! Every `save_messages` goes to the primary repository (SQLite) first and is then appended
+//! to `data/mirror/<chat_id>.jsonl` through `FsRepo`. Reads always come from the primary.
+//! Mirror write failures are logged and counted but never fail the save; a missing or stale
+//! mirror can be rebuilt from the primary (`tg-sync mirror-rebuild`).
+
+use super::fs_repo::{FsRepo, append_jsonl};
+use crate::domain::{
+    ActivityBin, ActivityBucket, ArchiveStats, Chat, ChatEvent, ChatPurge, ChatSettings, ChatStats,
+    DomainError, ForumTopic, Message, SearchHit, TimeRange, User,
+};
+use crate::ports::RepoPort;
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::fs;
+use tracing::{info, warn};
+
+/// Directory under the data dir holding the mirror files.
+pub const MIRROR_DIR: &str = "mirror";
+
+/// Messages per page when rebuilding a mirror from the primary.
+const REBUILD_BATCH: u32 = 1000;
+
+/// Primary repository plus append-only JSONL mirror.
+pub struct MirrorRepo {
+    primary: Arc<dyn RepoPort>,
+    mirror: FsRepo,
+    /// Mirror writes that failed since startup.
+    failures: AtomicU64,
+}
+
+impl MirrorRepo {
+    /// Wrap `primary`; mirror files go to `mirror_dir`.
+    pub fn new(primary: Arc<dyn RepoPort>, mirror_dir: impl AsRef<Path>) -> Self {
+        Self {
+            primary,
+            mirror: FsRepo::new(mirror_dir),
+            failures: AtomicU64::new(0),
+        }
+    }
+
+    /// Number of mirror writes that failed since startup.
+    pub fn failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    /// Rewrite a chat's mirror from the primary (oldest first). Returns messages written.
+    /// The file is replaced atomically, so a failed rebuild leaves the old mirror in place.
+    pub async fn rebuild_chat(&self, chat_id: i64) -> Result<u64, DomainError> {
+        let path = self.mirror.chat_path(chat_id);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .await
+                .map_err(|e| DomainError::Repo(format!("create {}: {}", dir.display(), e)))?;
+        }
+        let tmp = path.with_extension("jsonl.tmp");
+        let _ = fs::remove_file(&tmp).await;
+
+        let mut written = 0u64;
+        let mut after_id = i32::MIN;
+        loop {
+            let page = self
+                .primary
+                .get_messages_after(chat_id, after_id, REBUILD_BATCH)
+                .await?;
+            let Some(last) = page.last() else {
+                break;
+            };
+            after_id = last.id;
+            append_jsonl(&tmp, &page).await?;
+            written += page.len() as u64;
+        }
+        if written == 0 {
+            return Ok(0);
+        }
+        fs::rename(&tmp, &path)
+            .await
+            .map_err(|e| DomainError::Repo(format!("rename {}: {}", tmp.display(), e)))?;
+        info!(chat_id, messages = written, path = %path.display(), "mirror rebuilt");
+        Ok(written)
+    }
+
+    /// Rebuild the mirrors of `chat_ids` whose file is missing. Returns (chat_id, messages)
+    /// for each rebuilt chat.
+    pub async fn rebuild_missing(&self, chat_ids: &[i64]) -> Result<Vec<(i64, u64)>, DomainError> {
+        let mut rebuilt = Vec::new();
+        for &chat_id in chat_ids {
+            if fs::try_exists(self.mirror.chat_path(chat_id))
+                .await
+                .unwrap_or(false)
+            {
+                continue;
+            }
+            let written = self.rebuild_chat(chat_id).await?;
+            if written > 0 {
+                rebuilt.push((chat_id, written));
+            }
+        }
+        Ok(rebuilt)
+    }
+}
+
+#[async_trait::async_trait]
+impl RepoPort for MirrorRepo {
+    async fn save_messages(&self, chat_id: i64, messages: &[Message]) -> Result<(), DomainError> {
+        self.primary.save_messages(chat_id, messages).await?;
+        if let Err(e) = self.mirror.append_messages(chat_id, messages).await {
+            let failures = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+            warn!(chat_id, failures, error = %e, "JSONL mirror write failed; archive unaffected");
+        }
+        Ok(())
+    }
+
+    async fn save_users(&self, users: &[User]) -> Result<(), DomainError> {
+        self.primary.save_users(users).await
+    }
+
+    async fn get_messages(
+        &self,
+        chat_id: i64,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<Message>, DomainError> {
+        self.primary.get_messages(chat_id, limit, offset).await
+    }
+
+    async fn get_messages_after(
+        &self,
+        chat_id: i64,
+        after_id: i32,
+        limit: u32,
+    ) -> Result<Vec<Message>, DomainError> {
+        self.primary
+            .get_messages_after(chat_id, after_id, limit)
+            .await
+    }
+
+    async fn get_messages_in_range(
+        &self,
+        chat_id: i64,
+        range: TimeRange,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<Message>, DomainError> {
+        self.primary
+            .get_messages_in_range(chat_id, range, limit, offset)
+            .await
+    }
+
+    async fn get_messages_by_ids(
+        &self,
+        chat_id: i64,
+        ids: &[i32],
+    ) -> Result<Vec<Message>, DomainError> {
+        self.primary.get_messages_by_ids(chat_id, ids).await
+    }
+
+    async fn search_messages(
+        &self,
+        query: &str,
+        chat_id: Option<i64>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<SearchHit>, DomainError> {
+        self.primary
+            .search_messages(query, chat_id, limit, offset)
+            .await
+    }
+
+    async fn get_blacklisted_ids(&self) -> Result<HashSet<i64>, DomainError> {
+        self.primary.get_blacklisted_ids().await
+    }
+
+    async fn update_blacklist(&self, ids: HashSet<i64>) -> Result<(), DomainError> {
+        self.primary.update_blacklist(ids).await
+    }
+
+    async fn get_target_ids(&self) -> Result<HashSet<i64>, DomainError> {
+        self.primary.get_target_ids().await
+    }
+
+    async fn update_targets(&self, ids: HashSet<i64>) -> Result<(), DomainError> {
+        self.primary.update_targets(ids).await
+    }
+
+    async fn get_chat_settings(&self, chat_id: i64) -> Result<ChatSettings, DomainError> {
+        self.primary.get_chat_settings(chat_id).await
+    }
+
+    async fn list_chat_settings(&self) -> Result<Vec<ChatSettings>, DomainError> {
+        self.primary.list_chat_settings().await
+    }
+
+    async fn set_chat_settings(&self, settings: &ChatSettings) -> Result<(), DomainError> {
+        self.primary.set_chat_settings(settings).await
+    }
+
+    async fn get_activity_histogram(
+        &self,
+        chat_id: i64,
+        bucket: ActivityBucket,
+        range: TimeRange,
+        utc_offset_secs: i32,
+    ) -> Result<Vec<ActivityBin>, DomainError> {
+        self.primary
+            .get_activity_histogram(chat_id, bucket, range, utc_offset_secs)
+            .await
+    }
+
+    async fn register_synthetic_chat(&self, chat: &Chat) -> Result<(), DomainError> {
+        self.primary.register_synthetic_chat(chat).await
+    }
+
+    async fn upsert_chats(&self, chats: &[Chat]) -> Result<(), DomainError> {
+        self.primary.upsert_chats(chats).await
+    }
+
+    async fn save_forum_topics(
+        &self,
+        chat_id: i64,
+        topics: &[ForumTopic],
+    ) -> Result<(), DomainError> {
+        self.primary.save_forum_topics(chat_id, topics).await
+    }
+
+    async fn save_events(&self, events: &[ChatEvent]) -> Result<(), DomainError> {
+        self.primary.save_events(events).await
+    }
+
+    async fn get_events(&self, chat_id: i64) -> Result<Vec<ChatEvent>, DomainError> {
+        self.primary.get_events(chat_id).await
+    }
+
+    async fn link_channel_comments(&self, chat_id: i64) -> Result<u64, DomainError> {
+        self.primary.link_channel_comments(chat_id).await
+    }
+
+    async fn get_channel_comments(
+        &self,
+        channel_id: i64,
+        post_ids: &[i32],
+    ) -> Result<Vec<Message>, DomainError> {
+        self.primary
+            .get_channel_comments(channel_id, post_ids)
+            .await
+    }
+
+    async fn get_known_chats(&self) -> Result<Vec<Chat>, DomainError> {
+        self.primary.get_known_chats().await
+    }
+
+    async fn get_chat_stats(&self, chat_id: i64) -> Result<ChatStats, DomainError> {
+        self.primary.get_chat_stats(chat_id).await
+    }
+
+    async fn get_global_stats(&self) -> Result<ArchiveStats, DomainError> {
+        self.primary.get_global_stats().await
+    }
+
+    async fn delete_chat_data(&self, chat_id: i64) -> Result<ChatPurge, DomainError> {
+        let mut purge = self.primary.delete_chat_data(chat_id).await?;
+        let path = self.mirror.chat_path(chat_id);
+        if let Ok(meta) = fs::metadata(&path).await {
+            match fs::remove_file(&path).await {
+                Ok(()) => {
+                    purge.files += 1;
+                    purge.bytes += meta.len();
+                }
+                Err(e) => warn!(chat_id, error = %e, "failed to delete mirror file"),
+            }
+        }
+        Ok(purge)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::persistence::sqlite_repo::SqliteRepo;
+    use crate::testing::fake_tg::text_message;
+    use std::path::PathBuf;
+
+    async fn setup(name: &str) -> (PathBuf, Arc<SqliteRepo>) {
+        let base = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join(name);
+        let _ = std::fs::remove_dir_all(&base);
+        let sqlite = Arc::new(SqliteRepo::connect(&base).await.unwrap());
+        (base, sqlite)
+    }
+
+    fn msg(id: i32, text: &str) -> Message {
+        Message {
+            from_user_id: Some(1),
+            ..text_message(7, id, 1704067200 + id as i64, text)
+        }
+    }
+
+    fn mirror_ids(path: &Path) -> Vec<i32> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str::<Message>(l).unwrap().id)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_saves_are_mirrored_and_reads_use_primary() {
+        let (base, sqlite) = setup("test_mirror_repo").await;
+        let repo = MirrorRepo::new(sqlite.clone(), base.join(MIRROR_DIR));
+
+        repo.save_messages(7, &[msg(1, "a"), msg(2, "b")])
+            .await
+            .unwrap();
+        repo.save_messages(7, &[msg(3, "c")]).await.unwrap();
+
+        let path = base.join(MIRROR_DIR).join("7.jsonl");
+        assert_eq!(mirror_ids(&path), vec![1, 2, 3]);
+        let fs_view = FsRepo::new(base.join(MIRROR_DIR))
+            .get_messages(7, 10, 0)
+            .await
+            .unwrap();
+        assert_eq!(
+            fs_view.iter().map(|m| m.id).collect::<Vec<_>>(),
+            vec![3, 2, 1]
+        );
+
+        // Reads ignore the mirror: deleting it changes nothing.
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(repo.get_messages(7, 10, 0).await.unwrap().len(), 3);
+        assert_eq!(repo.failures(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_mirror_failure_does_not_fail_save() {
+        let (base, sqlite) = setup("test_mirror_repo_failure").await;
+        // A file where the mirror directory should be: every mirror write fails.
+        let blocked = base.join("blocked");
+        std::fs::write(&blocked, "").unwrap();
+        let repo = MirrorRepo::new(sqlite.clone(), &blocked);
+
+        repo.save_messages(7, &[msg(1, "a")]).await.unwrap();
+        repo.save_messages(7, &[msg(2, "b")]).await.unwrap();
+        assert_eq!(repo.failures(), 2);
+        assert_eq!(sqlite.get_messages(7, 10, 0).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_rebuild_missing_mirror_from_primary() {
+        let (base, sqlite) = setup("test_mirror_repo_rebuild").await;
+        // Saved before the mirror was enabled.
+        sqlite
+            .save_messages(7, &[msg(2, "b"), msg(1, "a"), msg(3, "c")])
+            .await
+            .unwrap();
+        let repo = MirrorRepo::new(sqlite.clone(), base.join(MIRROR_DIR));
+        repo.save_messages(8, &[msg(1, "x")]).await.unwrap();
+
+        let rebuilt = repo.rebuild_missing(&[7, 8, 9]).await.unwrap();
+        assert_eq!(rebuilt, vec![(7, 3)]);
+        let path = base.join(MIRROR_DIR).join("7.jsonl");
+        assert_eq!(mirror_ids(&path), vec![1, 2, 3]);
+        assert!(!base.join(MIRROR_DIR).join("9.jsonl").exists());
+
+        // Existing mirrors are left alone; an explicit rebuild rewrites (and deduplicates) one.
+        repo.save_messages(7, &[msg(3, "c edited")]).await.unwrap();
+        assert!(repo.rebuild_missing(&[7]).await.unwrap().is_empty());
+        assert_eq!(mirror_ids(&path), vec![1, 2, 3, 3]);
+        assert_eq!(repo.rebuild_chat(7).await.unwrap(), 3);
+        assert_eq!(mirror_ids(&path), vec![1, 2, 3]);
+    }
+}
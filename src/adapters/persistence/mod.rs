@@ -1,2 +1,93 @@
+pub mod fs_repo;
+mod migrations;
+pub mod mirror_repo;
+#[cfg(test)]
+mod repo_contract;
+mod scan_search;
 pub mod sqlite_repo;
 pub mod state_json;
+pub mod state_sqlite;
+pub mod tee_repo;
+
+use crate::domain::DomainError;
+use crate::ports::{RepoPort, StatePort};
+use crate::shared::config::{AppConfig, RepoBackend, StateBackend};
+use fs_repo::FsRepo;
+use mirror_repo::{MIRROR_DIR, MirrorRepo};
+use sqlite_repo::SqliteRepo;
+use state_json::StateJson;
+use state_sqlite::StateSqlite;
+use std::path::Path;
+use std::sync::Arc;
+use tee_repo::TeeRepo;
+use tracing::{info, warn};
+
+/// Directory under the data dir holding the JSONL repository.
+pub const JSONL_DIR: &str = "jsonl";
+
+/// The JSONL repository (`<data_dir>/jsonl/`) when TG_SYNC_REPO_BACKEND=jsonl. It then
+/// also serves as the entity registry.
+pub fn jsonl_repo(data_dir: &Path, cfg: &AppConfig) -> Option<Arc<FsRepo>> {
+    match cfg.repo_backend() {
+        RepoBackend::Sqlite | RepoBackend::Both => None,
+        RepoBackend::Jsonl => Some(Arc::new(FsRepo::new(data_dir.join(JSONL_DIR)))),
+    }
+}
+
+/// Message repository used by the app: the JSONL repository when one is given (see
+/// [`jsonl_repo`]), else the SQLite archive. With TG_SYNC_REPO_BACKEND=both every write is
+/// repeated into `<data_dir>/jsonl/`; otherwise SQLite is wrapped in a JSONL mirror
+/// (`<data_dir>/mirror/<chat_id>.jsonl`) when TG_SYNC_JSONL_MIRROR is enabled.
+pub fn message_repo(
+    sqlite: Arc<SqliteRepo>,
+    jsonl: Option<Arc<FsRepo>>,
+    data_dir: &Path,
+    cfg: &AppConfig,
+) -> Arc<dyn RepoPort> {
+    if let Some(jsonl) = jsonl {
+        if cfg.jsonl_mirror_enabled() {
+            warn!("JSONL mirror disabled: the repository already is JSONL");
+        }
+        info!(dir = %data_dir.join(JSONL_DIR).display(), "JSONL repository backend");
+        jsonl
+    } else if cfg.repo_backend() == RepoBackend::Both {
+        if cfg.jsonl_mirror_enabled() {
+            warn!("JSONL mirror disabled: every write already goes to the JSONL repository");
+        }
+        let dir = data_dir.join(JSONL_DIR);
+        info!(dir = %dir.display(), "dual-write: SQLite plus JSONL repository");
+        Arc::new(TeeRepo::new(sqlite, Arc::new(FsRepo::new(dir))))
+    } else if cfg.jsonl_mirror_enabled() && sqlite.cipher().is_some() {
+        warn!("JSONL mirror disabled: it would store the encrypted archive in plaintext");
+        sqlite
+    } else if cfg.jsonl_mirror_enabled() {
+        let dir = data_dir.join(MIRROR_DIR);
+        info!(dir = %dir.display(), "JSONL mirror enabled");
+        Arc::new(MirrorRepo::new(sqlite, dir))
+    } else {
+        sqlite
+    }
+}
+
+/// Sync state store selected by TG_SYNC_STATE_BACKEND, loaded and ready. The SQLite backend
+/// imports `<data_dir>/state.json` on first use so existing checkpoints carry over.
+pub async fn state_store(
+    sqlite: Arc<SqliteRepo>,
+    data_dir: &Path,
+    cfg: &AppConfig,
+) -> Result<Arc<dyn StatePort>, DomainError> {
+    let json_path = data_dir.join("state.json");
+    match cfg.state_backend() {
+        StateBackend::Json => {
+            let state = StateJson::new(&json_path);
+            state.load().await?;
+            Ok(Arc::new(state))
+        }
+        StateBackend::Sqlite => {
+            let state = StateSqlite::new(sqlite);
+            state.import_json(&json_path).await?;
+            info!("sync state stored in SQLite (sync_state table)");
+            Ok(Arc::new(state))
+        }
+    }
+}
@@ -0,0 +1,433 @@
+//! Contract tests shared by the `RepoPort` backends: the same checks run against
+//! `SqliteRepo` and `FsRepo`, so the two keep answering alike.
+
+use super::fs_repo::FsRepo;
+use super::sqlite_repo::SqliteRepo;
+use crate::domain::{
+    ActivityBin, ActivityBucket, Chat, ChatEvent, ChatEventKind, ChatSettings, ChatType,
+    ForumTopic, MediaReference, MediaType, Message, TimeRange, User,
+};
+use crate::ports::{EntityRegistry, RepoPort};
+use crate::testing::fake_tg::text_message;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// 2024-01-01 00:00:00 UTC.
+const DAY: i64 = 1704067200;
+
+fn base_dir(name: &str) -> PathBuf {
+    let base = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("target")
+        .join(name);
+    let _ = std::fs::remove_dir_all(&base);
+    base
+}
+
+fn chat(id: i64, title: &str) -> Chat {
+    Chat {
+        id,
+        title: title.to_string(),
+        username: None,
+        kind: ChatType::Group,
+        approx_message_count: None,
+        is_forum: false,
+        last_message_date: None,
+    }
+}
+
+fn ids(messages: &[Message]) -> Vec<i32> {
+    messages.iter().map(|m| m.id).collect()
+}
+
+/// Messages, edits, senders, topics, paging and search.
+async fn check_messages(repo: &dyn RepoPort) {
+    let mut photo = text_message(1, 2, DAY + 3600, "second with photo");
+    photo.media = Some(MediaReference {
+        message_id: 2,
+        chat_id: 1,
+        media_type: MediaType::Photo,
+        opaque_ref: "ref".to_string(),
+        size_bytes: None,
+        filename: None,
+        mime_type: None,
+    });
+    photo.grouped_id = Some(77);
+    photo.mentioned = true;
+    photo.mentioned_user_ids = vec![10, 12];
+    let mut first = text_message(1, 1, DAY, "Hello world");
+    first.from_user_id = Some(10);
+    first.topic_id = Some(5);
+    repo.save_messages(
+        1,
+        &[
+            first.clone(),
+            photo,
+            text_message(1, 3, DAY + 86400, "third"),
+        ],
+    )
+    .await
+    .unwrap();
+    repo.save_users(&[User {
+        id: 10,
+        first_name: Some("Ann".to_string()),
+        last_name: None,
+        username: Some("ann".to_string()),
+    }])
+    .await
+    .unwrap();
+    repo.save_forum_topics(
+        1,
+        &[ForumTopic {
+            id: 5,
+            title: "Releases".to_string(),
+        }],
+    )
+    .await
+    .unwrap();
+
+    // Saving again with new text records the old version.
+    first.text = "Hello there world".to_string();
+    repo.save_messages(1, &[first]).await.unwrap();
+
+    let newest = repo.get_messages(1, 2, 0).await.unwrap();
+    assert_eq!(ids(&newest), vec![3, 2]);
+    assert_eq!(newest[1].grouped_id, Some(77));
+    assert!(newest[1].mentioned && newest[1].mentions(12));
+    assert!(!newest[0].mentioned && newest[0].mentioned_user_ids.is_empty());
+    assert_eq!(ids(&repo.get_messages(1, 10, 2).await.unwrap()), vec![1]);
+    assert_eq!(
+        ids(&repo.get_messages_after(1, 1, 10).await.unwrap()),
+        vec![2, 3]
+    );
+    let edited = repo.get_messages_by_ids(1, &[1, 99]).await.unwrap();
+    assert_eq!(ids(&edited), vec![1]);
+    assert_eq!(edited[0].text, "Hello there world");
+    let history = edited[0].edit_history.as_ref().unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].text, "Hello world");
+    assert_eq!(edited[0].sender_name.as_deref(), Some("Ann"));
+    assert_eq!(edited[0].topic_title.as_deref(), Some("Releases"));
+    assert!(repo.get_messages(404, 10, 0).await.unwrap().is_empty());
+
+    repo.upsert_chats(&[chat(1, "Team")]).await.unwrap();
+    let hits = repo
+        .search_messages("hello WORLD", None, 10, 0)
+        .await
+        .unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].message.id, 1);
+    assert_eq!(hits[0].chat_title.as_deref(), Some("Team"));
+    assert!(hits[0].snippet.contains("[Hello]"));
+    assert!(
+        repo.search_messages("hello", Some(2), 10, 0)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert!(
+        repo.search_messages("", None, 10, 0)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    let bins = repo
+        .get_activity_histogram(
+            1,
+            ActivityBucket::Day,
+            TimeRange {
+                from: DAY,
+                to: DAY + 2 * 86400,
+            },
+            0,
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        bins,
+        vec![
+            ActivityBin {
+                bucket_start: DAY,
+                count: 2
+            },
+            ActivityBin {
+                bucket_start: DAY + 86400,
+                count: 1
+            }
+        ]
+    );
+
+    let stats = repo.get_chat_stats(1).await.unwrap();
+    assert_eq!(stats.messages, 3);
+    assert_eq!((stats.oldest, stats.newest), (Some(DAY), Some(DAY + 86400)));
+    assert_eq!(stats.media_by_type, vec![(MediaType::Photo, 1)]);
+    assert_eq!(repo.get_chat_stats(2).await.unwrap().messages, 0);
+    let global = repo.get_global_stats().await.unwrap();
+    assert_eq!(
+        global.chats.iter().map(|c| c.chat_id).collect::<Vec<_>>(),
+        vec![1]
+    );
+}
+
+/// Oldest-first reads: keyset pages by id and date ranges, across inserts between pages.
+async fn check_ordered_reads(repo: &dyn RepoPort) {
+    let chat = 50;
+    let minute = |id: i32| text_message(chat, id, DAY + i64::from(id) * 60, "m");
+    repo.save_messages(chat, &(1..=5).map(minute).collect::<Vec<_>>())
+        .await
+        .unwrap();
+    let range = |from: i64, to: i64| TimeRange {
+        from: DAY + from * 60,
+        to: DAY + to * 60,
+    };
+
+    // `from` is included, `to` is not.
+    let in_range = repo
+        .get_messages_in_range(chat, range(1, 3), 10, 0)
+        .await
+        .unwrap();
+    assert_eq!(ids(&in_range), vec![1, 2]);
+    assert_eq!(
+        ids(&repo
+            .get_messages_in_range(chat, range(2, 5), 10, 0)
+            .await
+            .unwrap()),
+        vec![2, 3, 4]
+    );
+    assert!(
+        repo.get_messages_in_range(chat, range(3, 3), 10, 0)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert!(
+        repo.get_messages_in_range(404, range(0, 10), 10, 0)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    // Paging a range while messages outside it arrive.
+    let page = |offset: u32| repo.get_messages_in_range(chat, range(1, 6), 2, offset);
+    assert_eq!(ids(&page(0).await.unwrap()), vec![1, 2]);
+    repo.save_messages(chat, &[minute(6), minute(7)])
+        .await
+        .unwrap();
+    assert_eq!(ids(&page(2).await.unwrap()), vec![3, 4]);
+    assert_eq!(ids(&page(4).await.unwrap()), vec![5]);
+    assert!(page(6).await.unwrap().is_empty());
+
+    // Equal dates are ordered by id; a message dated before its id's neighbours sorts by date.
+    repo.save_messages(
+        chat,
+        &[text_message(chat, 8, DAY + 60, "late id, early date")],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        ids(&repo
+            .get_messages_in_range(chat, range(1, 3), 10, 0)
+            .await
+            .unwrap()),
+        vec![1, 8, 2]
+    );
+
+    // Keyset pages by id neither repeat nor skip messages saved between them.
+    let first = repo.get_messages_after(chat, 0, 3).await.unwrap();
+    assert_eq!(ids(&first), vec![1, 2, 3]);
+    repo.save_messages(chat, &[minute(-1), minute(9)])
+        .await
+        .unwrap();
+    let mut after = 3;
+    let mut rest = Vec::new();
+    loop {
+        let page = repo.get_messages_after(chat, after, 3).await.unwrap();
+        let Some(last) = page.last() else {
+            break;
+        };
+        after = last.id;
+        rest.extend(ids(&page));
+    }
+    assert_eq!(rest, vec![4, 5, 6, 7, 8, 9]);
+}
+
+/// Blacklist, targets, chat titles and service events.
+async fn check_lists_and_chats(repo: &dyn RepoPort) {
+    assert!(repo.get_blacklisted_ids().await.unwrap().is_empty());
+    repo.update_blacklist(HashSet::from([3, 4])).await.unwrap();
+    repo.update_blacklist(HashSet::from([4, 5])).await.unwrap();
+    assert_eq!(
+        repo.get_blacklisted_ids().await.unwrap(),
+        HashSet::from([4, 5])
+    );
+    repo.update_targets(HashSet::from([7])).await.unwrap();
+    assert_eq!(repo.get_target_ids().await.unwrap(), HashSet::from([7]));
+
+    repo.upsert_chats(&[chat(7, "beta"), chat(8, "Alpha")])
+        .await
+        .unwrap();
+    repo.upsert_chats(&[chat(7, "Gamma")]).await.unwrap();
+    repo.register_synthetic_chat(&chat(-9, "Imported"))
+        .await
+        .unwrap();
+    let titles: Vec<String> = repo
+        .get_known_chats()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|c| c.title)
+        .collect();
+    assert_eq!(titles, vec!["Alpha", "Gamma", "Imported", "Team"]);
+
+    let event = |id, payload: &str| ChatEvent {
+        id,
+        chat_id: 7,
+        date: DAY + id as i64,
+        kind: ChatEventKind::TitleChanged,
+        actor_id: Some(10),
+        payload: Some(payload.to_string()),
+    };
+    repo.save_events(&[event(2, "b"), event(1, "a")])
+        .await
+        .unwrap();
+    repo.save_events(&[event(2, "c")]).await.unwrap();
+    assert_eq!(
+        repo.get_events(7).await.unwrap(),
+        vec![event(1, "a"), event(2, "c")]
+    );
+}
+
+/// Per-chat sync overrides: stored, replaced, and dropped once back to the defaults.
+async fn check_chat_settings(repo: &dyn RepoPort) {
+    assert_eq!(
+        repo.get_chat_settings(40).await.unwrap(),
+        ChatSettings::new(40)
+    );
+    let mut photos = ChatSettings::new(40);
+    photos.media_types = Some(vec![MediaType::Photo, MediaType::Voice]);
+    photos.batch_limit = Some(50);
+    let mut muted = ChatSettings::new(41);
+    muted.include_media = Some(false);
+    muted.sync_enabled = false;
+    repo.set_chat_settings(&muted).await.unwrap();
+    repo.set_chat_settings(&photos).await.unwrap();
+    assert_eq!(repo.get_chat_settings(40).await.unwrap(), photos);
+    assert_eq!(
+        repo.list_chat_settings().await.unwrap(),
+        vec![photos.clone(), muted]
+    );
+
+    repo.set_chat_settings(&ChatSettings::new(41))
+        .await
+        .unwrap();
+    assert_eq!(repo.list_chat_settings().await.unwrap(), vec![photos]);
+}
+
+/// Discussion group replies linked to channel posts.
+async fn check_channel_comments(repo: &dyn RepoPort) {
+    let channel = 100;
+    let mut copy = text_message(20, 1, DAY, "post copy");
+    copy.forwarded_from_id = Some(channel);
+    copy.linked_channel_post = Some(50);
+    let mut reply = text_message(20, 2, DAY + 1, "comment");
+    reply.reply_to_msg_id = Some(1);
+    let mut nested = text_message(20, 3, DAY + 2, "reply to comment");
+    nested.reply_to_msg_id = Some(2);
+    repo.save_messages(
+        20,
+        &[
+            copy,
+            reply,
+            nested,
+            text_message(20, 4, DAY + 3, "unrelated"),
+        ],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(repo.link_channel_comments(20).await.unwrap(), 2);
+    assert_eq!(repo.link_channel_comments(20).await.unwrap(), 0);
+    let comments = repo.get_channel_comments(channel, &[50]).await.unwrap();
+    assert_eq!(ids(&comments), vec![2, 3]);
+    assert!(
+        repo.get_channel_comments(channel, &[51])
+            .await
+            .unwrap()
+            .is_empty()
+    );
+}
+
+/// Purging one chat leaves the others alone.
+async fn check_delete_chat(repo: &dyn RepoPort) {
+    repo.save_messages(
+        30,
+        &[text_message(30, 1, DAY, "a"), text_message(30, 2, DAY, "b")],
+    )
+    .await
+    .unwrap();
+    repo.save_messages(31, &[text_message(31, 1, DAY, "c")])
+        .await
+        .unwrap();
+    repo.upsert_chats(&[chat(30, "Gone")]).await.unwrap();
+    repo.update_targets(HashSet::from([30, 31])).await.unwrap();
+    let mut settings = ChatSettings::new(30);
+    settings.batch_limit = Some(10);
+    repo.set_chat_settings(&settings).await.unwrap();
+
+    let purge = repo.delete_chat_data(30).await.unwrap();
+    assert_eq!(
+        purge.rows,
+        vec![
+            ("messages", 2),
+            ("targets", 1),
+            ("chat_settings", 1),
+            ("chats", 1)
+        ]
+    );
+    assert_eq!(
+        repo.get_chat_settings(30).await.unwrap(),
+        ChatSettings::new(30)
+    );
+    assert!(repo.get_messages(30, 10, 0).await.unwrap().is_empty());
+    assert_eq!(repo.get_messages(31, 10, 0).await.unwrap().len(), 1);
+    assert_eq!(repo.get_target_ids().await.unwrap(), HashSet::from([31]));
+    assert!(repo.delete_chat_data(30).await.unwrap().rows.is_empty());
+}
+
+async fn check_entity_registry(registry: &dyn EntityRegistry) {
+    assert_eq!(registry.get_access_hash(1).await.unwrap(), None);
+    registry
+        .save_entity(1, 111, "channel", Some("news"))
+        .await
+        .unwrap();
+    registry.save_entity(1, 222, "channel", None).await.unwrap();
+    assert_eq!(registry.get_access_hash(1).await.unwrap(), Some(222));
+    assert_eq!(
+        registry.get_entity(1).await.unwrap(),
+        Some((222, "channel".to_string()))
+    );
+    assert_eq!(registry.get_username(1).await.unwrap(), None);
+}
+
+async fn check_all<R: RepoPort + EntityRegistry>(repo: &R) {
+    check_messages(repo).await;
+    check_ordered_reads(repo).await;
+    check_lists_and_chats(repo).await;
+    check_channel_comments(repo).await;
+    check_chat_settings(repo).await;
+    check_delete_chat(repo).await;
+    check_entity_registry(repo).await;
+}
+
+#[tokio::test]
+async fn test_sqlite_repo_contract() {
+    let repo = SqliteRepo::connect(base_dir("test_contract_sqlite"))
+        .await
+        .unwrap();
+    check_all(&repo).await;
+}
+
+#[tokio::test]
+async fn test_jsonl_repo_contract() {
+    check_all(&FsRepo::new(base_dir("test_contract_jsonl"))).await;
+}
@@ -0,0 +1,55 @@
+//! Search by scanning message text, for stores without a full-text index (encrypted SQLite
+//! archives, the JSONL repository): every query word must occur in the text,
+//! case-insensitively, and snippets mimic the FTS snippet format.
+
+/// Words of a snippet built for scanned search hits.
+const SNIPPET_WORDS: usize = 12;
+
+/// Lowercase query words, FTS operators (`*`, quotes) stripped. Empty when nothing
+/// searchable is left.
+pub(super) fn search_terms(query: &str) -> Vec<String> {
+    query
+        .split_whitespace()
+        .map(|w| {
+            w.trim_matches(|c: char| c == '*' || c == '"')
+                .to_lowercase()
+        })
+        .filter(|w| w.chars().any(char::is_alphanumeric))
+        .collect()
+}
+
+/// True when `text` contains every term.
+pub(super) fn matches_all(text: &str, terms: &[String]) -> bool {
+    let lower = text.to_lowercase();
+    terms.iter().all(|t| lower.contains(t.as_str()))
+}
+
+/// Up to [`SNIPPET_WORDS`] words of `text` around the first match, matched words in
+/// brackets (the FTS snippet format).
+pub(super) fn snippet(text: &str, terms: &[String]) -> String {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let matches = |w: &str| {
+        let w = w.to_lowercase();
+        terms.iter().any(|t| w.contains(t.as_str()))
+    };
+    let first = words.iter().position(|w| matches(w)).unwrap_or(0);
+    let start = first.saturating_sub(SNIPPET_WORDS / 3);
+    let end = (start + SNIPPET_WORDS).min(words.len());
+    let mut out: Vec<String> = words[start..end]
+        .iter()
+        .map(|w| {
+            if matches(w) {
+                format!("[{}]", w)
+            } else {
+                w.to_string()
+            }
+        })
+        .collect();
+    if start > 0 {
+        out.insert(0, "…".to_string());
+    }
+    if end < words.len() {
+        out.push("…".to_string());
+    }
+    out.join(" ")
+}
@@ -3,12 +3,34 @@
 //! Uses the same libsql backend as grammers-session to avoid duplicate SQLite symbol link errors.
 //! Single `messages` table with (chat_id, id) as primary key; batch saves use INSERT OR IGNORE.
 //! All chats share one database file: data/messages.db
+//!
+//! With an encryption key ([`SqliteRepo::connect_encrypted`]) message text, edit history, media
+//! references and polls are sealed before insert and opened on read (see
+//! [`crate::shared::crypto`]); everything above the repository sees plaintext. The full-text
+//! index would hold the plaintext, so it is dropped and search scans the decrypted messages
+//! instead. Media references keep their `media_type` readable for the statistics index.
 
-use crate::domain::{AnalysisResult, DomainError, MediaReference, Message, MessageEdit, WeekGroup};
-use crate::ports::{AnalysisLogPort, EntityRegistry, RepoPort};
+use super::migrations::{self, Migration, MigrationStep};
+use super::scan_search::{matches_all, search_terms, snippet};
+use crate::domain::{
+    ActivityBin, ActivityBucket, AiUsageRecord, AnalysisResult, AnalyzedPeriod, ArchiveStats,
+    COMBINED_CHAT_ID, Chat, ChatEvent, ChatEventKind, ChatPurge, ChatSettings, ChatStats, ChatType,
+    ChunkSummary, DEFAULT_WATCH_KEYWORDS, DatabaseMaintenance, DomainError, ForumTopic,
+    Granularity, MediaFile, MediaReference, MediaStatus, MediaType, Message, MessageEdit,
+    PeriodGroup, SearchHit, TimeRange, UsageTotals, User, WatchRule, WatchRuleKind,
+};
+use crate::ports::{
+    AnalysisLogPort, ArchiveAuditPort, EntityRegistry, MediaIndexPort, MediaQueuePort, RepoPort,
+    SettingsPort, WatchRulePort,
+};
+use crate::shared::crypto::ArchiveCipher;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use futures::future::BoxFuture;
 use libsql::{Database, params};
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tracing::info;
 
 const MESSAGES_TABLE: &str = r#"
@@ -21,14 +43,72 @@ CREATE TABLE IF NOT EXISTS messages (
     from_user_id INTEGER,
     reply_to_msg_id INTEGER,
     history_json TEXT NOT NULL DEFAULT '[]',
+    topic_id INTEGER,
+    forwarded_from_name TEXT,
+    forwarded_from_id INTEGER,
+    forwarded_date INTEGER,
+    poll_json TEXT,
+    is_service INTEGER NOT NULL DEFAULT 0,
+    linked_channel_post INTEGER,
     PRIMARY KEY (chat_id, id)
 )"#;
 
 /// Migration: add history_json to existing databases that were created before message versioning.
 const MIGRATION_ADD_HISTORY_JSON: &str =
     "ALTER TABLE messages ADD COLUMN history_json TEXT NOT NULL DEFAULT '[]'";
+/// Migration: forum topic of each message, for databases created before topic-aware sync.
+const MIGRATION_ADD_TOPIC_ID: &str = "ALTER TABLE messages ADD COLUMN topic_id INTEGER";
+/// Migration: forward provenance, for databases created before it was archived. Messages
+/// stored earlier keep NULLs.
+const MIGRATIONS_ADD_FORWARD: [&str; 3] = [
+    "ALTER TABLE messages ADD COLUMN forwarded_from_name TEXT",
+    "ALTER TABLE messages ADD COLUMN forwarded_from_id INTEGER",
+    "ALTER TABLE messages ADD COLUMN forwarded_date INTEGER",
+];
+/// Migration: structured polls, for databases created before polls were archived.
+const MIGRATION_ADD_POLL_JSON: &str = "ALTER TABLE messages ADD COLUMN poll_json TEXT";
+/// Migration: structural service flag, for databases created before it. Existing rows are
+/// regular messages (0) until a chat event with the same id marks them.
+const MIGRATION_ADD_IS_SERVICE: &str =
+    "ALTER TABLE messages ADD COLUMN is_service INTEGER NOT NULL DEFAULT 0";
+/// Migration: channel post discussed by a discussion group message, for databases created
+/// before comment threads were linked.
+const MIGRATION_ADD_LINKED_CHANNEL_POST: &str =
+    "ALTER TABLE messages ADD COLUMN linked_channel_post INTEGER";
+/// Discussion group messages by the channel post they belong to (comment lookups in exports).
+const MESSAGES_LINKED_POST_INDEX: &str = "CREATE INDEX IF NOT EXISTS idx_messages_linked_post ON messages (linked_channel_post) WHERE linked_channel_post IS NOT NULL";
+/// Rounds of [`RepoPort::link_channel_comments`]; each links one more level of replies.
+pub(super) const MAX_COMMENT_DEPTH: usize = 64;
+
+/// Topic titles of forum supergroups, refreshed whenever a forum is synced. Reads join it to
+/// fill `Message::topic_title`.
+const FORUM_TOPICS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS forum_topics (
+    chat_id INTEGER NOT NULL,
+    topic_id INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    PRIMARY KEY (chat_id, topic_id)
+)"#;
+/// Service messages (joins, leaves, title changes, pins, calls) as structured events. They
+/// share the chat's message id sequence but never enter `messages`, so text-based reads
+/// (AI analysis, search, statistics) don't see them. A `messages` row with an event's id (text
+/// of a service message stored by an older version or an import) is flagged `is_service`.
+const CHAT_EVENTS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS chat_events (
+    chat_id INTEGER NOT NULL,
+    id INTEGER NOT NULL,
+    date INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    actor_id INTEGER,
+    payload TEXT,
+    PRIMARY KEY (chat_id, id)
+)"#;
 const MESSAGES_INDEX: &str =
     "CREATE INDEX IF NOT EXISTS idx_messages_chat_date ON messages (chat_id, date DESC)";
+/// Media type of messages with media, so per-type counts (Statistics) read the index only.
+/// Queries must repeat [`MEDIA_TYPE_EXPR`] verbatim for SQLite to use it.
+const MESSAGES_MEDIA_TYPE_INDEX: &str = "CREATE INDEX IF NOT EXISTS idx_messages_media_type ON messages (chat_id, json_extract(media_json, '$.media_type')) WHERE media_json IS NOT NULL";
+const MEDIA_TYPE_EXPR: &str = "json_extract(media_json, '$.media_type')";
 
 /// Audit §6.2: Persistent entity registry for access_hash caching.
 /// Avoids re-iterating dialogs (getDialogs) which triggers FLOOD_WAIT.
@@ -53,33 +133,653 @@ CREATE TABLE IF NOT EXISTS targets (
     chat_id INTEGER PRIMARY KEY
 )"#;
 
-/// AI Analysis log: tracks which weeks have been analyzed per chat.
+/// AI Analysis log: tracks which periods have been analyzed per chat. `granularity` is
+/// day | week | month; `week_group` is the period key in that granularity's format.
 /// Stores full AnalysisResult as JSON for retrieval.
 const ANALYSIS_LOG_TABLE: &str = r#"
 CREATE TABLE IF NOT EXISTS analysis_log (
     chat_id INTEGER NOT NULL,
+    granularity TEXT NOT NULL DEFAULT 'week',
     week_group TEXT NOT NULL,
     analyzed_at INTEGER NOT NULL,
     summary TEXT NOT NULL,
     result_json TEXT NOT NULL,
-    PRIMARY KEY (chat_id, week_group)
+    PRIMARY KEY (chat_id, granularity, week_group)
+)"#;
+
+/// Migration: analysis_log tables from before granularities were weekly only. The primary
+/// key changes, so the table is rebuilt; existing rows become `week`.
+const MIGRATION_ANALYSIS_LOG_GRANULARITY: [&str; 4] = [
+    "ALTER TABLE analysis_log RENAME TO analysis_log_weekly",
+    ANALYSIS_LOG_TABLE,
+    r#"INSERT INTO analysis_log (chat_id, granularity, week_group, analyzed_at, summary, result_json)
+       SELECT chat_id, 'week', week_group, analyzed_at, summary, result_json FROM analysis_log_weekly"#,
+    "DROP TABLE analysis_log_weekly",
+];
+
+/// Period key of `m.date` for the granularity stored in `a.granularity` (SQL expression).
+const PERIOD_KEY_EXPR: &str = "strftime(CASE a.granularity WHEN 'day' THEN '%Y-%m-%d' WHEN 'month' THEN '%Y-%m' ELSE '%Y-%W' END, m.date, 'unixepoch')";
+
+/// Map-phase chunk summaries of weeks whose analysis hasn't been saved yet. Rows are deleted
+/// once the week lands in `analysis_log`; `chunk_hash` guards against reuse after the week's
+/// messages changed.
+const ANALYSIS_CHUNKS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS analysis_chunks (
+    chat_id INTEGER NOT NULL,
+    granularity TEXT NOT NULL,
+    week_group TEXT NOT NULL,
+    chunk_index INTEGER NOT NULL,
+    chunk_hash INTEGER NOT NULL,
+    summary TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (chat_id, granularity, week_group, chunk_index)
+)"#;
+
+/// One row per LLM call made by AI analysis, for cost tracking. `cost_estimate` (USD) is
+/// NULL when the model had no configured price.
+const AI_USAGE_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS ai_usage (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    chat_id INTEGER NOT NULL,
+    week_group TEXT NOT NULL,
+    model TEXT NOT NULL,
+    prompt_tokens INTEGER NOT NULL,
+    completion_tokens INTEGER NOT NULL,
+    cost_estimate REAL,
+    called_at INTEGER NOT NULL
+)"#;
+
+/// Task tracker cards created from action items, so re-analyzing a period doesn't create
+/// them again. `description_hash` is a fingerprint of the normalized item description.
+const TRACKER_TASKS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS tracker_tasks (
+    chat_id INTEGER NOT NULL,
+    granularity TEXT NOT NULL,
+    week_group TEXT NOT NULL,
+    description_hash INTEGER NOT NULL,
+    external_id TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (chat_id, granularity, week_group, description_hash)
+)"#;
+
+/// Media index: one row per media file. `rel_path` is relative to data/media.
+/// Message dates are not duplicated here; reads join `messages`.
+const MEDIA_FILES_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS media_files (
+    chat_id INTEGER NOT NULL,
+    message_id INTEGER NOT NULL,
+    media_type TEXT NOT NULL,
+    rel_path TEXT NOT NULL,
+    size_bytes INTEGER,
+    sha256 TEXT,
+    status TEXT NOT NULL DEFAULT 'pending',
+    updated_at INTEGER NOT NULL,
+    PRIMARY KEY (chat_id, message_id)
+)"#;
+
+/// Durable media download queue. The media reference itself is read from `messages.media_json`.
+/// status: queued | in_progress | done | failed.
+const MEDIA_QUEUE_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS media_queue (
+    chat_id INTEGER NOT NULL,
+    message_id INTEGER NOT NULL,
+    media_type TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'queued',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    updated_at INTEGER NOT NULL,
+    PRIMARY KEY (chat_id, message_id)
+)"#;
+
+const MEDIA_QUEUE_STATUS_INDEX: &str =
+    "CREATE INDEX IF NOT EXISTS idx_media_queue_status ON media_queue(status, updated_at)";
+
+/// Chats known to the archive. `synthetic` marks chats that exist only locally (ingested
+/// fragments) and have no Telegram dialog behind them. Refreshed on every dialog listing;
+/// `previous_titles` is a JSON array of titles replaced by renames, oldest first.
+const CHATS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS chats (
+    chat_id INTEGER PRIMARY KEY,
+    title TEXT NOT NULL,
+    username TEXT,
+    kind TEXT NOT NULL,
+    synthetic INTEGER NOT NULL DEFAULT 0,
+    first_synced_at INTEGER NOT NULL,
+    last_synced_at INTEGER NOT NULL,
+    approx_message_count INTEGER,
+    previous_titles TEXT NOT NULL DEFAULT '[]',
+    is_forum INTEGER NOT NULL DEFAULT 0
+)"#;
+
+/// Migration: columns added to chats tables created before dialog metadata was stored.
+const MIGRATIONS_CHATS_METADATA: [&str; 3] = [
+    "ALTER TABLE chats ADD COLUMN approx_message_count INTEGER",
+    "ALTER TABLE chats ADD COLUMN previous_titles TEXT NOT NULL DEFAULT '[]'",
+    "ALTER TABLE chats ADD COLUMN is_forum INTEGER NOT NULL DEFAULT 0",
+];
+
+/// Incremental sync checkpoints (see `StateSqlite`), used when TG_SYNC_STATE_BACKEND=sqlite.
+const SYNC_STATE_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS sync_state (
+    chat_id INTEGER PRIMARY KEY,
+    last_message_id INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    sync_gap INTEGER NOT NULL DEFAULT 0
+)"#;
+
+/// Migration: add sync_gap to sync_state tables created before date-range syncs (see
+/// `StatePort::has_sync_gap`).
+const MIGRATION_SYNC_STATE_GAP: &str =
+    "ALTER TABLE sync_state ADD COLUMN sync_gap INTEGER NOT NULL DEFAULT 0";
+
+/// Message senders, refreshed from the users list of every synced history batch. Reads
+/// join it to fill `Message::sender_name`.
+const USERS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS users (
+    user_id INTEGER PRIMARY KEY,
+    first_name TEXT,
+    last_name TEXT,
+    username TEXT,
+    updated_at INTEGER NOT NULL
+)"#;
+
+/// Watcher keyword rules; chat_id 0 = global. A new table is seeded with
+/// [`DEFAULT_WATCH_KEYWORDS`] so the watcher keeps its former behaviour.
+const WATCH_RULES_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS watch_rules (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    chat_id INTEGER NOT NULL DEFAULT 0,
+    pattern TEXT NOT NULL,
+    is_regex INTEGER NOT NULL DEFAULT 0,
+    enabled INTEGER NOT NULL DEFAULT 1,
+    UNIQUE (chat_id, pattern, is_regex)
+)"#;
+
+/// Small persistent key/value settings (e.g. the watcher alert destination).
+const SETTINGS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS settings (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+)"#;
+
+/// Message columns in `row_to_message` order, for `messages m LEFT JOIN users u`. The sender
+/// name is "First Last", else "@username", else NULL; the topic title comes from forum_topics.
+const MESSAGE_COLUMNS: &str = r#"m.chat_id, m.id, m.date, m.text, m.media_json, m.from_user_id,
+    m.reply_to_msg_id, m.history_json,
+    COALESCE(NULLIF(TRIM(COALESCE(u.first_name, '') || ' ' || COALESCE(u.last_name, '')), ''), '@' || u.username),
+    m.topic_id,
+    (SELECT t.title FROM forum_topics t WHERE t.chat_id = m.chat_id AND t.topic_id = m.topic_id),
+    m.forwarded_from_name, m.forwarded_from_id, m.forwarded_date, m.poll_json,
+    m.linked_channel_post, m.grouped_id, m.mentioned, m.mentioned_user_ids"#;
+/// Number of [`MESSAGE_COLUMNS`]; extra selected columns start at this index.
+const MESSAGE_COLUMN_COUNT: i32 = 19;
+
+/// Full-text index over `messages.text` (FTS5, external content: the text is not stored
+/// twice). Kept in sync by triggers, so every `save_messages` insert or edit updates it.
+/// Audits compare its row count with `messages` and can rebuild it.
+const FTS_TABLE: &str = "messages_fts";
+const FTS_SCHEMA: &[&str] = &[
+    r#"
+    CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5(
+        text, content='messages', tokenize='unicode61 remove_diacritics 2'
+    )"#,
+    r#"
+    CREATE TRIGGER IF NOT EXISTS messages_fts_ai AFTER INSERT ON messages BEGIN
+        INSERT INTO messages_fts (rowid, text) VALUES (new.rowid, new.text);
+    END"#,
+    r#"
+    CREATE TRIGGER IF NOT EXISTS messages_fts_ad AFTER DELETE ON messages BEGIN
+        INSERT INTO messages_fts (messages_fts, rowid, text) VALUES ('delete', old.rowid, old.text);
+    END"#,
+    r#"
+    CREATE TRIGGER IF NOT EXISTS messages_fts_au AFTER UPDATE OF text ON messages BEGIN
+        INSERT INTO messages_fts (messages_fts, rowid, text) VALUES ('delete', old.rowid, old.text);
+        INSERT INTO messages_fts (rowid, text) VALUES (new.rowid, new.text);
+    END"#,
+];
+
+/// Turn free text into an FTS5 query: every word quoted as a literal (so `-`, `:` or quotes
+/// typed by the user are not query syntax) and all words required. A trailing `*` keeps
+/// prefix matching. None when there is nothing to search for.
+fn fts_query(input: &str) -> Option<String> {
+    let terms: Vec<String> = input
+        .split_whitespace()
+        .filter_map(|word| {
+            let (word, prefix) = match word.strip_suffix('*') {
+                Some(stem) => (stem, true),
+                None => (word, false),
+            };
+            let word = word.trim_matches('*');
+            // Pure punctuation has no tokens and would make an empty phrase.
+            word.chars().any(char::is_alphanumeric).then(|| {
+                let quoted = format!("\"{}\"", word.replace('"', "\"\""));
+                if prefix { quoted + "*" } else { quoted }
+            })
+        })
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// Settings key of the archive's passphrase salt (base64).
+const ENCRYPTION_SALT_KEY: &str = "encryption_salt";
+/// Settings key of a sealed known value: marks the archive as encrypted and detects wrong keys.
+const ENCRYPTION_CHECK_KEY: &str = "encryption_check";
+const ENCRYPTION_CHECK_VALUE: &str = "tg-sync archive";
+
+/// FTS schema objects, dropped when the archive is encrypted.
+const FTS_DROP: &[&str] = &[
+    "DROP TRIGGER IF EXISTS messages_fts_ai",
+    "DROP TRIGGER IF EXISTS messages_fts_ad",
+    "DROP TRIGGER IF EXISTS messages_fts_au",
+    "DROP TABLE IF EXISTS messages_fts",
+];
+
+/// Tables cleared by [`RepoPort::delete_chat_data`], children before the chat row. The FTS
+/// index follows `messages` through its triggers.
+const PURGE_TABLES: [&str; 12] = [
+    "messages",
+    "chat_events",
+    "forum_topics",
+    "analysis_log",
+    "analysis_chunks",
+    "tracker_tasks",
+    "media_files",
+    "media_queue",
+    "watch_rules",
+    "targets",
+    "chat_settings",
+    "chats",
+];
+
+/// Media index rows by status, for the stale pending sweep and the per-chat file totals.
+const MEDIA_FILES_STATUS_INDEX: &str =
+    "CREATE INDEX IF NOT EXISTS idx_media_files_status ON media_files (status, updated_at)";
+
+/// Remote storage key of uploaded media (NULL until uploaded), and the downloaded files
+/// still waiting for an upload.
+const MIGRATIONS_MEDIA_REMOTE_KEY: [&str; 2] = [
+    "ALTER TABLE media_files ADD COLUMN remote_key TEXT",
+    "CREATE INDEX IF NOT EXISTS idx_media_files_unuploaded ON media_files (chat_id, message_id) WHERE status = 'done' AND remote_key IS NULL",
+];
+
+/// Migration: size Telegram reported for each media file, to catch truncated downloads.
+const MIGRATION_MEDIA_EXPECTED_SIZE: &str =
+    "ALTER TABLE media_files ADD COLUMN expected_size INTEGER";
+
+/// Migration: album of each message, and the parts of an album by chat (album-aware reads).
+const MIGRATIONS_MESSAGES_GROUPED_ID: [&str; 2] = [
+    "ALTER TABLE messages ADD COLUMN grouped_id INTEGER",
+    "CREATE INDEX IF NOT EXISTS idx_messages_grouped ON messages (chat_id, grouped_id) WHERE grouped_id IS NOT NULL",
+];
+
+/// Migration: Telegram's "mentions you" flag and the users mentioned by name link
+/// (comma-separated ids, NULL when none) of each message.
+const MIGRATIONS_MESSAGES_MENTIONS: [&str; 2] = [
+    "ALTER TABLE messages ADD COLUMN mentioned INTEGER NOT NULL DEFAULT 0",
+    "ALTER TABLE messages ADD COLUMN mentioned_user_ids TEXT",
+];
+
+/// Migration: watch rules get a kind (keyword, mention, reply). SQLite cannot change a
+/// UNIQUE constraint in place, so the table is rebuilt with the kind in it, keeping ids.
+const MIGRATIONS_WATCH_RULE_KIND: [&str; 4] = [
+    r#"
+CREATE TABLE watch_rules_v8 (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    chat_id INTEGER NOT NULL DEFAULT 0,
+    kind TEXT NOT NULL DEFAULT 'keyword',
+    pattern TEXT NOT NULL,
+    is_regex INTEGER NOT NULL DEFAULT 0,
+    enabled INTEGER NOT NULL DEFAULT 1,
+    UNIQUE (chat_id, kind, pattern, is_regex)
+)"#,
+    "INSERT INTO watch_rules_v8 (id, chat_id, pattern, is_regex, enabled) SELECT id, chat_id, pattern, is_regex, enabled FROM watch_rules",
+    "DROP TABLE watch_rules",
+    "ALTER TABLE watch_rules_v8 RENAME TO watch_rules",
+];
+
+/// Migration: low-water mark of an unfinished backfill (see `StatePort::get_backfill_low_id`).
+const MIGRATION_SYNC_STATE_BACKFILL_LOW: &str =
+    "ALTER TABLE sync_state ADD COLUMN backfill_low_id INTEGER NOT NULL DEFAULT 0";
+
+/// Per-chat sync overrides. NULL columns fall back to the sync's own choice; `media_types`
+/// is a comma-separated list of type names ('' = none).
+const CHAT_SETTINGS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS chat_settings (
+    chat_id INTEGER PRIMARY KEY,
+    include_media INTEGER,
+    media_types TEXT,
+    batch_limit INTEGER,
+    sync_enabled INTEGER NOT NULL DEFAULT 1
 )"#;
 
+/// Schema versions, oldest first (see [`migrations`]). Append new schema changes here.
+const MIGRATIONS: [Migration; 9] = [
+    Migration {
+        version: 1,
+        name: "baseline schema",
+        step: MigrationStep::Rust(baseline_schema),
+    },
+    Migration {
+        version: 2,
+        name: "media_files status index",
+        step: MigrationStep::Sql(&[MEDIA_FILES_STATUS_INDEX]),
+    },
+    Migration {
+        version: 3,
+        name: "media_files remote_key",
+        step: MigrationStep::Sql(&MIGRATIONS_MEDIA_REMOTE_KEY),
+    },
+    Migration {
+        version: 4,
+        name: "chat_settings",
+        step: MigrationStep::Sql(&[CHAT_SETTINGS_TABLE]),
+    },
+    Migration {
+        version: 5,
+        name: "sync_state backfill_low_id",
+        step: MigrationStep::Sql(&[MIGRATION_SYNC_STATE_BACKFILL_LOW]),
+    },
+    Migration {
+        version: 6,
+        name: "media_files expected_size",
+        step: MigrationStep::Sql(&[MIGRATION_MEDIA_EXPECTED_SIZE]),
+    },
+    Migration {
+        version: 7,
+        name: "messages grouped_id",
+        step: MigrationStep::Sql(&MIGRATIONS_MESSAGES_GROUPED_ID),
+    },
+    Migration {
+        version: 8,
+        name: "messages mentions",
+        step: MigrationStep::Sql(&MIGRATIONS_MESSAGES_MENTIONS),
+    },
+    Migration {
+        version: 9,
+        name: "watch_rules kind",
+        step: MigrationStep::Sql(&MIGRATIONS_WATCH_RULE_KIND),
+    },
+];
+
+/// Migration 1: the schema as it was when versioning was introduced. Every statement is
+/// idempotent, so it also upgrades databases created before (which are at version 0) from
+/// whatever layout they have.
+fn baseline_schema(conn: &libsql::Connection) -> BoxFuture<'_, Result<(), DomainError>> {
+    Box::pin(async move {
+        conn.execute(MESSAGES_TABLE, ())
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        // Add history_json, topic_id, forward, poll, service and discussion columns to existing
+        // DBs that predate them (idempotent).
+        let columns = [MIGRATION_ADD_HISTORY_JSON, MIGRATION_ADD_TOPIC_ID]
+            .into_iter()
+            .chain(MIGRATIONS_ADD_FORWARD)
+            .chain([
+                MIGRATION_ADD_POLL_JSON,
+                MIGRATION_ADD_IS_SERVICE,
+                MIGRATION_ADD_LINKED_CHANNEL_POST,
+            ]);
+        for sql in columns {
+            if let Err(e) = conn.execute(sql, ()).await {
+                let msg = e.to_string();
+                if !msg.contains("duplicate column name") {
+                    return Err(DomainError::Repo(msg));
+                }
+            }
+        }
+        conn.execute(FORUM_TOPICS_TABLE, ())
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        conn.execute(CHAT_EVENTS_TABLE, ())
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        conn.execute(MESSAGES_INDEX, ())
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        conn.execute(MESSAGES_MEDIA_TYPE_INDEX, ())
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        conn.execute(MESSAGES_LINKED_POST_INDEX, ())
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        // Audit §6.2: Entity registry for persistent access_hash caching.
+        conn.execute(ENTITY_REGISTRY_TABLE, ())
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+
+        conn.execute(BLACKLIST_TABLE, ())
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+
+        conn.execute(TARGETS_TABLE, ())
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+
+        // AI Analysis: Create analysis_log table for tracking analyzed periods.
+        if SqliteRepo::has_table(conn, "analysis_log").await?
+            && !SqliteRepo::has_column(conn, "analysis_log", "granularity").await?
+        {
+            for sql in MIGRATION_ANALYSIS_LOG_GRANULARITY {
+                conn.execute(sql, ())
+                    .await
+                    .map_err(|e| DomainError::Repo(format!("analysis_log migration: {}", e)))?;
+            }
+            info!("analysis_log migrated to per-granularity keys");
+        }
+        conn.execute(ANALYSIS_LOG_TABLE, ())
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        // Chunk summaries are only a cache: an older layout is dropped, not migrated.
+        if SqliteRepo::has_table(conn, "analysis_chunks").await?
+            && !SqliteRepo::has_column(conn, "analysis_chunks", "granularity").await?
+        {
+            conn.execute("DROP TABLE analysis_chunks", ())
+                .await
+                .map_err(|e| DomainError::Repo(e.to_string()))?;
+        }
+        conn.execute(ANALYSIS_CHUNKS_TABLE, ())
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        conn.execute(AI_USAGE_TABLE, ())
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        conn.execute(TRACKER_TASKS_TABLE, ())
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+
+        conn.execute(MEDIA_FILES_TABLE, ())
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+
+        conn.execute(CHATS_TABLE, ())
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        for sql in MIGRATIONS_CHATS_METADATA {
+            if let Err(e) = conn.execute(sql, ()).await {
+                let msg = e.to_string();
+                if !msg.contains("duplicate column name") {
+                    return Err(DomainError::Repo(msg));
+                }
+            }
+        }
+
+        conn.execute(SYNC_STATE_TABLE, ())
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        if let Err(e) = conn.execute(MIGRATION_SYNC_STATE_GAP, ()).await {
+            let msg = e.to_string();
+            if !msg.contains("duplicate column name") {
+                return Err(DomainError::Repo(msg));
+            }
+        }
+
+        conn.execute(USERS_TABLE, ())
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+
+        let had_watch_rules = SqliteRepo::has_table(conn, "watch_rules").await?;
+        conn.execute(WATCH_RULES_TABLE, ())
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        if !had_watch_rules {
+            for keyword in DEFAULT_WATCH_KEYWORDS {
+                conn.execute(
+                    "INSERT OR IGNORE INTO watch_rules (chat_id, pattern) VALUES (0, ?1)",
+                    params![*keyword],
+                )
+                .await
+                .map_err(|e| DomainError::Repo(e.to_string()))?;
+            }
+        }
+        conn.execute(SETTINGS_TABLE, ())
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        conn.execute(MEDIA_QUEUE_TABLE, ())
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        conn.execute(MEDIA_QUEUE_STATUS_INDEX, ())
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        Ok(())
+    })
+}
+
+/// Unix time range `[from, until)` of the period key `key` (UTC, as SQLite's strftime
+/// computes it). Week keys are "%Y-%W": week 00 runs from January 1 to the first Monday.
+fn period_bounds(granularity: Granularity, key: &str) -> Option<(i64, i64)> {
+    use chrono::{Datelike, Days, Months, NaiveDate};
+
+    let (start, end) = match granularity {
+        Granularity::Day => {
+            let day = NaiveDate::parse_from_str(key, "%Y-%m-%d").ok()?;
+            (day, day.checked_add_days(Days::new(1))?)
+        }
+        Granularity::Month => {
+            let first = NaiveDate::parse_from_str(&format!("{}-01", key), "%Y-%m-%d").ok()?;
+            (first, first.checked_add_months(Months::new(1))?)
+        }
+        Granularity::Week => {
+            let (year, week) = key.split_once('-')?;
+            let (year, week): (i32, u64) = (year.parse().ok()?, week.parse().ok()?);
+            let jan1 = NaiveDate::from_ymd_opt(year, 1, 1)?;
+            let next_year = NaiveDate::from_ymd_opt(year + 1, 1, 1)?;
+            let offset = (7 - u64::from(jan1.weekday().num_days_from_monday())) % 7;
+            let first_monday = jan1.checked_add_days(Days::new(offset))?;
+            let (start, end) = match week {
+                0 => (jan1, first_monday),
+                n => {
+                    let start = first_monday.checked_add_days(Days::new((n - 1) * 7))?;
+                    (start, start.checked_add_days(Days::new(7))?)
+                }
+            };
+            (start, end.min(next_year))
+        }
+    };
+    let ts = |d: NaiveDate| d.and_hms_opt(0, 0, 0).map(|t| t.and_utc().timestamp());
+    Some((ts(start)?, ts(end)?))
+}
+
+/// Context of a sealed message field: binds the value to its message.
+fn field_context(field: &str, chat_id: i64, id: i32) -> String {
+    format!("{}:{}:{}", field, chat_id, id)
+}
+
+/// `value` sealed, or None when it is empty or already opens under `context`. Plaintext that
+/// merely starts with the sealed prefix does not open, so it gets sealed like any other.
+fn seal_plain(cipher: &ArchiveCipher, context: &str, value: &str) -> Option<String> {
+    let sealed = ArchiveCipher::is_sealed(value) && cipher.open(context, value).is_ok();
+    (!value.is_empty() && !sealed).then(|| cipher.seal(context, value))
+}
+
+/// Stored form of a media reference in an encrypted archive. The type stays readable for
+/// the media-type index and statistics.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SealedMedia {
+    media_type: MediaType,
+    sealed: String,
+}
+
+fn seal_media(cipher: &ArchiveCipher, chat_id: i64, id: i32, media: &MediaReference) -> String {
+    let json = serde_json::to_string(media).unwrap_or_default();
+    serde_json::to_string(&SealedMedia {
+        media_type: media.media_type,
+        sealed: cipher.seal(&field_context("media", chat_id, id), &json),
+    })
+    .unwrap_or_default()
+}
+
+fn is_sealed_media(json: &str) -> bool {
+    json.contains("\"sealed\":\"")
+}
+
+/// Edit history with every plaintext version sealed, or None when nothing changed.
+fn seal_history(cipher: &ArchiveCipher, chat_id: i64, id: i32, json: &str) -> Option<String> {
+    let mut edits: Vec<MessageEdit> = serde_json::from_str(json).ok()?;
+    let context = field_context("text", chat_id, id);
+    let mut changed = false;
+    for edit in &mut edits {
+        if let Some(sealed) = seal_plain(cipher, &context, &edit.text) {
+            edit.text = sealed;
+            changed = true;
+        }
+    }
+    changed
+        .then(|| serde_json::to_string(&edits).ok())
+        .flatten()
+}
+
 /// SQLite repository. One database file (messages.db) in the given base directory.
 /// Chat IDs are stored as a column; all chats share the same file.
 pub struct SqliteRepo {
     db: Database,
     db_path: PathBuf,
+    /// Set for encrypted archives: seals message fields on write, opens them on read.
+    cipher: Option<Arc<ArchiveCipher>>,
 }
 
 impl SqliteRepo {
-    /// Connect to (or create) the SQLite database and ensure the schema exists.
-    /// Call this once at startup; the returned repo is safe to share via Arc.
+    /// Connect to (or create) the SQLite database and apply pending schema migrations
+    /// ([`MIGRATIONS`]). Call this once at startup; the returned repo is safe to share via Arc.
     ///
     /// Audit §5.3: Sets WAL mode and synchronous=NORMAL for concurrent read/write
     /// and better performance without sacrificing durability.
+    ///
+    /// Fails for an encrypted archive; open those with [`Self::connect_encrypted`].
     pub async fn connect(base_dir: impl AsRef<Path>) -> Result<Self, DomainError> {
-        let base = base_dir.as_ref();
+        Self::open(base_dir.as_ref(), None).await
+    }
+
+    /// Connect with encryption at rest under `secret` (a 64-hex-digit key or a passphrase).
+    /// The first encrypted connection marks the archive as encrypted; later ones fail with a
+    /// different key. Rows written before encryption stay readable until [`Self::rekey`].
+    pub async fn connect_encrypted(
+        base_dir: impl AsRef<Path>,
+        secret: &str,
+    ) -> Result<Self, DomainError> {
+        Self::open(base_dir.as_ref(), Some(secret)).await
+    }
+
+    /// True when the archive in `base_dir` was encrypted (needs a key to open). False when
+    /// there is no archive yet.
+    pub async fn is_encrypted(base_dir: impl AsRef<Path>) -> Result<bool, DomainError> {
+        let db_path = base_dir.as_ref().join("messages.db");
+        if !db_path.exists() {
+            return Ok(false);
+        }
+        let db = libsql::Builder::new_local(db_path.to_string_lossy().as_ref())
+            .build()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        let conn = db.connect().map_err(|e| DomainError::Repo(e.to_string()))?;
+        if !Self::has_table(&conn, "settings").await? {
+            return Ok(false);
+        }
+        Ok(Self::read_setting(&conn, ENCRYPTION_CHECK_KEY)
+            .await?
+            .is_some())
+    }
+
+    async fn open(base: &Path, secret: Option<&str>) -> Result<Self, DomainError> {
         std::fs::create_dir_all(base).map_err(|e| DomainError::Repo(e.to_string()))?;
         let db_path = base.join("messages.db");
         let path_str = db_path.to_string_lossy();
@@ -113,83 +813,480 @@ impl SqliteRepo {
             .is_some()
         {}
 
-        conn.execute(MESSAGES_TABLE, ())
-            .await
-            .map_err(|e| DomainError::Repo(e.to_string()))?;
-        // Add history_json to existing DBs that predate message versioning (idempotent).
-        if let Err(e) = conn.execute(MIGRATION_ADD_HISTORY_JSON, ()).await {
-            let msg = e.to_string();
-            if !msg.contains("duplicate column name") {
-                return Err(DomainError::Repo(msg));
-            }
-        }
-        conn.execute(MESSAGES_INDEX, ())
-            .await
-            .map_err(|e| DomainError::Repo(e.to_string()))?;
-        // Audit §6.2: Entity registry for persistent access_hash caching.
-        conn.execute(ENTITY_REGISTRY_TABLE, ())
-            .await
-            .map_err(|e| DomainError::Repo(e.to_string()))?;
-
-        conn.execute(BLACKLIST_TABLE, ())
-            .await
-            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        let version = migrations::migrate(&conn, &MIGRATIONS).await?;
 
-        conn.execute(TARGETS_TABLE, ())
-            .await
-            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        let cipher = match secret {
+            Some(secret) => Some(Arc::new(Self::archive_cipher(&conn, secret).await?)),
+            None if Self::read_setting(&conn, ENCRYPTION_CHECK_KEY)
+                .await?
+                .is_some() =>
+            {
+                return Err(DomainError::Crypto(
+                    "the archive is encrypted; set TG_SYNC_ENCRYPTION_KEY".to_string(),
+                ));
+            }
+            None => None,
+        };
 
-        // AI Analysis: Create analysis_log table for tracking analyzed weeks.
-        conn.execute(ANALYSIS_LOG_TABLE, ())
+        // Full-text search; databases created before it get the index backfilled once. An
+        // encrypted archive has none: the index would keep the plaintext.
+        let had_fts = Self::has_table(&conn, FTS_TABLE).await?;
+        let fts_schema = if cipher.is_some() {
+            FTS_DROP
+        } else {
+            FTS_SCHEMA
+        };
+        for sql in fts_schema {
+            conn.execute(sql, ())
+                .await
+                .map_err(|e| DomainError::Repo(format!("FTS schema: {}", e)))?;
+        }
+        if cipher.is_some() && had_fts {
+            info!("archive encrypted: full-text index dropped");
+        } else if !had_fts && cipher.is_none() {
+            conn.execute(
+                &format!("INSERT INTO {0} ({0}) VALUES ('rebuild')", FTS_TABLE),
+                (),
+            )
             .await
-            .map_err(|e| DomainError::Repo(e.to_string()))?;
+            .map_err(|e| DomainError::Repo(format!("FTS backfill failed: {}", e)))?;
+            info!("full-text index created and backfilled");
+        }
 
         info!(
             path = %db_path.display(),
-            "SQLite connected with WAL mode, entity_registry, and analysis_log"
+            schema_version = version,
+            "SQLite connected with WAL mode, entity_registry, analysis_log, media_files, and chats"
         );
 
         Ok(Self {
             db,
             db_path: db_path.to_path_buf(),
+            cipher,
         })
     }
 
-    fn media_to_json(media: &Option<MediaReference>) -> Option<String> {
-        media.as_ref().and_then(|m| serde_json::to_string(m).ok())
+    /// Cipher for `secret`, with the archive's salt (created on first use). The check value
+    /// is sealed on first use and must open with every later key.
+    async fn archive_cipher(
+        conn: &libsql::Connection,
+        secret: &str,
+    ) -> Result<ArchiveCipher, DomainError> {
+        let salt = match Self::read_setting(conn, ENCRYPTION_SALT_KEY).await? {
+            Some(encoded) => BASE64
+                .decode(encoded)
+                .map_err(|e| DomainError::Crypto(format!("malformed archive salt: {}", e)))?,
+            None => {
+                let salt = ArchiveCipher::new_salt().to_vec();
+                Self::write_setting(conn, ENCRYPTION_SALT_KEY, &BASE64.encode(&salt)).await?;
+                salt
+            }
+        };
+        let cipher = ArchiveCipher::from_secret(secret, &salt)?;
+        match Self::read_setting(conn, ENCRYPTION_CHECK_KEY).await? {
+            Some(check) => {
+                if cipher.open(ENCRYPTION_CHECK_KEY, &check).ok().as_deref()
+                    != Some(ENCRYPTION_CHECK_VALUE)
+                {
+                    return Err(DomainError::Crypto(
+                        "wrong encryption key for this archive".to_string(),
+                    ));
+                }
+            }
+            None => {
+                let check = cipher.seal(ENCRYPTION_CHECK_KEY, ENCRYPTION_CHECK_VALUE);
+                Self::write_setting(conn, ENCRYPTION_CHECK_KEY, &check).await?;
+                info!("archive encryption enabled");
+            }
+        }
+        Ok(cipher)
     }
 
-    fn json_to_media(s: Option<&str>) -> Option<MediaReference> {
-        s.and_then(|s| serde_json::from_str(s).ok())
+    async fn read_setting(
+        conn: &libsql::Connection,
+        key: &str,
+    ) -> Result<Option<String>, DomainError> {
+        let mut rows = conn
+            .query("SELECT value FROM settings WHERE key = ?1", params![key])
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        match rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            Some(row) => Ok(row.get::<String>(0).ok()),
+            None => Ok(None),
+        }
     }
 
-    fn json_to_edit_history(s: Option<&str>) -> Option<Vec<MessageEdit>> {
-        let s = s.unwrap_or("[]").trim();
-        if s.is_empty() || s == "[]" {
-            return None;
-        }
-        match serde_json::from_str::<Vec<MessageEdit>>(s) {
-            Ok(v) if v.is_empty() => None,
-            Ok(v) => Some(v),
-            _ => None,
-        }
+    async fn write_setting(
+        conn: &libsql::Connection,
+        key: &str,
+        value: &str,
+    ) -> Result<(), DomainError> {
+        conn.execute(
+            "INSERT INTO settings (key, value) VALUES (?1, ?2) ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+            params![key, value],
+        )
+        .await
+        .map_err(|e| DomainError::Repo(e.to_string()))?;
+        Ok(())
     }
-}
 
-#[async_trait::async_trait]
-impl RepoPort for SqliteRepo {
-    async fn save_messages(&self, chat_id: i64, messages: &[Message]) -> Result<(), DomainError> {
-        if messages.is_empty() {
-            return Ok(());
+    /// Cipher of an encrypted archive, for files stored next to it (media).
+    pub fn cipher(&self) -> Option<Arc<ArchiveCipher>> {
+        self.cipher.clone()
+    }
+
+    /// Seal every message field still stored in plaintext (rows written before encryption
+    /// was enabled), `batch` rows per transaction, then VACUUM so the old plaintext pages
+    /// leave the file. Returns the number of messages rewritten.
+    pub async fn rekey(&self, batch: u32) -> Result<u64, DomainError> {
+        let Some(cipher) = self.cipher.as_deref() else {
+            return Err(DomainError::Crypto(
+                "rekey needs an encryption key (TG_SYNC_ENCRYPTION_KEY)".to_string(),
+            ));
+        };
+        let conn = self.connection()?;
+        let mut after_rowid = 0i64;
+        let mut rewritten = 0u64;
+        loop {
+            let mut rows = conn
+                .query(
+                    "SELECT rowid, chat_id, id, text, media_json, poll_json, history_json FROM messages WHERE rowid > ?1 ORDER BY rowid LIMIT ?2",
+                    params![after_rowid, batch as i64],
+                )
+                .await
+                .map_err(|e| DomainError::Repo(e.to_string()))?;
+            let mut updates = Vec::new();
+            let mut seen = 0u32;
+            while let Some(row) = rows
+                .next()
+                .await
+                .map_err(|e| DomainError::Repo(e.to_string()))?
+            {
+                seen += 1;
+                let rowid: i64 = row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?;
+                after_rowid = rowid;
+                let chat_id: i64 = row.get(1).map_err(|e| DomainError::Repo(e.to_string()))?;
+                let id: i32 = row.get(2).map_err(|e| DomainError::Repo(e.to_string()))?;
+                let text = row.get::<String>(3).unwrap_or_default();
+                let media_json: Option<String> = row.get(4).ok();
+                let poll_json: Option<String> = row.get(5).ok();
+                let history_json: Option<String> = row.get(6).ok();
+
+                let sealed_text = seal_plain(cipher, &field_context("text", chat_id, id), &text);
+                let sealed_media = media_json.as_deref().and_then(|json| {
+                    if is_sealed_media(json) {
+                        return None;
+                    }
+                    let media: MediaReference = serde_json::from_str(json).ok()?;
+                    Some(seal_media(cipher, chat_id, id, &media))
+                });
+                let sealed_poll = poll_json
+                    .as_deref()
+                    .and_then(|json| seal_plain(cipher, &field_context("poll", chat_id, id), json));
+                let sealed_history = history_json
+                    .as_deref()
+                    .and_then(|json| seal_history(cipher, chat_id, id, json));
+                if sealed_text.is_none()
+                    && sealed_media.is_none()
+                    && sealed_poll.is_none()
+                    && sealed_history.is_none()
+                {
+                    continue;
+                }
+                updates.push((
+                    rowid,
+                    sealed_text.unwrap_or(text),
+                    sealed_media.or(media_json),
+                    sealed_poll.or(poll_json),
+                    sealed_history.or(history_json),
+                ));
+            }
+            drop(rows);
+            if !updates.is_empty() {
+                let tx = conn
+                    .transaction()
+                    .await
+                    .map_err(|e| DomainError::Repo(e.to_string()))?;
+                for (rowid, text, media_json, poll_json, history_json) in &updates {
+                    tx.execute(
+                        "UPDATE messages SET text = ?1, media_json = ?2, poll_json = ?3, history_json = ?4 WHERE rowid = ?5",
+                        params![
+                            text.as_str(),
+                            media_json.as_deref(),
+                            poll_json.as_deref(),
+                            history_json.as_deref(),
+                            *rowid
+                        ],
+                    )
+                    .await
+                    .map_err(|e| DomainError::Repo(e.to_string()))?;
+                }
+                tx.commit()
+                    .await
+                    .map_err(|e| DomainError::Repo(e.to_string()))?;
+                rewritten += updates.len() as u64;
+                info!(rewritten, "rekey: batch sealed");
+            }
+            if seen < batch {
+                break;
+            }
         }
-        let abs_path = self
-            .db_path
-            .canonicalize()
+        conn.execute("VACUUM", ())
+            .await
+            .map_err(|e| DomainError::Repo(format!("VACUUM after rekey failed: {}", e)))?;
+        // VACUUM goes through the WAL; truncate it so no old page stays behind there.
+        Self::checkpoint_wal(&conn).await?;
+        Ok(rewritten)
+    }
+
+    /// New connection to the database, for adapters sharing this file (e.g. `StateSqlite`).
+    pub(crate) fn connection(&self) -> Result<libsql::Connection, DomainError> {
+        self.db
+            .connect()
+            .map_err(|e| DomainError::Repo(e.to_string()))
+    }
+
+    /// Per-chat totals for `chat_id` (or every chat with stored messages), ordered by chat id.
+    /// Three grouped aggregates: messages (chat/date index), media references per type
+    /// (media type index) and downloaded files (media_files primary key).
+    async fn collect_chat_stats(
+        &self,
+        chat_id: Option<i64>,
+    ) -> Result<Vec<ChatStats>, DomainError> {
+        let conn = self.connection()?;
+        let (filter, args) = match chat_id {
+            Some(id) => ("chat_id = ?1", vec![id]),
+            None => ("1", Vec::new()),
+        };
+        let mut stats: Vec<ChatStats> = Vec::new();
+
+        let mut rows = conn
+            .query(
+                &format!(
+                    "SELECT chat_id, COUNT(*), MIN(date), MAX(date) FROM messages WHERE {} GROUP BY chat_id ORDER BY chat_id",
+                    filter
+                ),
+                args.clone(),
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            stats.push(ChatStats {
+                chat_id: row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?,
+                messages: row
+                    .get::<i64>(1)
+                    .map_err(|e| DomainError::Repo(e.to_string()))?
+                    as u64,
+                oldest: row.get::<i64>(2).ok(),
+                newest: row.get::<i64>(3).ok(),
+                ..Default::default()
+            });
+        }
+
+        let mut rows = conn
+            .query(
+                &format!(
+                    "SELECT chat_id, {0}, COUNT(*) FROM messages WHERE media_json IS NOT NULL AND {1} GROUP BY chat_id, {0} ORDER BY chat_id, COUNT(*) DESC",
+                    MEDIA_TYPE_EXPR, filter
+                ),
+                args.clone(),
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            let id: i64 = row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?;
+            let kind = MediaType::from_name(&row.get::<String>(1).unwrap_or_default());
+            let count = row
+                .get::<i64>(2)
+                .map_err(|e| DomainError::Repo(e.to_string()))? as u64;
+            if let Some(chat) = stats.iter_mut().find(|c| c.chat_id == id) {
+                // Unknown names all map to Other; merge them.
+                match chat.media_by_type.iter_mut().find(|(k, _)| *k == kind) {
+                    Some((_, n)) => *n += count,
+                    None => chat.media_by_type.push((kind, count)),
+                }
+            }
+        }
+
+        let mut rows = conn
+            .query(
+                &format!(
+                    "SELECT chat_id, COUNT(*), COALESCE(SUM(size_bytes), 0) FROM media_files WHERE status = 'done' AND {} GROUP BY chat_id",
+                    filter
+                ),
+                args,
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            let id: i64 = row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?;
+            if let Some(chat) = stats.iter_mut().find(|c| c.chat_id == id) {
+                chat.media_files =
+                    row.get::<i64>(1)
+                        .map_err(|e| DomainError::Repo(e.to_string()))? as u64;
+                chat.media_bytes =
+                    row.get::<i64>(2)
+                        .map_err(|e| DomainError::Repo(e.to_string()))? as u64;
+            }
+        }
+        Ok(stats)
+    }
+
+    fn media_to_json(
+        &self,
+        chat_id: i64,
+        id: i32,
+        media: &Option<MediaReference>,
+    ) -> Option<String> {
+        let media = media.as_ref()?;
+        match self.cipher.as_deref() {
+            Some(cipher) => Some(seal_media(cipher, chat_id, id, media)),
+            None => serde_json::to_string(media).ok(),
+        }
+    }
+
+    /// Media reference of message `id`; None when absent, unparseable or not decryptable.
+    fn json_to_media(&self, chat_id: i64, id: i32, s: Option<&str>) -> Option<MediaReference> {
+        let s = s?;
+        match (self.cipher.as_deref(), is_sealed_media(s)) {
+            (Some(cipher), true) => {
+                let sealed: SealedMedia = serde_json::from_str(s).ok()?;
+                let json = cipher
+                    .open(&field_context("media", chat_id, id), &sealed.sealed)
+                    .ok()?;
+                serde_json::from_str(&json).ok()
+            }
+            _ => serde_json::from_str(s).ok(),
+        }
+    }
+
+    /// Seal `value` of field `field` of message (`chat_id`, `id`); unchanged without a key.
+    fn seal(&self, field: &str, chat_id: i64, id: i32, value: &str) -> String {
+        match self.cipher.as_deref() {
+            Some(cipher) => cipher.seal(&field_context(field, chat_id, id), value),
+            None => value.to_string(),
+        }
+    }
+
+    /// Open a value sealed by [`Self::seal`]; plaintext values pass through.
+    fn unseal(
+        &self,
+        field: &str,
+        chat_id: i64,
+        id: i32,
+        value: String,
+    ) -> Result<String, DomainError> {
+        match self.cipher.as_deref() {
+            Some(cipher) => cipher.open(&field_context(field, chat_id, id), &value),
+            None => Ok(value),
+        }
+    }
+
+    /// Map a row selected as [`MESSAGE_COLUMNS`] (`chat_id, id, date, text, media_json,
+    /// from_user_id, reply_to_msg_id, history_json, sender_name, topic_id, topic_title,
+    /// forwarded_from_name, forwarded_from_id, forwarded_date, poll_json, linked_channel_post,
+    /// grouped_id, mentioned, mentioned_user_ids`).
+    fn row_to_message(&self, row: &libsql::Row) -> Result<Message, DomainError> {
+        let id: i32 = row.get(1).map_err(|e| DomainError::Repo(e.to_string()))?;
+        let chat_id: i64 = row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?;
+        let date: i64 = row.get(2).map_err(|e| DomainError::Repo(e.to_string()))?;
+        let text = self.unseal(
+            "text",
+            chat_id,
+            id,
+            row.get::<String>(3).unwrap_or_default(),
+        )?;
+        let media_json: Option<String> = row.get(4).ok();
+        let from_user_id: Option<i64> = row.get(5).ok();
+        let reply_to_msg_id: Option<i32> = row.get(6).ok();
+        let mut edit_history = Self::json_to_edit_history(row.get::<String>(7).ok().as_deref());
+        for edit in edit_history.iter_mut().flatten() {
+            edit.text = self.unseal("text", chat_id, id, std::mem::take(&mut edit.text))?;
+        }
+        let sender_name: Option<String> = row.get(8).ok();
+        let topic_id: Option<i32> = row.get(9).ok();
+        let topic_title: Option<String> = row.get(10).ok();
+        let forwarded_from_name: Option<String> = row.get(11).ok();
+        let forwarded_from_id: Option<i64> = row.get(12).ok();
+        let forwarded_date: Option<i64> = row.get(13).ok();
+        let poll_json = match row.get::<String>(14).ok() {
+            Some(json) => Some(self.unseal("poll", chat_id, id, json)?),
+            None => None,
+        };
+        let linked_channel_post: Option<i32> = row.get(15).ok();
+        let grouped_id: Option<i64> = row.get(16).ok();
+        let mentioned = row.get::<i64>(17).unwrap_or(0) != 0;
+        let mentioned_user_ids = row
+            .get::<String>(18)
+            .ok()
+            .map(|ids| ids.split(',').filter_map(|id| id.parse().ok()).collect())
+            .unwrap_or_default();
+        Ok(Message {
+            id,
+            chat_id,
+            date,
+            text,
+            media: self.json_to_media(chat_id, id, media_json.as_deref()),
+            from_user_id,
+            reply_to_msg_id,
+            edit_history,
+            sender_name,
+            topic_id,
+            topic_title,
+            forwarded_from_name,
+            forwarded_from_id,
+            forwarded_date,
+            poll: poll_json.and_then(|s| serde_json::from_str(&s).ok()),
+            linked_channel_post,
+            grouped_id,
+            mentioned,
+            mentioned_user_ids,
+        })
+    }
+
+    fn json_to_edit_history(s: Option<&str>) -> Option<Vec<MessageEdit>> {
+        let s = s.unwrap_or("[]").trim();
+        if s.is_empty() || s == "[]" {
+            return None;
+        }
+        match serde_json::from_str::<Vec<MessageEdit>>(s) {
+            Ok(v) if v.is_empty() => None,
+            Ok(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl RepoPort for SqliteRepo {
+    async fn save_messages(&self, chat_id: i64, messages: &[Message]) -> Result<(), DomainError> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+        let abs_path = self
+            .db_path
+            .canonicalize()
             .unwrap_or_else(|_| self.db_path.clone());
         info!(
             path = %abs_path.display(),
             chat_id,
-            count = messages.len(),
+            batch_size = messages.len(),
             "saved messages to disk"
         );
         let conn = self
@@ -201,24 +1298,105 @@ impl RepoPort for SqliteRepo {
             .await
             .map_err(|e| DomainError::Repo(e.to_string()))?;
         for m in messages {
-            let media_json = Self::media_to_json(&m.media);
+            let media_json = self.media_to_json(chat_id, m.id, &m.media);
+            let poll_json = m
+                .poll
+                .as_ref()
+                .and_then(|p| serde_json::to_string(p).ok())
+                .map(|json| self.seal("poll", chat_id, m.id, &json));
+            // Sealing is deterministic, so the upsert still sees unchanged text as unchanged.
+            let text = self.seal("text", chat_id, m.id, &m.text);
+            let mentioned_user_ids = (!m.mentioned_user_ids.is_empty()).then(|| {
+                m.mentioned_user_ids
+                    .iter()
+                    .map(i64::to_string)
+                    .collect::<Vec<_>>()
+                    .join(",")
+            });
             tx.execute(
                 r#"
-                INSERT INTO messages (chat_id, id, date, text, media_json, from_user_id, reply_to_msg_id, history_json)
-                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, '[]')
+                INSERT INTO messages (chat_id, id, date, text, media_json, from_user_id, reply_to_msg_id, history_json, topic_id, forwarded_from_name, forwarded_from_id, forwarded_date, poll_json, linked_channel_post, grouped_id, mentioned, mentioned_user_ids)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, '[]', ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)
                 ON CONFLICT (chat_id, id) DO UPDATE SET
                     date = excluded.date,
                     text = excluded.text,
                     media_json = excluded.media_json,
                     from_user_id = excluded.from_user_id,
                     reply_to_msg_id = excluded.reply_to_msg_id,
+                    topic_id = excluded.topic_id,
+                    forwarded_from_name = excluded.forwarded_from_name,
+                    forwarded_from_id = excluded.forwarded_from_id,
+                    forwarded_date = excluded.forwarded_date,
+                    poll_json = excluded.poll_json,
+                    linked_channel_post = COALESCE(excluded.linked_channel_post, messages.linked_channel_post),
+                    grouped_id = COALESCE(excluded.grouped_id, messages.grouped_id),
+                    mentioned = MAX(excluded.mentioned, messages.mentioned),
+                    mentioned_user_ids = COALESCE(excluded.mentioned_user_ids, messages.mentioned_user_ids),
                     history_json = CASE
-                        WHEN messages.text != excluded.text
+                        WHEN messages.text != excluded.text AND excluded.poll_json IS NULL
                         THEN json_insert(COALESCE(messages.history_json, '[]'), '$[#]', json_object('date', messages.date, 'text', messages.text))
                         ELSE COALESCE(messages.history_json, '[]')
                     END
                 "#,
-                params![chat_id, m.id, m.date, m.text.as_str(), media_json, m.from_user_id, m.reply_to_msg_id],
+                params![
+                    chat_id,
+                    m.id,
+                    m.date,
+                    text,
+                    media_json,
+                    m.from_user_id,
+                    m.reply_to_msg_id,
+                    m.topic_id,
+                    m.forwarded_from_name.as_deref(),
+                    m.forwarded_from_id,
+                    m.forwarded_date,
+                    poll_json,
+                    m.linked_channel_post,
+                    m.grouped_id,
+                    m.mentioned as i64,
+                    mentioned_user_ids
+                ],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        }
+        tx.commit()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn save_users(&self, users: &[User]) -> Result<(), DomainError> {
+        if users.is_empty() {
+            return Ok(());
+        }
+        let conn = self
+            .db
+            .connect()
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        let now = chrono::Utc::now().timestamp();
+        let tx = conn
+            .transaction()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        for u in users {
+            tx.execute(
+                r#"
+                INSERT INTO users (user_id, first_name, last_name, username, updated_at)
+                VALUES (?1, ?2, ?3, ?4, ?5)
+                ON CONFLICT (user_id) DO UPDATE SET
+                    first_name = excluded.first_name,
+                    last_name = excluded.last_name,
+                    username = excluded.username,
+                    updated_at = excluded.updated_at
+                "#,
+                params![
+                    u.id,
+                    u.first_name.as_deref(),
+                    u.last_name.as_deref(),
+                    u.username.as_deref(),
+                    now
+                ],
             )
             .await
             .map_err(|e| DomainError::Repo(e.to_string()))?;
@@ -241,13 +1419,17 @@ impl RepoPort for SqliteRepo {
             .map_err(|e| DomainError::Repo(e.to_string()))?;
         let mut rows = conn
             .query(
-                r#"
-                SELECT chat_id, id, date, text, media_json, from_user_id, reply_to_msg_id, history_json
-                FROM messages
-                WHERE chat_id = ?1
-                ORDER BY date DESC
+                &format!(
+                    r#"
+                SELECT {}
+                FROM messages m
+                LEFT JOIN users u ON u.user_id = m.from_user_id
+                WHERE m.chat_id = ?1
+                ORDER BY m.date DESC
                 LIMIT ?2 OFFSET ?3
                 "#,
+                    MESSAGE_COLUMNS
+                ),
                 params![chat_id, limit as i64, offset as i64],
             )
             .await
@@ -258,28 +1440,260 @@ impl RepoPort for SqliteRepo {
             .await
             .map_err(|e| DomainError::Repo(e.to_string()))?
         {
-            let id: i32 = row.get(1).map_err(|e| DomainError::Repo(e.to_string()))?;
-            let chat_id: i64 = row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?;
-            let date: i64 = row.get(2).map_err(|e| DomainError::Repo(e.to_string()))?;
-            let text: String = row.get::<String>(3).unwrap_or_default();
-            let media_json: Option<String> = row.get(4).ok();
-            let from_user_id: Option<i64> = row.get(5).ok();
-            let reply_to_msg_id: Option<i32> = row.get(6).ok();
-            let edit_history = Self::json_to_edit_history(row.get::<String>(7).ok().as_deref());
-            messages.push(Message {
-                id,
-                chat_id,
-                date,
-                text,
-                media: Self::json_to_media(media_json.as_deref()),
-                from_user_id,
-                reply_to_msg_id,
-                edit_history,
-            });
+            messages.push(self.row_to_message(&row)?);
+        }
+        Ok(messages)
+    }
+
+    async fn get_messages_after(
+        &self,
+        chat_id: i64,
+        after_id: i32,
+        limit: u32,
+    ) -> Result<Vec<Message>, DomainError> {
+        let conn = self
+            .db
+            .connect()
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        let mut rows = conn
+            .query(
+                &format!(
+                    r#"
+                SELECT {}
+                FROM messages m
+                LEFT JOIN users u ON u.user_id = m.from_user_id
+                WHERE m.chat_id = ?1 AND m.id > ?2
+                ORDER BY m.id ASC
+                LIMIT ?3
+                "#,
+                    MESSAGE_COLUMNS
+                ),
+                params![chat_id, after_id, limit as i64],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        let mut messages = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            messages.push(self.row_to_message(&row)?);
+        }
+        Ok(messages)
+    }
+
+    /// Served by `idx_messages_chat_date`, read backwards.
+    async fn get_messages_in_range(
+        &self,
+        chat_id: i64,
+        range: TimeRange,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<Message>, DomainError> {
+        let conn = self
+            .db
+            .connect()
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        let mut rows = conn
+            .query(
+                &format!(
+                    r#"
+                SELECT {}
+                FROM messages m
+                LEFT JOIN users u ON u.user_id = m.from_user_id
+                WHERE m.chat_id = ?1 AND m.date >= ?2 AND m.date < ?3
+                ORDER BY m.date ASC, m.id ASC
+                LIMIT ?4 OFFSET ?5
+                "#,
+                    MESSAGE_COLUMNS
+                ),
+                params![chat_id, range.from, range.to, limit as i64, offset as i64],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        let mut messages = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            messages.push(self.row_to_message(&row)?);
+        }
+        Ok(messages)
+    }
+
+    async fn get_messages_by_ids(
+        &self,
+        chat_id: i64,
+        ids: &[i32],
+    ) -> Result<Vec<Message>, DomainError> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let ids_json = serde_json::to_string(ids).map_err(|e| DomainError::Repo(e.to_string()))?;
+        let conn = self
+            .db
+            .connect()
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        // One bound JSON array instead of a variable-length IN (...) list.
+        let mut rows = conn
+            .query(
+                &format!(
+                    r#"
+                SELECT {}
+                FROM messages m
+                LEFT JOIN users u ON u.user_id = m.from_user_id
+                WHERE m.chat_id = ?1 AND m.id IN (SELECT value FROM json_each(?2))
+                ORDER BY m.id ASC
+                "#,
+                    MESSAGE_COLUMNS
+                ),
+                params![chat_id, ids_json],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        let mut messages = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            messages.push(self.row_to_message(&row)?);
+        }
+        Ok(messages)
+    }
+
+    async fn link_channel_comments(&self, chat_id: i64) -> Result<u64, DomainError> {
+        let conn = self.connection()?;
+        let mut linked = 0u64;
+        for _ in 0..MAX_COMMENT_DEPTH {
+            let changed = conn
+                .execute(
+                    r#"
+                    UPDATE messages SET linked_channel_post = (
+                        SELECT p.linked_channel_post FROM messages p
+                        WHERE p.chat_id = messages.chat_id AND p.id = messages.reply_to_msg_id
+                    )
+                    WHERE chat_id = ?1
+                      AND linked_channel_post IS NULL
+                      AND reply_to_msg_id IS NOT NULL
+                      AND EXISTS (
+                          SELECT 1 FROM messages p
+                          WHERE p.chat_id = messages.chat_id AND p.id = messages.reply_to_msg_id
+                            AND p.linked_channel_post IS NOT NULL
+                      )
+                    "#,
+                    params![chat_id],
+                )
+                .await
+                .map_err(|e| DomainError::Repo(e.to_string()))?;
+            if changed == 0 {
+                break;
+            }
+            linked += changed;
+        }
+        Ok(linked)
+    }
+
+    async fn get_channel_comments(
+        &self,
+        channel_id: i64,
+        post_ids: &[i32],
+    ) -> Result<Vec<Message>, DomainError> {
+        if post_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let ids_json =
+            serde_json::to_string(post_ids).map_err(|e| DomainError::Repo(e.to_string()))?;
+        let conn = self.connection()?;
+        // Discussion groups are the chats holding automatic copies of the channel's posts;
+        // the copies themselves are not comments.
+        let mut rows = conn
+            .query(
+                &format!(
+                    r#"
+                SELECT {}
+                FROM messages m
+                LEFT JOIN users u ON u.user_id = m.from_user_id
+                WHERE m.linked_channel_post IN (SELECT value FROM json_each(?2))
+                  AND m.chat_id IN (
+                      SELECT DISTINCT chat_id FROM messages
+                      WHERE linked_channel_post IS NOT NULL AND forwarded_from_id = ?1
+                  )
+                  AND m.forwarded_from_id IS NOT ?1
+                ORDER BY m.linked_channel_post ASC, m.id ASC
+                "#,
+                    MESSAGE_COLUMNS
+                ),
+                params![channel_id, ids_json],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        let mut messages = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            messages.push(self.row_to_message(&row)?);
         }
         Ok(messages)
     }
 
+    async fn search_messages(
+        &self,
+        query: &str,
+        chat_id: Option<i64>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<SearchHit>, DomainError> {
+        if self.cipher.is_some() {
+            return self.search_sealed(query, chat_id, limit, offset).await;
+        }
+        let Some(fts_query) = fts_query(query) else {
+            return Ok(Vec::new());
+        };
+        let conn = self
+            .db
+            .connect()
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        let mut rows = conn
+            .query(
+                &format!(
+                    r#"
+                SELECT {}, c.title, snippet(messages_fts, 0, '[', ']', '…', 12)
+                FROM messages_fts
+                JOIN messages m ON m.rowid = messages_fts.rowid
+                LEFT JOIN users u ON u.user_id = m.from_user_id
+                LEFT JOIN chats c ON c.chat_id = m.chat_id
+                WHERE messages_fts MATCH ?1 AND (?2 IS NULL OR m.chat_id = ?2)
+                ORDER BY bm25(messages_fts), m.date DESC
+                LIMIT ?3 OFFSET ?4
+                "#,
+                    MESSAGE_COLUMNS
+                ),
+                params![fts_query, chat_id, limit as i64, offset as i64],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(format!("search failed: {}", e)))?;
+        let mut hits = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            hits.push(SearchHit {
+                message: self.row_to_message(&row)?,
+                chat_title: row.get::<String>(MESSAGE_COLUMN_COUNT).ok(),
+                snippet: row
+                    .get::<String>(MESSAGE_COLUMN_COUNT + 1)
+                    .unwrap_or_default(),
+            });
+        }
+        Ok(hits)
+    }
+
     async fn get_blacklisted_ids(&self) -> Result<HashSet<i64>, DomainError> {
         let conn = self
             .db
@@ -373,44 +1787,111 @@ impl RepoPort for SqliteRepo {
             .map_err(|e| DomainError::Repo(e.to_string()))?;
         Ok(())
     }
-}
 
-/// Audit §6.2: Persistent entity registry implementation.
-/// Enables fast InputPeer resolution without re-iterating dialogs.
-#[async_trait::async_trait]
-impl EntityRegistry for SqliteRepo {
-    async fn get_access_hash(&self, peer_id: i64) -> Result<Option<i64>, DomainError> {
-        let conn = self
-            .db
-            .connect()
-            .map_err(|e| DomainError::Repo(e.to_string()))?;
+    async fn get_chat_settings(&self, chat_id: i64) -> Result<ChatSettings, DomainError> {
+        Ok(self
+            .chat_settings_rows(Some(chat_id))
+            .await?
+            .pop()
+            .unwrap_or_else(|| ChatSettings::new(chat_id)))
+    }
+
+    async fn list_chat_settings(&self) -> Result<Vec<ChatSettings>, DomainError> {
+        self.chat_settings_rows(None).await
+    }
+
+    async fn set_chat_settings(&self, settings: &ChatSettings) -> Result<(), DomainError> {
+        let conn = self.connection()?;
+        if settings.is_default() {
+            conn.execute(
+                "DELETE FROM chat_settings WHERE chat_id = ?1",
+                params![settings.chat_id],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+            return Ok(());
+        }
+        let media_types = settings.media_types.as_ref().map(|types| {
+            types
+                .iter()
+                .map(|t| t.as_str())
+                .collect::<Vec<_>>()
+                .join(",")
+        });
+        conn.execute(
+            r#"
+            INSERT INTO chat_settings (chat_id, include_media, media_types, batch_limit, sync_enabled)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT(chat_id) DO UPDATE SET
+                include_media = excluded.include_media,
+                media_types = excluded.media_types,
+                batch_limit = excluded.batch_limit,
+                sync_enabled = excluded.sync_enabled
+            "#,
+            params![
+                settings.chat_id,
+                settings.include_media.map(i64::from),
+                media_types,
+                settings.batch_limit.map(i64::from),
+                settings.sync_enabled as i64
+            ],
+        )
+        .await
+        .map_err(|e| DomainError::Repo(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn get_activity_histogram(
+        &self,
+        chat_id: i64,
+        bucket: ActivityBucket,
+        range: TimeRange,
+        utc_offset_secs: i32,
+    ) -> Result<Vec<ActivityBin>, DomainError> {
+        let conn = self
+            .db
+            .connect()
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        // Shift into local time before bucketing, then (for days) shift the bucket back to UTC
+        // so callers get the Unix timestamp of local midnight. Uses idx_messages_chat_date.
+        let bucket_expr = match bucket {
+            ActivityBucket::Day => "((date + ?4) / 86400) * 86400 - ?4",
+            ActivityBucket::HourOfDay => "((date + ?4) % 86400) / 3600",
+        };
+        let sql = format!(
+            r#"
+            SELECT {} AS bucket, COUNT(*) AS cnt
+            FROM messages
+            WHERE chat_id = ?1 AND date >= ?2 AND date < ?3
+            GROUP BY bucket
+            ORDER BY bucket ASC
+            "#,
+            bucket_expr
+        );
         let mut rows = conn
             .query(
-                "SELECT access_hash FROM entity_registry WHERE peer_id = ?1",
-                params![peer_id],
+                &sql,
+                params![chat_id, range.from, range.to, utc_offset_secs as i64],
             )
             .await
             .map_err(|e| DomainError::Repo(e.to_string()))?;
-
-        if let Some(row) = rows
+        let mut bins = Vec::new();
+        while let Some(row) = rows
             .next()
             .await
             .map_err(|e| DomainError::Repo(e.to_string()))?
         {
-            let access_hash: i64 = row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?;
-            Ok(Some(access_hash))
-        } else {
-            Ok(None)
+            let bucket_start: i64 = row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?;
+            let count: i64 = row.get(1).map_err(|e| DomainError::Repo(e.to_string()))?;
+            bins.push(ActivityBin {
+                bucket_start,
+                count: count as u64,
+            });
         }
+        Ok(bins)
     }
 
-    async fn save_entity(
-        &self,
-        peer_id: i64,
-        access_hash: i64,
-        peer_type: &str,
-        username: Option<&str>,
-    ) -> Result<(), DomainError> {
+    async fn register_synthetic_chat(&self, chat: &Chat) -> Result<(), DomainError> {
         let conn = self
             .db
             .connect()
@@ -422,198 +1903,287 @@ impl EntityRegistry for SqliteRepo {
 
         conn.execute(
             r#"
-            INSERT INTO entity_registry (peer_id, access_hash, peer_type, username, updated_at)
-            VALUES (?1, ?2, ?3, ?4, ?5)
-            ON CONFLICT (peer_id) DO UPDATE SET
-                access_hash = excluded.access_hash,
-                peer_type = excluded.peer_type,
+            INSERT INTO chats (chat_id, title, username, kind, synthetic, first_synced_at, last_synced_at)
+            VALUES (?1, ?2, ?3, ?4, 1, ?5, ?5)
+            ON CONFLICT (chat_id) DO UPDATE SET
+                title = excluded.title,
                 username = excluded.username,
-                updated_at = excluded.updated_at
+                kind = excluded.kind,
+                synthetic = 1,
+                last_synced_at = excluded.last_synced_at
             "#,
-            params![peer_id, access_hash, peer_type, username, now],
+            params![
+                chat.id,
+                chat.title.as_str(),
+                chat.username.as_deref(),
+                chat.kind.as_str(),
+                now
+            ],
         )
         .await
         .map_err(|e| DomainError::Repo(e.to_string()))?;
 
         Ok(())
     }
-}
 
-// ─────────────────────────────────────────────────────────────────────────────
-// AI Analysis: AnalysisLogPort implementation
-// ─────────────────────────────────────────────────────────────────────────────
+    async fn upsert_chats(&self, chats: &[Chat]) -> Result<(), DomainError> {
+        if chats.is_empty() {
+            return Ok(());
+        }
+        let conn = self.connection()?;
+        let now = chrono::Utc::now().timestamp();
+        let tx = conn
+            .transaction()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        for chat in chats {
+            tx.execute(
+                r#"
+                INSERT INTO chats (chat_id, title, username, kind, synthetic, first_synced_at, last_synced_at, approx_message_count, is_forum)
+                VALUES (?1, ?2, ?3, ?4, 0, ?5, ?5, ?6, ?7)
+                ON CONFLICT (chat_id) DO UPDATE SET
+                    previous_titles = CASE
+                        WHEN chats.title != excluded.title
+                        THEN json_insert(chats.previous_titles, '$[#]', chats.title)
+                        ELSE chats.previous_titles
+                    END,
+                    title = excluded.title,
+                    username = excluded.username,
+                    kind = excluded.kind,
+                    last_synced_at = excluded.last_synced_at,
+                    approx_message_count = excluded.approx_message_count,
+                    is_forum = excluded.is_forum
+                "#,
+                params![
+                    chat.id,
+                    chat.title.as_str(),
+                    chat.username.as_deref(),
+                    chat.kind.as_str(),
+                    now,
+                    chat.approx_message_count,
+                    chat.is_forum as i64
+                ],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        }
+        tx.commit()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        Ok(())
+    }
 
-#[async_trait::async_trait]
-impl AnalysisLogPort for SqliteRepo {
-    async fn get_unanalyzed_weeks(&self, chat_id: i64) -> Result<Vec<WeekGroup>, DomainError> {
-        let conn = self
-            .db
-            .connect()
+    async fn save_forum_topics(
+        &self,
+        chat_id: i64,
+        topics: &[ForumTopic],
+    ) -> Result<(), DomainError> {
+        let conn = self.connection()?;
+        let tx = conn
+            .transaction()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        tx.execute(
+            "DELETE FROM forum_topics WHERE chat_id = ?1",
+            params![chat_id],
+        )
+        .await
+        .map_err(|e| DomainError::Repo(e.to_string()))?;
+        for topic in topics {
+            tx.execute(
+                "INSERT INTO forum_topics (chat_id, topic_id, title) VALUES (?1, ?2, ?3)",
+                params![chat_id, topic.id, topic.title.as_str()],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        }
+        tx.commit()
+            .await
             .map_err(|e| DomainError::Repo(e.to_string()))?;
+        Ok(())
+    }
 
-        // Find weeks with non-empty messages that haven't been analyzed yet.
-        // Uses strftime with 'unixepoch' since date is stored as Unix timestamp.
-        let mut rows = conn
-            .query(
+    async fn save_events(&self, events: &[ChatEvent]) -> Result<(), DomainError> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        let conn = self.connection()?;
+        let tx = conn
+            .transaction()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        for e in events {
+            tx.execute(
                 r#"
-                SELECT DISTINCT strftime('%Y-%W', date, 'unixepoch') as week_group
-                FROM messages
-                WHERE chat_id = ?1
-                  AND text != ''
-                  AND text NOT LIKE '%joined the group%'
-                  AND text NOT LIKE '%left the group%'
-                  AND strftime('%Y-%W', date, 'unixepoch') NOT IN (
-                      SELECT week_group FROM analysis_log WHERE chat_id = ?1
-                  )
-                ORDER BY week_group ASC
+                INSERT INTO chat_events (chat_id, id, date, kind, actor_id, payload)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                ON CONFLICT (chat_id, id) DO UPDATE SET
+                    date = excluded.date,
+                    kind = excluded.kind,
+                    actor_id = excluded.actor_id,
+                    payload = excluded.payload
                 "#,
-                params![chat_id],
+                params![
+                    e.chat_id,
+                    e.id,
+                    e.date,
+                    e.kind.as_str(),
+                    e.actor_id,
+                    e.payload.as_deref()
+                ],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+            tx.execute(
+                "UPDATE messages SET is_service = 1 WHERE chat_id = ?1 AND id = ?2",
+                params![e.chat_id, e.id],
             )
             .await
             .map_err(|e| DomainError::Repo(e.to_string()))?;
+        }
+        tx.commit()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        Ok(())
+    }
 
-        let mut weeks = Vec::new();
+    async fn get_events(&self, chat_id: i64) -> Result<Vec<ChatEvent>, DomainError> {
+        let conn = self.connection()?;
+        let mut rows = conn
+            .query(
+                "SELECT id, date, kind, actor_id, payload FROM chat_events WHERE chat_id = ?1 ORDER BY id",
+                params![chat_id],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        let mut events = Vec::new();
         while let Some(row) = rows
             .next()
             .await
             .map_err(|e| DomainError::Repo(e.to_string()))?
         {
-            let week_str: String = row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?;
-            weeks.push(WeekGroup::new(week_str));
+            let kind: String = row.get(2).map_err(|e| DomainError::Repo(e.to_string()))?;
+            events.push(ChatEvent {
+                id: row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?,
+                chat_id,
+                date: row.get(1).map_err(|e| DomainError::Repo(e.to_string()))?,
+                kind: ChatEventKind::from_name(&kind),
+                actor_id: row.get(3).map_err(|e| DomainError::Repo(e.to_string()))?,
+                payload: row.get(4).map_err(|e| DomainError::Repo(e.to_string()))?,
+            });
         }
-
-        Ok(weeks)
+        Ok(events)
     }
 
-    async fn get_messages_by_week(
-        &self,
-        chat_id: i64,
-    ) -> Result<Vec<(WeekGroup, Vec<Message>)>, DomainError> {
-        let conn = self
-            .db
-            .connect()
-            .map_err(|e| DomainError::Repo(e.to_string()))?;
-
-        // Fetch all messages with week grouping, filtering out empty/service messages.
+    async fn get_known_chats(&self) -> Result<Vec<Chat>, DomainError> {
+        let conn = self.connection()?;
         let mut rows = conn
             .query(
-                r#"
-                SELECT
-                    strftime('%Y-%W', date, 'unixepoch') as week_group,
-                    chat_id, id, date, text, media_json, from_user_id, reply_to_msg_id, history_json
-                FROM messages
-                WHERE chat_id = ?1
-                  AND text != ''
-                  AND text NOT LIKE '%joined the group%'
-                  AND text NOT LIKE '%left the group%'
-                ORDER BY week_group ASC, date ASC
-                "#,
-                params![chat_id],
+                "SELECT chat_id, title, username, kind, approx_message_count, is_forum FROM chats ORDER BY title COLLATE NOCASE, chat_id",
+                (),
             )
             .await
             .map_err(|e| DomainError::Repo(e.to_string()))?;
-
-        // Group messages by week using a HashMap, preserving order via insertion.
-        let mut week_map: HashMap<String, Vec<Message>> = HashMap::new();
-        let mut week_order: Vec<String> = Vec::new();
-
+        let mut chats = Vec::new();
         while let Some(row) = rows
             .next()
             .await
             .map_err(|e| DomainError::Repo(e.to_string()))?
         {
-            let week_str: String = row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?;
-            let msg_chat_id: i64 = row.get(1).map_err(|e| DomainError::Repo(e.to_string()))?;
-            let id: i32 = row.get(2).map_err(|e| DomainError::Repo(e.to_string()))?;
-            let date: i64 = row.get(3).map_err(|e| DomainError::Repo(e.to_string()))?;
-            let text: String = row.get::<String>(4).unwrap_or_default();
-            let media_json: Option<String> = row.get(5).ok();
-            let from_user_id: Option<i64> = row.get(6).ok();
-            let reply_to_msg_id: Option<i32> = row.get(7).ok();
-            let edit_history = Self::json_to_edit_history(row.get::<String>(8).ok().as_deref());
-
-            let message = Message {
-                id,
-                chat_id: msg_chat_id,
-                date,
-                text,
-                media: Self::json_to_media(media_json.as_deref()),
-                from_user_id,
-                reply_to_msg_id,
-                edit_history,
-            };
-
-            if !week_map.contains_key(&week_str) {
-                week_order.push(week_str.clone());
-            }
-            week_map.entry(week_str).or_default().push(message);
+            chats.push(Chat {
+                id: row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?,
+                title: row.get(1).map_err(|e| DomainError::Repo(e.to_string()))?,
+                username: row.get::<String>(2).ok(),
+                kind: ChatType::from_name(
+                    &row.get::<String>(3)
+                        .map_err(|e| DomainError::Repo(e.to_string()))?,
+                ),
+                approx_message_count: row.get::<i32>(4).ok(),
+                is_forum: row.get::<i64>(5).unwrap_or(0) != 0,
+                last_message_date: None,
+            });
         }
+        Ok(chats)
+    }
 
-        // Convert to Vec<(WeekGroup, Vec<Message>)> preserving chronological order.
-        let result: Vec<(WeekGroup, Vec<Message>)> = week_order
-            .into_iter()
-            .filter_map(|week| {
-                week_map
-                    .remove(&week)
-                    .map(|messages| (WeekGroup::new(week), messages))
-            })
-            .collect();
+    async fn get_chat_stats(&self, chat_id: i64) -> Result<ChatStats, DomainError> {
+        let mut stats = self.collect_chat_stats(Some(chat_id)).await?;
+        Ok(stats.pop().unwrap_or(ChatStats {
+            chat_id,
+            ..Default::default()
+        }))
+    }
 
-        Ok(result)
+    async fn get_global_stats(&self) -> Result<ArchiveStats, DomainError> {
+        let chats = self.collect_chat_stats(None).await?;
+        Ok(ArchiveStats {
+            chats,
+            db_size_bytes: self.db_size_bytes(),
+        })
     }
 
-    async fn save_analysis(&self, result: &AnalysisResult) -> Result<(), DomainError> {
-        let conn = self
-            .db
-            .connect()
+    async fn delete_chat_data(&self, chat_id: i64) -> Result<ChatPurge, DomainError> {
+        let conn = self.connection()?;
+        let tx = conn
+            .transaction()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        let mut purge = ChatPurge::default();
+        let mut rows = tx
+            .query(
+                "SELECT rel_path FROM media_files WHERE chat_id = ?1 ORDER BY message_id",
+                params![chat_id],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            purge
+                .media_paths
+                .push(row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?);
+        }
+        drop(rows);
+        for table in PURGE_TABLES {
+            let deleted = tx
+                .execute(
+                    &format!("DELETE FROM {} WHERE chat_id = ?1", table),
+                    params![chat_id],
+                )
+                .await
+                .map_err(|e| DomainError::Repo(e.to_string()))?;
+            if deleted > 0 {
+                purge.rows.push((table, deleted));
+            }
+        }
+        tx.commit()
+            .await
             .map_err(|e| DomainError::Repo(e.to_string()))?;
-
-        let result_json = serde_json::to_string(result)
-            .map_err(|e| DomainError::Repo(format!("Failed to serialize AnalysisResult: {}", e)))?;
-
-        conn.execute(
-            r#"
-            INSERT INTO analysis_log (chat_id, week_group, analyzed_at, summary, result_json)
-            VALUES (?1, ?2, ?3, ?4, ?5)
-            ON CONFLICT (chat_id, week_group) DO UPDATE SET
-                analyzed_at = excluded.analyzed_at,
-                summary = excluded.summary,
-                result_json = excluded.result_json
-            "#,
-            params![
-                result.chat_id,
-                result.week_group.as_str(),
-                result.analyzed_at,
-                result.summary.as_str(),
-                result_json.as_str()
-            ],
-        )
-        .await
-        .map_err(|e| DomainError::Repo(e.to_string()))?;
-
         info!(
-            chat_id = result.chat_id,
-            week_group = %result.week_group,
-            "saved analysis result"
+            chat_id,
+            rows = purge.total_rows(),
+            media = purge.media_paths.len(),
+            "chat data deleted"
         );
-
-        Ok(())
+        Ok(purge)
     }
+}
 
-    async fn get_analysis(
-        &self,
-        chat_id: i64,
-        week_group: &WeekGroup,
-    ) -> Result<Option<AnalysisResult>, DomainError> {
+/// Audit §6.2: Persistent entity registry implementation.
+/// Enables fast InputPeer resolution without re-iterating dialogs.
+#[async_trait::async_trait]
+impl EntityRegistry for SqliteRepo {
+    async fn get_access_hash(&self, peer_id: i64) -> Result<Option<i64>, DomainError> {
         let conn = self
             .db
             .connect()
             .map_err(|e| DomainError::Repo(e.to_string()))?;
-
         let mut rows = conn
             .query(
-                "SELECT result_json FROM analysis_log WHERE chat_id = ?1 AND week_group = ?2",
-                params![chat_id, week_group.as_str()],
+                "SELECT access_hash FROM entity_registry WHERE peer_id = ?1",
+                params![peer_id],
             )
             .await
             .map_err(|e| DomainError::Repo(e.to_string()))?;
@@ -623,264 +2193,3216 @@ impl AnalysisLogPort for SqliteRepo {
             .await
             .map_err(|e| DomainError::Repo(e.to_string()))?
         {
-            let json_str: String = row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?;
-            let result: AnalysisResult = serde_json::from_str(&json_str).map_err(|e| {
-                DomainError::Repo(format!("Failed to deserialize AnalysisResult: {}", e))
-            })?;
-            Ok(Some(result))
+            let access_hash: i64 = row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?;
+            Ok(Some(access_hash))
         } else {
             Ok(None)
         }
     }
-}
-
-// ─────────────────────────────────────────────────────────────────────────────
-// Unit Tests
-// ─────────────────────────────────────────────────────────────────────────────
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use libsql::params;
-
-    /// Helper: Create an in-memory database with schema for testing.
-    async fn setup_test_db() -> libsql::Connection {
-        let db = libsql::Builder::new_local(":memory:")
+    async fn get_entity(&self, peer_id: i64) -> Result<Option<(i64, String)>, DomainError> {
+        let conn = self
+            .db
+            .connect()
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        let mut rows = conn
+            .query(
+                "SELECT access_hash, peer_type FROM entity_registry WHERE peer_id = ?1",
+                params![peer_id],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+
+        match rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            Some(row) => {
+                let access_hash: i64 = row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?;
+                let peer_type: String = row.get(1).map_err(|e| DomainError::Repo(e.to_string()))?;
+                Ok(Some((access_hash, peer_type)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn save_entity(
+        &self,
+        peer_id: i64,
+        access_hash: i64,
+        peer_type: &str,
+        username: Option<&str>,
+    ) -> Result<(), DomainError> {
+        let conn = self
+            .db
+            .connect()
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as i64;
+
+        conn.execute(
+            r#"
+            INSERT INTO entity_registry (peer_id, access_hash, peer_type, username, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT (peer_id) DO UPDATE SET
+                access_hash = excluded.access_hash,
+                peer_type = excluded.peer_type,
+                username = excluded.username,
+                updated_at = excluded.updated_at
+            "#,
+            params![peer_id, access_hash, peer_type, username, now],
+        )
+        .await
+        .map_err(|e| DomainError::Repo(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn get_username(&self, peer_id: i64) -> Result<Option<String>, DomainError> {
+        let conn = self
+            .db
+            .connect()
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        let mut rows = conn
+            .query(
+                "SELECT username FROM entity_registry WHERE peer_id = ?1",
+                params![peer_id],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+
+        match rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            Some(row) => Ok(row.get::<Option<String>>(0).ok().flatten()),
+            None => Ok(None),
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Watcher keyword rules: WatchRulePort implementation
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[async_trait::async_trait]
+impl WatchRulePort for SqliteRepo {
+    async fn list_watch_rules(&self) -> Result<Vec<WatchRule>, DomainError> {
+        let conn = self.connection()?;
+        let mut rows = conn
+            .query(
+                "SELECT id, chat_id, kind, pattern, is_regex, enabled FROM watch_rules ORDER BY chat_id != 0, chat_id, id",
+                (),
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        let mut rules = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            rules.push(WatchRule {
+                id: row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?,
+                chat_id: row.get(1).map_err(|e| DomainError::Repo(e.to_string()))?,
+                kind: WatchRuleKind::from_name(&row.get::<String>(2).unwrap_or_default()),
+                pattern: row.get(3).map_err(|e| DomainError::Repo(e.to_string()))?,
+                is_regex: row.get::<i64>(4).unwrap_or(0) != 0,
+                enabled: row.get::<i64>(5).unwrap_or(1) != 0,
+            });
+        }
+        Ok(rules)
+    }
+
+    async fn add_watch_rule(
+        &self,
+        chat_id: i64,
+        pattern: &str,
+        is_regex: bool,
+    ) -> Result<i64, DomainError> {
+        let conn = self.connection()?;
+        conn.execute(
+            r#"
+            INSERT INTO watch_rules (chat_id, kind, pattern, is_regex, enabled) VALUES (?1, 'keyword', ?2, ?3, 1)
+            ON CONFLICT (chat_id, kind, pattern, is_regex) DO UPDATE SET enabled = 1
+            "#,
+            params![chat_id, pattern, is_regex as i64],
+        )
+        .await
+        .map_err(|e| DomainError::Repo(e.to_string()))?;
+        watch_rule_id(&conn, chat_id, WatchRuleKind::Keyword, pattern, is_regex).await
+    }
+
+    async fn remove_watch_rule(&self, id: i64) -> Result<bool, DomainError> {
+        let conn = self.connection()?;
+        let removed = conn
+            .execute("DELETE FROM watch_rules WHERE id = ?1", params![id])
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        Ok(removed > 0)
+    }
+
+    async fn set_watch_rule_enabled(&self, id: i64, enabled: bool) -> Result<(), DomainError> {
+        let conn = self.connection()?;
+        conn.execute(
+            "UPDATE watch_rules SET enabled = ?2 WHERE id = ?1",
+            params![id, enabled as i64],
+        )
+        .await
+        .map_err(|e| DomainError::Repo(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn set_watch_trigger(
+        &self,
+        chat_id: i64,
+        kind: WatchRuleKind,
+        enabled: bool,
+    ) -> Result<i64, DomainError> {
+        let conn = self.connection()?;
+        conn.execute(
+            r#"
+            INSERT INTO watch_rules (chat_id, kind, pattern, is_regex, enabled) VALUES (?1, ?2, '', 0, ?3)
+            ON CONFLICT (chat_id, kind, pattern, is_regex) DO UPDATE SET enabled = excluded.enabled
+            "#,
+            params![chat_id, kind.as_str(), enabled as i64],
+        )
+        .await
+        .map_err(|e| DomainError::Repo(e.to_string()))?;
+        watch_rule_id(&conn, chat_id, kind, "", false).await
+    }
+}
+
+/// Id of the watch rule with this key, right after it was upserted.
+async fn watch_rule_id(
+    conn: &libsql::Connection,
+    chat_id: i64,
+    kind: WatchRuleKind,
+    pattern: &str,
+    is_regex: bool,
+) -> Result<i64, DomainError> {
+    let mut rows = conn
+        .query(
+            "SELECT id FROM watch_rules WHERE chat_id = ?1 AND kind = ?2 AND pattern = ?3 AND is_regex = ?4",
+            params![chat_id, kind.as_str(), pattern, is_regex as i64],
+        )
+        .await
+        .map_err(|e| DomainError::Repo(e.to_string()))?;
+    match rows
+        .next()
+        .await
+        .map_err(|e| DomainError::Repo(e.to_string()))?
+    {
+        Some(row) => row.get(0).map_err(|e| DomainError::Repo(e.to_string())),
+        None => Err(DomainError::Repo("watch rule vanished after insert".into())),
+    }
+}
+
+#[async_trait::async_trait]
+impl SettingsPort for SqliteRepo {
+    async fn get_setting(&self, key: &str) -> Result<Option<String>, DomainError> {
+        let conn = self.connection()?;
+        let mut rows = conn
+            .query("SELECT value FROM settings WHERE key = ?1", params![key])
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        match rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            Some(row) => Ok(Some(
+                row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    async fn set_setting(&self, key: &str, value: Option<&str>) -> Result<(), DomainError> {
+        let conn = self.connection()?;
+        match value {
+            Some(value) => conn
+                .execute(
+                    "INSERT INTO settings (key, value) VALUES (?1, ?2) ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+                    params![key, value],
+                )
+                .await,
+            None => {
+                conn.execute("DELETE FROM settings WHERE key = ?1", params![key])
+                    .await
+            }
+        }
+        .map_err(|e| DomainError::Repo(e.to_string()))?;
+        Ok(())
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Media index: MediaIndexPort implementation
+// ─────────────────────────────────────────────────────────────────────────────
+
+impl SqliteRepo {
+    /// One keyset page of the media index (see [`MediaIndexPort::list_media_files`]);
+    /// `unuploaded_only` keeps entries without a remote key.
+    async fn media_files_page(
+        &self,
+        chat_id: Option<i64>,
+        status: Option<MediaStatus>,
+        after: Option<(i64, i32)>,
+        limit: u32,
+        unuploaded_only: bool,
+    ) -> Result<Vec<MediaFile>, DomainError> {
+        let conn = self
+            .db
+            .connect()
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        // NULL parameters disable the corresponding filter; the row-value comparison is the keyset cursor.
+        let (after_chat, after_msg) = match after {
+            Some((c, m)) => (Some(c), Some(m)),
+            None => (None, None),
+        };
+        let mut rows = conn
+            .query(
+                r#"
+                SELECT f.chat_id, f.message_id, f.media_type, f.rel_path, f.size_bytes, f.sha256, f.status, m.date, f.remote_key, f.expected_size
+                FROM media_files f
+                LEFT JOIN messages m ON m.chat_id = f.chat_id AND m.id = f.message_id
+                WHERE (?1 IS NULL OR f.chat_id = ?1)
+                  AND (?2 IS NULL OR f.status = ?2)
+                  AND (?3 IS NULL OR (f.chat_id, f.message_id) > (?3, ?4))
+                  AND (?6 IS NULL OR f.remote_key IS NULL)
+                ORDER BY f.chat_id ASC, f.message_id ASC
+                LIMIT ?5
+                "#,
+                params![
+                    chat_id,
+                    status.map(|s| s.as_str()),
+                    after_chat,
+                    after_msg,
+                    limit as i64,
+                    unuploaded_only.then_some(1)
+                ],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+
+        let mut out = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            let media_type: String = row.get(2).map_err(|e| DomainError::Repo(e.to_string()))?;
+            let size_bytes: Option<i64> =
+                row.get(4).map_err(|e| DomainError::Repo(e.to_string()))?;
+            let status: String = row.get(6).map_err(|e| DomainError::Repo(e.to_string()))?;
+            let expected_size: Option<i64> =
+                row.get(9).map_err(|e| DomainError::Repo(e.to_string()))?;
+            out.push(MediaFile {
+                chat_id: row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?,
+                message_id: row.get(1).map_err(|e| DomainError::Repo(e.to_string()))?,
+                media_type: MediaType::from_name(&media_type),
+                rel_path: row.get(3).map_err(|e| DomainError::Repo(e.to_string()))?,
+                size_bytes: size_bytes.map(|n| n as u64),
+                expected_size: expected_size.map(|n| n as u64),
+                sha256: row.get(5).map_err(|e| DomainError::Repo(e.to_string()))?,
+                status: MediaStatus::from_name(&status),
+                message_date: row.get(7).map_err(|e| DomainError::Repo(e.to_string()))?,
+                remote_key: row.get(8).map_err(|e| DomainError::Repo(e.to_string()))?,
+            });
+        }
+        Ok(out)
+    }
+
+    /// Stored chat_settings rows of `chat_id` (or all), by chat id.
+    async fn chat_settings_rows(
+        &self,
+        chat_id: Option<i64>,
+    ) -> Result<Vec<ChatSettings>, DomainError> {
+        let conn = self.connection()?;
+        let mut rows = conn
+            .query(
+                r#"
+                SELECT chat_id, include_media, media_types, batch_limit, sync_enabled
+                FROM chat_settings
+                WHERE ?1 IS NULL OR chat_id = ?1
+                ORDER BY chat_id
+                "#,
+                params![chat_id],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        let mut out = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            let include_media: Option<i64> =
+                row.get(1).map_err(|e| DomainError::Repo(e.to_string()))?;
+            let media_types: Option<String> =
+                row.get(2).map_err(|e| DomainError::Repo(e.to_string()))?;
+            let batch_limit: Option<i64> =
+                row.get(3).map_err(|e| DomainError::Repo(e.to_string()))?;
+            let sync_enabled: i64 = row.get(4).map_err(|e| DomainError::Repo(e.to_string()))?;
+            out.push(ChatSettings {
+                chat_id: row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?,
+                include_media: include_media.map(|n| n != 0),
+                media_types: media_types.map(|s| {
+                    s.split(',')
+                        .filter(|name| !name.is_empty())
+                        .map(MediaType::from_name)
+                        .collect()
+                }),
+                batch_limit: batch_limit.map(|n| n as i32),
+                sync_enabled: sync_enabled != 0,
+            });
+        }
+        Ok(out)
+    }
+}
+
+#[async_trait::async_trait]
+impl MediaIndexPort for SqliteRepo {
+    async fn upsert_media_file(&self, file: &MediaFile) -> Result<(), DomainError> {
+        let conn = self
+            .db
+            .connect()
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as i64;
+
+        conn.execute(
+            r#"
+            INSERT INTO media_files (chat_id, message_id, media_type, rel_path, size_bytes, sha256, status, updated_at, remote_key, expected_size)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+            ON CONFLICT (chat_id, message_id) DO UPDATE SET
+                media_type = excluded.media_type,
+                rel_path = excluded.rel_path,
+                size_bytes = excluded.size_bytes,
+                expected_size = COALESCE(excluded.expected_size, media_files.expected_size),
+                sha256 = COALESCE(excluded.sha256, media_files.sha256),
+                status = excluded.status,
+                updated_at = excluded.updated_at,
+                remote_key = COALESCE(excluded.remote_key, media_files.remote_key)
+            "#,
+            params![
+                file.chat_id,
+                file.message_id,
+                file.media_type.as_str(),
+                file.rel_path.as_str(),
+                file.size_bytes.map(|n| n as i64),
+                file.sha256.as_deref(),
+                file.status.as_str(),
+                now,
+                file.remote_key.as_deref(),
+                file.expected_size.map(|n| n as i64)
+            ],
+        )
+        .await
+        .map_err(|e| DomainError::Repo(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn list_media_files(
+        &self,
+        chat_id: Option<i64>,
+        status: Option<MediaStatus>,
+        after: Option<(i64, i32)>,
+        limit: u32,
+    ) -> Result<Vec<MediaFile>, DomainError> {
+        self.media_files_page(chat_id, status, after, limit, false)
+            .await
+    }
+
+    async fn list_unuploaded_media(
+        &self,
+        after: Option<(i64, i32)>,
+        limit: u32,
+    ) -> Result<Vec<MediaFile>, DomainError> {
+        self.media_files_page(None, Some(MediaStatus::Done), after, limit, true)
+            .await
+    }
+
+    async fn list_chat_media(
+        &self,
+        chat_id: i64,
+        after: Option<i32>,
+        limit: u32,
+    ) -> Result<Vec<(MediaReference, Option<MediaFile>)>, DomainError> {
+        let conn = self
+            .db
+            .connect()
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        let mut rows = conn
+            .query(
+                r#"
+                SELECT m.id, m.media_json, m.date, f.media_type, f.rel_path, f.size_bytes, f.sha256, f.status, f.remote_key, f.expected_size
+                FROM messages m
+                LEFT JOIN media_files f ON f.chat_id = m.chat_id AND f.message_id = m.id
+                WHERE m.chat_id = ?1
+                  AND m.media_json IS NOT NULL
+                  AND (?2 IS NULL OR m.id > ?2)
+                ORDER BY m.id ASC
+                LIMIT ?3
+                "#,
+                params![chat_id, after, limit as i64],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+
+        let mut out = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            let message_id: i32 = row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?;
+            let media_json: Option<String> = row.get(1).ok();
+            // Keep the key even without a usable reference so paging advances.
+            let reference = self
+                .json_to_media(chat_id, message_id, media_json.as_deref())
+                .unwrap_or(MediaReference {
+                    message_id,
+                    chat_id,
+                    media_type: MediaType::Other,
+                    opaque_ref: String::new(),
+                    size_bytes: None,
+                    filename: None,
+                    mime_type: None,
+                });
+            let media_type: Option<String> =
+                row.get(3).map_err(|e| DomainError::Repo(e.to_string()))?;
+            let file = match media_type {
+                Some(media_type) => {
+                    let size_bytes: Option<i64> =
+                        row.get(5).map_err(|e| DomainError::Repo(e.to_string()))?;
+                    let status: String =
+                        row.get(7).map_err(|e| DomainError::Repo(e.to_string()))?;
+                    let expected_size: Option<i64> =
+                        row.get(9).map_err(|e| DomainError::Repo(e.to_string()))?;
+                    Some(MediaFile {
+                        chat_id,
+                        message_id,
+                        media_type: MediaType::from_name(&media_type),
+                        rel_path: row.get(4).map_err(|e| DomainError::Repo(e.to_string()))?,
+                        size_bytes: size_bytes.map(|n| n as u64),
+                        expected_size: expected_size.map(|n| n as u64),
+                        sha256: row.get(6).map_err(|e| DomainError::Repo(e.to_string()))?,
+                        status: MediaStatus::from_name(&status),
+                        message_date: row.get(2).map_err(|e| DomainError::Repo(e.to_string()))?,
+                        remote_key: row.get(8).map_err(|e| DomainError::Repo(e.to_string()))?,
+                    })
+                }
+                None => None,
+            };
+            out.push((reference, file));
+        }
+        Ok(out)
+    }
+
+    async fn find_stale_pending_media(
+        &self,
+        updated_before: i64,
+        after: Option<(i64, i32)>,
+        limit: u32,
+    ) -> Result<Vec<MediaReference>, DomainError> {
+        let conn = self
+            .db
+            .connect()
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        let (after_chat, after_msg) = match after {
+            Some((c, m)) => (Some(c), Some(m)),
+            None => (None, None),
+        };
+        let mut rows = conn
+            .query(
+                r#"
+                SELECT f.chat_id, f.message_id, f.media_type, m.media_json
+                FROM media_files f
+                JOIN messages m ON m.chat_id = f.chat_id AND m.id = f.message_id
+                WHERE f.status = 'pending'
+                  AND f.updated_at <= ?1
+                  AND (?2 IS NULL OR (f.chat_id, f.message_id) > (?2, ?3))
+                ORDER BY f.chat_id ASC, f.message_id ASC
+                LIMIT ?4
+                "#,
+                params![updated_before, after_chat, after_msg, limit as i64],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+
+        let mut out = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            let chat_id: i64 = row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?;
+            let message_id: i32 = row.get(1).map_err(|e| DomainError::Repo(e.to_string()))?;
+            let media_type: String = row.get(2).map_err(|e| DomainError::Repo(e.to_string()))?;
+            let media_json: Option<String> = row.get(3).ok();
+            // Keep the key even without a usable reference so paging advances.
+            let stored = self.json_to_media(chat_id, message_id, media_json.as_deref());
+            out.push(MediaReference {
+                message_id,
+                chat_id,
+                media_type: MediaType::from_name(&media_type),
+                size_bytes: stored.as_ref().and_then(|m| m.size_bytes),
+                filename: None,
+                mime_type: None,
+                opaque_ref: stored.map(|m| m.opaque_ref).unwrap_or_default(),
+            });
+        }
+        Ok(out)
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Media queue: MediaQueuePort implementation
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[async_trait::async_trait]
+impl MediaQueuePort for SqliteRepo {
+    async fn enqueue_media(&self, refs: &[MediaReference]) -> Result<u64, DomainError> {
+        if refs.is_empty() {
+            return Ok(0);
+        }
+        let conn = self.connection()?;
+        let now = chrono::Utc::now().timestamp();
+        let tx = conn
+            .transaction()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        let mut added = 0;
+        for media_ref in refs {
+            added += tx
+                .execute(
+                    r#"
+                    INSERT OR IGNORE INTO media_queue (chat_id, message_id, media_type, status, updated_at)
+                    VALUES (?1, ?2, ?3, 'queued', ?4)
+                    "#,
+                    params![
+                        media_ref.chat_id,
+                        media_ref.message_id,
+                        media_ref.media_type.as_str(),
+                        now
+                    ],
+                )
+                .await
+                .map_err(|e| DomainError::Repo(e.to_string()))?;
+        }
+        tx.commit()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        Ok(added)
+    }
+
+    async fn requeue_media(&self, refs: &[MediaReference]) -> Result<u64, DomainError> {
+        if refs.is_empty() {
+            return Ok(0);
+        }
+        let conn = self.connection()?;
+        let now = chrono::Utc::now().timestamp();
+        let tx = conn
+            .transaction()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        let mut queued = 0;
+        for media_ref in refs {
+            queued += tx
+                .execute(
+                    r#"
+                    INSERT INTO media_queue (chat_id, message_id, media_type, status, updated_at)
+                    VALUES (?1, ?2, ?3, 'queued', ?4)
+                    ON CONFLICT (chat_id, message_id) DO UPDATE SET
+                        status = 'queued',
+                        attempts = 0,
+                        last_error = NULL,
+                        updated_at = excluded.updated_at
+                    WHERE media_queue.status = 'done'
+                    "#,
+                    params![
+                        media_ref.chat_id,
+                        media_ref.message_id,
+                        media_ref.media_type.as_str(),
+                        now
+                    ],
+                )
+                .await
+                .map_err(|e| DomainError::Repo(e.to_string()))?;
+        }
+        tx.commit()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        Ok(queued)
+    }
+
+    async fn claim_media(&self, media_ref: &MediaReference) -> Result<bool, DomainError> {
+        let conn = self.connection()?;
+        // DO UPDATE ... WHERE false changes nothing: 0 rows = in progress or failed (backoff).
+        // Done rows are claimed again; the worker skips files that exist.
+        let changed = conn
+            .execute(
+                r#"
+                INSERT INTO media_queue (chat_id, message_id, media_type, status, updated_at)
+                VALUES (?1, ?2, ?3, 'in_progress', ?4)
+                ON CONFLICT (chat_id, message_id) DO UPDATE SET
+                    status = 'in_progress',
+                    updated_at = excluded.updated_at
+                WHERE media_queue.status IN ('queued', 'done')
+                "#,
+                params![
+                    media_ref.chat_id,
+                    media_ref.message_id,
+                    media_ref.media_type.as_str(),
+                    chrono::Utc::now().timestamp()
+                ],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        Ok(changed > 0)
+    }
+
+    async fn claim_due_media(
+        &self,
+        now: i64,
+        base_backoff_secs: i64,
+        max_attempts: u32,
+        limit: u32,
+    ) -> Result<Vec<MediaReference>, DomainError> {
+        let conn = self.connection()?;
+        let tx = conn
+            .transaction()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        let mut rows = tx
+            .query(
+                r#"
+                SELECT q.chat_id, q.message_id, q.media_type, m.media_json
+                FROM media_queue q
+                LEFT JOIN messages m ON m.chat_id = q.chat_id AND m.id = q.message_id
+                WHERE q.status = 'queued'
+                   OR (q.status = 'failed' AND q.attempts < ?3
+                       AND q.updated_at + (?2 << MIN(MAX(q.attempts - 1, 0), 20)) <= ?1)
+                ORDER BY q.status = 'failed', q.updated_at, q.chat_id, q.message_id
+                LIMIT ?4
+                "#,
+                params![now, base_backoff_secs, max_attempts as i64, limit as i64],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+
+        let mut claimed = Vec::new();
+        let mut unusable = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            let chat_id: i64 = row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?;
+            let message_id: i32 = row.get(1).map_err(|e| DomainError::Repo(e.to_string()))?;
+            let media_type: String = row.get(2).map_err(|e| DomainError::Repo(e.to_string()))?;
+            let media_json: Option<String> = row.get(3).ok();
+            match self.json_to_media(chat_id, message_id, media_json.as_deref()) {
+                Some(m) if !m.opaque_ref.is_empty() => claimed.push(MediaReference {
+                    message_id,
+                    chat_id,
+                    media_type: MediaType::from_name(&media_type),
+                    opaque_ref: m.opaque_ref,
+                    size_bytes: m.size_bytes,
+                    filename: None,
+                    mime_type: None,
+                }),
+                _ => unusable.push((chat_id, message_id)),
+            }
+        }
+        drop(rows);
+
+        for media_ref in &claimed {
+            tx.execute(
+                "UPDATE media_queue SET status = 'in_progress', updated_at = ?3 WHERE chat_id = ?1 AND message_id = ?2",
+                params![media_ref.chat_id, media_ref.message_id, now],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        }
+        for (chat_id, message_id) in unusable {
+            tx.execute(
+                r#"
+                UPDATE media_queue SET status = 'failed', attempts = MAX(attempts, ?3),
+                    last_error = 'message has no media reference', updated_at = ?4
+                WHERE chat_id = ?1 AND message_id = ?2
+                "#,
+                params![chat_id, message_id, max_attempts as i64, now],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        }
+        tx.commit()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        Ok(claimed)
+    }
+
+    async fn finish_media(
+        &self,
+        chat_id: i64,
+        message_id: i32,
+        error: Option<&str>,
+    ) -> Result<(), DomainError> {
+        let conn = self.connection()?;
+        let now = chrono::Utc::now().timestamp();
+        match error {
+            None => conn
+                .execute(
+                    "UPDATE media_queue SET status = 'done', last_error = NULL, updated_at = ?3 WHERE chat_id = ?1 AND message_id = ?2",
+                    params![chat_id, message_id, now],
+                )
+                .await,
+            Some(error) => conn
+                .execute(
+                    "UPDATE media_queue SET status = 'failed', attempts = attempts + 1, last_error = ?3, updated_at = ?4 WHERE chat_id = ?1 AND message_id = ?2",
+                    params![chat_id, message_id, error, now],
+                )
+                .await,
+        }
+        .map_err(|e| DomainError::Repo(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn requeue_interrupted_media(&self) -> Result<u64, DomainError> {
+        let conn = self.connection()?;
+        conn.execute(
+            "UPDATE media_queue SET status = 'queued' WHERE status = 'in_progress'",
+            (),
+        )
+        .await
+        .map_err(|e| DomainError::Repo(e.to_string()))
+    }
+
+    async fn retry_failed_media(&self) -> Result<u64, DomainError> {
+        let conn = self.connection()?;
+        conn.execute(
+            "UPDATE media_queue SET status = 'queued', attempts = 0, updated_at = ?1 WHERE status = 'failed'",
+            params![chrono::Utc::now().timestamp()],
+        )
+        .await
+        .map_err(|e| DomainError::Repo(e.to_string()))
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Archive audit: ArchiveAuditPort implementation
+// ─────────────────────────────────────────────────────────────────────────────
+
+impl SqliteRepo {
+    /// Search of an encrypted archive: every message (of `chat_id`) is decrypted and must
+    /// contain all query words, case-insensitively. Newest first; no relevance ranking.
+    async fn search_sealed(
+        &self,
+        query: &str,
+        chat_id: Option<i64>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<SearchHit>, DomainError> {
+        let terms = search_terms(query);
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+        let conn = self.connection()?;
+        let mut rows = conn
+            .query(
+                &format!(
+                    r#"
+                SELECT {}, c.title
+                FROM messages m
+                LEFT JOIN users u ON u.user_id = m.from_user_id
+                LEFT JOIN chats c ON c.chat_id = m.chat_id
+                WHERE (?1 IS NULL OR m.chat_id = ?1) AND m.text != ''
+                ORDER BY m.date DESC, m.chat_id, m.id
+                "#,
+                    MESSAGE_COLUMNS
+                ),
+                params![chat_id],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(format!("search failed: {}", e)))?;
+        let mut hits = Vec::new();
+        let mut skipped = 0u32;
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            let message = self.row_to_message(&row)?;
+            if !matches_all(&message.text, &terms) {
+                continue;
+            }
+            if skipped < offset {
+                skipped += 1;
+                continue;
+            }
+            hits.push(SearchHit {
+                snippet: snippet(&message.text, &terms),
+                chat_title: row.get::<String>(MESSAGE_COLUMN_COUNT).ok(),
+                message,
+            });
+            if hits.len() as u32 >= limit {
+                break;
+            }
+        }
+        Ok(hits)
+    }
+
+    /// messages.db plus its WAL, in bytes.
+    fn db_size_bytes(&self) -> u64 {
+        let wal = self.db_path.with_extension("db-wal");
+        [&self.db_path, &wal]
+            .iter()
+            .filter_map(|p| std::fs::metadata(p).ok())
+            .map(|m| m.len())
+            .sum()
+    }
+
+    /// First column of every row a PRAGMA returns, as text.
+    async fn pragma_rows(conn: &libsql::Connection, sql: &str) -> Result<Vec<String>, DomainError> {
+        let mut rows = conn
+            .query(sql, ())
+            .await
+            .map_err(|e| DomainError::Repo(format!("{} failed: {}", sql, e)))?;
+        let mut values = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            values.push(match row.get_value(0) {
+                Ok(libsql::Value::Text(text)) => text,
+                Ok(libsql::Value::Integer(n)) => n.to_string(),
+                _ => String::new(),
+            });
+        }
+        Ok(values)
+    }
+
+    /// Copy the WAL into the database file and truncate it to zero bytes.
+    async fn checkpoint_wal(conn: &libsql::Connection) -> Result<(), DomainError> {
+        Self::pragma_rows(conn, "PRAGMA wal_checkpoint(TRUNCATE)").await?;
+        Ok(())
+    }
+
+    async fn has_table(conn: &libsql::Connection, name: &str) -> Result<bool, DomainError> {
+        let mut rows = conn
+            .query(
+                "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+                params![name],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        Ok(rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+            .is_some())
+    }
+
+    async fn has_column(
+        conn: &libsql::Connection,
+        table: &str,
+        column: &str,
+    ) -> Result<bool, DomainError> {
+        let mut rows = conn
+            .query(
+                "SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2",
+                params![table, column],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        Ok(rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+            .is_some())
+    }
+
+    async fn count_rows(conn: &libsql::Connection, sql: &str) -> Result<u64, DomainError> {
+        let mut rows = conn
+            .query(sql, ())
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        match rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            Some(row) => {
+                let n: i64 = row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?;
+                Ok(n as u64)
+            }
+            None => Ok(0),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ArchiveAuditPort for SqliteRepo {
+    async fn count_media_references(&self) -> Result<u64, DomainError> {
+        let conn = self
+            .db
+            .connect()
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        Self::count_rows(
+            &conn,
+            "SELECT COUNT(*) FROM messages WHERE media_json IS NOT NULL",
+        )
+        .await
+    }
+
+    async fn find_unindexed_media(
+        &self,
+        after: Option<(i64, i32)>,
+        limit: u32,
+    ) -> Result<Vec<MediaReference>, DomainError> {
+        let conn = self
+            .db
+            .connect()
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        let (after_chat, after_msg) = match after {
+            Some((c, m)) => (Some(c), Some(m)),
+            None => (None, None),
+        };
+        let mut rows = conn
+            .query(
+                r#"
+                SELECT m.chat_id, m.id, m.media_json
+                FROM messages m
+                LEFT JOIN media_files f ON f.chat_id = m.chat_id AND f.message_id = m.id
+                WHERE m.media_json IS NOT NULL
+                  AND f.chat_id IS NULL
+                  AND (?1 IS NULL OR (m.chat_id, m.id) > (?1, ?2))
+                ORDER BY m.chat_id ASC, m.id ASC
+                LIMIT ?3
+                "#,
+                params![after_chat, after_msg, limit as i64],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+
+        let mut out = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            let chat_id: i64 = row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?;
+            let message_id: i32 = row.get(1).map_err(|e| DomainError::Repo(e.to_string()))?;
+            let media_json: Option<String> = row.get(2).ok();
+            // Unparseable JSON is still an unindexed reference; keep the key so paging advances.
+            let (media_type, opaque_ref, size_bytes) =
+                match self.json_to_media(chat_id, message_id, media_json.as_deref()) {
+                    Some(m) => (m.media_type, m.opaque_ref, m.size_bytes),
+                    None => (MediaType::Other, String::new(), None),
+                };
+            out.push(MediaReference {
+                message_id,
+                chat_id,
+                media_type,
+                opaque_ref,
+                size_bytes,
+                filename: None,
+                mime_type: None,
+            });
+        }
+        Ok(out)
+    }
+
+    async fn max_message_ids(&self) -> Result<Vec<(i64, i32)>, DomainError> {
+        let conn = self
+            .db
+            .connect()
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        let mut rows = conn
+            .query(
+                "SELECT chat_id, MAX(id) FROM messages GROUP BY chat_id ORDER BY chat_id",
+                (),
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+
+        let mut out = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            out.push((
+                row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?,
+                row.get(1).map_err(|e| DomainError::Repo(e.to_string()))?,
+            ));
+        }
+        Ok(out)
+    }
+
+    async fn find_orphan_analyses(
+        &self,
+    ) -> Result<(u64, Vec<(i64, Granularity, PeriodGroup)>), DomainError> {
+        let conn = self
+            .db
+            .connect()
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        // Combined digests span several chats and have no messages of their own.
+        let total = Self::count_rows(
+            &conn,
+            &format!(
+                "SELECT COUNT(*) FROM analysis_log WHERE chat_id != {}",
+                COMBINED_CHAT_ID
+            ),
+        )
+        .await?;
+        // Same period keys as get_unanalyzed_weeks: strftime in UTC.
+        let mut rows = conn
+            .query(
+                &format!(
+                    r#"
+                SELECT a.chat_id, a.granularity, a.week_group
+                FROM analysis_log a
+                WHERE a.chat_id != {}
+                  AND NOT EXISTS (
+                    SELECT 1 FROM messages m
+                    WHERE m.chat_id = a.chat_id
+                      AND {} = a.week_group
+                )
+                ORDER BY a.chat_id, a.granularity, a.week_group
+                "#,
+                    COMBINED_CHAT_ID, PERIOD_KEY_EXPR
+                ),
+                (),
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+
+        let mut orphans = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            let chat_id: i64 = row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?;
+            let granularity: String = row.get(1).map_err(|e| DomainError::Repo(e.to_string()))?;
+            let period: String = row.get(2).map_err(|e| DomainError::Repo(e.to_string()))?;
+            orphans.push((
+                chat_id,
+                Granularity::from_name(&granularity).unwrap_or_default(),
+                PeriodGroup::new(period),
+            ));
+        }
+        Ok((total, orphans))
+    }
+
+    async fn fts_row_counts(&self) -> Result<Option<(u64, u64)>, DomainError> {
+        let conn = self
+            .db
+            .connect()
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        if !Self::has_table(&conn, FTS_TABLE).await? {
+            return Ok(None);
+        }
+        let messages = Self::count_rows(&conn, "SELECT COUNT(*) FROM messages").await?;
+        // External content: scanning the FTS table reads `messages`; `_docsize` holds one
+        // row per indexed document.
+        let indexed = Self::count_rows(
+            &conn,
+            &format!("SELECT COUNT(*) FROM {}_docsize", FTS_TABLE),
+        )
+        .await?;
+        Ok(Some((messages, indexed)))
+    }
+
+    async fn rebuild_fts(&self) -> Result<(), DomainError> {
+        let conn = self
+            .db
+            .connect()
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        if !Self::has_table(&conn, FTS_TABLE).await? {
+            return Ok(());
+        }
+        // FTS5 external-content tables repopulate themselves from `messages` on 'rebuild'.
+        conn.execute(
+            &format!("INSERT INTO {0} ({0}) VALUES ('rebuild')", FTS_TABLE),
+            (),
+        )
+        .await
+        .map_err(|e| DomainError::Repo(format!("FTS rebuild failed: {}", e)))?;
+        info!("full-text index rebuilt");
+        Ok(())
+    }
+
+    async fn maintenance(&self) -> Result<DatabaseMaintenance, DomainError> {
+        let size_before = self.db_size_bytes();
+        let conn = self.connection()?;
+        let integrity_errors: Vec<String> = Self::pragma_rows(&conn, "PRAGMA integrity_check")
+            .await?
+            .into_iter()
+            .filter(|line| line != "ok")
+            .collect();
+        Self::checkpoint_wal(&conn).await?;
+        // VACUUM rewrites every page; on a damaged file that can lose what is still readable.
+        let vacuumed = integrity_errors.is_empty();
+        if vacuumed {
+            conn.execute("VACUUM", ())
+                .await
+                .map_err(|e| DomainError::Repo(format!("VACUUM failed: {}", e)))?;
+            Self::checkpoint_wal(&conn).await?;
+        }
+        let report = DatabaseMaintenance {
+            integrity_errors,
+            vacuumed,
+            size_before,
+            size_after: self.db_size_bytes(),
+        };
+        info!(
+            integrity_errors = report.integrity_errors.len(),
+            size_before = report.size_before,
+            size_after = report.size_after,
+            "database maintenance finished"
+        );
+        Ok(report)
+    }
+
+    async fn snapshot(&self, dest: &Path) -> Result<u64, DomainError> {
+        let dest_str = dest
+            .to_str()
+            .ok_or_else(|| DomainError::Repo(format!("non-UTF-8 path {}", dest.display())))?;
+        // VACUUM INTO reads inside one transaction: WAL writers go on, the copy is consistent.
+        self.connection()?
+            .execute("VACUUM INTO ?1", params![dest_str])
+            .await
+            .map_err(|e| DomainError::Repo(format!("VACUUM INTO {} failed: {}", dest_str, e)))?;
+        let check = async {
+            let conn = libsql::Builder::new_local(dest)
+                .build()
+                .await
+                .map_err(|e| DomainError::Repo(e.to_string()))?
+                .connect()
+                .map_err(|e| DomainError::Repo(e.to_string()))?;
+            Self::pragma_rows(&conn, "PRAGMA integrity_check").await
+        };
+        let errors: Vec<String> = match check.await {
+            Ok(lines) => lines.into_iter().filter(|line| line != "ok").collect(),
+            Err(e) => vec![e.to_string()],
+        };
+        if !errors.is_empty() {
+            let _ = std::fs::remove_file(dest);
+            return Err(DomainError::Repo(format!(
+                "snapshot {} failed the integrity check: {}",
+                dest.display(),
+                errors.join("; ")
+            )));
+        }
+        std::fs::metadata(dest)
+            .map(|m| m.len())
+            .map_err(|e| DomainError::Repo(format!("stat {}: {}", dest.display(), e)))
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// AI Analysis: AnalysisLogPort implementation
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[async_trait::async_trait]
+impl AnalysisLogPort for SqliteRepo {
+    async fn get_unanalyzed_weeks(
+        &self,
+        chat_id: i64,
+        granularity: Granularity,
+    ) -> Result<Vec<PeriodGroup>, DomainError> {
+        let conn = self
+            .db
+            .connect()
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+
+        // Find periods with non-empty messages that haven't been analyzed yet.
+        // Uses strftime with 'unixepoch' since date is stored as Unix timestamp.
+        let mut rows = conn
+            .query(
+                r#"
+                SELECT DISTINCT strftime(?2, date, 'unixepoch') as week_group
+                FROM messages
+                WHERE chat_id = ?1
+                  AND text != ''
+                  AND is_service = 0
+                  AND strftime(?2, date, 'unixepoch') NOT IN (
+                      SELECT week_group FROM analysis_log WHERE chat_id = ?1 AND granularity = ?3
+                  )
+                ORDER BY week_group ASC
+                "#,
+                params![chat_id, granularity.strftime_format(), granularity.as_str()],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+
+        let mut weeks = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            let week_str: String = row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?;
+            weeks.push(PeriodGroup::new(week_str));
+        }
+
+        Ok(weeks)
+    }
+
+    async fn get_periods(
+        &self,
+        chat_id: i64,
+        granularity: Granularity,
+    ) -> Result<Vec<PeriodGroup>, DomainError> {
+        let conn = self.connection()?;
+        let mut rows = conn
+            .query(
+                r#"
+                SELECT DISTINCT strftime(?2, date, 'unixepoch') as week_group
+                FROM messages
+                WHERE chat_id = ?1
+                  AND text != ''
+                  AND is_service = 0
+                ORDER BY week_group ASC
+                "#,
+                params![chat_id, granularity.strftime_format()],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        let mut periods = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            let key: String = row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?;
+            periods.push(PeriodGroup::new(key));
+        }
+        Ok(periods)
+    }
+
+    async fn get_messages_for_week(
+        &self,
+        chat_id: i64,
+        granularity: Granularity,
+        week_group: &PeriodGroup,
+    ) -> Result<Vec<Message>, DomainError> {
+        let conn = self.connection()?;
+        // The date range lets the (chat_id, date) index find the period; the key comparison
+        // keeps the result exact. Unparseable keys fall back to scanning the chat.
+        let (from, until) =
+            period_bounds(granularity, week_group.as_str()).unwrap_or((i64::MIN, i64::MAX));
+
+        // Skip empty and service messages. The service check is the structural flag, never
+        // the (localized) text. Senders are joined so the CSV for the LLM can name them.
+        // Captionless parts of a captioned album are kept so the CSV can count them.
+        let mut rows = conn
+            .query(
+                &format!(
+                    r#"
+                SELECT {}
+                FROM messages m
+                LEFT JOIN users u ON u.user_id = m.from_user_id
+                WHERE m.chat_id = ?1
+                  AND m.date >= ?2 AND m.date < ?3
+                  AND strftime(?4, m.date, 'unixepoch') = ?5
+                  AND (m.text != '' OR (m.grouped_id IS NOT NULL AND EXISTS (
+                      SELECT 1 FROM messages c
+                      WHERE c.chat_id = m.chat_id AND c.grouped_id = m.grouped_id AND c.text != ''
+                  )))
+                  AND m.is_service = 0
+                ORDER BY m.date ASC, m.id ASC
+                "#,
+                    MESSAGE_COLUMNS
+                ),
+                params![
+                    chat_id,
+                    from,
+                    until,
+                    granularity.strftime_format(),
+                    week_group.as_str()
+                ],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+
+        let mut messages = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            messages.push(self.row_to_message(&row)?);
+        }
+        Ok(messages)
+    }
+
+    async fn save_analysis(&self, result: &AnalysisResult) -> Result<(), DomainError> {
+        let conn = self
+            .db
+            .connect()
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+
+        let result_json = serde_json::to_string(result)
+            .map_err(|e| DomainError::Repo(format!("Failed to serialize AnalysisResult: {}", e)))?;
+
+        conn.execute(
+            r#"
+            INSERT INTO analysis_log (chat_id, week_group, analyzed_at, summary, result_json, granularity)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            ON CONFLICT (chat_id, granularity, week_group) DO UPDATE SET
+                analyzed_at = excluded.analyzed_at,
+                summary = excluded.summary,
+                result_json = excluded.result_json
+            "#,
+            params![
+                result.chat_id,
+                result.week_group.as_str(),
+                result.analyzed_at,
+                result.summary.as_str(),
+                result_json.as_str(),
+                result.granularity.as_str()
+            ],
+        )
+        .await
+        .map_err(|e| DomainError::Repo(e.to_string()))?;
+
+        info!(
+            chat_id = result.chat_id,
+            granularity = result.granularity.as_str(),
+            week_group = %result.week_group,
+            "saved analysis result"
+        );
+
+        Ok(())
+    }
+
+    async fn get_analysis(
+        &self,
+        chat_id: i64,
+        granularity: Granularity,
+        week_group: &PeriodGroup,
+    ) -> Result<Option<AnalysisResult>, DomainError> {
+        let conn = self
+            .db
+            .connect()
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+
+        let mut rows = conn
+            .query(
+                "SELECT result_json FROM analysis_log WHERE chat_id = ?1 AND granularity = ?2 AND week_group = ?3",
+                params![chat_id, granularity.as_str(), week_group.as_str()],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+
+        if let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            let json_str: String = row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?;
+            let result: AnalysisResult = serde_json::from_str(&json_str).map_err(|e| {
+                DomainError::Repo(format!("Failed to deserialize AnalysisResult: {}", e))
+            })?;
+            Ok(Some(result))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn get_analyzed_weeks(&self, chat_id: i64) -> Result<Vec<AnalyzedPeriod>, DomainError> {
+        let conn = self.connection()?;
+        let mut rows = conn
+            .query(
+                r#"
+                SELECT week_group, granularity, analyzed_at,
+                       COALESCE(json_array_length(result_json, '$.action_items'), 0)
+                FROM analysis_log
+                WHERE chat_id = ?1
+                ORDER BY analyzed_at DESC, week_group DESC
+                "#,
+                params![chat_id],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+
+        let mut periods = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            let week: String = row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?;
+            let granularity: String = row.get(1).map_err(|e| DomainError::Repo(e.to_string()))?;
+            let items: i64 = row.get(3).map_err(|e| DomainError::Repo(e.to_string()))?;
+            periods.push(AnalyzedPeriod {
+                week_group: PeriodGroup::new(week),
+                granularity: Granularity::from_name(&granularity).unwrap_or_default(),
+                analyzed_at: row.get(2).map_err(|e| DomainError::Repo(e.to_string()))?,
+                action_items: items as usize,
+            });
+        }
+        Ok(periods)
+    }
+
+    async fn delete_analysis(
+        &self,
+        chat_id: i64,
+        granularity: Granularity,
+        week_group: &PeriodGroup,
+    ) -> Result<bool, DomainError> {
+        let conn = self.connection()?;
+        let deleted = conn
+            .execute(
+                "DELETE FROM analysis_log WHERE chat_id = ?1 AND granularity = ?2 AND week_group = ?3",
+                params![chat_id, granularity.as_str(), week_group.as_str()],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        Ok(deleted > 0)
+    }
+
+    async fn list_analyses(
+        &self,
+        chat_id: Option<i64>,
+    ) -> Result<Vec<AnalysisResult>, DomainError> {
+        let conn = self
+            .db
+            .connect()
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+
+        let mut rows = conn
+            .query(
+                r#"
+                SELECT result_json FROM analysis_log
+                WHERE ?1 IS NULL OR chat_id = ?1
+                ORDER BY chat_id, granularity, week_group
+                "#,
+                params![chat_id],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+
+        let mut results = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            let json_str: String = row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?;
+            let result: AnalysisResult = serde_json::from_str(&json_str).map_err(|e| {
+                DomainError::Repo(format!("Failed to deserialize AnalysisResult: {}", e))
+            })?;
+            results.push(result);
+        }
+        Ok(results)
+    }
+
+    async fn get_chunk_summaries(
+        &self,
+        chat_id: i64,
+        granularity: Granularity,
+        week_group: &PeriodGroup,
+    ) -> Result<Vec<ChunkSummary>, DomainError> {
+        let conn = self.connection()?;
+        let mut rows = conn
+            .query(
+                r#"
+                SELECT chunk_index, chunk_hash, summary FROM analysis_chunks
+                WHERE chat_id = ?1 AND granularity = ?2 AND week_group = ?3
+                ORDER BY chunk_index
+                "#,
+                params![chat_id, granularity.as_str(), week_group.as_str()],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+
+        let mut chunks = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            let index: i64 = row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?;
+            chunks.push(ChunkSummary {
+                chunk_index: index as usize,
+                chunk_hash: row.get(1).map_err(|e| DomainError::Repo(e.to_string()))?,
+                summary: row.get(2).map_err(|e| DomainError::Repo(e.to_string()))?,
+            });
+        }
+        Ok(chunks)
+    }
+
+    async fn save_chunk_summary(
+        &self,
+        chat_id: i64,
+        granularity: Granularity,
+        week_group: &PeriodGroup,
+        chunk: &ChunkSummary,
+    ) -> Result<(), DomainError> {
+        let conn = self.connection()?;
+        conn.execute(
+            r#"
+            INSERT INTO analysis_chunks (chat_id, week_group, chunk_index, chunk_hash, summary, created_at, granularity)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            ON CONFLICT (chat_id, granularity, week_group, chunk_index) DO UPDATE SET
+                chunk_hash = excluded.chunk_hash,
+                summary = excluded.summary,
+                created_at = excluded.created_at
+            "#,
+            params![
+                chat_id,
+                week_group.as_str(),
+                chunk.chunk_index as i64,
+                chunk.chunk_hash,
+                chunk.summary.as_str(),
+                chrono::Utc::now().timestamp(),
+                granularity.as_str()
+            ],
+        )
+        .await
+        .map_err(|e| DomainError::Repo(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn delete_chunk_summaries(
+        &self,
+        chat_id: i64,
+        granularity: Granularity,
+        week_group: &PeriodGroup,
+    ) -> Result<(), DomainError> {
+        let conn = self.connection()?;
+        conn.execute(
+            "DELETE FROM analysis_chunks WHERE chat_id = ?1 AND granularity = ?2 AND week_group = ?3",
+            params![chat_id, granularity.as_str(), week_group.as_str()],
+        )
+        .await
+        .map_err(|e| DomainError::Repo(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn record_ai_usage(&self, record: &AiUsageRecord) -> Result<(), DomainError> {
+        let conn = self.connection()?;
+        conn.execute(
+            r#"
+            INSERT INTO ai_usage (chat_id, week_group, model, prompt_tokens, completion_tokens, cost_estimate, called_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            "#,
+            params![
+                record.chat_id,
+                record.week_group.as_str(),
+                record.usage.model.as_str(),
+                record.usage.prompt_tokens as i64,
+                record.usage.completion_tokens as i64,
+                record.cost_estimate,
+                record.called_at
+            ],
+        )
+        .await
+        .map_err(|e| DomainError::Repo(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn get_ai_usage_by_month(&self) -> Result<Vec<(String, UsageTotals)>, DomainError> {
+        let conn = self.connection()?;
+        let mut rows = conn
+            .query(
+                r#"
+                SELECT strftime('%Y-%m', called_at, 'unixepoch') AS month,
+                       COUNT(*), SUM(prompt_tokens), SUM(completion_tokens),
+                       COALESCE(SUM(cost_estimate), 0.0), SUM(cost_estimate IS NULL)
+                FROM ai_usage
+                GROUP BY month
+                ORDER BY month DESC
+                "#,
+                (),
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+
+        let mut months = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            let count = |i: i32| -> Result<u64, DomainError> {
+                let n: i64 = row.get(i).map_err(|e| DomainError::Repo(e.to_string()))?;
+                Ok(n.max(0) as u64)
+            };
+            let totals = UsageTotals {
+                calls: count(1)?,
+                prompt_tokens: count(2)?,
+                completion_tokens: count(3)?,
+                cost_estimate: row.get(4).map_err(|e| DomainError::Repo(e.to_string()))?,
+                unpriced_calls: count(5)?,
+            };
+            let month: String = row.get(0).map_err(|e| DomainError::Repo(e.to_string()))?;
+            months.push((month, totals));
+        }
+        Ok(months)
+    }
+
+    async fn has_tracker_task(
+        &self,
+        chat_id: i64,
+        granularity: Granularity,
+        week_group: &PeriodGroup,
+        description_hash: i64,
+    ) -> Result<bool, DomainError> {
+        let conn = self.connection()?;
+        let mut rows = conn
+            .query(
+                r#"
+                SELECT 1 FROM tracker_tasks
+                WHERE chat_id = ?1 AND granularity = ?2 AND week_group = ?3 AND description_hash = ?4
+                "#,
+                params![
+                    chat_id,
+                    granularity.as_str(),
+                    week_group.as_str(),
+                    description_hash
+                ],
+            )
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?;
+        Ok(rows
+            .next()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+            .is_some())
+    }
+
+    async fn save_tracker_task(
+        &self,
+        chat_id: i64,
+        granularity: Granularity,
+        week_group: &PeriodGroup,
+        description_hash: i64,
+        external_id: &str,
+    ) -> Result<(), DomainError> {
+        let conn = self.connection()?;
+        conn.execute(
+            r#"
+            INSERT INTO tracker_tasks (chat_id, granularity, week_group, description_hash, external_id, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            ON CONFLICT (chat_id, granularity, week_group, description_hash) DO UPDATE SET
+                external_id = excluded.external_id,
+                created_at = excluded.created_at
+            "#,
+            params![
+                chat_id,
+                granularity.as_str(),
+                week_group.as_str(),
+                description_hash,
+                external_id,
+                chrono::Utc::now().timestamp()
+            ],
+        )
+        .await
+        .map_err(|e| DomainError::Repo(e.to_string()))?;
+        Ok(())
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Unit Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::ChatType;
+    use crate::testing::fake_tg::text_message;
+    use libsql::params;
+
+    /// Helper: Create an in-memory database with schema for testing.
+    async fn setup_test_db() -> libsql::Connection {
+        let db = libsql::Builder::new_local(":memory:")
             .build()
             .await
-            .expect("Failed to create in-memory db");
-        let conn = db.connect().expect("Failed to connect");
+            .expect("Failed to create in-memory db");
+        let conn = db.connect().expect("Failed to connect");
+
+        // Create required tables
+        conn.execute(MESSAGES_TABLE, ()).await.unwrap();
+        conn.execute(ANALYSIS_LOG_TABLE, ()).await.unwrap();
+
+        conn
+    }
+
+    /// Insert a test message with a specific timestamp.
+    async fn insert_message(
+        conn: &libsql::Connection,
+        chat_id: i64,
+        msg_id: i32,
+        timestamp: i64,
+        text: &str,
+    ) {
+        conn.execute(
+            "INSERT INTO messages (chat_id, id, date, text) VALUES (?1, ?2, ?3, ?4)",
+            params![chat_id, msg_id, timestamp, text],
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_week_grouping_basic() {
+        let conn = setup_test_db().await;
+        let chat_id = 123i64;
+
+        // Week 1 of 2024: Jan 1-7, 2024
+        // Week 2 of 2024: Jan 8-14, 2024
+        let week1_ts = 1704067200i64; // 2024-01-01 00:00:00 UTC (Monday)
+        let week1_ts2 = 1704153600i64; // 2024-01-02 00:00:00 UTC (Tuesday)
+        let week2_ts = 1704672000i64; // 2024-01-08 00:00:00 UTC (Monday)
+
+        insert_message(&conn, chat_id, 1, week1_ts, "Hello Week 1").await;
+        insert_message(&conn, chat_id, 2, week1_ts2, "Also Week 1").await;
+        insert_message(&conn, chat_id, 3, week2_ts, "Hello Week 2").await;
+
+        // Query for week grouping
+        let mut rows = conn
+            .query(
+                r#"
+                SELECT strftime('%Y-%W', date, 'unixepoch') as week_group, COUNT(*) as cnt
+                FROM messages
+                WHERE chat_id = ?1 AND text != ''
+                GROUP BY week_group
+                ORDER BY week_group ASC
+                "#,
+                params![chat_id],
+            )
+            .await
+            .unwrap();
+
+        let mut weeks = Vec::new();
+        while let Some(row) = rows.next().await.unwrap() {
+            let week: String = row.get(0).unwrap();
+            let count: i64 = row.get(1).unwrap();
+            weeks.push((week, count));
+        }
+
+        assert_eq!(weeks.len(), 2, "Should have 2 distinct weeks");
+        assert_eq!(weeks[0].1, 2, "Week 1 should have 2 messages");
+        assert_eq!(weeks[1].1, 1, "Week 2 should have 1 message");
+    }
+
+    #[tokio::test]
+    async fn test_analysis_idempotency() {
+        let conn = setup_test_db().await;
+        let chat_id = 123i64;
+
+        // Insert messages
+        let week1_ts = 1704067200i64; // 2024-01-01 (Week 00 or 01 depending on locale)
+        insert_message(&conn, chat_id, 1, week1_ts, "Hello").await;
+
+        // Get the week group that was generated
+        let mut rows = conn
+            .query(
+                "SELECT DISTINCT strftime('%Y-%W', date, 'unixepoch') FROM messages WHERE chat_id = ?1",
+                params![chat_id],
+            )
+            .await
+            .unwrap();
+        let week_group: String = rows.next().await.unwrap().unwrap().get(0).unwrap();
+
+        // Initially, the week should be unanalyzed
+        let mut rows = conn
+            .query(
+                r#"
+                SELECT strftime('%Y-%W', date, 'unixepoch') as week_group
+                FROM messages
+                WHERE chat_id = ?1
+                  AND strftime('%Y-%W', date, 'unixepoch') NOT IN (
+                      SELECT week_group FROM analysis_log WHERE chat_id = ?1
+                  )
+                GROUP BY week_group
+                "#,
+                params![chat_id],
+            )
+            .await
+            .unwrap();
+        let unanalyzed: Option<_> = rows.next().await.unwrap();
+        assert!(unanalyzed.is_some(), "Week should be unanalyzed initially");
+
+        // Mark the week as analyzed
+        conn.execute(
+            "INSERT INTO analysis_log (chat_id, week_group, analyzed_at, summary, result_json) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![chat_id, week_group.as_str(), 1704067200i64, "Test summary", "{}"],
+        )
+        .await
+        .unwrap();
+
+        // Now the week should NOT appear in unanalyzed list
+        let mut rows = conn
+            .query(
+                r#"
+                SELECT strftime('%Y-%W', date, 'unixepoch') as week_group
+                FROM messages
+                WHERE chat_id = ?1
+                  AND strftime('%Y-%W', date, 'unixepoch') NOT IN (
+                      SELECT week_group FROM analysis_log WHERE chat_id = ?1
+                  )
+                GROUP BY week_group
+                "#,
+                params![chat_id],
+            )
+            .await
+            .unwrap();
+        let unanalyzed_after: Option<_> = rows.next().await.unwrap();
+        assert!(
+            unanalyzed_after.is_none(),
+            "Week should NOT appear after being analyzed"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_structural_filtering_keeps_user_text() {
+        let conn = setup_test_db().await;
+        let chat_id = 123i64;
+        let ts = 1704067200i64;
+
+        // Insert regular messages, including ones that merely mention joining or leaving
+        insert_message(&conn, chat_id, 1, ts, "Hello world").await;
+        insert_message(&conn, chat_id, 2, ts, "Glad you joined the group!").await;
+        insert_message(&conn, chat_id, 3, ts, "Who left the group chat open?").await;
+        // Insert empty message
+        insert_message(&conn, chat_id, 4, ts, "").await;
+        // Insert a service message flagged structurally (German service text)
+        insert_message(&conn, chat_id, 5, ts, "Bob ist der Gruppe beigetreten").await;
+        conn.execute(
+            "UPDATE messages SET is_service = 1 WHERE chat_id = ?1 AND id = 5",
+            params![chat_id],
+        )
+        .await
+        .unwrap();
+
+        // Query with filters (same as get_messages_for_week)
+        let mut rows = conn
+            .query(
+                r#"
+                SELECT COUNT(*) FROM messages
+                WHERE chat_id = ?1
+                  AND text != ''
+                  AND is_service = 0
+                "#,
+                params![chat_id],
+            )
+            .await
+            .unwrap();
+
+        let count: i64 = rows.next().await.unwrap().unwrap().get(0).unwrap();
+        assert_eq!(
+            count, 3,
+            "Only the empty and the service message should be filtered out"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chat_events_round_trip() {
+        use std::path::PathBuf;
+
+        let base_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("target")
+            .join("test_chat_events_db");
+        let _ = std::fs::remove_dir_all(&base_dir);
+        let repo = SqliteRepo::connect(&base_dir).await.expect("connect");
+
+        let event = |id, kind, payload: Option<&str>| ChatEvent {
+            id,
+            chat_id: 5,
+            date: 1704067200 + id as i64,
+            kind,
+            actor_id: Some(7),
+            payload: payload.map(String::from),
+        };
+        repo.save_events(&[
+            event(9, ChatEventKind::MessagePinned, Some("3")),
+            event(2, ChatEventKind::UserJoined, None),
+            event(4, ChatEventKind::TitleChanged, Some("Old")),
+        ])
+        .await
+        .unwrap();
+        // Re-syncing the same service message refreshes it instead of duplicating.
+        repo.save_events(&[event(4, ChatEventKind::TitleChanged, Some("New"))])
+            .await
+            .unwrap();
+
+        let events = repo.get_events(5).await.unwrap();
+        let ids: Vec<i32> = events.iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec![2, 4, 9]);
+        assert_eq!(events[1].payload.as_deref(), Some("New"));
+        assert_eq!(events[2].kind, ChatEventKind::MessagePinned);
+        assert!(repo.get_events(6).await.unwrap().is_empty());
+        // Events are not messages.
+        assert!(repo.get_messages(5, 10, 0).await.unwrap().is_empty());
+
+        let _ = std::fs::remove_dir_all(&base_dir);
+    }
+
+    /// Comment threads: replies inherit the channel post of the message they answer, however
+    /// deep, whatever order they were saved in.
+    #[tokio::test]
+    async fn test_channel_comments_are_linked_through_replies() {
+        use std::path::PathBuf;
+
+        let base_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("target")
+            .join("test_channel_comments_db");
+        let _ = std::fs::remove_dir_all(&base_dir);
+        let repo = SqliteRepo::connect(&base_dir).await.expect("connect");
+
+        let (channel, group) = (-1000000000042i64, -1000000000077i64);
+        let msg = |id: i32, reply_to: Option<i32>, post: Option<i32>| Message {
+            from_user_id: post.is_none().then_some(7),
+            reply_to_msg_id: reply_to,
+            forwarded_from_id: post.map(|_| channel),
+            forwarded_date: post.map(|_| 1704067200),
+            linked_channel_post: post,
+            ..text_message(group, id, 1704067200 + id as i64, &format!("msg {}", id))
+        };
+        // Newest first, like a sync: replies are stored before what they answer.
+        repo.save_messages(group, &[msg(14, Some(13), None), msg(13, Some(11), None)])
+            .await
+            .unwrap();
+        repo.save_messages(
+            group,
+            &[
+                msg(12, None, None),
+                msg(11, Some(10), None),
+                msg(10, None, Some(500)),
+                msg(9, None, Some(499)),
+            ],
+        )
+        .await
+        .unwrap();
+        assert_eq!(repo.link_channel_comments(group).await.unwrap(), 3);
+        assert_eq!(repo.link_channel_comments(group).await.unwrap(), 0);
+
+        let comments = repo
+            .get_channel_comments(channel, &[500, 499])
+            .await
+            .unwrap();
+        let ids: Vec<(Option<i32>, i32)> = comments
+            .iter()
+            .map(|m| (m.linked_channel_post, m.id))
+            .collect();
+        // The post copies (10, 9) and the unrelated message 12 are not comments.
+        assert_eq!(ids, vec![(Some(500), 11), (Some(500), 13), (Some(500), 14)]);
+        assert!(
+            repo.get_channel_comments(channel, &[])
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            repo.get_channel_comments(-1000000000099, &[500])
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        // Re-saving a copy without the link (e.g. an edit rescan) keeps it.
+        let mut copy = msg(10, None, None);
+        copy.text = "edited".to_string();
+        repo.save_messages(group, &[copy]).await.unwrap();
+        let stored = repo.get_messages_by_ids(group, &[10]).await.unwrap();
+        assert_eq!(stored[0].linked_channel_post, Some(500));
+
+        let _ = std::fs::remove_dir_all(&base_dir);
+    }
+
+    /// Message versioning: saving the same message ID with new text appends the previous version to edit_history.
+    #[tokio::test]
+    async fn test_edit_history_versioning() {
+        use std::path::PathBuf;
+
+        let base_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("target")
+            .join("test_edit_history_db");
+        let repo = SqliteRepo::connect(&base_dir).await.expect("connect");
+
+        let chat_id = 999i64;
+        let msg_id = 1i32;
+        let ts_a = 1704067200i64;
+        let ts_b = 1704153600i64;
+
+        let msg_a = text_message(chat_id, msg_id, ts_a, "Text A");
+        repo.save_messages(chat_id, &[msg_a]).await.unwrap();
+
+        let msg_b = text_message(chat_id, msg_id, ts_b, "Text B");
+        repo.save_messages(chat_id, &[msg_b]).await.unwrap();
+
+        let messages = repo.get_messages(chat_id, 10, 0).await.unwrap();
+        assert_eq!(messages.len(), 1, "should have one message");
+        let m = &messages[0];
+        assert_eq!(m.text, "Text B", "current text should be B");
+        let history = m
+            .edit_history
+            .as_ref()
+            .expect("edit_history should be present");
+        assert!(!history.is_empty(), "edit_history should not be empty");
+        assert_eq!(history.len(), 1, "one prior version");
+        assert_eq!(history[0].text, "Text A", "prior version should be Text A");
+        assert_eq!(
+            history[0].date, ts_a,
+            "prior version should have original date"
+        );
+    }
+
+    /// Activity histogram: day buckets are local midnights, hour buckets shift with the offset.
+    #[tokio::test]
+    async fn test_activity_histogram_buckets() {
+        use std::path::PathBuf;
+
+        let base_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("target")
+            .join("test_activity_histogram_db");
+        let _ = std::fs::remove_dir_all(&base_dir);
+        let repo = SqliteRepo::connect(&base_dir).await.expect("connect");
+
+        let chat_id = 42i64;
+        let mon = 1704067200i64; // 2024-01-01 00:00:00 UTC (Monday)
+        let dates = [
+            mon + 10 * 3600,         // Mon 10:00 UTC
+            mon + 23 * 3600 + 1800,  // Mon 23:30 UTC (Tue 04:30 at UTC+5)
+            mon + 86_400 + 9 * 3600, // Tue 09:00 UTC
+            mon + 7 * 86_400 + 3600, // next Monday, outside the range
+        ];
+        let messages: Vec<Message> = dates
+            .iter()
+            .enumerate()
+            .map(|(i, &date)| text_message(chat_id, i as i32 + 1, date, &format!("msg {}", i)))
+            .collect();
+        repo.save_messages(chat_id, &messages).await.unwrap();
+
+        let range = TimeRange {
+            from: mon - 86_400,
+            to: mon + 7 * 86_400,
+        };
+        let pairs = |bins: Vec<ActivityBin>| -> Vec<(i64, u64)> {
+            bins.into_iter()
+                .map(|b| (b.bucket_start, b.count))
+                .collect()
+        };
+
+        let utc_days = repo
+            .get_activity_histogram(chat_id, ActivityBucket::Day, range, 0)
+            .await
+            .unwrap();
+        assert_eq!(pairs(utc_days), vec![(mon, 2), (mon + 86_400, 1)]);
+
+        let plus5 = 5 * 3600;
+        let local_days = repo
+            .get_activity_histogram(chat_id, ActivityBucket::Day, range, plus5)
+            .await
+            .unwrap();
+        // Local midnights at UTC+5 fall at 19:00 UTC the previous day.
+        assert_eq!(
+            pairs(local_days),
+            vec![(mon - plus5 as i64, 1), (mon + 86_400 - plus5 as i64, 2)]
+        );
+
+        let hours = repo
+            .get_activity_histogram(chat_id, ActivityBucket::HourOfDay, range, plus5)
+            .await
+            .unwrap();
+        assert_eq!(pairs(hours), vec![(4, 1), (14, 1), (15, 1)]);
+    }
+
+    #[test]
+    fn test_fts_query_quotes_user_input() {
+        assert_eq!(
+            fts_query("deploy prod*").as_deref(),
+            Some(r#""deploy" "prod"*"#)
+        );
+        assert_eq!(
+            fts_query(r#"say "hi" - NOT x:y"#).as_deref(),
+            Some(r#""say" """hi""" "NOT" "x:y""#)
+        );
+        assert_eq!(fts_query("  - * "), None);
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_archive_round_trip_search_and_rekey() {
+        use std::path::PathBuf;
+
+        const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+        const OTHER_KEY: &str = "1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100";
+        let base_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("target")
+            .join("test_encrypted_archive_db");
+        let _ = std::fs::remove_dir_all(&base_dir);
+        let msg = |id: i32, text: &str| text_message(1, id, 1704067200 + id as i64, text);
+        let stored_texts = |conn: libsql::Connection| async move {
+            let mut rows = conn
+                .query("SELECT text FROM messages ORDER BY id", ())
+                .await
+                .unwrap();
+            let mut texts = Vec::new();
+            while let Some(row) = rows.next().await.unwrap() {
+                texts.push(row.get::<String>(0).unwrap());
+            }
+            texts
+        };
+
+        // A plaintext archive, then encrypted: old rows stay readable until rekey.
+        let plain = SqliteRepo::connect(&base_dir).await.unwrap();
+        plain
+            .save_messages(1, &[msg(1, "the vault code is 1234")])
+            .await
+            .unwrap();
+        drop(plain);
+        assert!(!SqliteRepo::is_encrypted(&base_dir).await.unwrap());
+        let repo = SqliteRepo::connect_encrypted(&base_dir, KEY).await.unwrap();
+        assert!(SqliteRepo::is_encrypted(&base_dir).await.unwrap());
+        let mut photo = msg(2, "Deploy notes: production is green");
+        photo.media = Some(MediaReference {
+            chat_id: 1,
+            message_id: 2,
+            media_type: MediaType::Photo,
+            opaque_ref: "ref-2".to_string(),
+            size_bytes: Some(10),
+            filename: None,
+            mime_type: None,
+        });
+        repo.save_messages(1, std::slice::from_ref(&photo))
+            .await
+            .unwrap();
+        let stored = stored_texts(repo.connection().unwrap()).await;
+        assert_eq!(stored[0], "the vault code is 1234");
+        assert!(ArchiveCipher::is_sealed(&stored[1]));
+
+        // Edits are still detected and the history is sealed too.
+        photo.text = "Deploy notes: production is red".to_string();
+        repo.save_messages(1, &[photo]).await.unwrap();
+        let edited = &repo.get_messages(1, 1, 0).await.unwrap()[0];
+        assert_eq!(edited.text, "Deploy notes: production is red");
+        let history = edited.edit_history.as_ref().unwrap();
+        assert_eq!(history[0].text, "Deploy notes: production is green");
+
+        // Search scans decrypted text; no FTS table keeps plaintext around.
+        let hits = repo
+            .search_messages("PRODUCTION deploy", None, 10, 0)
+            .await
+            .unwrap();
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].snippet.contains("[production]"));
+        assert!(
+            !SqliteRepo::has_table(&repo.connection().unwrap(), FTS_TABLE)
+                .await
+                .unwrap()
+        );
+
+        assert_eq!(repo.rekey(1).await.unwrap(), 1);
+        assert_eq!(repo.rekey(1).await.unwrap(), 0);
+        let stored = stored_texts(repo.connection().unwrap()).await;
+        assert!(stored.iter().all(|t| ArchiveCipher::is_sealed(t)));
+        assert_eq!(
+            repo.search_messages("vault", Some(1), 10, 0).await.unwrap()[0]
+                .message
+                .id,
+            1
+        );
+        drop(repo);
+
+        assert!(matches!(
+            SqliteRepo::connect(&base_dir).await,
+            Err(DomainError::Crypto(_))
+        ));
+        assert!(matches!(
+            SqliteRepo::connect_encrypted(&base_dir, OTHER_KEY).await,
+            Err(DomainError::Crypto(_))
+        ));
+        let reopened = SqliteRepo::connect_encrypted(&base_dir, KEY).await.unwrap();
+        let media = reopened.get_messages(1, 1, 0).await.unwrap()[0]
+            .media
+            .clone()
+            .unwrap();
+        assert_eq!(media.opaque_ref, "ref-2");
+    }
+
+    #[tokio::test]
+    async fn test_rekey_seals_plaintext_that_looks_sealed() {
+        use std::path::PathBuf;
+
+        const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+        let base_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("target")
+            .join("test_rekey_sealed_prefix_db");
+        let _ = std::fs::remove_dir_all(&base_dir);
+
+        let plain = SqliteRepo::connect(&base_dir).await.unwrap();
+        plain
+            .save_messages(1, &[text_message(1, 1, 1704067200, "enc1:hello")])
+            .await
+            .unwrap();
+        drop(plain);
+
+        let repo = SqliteRepo::connect_encrypted(&base_dir, KEY).await.unwrap();
+        assert_eq!(repo.rekey(10).await.unwrap(), 1);
+        assert_eq!(repo.rekey(10).await.unwrap(), 0);
+        let messages = repo.get_messages(1, 10, 0).await.unwrap();
+        assert_eq!(messages[0].text, "enc1:hello");
+    }
+
+    #[tokio::test]
+    async fn test_search_messages_ranked_filtered_and_backfilled() {
+        use std::path::PathBuf;
+
+        let base_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("target")
+            .join("test_search_messages_db");
+        let _ = std::fs::remove_dir_all(&base_dir);
+        let repo = SqliteRepo::connect(&base_dir).await.expect("connect");
+        let msg = |chat_id: i64, id: i32, text: &str| {
+            text_message(chat_id, id, 1704067200 + id as i64, text)
+        };
+        repo.save_messages(
+            1,
+            &[
+                msg(1, 1, "deploy failed on production again"),
+                msg(1, 2, "lunch?"),
+                msg(1, 3, "deploy deploy deploy: the production deploy is green"),
+            ],
+        )
+        .await
+        .unwrap();
+        repo.save_messages(2, &[msg(2, 1, "Deployment notes for production")])
+            .await
+            .unwrap();
+        repo.register_synthetic_chat(&Chat {
+            id: 2,
+            title: "Ops log".to_string(),
+            username: None,
+            kind: ChatType::Group,
+            approx_message_count: None,
+            is_forum: false,
+            last_message_date: None,
+        })
+        .await
+        .unwrap();
+
+        let ids = |hits: Vec<SearchHit>| -> Vec<(i64, i32)> {
+            hits.iter()
+                .map(|h| (h.message.chat_id, h.message.id))
+                .collect()
+        };
+        // Best match (most occurrences) first; both words required.
+        let hits = repo
+            .search_messages("deploy production", None, 10, 0)
+            .await
+            .unwrap();
+        assert_eq!(hits[0].snippet.matches("[deploy]").count(), 4);
+        assert_eq!(ids(hits), vec![(1, 3), (1, 1)]);
+        // Prefix matching, chat title from the archive, chat filter and paging.
+        let hits = repo.search_messages("deploy*", None, 10, 0).await.unwrap();
+        assert_eq!(hits.len(), 3);
+        let ops = hits.iter().find(|h| h.message.chat_id == 2).unwrap();
+        assert_eq!(ops.chat_title.as_deref(), Some("Ops log"));
+        assert!(
+            hits.iter()
+                .filter(|h| h.message.chat_id == 1)
+                .all(|h| h.chat_title.is_none())
+        );
+        assert_eq!(
+            ids(repo
+                .search_messages("deploy*", Some(2), 10, 0)
+                .await
+                .unwrap()),
+            vec![(2, 1)]
+        );
+        assert_eq!(
+            repo.search_messages("deploy*", None, 2, 2)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(
+            repo.search_messages(" - ", None, 10, 0)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        // Edits re-index the message.
+        repo.save_messages(1, &[msg(1, 2, "lunch after the deploy")])
+            .await
+            .unwrap();
+        assert_eq!(
+            repo.search_messages("lunch deploy", None, 10, 0)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // A database from before full-text search is backfilled on connect.
+        let conn = repo.connection().unwrap();
+        for sql in [
+            "DROP TRIGGER messages_fts_ai",
+            "DROP TRIGGER messages_fts_ad",
+            "DROP TRIGGER messages_fts_au",
+            "DROP TABLE messages_fts",
+        ] {
+            conn.execute(sql, ()).await.unwrap();
+        }
+        drop(repo);
+        let repo = SqliteRepo::connect(&base_dir).await.expect("reconnect");
+        assert_eq!(
+            repo.search_messages("production", None, 10, 0)
+                .await
+                .unwrap()
+                .len(),
+            3
+        );
+        assert_eq!(repo.fts_row_counts().await.unwrap(), Some((4, 4)));
+    }
+
+    #[tokio::test]
+    async fn test_reads_join_sender_names() {
+        use std::path::PathBuf;
+
+        let base_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("target")
+            .join("test_sender_names_db");
+        let _ = std::fs::remove_dir_all(&base_dir);
+        let repo = SqliteRepo::connect(&base_dir).await.expect("connect");
+        let msg = |id: i32, from: i64| Message {
+            from_user_id: Some(from),
+            ..text_message(1, id, 1704067200 + id as i64, &format!("message {}", id))
+        };
+        repo.save_messages(1, &[msg(1, 10), msg(2, 20), msg(3, 30)])
+            .await
+            .unwrap();
+        let user =
+            |id: i64, first: Option<&str>, last: Option<&str>, username: Option<&str>| User {
+                id,
+                first_name: first.map(String::from),
+                last_name: last.map(String::from),
+                username: username.map(String::from),
+            };
+        repo.save_users(&[
+            user(10, Some("Alice"), None, Some("alice")),
+            user(20, None, None, Some("bob")),
+        ])
+        .await
+        .unwrap();
+        // A later batch refreshes the name.
+        repo.save_users(&[user(10, Some("Alice"), Some("Smith"), Some("alice"))])
+            .await
+            .unwrap();
+
+        let names = |messages: Vec<Message>| -> Vec<Option<String>> {
+            messages.into_iter().map(|m| m.sender_name).collect()
+        };
+        let expected = vec![
+            Some("Alice Smith".to_string()),
+            Some("@bob".to_string()),
+            None,
+        ];
+        assert_eq!(
+            names(repo.get_messages_after(1, 0, 10).await.unwrap()),
+            expected
+        );
+        let weeks = repo.get_periods(1, Granularity::Week).await.unwrap();
+        assert_eq!(weeks.len(), 1);
+        assert_eq!(
+            names(
+                repo.get_messages_for_week(1, Granularity::Week, &weeks[0])
+                    .await
+                    .unwrap()
+            ),
+            expected
+        );
+        assert_eq!(
+            names(repo.get_messages_by_ids(1, &[2]).await.unwrap()),
+            vec![Some("@bob".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_analysis_granularities_migrate_and_do_not_collide() {
+        use std::path::PathBuf;
+
+        let base_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("target")
+            .join("test_analysis_granularity_db");
+        let _ = std::fs::remove_dir_all(&base_dir);
+        std::fs::create_dir_all(&base_dir).unwrap();
+
+        // A database from before granularities: weekly rows keyed by (chat_id, week_group).
+        {
+            let db = libsql::Builder::new_local(base_dir.join("messages.db"))
+                .build()
+                .await
+                .unwrap();
+            let conn = db.connect().unwrap();
+            conn.execute(
+                "CREATE TABLE analysis_log (chat_id INTEGER NOT NULL, week_group TEXT NOT NULL, analyzed_at INTEGER NOT NULL, summary TEXT NOT NULL, result_json TEXT NOT NULL, PRIMARY KEY (chat_id, week_group))",
+                (),
+            )
+            .await
+            .unwrap();
+            conn.execute(
+                "INSERT INTO analysis_log VALUES (1, '2024-01', 0, 'old', ?1)",
+                params![
+                    r#"{"week_group":"2024-01","chat_id":1,"summary":"old","key_topics":[],"action_items":[],"analyzed_at":0}"#
+                ],
+            )
+            .await
+            .unwrap();
+        }
+
+        let repo = SqliteRepo::connect(&base_dir).await.expect("connect");
+        let msg = |id: i32, date: i64| text_message(1, id, date, &format!("message {}", id));
+        // Monday 2024-01-01 (week "2024-01") and Monday 2024-01-15 (week "2024-03").
+        repo.save_messages(1, &[msg(1, 1704067200), msg(2, 1704067200 + 14 * 86_400)])
+            .await
+            .unwrap();
+
+        let key = PeriodGroup::new("2024-01");
+        let weekly = repo
+            .get_analysis(1, Granularity::Week, &key)
+            .await
+            .unwrap()
+            .expect("migrated weekly row");
+        assert_eq!(weekly.summary, "old");
+        assert_eq!(weekly.granularity, Granularity::Week);
+        assert_eq!(
+            repo.get_unanalyzed_weeks(1, Granularity::Week)
+                .await
+                .unwrap(),
+            vec![PeriodGroup::new("2024-03")]
+        );
+
+        // Month "2024-01" looks like week "2024-01" but is pending on its own.
+        assert_eq!(
+            repo.get_unanalyzed_weeks(1, Granularity::Month)
+                .await
+                .unwrap(),
+            vec![key.clone()]
+        );
+        repo.save_analysis(&AnalysisResult {
+            week_group: key.clone(),
+            granularity: Granularity::Month,
+            chat_id: 1,
+            summary: "january".to_string(),
+            key_topics: vec![],
+            action_items: vec![crate::domain::ActionItem {
+                description: "Book the venue".to_string(),
+                owner: None,
+                deadline: None,
+                priority: None,
+            }],
+            analyzed_at: 1,
+        })
+        .await
+        .unwrap();
+        assert!(
+            repo.get_unanalyzed_weeks(1, Granularity::Month)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        let week_summary = |r: Option<AnalysisResult>| r.map(|r| r.summary);
+        assert_eq!(
+            week_summary(repo.get_analysis(1, Granularity::Week, &key).await.unwrap()),
+            Some("old".to_string())
+        );
+        assert_eq!(
+            week_summary(
+                repo.get_analysis(1, Granularity::Month, &key)
+                    .await
+                    .unwrap()
+            ),
+            Some("january".to_string())
+        );
+        assert_eq!(repo.list_analyses(Some(1)).await.unwrap().len(), 2);
+        assert_eq!(
+            repo.get_periods(1, Granularity::Day).await.unwrap().len(),
+            2
+        );
+
+        // Newest first, with the action item count read from the stored result.
+        let analyzed = repo.get_analyzed_weeks(1).await.unwrap();
+        assert_eq!(
+            analyzed
+                .iter()
+                .map(|p| (p.granularity, p.analyzed_at, p.action_items))
+                .collect::<Vec<_>>(),
+            vec![(Granularity::Month, 1, 1), (Granularity::Week, 0, 0)]
+        );
+        assert!(repo.get_analyzed_weeks(2).await.unwrap().is_empty());
+        assert!(
+            repo.delete_analysis(1, Granularity::Month, &key)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !repo
+                .delete_analysis(1, Granularity::Month, &key)
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            repo.get_unanalyzed_weeks(1, Granularity::Month)
+                .await
+                .unwrap(),
+            vec![key.clone()]
+        );
+        assert!(
+            repo.get_analysis(1, Granularity::Week, &key)
+                .await
+                .unwrap()
+                .is_some()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_watch_rules_and_settings_persist() {
+        use std::path::PathBuf;
+
+        let base_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("target")
+            .join("test_watch_rules_db");
+        let _ = std::fs::remove_dir_all(&base_dir);
+        let repo = SqliteRepo::connect(&base_dir).await.expect("connect");
+
+        let seeded = repo.list_watch_rules().await.unwrap();
+        assert_eq!(seeded.len(), DEFAULT_WATCH_KEYWORDS.len());
+        assert!(
+            seeded
+                .iter()
+                .all(|r| r.is_global() && r.enabled && !r.is_regex)
+        );
+
+        let chat_rule = repo.add_watch_rule(42, r"inv-\d+", true).await.unwrap();
+        repo.set_watch_rule_enabled(chat_rule, false).await.unwrap();
+        // Re-adding the same rule re-enables it instead of duplicating.
+        assert_eq!(
+            repo.add_watch_rule(42, r"inv-\d+", true).await.unwrap(),
+            chat_rule
+        );
+        let rules = repo.list_watch_rules().await.unwrap();
+        let last = rules.last().unwrap();
+        assert_eq!((last.id, last.chat_id, last.enabled), (chat_rule, 42, true));
+
+        // Mention and reply rules live next to keywords, one per chat and kind.
+        let mention = repo
+            .set_watch_trigger(42, WatchRuleKind::Mention, true)
+            .await
+            .unwrap();
+        let reply = repo
+            .set_watch_trigger(42, WatchRuleKind::Reply, true)
+            .await
+            .unwrap();
+        assert_ne!(mention, reply);
+        assert_eq!(
+            repo.set_watch_trigger(42, WatchRuleKind::Mention, false)
+                .await
+                .unwrap(),
+            mention
+        );
+        let triggers: Vec<(WatchRuleKind, bool)> = repo
+            .list_watch_rules()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|r| r.kind != WatchRuleKind::Keyword)
+            .map(|r| (r.kind, r.enabled))
+            .collect();
+        assert_eq!(
+            triggers,
+            vec![
+                (WatchRuleKind::Mention, false),
+                (WatchRuleKind::Reply, true)
+            ]
+        );
+        assert!(repo.remove_watch_rule(mention).await.unwrap());
+        assert!(repo.remove_watch_rule(reply).await.unwrap());
+
+        for rule in &seeded {
+            assert!(repo.remove_watch_rule(rule.id).await.unwrap());
+        }
+        assert!(!repo.remove_watch_rule(seeded[0].id).await.unwrap());
+        repo.set_setting("watcher.alert_chat_id", Some("-5"))
+            .await
+            .unwrap();
+        repo.set_setting("other", Some("x")).await.unwrap();
+        repo.set_setting("other", None).await.unwrap();
+        drop(repo);
+
+        // Removed defaults are not re-seeded on the next open.
+        let repo = SqliteRepo::connect(&base_dir).await.expect("reconnect");
+        assert_eq!(repo.list_watch_rules().await.unwrap().len(), 1);
+        assert_eq!(
+            repo.get_setting("watcher.alert_chat_id").await.unwrap(),
+            Some("-5".to_string())
+        );
+        assert_eq!(repo.get_setting("other").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_media_queue_resume_and_backoff() {
+        use std::path::PathBuf;
+
+        let base_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("target")
+            .join("test_media_queue_db");
+        let _ = std::fs::remove_dir_all(&base_dir);
+        let repo = SqliteRepo::connect(&base_dir).await.expect("connect");
+        let media = |id: i32| MediaReference {
+            message_id: id,
+            chat_id: 7,
+            media_type: MediaType::Photo,
+            opaque_ref: format!("ref-{}", id),
+            size_bytes: None,
+            filename: None,
+            mime_type: None,
+        };
+        let messages: Vec<Message> = (1..=3)
+            .map(|id| Message {
+                media: Some(media(id)),
+                ..text_message(7, id, 1704067200, "")
+            })
+            .collect();
+        repo.save_messages(7, &messages).await.unwrap();
+        let refs: Vec<MediaReference> = (1..=3).map(media).collect();
+        assert_eq!(repo.enqueue_media(&refs).await.unwrap(), 3);
+        assert_eq!(repo.enqueue_media(&refs).await.unwrap(), 0);
+
+        // 1 arrives on the channel, 2 is claimed from the table, then the process dies.
+        assert!(repo.claim_media(&media(1)).await.unwrap());
+        assert!(!repo.claim_media(&media(1)).await.unwrap());
+        let now = chrono::Utc::now().timestamp();
+        let due = repo.claim_due_media(now, 30, 5, 1).await.unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].opaque_ref, "ref-2");
+        drop(repo);
+
+        let repo = SqliteRepo::connect(&base_dir).await.expect("reconnect");
+        assert_eq!(repo.requeue_interrupted_media().await.unwrap(), 2);
+        let due = repo.claim_due_media(now, 30, 5, 10).await.unwrap();
+        let ids: Vec<i32> = due.iter().map(|m| m.message_id).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+
+        repo.finish_media(7, 1, None).await.unwrap();
+        repo.finish_media(7, 2, Some("timeout")).await.unwrap();
+        repo.finish_media(7, 3, Some("timeout")).await.unwrap();
+        // Failed rows wait for their backoff; out of attempts they wait for a manual retry.
+        assert!(
+            repo.claim_due_media(now, 30, 5, 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        let later = repo.claim_due_media(now + 31, 30, 2, 1).await.unwrap();
+        assert_eq!(later.len(), 1);
+        repo.finish_media(7, later[0].message_id, Some("timeout"))
+            .await
+            .unwrap();
+        let rest = repo.claim_due_media(now + 3600, 30, 2, 10).await.unwrap();
+        assert_eq!(rest.len(), 1);
+        assert_ne!(rest[0].message_id, later[0].message_id);
+        repo.finish_media(7, rest[0].message_id, Some("timeout"))
+            .await
+            .unwrap();
+        assert!(!repo.claim_media(&media(2)).await.unwrap());
+        assert!(
+            repo.claim_due_media(now + 3600, 30, 2, 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        assert_eq!(repo.retry_failed_media().await.unwrap(), 2);
+        assert_eq!(repo.claim_due_media(now, 30, 2, 10).await.unwrap().len(), 2);
+        // Done rows can be re-requested (e.g. file deleted); the worker skips existing files.
+        assert!(repo.claim_media(&media(1)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_upsert_chats_keeps_previous_titles() {
+        use std::path::PathBuf;
+
+        let base_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("target")
+            .join("test_upsert_chats_db");
+        let _ = std::fs::remove_dir_all(&base_dir);
+        let repo = SqliteRepo::connect(&base_dir).await.expect("connect");
+        let chat = |id: i64, title: &str, count: i32| Chat {
+            id,
+            title: title.to_string(),
+            username: None,
+            kind: ChatType::Supergroup,
+            approx_message_count: Some(count),
+            is_forum: false,
+            last_message_date: None,
+        };
+
+        repo.upsert_chats(&[chat(-100, "Team", 10), chat(5, "alice", 3)])
+            .await
+            .unwrap();
+        repo.upsert_chats(&[chat(-100, "Team (archived)", 12)])
+            .await
+            .unwrap();
+        repo.upsert_chats(&[chat(-100, "Team (archived)", 13)])
+            .await
+            .unwrap();
 
-        // Create required tables
-        conn.execute(MESSAGES_TABLE, ()).await.unwrap();
-        conn.execute(ANALYSIS_LOG_TABLE, ()).await.unwrap();
+        let known = repo.get_known_chats().await.unwrap();
+        let titles: Vec<_> = known.iter().map(|c| (c.id, c.title.as_str())).collect();
+        assert_eq!(titles, vec![(5, "alice"), (-100, "Team (archived)")]);
+        assert_eq!(known[1].kind, ChatType::Supergroup);
+        assert_eq!(known[1].approx_message_count, Some(13));
 
-        conn
+        let conn = repo.connection().unwrap();
+        let mut rows = conn
+            .query("SELECT previous_titles FROM chats WHERE chat_id = -100", ())
+            .await
+            .unwrap();
+        let previous: String = rows.next().await.unwrap().unwrap().get(0).unwrap();
+        assert_eq!(previous, r#"["Team"]"#);
     }
 
-    /// Insert a test message with a specific timestamp.
-    async fn insert_message(
-        conn: &libsql::Connection,
-        chat_id: i64,
-        msg_id: i32,
-        timestamp: i64,
-        text: &str,
-    ) {
-        conn.execute(
-            "INSERT INTO messages (chat_id, id, date, text) VALUES (?1, ?2, ?3, ?4)",
-            params![chat_id, msg_id, timestamp, text],
+    #[tokio::test]
+    async fn test_chat_and_global_stats_use_aggregates() {
+        use std::path::PathBuf;
+
+        let base_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("target")
+            .join("test_archive_stats_db");
+        let _ = std::fs::remove_dir_all(&base_dir);
+        let repo = SqliteRepo::connect(&base_dir).await.expect("connect");
+        let msg = |chat_id: i64, id: i32, media: Option<MediaType>| Message {
+            media: media.map(|media_type| MediaReference {
+                message_id: id,
+                chat_id,
+                media_type,
+                opaque_ref: String::new(),
+                size_bytes: None,
+                filename: None,
+                mime_type: None,
+            }),
+            ..text_message(chat_id, id, 1704067200 + id as i64, "")
+        };
+        repo.save_messages(
+            1,
+            &[
+                msg(1, 1, None),
+                msg(1, 2, Some(MediaType::Photo)),
+                msg(1, 3, Some(MediaType::Photo)),
+                msg(1, 4, Some(MediaType::Voice)),
+            ],
         )
         .await
         .unwrap();
-    }
-
-    #[tokio::test]
-    async fn test_week_grouping_basic() {
-        let conn = setup_test_db().await;
-        let chat_id = 123i64;
+        repo.save_messages(2, &[msg(2, 10, None)]).await.unwrap();
+        repo.upsert_media_file(&MediaFile {
+            chat_id: 1,
+            message_id: 2,
+            media_type: MediaType::Photo,
+            rel_path: "1_2.jpg".to_string(),
+            size_bytes: Some(1000),
+            expected_size: None,
+            sha256: None,
+            status: MediaStatus::Done,
+            message_date: None,
+            remote_key: None,
+        })
+        .await
+        .unwrap();
 
-        // Week 1 of 2024: Jan 1-7, 2024
-        // Week 2 of 2024: Jan 8-14, 2024
-        let week1_ts = 1704067200i64; // 2024-01-01 00:00:00 UTC (Monday)
-        let week1_ts2 = 1704153600i64; // 2024-01-02 00:00:00 UTC (Tuesday)
-        let week2_ts = 1704672000i64; // 2024-01-08 00:00:00 UTC (Monday)
+        let chat = repo.get_chat_stats(1).await.unwrap();
+        assert_eq!(chat.messages, 4);
+        assert_eq!(chat.oldest, Some(1704067201));
+        assert_eq!(chat.newest, Some(1704067204));
+        assert_eq!(
+            chat.media_by_type,
+            vec![(MediaType::Photo, 2), (MediaType::Voice, 1)]
+        );
+        assert_eq!((chat.media_files, chat.media_bytes), (1, 1000));
+        assert_eq!(repo.get_chat_stats(99).await.unwrap().messages, 0);
 
-        insert_message(&conn, chat_id, 1, week1_ts, "Hello Week 1").await;
-        insert_message(&conn, chat_id, 2, week1_ts2, "Also Week 1").await;
-        insert_message(&conn, chat_id, 3, week2_ts, "Hello Week 2").await;
+        let global = repo.get_global_stats().await.unwrap();
+        assert_eq!(global.chats.len(), 2);
+        assert_eq!(global.messages(), 5);
+        assert_eq!(global.media_files(), 1);
+        assert!(global.db_size_bytes > 0);
 
-        // Query for week grouping
+        // The per-type count reads the expression index, not the message rows.
+        let conn = repo.connection().unwrap();
         let mut rows = conn
             .query(
-                r#"
-                SELECT strftime('%Y-%W', date, 'unixepoch') as week_group, COUNT(*) as cnt
-                FROM messages
-                WHERE chat_id = ?1 AND text != ''
-                GROUP BY week_group
-                ORDER BY week_group ASC
-                "#,
-                params![chat_id],
+                &format!(
+                    "EXPLAIN QUERY PLAN SELECT chat_id, {0}, COUNT(*) FROM messages WHERE media_json IS NOT NULL AND 1 GROUP BY chat_id, {0}",
+                    MEDIA_TYPE_EXPR
+                ),
+                (),
             )
             .await
             .unwrap();
-
-        let mut weeks = Vec::new();
+        let mut plan = String::new();
         while let Some(row) = rows.next().await.unwrap() {
-            let week: String = row.get(0).unwrap();
-            let count: i64 = row.get(1).unwrap();
-            weeks.push((week, count));
+            plan.push_str(&row.get::<String>(3).unwrap());
         }
+        assert!(plan.contains("idx_messages_media_type"), "{}", plan);
+    }
 
-        assert_eq!(weeks.len(), 2, "Should have 2 distinct weeks");
-        assert_eq!(weeks[0].1, 2, "Week 1 should have 2 messages");
-        assert_eq!(weeks[1].1, 1, "Week 2 should have 1 message");
+    /// Captionless album parts reach the analysis only when the album has a caption.
+    #[tokio::test]
+    async fn test_analysis_keeps_parts_of_captioned_albums() {
+        use std::path::PathBuf;
+
+        let base_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("target")
+            .join("test_album_analysis_db");
+        let _ = std::fs::remove_dir_all(&base_dir);
+        let repo = SqliteRepo::connect(&base_dir).await.expect("connect");
+        let part = |id: i32, text: &str, grouped_id: i64| Message {
+            grouped_id: Some(grouped_id),
+            ..text_message(1, id, 1704067200, text)
+        };
+        repo.save_messages(
+            1,
+            &[
+                part(1, "", 900),
+                part(2, "Trip photos", 900),
+                part(3, "", 900),
+                part(4, "", 901),
+                part(5, "", 901),
+                text_message(1, 6, 1704067300, "nice"),
+                text_message(1, 7, 1704067400, ""),
+            ],
+        )
+        .await
+        .unwrap();
+
+        let weeks = repo.get_periods(1, Granularity::Week).await.unwrap();
+        let messages = repo
+            .get_messages_for_week(1, Granularity::Week, &weeks[0])
+            .await
+            .unwrap();
+        let ids: Vec<(i32, Option<i64>)> = messages.iter().map(|m| (m.id, m.grouped_id)).collect();
+        assert_eq!(
+            ids,
+            vec![(1, Some(900)), (2, Some(900)), (3, Some(900)), (6, None)]
+        );
     }
 
     #[tokio::test]
-    async fn test_analysis_idempotency() {
-        let conn = setup_test_db().await;
-        let chat_id = 123i64;
+    async fn test_service_flag_migrates_and_filters_analysis() {
+        use std::path::PathBuf;
 
-        // Insert messages
-        let week1_ts = 1704067200i64; // 2024-01-01 (Week 00 or 01 depending on locale)
-        insert_message(&conn, chat_id, 1, week1_ts, "Hello").await;
+        let base_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("target")
+            .join("test_service_flag_db");
+        let _ = std::fs::remove_dir_all(&base_dir);
+        std::fs::create_dir_all(&base_dir).unwrap();
 
-        // Get the week group that was generated
-        let mut rows = conn
-            .query(
-                "SELECT DISTINCT strftime('%Y-%W', date, 'unixepoch') FROM messages WHERE chat_id = ?1",
-                params![chat_id],
+        // A messages table from before the service flag, holding a user message that mentions
+        // joining and the German text of a service message.
+        {
+            let db = libsql::Builder::new_local(base_dir.join("messages.db"))
+                .build()
+                .await
+                .unwrap();
+            let conn = db.connect().unwrap();
+            conn.execute(
+                "CREATE TABLE messages (chat_id INTEGER NOT NULL, id INTEGER NOT NULL, date INTEGER NOT NULL, text TEXT NOT NULL DEFAULT '', media_json TEXT, from_user_id INTEGER, reply_to_msg_id INTEGER, PRIMARY KEY (chat_id, id))",
+                (),
             )
             .await
             .unwrap();
-        let week_group: String = rows.next().await.unwrap().unwrap().get(0).unwrap();
-
-        // Initially, the week should be unanalyzed
-        let mut rows = conn
-            .query(
-                r#"
-                SELECT strftime('%Y-%W', date, 'unixepoch') as week_group
-                FROM messages
-                WHERE chat_id = ?1
-                  AND strftime('%Y-%W', date, 'unixepoch') NOT IN (
-                      SELECT week_group FROM analysis_log WHERE chat_id = ?1
-                  )
-                GROUP BY week_group
-                "#,
-                params![chat_id],
+            conn.execute(
+                "INSERT INTO messages (chat_id, id, date, text) VALUES (1, 1, 1704067200, 'So glad you joined the group!'), (1, 2, 1704067300, 'Bob ist der Gruppe beigetreten')",
+                (),
             )
             .await
             .unwrap();
-        let unanalyzed: Option<_> = rows.next().await.unwrap();
-        assert!(unanalyzed.is_some(), "Week should be unanalyzed initially");
+        }
 
-        // Mark the week as analyzed
-        conn.execute(
-            "INSERT INTO analysis_log (chat_id, week_group, analyzed_at, summary, result_json) VALUES (?1, ?2, ?3, ?4, ?5)",
-            params![chat_id, week_group.as_str(), 1704067200i64, "Test summary", "{}"],
-        )
+        let repo = SqliteRepo::connect(&base_dir).await.expect("connect");
+        let week = PeriodGroup::new("2024-01");
+        let texts = |messages: Vec<Message>| -> Vec<String> {
+            messages.into_iter().map(|m| m.text).collect()
+        };
+        // Backfilled as regular messages: nothing is dropped for its wording.
+        let weeks = repo
+            .get_messages_for_week(1, Granularity::Week, &week)
+            .await
+            .unwrap();
+        assert_eq!(texts(weeks).len(), 2);
+
+        // The service message's event flags the stored row; the user message stays.
+        repo.save_events(&[ChatEvent {
+            id: 2,
+            chat_id: 1,
+            date: 1704067300,
+            kind: ChatEventKind::UserJoined,
+            actor_id: Some(8),
+            payload: None,
+        }])
         .await
         .unwrap();
-
-        // Now the week should NOT appear in unanalyzed list
-        let mut rows = conn
-            .query(
-                r#"
-                SELECT strftime('%Y-%W', date, 'unixepoch') as week_group
-                FROM messages
-                WHERE chat_id = ?1
-                  AND strftime('%Y-%W', date, 'unixepoch') NOT IN (
-                      SELECT week_group FROM analysis_log WHERE chat_id = ?1
-                  )
-                GROUP BY week_group
-                "#,
-                params![chat_id],
-            )
+        let weeks = repo
+            .get_messages_for_week(1, Granularity::Week, &week)
             .await
             .unwrap();
-        let unanalyzed_after: Option<_> = rows.next().await.unwrap();
-        assert!(
-            unanalyzed_after.is_none(),
-            "Week should NOT appear after being analyzed"
+        assert_eq!(texts(weeks), vec!["So glad you joined the group!"]);
+        assert_eq!(
+            repo.get_unanalyzed_weeks(1, Granularity::Week)
+                .await
+                .unwrap()
+                .len(),
+            1
         );
+
+        let _ = std::fs::remove_dir_all(&base_dir);
     }
 
     #[tokio::test]
-    async fn test_service_message_filtering() {
-        let conn = setup_test_db().await;
-        let chat_id = 123i64;
-        let ts = 1704067200i64;
+    async fn test_forward_columns_migrate_and_round_trip() {
+        use std::path::PathBuf;
 
-        // Insert regular message
-        insert_message(&conn, chat_id, 1, ts, "Hello world").await;
-        // Insert service messages that should be filtered
-        insert_message(&conn, chat_id, 2, ts, "User joined the group").await;
-        insert_message(
-            &conn,
-            chat_id,
-            3,
-            ts,
-            "Admin left the group via invite link",
-        )
-        .await;
-        // Insert empty message
-        insert_message(&conn, chat_id, 4, ts, "").await;
+        let base_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("target")
+            .join("test_forward_columns_db");
+        let _ = std::fs::remove_dir_all(&base_dir);
+        std::fs::create_dir_all(&base_dir).unwrap();
 
-        // Query with filters (same as get_messages_by_week)
-        let mut rows = conn
-            .query(
-                r#"
-                SELECT COUNT(*) FROM messages
-                WHERE chat_id = ?1
-                  AND text != ''
-                  AND text NOT LIKE '%joined the group%'
-                  AND text NOT LIKE '%left the group%'
-                "#,
-                params![chat_id],
+        // A messages table from before forward provenance (and topics) was archived.
+        {
+            let db = libsql::Builder::new_local(base_dir.join("messages.db"))
+                .build()
+                .await
+                .unwrap();
+            let conn = db.connect().unwrap();
+            conn.execute(
+                "CREATE TABLE messages (chat_id INTEGER NOT NULL, id INTEGER NOT NULL, date INTEGER NOT NULL, text TEXT NOT NULL DEFAULT '', media_json TEXT, from_user_id INTEGER, reply_to_msg_id INTEGER, PRIMARY KEY (chat_id, id))",
+                (),
             )
             .await
             .unwrap();
+            conn.execute(
+                "INSERT INTO messages (chat_id, id, date, text) VALUES (1, 1, 1704067200, 'old')",
+                (),
+            )
+            .await
+            .unwrap();
+        }
 
-        let count: i64 = rows.next().await.unwrap().unwrap().get(0).unwrap();
-        assert_eq!(
-            count, 1,
-            "Only the regular message should remain after filtering"
-        );
+        let repo = SqliteRepo::connect(&base_dir).await.expect("connect");
+        let forwarded = Message {
+            from_user_id: Some(7),
+            forwarded_from_name: Some("Daily News".to_string()),
+            forwarded_from_id: Some(-1000000000042),
+            forwarded_date: Some(1704000000),
+            ..text_message(1, 2, 1704067300, "quoted news")
+        };
+        repo.save_messages(1, &[forwarded]).await.unwrap();
+
+        let stored = repo.get_messages_by_ids(1, &[1, 2]).await.unwrap();
+        assert_eq!(stored.len(), 2);
+        assert_eq!(stored[0].text, "old");
+        assert_eq!(stored[0].forwarded_from(), None);
+        assert_eq!(stored[1].forwarded_from_name.as_deref(), Some("Daily News"));
+        assert_eq!(stored[1].forwarded_from_id, Some(-1000000000042));
+        assert_eq!(stored[1].forwarded_date, Some(1704000000));
     }
 
-    /// Message versioning: saving the same message ID with new text appends the previous version to edit_history.
+    /// Re-saving a poll refreshes its results without recording an edit.
     #[tokio::test]
-    async fn test_edit_history_versioning() {
+    async fn test_poll_results_refresh_without_edit_history() {
+        use crate::domain::{Poll, PollAnswer};
         use std::path::PathBuf;
 
         let base_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
             .join("target")
-            .join("test_edit_history_db");
+            .join("test_poll_refresh_db");
+        let _ = std::fs::remove_dir_all(&base_dir);
         let repo = SqliteRepo::connect(&base_dir).await.expect("connect");
 
-        let chat_id = 999i64;
-        let msg_id = 1i32;
-        let ts_a = 1704067200i64;
-        let ts_b = 1704153600i64;
-
-        let msg_a = Message {
-            id: msg_id,
-            chat_id,
-            date: ts_a,
-            text: "Text A".to_string(),
-            media: None,
-            from_user_id: None,
-            reply_to_msg_id: None,
-            edit_history: None,
+        let poll = |pizza: i32, sushi: i32| Poll {
+            question: "Lunch?".to_string(),
+            answers: vec![
+                PollAnswer {
+                    text: "Pizza".to_string(),
+                    voters: Some(pizza),
+                },
+                PollAnswer {
+                    text: "Sushi".to_string(),
+                    voters: Some(sushi),
+                },
+            ],
+            quiz: false,
+            closed: false,
+            total_voters: Some(pizza + sushi),
         };
-        repo.save_messages(chat_id, &[msg_a]).await.unwrap();
-
-        let msg_b = Message {
-            id: msg_id,
-            chat_id,
-            date: ts_b,
-            text: "Text B".to_string(),
-            media: None,
-            from_user_id: None,
-            reply_to_msg_id: None,
-            edit_history: None,
+        let message = |poll: Poll| Message {
+            text: poll.render(),
+            from_user_id: Some(7),
+            poll: Some(poll),
+            ..text_message(5, 1, 1704067200, "")
         };
-        repo.save_messages(chat_id, &[msg_b]).await.unwrap();
+        repo.save_messages(5, &[message(poll(1, 0))]).await.unwrap();
+        repo.save_messages(5, &[message(poll(12, 3))])
+            .await
+            .unwrap();
 
-        let messages = repo.get_messages(chat_id, 10, 0).await.unwrap();
-        assert_eq!(messages.len(), 1, "should have one message");
-        let m = &messages[0];
-        assert_eq!(m.text, "Text B", "current text should be B");
-        let history = m
-            .edit_history
-            .as_ref()
-            .expect("edit_history should be present");
-        assert!(!history.is_empty(), "edit_history should not be empty");
-        assert_eq!(history.len(), 1, "one prior version");
-        assert_eq!(history[0].text, "Text A", "prior version should be Text A");
-        assert_eq!(
-            history[0].date, ts_a,
-            "prior version should have original date"
-        );
+        let stored = repo.get_messages_by_ids(5, &[1]).await.unwrap();
+        assert_eq!(stored[0].text, "[Poll] Lunch? — Pizza (12), Sushi (3)");
+        assert_eq!(stored[0].poll, Some(poll(12, 3)));
+        assert!(stored[0].edit_history.is_none());
+        let hits = repo.search_messages("sushi", None, 10, 0).await.unwrap();
+        assert_eq!(hits.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_connect_upgrades_v1_database_to_latest_version() {
+        use std::path::PathBuf;
+
+        let base_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("target")
+            .join("test_schema_v1_db");
+        let _ = std::fs::remove_dir_all(&base_dir);
+        std::fs::create_dir_all(&base_dir).unwrap();
+
+        // A database at schema version 1 with a message and a downloaded file.
+        {
+            let db = libsql::Builder::new_local(base_dir.join("messages.db"))
+                .build()
+                .await
+                .unwrap();
+            let conn = db.connect().unwrap();
+            assert_eq!(
+                migrations::migrate(&conn, &MIGRATIONS[..1]).await.unwrap(),
+                1
+            );
+            conn.execute(
+                "INSERT INTO messages (chat_id, id, date, text) VALUES (1, 1, 1704067200, 'kept')",
+                (),
+            )
+            .await
+            .unwrap();
+            conn.execute(
+                "INSERT INTO media_files (chat_id, message_id, media_type, rel_path, status, updated_at) VALUES (1, 1, 'photo', '1_1.jpg', 'done', 0)",
+                (),
+            )
+            .await
+            .unwrap();
+        }
+
+        let repo = SqliteRepo::connect(&base_dir).await.expect("connect");
+        let conn = repo.connection().unwrap();
+        let latest = MIGRATIONS.last().unwrap().version;
+        assert_eq!(migrations::current_version(&conn).await.unwrap(), latest);
+        let mut rows = conn
+            .query(
+                "SELECT 1 FROM sqlite_master WHERE type = 'index' AND name = 'idx_media_files_status'",
+                (),
+            )
+            .await
+            .unwrap();
+        assert!(rows.next().await.unwrap().is_some());
+
+        let stored = repo.get_messages(1, 10, 0).await.unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].text, "kept");
+        assert!(!stored[0].mentioned);
+        // Seeded keywords survive the rebuild of watch_rules.
+        let rules = repo.list_watch_rules().await.unwrap();
+        assert_eq!(rules.len(), DEFAULT_WATCH_KEYWORDS.len());
+        assert!(rules.iter().all(|r| r.kind == WatchRuleKind::Keyword));
+        let files = repo
+            .list_media_files(Some(1), None, None, 10)
+            .await
+            .unwrap();
+        assert_eq!(files[0].rel_path, "1_1.jpg");
+
+        // Reconnecting applies nothing.
+        drop(repo);
+        let repo = SqliteRepo::connect(&base_dir).await.expect("reconnect");
+        let conn = repo.connection().unwrap();
+        assert_eq!(migrations::current_version(&conn).await.unwrap(), latest);
+        let mut rows = conn
+            .query("SELECT COUNT(*) FROM schema_version", ())
+            .await
+            .unwrap();
+        let row = rows.next().await.unwrap().unwrap();
+        assert_eq!(row.get::<i64>(0).unwrap(), latest as i64);
+    }
+
+    #[tokio::test]
+    async fn test_period_bounds_match_strftime() {
+        let conn = libsql::Builder::new_local(":memory:")
+            .build()
+            .await
+            .unwrap()
+            .connect()
+            .unwrap();
+        // Year boundaries (week 00 and the partial last week), a leap day, a month end.
+        let dates = [
+            1704067200, // 2024-01-01, a Monday
+            1704067199, // 2023-12-31 23:59:59
+            1672531200, // 2023-01-01, a Sunday (week 00)
+            1672617600, // 2023-01-02
+            1709164800, // 2024-02-29
+            1711929599, // 2024-03-31 23:59:59
+            1735603200, // 2024-12-31
+        ];
+        for granularity in Granularity::ALL {
+            for date in dates {
+                let mut rows = conn
+                    .query(
+                        "SELECT strftime(?1, ?2, 'unixepoch')",
+                        params![granularity.strftime_format(), date],
+                    )
+                    .await
+                    .unwrap();
+                let key: String = rows.next().await.unwrap().unwrap().get(0).unwrap();
+                let (from, until) = period_bounds(granularity, &key).unwrap();
+                assert!(
+                    from <= date && date < until,
+                    "{:?} {} -> {} [{}, {})",
+                    granularity,
+                    date,
+                    key,
+                    from,
+                    until
+                );
+                // Just outside the range is another period.
+                for outside in [from - 1, until] {
+                    let mut rows = conn
+                        .query(
+                            "SELECT strftime(?1, ?2, 'unixepoch')",
+                            params![granularity.strftime_format(), outside],
+                        )
+                        .await
+                        .unwrap();
+                    let other: String = rows.next().await.unwrap().unwrap().get(0).unwrap();
+                    assert_ne!(other, key, "{:?} {}", granularity, outside);
+                }
+            }
+        }
+        assert_eq!(period_bounds(Granularity::Week, "bogus"), None);
     }
 }
@@ -1,20 +1,25 @@
 //! Implements StatePort using a JSON file.
 //!
-//! Tracks last_message_id per chat for incremental sync.
+//! Tracks last_message_id per chat for incremental sync, plus the low-water mark of an
+//! unfinished backfill and the chats a bounded sync left a gap in.
 
 use crate::domain::DomainError;
 use crate::ports::StatePort;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::ErrorKind;
 use std::path::Path;
 use tokio::fs;
 use tokio::io::AsyncWriteExt;
 
-/// State: chat_id -> last_message_id
+/// State: chat_id -> last_message_id, chat_id -> backfill_low_id, chats with a sync gap
 #[derive(Debug, Default, Serialize, Deserialize)]
 struct StateData {
     last_message_ids: HashMap<i64, i32>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    backfill_low_ids: HashMap<i64, i32>,
+    #[serde(default, skip_serializing_if = "HashSet::is_empty")]
+    sync_gaps: HashSet<i64>,
 }
 
 /// JSON file-based state storage.
@@ -49,6 +54,11 @@ impl StateJson {
         Ok(())
     }
 
+    /// All checkpoints currently loaded (chat_id -> last_message_id).
+    pub async fn last_message_ids(&self) -> HashMap<i64, i32> {
+        self.cache.read().await.last_message_ids.clone()
+    }
+
     /// Audit §2.3: Atomic save using write-replace pattern.
     /// 1. Write to temp file
     /// 2. sync_all() to ensure flush to disk
@@ -97,4 +107,47 @@ impl StatePort for StateJson {
         }
         self.save().await
     }
+
+    async fn get_backfill_low_id(&self, chat_id: i64) -> Result<i32, DomainError> {
+        let cache = self.cache.read().await;
+        Ok(cache.backfill_low_ids.get(&chat_id).copied().unwrap_or(0))
+    }
+
+    async fn set_backfill_low_id(&self, chat_id: i64, message_id: i32) -> Result<(), DomainError> {
+        {
+            let mut cache = self.cache.write().await;
+            if message_id > 0 {
+                cache.backfill_low_ids.insert(chat_id, message_id);
+            } else if cache.backfill_low_ids.remove(&chat_id).is_none() {
+                return Ok(());
+            }
+        }
+        self.save().await
+    }
+
+    async fn has_sync_gap(&self, chat_id: i64) -> Result<bool, DomainError> {
+        Ok(self.cache.read().await.sync_gaps.contains(&chat_id))
+    }
+
+    async fn set_sync_gap(&self, chat_id: i64, gap: bool) -> Result<(), DomainError> {
+        let changed = {
+            let mut cache = self.cache.write().await;
+            if gap {
+                cache.sync_gaps.insert(chat_id)
+            } else {
+                cache.sync_gaps.remove(&chat_id)
+            }
+        };
+        if changed { self.save().await } else { Ok(()) }
+    }
+
+    async fn clear(&self, chat_id: i64) -> Result<(), DomainError> {
+        let removed = {
+            let mut cache = self.cache.write().await;
+            let low = cache.backfill_low_ids.remove(&chat_id).is_some();
+            let gap = cache.sync_gaps.remove(&chat_id);
+            cache.last_message_ids.remove(&chat_id).is_some() || low || gap
+        };
+        if removed { self.save().await } else { Ok(()) }
+    }
 }
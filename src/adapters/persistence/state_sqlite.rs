@@ -0,0 +1,268 @@
+//! Implements StatePort with the `sync_state` table of the SQLite archive.
+//!
+//! Same data as `StateJson`, but every checkpoint is one UPSERT in messages.db (cheap with
+//! WAL) instead of rewriting and fsyncing a JSON file, and there is a single source of truth.
+
+use super::sqlite_repo::SqliteRepo;
+use super::state_json::StateJson;
+use crate::domain::DomainError;
+use crate::ports::StatePort;
+use libsql::params;
+use std::path::Path;
+use std::sync::Arc;
+use tracing::info;
+
+/// SQLite-backed sync state.
+pub struct StateSqlite {
+    repo: Arc<SqliteRepo>,
+}
+
+impl StateSqlite {
+    pub fn new(repo: Arc<SqliteRepo>) -> Self {
+        Self { repo }
+    }
+
+    /// One-time migration: copy the checkpoints of an existing `state.json` into `sync_state`
+    /// while the table is still empty. Later calls (and a missing file) import nothing; the
+    /// JSON file is left in place. Returns the number of imported chats.
+    pub async fn import_json(&self, path: &Path) -> Result<u64, DomainError> {
+        let conn = self.repo.connection()?;
+        let mut rows = conn
+            .query("SELECT COUNT(*) FROM sync_state", ())
+            .await
+            .map_err(|e| DomainError::State(e.to_string()))?;
+        let existing: i64 = match rows
+            .next()
+            .await
+            .map_err(|e| DomainError::State(e.to_string()))?
+        {
+            Some(row) => row.get(0).map_err(|e| DomainError::State(e.to_string()))?,
+            None => 0,
+        };
+        if existing > 0 || !path.exists() {
+            return Ok(0);
+        }
+
+        let json = StateJson::new(path);
+        json.load().await?;
+        let checkpoints = json.last_message_ids().await;
+        let now = chrono::Utc::now().timestamp();
+        let tx = conn
+            .transaction()
+            .await
+            .map_err(|e| DomainError::State(e.to_string()))?;
+        for (chat_id, last_id) in &checkpoints {
+            tx.execute(
+                "INSERT OR IGNORE INTO sync_state (chat_id, last_message_id, updated_at) VALUES (?1, ?2, ?3)",
+                params![*chat_id, *last_id, now],
+            )
+            .await
+            .map_err(|e| DomainError::State(e.to_string()))?;
+        }
+        tx.commit()
+            .await
+            .map_err(|e| DomainError::State(e.to_string()))?;
+        info!(
+            chats = checkpoints.len(),
+            path = %path.display(),
+            "imported sync checkpoints from state.json"
+        );
+        Ok(checkpoints.len() as u64)
+    }
+}
+
+#[async_trait::async_trait]
+impl StatePort for StateSqlite {
+    async fn get_last_message_id(&self, chat_id: i64) -> Result<i32, DomainError> {
+        let conn = self.repo.connection()?;
+        let mut rows = conn
+            .query(
+                "SELECT last_message_id FROM sync_state WHERE chat_id = ?1",
+                params![chat_id],
+            )
+            .await
+            .map_err(|e| DomainError::State(e.to_string()))?;
+        match rows
+            .next()
+            .await
+            .map_err(|e| DomainError::State(e.to_string()))?
+        {
+            Some(row) => row.get(0).map_err(|e| DomainError::State(e.to_string())),
+            None => Ok(0),
+        }
+    }
+
+    async fn get_backfill_low_id(&self, chat_id: i64) -> Result<i32, DomainError> {
+        let conn = self.repo.connection()?;
+        let mut rows = conn
+            .query(
+                "SELECT backfill_low_id FROM sync_state WHERE chat_id = ?1",
+                params![chat_id],
+            )
+            .await
+            .map_err(|e| DomainError::State(e.to_string()))?;
+        match rows
+            .next()
+            .await
+            .map_err(|e| DomainError::State(e.to_string()))?
+        {
+            Some(row) => row.get(0).map_err(|e| DomainError::State(e.to_string())),
+            None => Ok(0),
+        }
+    }
+
+    /// A chat without a checkpoint row gets one at 0, so the mark survives until the pass
+    /// stores its first batch.
+    async fn set_backfill_low_id(&self, chat_id: i64, message_id: i32) -> Result<(), DomainError> {
+        let conn = self.repo.connection()?;
+        conn.execute(
+            r#"
+            INSERT INTO sync_state (chat_id, last_message_id, updated_at, backfill_low_id)
+            VALUES (?1, 0, ?2, ?3)
+            ON CONFLICT (chat_id) DO UPDATE SET
+                backfill_low_id = excluded.backfill_low_id,
+                updated_at = excluded.updated_at
+            "#,
+            params![chat_id, chrono::Utc::now().timestamp(), message_id.max(0)],
+        )
+        .await
+        .map_err(|e| DomainError::State(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn set_last_message_id(&self, chat_id: i64, message_id: i32) -> Result<(), DomainError> {
+        let conn = self.repo.connection()?;
+        conn.execute(
+            r#"
+            INSERT INTO sync_state (chat_id, last_message_id, updated_at) VALUES (?1, ?2, ?3)
+            ON CONFLICT (chat_id) DO UPDATE SET
+                last_message_id = excluded.last_message_id,
+                updated_at = excluded.updated_at
+            "#,
+            params![chat_id, message_id, chrono::Utc::now().timestamp()],
+        )
+        .await
+        .map_err(|e| DomainError::State(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn has_sync_gap(&self, chat_id: i64) -> Result<bool, DomainError> {
+        let conn = self.repo.connection()?;
+        let mut rows = conn
+            .query(
+                "SELECT sync_gap FROM sync_state WHERE chat_id = ?1",
+                params![chat_id],
+            )
+            .await
+            .map_err(|e| DomainError::State(e.to_string()))?;
+        match rows
+            .next()
+            .await
+            .map_err(|e| DomainError::State(e.to_string()))?
+        {
+            Some(row) => Ok(row
+                .get::<i64>(0)
+                .map_err(|e| DomainError::State(e.to_string()))?
+                != 0),
+            None => Ok(false),
+        }
+    }
+
+    /// Like the low-water mark, a chat without a checkpoint row gets one at 0.
+    async fn set_sync_gap(&self, chat_id: i64, gap: bool) -> Result<(), DomainError> {
+        let conn = self.repo.connection()?;
+        conn.execute(
+            r#"
+            INSERT INTO sync_state (chat_id, last_message_id, updated_at, sync_gap)
+            VALUES (?1, 0, ?2, ?3)
+            ON CONFLICT (chat_id) DO UPDATE SET
+                sync_gap = excluded.sync_gap,
+                updated_at = excluded.updated_at
+            "#,
+            params![chat_id, chrono::Utc::now().timestamp(), gap as i64],
+        )
+        .await
+        .map_err(|e| DomainError::State(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn clear(&self, chat_id: i64) -> Result<(), DomainError> {
+        let conn = self.repo.connection()?;
+        conn.execute(
+            "DELETE FROM sync_state WHERE chat_id = ?1",
+            params![chat_id],
+        )
+        .await
+        .map_err(|e| DomainError::State(e.to_string()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_checkpoints_persist_and_json_is_imported_once() {
+        let dir = test_dir("test_state_sqlite");
+        let json_path = dir.join("state.json");
+        std::fs::write(
+            &json_path,
+            r#"{"last_message_ids": {"-1001": 500, "42": 7}}"#,
+        )
+        .unwrap();
+
+        let state = StateSqlite::new(Arc::new(SqliteRepo::connect(&dir).await.unwrap()));
+        assert_eq!(state.import_json(&json_path).await.unwrap(), 2);
+        assert_eq!(state.get_last_message_id(-1001).await.unwrap(), 500);
+        assert_eq!(state.get_last_message_id(42).await.unwrap(), 7);
+        assert_eq!(state.get_last_message_id(99).await.unwrap(), 0);
+
+        state.set_last_message_id(-1001, 650).await.unwrap();
+        state.set_last_message_id(99, 3).await.unwrap();
+
+        // Reconnect: checkpoints survive, and the (now stale) JSON is not imported again.
+        let state = StateSqlite::new(Arc::new(SqliteRepo::connect(&dir).await.unwrap()));
+        assert_eq!(state.import_json(&json_path).await.unwrap(), 0);
+        assert_eq!(state.get_last_message_id(-1001).await.unwrap(), 650);
+        assert_eq!(state.get_last_message_id(99).await.unwrap(), 3);
+        assert!(json_path.exists());
+
+        // The low-water mark is independent of the checkpoint and cleared with it.
+        assert_eq!(state.get_backfill_low_id(-1001).await.unwrap(), 0);
+        state.set_backfill_low_id(-1001, 120).await.unwrap();
+        state.set_last_message_id(-1001, 700).await.unwrap();
+        assert_eq!(state.get_backfill_low_id(-1001).await.unwrap(), 120);
+        assert_eq!(state.get_last_message_id(-1001).await.unwrap(), 700);
+        state.clear(-1001).await.unwrap();
+        assert_eq!(state.get_backfill_low_id(-1001).await.unwrap(), 0);
+
+        // So is the gap flag; setting it on an unknown chat keeps the checkpoint at 0.
+        state.set_sync_gap(7, true).await.unwrap();
+        assert!(state.has_sync_gap(7).await.unwrap());
+        assert_eq!(state.get_last_message_id(7).await.unwrap(), 0);
+        state.set_sync_gap(7, false).await.unwrap();
+        assert!(!state.has_sync_gap(7).await.unwrap());
+        state.set_sync_gap(7, true).await.unwrap();
+        state.clear(7).await.unwrap();
+        assert!(!state.has_sync_gap(7).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_import_without_json_file_is_noop() {
+        let dir = test_dir("test_state_sqlite_no_json");
+        let state = StateSqlite::new(Arc::new(SqliteRepo::connect(&dir).await.unwrap()));
+        assert_eq!(state.import_json(&dir.join("state.json")).await.unwrap(), 0);
+        assert_eq!(state.get_last_message_id(1).await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,336 @@
+//! `RepoPort` adapter that writes to two repositories (TG_SYNC_REPO_BACKEND=both).
+//!
+//! Every write goes to the primary (SQLite) first and fails when the primary fails; it is
+//! then repeated on the secondary (the JSONL repository), whose failures are logged and
+//! counted but never fail the write, so a full disk on the JSONL side doesn't abort a backup.
+//! Reads always come from the primary. The blacklist, targets and per-chat settings are sync
+//! settings, not archive data: they are kept by the primary only.
+
+use crate::domain::{
+    ActivityBin, ActivityBucket, ArchiveStats, Chat, ChatEvent, ChatPurge, ChatSettings, ChatStats,
+    DomainError, ForumTopic, Message, SearchHit, TimeRange, User,
+};
+use crate::ports::RepoPort;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::warn;
+
+/// Primary repository plus best-effort secondary.
+pub struct TeeRepo {
+    primary: Arc<dyn RepoPort>,
+    secondary: Arc<dyn RepoPort>,
+    /// Secondary writes that failed since startup.
+    failures: AtomicU64,
+}
+
+impl TeeRepo {
+    pub fn new(primary: Arc<dyn RepoPort>, secondary: Arc<dyn RepoPort>) -> Self {
+        Self {
+            primary,
+            secondary,
+            failures: AtomicU64::new(0),
+        }
+    }
+
+    /// Number of secondary writes that failed since startup.
+    pub fn failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    /// Log and count a failed secondary write; None then.
+    fn secondary<T>(&self, op: &'static str, result: Result<T, DomainError>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(e) => {
+                let failures = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+                warn!(op, failures, error = %e, "secondary repository write failed; primary unaffected");
+                None
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl RepoPort for TeeRepo {
+    async fn save_messages(&self, chat_id: i64, messages: &[Message]) -> Result<(), DomainError> {
+        self.primary.save_messages(chat_id, messages).await?;
+        self.secondary(
+            "save_messages",
+            self.secondary.save_messages(chat_id, messages).await,
+        );
+        Ok(())
+    }
+
+    async fn save_users(&self, users: &[User]) -> Result<(), DomainError> {
+        self.primary.save_users(users).await?;
+        self.secondary("save_users", self.secondary.save_users(users).await);
+        Ok(())
+    }
+
+    async fn get_messages(
+        &self,
+        chat_id: i64,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<Message>, DomainError> {
+        self.primary.get_messages(chat_id, limit, offset).await
+    }
+
+    async fn get_messages_after(
+        &self,
+        chat_id: i64,
+        after_id: i32,
+        limit: u32,
+    ) -> Result<Vec<Message>, DomainError> {
+        self.primary
+            .get_messages_after(chat_id, after_id, limit)
+            .await
+    }
+
+    async fn get_messages_in_range(
+        &self,
+        chat_id: i64,
+        range: TimeRange,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<Message>, DomainError> {
+        self.primary
+            .get_messages_in_range(chat_id, range, limit, offset)
+            .await
+    }
+
+    async fn get_messages_by_ids(
+        &self,
+        chat_id: i64,
+        ids: &[i32],
+    ) -> Result<Vec<Message>, DomainError> {
+        self.primary.get_messages_by_ids(chat_id, ids).await
+    }
+
+    async fn search_messages(
+        &self,
+        query: &str,
+        chat_id: Option<i64>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<SearchHit>, DomainError> {
+        self.primary
+            .search_messages(query, chat_id, limit, offset)
+            .await
+    }
+
+    async fn get_blacklisted_ids(&self) -> Result<HashSet<i64>, DomainError> {
+        self.primary.get_blacklisted_ids().await
+    }
+
+    async fn update_blacklist(&self, ids: HashSet<i64>) -> Result<(), DomainError> {
+        self.primary.update_blacklist(ids).await
+    }
+
+    async fn get_target_ids(&self) -> Result<HashSet<i64>, DomainError> {
+        self.primary.get_target_ids().await
+    }
+
+    async fn update_targets(&self, ids: HashSet<i64>) -> Result<(), DomainError> {
+        self.primary.update_targets(ids).await
+    }
+
+    async fn get_chat_settings(&self, chat_id: i64) -> Result<ChatSettings, DomainError> {
+        self.primary.get_chat_settings(chat_id).await
+    }
+
+    async fn list_chat_settings(&self) -> Result<Vec<ChatSettings>, DomainError> {
+        self.primary.list_chat_settings().await
+    }
+
+    async fn set_chat_settings(&self, settings: &ChatSettings) -> Result<(), DomainError> {
+        self.primary.set_chat_settings(settings).await
+    }
+
+    async fn get_activity_histogram(
+        &self,
+        chat_id: i64,
+        bucket: ActivityBucket,
+        range: TimeRange,
+        utc_offset_secs: i32,
+    ) -> Result<Vec<ActivityBin>, DomainError> {
+        self.primary
+            .get_activity_histogram(chat_id, bucket, range, utc_offset_secs)
+            .await
+    }
+
+    async fn register_synthetic_chat(&self, chat: &Chat) -> Result<(), DomainError> {
+        self.primary.register_synthetic_chat(chat).await?;
+        self.secondary(
+            "register_synthetic_chat",
+            self.secondary.register_synthetic_chat(chat).await,
+        );
+        Ok(())
+    }
+
+    async fn upsert_chats(&self, chats: &[Chat]) -> Result<(), DomainError> {
+        self.primary.upsert_chats(chats).await?;
+        self.secondary("upsert_chats", self.secondary.upsert_chats(chats).await);
+        Ok(())
+    }
+
+    async fn save_forum_topics(
+        &self,
+        chat_id: i64,
+        topics: &[ForumTopic],
+    ) -> Result<(), DomainError> {
+        self.primary.save_forum_topics(chat_id, topics).await?;
+        self.secondary(
+            "save_forum_topics",
+            self.secondary.save_forum_topics(chat_id, topics).await,
+        );
+        Ok(())
+    }
+
+    async fn save_events(&self, events: &[ChatEvent]) -> Result<(), DomainError> {
+        self.primary.save_events(events).await?;
+        self.secondary("save_events", self.secondary.save_events(events).await);
+        Ok(())
+    }
+
+    async fn get_events(&self, chat_id: i64) -> Result<Vec<ChatEvent>, DomainError> {
+        self.primary.get_events(chat_id).await
+    }
+
+    async fn link_channel_comments(&self, chat_id: i64) -> Result<u64, DomainError> {
+        let linked = self.primary.link_channel_comments(chat_id).await?;
+        self.secondary(
+            "link_channel_comments",
+            self.secondary.link_channel_comments(chat_id).await,
+        );
+        Ok(linked)
+    }
+
+    async fn get_channel_comments(
+        &self,
+        channel_id: i64,
+        post_ids: &[i32],
+    ) -> Result<Vec<Message>, DomainError> {
+        self.primary
+            .get_channel_comments(channel_id, post_ids)
+            .await
+    }
+
+    async fn get_known_chats(&self) -> Result<Vec<Chat>, DomainError> {
+        self.primary.get_known_chats().await
+    }
+
+    async fn get_chat_stats(&self, chat_id: i64) -> Result<ChatStats, DomainError> {
+        self.primary.get_chat_stats(chat_id).await
+    }
+
+    async fn get_global_stats(&self) -> Result<ArchiveStats, DomainError> {
+        self.primary.get_global_stats().await
+    }
+
+    /// Rows are reported from the primary; files the secondary removed are added.
+    async fn delete_chat_data(&self, chat_id: i64) -> Result<ChatPurge, DomainError> {
+        let mut purge = self.primary.delete_chat_data(chat_id).await?;
+        if let Some(secondary) = self.secondary(
+            "delete_chat_data",
+            self.secondary.delete_chat_data(chat_id).await,
+        ) {
+            purge.media_paths.extend(secondary.media_paths);
+            purge.files += secondary.files;
+            purge.bytes += secondary.bytes;
+        }
+        Ok(purge)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::persistence::fs_repo::FsRepo;
+    use crate::adapters::persistence::sqlite_repo::SqliteRepo;
+    use crate::testing::fake_tg::text_message;
+    use std::path::{Path, PathBuf};
+
+    async fn setup(name: &str) -> (PathBuf, Arc<SqliteRepo>) {
+        let base = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join(name);
+        let _ = std::fs::remove_dir_all(&base);
+        let sqlite = Arc::new(SqliteRepo::connect(&base).await.unwrap());
+        (base, sqlite)
+    }
+
+    fn msg(id: i32, text: &str) -> Message {
+        text_message(7, id, 1704067200 + id as i64, text)
+    }
+
+    /// A file where the JSONL directory should be: every write to it fails.
+    fn blocked_jsonl(base: &Path) -> Arc<FsRepo> {
+        let blocked = base.join("blocked");
+        std::fs::write(&blocked, "").unwrap();
+        Arc::new(FsRepo::new(blocked))
+    }
+
+    #[tokio::test]
+    async fn test_writes_reach_both_and_lists_stay_primary() {
+        let (base, sqlite) = setup("test_tee_repo").await;
+        let jsonl = Arc::new(FsRepo::new(base.join("jsonl")));
+        let repo = TeeRepo::new(sqlite.clone(), jsonl.clone());
+
+        repo.save_messages(7, &[msg(1, "a"), msg(2, "b")])
+            .await
+            .unwrap();
+        repo.update_blacklist(HashSet::from([9])).await.unwrap();
+        repo.update_targets(HashSet::from([7])).await.unwrap();
+
+        assert_eq!(sqlite.get_messages(7, 10, 0).await.unwrap().len(), 2);
+        assert_eq!(jsonl.get_messages(7, 10, 0).await.unwrap().len(), 2);
+        assert_eq!(
+            repo.get_blacklisted_ids().await.unwrap(),
+            HashSet::from([9])
+        );
+        assert!(jsonl.get_blacklisted_ids().await.unwrap().is_empty());
+        assert!(jsonl.get_target_ids().await.unwrap().is_empty());
+
+        let purge = repo.delete_chat_data(7).await.unwrap();
+        assert_eq!(purge.rows, vec![("messages", 2), ("targets", 1)]);
+        assert_eq!(purge.files, 1);
+        assert!(jsonl.get_messages(7, 10, 0).await.unwrap().is_empty());
+        assert_eq!(repo.failures(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_secondary_failure_does_not_fail_writes() {
+        let (base, sqlite) = setup("test_tee_repo_secondary_failure").await;
+        let repo = TeeRepo::new(sqlite.clone(), blocked_jsonl(&base));
+
+        repo.save_messages(7, &[msg(1, "a")]).await.unwrap();
+        repo.save_messages(7, &[msg(2, "b")]).await.unwrap();
+        repo.upsert_chats(&[Chat {
+            id: 7,
+            title: "Team".to_string(),
+            username: None,
+            kind: crate::domain::ChatType::Group,
+            approx_message_count: None,
+            is_forum: false,
+            last_message_date: None,
+        }])
+        .await
+        .unwrap();
+        assert_eq!(repo.failures(), 3);
+        assert_eq!(repo.get_messages(7, 10, 0).await.unwrap().len(), 2);
+        assert_eq!(repo.get_known_chats().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_primary_failure_fails_and_skips_secondary() {
+        let (base, _sqlite) = setup("test_tee_repo_primary_failure").await;
+        let jsonl = Arc::new(FsRepo::new(base.join("jsonl")));
+        let repo = TeeRepo::new(blocked_jsonl(&base), jsonl.clone());
+
+        assert!(repo.save_messages(7, &[msg(1, "a")]).await.is_err());
+        assert!(jsonl.get_messages(7, 10, 0).await.unwrap().is_empty());
+        assert_eq!(repo.failures(), 0);
+    }
+}
@@ -0,0 +1,137 @@
+//! Record-and-replay fixtures for the Telegram gateway.
+//!
+//! [`RecordingTgGateway`] wraps a live `TgGateway` and writes every call (method, arguments,
+//! domain-level result or error) to a numbered JSON file: `000001_get_messages.json`, ...
+//! [`ReplayTgGateway`] reads such a directory and answers calls from it, either strictly in
+//! recorded order or keyed by a hash of method + arguments. Enabled via `TG_SYNC_RECORD_DIR`
+//! (record) and `TG_SYNC_REPLAY_DIR` (replay, no Telegram login).
+
+pub mod recorder;
+pub mod replay;
+
+pub use recorder::RecordingTgGateway;
+pub use replay::{ReplayMode, ReplayTgGateway};
+
+use crate::domain::DomainError;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// One recorded gateway call. Serialized as one JSON file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedCall {
+    /// 1-based call number in recording order.
+    pub seq: u64,
+    pub method: String,
+    /// Stable hash of method + args; used by keyed replay.
+    pub key: String,
+    pub args: Value,
+    pub result: RecordedResult,
+}
+
+/// Domain-level outcome of a call.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordedResult {
+    Ok(Value),
+    Err(RecordedError),
+}
+
+/// Serializable mirror of [`DomainError`]. Keeps FloodWait seconds so retry logic can be replayed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RecordedError {
+    TgGateway { message: String },
+    Repo { message: String },
+    State { message: String },
+    Processor { message: String },
+    Auth { message: String },
+    Media { message: String },
+    FloodWait { seconds: u64 },
+    TakeoutDelay { seconds: u64 },
+    Ai { message: String },
+    TaskTracker { message: String },
+    Ingest { message: String },
+    Notify { message: String },
+    WatchRule { message: String },
+    Crypto { message: String },
+    Storage { message: String },
+    Cancelled,
+}
+
+impl From<&DomainError> for RecordedError {
+    fn from(e: &DomainError) -> Self {
+        match e {
+            DomainError::TgGateway(m) => Self::TgGateway { message: m.clone() },
+            DomainError::Repo(m) => Self::Repo { message: m.clone() },
+            DomainError::State(m) => Self::State { message: m.clone() },
+            DomainError::Processor(m) => Self::Processor { message: m.clone() },
+            DomainError::Auth(m) => Self::Auth { message: m.clone() },
+            DomainError::Media(m) => Self::Media { message: m.clone() },
+            DomainError::FloodWait { seconds } => Self::FloodWait { seconds: *seconds },
+            DomainError::TakeoutDelay { seconds } => Self::TakeoutDelay { seconds: *seconds },
+            DomainError::Ai(m) => Self::Ai { message: m.clone() },
+            DomainError::TaskTracker(m) => Self::TaskTracker { message: m.clone() },
+            DomainError::Ingest(m) => Self::Ingest { message: m.clone() },
+            DomainError::Notify(m) => Self::Notify { message: m.clone() },
+            DomainError::WatchRule(m) => Self::WatchRule { message: m.clone() },
+            DomainError::Crypto(m) => Self::Crypto { message: m.clone() },
+            DomainError::Storage(m) => Self::Storage { message: m.clone() },
+            DomainError::Cancelled => Self::Cancelled,
+        }
+    }
+}
+
+impl From<RecordedError> for DomainError {
+    fn from(e: RecordedError) -> Self {
+        match e {
+            RecordedError::TgGateway { message } => DomainError::TgGateway(message),
+            RecordedError::Repo { message } => DomainError::Repo(message),
+            RecordedError::State { message } => DomainError::State(message),
+            RecordedError::Processor { message } => DomainError::Processor(message),
+            RecordedError::Auth { message } => DomainError::Auth(message),
+            RecordedError::Media { message } => DomainError::Media(message),
+            RecordedError::FloodWait { seconds } => DomainError::FloodWait { seconds },
+            RecordedError::TakeoutDelay { seconds } => DomainError::TakeoutDelay { seconds },
+            RecordedError::Ai { message } => DomainError::Ai(message),
+            RecordedError::TaskTracker { message } => DomainError::TaskTracker(message),
+            RecordedError::Ingest { message } => DomainError::Ingest(message),
+            RecordedError::Notify { message } => DomainError::Notify(message),
+            RecordedError::WatchRule { message } => DomainError::WatchRule(message),
+            RecordedError::Crypto { message } => DomainError::Crypto(message),
+            RecordedError::Storage { message } => DomainError::Storage(message),
+            RecordedError::Cancelled => DomainError::Cancelled,
+        }
+    }
+}
+
+/// Result payload of a recorded `download_media` call. The file itself is not recorded;
+/// replay writes a placeholder of the same size at the requested destination.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedDownload {
+    pub file_name: Option<String>,
+    pub size_bytes: u64,
+}
+
+/// Stable key for a call: FNV-1a 64 over `method` and the canonical JSON of `args`
+/// (serde_json maps are sorted, so equal arguments always serialize identically).
+pub fn call_key(method: &str, args: &Value) -> String {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    let mut hash = OFFSET;
+    let canonical = args.to_string();
+    for b in method
+        .as_bytes()
+        .iter()
+        .chain(b"\n")
+        .chain(canonical.as_bytes())
+    {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(PRIME);
+    }
+    format!("{:016x}", hash)
+}
+
+/// File name for a recorded call, e.g. `000042_get_messages.json`.
+pub fn call_file_name(seq: u64, method: &str) -> String {
+    format!("{:06}_{}.json", seq, method)
+}
@@ -0,0 +1,198 @@
+//! Recording decorator: forwards to the inner gateway and writes each call to disk.
+
+use super::{
+    RecordedCall, RecordedDownload, RecordedError, RecordedResult, call_file_name, call_key,
+};
+use crate::domain::{
+    Chat, ChatEvent, DiscussionLink, DomainError, ForumTopic, MediaReference, Message, User,
+};
+use crate::ports::TgGateway;
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::{info, warn};
+
+/// `TgGateway` decorator that records every call to numbered JSON files in `dir`.
+/// Recording failures are logged and never affect the call result.
+pub struct RecordingTgGateway {
+    inner: Arc<dyn TgGateway>,
+    dir: PathBuf,
+    seq: AtomicU64,
+}
+
+impl RecordingTgGateway {
+    /// Create the recorder. Numbering continues after any calls already in `dir`.
+    pub fn new(inner: Arc<dyn TgGateway>, dir: impl AsRef<Path>) -> Result<Self, DomainError> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| DomainError::TgGateway(format!("create record dir: {}", e)))?;
+        let existing = std::fs::read_dir(&dir)
+            .map_err(|e| DomainError::TgGateway(format!("read record dir: {}", e)))?
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().extension().is_some_and(|x| x == "json"))
+            .count() as u64;
+        info!(dir = %dir.display(), existing, "recording Telegram gateway calls");
+        Ok(Self {
+            inner,
+            dir,
+            seq: AtomicU64::new(existing),
+        })
+    }
+
+    /// Write one call. `ok` converts the success value to JSON.
+    async fn record<T, F>(&self, method: &str, args: Value, result: &Result<T, DomainError>, ok: F)
+    where
+        F: FnOnce(&T) -> Value,
+    {
+        let seq = self.seq.fetch_add(1, Ordering::SeqCst) + 1;
+        let call = RecordedCall {
+            seq,
+            method: method.to_string(),
+            key: call_key(method, &args),
+            args,
+            result: match result {
+                Ok(v) => RecordedResult::Ok(ok(v)),
+                Err(e) => RecordedResult::Err(RecordedError::from(e)),
+            },
+        };
+        let path = self.dir.join(call_file_name(seq, method));
+        let written = match serde_json::to_vec_pretty(&call) {
+            Ok(bytes) => tokio::fs::write(&path, bytes)
+                .await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        if let Err(e) = written {
+            warn!(path = %path.display(), error = %e, "failed to record gateway call");
+        }
+    }
+}
+
+fn to_value<T: Serialize>(v: &T) -> Value {
+    serde_json::to_value(v).unwrap_or(Value::Null)
+}
+
+#[async_trait::async_trait]
+impl TgGateway for RecordingTgGateway {
+    async fn get_dialogs(&self) -> Result<Vec<Chat>, DomainError> {
+        let result = self.inner.get_dialogs().await;
+        self.record("get_dialogs", json!({}), &result, to_value)
+            .await;
+        result
+    }
+
+    async fn get_messages(
+        &self,
+        chat_id: i64,
+        min_id: i32,
+        max_id: i32,
+        limit: i32,
+    ) -> Result<Vec<Message>, DomainError> {
+        let result = self
+            .inner
+            .get_messages(chat_id, min_id, max_id, limit)
+            .await;
+        let args =
+            json!({ "chat_id": chat_id, "min_id": min_id, "max_id": max_id, "limit": limit });
+        self.record("get_messages", args, &result, to_value).await;
+        result
+    }
+
+    async fn download_media(
+        &self,
+        media_ref: &MediaReference,
+        dest_path: &Path,
+    ) -> Result<(), DomainError> {
+        let result = self.inner.download_media(media_ref, dest_path).await;
+        // Key on the media only: the destination depends on the local data dir.
+        let args = json!({ "media_ref": to_value(media_ref) });
+        let size_bytes = match &result {
+            Ok(()) => tokio::fs::metadata(dest_path)
+                .await
+                .map(|m| m.len())
+                .unwrap_or(0),
+            Err(_) => 0,
+        };
+        let download = RecordedDownload {
+            file_name: dest_path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned()),
+            size_bytes,
+        };
+        self.record("download_media", args, &result, |_| to_value(&download))
+            .await;
+        result
+    }
+
+    async fn get_me_id(&self) -> Result<i64, DomainError> {
+        let result = self.inner.get_me_id().await;
+        self.record("get_me_id", json!({}), &result, to_value).await;
+        result
+    }
+
+    async fn send_message(&self, chat_id: i64, text: &str) -> Result<(), DomainError> {
+        let result = self.inner.send_message(chat_id, text).await;
+        let args = json!({ "chat_id": chat_id, "text": text });
+        self.record("send_message", args, &result, |_| Value::Null)
+            .await;
+        result
+    }
+
+    async fn get_forum_topics(&self, chat_id: i64) -> Result<Vec<ForumTopic>, DomainError> {
+        let result = self.inner.get_forum_topics(chat_id).await;
+        self.record(
+            "get_forum_topics",
+            json!({ "chat_id": chat_id }),
+            &result,
+            to_value,
+        )
+        .await;
+        result
+    }
+
+    /// Passed through unrecorded: replays have no sender names.
+    async fn get_users_from_batch(&self) -> Result<Vec<User>, DomainError> {
+        self.inner.get_users_from_batch().await
+    }
+
+    /// Passed through unrecorded: replays never open a takeout session.
+    async fn begin_takeout(&self) -> Result<(), DomainError> {
+        self.inner.begin_takeout().await
+    }
+
+    async fn end_takeout(&self, success: bool) -> Result<(), DomainError> {
+        self.inner.end_takeout(success).await
+    }
+
+    /// Passed through unrecorded: replays have no discussion groups.
+    async fn get_discussion_message(
+        &self,
+        channel_id: i64,
+        msg_id: i32,
+    ) -> Result<Option<DiscussionLink>, DomainError> {
+        self.inner.get_discussion_message(channel_id, msg_id).await
+    }
+
+    /// Passed through unrecorded: replays have no service messages.
+    async fn get_events_from_batch(&self) -> Result<Vec<ChatEvent>, DomainError> {
+        self.inner.get_events_from_batch().await
+    }
+
+    async fn resolve_chat(&self, reference: &str) -> Result<Chat, DomainError> {
+        let result = self.inner.resolve_chat(reference).await;
+        self.record(
+            "resolve_chat",
+            json!({ "reference": reference }),
+            &result,
+            to_value,
+        )
+        .await;
+        result
+    }
+
+    fn is_bot(&self) -> bool {
+        self.inner.is_bot()
+    }
+}
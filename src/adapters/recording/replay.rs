@@ -0,0 +1,373 @@
+//! Replay gateway: answers `TgGateway` calls from a directory written by the recorder.
+
+use super::{RecordedCall, RecordedDownload, RecordedResult, call_key};
+use crate::domain::{Chat, DomainError, ForumTopic, MediaReference, Message};
+use crate::ports::TgGateway;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// How recorded calls are matched to incoming calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayMode {
+    /// Calls must arrive in recorded order; any deviation is an error. Best for regression tests.
+    Sequential,
+    /// Calls are matched by method + args; repeated identical calls get successive responses.
+    /// Tolerates reordering (e.g. concurrent media downloads).
+    Keyed,
+}
+
+/// `TgGateway` that plays back recorded calls. Never touches the network.
+pub struct ReplayTgGateway {
+    mode: ReplayMode,
+    /// Remaining calls in recorded order (Sequential).
+    queue: Mutex<VecDeque<RecordedCall>>,
+    /// Remaining calls grouped by key (Keyed).
+    by_key: Mutex<HashMap<String, VecDeque<RecordedCall>>>,
+}
+
+impl ReplayTgGateway {
+    /// Load all `*.json` calls from `dir`, ordered by sequence number.
+    pub fn open(dir: impl AsRef<Path>, mode: ReplayMode) -> Result<Self, DomainError> {
+        let dir = dir.as_ref();
+        let mut calls = Vec::new();
+        let entries = std::fs::read_dir(dir)
+            .map_err(|e| DomainError::TgGateway(format!("read replay dir {:?}: {}", dir, e)))?;
+        for entry in entries.filter_map(|e| e.ok()) {
+            let path = entry.path();
+            if path.extension().is_none_or(|x| x != "json") {
+                continue;
+            }
+            let bytes = std::fs::read(&path)
+                .map_err(|e| DomainError::TgGateway(format!("read {:?}: {}", path, e)))?;
+            let call: RecordedCall = serde_json::from_slice(&bytes)
+                .map_err(|e| DomainError::TgGateway(format!("parse {:?}: {}", path, e)))?;
+            calls.push(call);
+        }
+        calls.sort_by_key(|c| c.seq);
+        Ok(Self::from_calls(calls, mode))
+    }
+
+    /// Build from in-memory calls (already in recorded order).
+    pub fn from_calls(calls: Vec<RecordedCall>, mode: ReplayMode) -> Self {
+        let mut by_key: HashMap<String, VecDeque<RecordedCall>> = HashMap::new();
+        if mode == ReplayMode::Keyed {
+            for call in &calls {
+                by_key
+                    .entry(call.key.clone())
+                    .or_default()
+                    .push_back(call.clone());
+            }
+        }
+        Self {
+            mode,
+            queue: Mutex::new(calls.into()),
+            by_key: Mutex::new(by_key),
+        }
+    }
+
+    /// Number of recorded calls not yet consumed.
+    pub fn remaining(&self) -> usize {
+        match self.mode {
+            ReplayMode::Sequential => self.queue.lock().map(|q| q.len()).unwrap_or(0),
+            ReplayMode::Keyed => self
+                .by_key
+                .lock()
+                .map(|m| m.values().map(|q| q.len()).sum())
+                .unwrap_or(0),
+        }
+    }
+
+    /// Take the recorded call matching `method` + `args`.
+    fn take(&self, method: &str, args: &Value) -> Result<RecordedCall, DomainError> {
+        let key = call_key(method, args);
+        match self.mode {
+            ReplayMode::Sequential => {
+                let mut queue = self.queue.lock().map_err(|_| poisoned())?;
+                let Some(next) = queue.front() else {
+                    return Err(DomainError::TgGateway(format!(
+                        "replay exhausted: unexpected {} {}",
+                        method, args
+                    )));
+                };
+                if next.method != method || next.key != key {
+                    return Err(DomainError::TgGateway(format!(
+                        "replay mismatch at #{}: recorded {} {}, got {} {}",
+                        next.seq, next.method, next.args, method, args
+                    )));
+                }
+                Ok(queue.pop_front().expect("front checked"))
+            }
+            ReplayMode::Keyed => {
+                let mut by_key = self.by_key.lock().map_err(|_| poisoned())?;
+                by_key
+                    .get_mut(&key)
+                    .and_then(|q| q.pop_front())
+                    .ok_or_else(|| {
+                        DomainError::TgGateway(format!("no recorded call for {} {}", method, args))
+                    })
+            }
+        }
+    }
+
+    /// Replay a call and decode its success payload.
+    fn replay<T: DeserializeOwned>(&self, method: &str, args: Value) -> Result<T, DomainError> {
+        match self.take(method, &args)?.result {
+            RecordedResult::Ok(v) => serde_json::from_value(v)
+                .map_err(|e| DomainError::TgGateway(format!("decode recorded {}: {}", method, e))),
+            RecordedResult::Err(e) => Err(e.into()),
+        }
+    }
+}
+
+fn poisoned() -> DomainError {
+    DomainError::TgGateway("replay state poisoned".to_string())
+}
+
+#[async_trait::async_trait]
+impl TgGateway for ReplayTgGateway {
+    async fn get_dialogs(&self) -> Result<Vec<Chat>, DomainError> {
+        self.replay("get_dialogs", json!({}))
+    }
+
+    async fn get_messages(
+        &self,
+        chat_id: i64,
+        min_id: i32,
+        max_id: i32,
+        limit: i32,
+    ) -> Result<Vec<Message>, DomainError> {
+        let args =
+            json!({ "chat_id": chat_id, "min_id": min_id, "max_id": max_id, "limit": limit });
+        self.replay("get_messages", args)
+    }
+
+    async fn download_media(
+        &self,
+        media_ref: &MediaReference,
+        dest_path: &Path,
+    ) -> Result<(), DomainError> {
+        let media = serde_json::to_value(media_ref).unwrap_or(Value::Null);
+        let download: RecordedDownload =
+            self.replay("download_media", json!({ "media_ref": media }))?;
+        // Placeholder with the recorded size (sparse; content is not recorded).
+        let file = tokio::fs::File::create(dest_path)
+            .await
+            .map_err(|e| DomainError::Media(e.to_string()))?;
+        file.set_len(download.size_bytes)
+            .await
+            .map_err(|e| DomainError::Media(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn get_me_id(&self) -> Result<i64, DomainError> {
+        self.replay("get_me_id", json!({}))
+    }
+
+    async fn send_message(&self, chat_id: i64, text: &str) -> Result<(), DomainError> {
+        let args = json!({ "chat_id": chat_id, "text": text });
+        let _: Value = self.replay("send_message", args)?;
+        Ok(())
+    }
+
+    async fn get_forum_topics(&self, chat_id: i64) -> Result<Vec<ForumTopic>, DomainError> {
+        self.replay("get_forum_topics", json!({ "chat_id": chat_id }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::recording::RecordingTgGateway;
+    use crate::domain::{ChatType, MediaType};
+    use crate::testing::fake_tg::text_message;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    /// Live-gateway stand-in: canned dialogs, two history pages, one FloodWait, a download.
+    struct ScriptedGateway;
+
+    fn msg(chat_id: i64, id: i32) -> Message {
+        Message {
+            from_user_id: Some(7),
+            ..text_message(
+                chat_id,
+                id,
+                1704067200 + id as i64,
+                &format!("message {}", id),
+            )
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl TgGateway for ScriptedGateway {
+        async fn get_dialogs(&self) -> Result<Vec<Chat>, DomainError> {
+            Ok(vec![Chat {
+                id: 10,
+                title: "Project X".to_string(),
+                username: None,
+                kind: ChatType::Supergroup,
+                approx_message_count: Some(3),
+                is_forum: false,
+                last_message_date: None,
+            }])
+        }
+
+        async fn get_messages(
+            &self,
+            chat_id: i64,
+            _min_id: i32,
+            max_id: i32,
+            _limit: i32,
+        ) -> Result<Vec<Message>, DomainError> {
+            match max_id {
+                0 => Ok(vec![msg(chat_id, 3), msg(chat_id, 2)]),
+                2 => Err(DomainError::FloodWait { seconds: 17 }),
+                _ => Ok(vec![]),
+            }
+        }
+
+        async fn download_media(
+            &self,
+            _media_ref: &MediaReference,
+            dest_path: &Path,
+        ) -> Result<(), DomainError> {
+            tokio::fs::write(dest_path, b"12345")
+                .await
+                .map_err(|e| DomainError::Media(e.to_string()))
+        }
+
+        async fn get_me_id(&self) -> Result<i64, DomainError> {
+            Ok(42)
+        }
+
+        async fn send_message(&self, _chat_id: i64, _text: &str) -> Result<(), DomainError> {
+            Ok(())
+        }
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("target")
+            .join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn media_ref() -> MediaReference {
+        MediaReference {
+            message_id: 3,
+            chat_id: 10,
+            media_type: MediaType::Photo,
+            opaque_ref: "ref".to_string(),
+            size_bytes: None,
+            filename: None,
+            mime_type: None,
+        }
+    }
+
+    /// Drive the same call sequence against any gateway; returns comparable JSON per call.
+    async fn drive(gw: &dyn TgGateway, media_dir: &Path) -> Vec<String> {
+        let mut out = Vec::new();
+        out.push(format!("{:?}", gw.get_dialogs().await.map(|v| to_json(&v))));
+        out.push(format!(
+            "{:?}",
+            gw.get_messages(10, 0, 0, 100).await.map(|v| to_json(&v))
+        ));
+        out.push(format!(
+            "{:?}",
+            gw.get_messages(10, 0, 2, 100).await.map(|v| to_json(&v))
+        ));
+        out.push(format!("{:?}", gw.get_me_id().await));
+        out.push(format!("{:?}", gw.send_message(42, "alert").await));
+        let dest = media_dir.join("10_3.jpg");
+        out.push(format!(
+            "{:?}",
+            gw.download_media(&media_ref(), &dest).await
+        ));
+        out.push(format!(
+            "{:?}",
+            std::fs::metadata(&dest).map(|m| m.len()).ok()
+        ));
+        out
+    }
+
+    fn to_json<T: serde::Serialize>(v: &T) -> String {
+        serde_json::to_string(v).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay_sequential_round_trip() {
+        let rec_dir = test_dir("test_replay_record_dir");
+        let live_media = test_dir("test_replay_live_media");
+        let replay_media = test_dir("test_replay_replayed_media");
+
+        let recorder = RecordingTgGateway::new(Arc::new(ScriptedGateway), &rec_dir).unwrap();
+        let live = drive(&recorder, &live_media).await;
+
+        let files = std::fs::read_dir(&rec_dir).unwrap().count();
+        assert_eq!(files, 6, "one file per call");
+        assert!(rec_dir.join("000002_get_messages.json").exists());
+
+        let replay = ReplayTgGateway::open(&rec_dir, ReplayMode::Sequential).unwrap();
+        let replayed = drive(&replay, &replay_media).await;
+        assert_eq!(replayed, live);
+        assert_eq!(replay.remaining(), 0);
+
+        // FloodWait survives the round trip with its seconds.
+        assert!(live[2].contains("FloodWait { seconds: 17 }"), "{}", live[2]);
+        // Placeholder download has the recorded size.
+        assert_eq!(replayed[6], "Some(5)");
+    }
+
+    #[tokio::test]
+    async fn test_replay_sequential_rejects_out_of_order_calls() {
+        let rec_dir = test_dir("test_replay_order_dir");
+        let media = test_dir("test_replay_order_media");
+        let recorder = RecordingTgGateway::new(Arc::new(ScriptedGateway), &rec_dir).unwrap();
+        drive(&recorder, &media).await;
+
+        let replay = ReplayTgGateway::open(&rec_dir, ReplayMode::Sequential).unwrap();
+        let err = replay.get_me_id().await.unwrap_err();
+        assert!(err.to_string().contains("replay mismatch at #1"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_replay_keyed_matches_by_args() {
+        let rec_dir = test_dir("test_replay_keyed_dir");
+        let recorder = RecordingTgGateway::new(Arc::new(ScriptedGateway), &rec_dir).unwrap();
+        // Same page twice: keyed replay must hand out both responses in order.
+        recorder.get_messages(10, 0, 0, 100).await.unwrap();
+        recorder.get_me_id().await.unwrap();
+        recorder.get_messages(10, 0, 0, 100).await.unwrap();
+
+        let replay = ReplayTgGateway::open(&rec_dir, ReplayMode::Keyed).unwrap();
+        assert_eq!(replay.get_me_id().await.unwrap(), 42);
+        assert_eq!(replay.get_messages(10, 0, 0, 100).await.unwrap().len(), 2);
+        assert_eq!(replay.get_messages(10, 0, 0, 100).await.unwrap().len(), 2);
+        assert!(
+            replay.get_messages(10, 0, 0, 100).await.is_err(),
+            "exhausted"
+        );
+        assert!(
+            replay.get_messages(11, 0, 0, 100).await.is_err(),
+            "never recorded"
+        );
+        assert_eq!(replay.remaining(), 0);
+    }
+
+    #[test]
+    fn test_call_key_is_stable_and_arg_sensitive() {
+        let a = call_key("get_messages", &json!({ "chat_id": 1, "limit": 100 }));
+        let b = call_key("get_messages", &json!({ "limit": 100, "chat_id": 1 }));
+        let c = call_key("get_messages", &json!({ "chat_id": 2, "limit": 100 }));
+        let d = call_key("get_dialogs", &json!({ "chat_id": 1, "limit": 100 }));
+        assert_eq!(a, b, "key order does not matter");
+        assert_ne!(a, c);
+        assert_ne!(a, d);
+        assert_eq!(a.len(), 16);
+    }
+}
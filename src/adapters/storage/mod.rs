@@ -0,0 +1,20 @@
+//! Remote storage adapters. Implement `RemoteStoragePort` (S3-compatible object storage).
+
+pub mod s3;
+
+pub use s3::S3Storage;
+
+use crate::domain::DomainError;
+use crate::ports::RemoteStoragePort;
+use crate::shared::config::AppConfig;
+use std::sync::Arc;
+
+/// Build the remote storage configured in the config, if any.
+///
+/// Misconfiguration (missing keys, bad endpoint URL) is an error here, so it surfaces at
+/// startup and in `tg-sync config validate` rather than on the first upload.
+pub fn remote_storage_from_config(
+    cfg: &AppConfig,
+) -> Result<Option<Arc<dyn RemoteStoragePort>>, DomainError> {
+    Ok(S3Storage::from_config(cfg)?.map(|s3| Arc::new(s3) as Arc<dyn RemoteStoragePort>))
+}
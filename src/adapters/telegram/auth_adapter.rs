@@ -2,12 +2,16 @@
 //!
 //! Holds a client (clone shared with TgGateway in main). No global lock.
 //! Stores login token and password token between calls for the auth flow.
+//! QR login uses raw `auth.exportLoginToken` / `auth.importLoginToken` invokes; bots sign in
+//! with their token in one call.
 
-use crate::domain::{DomainError, SignInResult};
+use crate::domain::{DomainError, QrLoginStatus, QrToken, SignInResult};
 use crate::ports::AuthPort;
 use async_trait::async_trait;
 use grammers_client::Client;
+use grammers_client::InvocationError;
 use grammers_client::client::{LoginToken, PasswordToken};
+use grammers_client::tl;
 use tokio::sync::Mutex;
 
 /// Auth adapter. Wraps grammers Client for login/2FA. Same session as TgGateway via clone in main.
@@ -17,6 +21,10 @@ pub struct GrammersAuthAdapter {
     login_token: Mutex<Option<LoginToken>>,
     /// Token from sign_in(PasswordRequired); consumed by check_password.
     password_token: Mutex<Option<PasswordToken>>,
+    /// (api_id, api_hash) from request_qr_login; reused by poll_qr_login.
+    qr_credentials: Mutex<Option<(i32, String)>>,
+    /// Last unscanned QR token returned by exportLoginToken.
+    qr_token: Mutex<Option<QrToken>>,
 }
 
 impl GrammersAuthAdapter {
@@ -26,8 +34,89 @@ impl GrammersAuthAdapter {
             client,
             login_token: Mutex::new(None),
             password_token: Mutex::new(None),
+            qr_credentials: Mutex::new(None),
+            qr_token: Mutex::new(None),
         }
     }
+
+    /// Export a login token. Telegram answers with a fresh token while nobody has scanned
+    /// the current one, and with the authorization (or a DC migration) once it was accepted.
+    async fn export_login_token(&self) -> Result<QrLoginStatus, DomainError> {
+        let (api_id, api_hash) = self.qr_credentials.lock().await.clone().ok_or_else(|| {
+            DomainError::Auth("request_qr_login must be called before poll_qr_login".into())
+        })?;
+        let req = tl::functions::auth::ExportLoginToken {
+            api_id,
+            api_hash,
+            except_ids: Vec::new(),
+        };
+        match self.client.invoke(&req).await {
+            Ok(answer) => self.qr_status(answer).await,
+            Err(e) => self.password_or_error(e).await,
+        }
+    }
+
+    /// Turn an exportLoginToken / importLoginToken answer into a status; a fresh token is
+    /// kept in `qr_token` for request_qr_login.
+    async fn qr_status(
+        &self,
+        answer: tl::enums::auth::LoginToken,
+    ) -> Result<QrLoginStatus, DomainError> {
+        use tl::enums::auth::LoginToken as Answer;
+        match answer {
+            Answer::Token(t) => {
+                *self.qr_token.lock().await = Some(QrToken {
+                    url: format!("tg://login?token={}", base64url(&t.token)),
+                    expires_at: i64::from(t.expires),
+                });
+                Ok(QrLoginStatus::Pending)
+            }
+            Answer::Success(_) => Ok(QrLoginStatus::Accepted(SignInResult::Success)),
+            // The account lives in another DC: finish the login there.
+            Answer::MigrateTo(m) => {
+                let req = tl::functions::auth::ImportLoginToken { token: m.token };
+                match self.client.invoke_in_dc(m.dc_id, &req).await {
+                    Ok(Answer::Success(_)) => Ok(QrLoginStatus::Accepted(SignInResult::Success)),
+                    Ok(_) => Ok(QrLoginStatus::Pending),
+                    Err(e) => self.password_or_error(e).await,
+                }
+            }
+        }
+    }
+
+    /// The QR was accepted but the account has 2FA enabled (SESSION_PASSWORD_NEEDED): fetch
+    /// the password token for check_password. Any other error fails the login.
+    async fn password_or_error(&self, e: InvocationError) -> Result<QrLoginStatus, DomainError> {
+        if !e.is("SESSION_PASSWORD_NEEDED") {
+            return Err(DomainError::Auth(format!("qr login: {}", e)));
+        }
+        let pt = self
+            .client
+            .get_password_information()
+            .await
+            .map_err(|e| DomainError::Auth(format!("get_password_information: {}", e)))?;
+        let hint = pt.hint().map(String::from);
+        *self.password_token.lock().await = Some(pt);
+        Ok(QrLoginStatus::Accepted(SignInResult::PasswordRequired {
+            hint,
+        }))
+    }
+}
+
+/// Unpadded base64url, as expected in `tg://login?token=` links.
+fn base64url(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | u32::from(*b) << (16 - 8 * i));
+        for i in 0..=chunk.len() {
+            out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+    }
+    out
 }
 
 #[async_trait]
@@ -81,4 +170,30 @@ impl AuthPort for GrammersAuthAdapter {
             .map_err(|e| DomainError::Auth(format!("check_password: {}", e)))?;
         Ok(())
     }
+
+    async fn request_qr_login(&self, api_id: i32, api_hash: &str) -> Result<QrToken, DomainError> {
+        *self.qr_credentials.lock().await = Some((api_id, api_hash.to_string()));
+        *self.qr_token.lock().await = None;
+        *self.password_token.lock().await = None;
+        match self.export_login_token().await? {
+            QrLoginStatus::Pending => self.qr_token.lock().await.take().ok_or_else(|| {
+                DomainError::Auth("qr login: Telegram returned no login token".into())
+            }),
+            QrLoginStatus::Accepted(_) => Err(DomainError::Auth(
+                "qr login: session was authorized while requesting a token".into(),
+            )),
+        }
+    }
+
+    async fn poll_qr_login(&self) -> Result<QrLoginStatus, DomainError> {
+        self.export_login_token().await
+    }
+
+    async fn sign_in_bot(&self, token: &str, api_hash: &str) -> Result<(), DomainError> {
+        self.client
+            .bot_sign_in(token, api_hash)
+            .await
+            .map_err(|e| DomainError::Auth(format!("bot sign in: {}", e)))?;
+        Ok(())
+    }
 }
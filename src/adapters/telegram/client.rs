@@ -1,53 +1,127 @@
 //! Implements TgGateway using grammers Client.
 //!
-//! Handles FloodWait by sleeping and retrying. Uses raw invoke for GetHistory
-//! with min_id for incremental sync.
+//! Every request waits for a slot of the shared rate limiter first (history or media budget);
+//! a FloodWait pauses and slows down the limiter, short ones are then retried. Uses raw invoke
+//! for GetHistory with min_id for incremental sync.
+//!
+//! Peers resolve through the entity registry first: a stored access_hash rebuilds the InputPeer
+//! without a request, so a known chat needs no getDialogs after a restart. Dialog walks (cold
+//! misses and `get_dialogs`) store what they find for next time, so one listing prewarms the
+//! peer cache for every chat a Full Backup, Blacklist or Watcher run goes on to touch.
+//!
+//! During a takeout session (`begin_takeout`), GetHistory is wrapped in invokeWithTakeout and
+//! photos and documents are downloaded with raw upload.getFile requests wrapped the same way,
+//! for the relaxed flood limits Telegram grants exports.
+//!
+//! A bot account (`with_bot_chats`) can neither list its dialogs nor call GetHistory. Its
+//! "dialogs" are the configured chats, resolved one by one (`resolve_chat`); history is read by
+//! message id with channels.getMessages / messages.getMessages, and media through the same raw
+//! upload.getFile path as takeout downloads.
 
 use crate::adapters::telegram::mapper;
-use crate::domain::{Chat, DomainError, MediaReference, Message};
-use crate::ports::TgGateway;
+use crate::domain::{
+    Chat, ChatEvent, DiscussionLink, DomainError, ForumTopic, MediaReference, Message, User,
+};
+use crate::ports::{EntityRegistry, TgGateway};
+use crate::shared::chat_link::ChatReference;
+use crate::shared::rate_limiter::{RateLimiter, RequestKind};
 use async_trait::async_trait;
 use grammers_client::Client;
 use grammers_client::InvocationError;
 use grammers_client::tl;
 use std::collections::HashMap;
+use std::future::Future;
 use std::path::Path;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
 use tokio::sync::{Mutex, Notify};
 use tracing::{debug, info, warn};
 
+/// Topics requested per channels.getForumTopics page (the API maximum).
+const FORUM_TOPICS_PAGE: i32 = 100;
+
+/// Largest file a takeout session may download (4 GiB, the Premium upload limit).
+const TAKEOUT_FILE_MAX_SIZE: i64 = 4 * 1024 * 1024 * 1024;
+
+/// Bytes per upload.getFile request in takeout downloads (divides 1 MiB, as required).
+const FILE_CHUNK_BYTES: i32 = 512 * 1024;
+
+/// Message ids a bot account reads per getMessages request.
+const BOT_ID_WINDOW: i32 = 100;
+
 /// Audit §4.1: FloodWait threshold in seconds. Waits below this sleep; waits >= this return error.
 const FLOOD_WAIT_THRESHOLD_SECS: u64 = 60;
 
 /// Telegram gateway adapter. Wraps grammers Client (clone shared with auth adapter; no global lock).
 pub struct GrammersTgGateway {
     client: Client,
-    /// If set, sleep this many ms before each message-history request (rate limiting).
-    export_delay_ms: Option<u64>,
+    /// History and media request budgets, shared by every caller of this gateway.
+    limiter: RateLimiter,
     /// Audit §2.1: Cache full Peer objects by chat_id to avoid iter_dialogs on every call.
     /// Stores the Peer (not just InputPeer) so we can call to_ref() for download operations.
     peer_cache: Mutex<HashMap<i64, grammers_client::peer::Peer>>,
+    /// Persistent access_hash cache (entity_registry table).
+    registry: Arc<dyn EntityRegistry>,
+    /// InputPeers rebuilt from the registry, so a sync doesn't query it on every page.
+    input_peers: Mutex<HashMap<i64, tl::enums::InputPeer>>,
     /// Audit: Request coalescing (singleflight). If a key exists, a resolution is in progress;
     /// waiters clone the Notify and wait; the leader removes the entry and notifies on completion.
     inflight_requests: Mutex<HashMap<i64, Arc<Notify>>>,
+    /// Users from GetHistory responses, drained by get_users_from_batch.
+    batch_users: Mutex<HashMap<i64, User>>,
+    /// Service messages from GetHistory responses, drained by get_events_from_batch.
+    batch_events: Mutex<Vec<ChatEvent>>,
+    /// Dialog iterations (listings and cold peer lookups) since startup, logged at debug level.
+    dialog_walks: AtomicU64,
+    /// Id of the active takeout session, if any.
+    takeout: Mutex<Option<i64>>,
+    /// Signed in as a bot: the chats it archives (ids or usernames), listed as its dialogs.
+    bot_chats: Option<Vec<String>>,
 }
 
 impl GrammersTgGateway {
     /// Create gateway with a client (use same session via clone in main).
-    /// `export_delay_ms`: optional delay in ms before each history batch request (e.g. 500 for throttling).
-    pub fn new(client: Client, export_delay_ms: Option<u64>) -> Self {
+    /// `limiter`: request budgets (TG_SYNC_RATE_HISTORY_PER_MIN / TG_SYNC_RATE_MEDIA_PER_MIN).
+    /// `registry`: where resolved access_hashes are read from and stored.
+    pub fn new(client: Client, limiter: RateLimiter, registry: Arc<dyn EntityRegistry>) -> Self {
         Self {
             client,
-            export_delay_ms,
+            limiter,
             peer_cache: Mutex::new(HashMap::new()),
+            registry,
+            input_peers: Mutex::new(HashMap::new()),
             inflight_requests: Mutex::new(HashMap::new()),
+            batch_users: Mutex::new(HashMap::new()),
+            batch_events: Mutex::new(Vec::new()),
+            dialog_walks: AtomicU64::new(0),
+            takeout: Mutex::new(None),
+            bot_chats: None,
         }
     }
 
-    /// Resolve chat_id to InputPeer, using cache to avoid repeated iter_dialogs (FLOOD_WAIT risk).
-    /// Audit §2.1: Caches the full Peer object so download_media can use to_ref() later.
-    /// Audit: Singleflight — only one iter_dialogs in flight per chat_id; others wait via Notify.
+    /// The session is a bot's: `chats` (Bot API ids or @usernames) stand in for its dialogs,
+    /// and history and media are read the way bots may.
+    pub fn with_bot_chats(mut self, chats: Vec<String>) -> Self {
+        self.bot_chats = Some(chats);
+        self
+    }
+
+    /// Dialog iterations so far. A Full Backup should add exactly one: the listing fills the
+    /// peer caches for every chat it syncs.
+    pub fn dialog_walks(&self) -> u64 {
+        self.dialog_walks.load(Ordering::Relaxed)
+    }
+
+    /// Count a dialog iteration before starting it.
+    fn count_dialog_walk(&self, reason: &'static str, chat_id: Option<i64>) {
+        let dialog_walks = self.dialog_walks.fetch_add(1, Ordering::Relaxed) + 1;
+        debug!(dialog_walks, reason, chat_id, "iterating dialogs");
+    }
+
+    /// Resolve chat_id to InputPeer, using caches to avoid repeated iter_dialogs (FLOOD_WAIT risk):
+    /// the in-memory Peer and InputPeer caches, then the entity registry, then a dialogs walk.
+    /// Audit: Singleflight — only one resolution in flight per chat_id; others wait via Notify.
     async fn resolve_input_peer(&self, chat_id: i64) -> Result<tl::enums::InputPeer, DomainError> {
         loop {
             // 1. Fast path: check cache (no lock held across await)
@@ -57,6 +131,9 @@ impl GrammersTgGateway {
                 }
                 // to_ref() failed, fall through to re-fetch
             }
+            if let Some(input_peer) = self.input_peers.lock().await.get(&chat_id) {
+                return Ok(input_peer.clone());
+            }
 
             // 2. Coalescing: either wait for an in-flight resolution or become the leader
             {
@@ -70,8 +147,17 @@ impl GrammersTgGateway {
                 inflight.insert(chat_id, Arc::new(Notify::new()));
             }
 
-            // 3. We are the leader: one network request for this chat_id
-            let result = self.resolve_input_peer_fetch(chat_id).await;
+            // 3. We are the leader: registry lookup, or one network request for this chat_id
+            let result = resolve_via_registry(self.registry.as_ref(), chat_id, || {
+                self.resolve_input_peer_fetch(chat_id)
+            })
+            .await;
+            if let Ok(input_peer) = &result {
+                self.input_peers
+                    .lock()
+                    .await
+                    .insert(chat_id, input_peer.clone());
+            }
 
             // 4. Remove from inflight and wake waiters (minimal critical section)
             {
@@ -87,19 +173,24 @@ impl GrammersTgGateway {
         }
     }
 
-    /// Performs the actual iter_dialogs fetch. Call only from the singleflight leader.
+    /// Performs the actual iter_dialogs fetch; caches the Peer and stores it in the registry.
+    /// Call from the singleflight leader, or when a full Peer or a fresh access_hash is needed.
     async fn resolve_input_peer_fetch(
         &self,
         chat_id: i64,
     ) -> Result<tl::enums::InputPeer, DomainError> {
+        if self.bot_chats.is_some() {
+            return Ok(self.bot_lookup(chat_id).await?.1);
+        }
+        self.limiter.acquire(RequestKind::History).await;
+        self.count_dialog_walk("peer lookup", Some(chat_id));
         let peer = {
             let mut dialogs = self.client.iter_dialogs();
             let mut found = None;
-            while let Some(dialog) = dialogs
-                .next()
-                .await
-                .map_err(|e| DomainError::TgGateway(e.to_string()))?
-            {
+            while let Some(dialog) = dialogs.next().await.map_err(|e| {
+                self.note_flood_wait(&e);
+                DomainError::TgGateway(e.to_string())
+            })? {
                 let p = dialog.peer();
                 if p.id().bot_api_dialog_id() == chat_id {
                     found = Some(p.clone());
@@ -117,7 +208,434 @@ impl GrammersTgGateway {
             .to_ref()
             .await
             .ok_or_else(|| DomainError::TgGateway("peer not in session cache".into()))?;
-        Ok(peer_ref.into())
+        let input_peer: tl::enums::InputPeer = peer_ref.into();
+        self.remember_peer(chat_id, &peer, &input_peer).await;
+        Ok(input_peer)
+    }
+
+    /// Store a resolved peer's access_hash in the registry. Failures are logged, not returned:
+    /// the registry only saves future dialog walks.
+    async fn remember_peer(
+        &self,
+        chat_id: i64,
+        peer: &grammers_client::peer::Peer,
+        input_peer: &tl::enums::InputPeer,
+    ) {
+        let username = peer.username();
+        register_input_peer(
+            self.registry.as_ref(),
+            chat_id,
+            input_peer,
+            username.as_deref(),
+        )
+        .await;
+    }
+
+    /// Full Peer for calls that need a PeerRef (downloads, sends). The registry only rebuilds
+    /// InputPeers, so a cold cache still walks the dialogs here; `get_dialogs` fills the cache.
+    async fn resolve_peer(&self, chat_id: i64) -> Result<grammers_client::peer::Peer, DomainError> {
+        if let Some(peer) = self.get_cached_peer(chat_id).await {
+            return Ok(peer);
+        }
+        if self.bot_chats.is_some() {
+            // Bots get full peers only from username lookups.
+            let username = self.registry.get_username(chat_id).await?.ok_or_else(|| {
+                DomainError::TgGateway(format!(
+                    "chat {}: a bot can only post to chats it resolved by @username",
+                    chat_id
+                ))
+            })?;
+            self.resolve_chat(&username).await?;
+        } else {
+            self.resolve_input_peer_fetch(chat_id).await?;
+        }
+        self.get_cached_peer(chat_id)
+            .await
+            .ok_or_else(|| DomainError::TgGateway("peer not in cache after resolve".into()))
+    }
+
+    /// Drop a cached InputPeer whose stored access_hash Telegram rejected, and resolve it again
+    /// through the dialogs (which also refreshes the registry).
+    async fn refresh_input_peer(&self, chat_id: i64) -> Result<tl::enums::InputPeer, DomainError> {
+        self.input_peers.lock().await.remove(&chat_id);
+        let input_peer = self.resolve_input_peer_fetch(chat_id).await?;
+        self.input_peers
+            .lock()
+            .await
+            .insert(chat_id, input_peer.clone());
+        Ok(input_peer)
+    }
+
+    /// [`TgGateway::resolve_chat`] for a chat id: cached, looked up directly by a bot, else
+    /// found among the dialogs. Unjoined chats have no id to go by, only their username.
+    async fn resolve_chat_id(&self, chat_id: i64) -> Result<Chat, DomainError> {
+        if let Some(peer) = self.get_cached_peer(chat_id).await {
+            return Ok(Self::peer_chat(chat_id, &peer));
+        }
+        if self.bot_chats.is_some() {
+            let (chat, input_peer) = self.bot_lookup(chat_id).await?;
+            self.input_peers.lock().await.insert(chat_id, input_peer);
+            return Ok(chat);
+        }
+        match self.resolve_input_peer_fetch(chat_id).await {
+            Ok(_) => {}
+            Err(e @ DomainError::FloodWait { .. }) => return Err(e),
+            Err(e) => {
+                debug!(chat_id, error = %e, "chat id not resolved");
+                return Err(DomainError::TgGateway(format!(
+                    "chat {} is not among your dialogs; enter its @username or t.me link instead",
+                    chat_id
+                )));
+            }
+        }
+        self.get_cached_peer(chat_id)
+            .await
+            .map(|peer| Self::peer_chat(chat_id, &peer))
+            .ok_or_else(|| DomainError::TgGateway("peer not in cache after resolve".into()))
+    }
+
+    /// Slow the limiter down if `e` is a FloodWait, so every other request backs off too.
+    fn note_flood_wait(&self, e: &InvocationError) {
+        if let InvocationError::Rpc(rpc) = e {
+            if rpc.code == 420 {
+                let wait_secs = rpc.value.unwrap_or(60) as u64;
+                warn!(wait_secs, "FloodWait: slowing down all Telegram requests");
+                self.limiter.flood_wait(Duration::from_secs(wait_secs));
+            }
+        }
+    }
+
+    /// Invoke `request`, inside the takeout session when one is active.
+    async fn invoke_history<R>(&self, request: &R) -> Result<R::Return, InvocationError>
+    where
+        R: tl::RemoteCall + Clone,
+    {
+        let takeout = *self.takeout.lock().await;
+        match takeout {
+            Some(takeout_id) => {
+                let wrapped = tl::functions::InvokeWithTakeout {
+                    takeout_id,
+                    query: request.clone(),
+                };
+                self.client.invoke(&wrapped).await
+            }
+            None => self.client.invoke(request).await,
+        }
+    }
+
+    /// Download a photo or document through the takeout session: the message is fetched with
+    /// GetHistory, the file with raw upload.getFile requests. Ok(false) when that isn't
+    /// possible (other media, file in another DC, CDN redirect); the caller then downloads it
+    /// the regular way.
+    async fn download_in_takeout(
+        &self,
+        media_ref: &MediaReference,
+        dest_path: &Path,
+    ) -> Result<bool, DomainError> {
+        use tl::enums::messages::Messages;
+
+        let id = media_ref.message_id;
+        let req = tl::functions::messages::GetHistory {
+            peer: self.resolve_input_peer(media_ref.chat_id).await?,
+            offset_id: id + 1,
+            offset_date: 0,
+            add_offset: 0,
+            limit: 1,
+            max_id: id + 1,
+            min_id: id - 1,
+            hash: 0,
+        };
+        let messages = match self.invoke_history(&req).await.map_err(|e| {
+            self.note_flood_wait(&e);
+            DomainError::Media(e.to_string())
+        })? {
+            Messages::Messages(m) => m.messages,
+            Messages::Slice(m) => m.messages,
+            Messages::ChannelMessages(m) => m.messages,
+            Messages::NotModified(_) => Vec::new(),
+        };
+        let location = messages.iter().find_map(|msg| match msg {
+            tl::enums::Message::Message(m) if m.id == id => {
+                m.media.as_ref().and_then(mapper::file_location)
+            }
+            _ => None,
+        });
+        match location {
+            Some(location) => self.download_location(location, dest_path).await,
+            None => Ok(false),
+        }
+    }
+
+    /// Download the file at `location` with raw upload.getFile requests (inside the takeout
+    /// session when one is active). Ok(false) when the file is in another DC or on a CDN.
+    async fn download_location(
+        &self,
+        location: tl::enums::InputFileLocation,
+        dest_path: &Path,
+    ) -> Result<bool, DomainError> {
+        use tokio::io::AsyncWriteExt;
+
+        let mut file = tokio::fs::File::create(dest_path)
+            .await
+            .map_err(|e| DomainError::Media(e.to_string()))?;
+        let mut offset = 0i64;
+        loop {
+            let req = tl::functions::upload::GetFile {
+                precise: false,
+                cdn_supported: false,
+                location: location.clone(),
+                offset,
+                limit: FILE_CHUNK_BYTES,
+            };
+            let bytes = match self.invoke_history(&req).await {
+                Ok(tl::enums::upload::File::File(f)) => f.bytes,
+                Ok(tl::enums::upload::File::CdnRedirect(_)) => return Ok(false),
+                Err(InvocationError::Rpc(rpc)) if rpc.name == "FILE_MIGRATE" => return Ok(false),
+                Err(e) => {
+                    self.note_flood_wait(&e);
+                    return Err(DomainError::Media(e.to_string()));
+                }
+            };
+            file.write_all(&bytes)
+                .await
+                .map_err(|e| DomainError::Media(e.to_string()))?;
+            if bytes.len() < FILE_CHUNK_BYTES as usize {
+                break;
+            }
+            offset += FILE_CHUNK_BYTES as i64;
+        }
+        file.flush()
+            .await
+            .map_err(|e| DomainError::Media(e.to_string()))?;
+        Ok(true)
+    }
+
+    /// Messages of `chat_id` in a history answer, newest first as Telegram sends them. Users
+    /// and service messages are kept for get_users_from_batch / get_events_from_batch.
+    async fn take_batch(&self, raw: tl::enums::messages::Messages, chat_id: i64) -> Vec<Message> {
+        use tl::enums::messages::Messages;
+
+        let (messages, users, chats) = match raw {
+            Messages::Messages(m) => (m.messages, m.users, m.chats),
+            Messages::Slice(m) => (m.messages, m.users, m.chats),
+            Messages::ChannelMessages(m) => (m.messages, m.users, m.chats),
+            Messages::NotModified(_) => return Vec::new(),
+        };
+        let mut batch_users = self.batch_users.lock().await;
+        for user in users.iter().filter_map(mapper::user_to_domain) {
+            batch_users.insert(user.id, user);
+        }
+        drop(batch_users);
+        let mut out = Vec::new();
+        let mut events = Vec::new();
+        for msg in messages
+            .iter()
+            .filter(|m| mapper::message_chat_id(m) == Some(chat_id))
+        {
+            if let Some((m, _)) = mapper::message_to_domain(msg, chat_id) {
+                out.push(m);
+            } else if let Some(event) = mapper::service_to_event(msg, chat_id) {
+                events.push(event);
+            }
+        }
+        self.batch_events.lock().await.extend(events);
+        mapper::fill_forward_names(&mut out, &users, &chats);
+        out
+    }
+
+    /// Domain chat of a resolved peer (no dialog: no message count or last date).
+    fn peer_chat(id: i64, peer: &grammers_client::peer::Peer) -> Chat {
+        let title = peer
+            .name()
+            .map(String::from)
+            .unwrap_or_else(|| peer.id().to_string());
+        let mut chat = mapper::dialog_to_chat(
+            id,
+            &title,
+            peer.username().as_deref(),
+            mapper::chat_type_from_peer(peer),
+            None,
+        );
+        chat.is_forum = mapper::is_forum(peer);
+        chat
+    }
+
+    /// Bot lookup of a group or channel by id: channels.getChannels / messages.getChats, which
+    /// bots may call for chats they are in. The access_hash is stored in the registry.
+    async fn bot_lookup(&self, chat_id: i64) -> Result<(Chat, tl::enums::InputPeer), DomainError> {
+        self.limiter.acquire(RequestKind::History).await;
+        let answer = if chat_id < -mapper::CHANNEL_ID_OFFSET {
+            let req = tl::functions::channels::GetChannels {
+                id: vec![
+                    tl::types::InputChannel {
+                        channel_id: -chat_id - mapper::CHANNEL_ID_OFFSET,
+                        access_hash: 0,
+                    }
+                    .into(),
+                ],
+            };
+            self.client.invoke(&req).await
+        } else if chat_id < 0 {
+            let req = tl::functions::messages::GetChats { id: vec![-chat_id] };
+            self.client.invoke(&req).await
+        } else {
+            return Err(DomainError::TgGateway(format!(
+                "chat {}: a bot resolves users by @username only",
+                chat_id
+            )));
+        };
+        let chats = match answer.map_err(|e| {
+            self.note_flood_wait(&e);
+            DomainError::TgGateway(format!("chat {}: {}", chat_id, e))
+        })? {
+            tl::enums::messages::Chats::Chats(c) => c.chats,
+            tl::enums::messages::Chats::Slice(c) => c.chats,
+        };
+        let (chat, input_peer) = chats
+            .iter()
+            .filter_map(mapper::raw_chat_to_domain)
+            .find(|(chat, _)| chat.id == chat_id)
+            .ok_or_else(|| {
+                DomainError::TgGateway(format!("chat {}: the bot is not a member", chat_id))
+            })?;
+        register_input_peer(
+            self.registry.as_ref(),
+            chat_id,
+            &input_peer,
+            chat.username.as_deref(),
+        )
+        .await;
+        Ok((chat, input_peer))
+    }
+
+    /// Raw messages `ids` of `chat_id`, read the way bots may (deleted ids come back empty).
+    async fn bot_raw_messages(
+        &self,
+        chat_id: i64,
+        ids: impl Iterator<Item = i32>,
+    ) -> Result<tl::enums::messages::Messages, DomainError> {
+        let id: Vec<tl::enums::InputMessage> = ids
+            .map(|id| tl::types::InputMessageId { id }.into())
+            .collect();
+        let input_peer = self.resolve_input_peer(chat_id).await?;
+        self.limiter.acquire(RequestKind::History).await;
+        let answer = match input_peer {
+            tl::enums::InputPeer::Channel(c) => {
+                let req = tl::functions::channels::GetMessages {
+                    channel: tl::types::InputChannel {
+                        channel_id: c.channel_id,
+                        access_hash: c.access_hash,
+                    }
+                    .into(),
+                    id,
+                };
+                self.client.invoke(&req).await
+            }
+            // Basic groups and private chats share the account's message id space.
+            _ => {
+                let req = tl::functions::messages::GetMessages { id };
+                self.client.invoke(&req).await
+            }
+        };
+        answer.map_err(|e| {
+            self.note_flood_wait(&e);
+            match e {
+                InvocationError::Rpc(rpc) if rpc.code == 420 => DomainError::FloodWait {
+                    seconds: rpc.value.unwrap_or(60) as u64,
+                },
+                e => DomainError::TgGateway(e.to_string()),
+            }
+        })
+    }
+
+    /// Messages of `chat_id` with ids `first..=last`, for a bot.
+    async fn bot_window(
+        &self,
+        chat_id: i64,
+        first: i32,
+        last: i32,
+    ) -> Result<Vec<Message>, DomainError> {
+        let raw = self.bot_raw_messages(chat_id, first..=last).await?;
+        Ok(self.take_batch(raw, chat_id).await)
+    }
+
+    /// `get_messages` for a bot: the newest messages below `max_id` (or the newest of the chat)
+    /// and above `min_id`, read window by window of ids. Without `max_id` the top is found by
+    /// reading upwards from `min_id` until a window is empty, so more than a window of deleted
+    /// messages in a row hides the ones after it.
+    async fn bot_messages(
+        &self,
+        chat_id: i64,
+        min_id: i32,
+        max_id: i32,
+        limit: i32,
+    ) -> Result<Vec<Message>, DomainError> {
+        let window = limit.clamp(1, BOT_ID_WINDOW);
+        let upper = if max_id > 0 {
+            max_id
+        } else {
+            let mut top = min_id;
+            while let Some(id) = self
+                .bot_window(chat_id, top + 1, top + window)
+                .await?
+                .iter()
+                .map(|m| m.id)
+                .max()
+            {
+                top = id;
+            }
+            top + 1
+        };
+        // Step down past windows of deleted ids: an empty page ends the caller's paging.
+        let mut high = upper;
+        while high > min_id.max(0) + 1 {
+            let low = (high - window).max(min_id.max(0) + 1);
+            let mut page = self.bot_window(chat_id, low, high - 1).await?;
+            if !page.is_empty() {
+                page.sort_by_key(|m| std::cmp::Reverse(m.id));
+                return Ok(page);
+            }
+            high = low;
+        }
+        Ok(Vec::new())
+    }
+
+    /// Download for a bot: the message is read by id, the file with upload.getFile.
+    async fn bot_download(
+        &self,
+        media_ref: &MediaReference,
+        dest_path: &Path,
+    ) -> Result<(), DomainError> {
+        use tl::enums::messages::Messages;
+
+        let id = media_ref.message_id;
+        let messages = match self
+            .bot_raw_messages(media_ref.chat_id, std::iter::once(id))
+            .await
+            .map_err(|e| DomainError::Media(e.to_string()))?
+        {
+            Messages::Messages(m) => m.messages,
+            Messages::Slice(m) => m.messages,
+            Messages::ChannelMessages(m) => m.messages,
+            Messages::NotModified(_) => Vec::new(),
+        };
+        let location = messages
+            .iter()
+            .find_map(|msg| match msg {
+                tl::enums::Message::Message(m) if m.id == id => {
+                    m.media.as_ref().and_then(mapper::file_location)
+                }
+                _ => None,
+            })
+            .ok_or_else(|| DomainError::Media("message not found or has no file".into()))?;
+        self.limiter.acquire(RequestKind::Media).await;
+        if self.download_location(location, dest_path).await? {
+            Ok(())
+        } else {
+            Err(DomainError::Media(
+                "file is stored in another data center; bots can't download it here".into(),
+            ))
+        }
     }
 
     /// Audit §2.1: Get cached Peer for PeerRef conversion. Avoids dialog re-iteration in download_media.
@@ -130,13 +648,30 @@ impl GrammersTgGateway {
 #[async_trait]
 impl TgGateway for GrammersTgGateway {
     async fn get_dialogs(&self) -> Result<Vec<Chat>, DomainError> {
+        if let Some(references) = &self.bot_chats {
+            // A chat that doesn't resolve is left out, like a dialog the account has left.
+            let mut chats = Vec::new();
+            for reference in references {
+                match self.resolve_chat(reference).await {
+                    Ok(chat) => chats.push(chat),
+                    Err(DomainError::FloodWait { seconds }) => {
+                        return Err(DomainError::FloodWait { seconds });
+                    }
+                    Err(e) => {
+                        warn!(chat = %reference, error = %e, "configured bot chat not resolved")
+                    }
+                }
+            }
+            return Ok(chats);
+        }
+        self.limiter.acquire(RequestKind::History).await;
+        self.count_dialog_walk("listing", None);
         let mut dialogs = self.client.iter_dialogs();
         let mut chats = Vec::new();
-        while let Some(dialog) = dialogs
-            .next()
-            .await
-            .map_err(|e| DomainError::TgGateway(e.to_string()))?
-        {
+        while let Some(dialog) = dialogs.next().await.map_err(|e| {
+            self.note_flood_wait(&e);
+            DomainError::TgGateway(e.to_string())
+        })? {
             let peer = dialog.peer();
             let id = peer.id().bot_api_dialog_id();
             let title = peer
@@ -145,13 +680,21 @@ impl TgGateway for GrammersTgGateway {
                 .unwrap_or_else(|| peer.id().to_string());
             let kind = mapper::chat_type_from_peer(peer);
             let approx_message_count = dialog.last_message.as_ref().map(|m| m.id());
-            chats.push(mapper::dialog_to_chat(
+            // Every listed peer is cached and registered, so syncing it later needs no lookup.
+            self.peer_cache.lock().await.insert(id, peer.clone());
+            if let Some(peer_ref) = peer.to_ref().await {
+                self.remember_peer(id, peer, &peer_ref.into()).await;
+            }
+            let mut chat = mapper::dialog_to_chat(
                 id,
                 &title,
                 peer.username().as_deref(),
                 kind,
                 approx_message_count,
-            ));
+            );
+            chat.is_forum = mapper::is_forum(peer);
+            chat.last_message_date = dialog.last_message.as_ref().map(|m| m.date().timestamp());
+            chats.push(chat);
         }
         Ok(chats)
     }
@@ -163,13 +706,11 @@ impl TgGateway for GrammersTgGateway {
         max_id: i32,
         limit: i32,
     ) -> Result<Vec<Message>, DomainError> {
-        use tl::enums::messages::Messages;
-
-        if let Some(ms) = self.export_delay_ms {
-            tokio::time::sleep(Duration::from_millis(ms)).await;
+        if self.bot_chats.is_some() {
+            return self.bot_messages(chat_id, min_id, max_id, limit).await;
         }
-
-        let input_peer = self.resolve_input_peer(chat_id).await?;
+        let mut input_peer = self.resolve_input_peer(chat_id).await?;
+        let mut refreshed = false;
 
         // When max_id > 0 we're paginating backward (older messages). Telegram requires
         // offset_id = max_id so the API returns the next page starting from that message.
@@ -177,6 +718,7 @@ impl TgGateway for GrammersTgGateway {
         let offset_id = if max_id > 0 { max_id } else { 0 };
 
         for attempt in 0..3 {
+            self.limiter.acquire(RequestKind::History).await;
             let req = tl::functions::messages::GetHistory {
                 peer: input_peer.clone(),
                 offset_id,
@@ -188,28 +730,17 @@ impl TgGateway for GrammersTgGateway {
                 hash: 0,
             };
 
-            match self.client.invoke(&req).await {
-                Ok(raw) => {
-                    let (messages, _users, _chats) = match raw {
-                        Messages::Messages(m) => (m.messages, m.users, m.chats),
-                        Messages::Slice(m) => (m.messages, m.users, m.chats),
-                        Messages::ChannelMessages(m) => (m.messages, m.users, m.chats),
-                        Messages::NotModified(_) => return Ok(vec![]),
-                    };
-                    let mut out = Vec::new();
-                    for msg in messages {
-                        if let Some((m, _)) = mapper::message_to_domain(&msg, chat_id) {
-                            out.push(m);
-                        }
-                    }
-                    return Ok(out);
-                }
+            match self.invoke_history(&req).await {
+                Ok(raw) => return Ok(self.take_batch(raw, chat_id).await),
                 Err(InvocationError::Rpc(rpc)) if rpc.code == 420 => {
                     let wait_secs = rpc.value.unwrap_or(60) as u64;
+                    // Every other request waits this out too, at a halved rate afterwards.
+                    self.limiter.flood_wait(Duration::from_secs(wait_secs));
                     // Audit §4.1: Long waits (≥60s) should not block the worker thread.
                     // Return error so caller (job scheduler) can reschedule.
                     if wait_secs >= FLOOD_WAIT_THRESHOLD_SECS {
                         info!(
+                            chat_id,
                             attempt,
                             wait_secs,
                             threshold = FLOOD_WAIT_THRESHOLD_SECS,
@@ -217,8 +748,17 @@ impl TgGateway for GrammersTgGateway {
                         );
                         return Err(DomainError::FloodWait { seconds: wait_secs });
                     }
-                    warn!(attempt, wait_secs, "FloodWait (short), sleeping");
-                    tokio::time::sleep(Duration::from_secs(wait_secs)).await;
+                    // The next acquire sleeps until the wait is over.
+                    warn!(
+                        chat_id,
+                        attempt, wait_secs, "FloodWait (short), retrying after the wait"
+                    );
+                }
+                // A stale registry access_hash: resolve through the dialogs once and retry.
+                Err(InvocationError::Rpc(rpc)) if !refreshed && is_invalid_peer(&rpc.name) => {
+                    warn!(chat_id, error = %rpc.name, "stored peer rejected, re-resolving");
+                    refreshed = true;
+                    input_peer = self.refresh_input_peer(chat_id).await?;
                 }
                 Err(e) => return Err(DomainError::TgGateway(e.to_string())),
             }
@@ -231,35 +771,43 @@ impl TgGateway for GrammersTgGateway {
         media_ref: &MediaReference,
         dest_path: &Path,
     ) -> Result<(), DomainError> {
-        // Audit §2.1: First ensure peer is cached via resolve_input_peer.
-        // This populates the peer_cache if not already present.
-        let _ = self
-            .resolve_input_peer(media_ref.chat_id)
-            .await
-            .map_err(|e| DomainError::Media(format!("peer resolution failed: {}", e)))?;
+        if self.bot_chats.is_some() {
+            return self.bot_download(media_ref, dest_path).await;
+        }
+        if self.takeout.lock().await.is_some() {
+            self.limiter.acquire(RequestKind::Media).await;
+            if self.download_in_takeout(media_ref, dest_path).await? {
+                debug!(
+                    chat_id = media_ref.chat_id,
+                    msg_id = media_ref.message_id,
+                    path = %dest_path.display(),
+                    "media downloaded in takeout session"
+                );
+                return Ok(());
+            }
+        }
 
         // Audit §2.1: Use cached Peer to get PeerRef without re-iterating dialogs.
         // This avoids the FloodWait risk from repeated getDialogs calls.
         let peer = self
-            .get_cached_peer(media_ref.chat_id)
+            .resolve_peer(media_ref.chat_id)
             .await
-            .ok_or_else(|| {
-                DomainError::Media(format!(
-                    "peer {} not in cache after resolve",
-                    media_ref.chat_id
-                ))
-            })?;
+            .map_err(|e| DomainError::Media(format!("peer resolution failed: {}", e)))?;
 
         let peer_ref = peer
             .to_ref()
             .await
             .ok_or_else(|| DomainError::Media("peer not in session cache".into()))?;
 
+        self.limiter.acquire(RequestKind::Media).await;
         let messages = self
             .client
             .get_messages_by_id(peer_ref, &[media_ref.message_id])
             .await
-            .map_err(|e| DomainError::Media(e.to_string()))?;
+            .map_err(|e| {
+                self.note_flood_wait(&e);
+                DomainError::Media(e.to_string())
+            })?;
 
         let msg = messages
             .into_iter()
@@ -286,28 +834,386 @@ impl TgGateway for GrammersTgGateway {
     }
 
     async fn get_me_id(&self) -> Result<i64, DomainError> {
-        let me = self
-            .client
-            .get_me()
-            .await
-            .map_err(|e| DomainError::TgGateway(e.to_string()))?;
+        self.limiter.acquire(RequestKind::History).await;
+        let me = self.client.get_me().await.map_err(|e| {
+            self.note_flood_wait(&e);
+            DomainError::TgGateway(e.to_string())
+        })?;
         Ok(me.id().bot_api_dialog_id())
     }
 
     async fn send_message(&self, chat_id: i64, text: &str) -> Result<(), DomainError> {
-        self.resolve_input_peer(chat_id).await?;
-        let peer = self
-            .get_cached_peer(chat_id)
-            .await
-            .ok_or_else(|| DomainError::TgGateway("peer not in cache after resolve".into()))?;
+        let peer = self.resolve_peer(chat_id).await?;
         let peer_ref = peer
             .to_ref()
             .await
             .ok_or_else(|| DomainError::TgGateway("peer not in session cache".into()))?;
+        self.limiter.acquire(RequestKind::History).await;
         self.client
             .send_message(peer_ref, text)
             .await
+            .map_err(|e| {
+                self.note_flood_wait(&e);
+                DomainError::TgGateway(e.to_string())
+            })?;
+        Ok(())
+    }
+
+    async fn get_forum_topics(&self, chat_id: i64) -> Result<Vec<ForumTopic>, DomainError> {
+        let tl::enums::InputPeer::Channel(channel) = self.resolve_input_peer(chat_id).await? else {
+            // Only supergroups (channels) can be forums.
+            return Ok(Vec::new());
+        };
+        let input_channel: tl::enums::InputChannel = tl::types::InputChannel {
+            channel_id: channel.channel_id,
+            access_hash: channel.access_hash,
+        }
+        .into();
+
+        let mut topics = Vec::new();
+        let (mut offset_date, mut offset_id, mut offset_topic) = (0, 0, 0);
+        loop {
+            self.limiter.acquire(RequestKind::History).await;
+            let req = tl::functions::channels::GetForumTopics {
+                channel: input_channel.clone(),
+                q: None,
+                offset_date,
+                offset_id,
+                offset_topic,
+                limit: FORUM_TOPICS_PAGE,
+            };
+            let tl::enums::messages::ForumTopics::Topics(page) =
+                match self.client.invoke(&req).await {
+                    Ok(page) => page,
+                    Err(InvocationError::Rpc(rpc)) if rpc.name == "CHANNEL_FORUM_MISSING" => {
+                        return Ok(Vec::new());
+                    }
+                    Err(e) => {
+                        self.note_flood_wait(&e);
+                        return Err(DomainError::TgGateway(e.to_string()));
+                    }
+                };
+            let mut last = None;
+            for topic in &page.topics {
+                if let tl::enums::ForumTopic::Topic(t) = topic {
+                    topics.push(ForumTopic {
+                        id: t.id,
+                        title: t.title.clone(),
+                    });
+                    last = Some((t.date, t.top_message, t.id));
+                }
+            }
+            match last {
+                Some(next) if topics.len() < page.count as usize => {
+                    (offset_date, offset_id, offset_topic) = next;
+                }
+                _ => break,
+            }
+        }
+        debug!(chat_id, count = topics.len(), "forum topics listed");
+        Ok(topics)
+    }
+
+    async fn resolve_chat(&self, reference: &str) -> Result<Chat, DomainError> {
+        let username = match ChatReference::parse(reference)? {
+            ChatReference::Username(username) => username,
+            ChatReference::Id(chat_id) => return self.resolve_chat_id(chat_id).await,
+        };
+        self.limiter.acquire(RequestKind::History).await;
+        let peer = match self.client.resolve_username(&username).await {
+            Ok(Some(peer)) => peer,
+            Ok(None) => return Err(no_such_username(&username)),
+            Err(e) if e.is("USERNAME_NOT_OCCUPIED") || e.is("USERNAME_INVALID") => {
+                return Err(no_such_username(&username));
+            }
+            Err(e) => {
+                self.note_flood_wait(&e);
+                return Err(match &e {
+                    InvocationError::Rpc(rpc) if rpc.code == 420 => DomainError::FloodWait {
+                        seconds: rpc.value.unwrap_or(60) as u64,
+                    },
+                    _ => DomainError::TgGateway(format!("@{}: {}", username, e)),
+                });
+            }
+        };
+        let chat_id = peer.id().bot_api_dialog_id();
+        self.peer_cache.lock().await.insert(chat_id, peer.clone());
+        if let Some(peer_ref) = peer.to_ref().await {
+            let input_peer: tl::enums::InputPeer = peer_ref.into();
+            self.input_peers
+                .lock()
+                .await
+                .insert(chat_id, input_peer.clone());
+            self.remember_peer(chat_id, &peer, &input_peer).await;
+        }
+        Ok(Self::peer_chat(chat_id, &peer))
+    }
+
+    fn is_bot(&self) -> bool {
+        self.bot_chats.is_some()
+    }
+
+    async fn get_users_from_batch(&self) -> Result<Vec<User>, DomainError> {
+        Ok(self
+            .batch_users
+            .lock()
+            .await
+            .drain()
+            .map(|(_, u)| u)
+            .collect())
+    }
+
+    async fn get_events_from_batch(&self) -> Result<Vec<ChatEvent>, DomainError> {
+        Ok(std::mem::take(&mut *self.batch_events.lock().await))
+    }
+
+    async fn get_discussion_message(
+        &self,
+        channel_id: i64,
+        msg_id: i32,
+    ) -> Result<Option<DiscussionLink>, DomainError> {
+        // Not available to bots: their comment threads stay unlinked.
+        if self.bot_chats.is_some() {
+            return Ok(None);
+        }
+        let peer = self.resolve_input_peer(channel_id).await?;
+        self.limiter.acquire(RequestKind::History).await;
+        let req = tl::functions::messages::GetDiscussionMessage { peer, msg_id };
+        let tl::enums::messages::DiscussionMessage::Message(discussion) =
+            match self.client.invoke(&req).await {
+                Ok(discussion) => discussion,
+                // No discussion group, or this post has no comment thread.
+                Err(InvocationError::Rpc(rpc))
+                    if matches!(rpc.name.as_str(), "MSG_ID_INVALID" | "CHANNEL_INVALID") =>
+                {
+                    return Ok(None);
+                }
+                Err(e) => {
+                    self.note_flood_wait(&e);
+                    return Err(DomainError::TgGateway(e.to_string()));
+                }
+            };
+        Ok(discussion.messages.iter().find_map(|m| match m {
+            tl::enums::Message::Message(m) => {
+                let chat_id = mapper::peer_id(&m.peer_id);
+                (chat_id != channel_id).then_some(DiscussionLink {
+                    chat_id,
+                    message_id: m.id,
+                })
+            }
+            _ => None,
+        }))
+    }
+
+    async fn begin_takeout(&self) -> Result<(), DomainError> {
+        self.limiter.acquire(RequestKind::History).await;
+        let req = tl::functions::account::InitTakeoutSession {
+            contacts: false,
+            message_users: true,
+            message_chats: true,
+            message_megagroups: true,
+            message_channels: true,
+            files: true,
+            file_max_size: Some(TAKEOUT_FILE_MAX_SIZE),
+        };
+        let tl::enums::account::Takeout::Takeout(takeout) =
+            self.client.invoke(&req).await.map_err(|e| match e {
+                InvocationError::Rpc(rpc) if rpc.name == "TAKEOUT_INIT_DELAY" => {
+                    DomainError::TakeoutDelay {
+                        seconds: rpc.value.unwrap_or(0) as u64,
+                    }
+                }
+                e => {
+                    self.note_flood_wait(&e);
+                    DomainError::TgGateway(e.to_string())
+                }
+            })?;
+        *self.takeout.lock().await = Some(takeout.id);
+        info!(takeout_id = takeout.id, "takeout session started");
+        Ok(())
+    }
+
+    async fn end_takeout(&self, success: bool) -> Result<(), DomainError> {
+        let Some(takeout_id) = self.takeout.lock().await.take() else {
+            return Ok(());
+        };
+        let req = tl::functions::InvokeWithTakeout {
+            takeout_id,
+            query: tl::functions::account::FinishTakeoutSession { success },
+        };
+        self.client
+            .invoke(&req)
+            .await
             .map_err(|e| DomainError::TgGateway(e.to_string()))?;
+        info!(takeout_id, success, "takeout session finished");
         Ok(())
     }
 }
+
+/// RPC errors meaning the InputPeer (its access_hash) is no longer valid.
+fn is_invalid_peer(rpc_name: &str) -> bool {
+    matches!(
+        rpc_name,
+        "PEER_ID_INVALID" | "CHANNEL_INVALID" | "USER_ID_INVALID" | "CHAT_ID_INVALID"
+    )
+}
+
+/// Store an InputPeer's access_hash in the registry; synthetic peers (no hash) are skipped.
+/// Failures are logged, not returned.
+async fn register_input_peer(
+    registry: &dyn EntityRegistry,
+    chat_id: i64,
+    input_peer: &tl::enums::InputPeer,
+    username: Option<&str>,
+) {
+    let Some((access_hash, peer_type)) = mapper::input_peer_entity(input_peer) else {
+        return;
+    };
+    if let Err(e) = registry
+        .save_entity(chat_id, access_hash, peer_type, username)
+        .await
+    {
+        warn!(chat_id, error = %e, "failed to store peer in entity registry");
+    }
+}
+
+/// Registry-first InputPeer resolution: a stored access_hash rebuilds the peer without any
+/// request; only unknown (or unusable) entries run `fetch`, the dialogs walk. Registry errors
+/// are logged and fall through to `fetch`.
+async fn resolve_via_registry<F, Fut>(
+    registry: &dyn EntityRegistry,
+    chat_id: i64,
+    fetch: F,
+) -> Result<tl::enums::InputPeer, DomainError>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<tl::enums::InputPeer, DomainError>>,
+{
+    match registry.get_entity(chat_id).await {
+        Ok(Some((access_hash, peer_type))) => {
+            if let Some(input_peer) =
+                mapper::input_peer_from_entity(chat_id, access_hash, &peer_type)
+            {
+                debug!(chat_id, "peer resolved from entity registry");
+                return Ok(input_peer);
+            }
+        }
+        Ok(None) => {}
+        Err(e) => warn!(chat_id, error = %e, "entity registry lookup failed"),
+    }
+    fetch().await
+}
+
+/// Error for a username no chat or user holds.
+fn no_such_username(username: &str) -> DomainError {
+    DomainError::TgGateway(format!(
+        "no public chat, channel or user is called @{}",
+        username
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// In-memory registry: peer_id -> (access_hash, peer_type).
+    #[derive(Default)]
+    struct FakeRegistry {
+        entities: std::sync::Mutex<HashMap<i64, (i64, String)>>,
+    }
+
+    #[async_trait]
+    impl EntityRegistry for FakeRegistry {
+        async fn get_access_hash(&self, peer_id: i64) -> Result<Option<i64>, DomainError> {
+            Ok(self.get_entity(peer_id).await?.map(|(hash, _)| hash))
+        }
+
+        async fn get_entity(&self, peer_id: i64) -> Result<Option<(i64, String)>, DomainError> {
+            Ok(self.entities.lock().unwrap().get(&peer_id).cloned())
+        }
+
+        async fn save_entity(
+            &self,
+            peer_id: i64,
+            access_hash: i64,
+            peer_type: &str,
+            _username: Option<&str>,
+        ) -> Result<(), DomainError> {
+            self.entities
+                .lock()
+                .unwrap()
+                .insert(peer_id, (access_hash, peer_type.to_string()));
+            Ok(())
+        }
+
+        async fn get_username(&self, _peer_id: i64) -> Result<Option<String>, DomainError> {
+            Ok(None)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stored_access_hash_skips_dialog_walk() {
+        let registry = FakeRegistry::default();
+        registry
+            .save_entity(-1001234567890, 777, mapper::PEER_TYPE_CHANNEL, None)
+            .await
+            .unwrap();
+        let walks = AtomicUsize::new(0);
+        let walk = || async {
+            walks.fetch_add(1, Ordering::SeqCst);
+            Err(DomainError::TgGateway("getDialogs".into()))
+        };
+
+        let peer = resolve_via_registry(&registry, -1001234567890, walk)
+            .await
+            .unwrap();
+        assert!(matches!(
+            peer,
+            tl::enums::InputPeer::Channel(tl::types::InputPeerChannel {
+                channel_id: 1234567890,
+                access_hash: 777,
+            })
+        ));
+        assert_eq!(walks.load(Ordering::SeqCst), 0);
+
+        // Unknown chats and synthetic senders (no real hash) still walk the dialogs.
+        registry
+            .save_entity(42, 0, "synthetic", Some("Alice"))
+            .await
+            .unwrap();
+        for chat_id in [42, 99] {
+            let result = resolve_via_registry(&registry, chat_id, walk).await;
+            assert!(result.is_err());
+        }
+        assert_eq!(walks.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_listing_prewarm_leaves_one_dialog_walk_for_many_chats() {
+        let registry = FakeRegistry::default();
+        let chat_ids: Vec<i64> = (1..=150).map(|n| -1_000_000_000_000 - n).collect();
+        let walks = AtomicUsize::new(0);
+
+        // The get_dialogs listing: one walk that registers every dialog it lists.
+        walks.fetch_add(1, Ordering::SeqCst);
+        for (n, &chat_id) in chat_ids.iter().enumerate() {
+            let input_peer =
+                mapper::input_peer_from_entity(chat_id, n as i64 + 1, mapper::PEER_TYPE_CHANNEL)
+                    .unwrap();
+            register_input_peer(&registry, chat_id, &input_peer, None).await;
+        }
+
+        // Syncing every listed chat resolves it from the registry, without another walk.
+        let walk = || async {
+            walks.fetch_add(1, Ordering::SeqCst);
+            Err(DomainError::TgGateway("getDialogs".into()))
+        };
+        for &chat_id in &chat_ids {
+            resolve_via_registry(&registry, chat_id, walk)
+                .await
+                .unwrap();
+        }
+        assert_eq!(walks.load(Ordering::SeqCst), 1);
+    }
+}
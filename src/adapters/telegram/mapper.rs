@@ -1,10 +1,14 @@
 //! Map Grammers types to domain entities.
 //!
-//! Extracts Chat, Message, MediaReference from grammers_client tl types.
+//! Extracts Chat, Message, ChatEvent, MediaReference, User from grammers_client tl types.
 
-use crate::domain::{Chat, ChatType, MediaReference, MediaType, Message};
+use crate::domain::{
+    Chat, ChatEvent, ChatEventKind, ChatType, MediaReference, MediaType, Message, Poll, PollAnswer,
+    User,
+};
 use grammers_client::peer::Peer;
 use grammers_client::tl;
+use std::collections::HashMap;
 
 /// Map a grammers Peer to domain ChatType.
 ///
@@ -25,6 +29,14 @@ pub fn chat_type_from_peer(peer: &Peer) -> ChatType {
     }
 }
 
+/// True for supergroups with Topics enabled (forums).
+pub fn is_forum(peer: &Peer) -> bool {
+    match peer {
+        Peer::Group(g) => matches!(&g.raw, tl::enums::Chat::Channel(c) if c.forum),
+        _ => false,
+    }
+}
+
 /// Map a grammers Dialog/PeerRef to domain Chat (used when building Chat in client).
 /// `top_message_id`: from dialog's last message; used as heuristic for approx_message_count.
 #[allow(dead_code)]
@@ -51,6 +63,21 @@ pub fn dialog_to_chat(
         username: username.map(String::from),
         kind,
         approx_message_count,
+        is_forum: false,
+        last_message_date: None,
+    }
+}
+
+/// Map a user from a history batch's users list. None for empty (inaccessible) users.
+pub fn user_to_domain(user: &tl::enums::User) -> Option<User> {
+    match user {
+        tl::enums::User::Empty(_) => None,
+        tl::enums::User::User(u) => Some(User {
+            id: u.id,
+            first_name: u.first_name.clone(),
+            last_name: u.last_name.clone(),
+            username: u.username.clone(),
+        }),
     }
 }
 
@@ -59,10 +86,15 @@ pub fn message_to_domain(
     msg: &tl::enums::Message,
     chat_id: i64,
 ) -> Option<(Message, Option<MediaReference>)> {
-    let (id, date, text, from_user_id, reply_to, media_ref) = match msg {
+    let (id, date, text, from_user_id, (reply_to, topic_id), forward, poll, media_ref) = match msg {
         tl::enums::Message::Empty(_) => return None,
         tl::enums::Message::Message(m) => {
-            let text = m.message.clone();
+            let poll = m.media.as_ref().and_then(poll_from_media);
+            // Polls carry no text of their own; the rendering makes them searchable.
+            let text = match &poll {
+                Some(poll) if m.message.is_empty() => poll.render(),
+                _ => m.message.clone(),
+            };
             let from = m.from_id.as_ref().and_then(|f| match f {
                 tl::enums::Peer::User(u) => Some(u.user_id as i64),
                 _ => None,
@@ -74,18 +106,18 @@ pub fn message_to_domain(
                 m.edit_date.map(|d| d as i64).unwrap_or(m.date as i64),
                 text,
                 from,
-                m.reply_to
-                    .as_ref()
-                    .and_then(|r| match r {
-                        tl::enums::MessageReplyHeader::Header(h) => Some(h.reply_to_msg_id),
-                        _ => None,
-                    })
-                    .flatten(),
+                m.reply_to.as_ref().map_or((None, None), reply_and_topic),
+                m.fwd_from.as_ref().map(forward_origin).unwrap_or_default(),
+                poll,
                 media_ref,
             )
         }
         tl::enums::Message::Service(_) => return None,
     };
+    let (grouped_id, mentioned, mentioned_user_ids) = match msg {
+        tl::enums::Message::Message(m) => (m.grouped_id, m.mentioned, mentioned_user_ids(m)),
+        _ => (None, false, Vec::new()),
+    };
 
     Some((
         Message {
@@ -97,38 +129,923 @@ pub fn message_to_domain(
             from_user_id,
             reply_to_msg_id: reply_to,
             edit_history: None,
+            sender_name: None,
+            topic_id,
+            topic_title: None,
+            forwarded_from_name: forward.name,
+            forwarded_from_id: forward.from_id,
+            forwarded_date: forward.date,
+            poll,
+            linked_channel_post: forward.channel_post,
+            grouped_id,
+            mentioned,
+            mentioned_user_ids,
         },
         media_ref,
     ))
 }
 
-fn extract_media_ref(m: &tl::types::Message, chat_id: i64) -> Option<MediaReference> {
-    let media = m.media.as_ref()?;
-    let (media_type, opaque) = match media {
-        tl::enums::MessageMedia::Photo(_) => (MediaType::Photo, format!("{}:{}", chat_id, m.id)),
-        tl::enums::MessageMedia::Document(d) => {
-            let mt = match d.document.as_ref() {
-                Some(tl::enums::Document::Document(doc)) => {
-                    if doc.mime_type.starts_with("video/") {
-                        MediaType::Video
-                    } else if doc.mime_type.starts_with("audio/") {
-                        MediaType::Audio
-                    } else if doc.mime_type == "application/x-tgsticker" {
-                        MediaType::Sticker
-                    } else {
-                        MediaType::Document
-                    }
-                }
-                _ => MediaType::Document,
+/// Users mentioned by a name link in the text, each once, in order of appearance.
+fn mentioned_user_ids(m: &tl::types::Message) -> Vec<i64> {
+    let mut ids = Vec::new();
+    let mentions = m
+        .entities
+        .iter()
+        .flatten()
+        .filter_map(|entity| match entity {
+            tl::enums::MessageEntity::MentionName(mention) => Some(mention.user_id),
+            _ => None,
+        });
+    for user_id in mentions {
+        if !ids.contains(&user_id) {
+            ids.push(user_id);
+        }
+    }
+    ids
+}
+
+/// Map a service message (join, leave, rename, pin, call, ...) to a chat event. Regular and
+/// empty messages give None.
+pub fn service_to_event(msg: &tl::enums::Message, chat_id: i64) -> Option<ChatEvent> {
+    let tl::enums::Message::Service(s) = msg else {
+        return None;
+    };
+    let actor_id = s.from_id.as_ref().and_then(|f| match f {
+        tl::enums::Peer::User(u) => Some(u.user_id),
+        _ => None,
+    });
+    let (kind, payload) = match &s.action {
+        tl::enums::MessageAction::ChatAddUser(a) => {
+            // Someone joining by themselves lists only themselves.
+            let added = (a.users.as_slice() != actor_id.as_slice()).then(|| {
+                let ids: Vec<String> = a.users.iter().map(|id| id.to_string()).collect();
+                ids.join(",")
+            });
+            (ChatEventKind::UserJoined, added)
+        }
+        tl::enums::MessageAction::ChatJoinedByLink(_)
+        | tl::enums::MessageAction::ChatJoinedByRequest => (ChatEventKind::UserJoined, None),
+        tl::enums::MessageAction::ChatDeleteUser(a) => (
+            ChatEventKind::UserLeft,
+            (Some(a.user_id) != actor_id).then(|| a.user_id.to_string()),
+        ),
+        tl::enums::MessageAction::ChatEditTitle(a) => {
+            (ChatEventKind::TitleChanged, Some(a.title.clone()))
+        }
+        tl::enums::MessageAction::PinMessage => {
+            let pinned = match &s.reply_to {
+                Some(tl::enums::MessageReplyHeader::Header(h)) => h.reply_to_msg_id,
+                _ => None,
             };
-            (mt, format!("{}:{}", chat_id, m.id))
+            (
+                ChatEventKind::MessagePinned,
+                pinned.map(|id| id.to_string()),
+            )
         }
-        _ => (MediaType::Other, format!("{}:{}", chat_id, m.id)),
+        tl::enums::MessageAction::GroupCall(a) if a.duration.is_none() => {
+            (ChatEventKind::CallStarted, None)
+        }
+        other => (ChatEventKind::Other, Some(action_name(other))),
+    };
+    Some(ChatEvent {
+        id: s.id,
+        chat_id,
+        date: s.date as i64,
+        kind,
+        actor_id,
+        payload,
+    })
+}
+
+/// Variant name of a service action, e.g. `ChatEditPhoto`.
+fn action_name(action: &tl::enums::MessageAction) -> String {
+    let debug = format!("{:?}", action);
+    debug
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// (replied-to message, forum topic) of a reply header. In a forum every message refers to its
+/// topic's creation message; only when `reply_to_top_id` is set is it also a real reply.
+fn reply_and_topic(header: &tl::enums::MessageReplyHeader) -> (Option<i32>, Option<i32>) {
+    let tl::enums::MessageReplyHeader::Header(h) = header else {
+        return (None, None);
+    };
+    if !h.forum_topic {
+        return (h.reply_to_msg_id, None);
+    }
+    match h.reply_to_top_id {
+        Some(top) => (h.reply_to_msg_id, Some(top)),
+        None => (None, h.reply_to_msg_id),
+    }
+}
+
+/// Provenance of a forwarded message, from its `fwd_from` header.
+#[derive(Debug, Default)]
+struct ForwardOrigin {
+    name: Option<String>,
+    from_id: Option<i64>,
+    date: Option<i64>,
+    /// Channel post this message is the discussion group copy of (`saved_from_peer` is a
+    /// channel).
+    channel_post: Option<i32>,
+}
+
+/// Original author and date of a forward. The name is only in the header for senders who hide
+/// their account; [`fill_forward_names`] adds it for the others.
+fn forward_origin(header: &tl::enums::MessageFwdHeader) -> ForwardOrigin {
+    let tl::enums::MessageFwdHeader::Header(h) = header;
+    ForwardOrigin {
+        name: h.from_name.clone(),
+        from_id: h.from_id.as_ref().map(peer_id),
+        date: Some(h.date as i64),
+        channel_post: match h.saved_from_peer {
+            Some(tl::enums::Peer::Channel(_)) => h.saved_from_msg_id,
+            _ => None,
+        },
+    }
+}
+
+/// Name forwarded messages after their original authors, using the users and chats that came
+/// with the history batch. Authors outside the batch keep only their id.
+pub fn fill_forward_names(
+    messages: &mut [Message],
+    users: &[tl::enums::User],
+    chats: &[tl::enums::Chat],
+) {
+    let mut names: HashMap<i64, String> = HashMap::new();
+    for user in users.iter().filter_map(user_to_domain) {
+        let full = format!(
+            "{} {}",
+            user.first_name.as_deref().unwrap_or_default(),
+            user.last_name.as_deref().unwrap_or_default()
+        );
+        let name = match (full.trim(), &user.username) {
+            ("", Some(username)) => format!("@{}", username),
+            (full, _) => full.to_string(),
+        };
+        if !name.is_empty() {
+            names.insert(user.id, name);
+        }
+    }
+    for chat in chats {
+        let (id, title) = match chat {
+            tl::enums::Chat::Chat(c) => (-c.id, &c.title),
+            tl::enums::Chat::Forbidden(c) => (-c.id, &c.title),
+            tl::enums::Chat::Channel(c) => (-CHANNEL_ID_OFFSET - c.id, &c.title),
+            tl::enums::Chat::ChannelForbidden(c) => (-CHANNEL_ID_OFFSET - c.id, &c.title),
+            tl::enums::Chat::Empty(_) => continue,
+        };
+        names.insert(id, title.clone());
+    }
+    for message in messages.iter_mut() {
+        if message.forwarded_from_name.is_none() {
+            message.forwarded_from_name = message
+                .forwarded_from_id
+                .and_then(|id| names.get(&id).cloned());
+        }
+    }
+}
+
+/// Bot API style id of a peer: users as is, basic groups negated, channels `-100<id>`.
+pub fn peer_id(peer: &tl::enums::Peer) -> i64 {
+    match peer {
+        tl::enums::Peer::User(u) => u.user_id,
+        tl::enums::Peer::Chat(c) => -c.chat_id,
+        tl::enums::Peer::Channel(c) => -CHANNEL_ID_OFFSET - c.channel_id,
+    }
+}
+
+/// Chat a message belongs to (Bot API style id). None for empty messages. Bots read basic
+/// group and private messages from one shared id space, so their reads filter by it.
+pub fn message_chat_id(msg: &tl::enums::Message) -> Option<i64> {
+    match msg {
+        tl::enums::Message::Empty(_) => None,
+        tl::enums::Message::Message(m) => Some(peer_id(&m.peer_id)),
+        tl::enums::Message::Service(m) => Some(peer_id(&m.peer_id)),
+    }
+}
+
+/// Domain chat and InputPeer of a group or channel from a `channels.getChannels` /
+/// `messages.getChats` answer. None for chats the account can't access.
+pub fn raw_chat_to_domain(chat: &tl::enums::Chat) -> Option<(Chat, tl::enums::InputPeer)> {
+    match chat {
+        tl::enums::Chat::Chat(c) => Some((
+            dialog_to_chat(-c.id, &c.title, None, ChatType::Group, None),
+            tl::types::InputPeerChat { chat_id: c.id }.into(),
+        )),
+        tl::enums::Chat::Channel(c) => {
+            let kind = if c.broadcast {
+                ChatType::Channel
+            } else {
+                ChatType::Supergroup
+            };
+            let mut domain = dialog_to_chat(
+                -CHANNEL_ID_OFFSET - c.id,
+                &c.title,
+                c.username.as_deref(),
+                kind,
+                None,
+            );
+            domain.is_forum = c.forum;
+            let input_peer = tl::types::InputPeerChannel {
+                channel_id: c.id,
+                access_hash: c.access_hash.unwrap_or(0),
+            };
+            Some((domain, input_peer.into()))
+        }
+        _ => None,
+    }
+}
+
+/// Question, answers and results of a poll message. Results stay None while Telegram hides
+/// them from this account.
+fn poll_from_media(media: &tl::enums::MessageMedia) -> Option<Poll> {
+    let tl::enums::MessageMedia::Poll(media) = media else {
+        return None;
+    };
+    let tl::enums::Poll::Poll(poll) = &media.poll;
+    let tl::enums::PollResults::Results(results) = &media.results;
+    let votes: HashMap<&[u8], i32> = results
+        .results
+        .iter()
+        .flatten()
+        .map(|r| {
+            let tl::enums::PollAnswerVoters::Voters(v) = r;
+            (v.option.as_slice(), v.voters)
+        })
+        .collect();
+    let has_results = results.results.is_some();
+    Some(Poll {
+        question: text_with_entities(&poll.question),
+        answers: poll
+            .answers
+            .iter()
+            .map(|a| {
+                let tl::enums::PollAnswer::Answer(a) = a;
+                PollAnswer {
+                    text: text_with_entities(&a.text),
+                    voters: has_results
+                        .then(|| votes.get(a.option.as_slice()).copied().unwrap_or(0)),
+                }
+            })
+            .collect(),
+        quiz: poll.quiz,
+        closed: poll.closed,
+        total_voters: results.total_voters,
+    })
+}
+
+fn text_with_entities(text: &tl::enums::TextWithEntities) -> String {
+    let tl::enums::TextWithEntities::Entities(t) = text;
+    t.text.clone()
+}
+
+fn extract_media_ref(m: &tl::types::Message, chat_id: i64) -> Option<MediaReference> {
+    let media = m.media.as_ref()?;
+    let mut document = None;
+    let (media_type, size_bytes) = match media {
+        // Nothing to download; the poll itself is kept on the message.
+        tl::enums::MessageMedia::Poll(_) => return None,
+        tl::enums::MessageMedia::Photo(p) => (
+            MediaType::Photo,
+            p.photo.as_ref().and_then(largest_photo_size),
+        ),
+        tl::enums::MessageMedia::Document(d) => match d.document.as_ref() {
+            Some(tl::enums::Document::Document(doc)) => {
+                let mt = if d.round {
+                    MediaType::VideoNote
+                } else if d.voice {
+                    MediaType::Voice
+                } else {
+                    document_media_type(doc)
+                };
+                document = Some(doc);
+                (mt, Some(doc.size))
+            }
+            _ => (MediaType::Document, None),
+        },
+        _ => (MediaType::Other, None),
     };
     Some(MediaReference {
         message_id: m.id,
         chat_id,
         media_type,
-        opaque_ref: opaque,
+        opaque_ref: format!("{}:{}", chat_id, m.id),
+        size_bytes,
+        filename: document.and_then(document_file_name),
+        mime_type: document
+            .map(|doc| doc.mime_type.clone())
+            .filter(|mime| !mime.is_empty()),
+    })
+}
+
+/// What a document is, from its attributes first: GIFs, voice notes and round videos are
+/// plain mp4/ogg files told apart only by them. The MIME type decides for the rest.
+fn document_media_type(doc: &tl::types::Document) -> MediaType {
+    use tl::enums::DocumentAttribute as A;
+    let attributes = &doc.attributes;
+    // Video stickers (webm) also carry a video attribute; the sticker one wins.
+    if attributes.iter().any(|a| matches!(a, A::Sticker(_))) {
+        return MediaType::Sticker;
+    }
+    if attributes.iter().any(|a| matches!(a, A::Animated)) {
+        return MediaType::Animation;
+    }
+    for attribute in attributes {
+        match attribute {
+            A::Video(v) if v.round_message => return MediaType::VideoNote,
+            A::Audio(a) if a.voice => return MediaType::Voice,
+            _ => {}
+        }
+    }
+    match doc.mime_type.as_str() {
+        "application/x-tgsticker" | "image/webp" => MediaType::Sticker,
+        "image/gif" => MediaType::Animation,
+        mime if mime.starts_with("video/") => MediaType::Video,
+        mime if mime.starts_with("audio/") => MediaType::Audio,
+        _ => MediaType::Document,
+    }
+}
+
+/// File name the sender gave a document (its filename attribute), if any.
+fn document_file_name(doc: &tl::types::Document) -> Option<String> {
+    doc.attributes.iter().find_map(|attribute| match attribute {
+        tl::enums::DocumentAttribute::Filename(f) if !f.file_name.trim().is_empty() => {
+            Some(f.file_name.clone())
+        }
+        _ => None,
     })
 }
+
+/// Byte size of the largest variant of a photo (the one that gets downloaded), if reported.
+fn largest_photo_size(photo: &tl::enums::Photo) -> Option<i64> {
+    let tl::enums::Photo::Photo(photo) = photo else {
+        return None;
+    };
+    photo
+        .sizes
+        .iter()
+        .filter_map(|size| match size {
+            tl::enums::PhotoSize::Size(s) => Some(s.size as i64),
+            tl::enums::PhotoSize::Progressive(s) => s.sizes.iter().max().map(|&n| n as i64),
+            _ => None,
+        })
+        .max()
+}
+
+/// Where upload.getFile reads a message's photo (largest variant) or document from. Used for
+/// raw downloads inside a takeout session; other media has no location.
+pub fn file_location(media: &tl::enums::MessageMedia) -> Option<tl::enums::InputFileLocation> {
+    match media {
+        tl::enums::MessageMedia::Photo(p) => {
+            let Some(tl::enums::Photo::Photo(photo)) = &p.photo else {
+                return None;
+            };
+            let thumb_size = photo
+                .sizes
+                .iter()
+                .filter_map(|size| match size {
+                    tl::enums::PhotoSize::Size(s) => Some((s.size, &s.r#type)),
+                    tl::enums::PhotoSize::Progressive(s) => {
+                        s.sizes.iter().max().map(|&n| (n, &s.r#type))
+                    }
+                    _ => None,
+                })
+                .max_by_key(|(bytes, _)| *bytes)?
+                .1
+                .clone();
+            Some(
+                tl::types::InputPhotoFileLocation {
+                    id: photo.id,
+                    access_hash: photo.access_hash,
+                    file_reference: photo.file_reference.clone(),
+                    thumb_size,
+                }
+                .into(),
+            )
+        }
+        tl::enums::MessageMedia::Document(d) => {
+            let Some(tl::enums::Document::Document(doc)) = &d.document else {
+                return None;
+            };
+            Some(
+                tl::types::InputDocumentFileLocation {
+                    id: doc.id,
+                    access_hash: doc.access_hash,
+                    file_reference: doc.file_reference.clone(),
+                    thumb_size: String::new(),
+                }
+                .into(),
+            )
+        }
+        _ => None,
+    }
+}
+
+/// Bot API dialog ids of channels and supergroups are `-100<channel_id>`.
+pub(crate) const CHANNEL_ID_OFFSET: i64 = 1_000_000_000_000;
+
+/// `entity_registry.peer_type` of peers resolved from Telegram.
+pub const PEER_TYPE_USER: &str = "user";
+pub const PEER_TYPE_CHAT: &str = "chat";
+pub const PEER_TYPE_CHANNEL: &str = "channel";
+
+/// (access_hash, peer_type) of a resolved InputPeer, for the entity registry. Basic groups
+/// need no hash and store 0. None for peers that can't be rebuilt later (self, from-message).
+pub fn input_peer_entity(peer: &tl::enums::InputPeer) -> Option<(i64, &'static str)> {
+    match peer {
+        tl::enums::InputPeer::User(u) => Some((u.access_hash, PEER_TYPE_USER)),
+        tl::enums::InputPeer::Chat(_) => Some((0, PEER_TYPE_CHAT)),
+        tl::enums::InputPeer::Channel(c) => Some((c.access_hash, PEER_TYPE_CHANNEL)),
+        _ => None,
+    }
+}
+
+/// Rebuild the InputPeer of Bot API dialog id `chat_id` from a registry entry. None when the
+/// peer type doesn't match the id (e.g. synthetic senders of imported archives).
+pub fn input_peer_from_entity(
+    chat_id: i64,
+    access_hash: i64,
+    peer_type: &str,
+) -> Option<tl::enums::InputPeer> {
+    match peer_type {
+        PEER_TYPE_USER if chat_id > 0 => Some(
+            tl::types::InputPeerUser {
+                user_id: chat_id,
+                access_hash,
+            }
+            .into(),
+        ),
+        PEER_TYPE_CHAT if chat_id < 0 && chat_id > -CHANNEL_ID_OFFSET => {
+            Some(tl::types::InputPeerChat { chat_id: -chat_id }.into())
+        }
+        PEER_TYPE_CHANNEL if chat_id < -CHANNEL_ID_OFFSET => Some(
+            tl::types::InputPeerChannel {
+                channel_id: -chat_id - CHANNEL_ID_OFFSET,
+                access_hash,
+            }
+            .into(),
+        ),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A message of user 7 in basic group 55 ("breaking news" unless it has `media`),
+    /// forwarded per `fwd_from`.
+    fn tl_message(
+        id: i32,
+        fwd_from: Option<tl::enums::MessageFwdHeader>,
+        media: Option<tl::enums::MessageMedia>,
+    ) -> tl::enums::Message {
+        tl::types::Message {
+            out: false,
+            mentioned: false,
+            media_unread: false,
+            silent: false,
+            post: false,
+            from_scheduled: false,
+            legacy: false,
+            edit_hide: false,
+            pinned: false,
+            noforwards: false,
+            invert_media: false,
+            offline: false,
+            video_processing_pending: false,
+            paid_suggested_post_stars: false,
+            paid_suggested_post_ton: false,
+            id,
+            from_id: Some(tl::types::PeerUser { user_id: 7 }.into()),
+            from_boosts_applied: None,
+            peer_id: tl::types::PeerChat { chat_id: 55 }.into(),
+            saved_peer_id: None,
+            fwd_from,
+            via_bot_id: None,
+            via_business_bot_id: None,
+            reply_to: None,
+            date: 1704067200,
+            message: if media.is_some() {
+                String::new()
+            } else {
+                "breaking news".to_string()
+            },
+            media,
+            reply_markup: None,
+            entities: None,
+            views: None,
+            forwards: None,
+            replies: None,
+            edit_date: None,
+            post_author: None,
+            grouped_id: None,
+            reactions: None,
+            restriction_reason: None,
+            ttl_period: None,
+            quick_reply_shortcut_id: None,
+            effect: None,
+            factcheck: None,
+            report_delivery_until_date: None,
+            paid_message_stars: None,
+            suggested_post: None,
+        }
+        .into()
+    }
+
+    fn fwd_header(
+        from_id: Option<tl::enums::Peer>,
+        from_name: Option<&str>,
+    ) -> tl::enums::MessageFwdHeader {
+        tl::types::MessageFwdHeader {
+            imported: false,
+            saved_out: false,
+            from_id,
+            from_name: from_name.map(String::from),
+            date: 1704000000,
+            channel_post: None,
+            post_author: None,
+            saved_from_peer: None,
+            saved_from_msg_id: None,
+            saved_from_id: None,
+            saved_from_name: None,
+            saved_date: None,
+            psa_type: None,
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_forward_from_channel_keeps_origin() {
+        let channel = tl::types::PeerChannel { channel_id: 42 }.into();
+        let msg = tl_message(10, Some(fwd_header(Some(channel), None)), None);
+        let (mut message, _) = message_to_domain(&msg, -55).unwrap();
+        assert_eq!(message.from_user_id, Some(7));
+        assert_eq!(message.date, 1704067200);
+        assert_eq!(message.forwarded_from_id, Some(-1000000000042));
+        assert_eq!(message.forwarded_date, Some(1704000000));
+        assert_eq!(message.forwarded_from_name, None);
+
+        // The channel came with the batch: its title names the forward.
+        let chats: Vec<tl::enums::Chat> = vec![
+            tl::types::ChannelForbidden {
+                broadcast: true,
+                megagroup: false,
+                id: 42,
+                access_hash: 1,
+                title: "Daily News".to_string(),
+                until_date: None,
+            }
+            .into(),
+        ];
+        fill_forward_names(std::slice::from_mut(&mut message), &[], &chats);
+        assert_eq!(message.forwarded_from_name.as_deref(), Some("Daily News"));
+        assert_eq!(message.forwarded_from().as_deref(), Some("Daily News"));
+    }
+
+    #[test]
+    fn test_forward_from_hidden_sender_and_plain_message() {
+        let msg = tl_message(11, Some(fwd_header(None, Some("Anonymous Fox"))), None);
+        let (message, _) = message_to_domain(&msg, -55).unwrap();
+        assert_eq!(message.forwarded_from_id, None);
+        assert_eq!(message.forwarded_from().as_deref(), Some("Anonymous Fox"));
+
+        let (plain, _) = message_to_domain(&tl_message(12, None, None), -55).unwrap();
+        assert_eq!(plain.text, "breaking news");
+        assert_eq!(plain.forwarded_date, None);
+        assert_eq!(plain.forwarded_from(), None);
+    }
+
+    #[test]
+    fn test_mentions_keep_the_flag_and_mentioned_users() {
+        let mut msg = tl_message(23, None, None);
+        if let tl::enums::Message::Message(m) = &mut msg {
+            m.mentioned = true;
+            m.entities = Some(vec![
+                tl::types::MessageEntityMention {
+                    offset: 0,
+                    length: 5,
+                }
+                .into(),
+                tl::types::MessageEntityMentionName {
+                    offset: 6,
+                    length: 3,
+                    user_id: 42,
+                }
+                .into(),
+                tl::types::MessageEntityMentionName {
+                    offset: 10,
+                    length: 3,
+                    user_id: 42,
+                }
+                .into(),
+            ]);
+        }
+        let (message, _) = message_to_domain(&msg, -55).unwrap();
+        assert!(message.mentioned);
+        assert_eq!(message.mentioned_user_ids, vec![42]);
+        assert!(message.mentions(42) && !message.mentions(7));
+
+        let (plain, _) = message_to_domain(&tl_message(24, None, None), -55).unwrap();
+        assert!(!plain.mentioned && plain.mentioned_user_ids.is_empty());
+    }
+
+    #[test]
+    fn test_discussion_copy_links_channel_post() {
+        let channel = || -> tl::enums::Peer { tl::types::PeerChannel { channel_id: 42 }.into() };
+        let tl::enums::MessageFwdHeader::Header(mut header) = fwd_header(Some(channel()), None);
+        header.channel_post = Some(500);
+        header.saved_from_peer = Some(channel());
+        header.saved_from_msg_id = Some(500);
+        let msg = tl_message(10, Some(header.clone().into()), None);
+        let (copy, _) = message_to_domain(&msg, -1000000000077).unwrap();
+        assert_eq!(copy.linked_channel_post, Some(500));
+        assert_eq!(copy.forwarded_from_id, Some(-1000000000042));
+
+        // Saved from a user's chat: a plain forward, not a discussion copy.
+        header.saved_from_peer = Some(tl::types::PeerUser { user_id: 7 }.into());
+        let msg = tl_message(11, Some(header.into()), None);
+        let (forward, _) = message_to_domain(&msg, -1000000000077).unwrap();
+        assert_eq!(forward.linked_channel_post, None);
+    }
+
+    fn poll_media(results: Option<Vec<(u8, i32)>>) -> tl::enums::MessageMedia {
+        let text = |s: &str| -> tl::enums::TextWithEntities {
+            tl::types::TextWithEntities {
+                text: s.to_string(),
+                entities: Vec::new(),
+            }
+            .into()
+        };
+        let answer = |option: u8, s: &str| -> tl::enums::PollAnswer {
+            tl::types::PollAnswer {
+                text: text(s),
+                option: vec![option],
+            }
+            .into()
+        };
+        tl::types::MessageMediaPoll {
+            poll: tl::types::Poll {
+                id: 1,
+                closed: false,
+                public_voters: false,
+                multiple_choice: false,
+                quiz: false,
+                question: text("Lunch?"),
+                answers: vec![answer(0, "Pizza"), answer(1, "Sushi")],
+                close_period: None,
+                close_date: None,
+            }
+            .into(),
+            results: tl::types::PollResults {
+                min: false,
+                total_voters: results.as_ref().map(|r| r.iter().map(|(_, n)| n).sum()),
+                results: results.map(|r| {
+                    r.into_iter()
+                        .map(|(option, voters)| {
+                            tl::types::PollAnswerVoters {
+                                chosen: false,
+                                correct: false,
+                                option: vec![option],
+                                voters,
+                            }
+                            .into()
+                        })
+                        .collect()
+                }),
+                recent_voters: None,
+                solution: None,
+                solution_entities: None,
+            }
+            .into(),
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_poll_is_kept_with_results_and_rendered_as_text() {
+        let msg = tl_message(13, None, Some(poll_media(Some(vec![(0, 12), (1, 3)]))));
+        let (message, media_ref) = message_to_domain(&msg, -55).unwrap();
+        assert!(media_ref.is_none());
+        assert_eq!(message.text, "[Poll] Lunch? — Pizza (12), Sushi (3)");
+        let poll = message.poll.unwrap();
+        assert_eq!(poll.total_voters, Some(15));
+        assert_eq!(poll.answers[1].voters, Some(3));
+
+        // Results hidden until the account votes: answers without counts.
+        let msg = tl_message(14, None, Some(poll_media(None)));
+        let (message, _) = message_to_domain(&msg, -55).unwrap();
+        assert_eq!(message.text, "[Poll] Lunch? — Pizza, Sushi");
+        assert_eq!(message.poll.unwrap().answers[0].voters, None);
+    }
+
+    fn document_media(
+        mime: &str,
+        attributes: Vec<tl::enums::DocumentAttribute>,
+    ) -> tl::enums::MessageMedia {
+        tl::types::MessageMediaDocument {
+            nopremium: false,
+            spoiler: false,
+            video: false,
+            round: false,
+            voice: false,
+            document: Some(
+                tl::types::Document {
+                    id: 1,
+                    access_hash: 2,
+                    file_reference: Vec::new(),
+                    date: 1704067200,
+                    mime_type: mime.to_string(),
+                    size: 4096,
+                    thumbs: None,
+                    video_thumbs: None,
+                    dc_id: 2,
+                    attributes,
+                }
+                .into(),
+            ),
+            alt_documents: None,
+            video_cover: None,
+            video_timestamp: None,
+            ttl_seconds: None,
+        }
+        .into()
+    }
+
+    fn video_attribute(round_message: bool) -> tl::enums::DocumentAttribute {
+        tl::types::DocumentAttributeVideo {
+            round_message,
+            supports_streaming: true,
+            nosound: false,
+            duration: 3.0,
+            w: 240,
+            h: 240,
+            preload_prefix_size: None,
+            video_start_ts: None,
+            video_codec: None,
+        }
+        .into()
+    }
+
+    fn audio_attribute(voice: bool) -> tl::enums::DocumentAttribute {
+        tl::types::DocumentAttributeAudio {
+            voice,
+            duration: 3,
+            title: None,
+            performer: None,
+            waveform: None,
+        }
+        .into()
+    }
+
+    fn sticker_attribute() -> tl::enums::DocumentAttribute {
+        tl::types::DocumentAttributeSticker {
+            mask: false,
+            alt: "🙂".to_string(),
+            stickerset: tl::enums::InputStickerSet::Empty,
+            mask_coords: None,
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_documents_are_classified_by_attributes_then_mime() {
+        use tl::enums::DocumentAttribute as A;
+        let file_name = |name: &str| -> A {
+            tl::types::DocumentAttributeFilename {
+                file_name: name.to_string(),
+            }
+            .into()
+        };
+        for (mime, attributes, expected) in [
+            ("audio/ogg", vec![audio_attribute(true)], MediaType::Voice),
+            (
+                "audio/mpeg",
+                vec![audio_attribute(false), file_name("song.mp3")],
+                MediaType::Audio,
+            ),
+            (
+                "video/mp4",
+                vec![video_attribute(true)],
+                MediaType::VideoNote,
+            ),
+            ("video/mp4", vec![video_attribute(false)], MediaType::Video),
+            (
+                "video/mp4",
+                vec![video_attribute(false), A::Animated],
+                MediaType::Animation,
+            ),
+            ("image/gif", vec![], MediaType::Animation),
+            ("image/webp", vec![sticker_attribute()], MediaType::Sticker),
+            (
+                "video/webm",
+                vec![sticker_attribute(), video_attribute(false)],
+                MediaType::Sticker,
+            ),
+            ("application/x-tgsticker", vec![], MediaType::Sticker),
+            ("image/webp", vec![], MediaType::Sticker),
+            (
+                "application/pdf",
+                vec![file_name("report.pdf")],
+                MediaType::Document,
+            ),
+        ] {
+            let msg = tl_message(20, None, Some(document_media(mime, attributes)));
+            let (_, media_ref) = message_to_domain(&msg, -55).unwrap();
+            let media_ref = media_ref.unwrap();
+            assert_eq!(media_ref.media_type, expected, "{}", mime);
+            assert_eq!(media_ref.mime_type.as_deref(), Some(mime));
+            assert_eq!(media_ref.size_bytes, Some(4096));
+        }
+
+        // Parts of an album keep its id.
+        let mut msg = tl_message(22, None, Some(document_media("video/mp4", vec![])));
+        if let tl::enums::Message::Message(m) = &mut msg {
+            m.grouped_id = Some(13_457_000_000_000_000);
+        }
+        let (message, _) = message_to_domain(&msg, -55).unwrap();
+        assert_eq!(message.grouped_id, Some(13_457_000_000_000_000));
+
+        // The message flags alone are enough for voice and round video messages.
+        let mut media = document_media("audio/ogg", vec![]);
+        if let tl::enums::MessageMedia::Document(d) = &mut media {
+            d.voice = true;
+        }
+        let (_, media_ref) = message_to_domain(&tl_message(21, None, Some(media)), -55).unwrap();
+        assert_eq!(media_ref.unwrap().media_type, MediaType::Voice);
+    }
+
+    /// Service message of user 7 in basic group 55.
+    fn tl_service(id: i32, action: tl::enums::MessageAction) -> tl::enums::Message {
+        tl::types::MessageService {
+            out: false,
+            mentioned: false,
+            media_unread: false,
+            reactions_are_possible: false,
+            silent: false,
+            post: false,
+            legacy: false,
+            id,
+            from_id: Some(tl::types::PeerUser { user_id: 7 }.into()),
+            peer_id: tl::types::PeerChat { chat_id: 55 }.into(),
+            saved_peer_id: None,
+            reply_to: None,
+            date: 1704067200,
+            action,
+            reactions: None,
+            ttl_period: None,
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_service_messages_become_events() {
+        let joined = tl_service(
+            20,
+            tl::types::MessageActionChatAddUser { users: vec![7] }.into(),
+        );
+        assert!(message_to_domain(&joined, -55).is_none());
+        let event = service_to_event(&joined, -55).unwrap();
+        assert_eq!((event.id, event.chat_id), (20, -55));
+        assert_eq!(event.kind, ChatEventKind::UserJoined);
+        assert_eq!(event.actor_id, Some(7));
+        assert_eq!(event.payload, None);
+
+        let added = tl_service(
+            21,
+            tl::types::MessageActionChatAddUser { users: vec![8, 9] }.into(),
+        );
+        let event = service_to_event(&added, -55).unwrap();
+        assert_eq!(event.payload.as_deref(), Some("8,9"));
+
+        let removed = tl_service(
+            22,
+            tl::types::MessageActionChatDeleteUser { user_id: 8 }.into(),
+        );
+        let event = service_to_event(&removed, -55).unwrap();
+        assert_eq!(event.kind, ChatEventKind::UserLeft);
+        assert_eq!(event.payload.as_deref(), Some("8"));
+
+        let renamed = tl_service(
+            23,
+            tl::types::MessageActionChatEditTitle {
+                title: "Launch".to_string(),
+            }
+            .into(),
+        );
+        let event = service_to_event(&renamed, -55).unwrap();
+        assert_eq!(event.kind, ChatEventKind::TitleChanged);
+        assert_eq!(event.payload.as_deref(), Some("Launch"));
+
+        let cleared = tl_service(24, tl::enums::MessageAction::HistoryClear);
+        let event = service_to_event(&cleared, -55).unwrap();
+        assert_eq!(event.kind, ChatEventKind::Other);
+        assert_eq!(event.payload.as_deref(), Some("HistoryClear"));
+
+        assert!(service_to_event(&tl_message(25, None, None), -55).is_none());
+    }
+}
@@ -0,0 +1,55 @@
+//! Implements AuthPromptPort with inquire prompts (interactive terminal login).
+
+use crate::domain::{DomainError, LoginMethod, QrToken};
+use crate::ports::AuthPromptPort;
+
+/// Asks for the login method, phone, code and 2FA password on the terminal.
+#[derive(Default)]
+pub struct InquireAuthPrompt;
+
+impl InquireAuthPrompt {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[async_trait::async_trait]
+impl AuthPromptPort for InquireAuthPrompt {
+    async fn login_method(&self) -> Result<LoginMethod, DomainError> {
+        let options = vec!["Phone + code", "QR code"];
+        let choice = inquire::Select::new("Log in with:", options)
+            .prompt()
+            .map_err(|e| DomainError::Auth(format!("input: {}", e)))?;
+        Ok(match choice {
+            "QR code" => LoginMethod::Qr,
+            _ => LoginMethod::PhoneCode,
+        })
+    }
+
+    async fn show_qr(&self, _token: &QrToken, qr: &str) -> Result<(), DomainError> {
+        println!("{}", qr);
+        println!(
+            "Scan with Telegram on your phone: Settings → Devices → Link Desktop Device. Waiting..."
+        );
+        Ok(())
+    }
+
+    async fn phone(&self) -> Result<String, DomainError> {
+        inquire::Text::new("Phone number (e.g. +1234567890):")
+            .prompt()
+            .map_err(|e| DomainError::Auth(format!("input: {}", e)))
+    }
+
+    async fn login_code(&self) -> Result<String, DomainError> {
+        inquire::Text::new("Login code from Telegram:")
+            .prompt()
+            .map_err(|e| DomainError::Auth(format!("input: {}", e)))
+    }
+
+    async fn password(&self, hint: Option<&str>) -> Result<String, DomainError> {
+        let prompt = format!("2FA password (hint: {}):", hint.unwrap_or("(no hint)"));
+        inquire::Password::new(&prompt)
+            .prompt()
+            .map_err(|e| DomainError::Auth(format!("input: {}", e)))
+    }
+}
@@ -1,3 +1,4 @@
+pub mod auth_prompt;
 pub mod banner;
 pub mod progress;
 pub mod tui;
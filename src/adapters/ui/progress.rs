@@ -1,3 +1,179 @@
-//! Progress bars via indicatif. Skeleton for sync/media progress.
+//! Progress bars via indicatif. Implements ProgressPort for the sync flow.
+//!
+//! One bar per chat (several at once when chats sync concurrently): a determinate bar with ETA when the dialog's approximate message
+//! count is known, otherwise a spinner that still shows the running count and rate.
+//! Log lines go through [`IndicatifProgress::log_writer`], which hides the bars while a
+//! line is written so tracing output and the bar do not overwrite each other.
+//!
+//! Without a terminal (systemd, redirected output) use [`LogProgress`] instead.
 
-// use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use crate::domain::SyncProgress;
+use crate::ports::ProgressPort;
+use crate::shared::eta::format_eta;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::info;
+use tracing_subscriber::fmt::MakeWriter;
+
+/// TUI progress reporter. Holds the bars of the chats currently being synced.
+#[derive(Default)]
+pub struct IndicatifProgress {
+    multi: MultiProgress,
+    bars: Mutex<HashMap<i64, ProgressBar>>,
+}
+
+impl IndicatifProgress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writer for the tracing fmt layer: suspends the bars around each log line.
+    pub fn log_writer(&self) -> ProgressLogWriter {
+        ProgressLogWriter {
+            multi: self.multi.clone(),
+        }
+    }
+}
+
+/// Stdout writer that clears the progress bars, writes, and redraws them.
+#[derive(Clone)]
+pub struct ProgressLogWriter {
+    multi: MultiProgress,
+}
+
+impl Write for ProgressLogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.multi
+            .suspend(|| io::stdout().lock().write_all(buf))
+            .map(|()| buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
+
+impl<'a> MakeWriter<'a> for ProgressLogWriter {
+    type Writer = ProgressLogWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+fn media_suffix(media_queued: u64) -> String {
+    if media_queued == 0 {
+        String::new()
+    } else {
+        format!(" · {} media queued", media_queued)
+    }
+}
+
+impl ProgressPort for IndicatifProgress {
+    fn chat_started(&self, chat_id: i64, title: &str, expected: Option<u64>) {
+        let bar = match expected {
+            Some(total) => {
+                let bar = ProgressBar::new(total);
+                bar.set_style(
+                    ProgressStyle::default_bar()
+                        .template("{prefix:.cyan} [{bar:30.magenta/blue}] {pos}/~{len} {msg}")
+                        .unwrap()
+                        .progress_chars("█▉ "),
+                );
+                bar
+            }
+            None => {
+                let bar = ProgressBar::new_spinner();
+                bar.set_style(
+                    ProgressStyle::default_spinner()
+                        .tick_chars("⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏")
+                        .template("{spinner:.cyan} {prefix:.cyan} {pos} msgs {msg}")
+                        .unwrap(),
+                );
+                bar.enable_steady_tick(Duration::from_millis(100));
+                bar
+            }
+        };
+        let bar = self.multi.add(bar);
+        bar.set_prefix(title.to_string());
+        bar.set_message(format_eta(None));
+        if let Ok(mut bars) = self.bars.lock() {
+            if let Some(old) = bars.insert(chat_id, bar) {
+                old.finish_and_clear();
+            }
+        }
+    }
+
+    fn chat_progress(&self, progress: &SyncProgress) {
+        let Ok(bars) = self.bars.lock() else {
+            return;
+        };
+        let Some(bar) = bars.get(&progress.chat_id) else {
+            return;
+        };
+        // approx_message_count is a heuristic; grow the bar instead of overflowing it.
+        if bar.length().is_some_and(|len| progress.synced > len) {
+            bar.set_length(progress.synced);
+        }
+        bar.set_position(progress.synced);
+        let rate = progress
+            .rate_per_sec
+            .map(|r| format!("{:.0} msg/s · ", r))
+            .unwrap_or_default();
+        bar.set_message(format!(
+            "{}{}{}",
+            rate,
+            format_eta(progress.eta),
+            media_suffix(progress.media_queued)
+        ));
+    }
+
+    fn chat_finished(&self, chat_id: i64, synced: u64, media_queued: u64) {
+        if let Ok(mut bars) = self.bars.lock() {
+            if let Some(bar) = bars.remove(&chat_id) {
+                // The estimate may have overshot; a finished chat is a full bar.
+                bar.set_length(synced);
+                bar.set_position(synced);
+                bar.finish_with_message(format!(
+                    "done ({} messages{})",
+                    synced,
+                    media_suffix(media_queued)
+                ));
+            }
+        }
+    }
+}
+
+/// Log-only progress reporter for runs without a terminal: one line per chat start, batch
+/// and finish, through tracing.
+#[derive(Default)]
+pub struct LogProgress;
+
+impl LogProgress {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl ProgressPort for LogProgress {
+    fn chat_started(&self, chat_id: i64, title: &str, expected: Option<u64>) {
+        info!(chat_id, title, expected, "chat sync started");
+    }
+
+    fn chat_progress(&self, progress: &SyncProgress) {
+        info!(
+            chat_id = progress.chat_id,
+            synced = progress.synced,
+            media_queued = progress.media_queued,
+            eta = %format_eta(progress.eta),
+            "chat sync progress"
+        );
+    }
+
+    fn chat_finished(&self, chat_id: i64, synced: u64, media_queued: u64) {
+        info!(chat_id, synced, media_queued, "chat sync finished");
+    }
+}
@@ -1,17 +1,39 @@
 //! Implements InputPort. Inquire-based interactive prompts.
 //!
 //! Cyberpunk/Neon theme: prompt prefix [?], colored ChatType indicators.
+//!
+//! Chat lists start with a type filter (All / Groups / Channels / Private), list the most
+//! recently active chats first, and match typed filter text against the plain option text
+//! (type tag, title, id), so the color codes of the tags never get in the way.
 
-use crate::domain::{Chat, ChatType, DomainError};
-use crate::ports::{InputPort, RepoPort, TgGateway};
-use crate::usecases::{AnalysisService, SyncService, WatcherService};
+use crate::domain::{
+    ActivityBucket, AnalysisResult, Chat, ChatSettings, ChatType, DomainError, ExportFormat,
+    GENERAL_TOPIC_ID, Granularity, MediaFilter, MediaType, PeriodGroup, TimeRange, TopicFilter,
+    UsageTotals, WatchRule, WatchRuleKind,
+};
+use crate::ports::{ExporterPort, InputPort, RepoPort, TgGateway};
+use crate::shared::activity;
+use crate::shared::eta::format_duration;
+use crate::shared::profile;
+use crate::usecases::{
+    AnalysisReport, AnalysisService, AuditService, BackupService, ChatSyncResult, DaemonController,
+    ExportOptions, ExportService, ImportOptions, ImportService, MaintenanceService, MediaBackfill,
+    PurgeService, SyncPlan, SyncService, WatcherService, validate_watch_pattern,
+};
 use async_trait::async_trait;
+use chrono::NaiveDate;
 use indicatif::{ProgressBar, ProgressStyle};
 use inquire::ui::{Color, RenderConfig, StyleSheet, Styled};
-use inquire::{Confirm, CustomType, MultiSelect, Select, Text, set_global_render_config};
-use std::collections::HashSet;
+use inquire::validator::Validation;
+use inquire::{
+    Confirm, CustomType, InquireError, MultiSelect, Select, Text, set_global_render_config,
+};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::sync::Arc;
 use std::time::Duration;
+use tracing::warn;
 
 /// Neon Purple (#bc13fe) for prompt prefix and accents.
 const NEON_PURPLE: Color = Color::Rgb {
@@ -38,15 +60,489 @@ fn ansi_rgb(r: u8, g: u8, b: u8) -> String {
 
 const RESET: &str = "\x1b[0m";
 
+/// Short description of a media filter, e.g. "photo, voice up to 50 MiB".
+fn describe_media_filter(filter: &MediaFilter) -> String {
+    let types = match &filter.types {
+        None => "all types".to_string(),
+        Some(t) if t.is_empty() => "no media".to_string(),
+        Some(t) => t.iter().map(|t| t.as_str()).collect::<Vec<_>>().join(", "),
+    };
+    match filter.max_size_bytes {
+        Some(max) => format!("{} up to {} MiB", types, max / (1024 * 1024)),
+        None => types,
+    }
+}
+
+/// Byte count with a binary unit, e.g. `1.5 MiB`.
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Final Full Backup table: one row per chat, failures with their error text.
+fn print_sync_summary(chats: &[Chat], results: &[ChatSyncResult]) {
+    let title_of = |chat_id: i64| {
+        chats
+            .iter()
+            .find(|c| c.id == chat_id)
+            .map_or_else(|| chat_id.to_string(), |c| c.title.clone())
+    };
+    let width = results
+        .iter()
+        .map(|(id, _)| title_of(*id).chars().count())
+        .max()
+        .unwrap_or(0)
+        .clamp(4, 40);
+
+    println!("\n📦 Backup summary\n");
+    println!(
+        "  {:<width$}  {:>8}  {:>6}  {:>8}",
+        "Chat", "New msgs", "Media", "Time"
+    );
+    let (mut messages, mut failed, mut skipped) = (0, 0, 0);
+    for (chat_id, result) in results {
+        let title: String = title_of(*chat_id).chars().take(width).collect();
+        match result {
+            Ok(stats) => {
+                messages += stats.messages_synced;
+                println!(
+                    "  {:<width$}  {:>8}  {:>6}  {:>7.1}s",
+                    title,
+                    stats.messages_synced,
+                    stats.media_queued,
+                    stats.duration.as_secs_f64()
+                );
+            }
+            Err(DomainError::FloodWait { seconds }) => {
+                skipped += 1;
+                println!(
+                    "  {:<width$}  ⏭️  skipped after repeated FloodWait ({}s); run the backup again later",
+                    title, seconds
+                );
+            }
+            Err(e) => {
+                failed += 1;
+                println!("  {:<width$}  ❌ {}", title, e);
+            }
+        }
+    }
+    println!(
+        "\n  {} chat(s) synced, {} new message(s), {} failed, {} skipped (FloodWait).",
+        results.len() - failed - skipped,
+        messages,
+        failed,
+        skipped
+    );
+}
+
+/// Full Backup preview: per chat the checkpoint, the newest message and what would be
+/// fetched, or why the chat is left out.
+fn print_sync_plan(plans: &[SyncPlan]) {
+    let title_of = |p: &SyncPlan| p.title.clone().unwrap_or_else(|| p.chat_id.to_string());
+    let width = plans
+        .iter()
+        .map(|p| title_of(p).chars().count())
+        .max()
+        .unwrap_or(0)
+        .clamp(4, 40);
+
+    println!("\n🔎 Backup preview (estimates)\n");
+    println!(
+        "  {:<width$}  {:>9}  {:>9}  {:>9}  {:>7}",
+        "Chat", "Synced to", "Newest", "~New msgs", "~Media"
+    );
+    let (mut messages, mut media, mut skipped) = (0, 0, 0);
+    for plan in plans {
+        let title: String = title_of(plan).chars().take(width).collect();
+        if let Some(skip) = plan.skip {
+            skipped += 1;
+            println!("  {:<width$}  ⏭️  skipped: {}", title, skip);
+        } else if let Some(e) = &plan.error {
+            println!("  {:<width$}  ⚠️  could not estimate: {}", title, e);
+        } else {
+            messages += plan.estimated_messages;
+            media += plan.estimated_media;
+            println!(
+                "  {:<width$}  {:>9}  {:>9}  {:>9}  {:>7}",
+                title,
+                plan.last_message_id,
+                plan.top_message_id
+                    .map_or_else(|| "-".to_string(), |id| id.to_string()),
+                plan.estimated_messages,
+                plan.estimated_media
+            );
+        }
+    }
+    println!(
+        "\n  ~{} new message(s) and ~{} media file(s) in {} chat(s); {} skipped.\n",
+        messages,
+        media,
+        plans.len() - skipped,
+        skipped
+    );
+}
+
+/// Media types offered by the per-chat settings editor.
+const MEDIA_TYPES: [MediaType; 9] = [
+    MediaType::Photo,
+    MediaType::Video,
+    MediaType::Document,
+    MediaType::Audio,
+    MediaType::Voice,
+    MediaType::Sticker,
+    MediaType::Animation,
+    MediaType::VideoNote,
+    MediaType::Other,
+];
+
+/// Entry of a chat list: a type tag (shown colored) followed by plain text. Filtering
+/// matches [`ChatOption::plain`], never the color codes.
+struct ChatOption {
+    kind: Option<ChatType>,
+    text: String,
+}
+
+impl ChatOption {
+    /// Title, id and the chat's overrides (e.g. "[media:off]").
+    fn new(chat: &Chat, settings: &HashMap<i64, ChatSettings>) -> Self {
+        let mut text = format!("{} ({})", chat.title, chat.id);
+        if let Some(label) = settings
+            .get(&chat.id)
+            .map(ChatSettings::label)
+            .filter(|label| !label.is_empty())
+        {
+            text = format!("{} {}", text, label);
+        }
+        Self {
+            kind: Some(chat.kind),
+            text,
+        }
+    }
+
+    /// A non-chat entry such as "Done".
+    fn other(text: &str) -> Self {
+        Self {
+            kind: None,
+            text: text.to_string(),
+        }
+    }
+
+    /// Option text without color codes.
+    fn plain(&self) -> String {
+        match self.kind {
+            Some(kind) => format!("{} {}", chat_type_tag(kind), self.text),
+            None => self.text.clone(),
+        }
+    }
+}
+
+impl fmt::Display for ChatOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            Some(kind) => write!(f, "{} {}", chat_type_indicator(kind), self.text),
+            None => f.write_str(&self.text),
+        }
+    }
+}
+
+/// Filter for chat options: case-insensitive substring of the plain text. Matches keep
+/// their list order.
+fn chat_scorer(input: &str, option: &ChatOption, _: &str, index: usize) -> Option<i64> {
+    let input = input.trim().to_lowercase();
+    (input.is_empty() || option.plain().to_lowercase().contains(&input)).then_some(-(index as i64))
+}
+
+/// Chat types offered in front of chat lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChatKindFilter {
+    All,
+    Groups,
+    Channels,
+    Private,
+}
+
+impl ChatKindFilter {
+    const ALL: [ChatKindFilter; 4] = [
+        ChatKindFilter::All,
+        ChatKindFilter::Groups,
+        ChatKindFilter::Channels,
+        ChatKindFilter::Private,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            ChatKindFilter::All => "All",
+            ChatKindFilter::Groups => "Groups",
+            ChatKindFilter::Channels => "Channels",
+            ChatKindFilter::Private => "Private",
+        }
+    }
+
+    /// Groups include supergroups.
+    fn matches(self, kind: ChatType) -> bool {
+        match self {
+            ChatKindFilter::All => true,
+            ChatKindFilter::Groups => matches!(kind, ChatType::Group | ChatType::Supergroup),
+            ChatKindFilter::Channels => kind == ChatType::Channel,
+            ChatKindFilter::Private => kind == ChatType::Private,
+        }
+    }
+}
+
+/// Ask which chat types to list (with counts); types without chats are left out. No prompt
+/// when only one type is present.
+fn prompt_chat_kind(message: &str, chats: &[Chat]) -> Result<ChatKindFilter, DomainError> {
+    let offered: Vec<(ChatKindFilter, usize)> = ChatKindFilter::ALL
+        .into_iter()
+        .map(|f| (f, chats.iter().filter(|c| f.matches(c.kind)).count()))
+        .filter(|(f, n)| *f == ChatKindFilter::All || *n > 0)
+        .collect();
+    if offered.len() <= 2 {
+        return Ok(ChatKindFilter::All);
+    }
+    let labels: Vec<String> = offered
+        .iter()
+        .map(|(f, n)| format!("{} ({})", f.label(), n))
+        .collect();
+    let picked = Select::new(message, labels)
+        .without_filtering()
+        .raw_prompt()
+        .map_err(prompt_error)?;
+    Ok(offered[picked.index].0)
+}
+
+/// Chats matching `filter`, most recently active first (chats without a known last message
+/// keep their order at the end).
+fn chats_by_activity(chats: &[Chat], filter: ChatKindFilter) -> Vec<Chat> {
+    let mut listed: Vec<Chat> = chats
+        .iter()
+        .filter(|c| filter.matches(c.kind))
+        .cloned()
+        .collect();
+    listed.sort_by_key(|c| Reverse(c.last_message_date));
+    listed
+}
+
+/// Type filter, then a searchable MultiSelect over the matching chats with `checked`
+/// pre-selected. Returns the ids of the listed chats and of the checked ones among them;
+/// callers keep their previous choice for chats that were not listed.
+fn pick_chats(
+    message: &str,
+    help: &str,
+    chats: &[Chat],
+    checked: &HashSet<i64>,
+    settings: &HashMap<i64, ChatSettings>,
+) -> Result<(HashSet<i64>, HashSet<i64>), DomainError> {
+    let filter = prompt_chat_kind("Which chats to list?", chats)?;
+    let listed = chats_by_activity(chats, filter);
+    let options: Vec<ChatOption> = listed
+        .iter()
+        .map(|c| ChatOption::new(c, settings))
+        .collect();
+    let default: Vec<usize> = listed
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| checked.contains(&c.id))
+        .map(|(i, _)| i)
+        .collect();
+    let help = format!(
+        "{} ({} of {} selected; type to search)",
+        help,
+        default.len(),
+        listed.len()
+    );
+    let selected = MultiSelect::new(message, options)
+        .with_default(&default)
+        .with_scorer(&chat_scorer)
+        .with_formatter(&|picked| format!("{} chat(s) selected", picked.len()))
+        .with_help_message(&help)
+        .raw_prompt()
+        .map_err(prompt_error)?;
+    Ok((
+        listed.iter().map(|c| c.id).collect(),
+        selected.iter().map(|o| listed[o.index].id).collect(),
+    ))
+}
+
+/// Type tag of a chat: [U] private, [G] group, [S] supergroup, [C] channel.
+fn chat_type_tag(kind: ChatType) -> &'static str {
+    match kind {
+        ChatType::Private => "[U]",
+        ChatType::Group => "[G]",
+        ChatType::Supergroup => "[S]",
+        ChatType::Channel => "[C]",
+    }
+}
+
 /// Returns the ChatType indicator with ANSI color: [U] cyan, [G]/[S] green, [C] yellow.
 fn chat_type_indicator(kind: ChatType) -> String {
-    let (tag, r, g, b) = match kind {
-        ChatType::Private => ("[U]", USER_CYAN.0, USER_CYAN.1, USER_CYAN.2),
-        ChatType::Group => ("[G]", GROUP_GREEN.0, GROUP_GREEN.1, GROUP_GREEN.2),
-        ChatType::Supergroup => ("[S]", GROUP_GREEN.0, GROUP_GREEN.1, GROUP_GREEN.2),
-        ChatType::Channel => ("[C]", CHANNEL_YELLOW.0, CHANNEL_YELLOW.1, CHANNEL_YELLOW.2),
+    let (r, g, b) = match kind {
+        ChatType::Private => USER_CYAN,
+        ChatType::Group | ChatType::Supergroup => GROUP_GREEN,
+        ChatType::Channel => CHANNEL_YELLOW,
+    };
+    format!("{}{}{}", ansi_rgb(r, g, b), chat_type_tag(kind), RESET)
+}
+
+/// Error of an inquire prompt: Esc and Ctrl+C become [`DomainError::Cancelled`] (back to the
+/// menu), anything else (e.g. no terminal) fails the action.
+/// Startup choice between the default profile and the named `profiles`. None = the default.
+pub fn pick_profile(profiles: &[String]) -> Result<Option<String>, DomainError> {
+    let mut options = vec![profile::DEFAULT_PROFILE.to_string()];
+    options.extend(profiles.iter().cloned());
+    let picked = Select::new("Profile:", options)
+        .with_help_message("Each profile has its own session, archive and settings")
+        .prompt()
+        .map_err(prompt_error)?;
+    Ok((picked != profile::DEFAULT_PROFILE).then_some(picked))
+}
+
+/// Print a stored analysis: summary, topics and action items.
+fn print_analysis(result: &AnalysisResult) {
+    println!(
+        "\n📄 {} digest {}\n\n{}\n",
+        result.granularity.adjective(),
+        result.week_group,
+        result.summary
+    );
+    if !result.key_topics.is_empty() {
+        println!("Topics:");
+        for topic in &result.key_topics {
+            println!("  • {}", topic);
+        }
+        println!();
+    }
+    if result.action_items.is_empty() {
+        println!("No action items.\n");
+        return;
+    }
+    println!("Action items:");
+    for item in &result.action_items {
+        let meta: Vec<String> = [
+            item.owner.as_ref().map(|o| format!("owner: {}", o)),
+            item.deadline.as_ref().map(|d| format!("due: {}", d)),
+            item.priority.as_ref().map(|p| format!("priority: {}", p)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if meta.is_empty() {
+            println!("  • {}", item.description);
+        } else {
+            println!("  • {} ({})", item.description, meta.join(", "));
+        }
+    }
+    println!();
+}
+
+fn prompt_error(e: InquireError) -> DomainError {
+    match e {
+        InquireError::OperationCanceled | InquireError::OperationInterrupted => {
+            DomainError::Cancelled
+        }
+        e => DomainError::Auth(e.to_string()),
+    }
+}
+
+/// Optional `YYYY-MM-DD` date; empty input is None.
+fn prompt_date(message: &str) -> Result<Option<NaiveDate>, DomainError> {
+    let input = Text::new(message)
+        .with_validator(|s: &str| {
+            if s.trim().is_empty() || NaiveDate::parse_from_str(s.trim(), "%Y-%m-%d").is_ok() {
+                Ok(Validation::Valid)
+            } else {
+                Ok(Validation::Invalid("Expected YYYY-MM-DD".into()))
+            }
+        })
+        .prompt()
+        .map_err(prompt_error)?;
+    Ok(NaiveDate::parse_from_str(input.trim(), "%Y-%m-%d").ok())
+}
+
+/// Ask for the overrides of one chat, starting from `current`.
+fn prompt_chat_settings(title: &str, current: ChatSettings) -> Result<ChatSettings, DomainError> {
+    const DEFAULT: &str = "Default (as chosen for each sync)";
+    const ALL: &str = "All media";
+    const SOME: &str = "Only some types";
+    const NONE: &str = "No media (text only)";
+    let cursor = match (current.include_media, &current.media_types) {
+        (Some(false), _) => 3,
+        (_, Some(_)) => 2,
+        (Some(true), None) => 1,
+        (None, None) => 0,
     };
-    format!("{}{}{}", ansi_rgb(r, g, b), tag, RESET)
+    let media = Select::new(
+        &format!("Media for {}", title),
+        vec![DEFAULT, ALL, SOME, NONE],
+    )
+    .with_starting_cursor(cursor)
+    .prompt()
+    .map_err(prompt_error)?;
+    let mut settings = ChatSettings {
+        include_media: None,
+        media_types: None,
+        ..current.clone()
+    };
+    match media {
+        ALL => settings.include_media = Some(true),
+        NONE => settings.include_media = Some(false),
+        SOME => {
+            let names: Vec<&str> = MEDIA_TYPES.iter().map(|t| t.as_str()).collect();
+            let default: Vec<usize> = MEDIA_TYPES
+                .iter()
+                .enumerate()
+                .filter(|(_, t)| {
+                    current
+                        .media_types
+                        .as_ref()
+                        .is_some_and(|types| types.contains(t))
+                })
+                .map(|(i, _)| i)
+                .collect();
+            let picked = MultiSelect::new("Media types to download", names)
+                .with_default(&default)
+                .prompt()
+                .map_err(prompt_error)?;
+            settings.include_media = Some(true);
+            settings.media_types = Some(
+                MEDIA_TYPES
+                    .into_iter()
+                    .filter(|t| picked.contains(&t.as_str()))
+                    .collect(),
+            );
+        }
+        _ => {}
+    }
+
+    let limit = CustomType::<i32>::new("Messages per history request (0 = default)")
+        .with_default(current.batch_limit.unwrap_or(0))
+        .with_parser(&|s: &str| {
+            s.trim()
+                .parse::<i32>()
+                .ok()
+                .filter(|n| (0..=100).contains(n))
+                .ok_or(())
+        })
+        .with_error_message("Enter a number from 0 to 100")
+        .prompt()
+        .map_err(prompt_error)?;
+    settings.batch_limit = (limit > 0).then_some(limit);
+
+    settings.sync_enabled = Confirm::new("Sync this chat in the Watcher?")
+        .with_default(current.sync_enabled)
+        .with_help_message("No = skipped by the watcher even when it is a target")
+        .prompt()
+        .map_err(prompt_error)?;
+    Ok(settings)
 }
 
 /// Applies the global Cyberpunk/Neon RenderConfig for inquire prompts.
@@ -68,95 +564,638 @@ pub struct TuiInputPort {
     repo: Arc<dyn RepoPort>,
     sync_service: Arc<SyncService>,
     watcher_service: Arc<WatcherService>,
+    /// Runs the watcher in the background while the menu stays open.
+    watcher_daemon: Arc<DaemonController>,
     analysis_service: Arc<AnalysisService>,
+    export_service: Arc<ExportService>,
+    /// Rendered chat formats offered by the Export menu (HTML, ...).
+    exporters: Vec<Arc<dyn ExporterPort>>,
+    import_service: Arc<ImportService>,
+    audit_service: Arc<AuditService>,
+    maintenance_service: Arc<MaintenanceService>,
+    purge_service: Arc<PurgeService>,
+    backup_service: Arc<BackupService>,
+    /// Configured UTC offset for the statistics view (day and hour-of-day buckets).
+    utc_offset_secs: i32,
+    /// Media filter from config (TG_SYNC_MEDIA_TYPES / TG_SYNC_MEDIA_MAX_SIZE_MB), offered as "Custom".
+    media_filter: MediaFilter,
+    /// Chats Full Backup syncs at once (TG_SYNC_PARALLEL_CHATS); 1 = sequential.
+    parallel_chats: usize,
+    /// Dialogs listed earlier in this session; "Refresh dialogs" clears it.
+    dialog_cache: tokio::sync::Mutex<Option<Vec<Chat>>>,
+    /// Effective configuration (secrets masked) and its problems, for "Show configuration".
+    config_report: String,
 }
 
+/// Main menu entries, in order.
+const MENU: [&str; 23] = [
+    "Full Backup",
+    "Manage Blacklist (exclude chats from backup)",
+    "Per-chat settings (media, batch size, watcher)",
+    "Watcher / Daemon",
+    "Watcher status",
+    "Stop watcher",
+    "AI Analysis",
+    "View past analyses",
+    "Statistics",
+    "Search Archive",
+    "Export",
+    "Import Telegram export",
+    "Maintenance (archive audit)",
+    "Maintenance (compact database, orphan media)",
+    "Maintenance (verify media files)",
+    "Purge chat from archive",
+    "Backup database now",
+    "Retry failed media",
+    "Download missing media",
+    "Refresh dialogs",
+    "Add chat by username/link",
+    "Show configuration",
+    QUIT,
+];
+
+const QUIT: &str = "Quit";
+
+/// Days covered by the Statistics view.
+const STATS_DAYS: i64 = 30;
+
+/// Search results shown per page.
+const SEARCH_PAGE: u32 = 20;
+
+/// Action items listed per report after an AI analysis run; the rest are in the report.
+const ACTION_ITEMS_PREVIEW: usize = 3;
+
 impl TuiInputPort {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         tg: Arc<dyn TgGateway>,
         repo: Arc<dyn RepoPort>,
         sync_service: Arc<SyncService>,
         watcher_service: Arc<WatcherService>,
+        watcher_daemon: Arc<DaemonController>,
         analysis_service: Arc<AnalysisService>,
+        export_service: Arc<ExportService>,
+        exporters: Vec<Arc<dyn ExporterPort>>,
+        import_service: Arc<ImportService>,
+        audit_service: Arc<AuditService>,
+        maintenance_service: Arc<MaintenanceService>,
+        purge_service: Arc<PurgeService>,
+        backup_service: Arc<BackupService>,
+        utc_offset_secs: i32,
+        media_filter: MediaFilter,
+        parallel_chats: usize,
+        config_report: String,
     ) -> Self {
         Self {
             tg,
             repo,
             sync_service,
             watcher_service,
+            watcher_daemon,
             analysis_service,
+            export_service,
+            exporters,
+            import_service,
+            audit_service,
+            maintenance_service,
+            purge_service,
+            backup_service,
+            utc_offset_secs,
+            media_filter,
+            parallel_chats,
+            dialog_cache: tokio::sync::Mutex::new(None),
+            config_report,
         }
     }
 }
 
 #[async_trait]
 impl InputPort for TuiInputPort {
+    /// Main menu loop: every action returns here until Quit. Esc in a prompt, or Ctrl+C while
+    /// an action runs (e.g. a backup), goes back to the menu; failed actions are reported and
+    /// the menu is shown again. A started watcher keeps running in the background.
     async fn run(&self) -> Result<(), DomainError> {
-        let options = vec![
-            "Full Backup".to_string(),
-            "Manage Blacklist (exclude chats from backup)".to_string(),
-            "Watcher / Daemon".to_string(),
-            "AI Analysis".to_string(),
-        ];
-        let choice = Select::new("Select mode", options.clone())
-            .prompt()
-            .map_err(|e| DomainError::Auth(e.to_string()))?;
-
-        match choice.as_str() {
-            "Full Backup" => self.run_sync().await,
-            "Manage Blacklist (exclude chats from backup)" => self.run_manage_blacklist().await,
-            "Watcher / Daemon" => self.run_watcher().await,
-            "AI Analysis" => self.run_ai_analysis().await,
-            _ => Ok(()),
+        let options = MENU.to_vec();
+        loop {
+            let choice = match Select::new("Select mode", options.clone()).prompt() {
+                Ok(choice) => choice,
+                // Esc or Ctrl+C at the main menu leaves, like Quit.
+                Err(InquireError::OperationCanceled | InquireError::OperationInterrupted) => {
+                    return Ok(());
+                }
+                Err(e) => return Err(prompt_error(e)),
+            };
+            if choice == QUIT {
+                return Ok(());
+            }
+            let result = tokio::select! {
+                result = self.dispatch(choice) => result,
+                _ = tokio::signal::ctrl_c() => Err(DomainError::Cancelled),
+            };
+            match result {
+                Ok(()) => {}
+                Err(DomainError::Cancelled) => println!("\n↩️  Back to the main menu.\n"),
+                Err(e) => {
+                    warn!(action = choice, error = %e, "menu action failed");
+                    println!("\n❌ {}\n", e);
+                }
+            }
         }
     }
 
     async fn run_sync(&self) -> Result<(), DomainError> {
-        // Full Backup flow: dialogs -> filter by stored blacklist -> sync (no blacklist UI here).
-        let chats = self.tg.get_dialogs().await?;
+        // Full Backup flow: dialogs -> filter by stored blacklist -> all or selected chats -> sync
+        // (no blacklist UI here).
+        let chats = self.dialogs().await?;
         if chats.is_empty() {
             println!("No dialogs found.");
             return Ok(());
         }
 
         let blacklisted_ids = self.repo.get_blacklisted_ids().await?;
-        let allowed: Vec<Chat> = chats
+        let mut allowed: Vec<Chat> = chats
             .iter()
             .filter(|c| !blacklisted_ids.contains(&c.id))
             .cloned()
             .collect();
-        let allowed_ids: Vec<i64> = allowed.iter().map(|c| c.id).collect();
 
-        if allowed_ids.is_empty() {
+        if allowed.is_empty() {
             println!(
                 "No chats to backup (all excluded by blacklist). Use \"Manage Blacklist\" to change."
             );
             return Ok(());
         }
+        const ALL_ALLOWED: &str = "Back up all allowed chats";
+        const SELECT: &str = "Select chats";
+        let scope = Select::new("Which chats?", vec![ALL_ALLOWED, SELECT])
+            .with_help_message("Blacklisted chats are never backed up")
+            .prompt()
+            .map_err(prompt_error)?;
+        // Most recently active chats are synced first.
+        if scope == SELECT {
+            let checked = match self.sync_service.backup_selection().await? {
+                Some(previous) => previous,
+                None => allowed.iter().map(|c| c.id).collect(),
+            };
+            let settings = self.chat_settings().await?;
+            let (_, chosen) = pick_chats(
+                "Select chats to back up",
+                "Checked = backed up now; remembered for next time",
+                &allowed,
+                &checked,
+                &settings,
+            )?;
+            if chosen.is_empty() {
+                println!("No chats selected.");
+                return Ok(());
+            }
+            self.sync_service.set_backup_selection(&chosen).await?;
+            // The blacklist applies on top of the selection.
+            allowed = chats_by_activity(&allowed, ChatKindFilter::All)
+                .into_iter()
+                .filter(|c| chosen.contains(&c.id) && !blacklisted_ids.contains(&c.id))
+                .collect();
+        } else {
+            let filter = prompt_chat_kind("Which chats to back up?", &allowed)?;
+            allowed = chats_by_activity(&allowed, filter);
+        }
+        self.prompt_discussion_groups(&chats, &mut allowed).await?;
 
-        let include_media = Confirm::new("Download media files?")
+        let custom = format!(
+            "Custom (from config: {})",
+            describe_media_filter(&self.media_filter)
+        );
+        let choice = Select::new(
+            "Download media files?",
+            vec![
+                "All".to_string(),
+                "Photos only".to_string(),
+                custom.clone(),
+                "None (text only)".to_string(),
+            ],
+        )
+        .with_help_message("Skipped media can be fetched by a later backup with looser filters")
+        .prompt()
+        .map_err(prompt_error)?;
+        let media = match choice.as_str() {
+            "All" => MediaFilter::all(),
+            "Photos only" => MediaFilter::photos_only(),
+            "None (text only)" => MediaFilter::none(),
+            _ => self.media_filter.clone(),
+        };
+
+        let since = prompt_date("Only messages after date (YYYY-MM-DD, empty = all)")?;
+        let range = since.map(|d| activity::date_range(Some(d), None, self.utc_offset_secs));
+        let topics = self.prompt_forum_topics(&allowed).await?;
+        let takeout = Confirm::new(
+            "Use takeout mode (faster, requires confirmation in another Telegram client)?",
+        )
+        .with_default(false)
+        .with_help_message("Takeout mode syncs one chat at a time")
+        .prompt()
+        .map_err(prompt_error)?;
+
+        // Preview: each chat's newest message, nothing saved. Chats it skips are left out.
+        let ids: Vec<i64> = allowed.iter().map(|c| c.id).collect();
+        let plans = self.sync_service.plan_sync(&ids, &media).await?;
+        print_sync_plan(&plans);
+        let skipped: HashSet<i64> = plans
+            .iter()
+            .filter(|p| p.skip.is_some())
+            .map(|p| p.chat_id)
+            .collect();
+        allowed.retain(|c| !skipped.contains(&c.id));
+        if allowed.is_empty() {
+            println!("Nothing to back up.");
+            return Ok(());
+        }
+        let start = Confirm::new("Start the backup?")
             .with_default(true)
-            .with_help_message("Photos, videos, documents. Press Enter for Yes.")
+            .with_help_message("Estimates ignore the date and topic selection")
             .prompt()
-            .map_err(|e| DomainError::Auth(e.to_string()))?;
+            .map_err(prompt_error)?;
+        if !start {
+            println!("Backup cancelled.");
+            return Ok(());
+        }
 
-        self.sync_service
-            .sync_chats(&allowed_ids, 100, include_media)
-            .await
+        let results = if takeout || self.parallel_chats <= 1 {
+            match self
+                .sync_service
+                .sync_chats(&allowed, 100, &media, range, &topics, takeout)
+                .await
+            {
+                Ok(results) => results,
+                Err(DomainError::TakeoutDelay { seconds }) => {
+                    println!(
+                        "⏳ Confirm the data export in another Telegram client (Service notifications). \
+                         Takeout can start in {}; run the backup again then, or without takeout mode.\n",
+                        format_duration(Duration::from_secs(seconds))
+                    );
+                    return Ok(());
+                }
+                Err(e) => return Err(e),
+            }
+        } else {
+            self.sync_service
+                .sync_chats_concurrent(&allowed, 100, &media, range, &topics, self.parallel_chats)
+                .await
+        };
+        print_sync_summary(&allowed, &results);
+        match self.sync_service.write_report(&allowed, &results).await {
+            Ok(path) => println!("📄 Summary saved to {}\n", path.display()),
+            Err(e) => println!("⚠️  Could not save the summary: {}\n", e),
+        }
+        Ok(())
     }
 
     async fn run_auth(&self) -> Result<(), DomainError> {
-        let _phone = Text::new("Phone number:")
-            .prompt()
-            .map_err(|e| DomainError::Auth(e.to_string()))?;
+        let _phone = Text::new("Phone number:").prompt().map_err(prompt_error)?;
         Ok(())
     }
 }
 
 impl TuiInputPort {
+    /// Run one main menu action.
+    async fn dispatch(&self, choice: &str) -> Result<(), DomainError> {
+        match choice {
+            "Full Backup" => self.run_sync().await,
+            "Manage Blacklist (exclude chats from backup)" => self.run_manage_blacklist().await,
+            "Per-chat settings (media, batch size, watcher)" => {
+                let chats = self.dialogs().await?;
+                self.edit_chat_settings(&chats).await
+            }
+            "Watcher / Daemon" => self.run_watcher().await,
+            "Watcher status" => {
+                println!("\n{}\n", self.watcher_daemon.status().await?);
+                Ok(())
+            }
+            "Stop watcher" => {
+                if !self.watcher_daemon.is_running().await {
+                    println!("The watcher is not running.");
+                    return Ok(());
+                }
+                println!("Stopping the watcher after the chat it is syncing...");
+                self.watcher_daemon.stop().await?;
+                println!("Watcher stopped.");
+                Ok(())
+            }
+            "AI Analysis" => self.run_ai_analysis().await,
+            "View past analyses" => self.run_past_analyses().await,
+            "Statistics" => self.run_statistics().await,
+            "Search Archive" => self.run_search().await,
+            "Export" => self.run_export().await,
+            "Import Telegram export" => self.run_import().await,
+            "Maintenance (archive audit)" => self.run_audit().await,
+            "Maintenance (compact database, orphan media)" => self.run_maintenance().await,
+            "Maintenance (verify media files)" => self.run_verify_media().await,
+            "Purge chat from archive" => self.run_purge().await,
+            "Backup database now" => {
+                println!("\n💾 Writing a database snapshot...\n");
+                let report = self.backup_service.backup_now().await?;
+                println!("{}\n", report);
+                Ok(())
+            }
+            "Retry failed media" => {
+                let count = self.sync_service.retry_failed_media().await?;
+                if count == 0 {
+                    println!("No failed media downloads.");
+                } else {
+                    println!(
+                        "Re-queued {} failed media download(s); they download in the background.",
+                        count
+                    );
+                }
+                Ok(())
+            }
+            "Download missing media" => self.run_backfill_media().await,
+            "Refresh dialogs" => {
+                *self.dialog_cache.lock().await = None;
+                let chats = self.dialogs().await?;
+                println!("{} dialogs listed.", chats.len());
+                Ok(())
+            }
+            "Add chat by username/link" => self.run_add_chat().await,
+            "Show configuration" => {
+                println!("\n{}", self.config_report);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Per-topic selection for forum chats, offered only when the backup includes one.
+    /// A forum keeps every topic unless some are unchecked.
+    async fn prompt_forum_topics(&self, chats: &[Chat]) -> Result<TopicFilter, DomainError> {
+        let mut filter = TopicFilter::all();
+        let forums: Vec<&Chat> = chats.iter().filter(|c| c.is_forum).collect();
+        if forums.is_empty() {
+            return Ok(filter);
+        }
+        let pick = Confirm::new(&format!(
+            "{} forum chat(s) included. Select topics per forum?",
+            forums.len()
+        ))
+        .with_default(false)
+        .prompt()
+        .map_err(prompt_error)?;
+        if !pick {
+            return Ok(filter);
+        }
+
+        for chat in forums {
+            let topics = match self.tg.get_forum_topics(chat.id).await {
+                Ok(topics) => topics,
+                Err(e) => {
+                    warn!(chat_id = chat.id, error = %e, "failed to list forum topics");
+                    println!("⚠️  Could not list topics of {}; syncing all.", chat.title);
+                    continue;
+                }
+            };
+            // General is listed first whether or not the server returns it as a topic.
+            let mut ids = vec![GENERAL_TOPIC_ID];
+            let mut options = vec!["General".to_string()];
+            for topic in topics.iter().filter(|t| t.id != GENERAL_TOPIC_ID) {
+                ids.push(topic.id);
+                options.push(format!("{} ({})", topic.title, topic.id));
+            }
+            let default: Vec<usize> = (0..options.len()).collect();
+            let selected = MultiSelect::new(
+                &format!("Topics to sync in {}", chat.title),
+                options.clone(),
+            )
+            .with_default(&default)
+            .with_help_message("Unchecked topics are skipped and fetched by a later full backup")
+            .prompt()
+            .map_err(prompt_error)?;
+            if selected.len() < options.len() {
+                let chosen = ids
+                    .iter()
+                    .zip(&options)
+                    .filter(|(_, option)| selected.contains(option))
+                    .map(|(id, _)| *id)
+                    .collect();
+                filter.select(chat.id, chosen);
+            }
+        }
+        Ok(filter)
+    }
+
+    /// Warn about backed-up channels whose discussion group is excluded (their comments would
+    /// be missing) and offer to include the group in this backup. A channel's group is found
+    /// through the comment thread of its latest post, so channels whose latest post has no
+    /// comments are not checked.
+    async fn prompt_discussion_groups(
+        &self,
+        chats: &[Chat],
+        allowed: &mut Vec<Chat>,
+    ) -> Result<(), DomainError> {
+        let channels: Vec<(i64, String, i32)> = allowed
+            .iter()
+            .filter(|c| c.kind == ChatType::Channel)
+            .filter_map(|c| Some((c.id, c.title.clone(), c.approx_message_count?)))
+            .collect();
+        for (channel_id, title, last_post) in channels {
+            let link = match self.tg.get_discussion_message(channel_id, last_post).await {
+                Ok(Some(link)) => link,
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!(chat_id = channel_id, error = %e, "failed to look up discussion group");
+                    continue;
+                }
+            };
+            if allowed.iter().any(|c| c.id == link.chat_id) {
+                continue;
+            }
+            let Some(group) = chats.iter().find(|c| c.id == link.chat_id) else {
+                continue;
+            };
+            println!(
+                "⚠️  Comments on {} are in {}, which is excluded from the backup.",
+                title, group.title
+            );
+            let include = Confirm::new(&format!("Include {} in this backup?", group.title))
+                .with_default(true)
+                .with_help_message("Without it, exports of the channel have no comments")
+                .prompt()
+                .map_err(prompt_error)?;
+            if include {
+                allowed.push(group.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// Current dialogs, listed once per session (until "Refresh dialogs"). Every listing
+    /// refreshes the stored chat titles, so renames are kept and chats stay labelled after
+    /// they leave Telegram.
+    async fn dialogs(&self) -> Result<Vec<Chat>, DomainError> {
+        let mut cache = self.dialog_cache.lock().await;
+        if let Some(chats) = cache.as_ref() {
+            return Ok(chats.clone());
+        }
+        let chats = self.tg.get_dialogs().await?;
+        if let Err(e) = self.repo.upsert_chats(&chats).await {
+            warn!(error = %e, "failed to store chat titles");
+        }
+        *cache = Some(chats.clone());
+        Ok(chats)
+    }
+
+    /// Download missing media flow: chats -> type / size filter -> queue the stored media
+    /// never downloaded (e.g. after a text-only backup). No history is fetched again; the
+    /// media worker downloads in the background.
+    async fn run_backfill_media(&self) -> Result<(), DomainError> {
+        let chats = self.archive_chats().await?;
+        let settings = self.chat_settings().await?;
+        let (_, chosen) = pick_chats(
+            "Select chats to download missing media for",
+            "Only media of already archived messages is fetched",
+            &chats,
+            &HashSet::new(),
+            &settings,
+        )?;
+        if chosen.is_empty() {
+            println!("No chats selected.");
+            return Ok(());
+        }
+        let media = self.prompt_backfill_filter()?;
+
+        let mut total = MediaBackfill::default();
+        for chat in chats_by_activity(&chats, ChatKindFilter::All)
+            .into_iter()
+            .filter(|c| chosen.contains(&c.id))
+        {
+            let report = self.sync_service.backfill_media(chat.id, &media).await?;
+            println!("{}: {}", chat.title, report);
+            total.queued += report.queued;
+            total.present += report.present;
+            total.pending += report.pending;
+            total.filtered += report.filtered;
+        }
+        if chosen.len() > 1 {
+            println!("\nTotal: {}", total);
+        }
+        if total.queued > 0 {
+            println!(
+                "\n📥 Downloads run in the background at the media rate limit and resume after a restart; \
+                 run this again later to check what is still missing.\n"
+            );
+        }
+        Ok(())
+    }
+
+    /// Media types and size limit for "Download missing media".
+    fn prompt_backfill_filter(&self) -> Result<MediaFilter, DomainError> {
+        const ALL: &str = "All types";
+        const PHOTOS: &str = "Photos only";
+        const SOME: &str = "Choose types";
+        let custom = format!(
+            "Custom (from config: {})",
+            describe_media_filter(&self.media_filter)
+        );
+        let choice = Select::new(
+            "Which media?",
+            vec![
+                ALL.to_string(),
+                PHOTOS.to_string(),
+                SOME.to_string(),
+                custom,
+            ],
+        )
+        .prompt()
+        .map_err(prompt_error)?;
+        let mut filter = match choice.as_str() {
+            ALL => MediaFilter::all(),
+            PHOTOS => MediaFilter::photos_only(),
+            SOME => {
+                let names: Vec<&str> = MEDIA_TYPES.iter().map(|t| t.as_str()).collect();
+                let picked = MultiSelect::new("Media types to download", names)
+                    .prompt()
+                    .map_err(prompt_error)?;
+                MediaFilter {
+                    types: Some(
+                        MEDIA_TYPES
+                            .into_iter()
+                            .filter(|t| picked.contains(&t.as_str()))
+                            .collect(),
+                    ),
+                    max_size_bytes: None,
+                }
+            }
+            _ => return Ok(self.media_filter.clone()),
+        };
+        let max_mib = CustomType::<u64>::new("Skip files larger than (MiB, 0 = no limit)")
+            .with_default(0)
+            .with_parser(&|s: &str| s.trim().parse::<u64>().map_err(|_| ()))
+            .with_error_message("Enter a whole number of MiB")
+            .prompt()
+            .map_err(prompt_error)?;
+        filter.max_size_bytes = (max_mib > 0).then(|| (max_mib * 1024 * 1024) as i64);
+        Ok(filter)
+    }
+
+    /// Add chat flow: resolve a chat by @username, t.me link or id (a public channel not
+    /// joined, or one lost in a long dialog list) -> store it -> sync it now and/or watch it.
+    /// It is listed with the dialogs for the rest of the session.
+    async fn run_add_chat(&self) -> Result<(), DomainError> {
+        let reference = Text::new("Chat username or link:")
+            .with_help_message("@username, t.me/name, a t.me post link or a chat id")
+            .prompt()
+            .map_err(prompt_error)?;
+        let chat = self.sync_service.resolve_chat(&reference).await?;
+        println!("\n✅ Found {} ({})\n", chat.title, chat.id);
+        if let Some(chats) = self.dialog_cache.lock().await.as_mut() {
+            if !chats.iter().any(|c| c.id == chat.id) {
+                chats.push(chat.clone());
+            }
+        }
+
+        const SYNC: &str = "Sync it now";
+        const WATCH: &str = "Add it to the watcher targets";
+        const BOTH: &str = "Sync it now and watch it";
+        const NOTHING: &str = "Nothing for now";
+        let action = Select::new("What next?", vec![SYNC, WATCH, BOTH, NOTHING])
+            .prompt()
+            .map_err(prompt_error)?;
+        if action == WATCH || action == BOTH {
+            let mut targets = self.repo.get_target_ids().await?;
+            targets.insert(chat.id);
+            self.repo.update_targets(targets).await?;
+            println!("👁  {} is now a watcher target.", chat.title);
+        }
+        if action == SYNC || action == BOTH {
+            if self.repo.get_blacklisted_ids().await?.contains(&chat.id) {
+                println!(
+                    "{} is blacklisted; remove it from the blacklist to back it up.",
+                    chat.title
+                );
+                return Ok(());
+            }
+            let chats = [chat];
+            let topics = self.prompt_forum_topics(&chats).await?;
+            let results = self
+                .sync_service
+                .sync_chats(&chats, 100, &self.media_filter, None, &topics, false)
+                .await?;
+            print_sync_summary(&chats, &results);
+        }
+        Ok(())
+    }
+
+    /// Dialogs followed by archived chats that are no longer among them (left, deleted),
+    /// under their stored titles.
+    async fn archive_chats(&self) -> Result<Vec<Chat>, DomainError> {
+        let mut chats = self.dialogs().await?;
+        let known = self.repo.get_known_chats().await?;
+        let listed: HashSet<i64> = chats.iter().map(|c| c.id).collect();
+        chats.extend(known.into_iter().filter(|c| !listed.contains(&c.id)));
+        Ok(chats)
+    }
+
     /// Manage Blacklist flow: dialogs -> threshold (optional) -> MultiSelect -> save blacklist.
     async fn run_manage_blacklist(&self) -> Result<(), DomainError> {
-        let chats = self.tg.get_dialogs().await?;
+        let chats = self.dialogs().await?;
         if chats.is_empty() {
             println!("No dialogs found.");
             return Ok(());
@@ -173,7 +1212,7 @@ impl TuiInputPort {
         .with_default(0)
         .with_parser(&|s: &str| s.trim().parse::<i32>().map_err(|_| ()))
         .prompt()
-        .map_err(|e| DomainError::Auth(e.to_string()))?;
+        .map_err(prompt_error)?;
 
         let large_chat_ids: HashSet<i64> = if threshold > 0 {
             chats
@@ -188,39 +1227,19 @@ impl TuiInputPort {
         let initial_blacklist: HashSet<i64> =
             blacklisted_ids.union(&large_chat_ids).copied().collect();
 
-        let options: Vec<String> = chats
-            .iter()
-            .map(|c| format!("{} {} ({})", chat_type_indicator(c.kind), c.title, c.id))
-            .collect();
-        let default: Vec<usize> = chats
-            .iter()
-            .enumerate()
-            .filter(|(_, c)| initial_blacklist.contains(&c.id))
-            .map(|(i, _)| i)
-            .collect();
-
-        let selected = MultiSelect::new(
+        let settings = self.chat_settings().await?;
+        let (listed, selected) = pick_chats(
             "Select chats to EXCLUDE from backup (Blacklist)",
-            options.clone(),
-        )
-        .with_default(&default)
-        .with_help_message(
             "Checked = excluded from backup. Union of saved blacklist + auto large chats.",
-        )
-        .prompt()
-        .map_err(|e| DomainError::Auth(e.to_string()))?;
-
-        let new_blacklist: HashSet<i64> = chats
-            .iter()
-            .filter(|c| {
-                selected.contains(&format!(
-                    "{} {} ({})",
-                    chat_type_indicator(c.kind),
-                    c.title,
-                    c.id
-                ))
-            })
-            .map(|c| c.id)
+            &chats,
+            &initial_blacklist,
+            &settings,
+        )?;
+        // Chats that were not listed keep their saved (or auto) choice.
+        let new_blacklist: HashSet<i64> = initial_blacklist
+            .difference(&listed)
+            .copied()
+            .chain(selected)
             .collect();
 
         self.repo.update_blacklist(new_blacklist.clone()).await?;
@@ -231,58 +1250,327 @@ impl TuiInputPort {
         Ok(())
     }
 
-    /// Watcher flow: dialogs -> target list (whitelist) MultiSelect -> update_targets -> run watcher loop.
+    /// Watcher flow: dialogs -> target list (whitelist) MultiSelect -> update_targets ->
+    /// optional keyword / alert destination management -> start the watcher in the background.
+    /// While it runs, the targets, keywords and settings can still be changed here; they apply
+    /// from its next cycle.
     async fn run_watcher(&self) -> Result<(), DomainError> {
-        let chats = self.tg.get_dialogs().await?;
+        let chats = self.dialogs().await?;
         if chats.is_empty() {
             println!("No dialogs found.");
             return Ok(());
         }
 
         let target_ids = self.repo.get_target_ids().await?;
-        let options: Vec<String> = chats
-            .iter()
-            .map(|c| format!("{} {} ({})", chat_type_indicator(c.kind), c.title, c.id))
+        let settings = self.chat_settings().await?;
+        let (listed, selected) = pick_chats(
+            "Select chats to WATCH (Target List)",
+            "Chats marked [sync:off] in their per-chat settings are skipped",
+            &chats,
+            &target_ids,
+            &settings,
+        )?;
+        // Chats that were not listed stay targets if they were.
+        let new_targets: HashSet<i64> = target_ids
+            .difference(&listed)
+            .copied()
+            .chain(selected)
             .collect();
-        let default: Vec<usize> = chats
+
+        self.repo.update_targets(new_targets.clone()).await?;
+
+        let targets: Vec<&Chat> = chats
             .iter()
-            .enumerate()
-            .filter(|(_, c)| target_ids.contains(&c.id))
-            .map(|(i, _)| i)
+            .filter(|c| new_targets.contains(&c.id))
             .collect();
-
-        let selected = MultiSelect::new("Select chats to WATCH (Target List)", options.clone())
-            .with_default(&default)
+        loop {
+            let action = Select::new(
+                "Watcher",
+                vec![
+                    "Start watcher",
+                    "Manage watch keywords",
+                    "Choose alert destination",
+                    "Per-chat settings",
+                ],
+            )
             .prompt()
-            .map_err(|e| DomainError::Auth(e.to_string()))?;
+            .map_err(prompt_error)?;
+            match action {
+                "Start watcher" if self.watcher_daemon.is_running().await => {
+                    println!("The watcher is already running; changes apply from its next cycle.");
+                    return Ok(());
+                }
+                "Start watcher" => break,
+                "Manage watch keywords" => self.manage_watch_rules(&chats, &targets).await?,
+                "Per-chat settings" => self.edit_chat_settings(&chats).await?,
+                _ => self.choose_alert_chat(&chats).await?,
+            }
+        }
+
+        let destination = match self.watcher_service.alert_chat().await? {
+            Some(id) => chats
+                .iter()
+                .find(|c| c.id == id)
+                .map(|c| format!("'{}'", c.title))
+                .unwrap_or_else(|| format!("chat {}", id)),
+            None => "Saved Messages".to_string(),
+        };
+        self.watcher_daemon.start().await?;
+        println!(
+            "Watcher started in the background. Alerts will go to {}. \
+             See \"Watcher status\"; stop it with \"Stop watcher\".",
+            destination
+        );
+        Ok(())
+    }
+
+    /// Stored per-chat settings by chat id.
+    async fn chat_settings(&self) -> Result<HashMap<i64, ChatSettings>, DomainError> {
+        Ok(self
+            .repo
+            .list_chat_settings()
+            .await?
+            .into_iter()
+            .map(|s| (s.chat_id, s))
+            .collect())
+    }
+
+    /// Per-chat settings editor: pick a chat, then its media, batch size and watcher switch.
+    /// Repeats until "Done".
+    async fn edit_chat_settings(&self, chats: &[Chat]) -> Result<(), DomainError> {
+        let filter = prompt_chat_kind("Which chats to list?", chats)?;
+        let listed = chats_by_activity(chats, filter);
+        loop {
+            let settings = self.chat_settings().await?;
+            let mut options: Vec<ChatOption> = listed
+                .iter()
+                .map(|c| ChatOption::new(c, &settings))
+                .collect();
+            options.push(ChatOption::other("Done"));
+            let picked = Select::new("Per-chat settings: choose a chat", options)
+                .with_scorer(&chat_scorer)
+                .with_help_message("Overrides apply to every sync of the chat; type to search")
+                .raw_prompt()
+                .map_err(prompt_error)?;
+            let Some(chat) = listed.get(picked.index) else {
+                return Ok(());
+            };
+            let current = settings
+                .get(&chat.id)
+                .cloned()
+                .unwrap_or_else(|| ChatSettings::new(chat.id));
+            let updated = prompt_chat_settings(&chat.title, current)?;
+            self.repo.set_chat_settings(&updated).await?;
+            match updated.label() {
+                label if label.is_empty() => println!("{}: back to defaults.", chat.title),
+                label => println!("{}: {}", chat.title, label),
+            }
+        }
+    }
+
+    /// Pick the chat that receives keyword alerts (Saved Messages, a channel or a group); stored
+    /// in settings so it survives restarts.
+    async fn choose_alert_chat(&self, chats: &[Chat]) -> Result<(), DomainError> {
+        let current = self.watcher_service.alert_chat().await?;
+        let mut options = vec!["Saved Messages".to_string()];
+        options.extend(
+            chats
+                .iter()
+                .map(|c| format!("{} {} ({})", chat_type_indicator(c.kind), c.title, c.id)),
+        );
+        let cursor = current
+            .and_then(|id| chats.iter().position(|c| c.id == id))
+            .map(|i| i + 1)
+            .unwrap_or(0);
 
-        let new_targets: HashSet<i64> = chats
+        let selected = Select::new("Send keyword alerts to", options.clone())
+            .with_starting_cursor(cursor)
+            .prompt()
+            .map_err(prompt_error)?;
+        let chat_id = options
             .iter()
-            .filter(|c| {
-                selected.contains(&format!(
-                    "{} {} ({})",
-                    chat_type_indicator(c.kind),
-                    c.title,
-                    c.id
-                ))
-            })
-            .map(|c| c.id)
-            .collect();
+            .skip(1)
+            .position(|o| *o == selected)
+            .map(|i| chats[i].id);
 
-        self.repo.update_targets(new_targets.clone()).await?;
+        self.watcher_service.set_alert_chat(chat_id).await?;
+        println!("Alert destination saved.");
+        Ok(())
+    }
+
+    /// Keyword submenu: list rules, add (global or per target chat, substring or regex), turn
+    /// mention and reply alerts on or off, remove.
+    async fn manage_watch_rules(
+        &self,
+        chats: &[Chat],
+        targets: &[&Chat],
+    ) -> Result<(), DomainError> {
+        let title_of = |chat_id: i64| -> String {
+            if chat_id == 0 {
+                return "all chats".to_string();
+            }
+            chats
+                .iter()
+                .find(|c| c.id == chat_id)
+                .map(|c| c.title.clone())
+                .unwrap_or_else(|| chat_id.to_string())
+        };
+        let label = |r: &WatchRule| -> String {
+            let rule = match r.kind {
+                WatchRuleKind::Keyword => {
+                    format!("{}{}", if r.is_regex { "/" } else { "" }, r.pattern)
+                }
+                WatchRuleKind::Mention => "(mentions of me)".to_string(),
+                WatchRuleKind::Reply => "(replies to my messages)".to_string(),
+            };
+            format!(
+                "{} [{}]{}",
+                rule,
+                title_of(r.chat_id),
+                if r.enabled { "" } else { " (disabled)" }
+            )
+        };
+        let pick_scope = || -> Result<i64, DomainError> {
+            let mut scopes = vec!["All chats (global)".to_string()];
+            scopes.extend(targets.iter().map(|c| format!("{} ({})", c.title, c.id)));
+            let scope = Select::new("Applies to", scopes.clone())
+                .prompt()
+                .map_err(prompt_error)?;
+            Ok(scopes
+                .iter()
+                .skip(1)
+                .position(|s| *s == scope)
+                .map(|i| targets[i].id)
+                .unwrap_or(0))
+        };
+
+        loop {
+            let rules = self.watcher_service.list_rules().await?;
+            println!("Watch keywords ({}):", rules.len());
+            for rule in &rules {
+                println!("  {}", label(rule));
+            }
+
+            let action = Select::new(
+                "Keywords",
+                vec![
+                    "Add keyword",
+                    "Alert on mentions / replies",
+                    "Remove keywords",
+                    "Back",
+                ],
+            )
+            .prompt()
+            .map_err(prompt_error)?;
+            match action {
+                "Add keyword" => {
+                    let chat_id = pick_scope()?;
 
-        println!("Watcher started. Notifications will go to Saved Messages. Press Ctrl+C to stop.");
-        self.watcher_service.run_loop().await
+                    let is_regex = Confirm::new("Is this a regular expression?")
+                        .with_default(false)
+                        .prompt()
+                        .map_err(prompt_error)?;
+                    // Invalid patterns are rejected here, before they reach the watcher loop.
+                    let pattern = Text::new("Keyword")
+                        .with_help_message("Matched case-insensitively")
+                        .with_validator(move |s: &str| {
+                            Ok(match validate_watch_pattern(s.trim(), is_regex) {
+                                Ok(()) => Validation::Valid,
+                                Err(e) => Validation::Invalid(e.to_string().into()),
+                            })
+                        })
+                        .prompt()
+                        .map_err(prompt_error)?;
+
+                    self.watcher_service
+                        .add_rule(chat_id, &pattern, is_regex)
+                        .await?;
+                    println!("Added '{}' for {}.", pattern.trim(), title_of(chat_id));
+                }
+                "Alert on mentions / replies" => {
+                    const MENTIONS: &str = "Mentions of me";
+                    const REPLIES: &str = "Replies to my messages";
+                    let kind = match Select::new("Alert on", vec![MENTIONS, REPLIES])
+                        .prompt()
+                        .map_err(prompt_error)?
+                    {
+                        MENTIONS => WatchRuleKind::Mention,
+                        _ => WatchRuleKind::Reply,
+                    };
+                    let chat_id = pick_scope()?;
+                    let enabled = Confirm::new("Send alerts for these?")
+                        .with_default(true)
+                        .prompt()
+                        .map_err(prompt_error)?;
+                    self.watcher_service
+                        .set_trigger(chat_id, kind, enabled)
+                        .await?;
+                    println!(
+                        "{} alerts {} for {}.",
+                        if kind == WatchRuleKind::Mention {
+                            "Mention"
+                        } else {
+                            "Reply"
+                        },
+                        if enabled { "on" } else { "off" },
+                        title_of(chat_id)
+                    );
+                }
+                "Remove keywords" => {
+                    if rules.is_empty() {
+                        println!("No keywords to remove.");
+                        continue;
+                    }
+                    let options: Vec<String> = rules.iter().map(&label).collect();
+                    let selected = MultiSelect::new("Select keywords to remove", options.clone())
+                        .prompt()
+                        .map_err(prompt_error)?;
+                    let mut removed = 0;
+                    for (rule, option) in rules.iter().zip(&options) {
+                        if selected.contains(option)
+                            && self.watcher_service.remove_rule(rule.id).await?
+                        {
+                            removed += 1;
+                        }
+                    }
+                    println!("Removed {} keyword(s).", removed);
+                }
+                _ => return Ok(()),
+            }
+        }
     }
 
-    /// AI Analysis flow: select chats -> analyze unprocessed weeks -> generate reports.
+    /// AI Analysis flow: select archived chats -> day/week/month grouping -> show pending
+    /// periods -> latest period or all -> generate reports and list the action items found.
     async fn run_ai_analysis(&self) -> Result<(), DomainError> {
-        let chats = self.tg.get_dialogs().await?;
+        // Only chats with stored messages can be analyzed; no point offering the rest.
+        let stats = self.repo.get_global_stats().await?;
+        let archived: HashSet<i64> = stats
+            .chats
+            .iter()
+            .filter(|c| c.messages > 0)
+            .map(|c| c.chat_id)
+            .collect();
+        let chats: Vec<Chat> = self
+            .archive_chats()
+            .await?
+            .into_iter()
+            .filter(|c| archived.contains(&c.id))
+            .collect();
         if chats.is_empty() {
-            println!("No dialogs found.");
+            println!(
+                "No archived messages to analyze yet. Run a Full Backup (or Sync) first, then come back."
+            );
             return Ok(());
         }
 
+        const PER_CHAT: &str = "Report per chat";
+        const COMBINED: &str = "Combined digest (one weekly report for several chats)";
+        let combined = Select::new("AI Analysis", vec![PER_CHAT, COMBINED])
+            .prompt()
+            .map_err(prompt_error)?
+            == COMBINED;
+
         // Build options list with chat indicators
         let options: Vec<String> = chats
             .iter()
@@ -292,15 +1580,14 @@ impl TuiInputPort {
         let selected = MultiSelect::new("Select chats to analyze", options.clone())
             .with_help_message("Space to select, Enter to confirm")
             .prompt()
-            .map_err(|e| DomainError::Auth(e.to_string()))?;
+            .map_err(prompt_error)?;
 
         if selected.is_empty() {
             println!("No chats selected.");
             return Ok(());
         }
 
-        // Extract selected chat IDs
-        let selected_chats: Vec<(i64, String)> = chats
+        let picked: Vec<&Chat> = chats
             .iter()
             .filter(|c| {
                 selected.contains(&format!(
@@ -310,8 +1597,79 @@ impl TuiInputPort {
                     c.id
                 ))
             })
-            .map(|c| (c.id, c.title.clone()))
             .collect();
+        if combined {
+            return self.run_combined_digest(&picked).await;
+        }
+
+        // Grouping is stored per chat; preselect it when all picked chats agree.
+        let mut current = HashSet::new();
+        for c in &picked {
+            current.insert(self.analysis_service.granularity(c.id).await?);
+        }
+        let preselected = if current.len() == 1 {
+            current.into_iter().next().unwrap_or_default()
+        } else {
+            Granularity::default()
+        };
+        let cursor = Granularity::ALL
+            .iter()
+            .position(|g| *g == preselected)
+            .unwrap_or(0);
+        let labels: Vec<&str> = Granularity::ALL.iter().map(|g| g.adjective()).collect();
+        let choice = Select::new("Group messages by", labels.clone())
+            .with_starting_cursor(cursor)
+            .prompt()
+            .map_err(prompt_error)?;
+        let granularity = labels
+            .iter()
+            .position(|l| *l == choice)
+            .map(|i| Granularity::ALL[i])
+            .unwrap_or_default();
+        let period = granularity.as_str();
+
+        // Extract selected chats with their pending periods
+        let mut selected_chats: Vec<(i64, String)> = Vec::new();
+        let mut pending_total = 0usize;
+        for c in picked {
+            self.analysis_service
+                .set_granularity(c.id, granularity)
+                .await?;
+            let pending = self.analysis_service.pending_weeks(c.id).await?;
+            match (pending.first(), pending.last()) {
+                (Some(first), Some(last)) => println!(
+                    "🗓️  {} — {} unanalyzed {}(s) ({} .. {})",
+                    c.title,
+                    pending.len(),
+                    period,
+                    first,
+                    last
+                ),
+                _ => println!("⏭️  {} — all {}s already analyzed", c.title, period),
+            }
+            if !pending.is_empty() {
+                pending_total += pending.len();
+                selected_chats.push((c.id, c.title.clone()));
+            }
+        }
+
+        if selected_chats.is_empty() {
+            println!("\nNothing new to analyze.\n");
+            return Ok(());
+        }
+
+        let latest_only = format!("Latest {} only", period);
+        let scope = Select::new(
+            &format!("Latest {} only or all?", period),
+            vec![
+                latest_only.clone(),
+                format!("All unanalyzed {}s ({} total)", period, pending_total),
+            ],
+        )
+        .prompt()
+        .map_err(prompt_error)?;
+        let single_week = scope == latest_only;
+        self.confirm_digest_to_telegram()?;
 
         println!(
             "\n🤖 Starting AI Analysis for {} chat(s)...\n",
@@ -319,6 +1677,8 @@ impl TuiInputPort {
         );
 
         let mut total_reports = 0usize;
+        let mut total_actions = 0usize;
+        let mut total_usage = UsageTotals::default();
         let mut failed_chats = Vec::new();
 
         for (chat_id, chat_title) in &selected_chats {
@@ -333,15 +1693,39 @@ impl TuiInputPort {
             spinner.set_message(format!("Analyzing {} (requesting LLM)...", chat_title));
             spinner.enable_steady_tick(Duration::from_millis(100));
 
-            match self.analysis_service.analyze_chat(*chat_id, false).await {
+            match self
+                .analysis_service
+                .analyze_chat(*chat_id, single_week)
+                .await
+            {
                 Ok(reports) => {
                     spinner.finish_and_clear();
                     if reports.is_empty() {
-                        println!("⏭️  {} — No new weeks to analyze", chat_title);
+                        println!("⏭️  {} — No new {}s to analyze", chat_title, period);
                     } else {
                         println!("✅ {} — Generated {} report(s):", chat_title, reports.len());
-                        for path in &reports {
-                            println!("   📄 {}", path.display());
+                        for report in &reports {
+                            let actions = &report.result.action_items;
+                            println!(
+                                "   📄 {} ({}, {} action item(s))",
+                                report.files_display(),
+                                report.result.week_group,
+                                actions.len()
+                            );
+                            for item in actions.iter().take(ACTION_ITEMS_PREVIEW) {
+                                println!("      • {}", item.description);
+                            }
+                            if actions.len() > ACTION_ITEMS_PREVIEW {
+                                println!(
+                                    "      … and {} more",
+                                    actions.len() - ACTION_ITEMS_PREVIEW
+                                );
+                            }
+                            total_actions += actions.len();
+                            total_usage.merge(&report.usage);
+                        }
+                        for report in &reports {
+                            self.review_action_items(report).await?;
                         }
                         total_reports += reports.len();
                     }
@@ -356,7 +1740,13 @@ impl TuiInputPort {
 
         println!();
         if total_reports > 0 {
-            println!("📊 Total reports generated: {}", total_reports);
+            println!(
+                "📊 Total reports generated: {} ({} action item(s))",
+                total_reports, total_actions
+            );
+        }
+        if total_usage.calls > 0 {
+            println!("💰 AI usage: {}", total_usage.describe());
         }
         if !failed_chats.is_empty() {
             println!("⚠️  Failed chats: {}", failed_chats.join(", "));
@@ -365,4 +1755,889 @@ impl TuiInputPort {
 
         Ok(())
     }
+
+    /// Browse the stored analyses of a chat: show one, then print its Markdown report path,
+    /// post it to Telegram again or analyze its period again.
+    async fn run_past_analyses(&self) -> Result<(), DomainError> {
+        let chats = self.archive_chats().await?;
+        if chats.is_empty() {
+            println!("The archive has no chats yet.");
+            return Ok(());
+        }
+        let options: Vec<String> = chats
+            .iter()
+            .map(|c| format!("{} {} ({})", chat_type_indicator(c.kind), c.title, c.id))
+            .collect();
+        let selected = Select::new("Chat", options.clone())
+            .prompt()
+            .map_err(prompt_error)?;
+        let Some(chat) = chats
+            .iter()
+            .zip(&options)
+            .find(|(_, o)| **o == selected)
+            .map(|(c, _)| c)
+        else {
+            return Ok(());
+        };
+
+        let periods = self.analysis_service.analyzed_periods(chat.id).await?;
+        if periods.is_empty() {
+            println!("No analyses of {} yet. Run AI Analysis first.", chat.title);
+            return Ok(());
+        }
+        let labels: Vec<String> = periods
+            .iter()
+            .map(|p| {
+                format!(
+                    "{} {} · analyzed {} · {} action item(s)",
+                    p.granularity.adjective(),
+                    p.week_group,
+                    activity::time_label(p.analyzed_at, self.utc_offset_secs),
+                    p.action_items
+                )
+            })
+            .collect();
+        let choice = Select::new("Analysis", labels.clone())
+            .prompt()
+            .map_err(prompt_error)?;
+        let Some(period) = labels
+            .iter()
+            .position(|l| *l == choice)
+            .map(|i| &periods[i])
+        else {
+            return Ok(());
+        };
+        let Some(result) = self
+            .analysis_service
+            .stored_analysis(chat.id, period)
+            .await?
+        else {
+            println!("That analysis is no longer stored.");
+            return Ok(());
+        };
+        print_analysis(&result);
+
+        const REPORT: &str = "Show the Markdown report path";
+        const RESEND: &str = "Post to Telegram again";
+        const REANALYZE: &str = "Analyze this period again";
+        const BACK: &str = "Back";
+        loop {
+            let action = Select::new("Next", vec![REPORT, RESEND, REANALYZE, BACK])
+                .prompt()
+                .map_err(prompt_error)?;
+            match action {
+                REPORT => {
+                    let path = self.analysis_service.report_file(&result).await?;
+                    println!("📄 {}", path.display());
+                }
+                RESEND => match self.analysis_service.resend_to_telegram(&result).await {
+                    Ok(chat_id) => println!("📨 Posted to chat {}.", chat_id),
+                    Err(e) => println!("❌ Posting failed: {}", e),
+                },
+                REANALYZE => {
+                    let confirmed = Confirm::new(&format!(
+                        "Replace the stored analysis of {} with a new one? This calls the AI again.",
+                        result.week_group
+                    ))
+                    .with_default(false)
+                    .prompt()
+                    .map_err(prompt_error)?;
+                    if !confirmed {
+                        continue;
+                    }
+                    println!("\n🤖 Analyzing {} again...", result.week_group);
+                    match self
+                        .analysis_service
+                        .reanalyze_period(chat.id, period)
+                        .await?
+                    {
+                        Some(report) => {
+                            print_analysis(&report.result);
+                            println!("📄 {}", report.files_display());
+                            if report.usage.calls > 0 {
+                                println!("💰 AI usage: {}", report.usage.describe());
+                            }
+                            self.review_action_items(&report).await?;
+                        }
+                        None => println!(
+                            "No messages left in {}; the stored analysis was removed.",
+                            result.week_group
+                        ),
+                    }
+                    return Ok(());
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Let the user pick (and optionally reword) the action items of a report that go to the
+    /// task tracker; all are preselected. Esc sends none. No-op without a tracker.
+    async fn review_action_items(&self, report: &AnalysisReport) -> Result<(), DomainError> {
+        let items = &report.result.action_items;
+        if items.is_empty() || !self.analysis_service.has_task_tracker() {
+            return Ok(());
+        }
+        let descriptions: Vec<String> = items.iter().map(|i| i.description.clone()).collect();
+        let picked = MultiSelect::new(
+            &format!("Send to task tracker ({})", report.result.week_group),
+            descriptions,
+        )
+        .with_all_selected_by_default()
+        .with_help_message("Space to toggle, Enter to confirm, Esc to send none")
+        .raw_prompt_skippable()
+        .map_err(prompt_error)?;
+        let Some(picked) = picked else {
+            println!("   ⏭️  No action items sent to the tracker");
+            return Ok(());
+        };
+        let selected: Vec<usize> = picked.iter().map(|o| o.index).collect();
+
+        let mut result = report.result.clone();
+        let edit = !selected.is_empty()
+            && Confirm::new("Edit descriptions before sending?")
+                .with_default(false)
+                .prompt()
+                .map_err(prompt_error)?;
+        if edit {
+            for &i in &selected {
+                let item = &mut result.action_items[i];
+                let edited = Text::new("Description")
+                    .with_initial_value(&item.description)
+                    .prompt()
+                    .map_err(prompt_error)?;
+                if !edited.trim().is_empty() {
+                    item.description = edited.trim().to_string();
+                }
+            }
+        }
+        let push = self
+            .analysis_service
+            .push_action_items(&result, &selected)
+            .await;
+        println!(
+            "   📌 {} of {} action item(s) sent to the tracker ({} already there)",
+            push.created,
+            items.len(),
+            push.skipped
+        );
+        for (title, error) in &push.failed {
+            println!("   ❌ {}: {}", title, error);
+        }
+        if !push.failed.is_empty() {
+            println!("   Failed items are retried on the next start.");
+        }
+        Ok(())
+    }
+
+    /// Ask whether this run's digests are posted to Telegram; defaults to the current setting
+    /// (TG_SYNC_DIGEST_TO_TELEGRAM).
+    fn confirm_digest_to_telegram(&self) -> Result<(), DomainError> {
+        let post = Confirm::new("Also post the digest to Telegram?")
+            .with_default(self.analysis_service.digest_to_telegram())
+            .with_help_message(
+                "Plain text to Saved Messages, or the watcher's alert chat if one is set",
+            )
+            .prompt()
+            .map_err(prompt_error)?;
+        self.analysis_service.set_digest_to_telegram(post);
+        Ok(())
+    }
+
+    /// Combined digest: one week of the picked chats analyzed as a single report.
+    async fn run_combined_digest(&self, picked: &[&Chat]) -> Result<(), DomainError> {
+        let chat_ids: Vec<i64> = picked.iter().map(|c| c.id).collect();
+        // Newest first: the latest week is the usual pick.
+        let weeks: Vec<String> = self
+            .analysis_service
+            .combined_weeks(&chat_ids)
+            .await?
+            .into_iter()
+            .rev()
+            .map(|w| w.to_string())
+            .collect();
+        if weeks.is_empty() {
+            println!("No stored messages in the selected chats.");
+            return Ok(());
+        }
+        let week = Select::new("Week to digest", weeks)
+            .prompt()
+            .map_err(prompt_error)?;
+        self.confirm_digest_to_telegram()?;
+
+        let spinner = ProgressBar::new_spinner();
+        spinner.set_style(
+            ProgressStyle::default_spinner()
+                .tick_chars("⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏")
+                .template("{spinner:.cyan} {msg}")
+                .unwrap(),
+        );
+        spinner.set_message(format!(
+            "Analyzing {} chat(s) for week {} (requesting LLM)...",
+            picked.len(),
+            week
+        ));
+        spinner.enable_steady_tick(Duration::from_millis(100));
+        let outcome = self
+            .analysis_service
+            .analyze_week_across_chats(&chat_ids, &PeriodGroup::new(week.as_str()))
+            .await;
+        spinner.finish_and_clear();
+
+        match outcome {
+            Ok(report) => {
+                let actions = &report.result.action_items;
+                println!(
+                    "\n✅ Combined digest for {}: {} ({} action item(s))",
+                    week,
+                    report.files_display(),
+                    actions.len()
+                );
+                for item in actions.iter().take(ACTION_ITEMS_PREVIEW) {
+                    println!("      • {}", item.description);
+                }
+                if actions.len() > ACTION_ITEMS_PREVIEW {
+                    println!("      … and {} more", actions.len() - ACTION_ITEMS_PREVIEW);
+                }
+                if report.usage.calls > 0 {
+                    println!("💰 AI usage: {}", report.usage.describe());
+                }
+                self.review_action_items(&report).await?;
+            }
+            Err(e) => println!("\n❌ Combined digest failed: {}", e),
+        }
+        println!();
+        Ok(())
+    }
+
+    /// Statistics: archive-wide totals per chat, or one chat's recent activity.
+    async fn run_statistics(&self) -> Result<(), DomainError> {
+        const OVERVIEW: &str = "Archive overview (totals per chat)";
+        const AI_USAGE: &str = "AI usage (tokens and cost per month)";
+        let choice = Select::new(
+            "Statistics",
+            vec![
+                OVERVIEW.to_string(),
+                format!("Chat activity (last {} days)", STATS_DAYS),
+                AI_USAGE.to_string(),
+            ],
+        )
+        .prompt()
+        .map_err(prompt_error)?;
+        match choice.as_str() {
+            OVERVIEW => self.run_archive_overview().await,
+            AI_USAGE => self.run_ai_usage().await,
+            _ => self.run_chat_activity().await,
+        }
+    }
+
+    /// Tokens and estimated cost of all AI analysis runs, one row per month.
+    async fn run_ai_usage(&self) -> Result<(), DomainError> {
+        let months = self.analysis_service.usage_by_month().await?;
+        if months.is_empty() {
+            println!("No AI usage recorded yet. Run an AI Analysis first.");
+            return Ok(());
+        }
+        let accent = ansi_rgb(0xbc, 0x13, 0xfe);
+        let cyan = ansi_rgb(USER_CYAN.0, USER_CYAN.1, USER_CYAN.2);
+
+        println!("\n{}💰 AI usage{}\n", accent, RESET);
+        println!(
+            "  {}{:<7}  {:>6}  {:>12}  {:>12}  {:>10}{}",
+            accent, "Month", "Calls", "Prompt", "Completion", "Cost (USD)", RESET
+        );
+        let mut total = UsageTotals::default();
+        for (month, usage) in &months {
+            println!(
+                "  {:<7}  {:>6}  {:>12}  {:>12}  {:>10.4}",
+                month,
+                usage.calls,
+                usage.prompt_tokens,
+                usage.completion_tokens,
+                usage.cost_estimate
+            );
+            total.merge(usage);
+        }
+        println!();
+        println!("  {}Total:{} {}", cyan, RESET, total.describe());
+        println!("  Costs are estimates from list prices (TG_SYNC_AI_PRICES overrides them).");
+        println!();
+        Ok(())
+    }
+
+    /// Totals straight from the archive (no Telegram calls): one row per stored chat, then the
+    /// archive totals. Enough to check that a backup actually landed.
+    async fn run_archive_overview(&self) -> Result<(), DomainError> {
+        let stats = self.repo.get_global_stats().await?;
+        if stats.chats.is_empty() {
+            println!("The archive is empty. Run a Full Backup first.");
+            return Ok(());
+        }
+        let titles: HashMap<i64, String> = self
+            .repo
+            .get_known_chats()
+            .await?
+            .into_iter()
+            .map(|c| (c.id, c.title))
+            .collect();
+        let offset = self.utc_offset_secs;
+        let date = |ts: Option<i64>| {
+            ts.map_or_else(|| "-".to_string(), |t| activity::time_label(t, offset))
+        };
+        let title_of = |chat_id: i64| {
+            titles
+                .get(&chat_id)
+                .cloned()
+                .unwrap_or_else(|| chat_id.to_string())
+        };
+        let width = stats
+            .chats
+            .iter()
+            .map(|c| title_of(c.chat_id).chars().count())
+            .max()
+            .unwrap_or(0)
+            .clamp(4, 32);
+        let accent = ansi_rgb(0xbc, 0x13, 0xfe);
+        let cyan = ansi_rgb(USER_CYAN.0, USER_CYAN.1, USER_CYAN.2);
+
+        println!("\n{}📚 Archive overview{}\n", accent, RESET);
+        println!(
+            "  {}{:<width$}  {:>9}  {:<16}  {:<16}  {:>7}  {:>7}{}",
+            accent, "Chat", "Messages", "Oldest", "Newest", "Media", "Files", RESET
+        );
+        for chat in &stats.chats {
+            let title: String = title_of(chat.chat_id).chars().take(width).collect();
+            println!(
+                "  {:<width$}  {:>9}  {:<16}  {:<16}  {:>7}  {:>7}",
+                title,
+                chat.messages,
+                date(chat.oldest),
+                date(chat.newest),
+                chat.media_references(),
+                chat.media_files
+            );
+        }
+
+        let by_type: Vec<String> = stats
+            .media_by_type()
+            .iter()
+            .map(|(kind, n)| format!("{} {}", n, kind.as_str()))
+            .collect();
+        println!();
+        println!(
+            "  {}Chats:{} {}   {}Messages:{} {}",
+            cyan,
+            RESET,
+            stats.chats.len(),
+            cyan,
+            RESET,
+            stats.messages()
+        );
+        if !by_type.is_empty() {
+            println!("  {}Media:{} {}", cyan, RESET, by_type.join(", "));
+        }
+        println!(
+            "  {}Downloaded files:{} {} ({})   {}Database:{} {}",
+            cyan,
+            RESET,
+            stats.media_files(),
+            format_size(stats.media_bytes()),
+            cyan,
+            RESET,
+            format_size(stats.db_size_bytes)
+        );
+        println!();
+        Ok(())
+    }
+
+    /// Activity of one chat over the last [`STATS_DAYS`] days: per day and by hour of day.
+    async fn run_chat_activity(&self) -> Result<(), DomainError> {
+        let chats = self.dialogs().await?;
+        if chats.is_empty() {
+            println!("No dialogs found.");
+            return Ok(());
+        }
+
+        let options: Vec<String> = chats
+            .iter()
+            .map(|c| format!("{} {} ({})", chat_type_indicator(c.kind), c.title, c.id))
+            .collect();
+        let selected = Select::new("Select chat", options.clone())
+            .prompt()
+            .map_err(prompt_error)?;
+        let Some(chat) = chats
+            .iter()
+            .zip(options.iter())
+            .find(|(_, o)| **o == selected)
+            .map(|(c, _)| c)
+        else {
+            return Ok(());
+        };
+
+        let now = chrono::Utc::now().timestamp();
+        let range = TimeRange {
+            from: now - (STATS_DAYS - 1) * 86_400,
+            to: now + 1,
+        };
+        let offset = self.utc_offset_secs;
+
+        let day_bins = self
+            .repo
+            .get_activity_histogram(chat.id, ActivityBucket::Day, range, offset)
+            .await?;
+        let days = activity::daily_series(&day_bins, range, offset);
+        let total: u64 = days.iter().map(|b| b.count).sum();
+
+        println!(
+            "\n📈 Activity — {} (last {} days, {} messages)\n",
+            chat.title, STATS_DAYS, total
+        );
+        let counts: Vec<u64> = days.iter().map(|b| b.count).collect();
+        println!("  {}\n", activity::sparkline(&counts));
+        let rows: Vec<(String, u64)> = days
+            .iter()
+            .map(|b| (activity::day_label(b.bucket_start, offset), b.count))
+            .collect();
+        for row in activity::bar_rows(&rows, 40) {
+            println!("  {}", row);
+        }
+
+        let hour_bins = self
+            .repo
+            .get_activity_histogram(chat.id, ActivityBucket::HourOfDay, range, offset)
+            .await?;
+        let hours = activity::hourly_series(&hour_bins);
+        println!("\n🕒 By hour of day\n");
+        println!("  {}\n", activity::sparkline(&hours));
+        let rows: Vec<(String, u64)> = hours
+            .iter()
+            .enumerate()
+            .map(|(h, c)| (format!("{:02}:00", h), *c))
+            .collect();
+        for row in activity::bar_rows(&rows, 40) {
+            println!("  {}", row);
+        }
+        println!();
+
+        Ok(())
+    }
+
+    /// Full-text search over the archive: query, optional chat, ranked pages of matches.
+    async fn run_search(&self) -> Result<(), DomainError> {
+        let query = Text::new("Search for")
+            .with_help_message("All words must match; end a word with * to match a prefix")
+            .prompt()
+            .map_err(prompt_error)?;
+        if query.trim().is_empty() {
+            return Ok(());
+        }
+
+        let chats = self.archive_chats().await.unwrap_or_default();
+        const ALL_CHATS: &str = "All chats";
+        let mut options = vec![ALL_CHATS.to_string()];
+        options.extend(
+            chats
+                .iter()
+                .map(|c| format!("{} {} ({})", chat_type_indicator(c.kind), c.title, c.id)),
+        );
+        let selected = Select::new("Search in", options.clone())
+            .prompt()
+            .map_err(prompt_error)?;
+        let chat_id = chats
+            .iter()
+            .zip(options.iter().skip(1))
+            .find(|(_, o)| **o == selected)
+            .map(|(c, _)| c.id);
+
+        let mut offset = 0;
+        loop {
+            let hits = self
+                .repo
+                .search_messages(&query, chat_id, SEARCH_PAGE, offset)
+                .await?;
+            if hits.is_empty() && offset == 0 {
+                println!("No matches.");
+                return Ok(());
+            }
+            println!();
+            for hit in &hits {
+                let m = &hit.message;
+                let title = hit
+                    .chat_title
+                    .clone()
+                    .or_else(|| {
+                        chats
+                            .iter()
+                            .find(|c| c.id == m.chat_id)
+                            .map(|c| c.title.clone())
+                    })
+                    .unwrap_or_else(|| m.chat_id.to_string());
+                println!(
+                    "  {} · {} · #{}\n    {}",
+                    activity::time_label(m.date, self.utc_offset_secs),
+                    title,
+                    m.id,
+                    hit.snippet.replace('\n', " ")
+                );
+            }
+            offset += hits.len() as u32;
+            if (hits.len() as u32) < SEARCH_PAGE {
+                println!("\n{} match(es).", offset);
+                return Ok(());
+            }
+            let more = Confirm::new("More?")
+                .with_default(true)
+                .prompt()
+                .map_err(prompt_error)?;
+            if !more {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Export flow: pick what to export, then write it under data/exports.
+    async fn run_export(&self) -> Result<(), DomainError> {
+        const MESSAGES_CSV: &str = "Chat messages (CSV)";
+        const ANALYSIS_CSV: &str = "AI analysis results (CSV)";
+        let mut choices = vec![MESSAGES_CSV.to_string()];
+        choices.extend(
+            self.exporters
+                .iter()
+                .map(|e| format!("Chat → {}", e.name())),
+        );
+        choices.push(ANALYSIS_CSV.to_string());
+        let choice = Select::new("Export", choices.clone())
+            .prompt()
+            .map_err(prompt_error)?;
+        let exporter = self
+            .exporters
+            .iter()
+            .zip(choices.iter().skip(1))
+            .find(|(_, c)| **c == choice)
+            .map(|(e, _)| e);
+        let anonymize = Confirm::new("Anonymize names and contact data?")
+            .with_default(false)
+            .with_help_message("Pseudonyms (User-A, ...) plus redacted emails/phones/cards; the mapping is saved next to the file")
+            .prompt()
+            .map_err(prompt_error)?;
+        let mut opts = ExportOptions {
+            anonymize,
+            ..Default::default()
+        };
+
+        let report = if choice == ANALYSIS_CSV {
+            self.export_service
+                .export_analysis(None, ExportFormat::Csv, &opts)
+                .await?
+        } else {
+            let chats = self.archive_chats().await?;
+            if chats.is_empty() {
+                println!("No dialogs found.");
+                return Ok(());
+            }
+            let options: Vec<String> = chats
+                .iter()
+                .map(|c| format!("{} {} ({})", chat_type_indicator(c.kind), c.title, c.id))
+                .collect();
+            let selected = Select::new("Select chat", options.clone())
+                .prompt()
+                .map_err(prompt_error)?;
+            let Some(chat) = chats
+                .iter()
+                .zip(options.iter())
+                .find(|(_, o)| **o == selected)
+                .map(|(c, _)| c)
+            else {
+                return Ok(());
+            };
+            opts.me_id = self.tg.get_me_id().await.ok();
+            match exporter {
+                Some(exporter) => {
+                    let from = prompt_date("From date (YYYY-MM-DD, empty = first message)")?;
+                    let to = prompt_date("To date (YYYY-MM-DD, inclusive, empty = last message)")?;
+                    if from.is_some() || to.is_some() {
+                        opts.range = Some(activity::date_range(from, to, self.utc_offset_secs));
+                    }
+                    let default = self
+                        .export_service
+                        .chat_path(chat.id, exporter.extension())
+                        .display()
+                        .to_string();
+                    let out = Text::new("Output file")
+                        .with_default(&default)
+                        .prompt()
+                        .map_err(prompt_error)?;
+                    opts.out = Some(out.trim().into());
+                    self.export_service
+                        .export_chat(chat, exporter.as_ref(), &opts)
+                        .await?
+                }
+                None => {
+                    self.export_service
+                        .export_messages(chat.id, ExportFormat::Csv, &opts)
+                        .await?
+                }
+            }
+        };
+
+        println!(
+            "✅ Exported {} row(s) → {}",
+            report.rows,
+            report.path.display()
+        );
+        if let Some(mapping) = &report.mapping_path {
+            println!(
+                "   🔑 Pseudonym mapping (keep private): {}",
+                mapping.display()
+            );
+        }
+        Ok(())
+    }
+
+    /// Maintenance flow: audit the archive, then offer to apply the fixes.
+    async fn run_audit(&self) -> Result<(), DomainError> {
+        println!("\n🔍 Auditing archive...\n");
+        let report = self.audit_service.run(false).await?;
+        println!("{}\n", report);
+        if report.is_clean() {
+            return Ok(());
+        }
+
+        let fix = Confirm::new("Apply fixes?")
+            .with_default(false)
+            .with_help_message(
+                "Re-queues media, clamps checkpoints, rebuilds the full-text index; orphaned analyses are left as-is",
+            )
+            .prompt()
+            .map_err(prompt_error)?;
+        if fix {
+            let report = self.audit_service.run(true).await?;
+            println!("\n{}\n", report);
+        }
+        Ok(())
+    }
+
+    /// Maintenance flow: integrity check, VACUUM and orphan scan, then offer to delete the
+    /// orphaned media files.
+    async fn run_maintenance(&self) -> Result<(), DomainError> {
+        println!("\n🧹 Checking and compacting the database...\n");
+        let report = match self.maintenance_service.run().await {
+            Ok(report) => report,
+            Err(DomainError::State(reason)) => {
+                println!("⚠️  {}\n", reason);
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        println!("{}\n", report);
+        println!(
+            "Reclaimed {} from the database.",
+            format_size(report.database.reclaimed_bytes())
+        );
+        if report.orphans.is_empty() {
+            return Ok(());
+        }
+
+        let delete = Confirm::new(&format!(
+            "Delete {} orphaned media file(s) ({})?",
+            report.orphans.len(),
+            format_size(report.orphan_bytes())
+        ))
+        .with_default(false)
+        .with_help_message("Files in data/media that no message or media index entry refers to")
+        .prompt()
+        .map_err(prompt_error)?;
+        if delete {
+            let (files, bytes) = self
+                .maintenance_service
+                .delete_orphans(&report.orphans)
+                .await?;
+            println!(
+                "🗑️  Deleted {} file(s), {} reclaimed.\n",
+                files,
+                format_size(bytes)
+            );
+        }
+        Ok(())
+    }
+
+    /// Maintenance flow: compare downloaded media with their expected sizes (and checksums),
+    /// then offer to download the damaged files again.
+    async fn run_verify_media(&self) -> Result<(), DomainError> {
+        let hashes = Confirm::new("Also compare checksums?")
+            .with_default(false)
+            .with_help_message(
+                "Reads every file with a recorded SHA-256 (TG_SYNC_MEDIA_HASH); slow on large archives",
+            )
+            .prompt()
+            .map_err(prompt_error)?;
+        println!("\n🔍 Verifying media files...\n");
+        let report = match self.maintenance_service.verify_media(hashes).await {
+            Ok(report) => report,
+            Err(DomainError::State(reason)) => {
+                println!("⚠️  {}\n", reason);
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        println!("{}\n", report);
+        if report.corrupt.is_empty() {
+            return Ok(());
+        }
+
+        let requeue = Confirm::new(&format!(
+            "Delete and download {} file(s) again?",
+            report.corrupt.len()
+        ))
+        .with_default(true)
+        .prompt()
+        .map_err(prompt_error)?;
+        if requeue {
+            let queued = self
+                .maintenance_service
+                .requeue_corrupt(&report.corrupt)
+                .await?;
+            println!(
+                "📥 Queued {} file(s); the media worker downloads them in the background.\n",
+                queued
+            );
+        }
+        Ok(())
+    }
+
+    /// Import flow: read a Telegram Desktop `result.json`, confirm its chat, optionally copy its
+    /// media files, then store it and move the chat's sync checkpoint past it.
+    async fn run_import(&self) -> Result<(), DomainError> {
+        let path = Text::new("Path to the export's result.json")
+            .with_help_message("Telegram Desktop: Export chat history, format JSON")
+            .prompt()
+            .map_err(prompt_error)?;
+        let path = std::path::PathBuf::from(path.trim());
+        let history = self.import_service.read_file(&path).await?;
+        let Some(chat_id) = history.chat_id else {
+            println!("⚠️  The export has no chat id; import it with `tg-sync import --chat <ID>`.");
+            return Ok(());
+        };
+        println!(
+            "\n{} ({}): {} message(s), {} service entr(ies), {} unreadable.",
+            history.title,
+            chat_id,
+            history.messages.len(),
+            history.events.len(),
+            history.skipped.len()
+        );
+        let proceed = Confirm::new("Import into this chat?")
+            .with_default(true)
+            .with_help_message("Messages already in the archive are kept as they are")
+            .prompt()
+            .map_err(prompt_error)?;
+        if !proceed {
+            return Ok(());
+        }
+        let has_files = history.messages.iter().any(|m| m.media_file.is_some());
+        let copy_media = has_files
+            && if self.import_service.can_copy_media() {
+                Confirm::new("Copy exported media files into the archive?")
+                    .with_default(true)
+                    .prompt()
+                    .map_err(prompt_error)?
+            } else {
+                println!("The archive is encrypted: exported media files are not copied.");
+                false
+            };
+
+        let export_dir = path.parent().unwrap_or(std::path::Path::new("."));
+        let opts = ImportOptions {
+            chat_id: None,
+            copy_media,
+        };
+        let report = self
+            .import_service
+            .import(history, export_dir, &opts)
+            .await?;
+        println!(
+            "\n📥 Imported {} message(s) into {}; {} already in the archive, {} event(s).",
+            report.imported, report.title, report.duplicates, report.events
+        );
+        if copy_media {
+            println!(
+                "   Media: {} file(s) copied, {} not in the export.",
+                report.media_copied, report.media_missing
+            );
+        }
+        println!(
+            "   The next sync continues after message {}.\n",
+            report.last_message_id
+        );
+        Ok(())
+    }
+
+    /// Purge flow: pick an archived chat, confirm by typing its id, then delete its rows,
+    /// checkpoint and media files and blacklist it.
+    async fn run_purge(&self) -> Result<(), DomainError> {
+        let mut chats = self.repo.get_known_chats().await?;
+        let known: HashSet<i64> = chats.iter().map(|c| c.id).collect();
+        // Chats with stored messages but no title row are listed by id.
+        for stats in self.repo.get_global_stats().await?.chats {
+            if !known.contains(&stats.chat_id) {
+                chats.push(Chat {
+                    id: stats.chat_id,
+                    title: stats.chat_id.to_string(),
+                    username: None,
+                    kind: ChatType::Group,
+                    approx_message_count: None,
+                    is_forum: false,
+                    last_message_date: None,
+                });
+            }
+        }
+        if chats.is_empty() {
+            println!("The archive is empty.");
+            return Ok(());
+        }
+
+        let options: Vec<String> = chats
+            .iter()
+            .map(|c| format!("{} {} ({})", chat_type_indicator(c.kind), c.title, c.id))
+            .collect();
+        let selected = Select::new("Chat to purge from the archive", options.clone())
+            .prompt()
+            .map_err(prompt_error)?;
+        let Some(chat) = options
+            .iter()
+            .position(|o| *o == selected)
+            .map(|i| &chats[i])
+        else {
+            return Ok(());
+        };
+
+        let stats = self.repo.get_chat_stats(chat.id).await?;
+        println!(
+            "\n⚠️  This deletes {} message(s), the analyses, tracker tasks and {} media file(s) of {}.",
+            stats.messages, stats.media_files, chat.title
+        );
+        println!("   The chat is added to the blacklist. This cannot be undone.\n");
+        let expected = chat.id.to_string();
+        let typed = Text::new(&format!("Type the chat id ({}) to confirm:", expected))
+            .with_help_message("Anything else cancels")
+            .prompt()
+            .map_err(prompt_error)?;
+        if typed.trim() != expected {
+            println!("Cancelled.");
+            return Ok(());
+        }
+
+        match self.purge_service.purge(chat.id).await {
+            Ok(report) => {
+                println!("\n🗑️  {}", report);
+                println!("Reclaimed {} on disk.\n", format_size(report.purge.bytes));
+                Ok(())
+            }
+            Err(DomainError::State(reason)) => {
+                println!("⚠️  {}\n", reason);
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
 }
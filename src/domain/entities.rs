@@ -3,6 +3,7 @@
 //! No Telegram/IO types here — these are mapped from adapters.
 
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 
 /// Represents a Telegram chat (user, group, or channel).
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,6 +16,12 @@ pub struct Chat {
     /// Approximate message count heuristic from dialog top/last message ID (no full history fetch).
     #[serde(skip_serializing_if = "Option::is_none")]
     pub approx_message_count: Option<i32>,
+    /// Supergroup with Topics enabled: its history interleaves several forum threads.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub is_forum: bool,
+    /// Date of the dialog's last message (Unix seconds); only known for listed dialogs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_message_date: Option<i64>,
 }
 
 /// Classification of a Telegram chat.
@@ -32,6 +39,29 @@ pub enum ChatType {
     Channel,
 }
 
+impl ChatType {
+    /// Stable lowercase name (same as the serde representation).
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChatType::Private => "private",
+            ChatType::Group => "group",
+            ChatType::Supergroup => "supergroup",
+            ChatType::Channel => "channel",
+        }
+    }
+
+    /// Inverse of [`ChatType::as_str`] (also accepts the legacy "user"). Unknown names map to
+    /// `Group`.
+    pub fn from_name(s: &str) -> Self {
+        match s {
+            "private" | "user" => ChatType::Private,
+            "supergroup" => ChatType::Supergroup,
+            "channel" => ChatType::Channel,
+            _ => ChatType::Group,
+        }
+    }
+}
+
 /// One prior version of a message (used for edit history).
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MessageEdit {
@@ -52,6 +82,294 @@ pub struct Message {
     /// Previous versions when the message was edited. Oldest first.
     #[serde(default)]
     pub edit_history: Option<Vec<MessageEdit>>,
+    /// Sender's display name from the archived users table. Filled by repository reads only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sender_name: Option<String>,
+    /// Forum topic (thread) of the message in a forum supergroup. None outside forums and for
+    /// the General topic.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub topic_id: Option<i32>,
+    /// Title of `topic_id` from the archived forum topics. Filled by repository reads only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub topic_title: Option<String>,
+    /// Original author of a forwarded message: display name, when Telegram provides one or the
+    /// author was in the fetched batch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forwarded_from_name: Option<String>,
+    /// Original author's peer id (Bot API style: users positive, chats and channels negative).
+    /// None for hidden senders.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forwarded_from_id: Option<i64>,
+    /// Unix timestamp of the original message. Set for every forwarded message.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forwarded_date: Option<i64>,
+    /// Poll attached to the message, with the results of the last sync. `text` holds its
+    /// [`Poll::render`] so search, keywords and analysis see the content.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub poll: Option<Poll>,
+    /// In a channel's discussion group: id of the channel post this message is (the
+    /// automatic copy) or comments on (directly or as a reply to another comment).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub linked_channel_post: Option<i32>,
+    /// Album the message belongs to: media sent together arrive as consecutive messages
+    /// sharing this id, usually with the caption on one of them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grouped_id: Option<i64>,
+    /// Telegram's "mentions you" flag for the account that synced the message: set for an
+    /// @username or name mention of it, and for replies to its messages.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub mentioned: bool,
+    /// Users mentioned by a name link (mentions picked from the list, or of users without a
+    /// username). Mentions typed as @username carry no id and are not listed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mentioned_user_ids: Vec<i64>,
+}
+
+/// A poll (or quiz) and its results as last fetched.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Poll {
+    pub question: String,
+    pub answers: Vec<PollAnswer>,
+    #[serde(default)]
+    pub quiz: bool,
+    #[serde(default)]
+    pub closed: bool,
+    /// None while results are hidden from this account (e.g. before voting).
+    #[serde(default)]
+    pub total_voters: Option<i32>,
+}
+
+/// One answer option of a [`Poll`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PollAnswer {
+    pub text: String,
+    /// None while results are hidden.
+    #[serde(default)]
+    pub voters: Option<i32>,
+}
+
+impl Poll {
+    /// Readable one-line form, e.g. `[Poll] Lunch? — Pizza (12), Sushi (3)`.
+    pub fn render(&self) -> String {
+        let answers: Vec<String> = self
+            .answers
+            .iter()
+            .map(|a| match a.voters {
+                Some(n) => format!("{} ({})", a.text, n),
+                None => a.text.clone(),
+            })
+            .collect();
+        let kind = if self.quiz { "[Quiz]" } else { "[Poll]" };
+        let closed = if self.closed { " [closed]" } else { "" };
+        format!(
+            "{} {} — {}{}",
+            kind,
+            self.question,
+            answers.join(", "),
+            closed
+        )
+    }
+}
+
+/// What a service message recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatEventKind {
+    UserJoined,
+    UserLeft,
+    TitleChanged,
+    MessagePinned,
+    CallStarted,
+    Other,
+}
+
+impl ChatEventKind {
+    /// Stable snake_case name (same as the serde representation).
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChatEventKind::UserJoined => "user_joined",
+            ChatEventKind::UserLeft => "user_left",
+            ChatEventKind::TitleChanged => "title_changed",
+            ChatEventKind::MessagePinned => "message_pinned",
+            ChatEventKind::CallStarted => "call_started",
+            ChatEventKind::Other => "other",
+        }
+    }
+
+    /// Inverse of [`ChatEventKind::as_str`]. Unknown names map to `Other`.
+    pub fn from_name(s: &str) -> Self {
+        match s {
+            "user_joined" => ChatEventKind::UserJoined,
+            "user_left" => ChatEventKind::UserLeft,
+            "title_changed" => ChatEventKind::TitleChanged,
+            "message_pinned" => ChatEventKind::MessagePinned,
+            "call_started" => ChatEventKind::CallStarted,
+            _ => ChatEventKind::Other,
+        }
+    }
+}
+
+/// A service message (join, leave, title change, pin, call). Archived apart from messages, so
+/// it never reaches search or AI analysis.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatEvent {
+    /// Id of the service message, from the chat's message id sequence.
+    pub id: i32,
+    pub chat_id: i64,
+    pub date: i64,
+    pub kind: ChatEventKind,
+    /// User who joined, left, renamed the chat, pinned or started the call.
+    pub actor_id: Option<i64>,
+    /// Detail by kind: users added or removed by the actor (comma-separated ids), the new
+    /// title, the pinned message id, or the Telegram action name for `Other`.
+    pub payload: Option<String>,
+}
+
+impl ChatEvent {
+    /// Users added or removed by the actor (joins and leaves of someone else).
+    pub fn payload_user_ids(&self) -> Vec<i64> {
+        match self.kind {
+            ChatEventKind::UserJoined | ChatEventKind::UserLeft => self
+                .payload
+                .iter()
+                .flat_map(|p| p.split(','))
+                .filter_map(|id| id.trim().parse().ok())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// One line for exports, e.g. `alice changed the title to "Ops"`. `name` labels the actor
+    /// (called with None when unknown) and the users in the payload.
+    pub fn describe(&self, name: impl Fn(Option<i64>) -> String) -> String {
+        let actor = name(self.actor_id);
+        let payload = self.payload.as_deref().unwrap_or_default();
+        let users = || {
+            self.payload_user_ids()
+                .into_iter()
+                .map(|id| name(Some(id)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match self.kind {
+            ChatEventKind::UserJoined if self.payload.is_some() => {
+                format!("{} added {}", actor, users())
+            }
+            ChatEventKind::UserJoined => format!("{} joined", actor),
+            ChatEventKind::UserLeft if self.payload.is_some() => {
+                format!("{} removed {}", actor, users())
+            }
+            ChatEventKind::UserLeft => format!("{} left", actor),
+            ChatEventKind::TitleChanged => {
+                format!("{} changed the title to \"{}\"", actor, payload)
+            }
+            ChatEventKind::MessagePinned => format!("{} pinned message #{}", actor, payload),
+            ChatEventKind::CallStarted => format!("{} started a call", actor),
+            ChatEventKind::Other => format!("{}: {}", actor, payload),
+        }
+    }
+}
+
+impl Message {
+    /// "X" of "Forwarded from X" for forwarded messages: the original author's name, else
+    /// their id, else "unknown". None when the message was not forwarded.
+    pub fn forwarded_from(&self) -> Option<String> {
+        self.forwarded_date?;
+        Some(match (&self.forwarded_from_name, self.forwarded_from_id) {
+            (Some(name), _) => name.clone(),
+            (None, Some(id)) => id.to_string(),
+            (None, None) => "unknown".to_string(),
+        })
+    }
+
+    /// Whether the message mentions `user_id` by a name link.
+    pub fn mentions(&self, user_id: i64) -> bool {
+        self.mentioned_user_ids.contains(&user_id)
+    }
+
+    /// Whether `other` is a part of the same album.
+    pub fn same_album(&self, other: &Message) -> bool {
+        self.grouped_id.is_some()
+            && self.grouped_id == other.grouped_id
+            && self.chat_id == other.chat_id
+    }
+}
+
+/// "album of 5 photos": the number of parts and what they hold (photos, videos, files or
+/// audio when all alike, else items).
+pub fn album_label<'a>(parts: impl IntoIterator<Item = &'a Message>) -> String {
+    let mut count = 0;
+    let mut kind = None;
+    for part in parts {
+        count += 1;
+        let part_kind = match part.media.as_ref().map(|m| m.media_type) {
+            Some(MediaType::Photo) => "photos",
+            Some(MediaType::Video) => "videos",
+            Some(MediaType::Document) => "files",
+            Some(MediaType::Audio) => "audio files",
+            _ => "items",
+        };
+        kind = match kind {
+            None => Some(part_kind),
+            Some(k) if k == part_kind => Some(k),
+            Some(_) => Some("items"),
+        };
+    }
+    format!("album of {} {}", count, kind.unwrap_or("items"))
+}
+
+/// Topic id of a forum's General thread; its messages carry no topic reference.
+pub const GENERAL_TOPIC_ID: i32 = 1;
+
+/// A topic (thread) of a forum supergroup.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForumTopic {
+    /// Id of the topic's creation message; messages in the topic refer to it.
+    pub id: i32,
+    pub title: String,
+}
+
+/// Where a channel post is discussed: its automatic copy in the channel's discussion group,
+/// which the post's comments reply to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiscussionLink {
+    /// Bot API style id of the discussion group.
+    pub chat_id: i64,
+    /// Id of the post's copy in the group.
+    pub message_id: i32,
+}
+
+/// Forum topics a multi-chat sync keeps, per chat. Chats without a selection sync every
+/// message, so non-forum chats are never affected.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TopicFilter {
+    selected: HashMap<i64, HashSet<i32>>,
+}
+
+impl TopicFilter {
+    /// Keep every topic of every chat.
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Keep only `topics` of `chat_id` ([`GENERAL_TOPIC_ID`] for the General thread).
+    pub fn select(&mut self, chat_id: i64, topics: HashSet<i32>) {
+        self.selected.insert(chat_id, topics);
+    }
+
+    /// Selected topics of `chat_id`; None when the whole chat is synced.
+    pub fn topics(&self, chat_id: i64) -> Option<&HashSet<i32>> {
+        self.selected.get(&chat_id)
+    }
+}
+
+/// A Telegram user seen as a message sender (from the users list of a history batch).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct User {
+    pub id: i64,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub username: Option<String>,
 }
 
 /// Reference to downloadable media. Sent to media pipeline.
@@ -62,15 +380,199 @@ pub struct MediaReference {
     pub media_type: MediaType,
     /// Opaque handle for the adapter to resolve (e.g. file reference, input location).
     pub opaque_ref: String,
+    /// File size in bytes as reported by Telegram (largest photo size, document size), if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size_bytes: Option<i64>,
+    /// File name the sender gave a document, unsanitized, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+    /// MIME type reported by Telegram (documents), if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+}
+
+impl MediaReference {
+    /// Byte size a complete download has, when Telegram reported one.
+    pub fn expected_size(&self) -> Option<u64> {
+        self.size_bytes.filter(|&n| n > 0).map(|n| n as u64)
+    }
+}
+
+/// Which media a sync downloads. Media that doesn't pass stays referenced in the stored
+/// message (media_json), so a later sync with looser filters can still fetch it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MediaFilter {
+    /// Allowed types; `None` = every type, empty = text only.
+    pub types: Option<Vec<MediaType>>,
+    /// Skip media larger than this. Media of unknown size is never skipped for size.
+    pub max_size_bytes: Option<i64>,
+}
+
+impl MediaFilter {
+    /// Download every media file.
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Download nothing (text-only sync).
+    pub fn none() -> Self {
+        Self {
+            types: Some(Vec::new()),
+            max_size_bytes: None,
+        }
+    }
+
+    /// Photos only, any size.
+    pub fn photos_only() -> Self {
+        Self {
+            types: Some(vec![MediaType::Photo]),
+            max_size_bytes: None,
+        }
+    }
+
+    /// True when no media can pass.
+    pub fn is_none(&self) -> bool {
+        self.types.as_ref().is_some_and(|t| t.is_empty())
+    }
+
+    /// Whether `media` should be downloaded.
+    pub fn allows(&self, media: &MediaReference) -> bool {
+        let type_ok = self
+            .types
+            .as_ref()
+            .is_none_or(|t| t.contains(&media.media_type));
+        let size_ok = match (self.max_size_bytes, media.size_bytes) {
+            (Some(max), Some(size)) => size <= max,
+            _ => true,
+        };
+        type_ok && size_ok
+    }
+}
+
+/// Per-chat sync overrides (chat_settings). Unset fields fall back to what the sync was
+/// called with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatSettings {
+    pub chat_id: i64,
+    /// Download media (`Some(true)`) or not (`Some(false)`) regardless of the sync's choice.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub include_media: Option<bool>,
+    /// Media types downloaded when media is on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_types: Option<Vec<MediaType>>,
+    /// Messages per history request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch_limit: Option<i32>,
+    /// False: the watcher skips the chat even when it is a target.
+    #[serde(default = "default_true")]
+    pub sync_enabled: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl ChatSettings {
+    /// No overrides.
+    pub fn new(chat_id: i64) -> Self {
+        Self {
+            chat_id,
+            include_media: None,
+            media_types: None,
+            batch_limit: None,
+            sync_enabled: true,
+        }
+    }
+
+    /// True when nothing is overridden.
+    pub fn is_default(&self) -> bool {
+        *self == Self::new(self.chat_id)
+    }
+
+    /// Media filter for this chat: `default` with the overrides applied. Media switched on for
+    /// a text-only sync downloads every type (or `media_types`); the size limit is kept.
+    pub fn media_filter(&self, default: &MediaFilter) -> MediaFilter {
+        let mut filter = match self.include_media {
+            Some(false) => return MediaFilter::none(),
+            Some(true) if default.is_none() => MediaFilter {
+                types: None,
+                max_size_bytes: default.max_size_bytes,
+            },
+            _ if default.is_none() => return MediaFilter::none(),
+            _ => default.clone(),
+        };
+        if let Some(types) = &self.media_types {
+            filter.types = Some(types.clone());
+        }
+        filter
+    }
+
+    /// Messages per history request: the override, else `default`.
+    pub fn limit(&self, default: i32) -> i32 {
+        self.batch_limit.filter(|&n| n > 0).unwrap_or(default)
+    }
+
+    /// Overrides as short tags for chat lists, e.g. "[media:off] [sync:off]"; empty when
+    /// nothing is overridden.
+    pub fn label(&self) -> String {
+        let mut tags = Vec::new();
+        match (self.include_media, &self.media_types) {
+            (Some(false), _) => tags.push("[media:off]".to_string()),
+            (_, Some(types)) => tags.push(format!(
+                "[media:{}]",
+                types
+                    .iter()
+                    .map(|t| t.as_str())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            )),
+            (Some(true), None) => tags.push("[media:on]".to_string()),
+            (None, None) => {}
+        }
+        if let Some(limit) = self.batch_limit {
+            tags.push(format!("[batch:{}]", limit));
+        }
+        if !self.sync_enabled {
+            tags.push("[sync:off]".to_string());
+        }
+        tags.join(" ")
+    }
 }
 
 /// Result of a sign-in attempt. Either success or 2FA password required.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SignInResult {
     Success,
     PasswordRequired { hint: Option<String> },
 }
 
+/// How the user logs in on first start.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginMethod {
+    /// Phone number, then the code sent by Telegram.
+    PhoneCode,
+    /// Scan a QR code with a logged-in Telegram app.
+    Qr,
+}
+
+/// QR login token to show to the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QrToken {
+    /// `tg://login?token=...` URL encoded in the QR code.
+    pub url: String,
+    /// Unix timestamp after which a new token must be requested.
+    pub expires_at: i64,
+}
+
+/// Progress of a QR login.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QrLoginStatus {
+    /// Not scanned (or not confirmed) yet.
+    Pending,
+    /// Scanned and accepted; 2FA may still be required.
+    Accepted(SignInResult),
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum MediaType {
@@ -81,22 +583,556 @@ pub enum MediaType {
     Voice,
     Sticker,
     Animation,
+    /// Round video message.
+    #[serde(rename = "video_note")]
+    VideoNote,
     Other,
 }
 
+impl MediaType {
+    /// Stable lowercase name (same as the serde representation).
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MediaType::Photo => "photo",
+            MediaType::Video => "video",
+            MediaType::Document => "document",
+            MediaType::Audio => "audio",
+            MediaType::Voice => "voice",
+            MediaType::Sticker => "sticker",
+            MediaType::Animation => "animation",
+            MediaType::VideoNote => "video_note",
+            MediaType::Other => "other",
+        }
+    }
+
+    /// Inverse of [`MediaType::as_str`]. Unknown names map to `Other`.
+    pub fn from_name(s: &str) -> Self {
+        match s {
+            "photo" => MediaType::Photo,
+            "video" => MediaType::Video,
+            "document" => MediaType::Document,
+            "audio" => MediaType::Audio,
+            "voice" => MediaType::Voice,
+            "sticker" => MediaType::Sticker,
+            "animation" => MediaType::Animation,
+            "video_note" => MediaType::VideoNote,
+            _ => MediaType::Other,
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Media Files
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Download state of a media file tracked in the media index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaStatus {
+    Pending,
+    Done,
+    Failed,
+}
+
+impl MediaStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MediaStatus::Pending => "pending",
+            MediaStatus::Done => "done",
+            MediaStatus::Failed => "failed",
+        }
+    }
+
+    /// Inverse of [`MediaStatus::as_str`]. Unknown values are treated as `Pending`.
+    pub fn from_name(s: &str) -> Self {
+        match s {
+            "done" => MediaStatus::Done,
+            "failed" => MediaStatus::Failed,
+            _ => MediaStatus::Pending,
+        }
+    }
+}
+
+/// One media file known to the archive (row of the media index). One per (chat_id, message_id).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaFile {
+    pub chat_id: i64,
+    pub message_id: i32,
+    pub media_type: MediaType,
+    /// Path relative to the media directory (e.g. `123_45.jpg`).
+    pub rel_path: String,
+    /// File size on disk. None until downloaded.
+    pub size_bytes: Option<u64>,
+    /// Size Telegram reported for the media, when known. A downloaded file of another size
+    /// is truncated or damaged.
+    pub expected_size: Option<u64>,
+    /// Hex SHA-256 of the file content, when computed (dedup).
+    pub sha256: Option<String>,
+    pub status: MediaStatus,
+    /// Date of the owning message, when the message is stored. Filled on read.
+    pub message_date: Option<i64>,
+    /// Object key in remote storage once uploaded. The local file may be deleted then.
+    pub remote_key: Option<String>,
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Export
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Output format for chat and analysis exports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Flat comma-separated values (RFC 4180 quoting), one row per message / action item.
+    Csv,
+}
+
+impl ExportFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+        }
+    }
+
+    /// Inverse of [`ExportFormat::as_str`] (case-insensitive). None for unknown formats.
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Some(ExportFormat::Csv),
+            _ => None,
+        }
+    }
+}
+
+/// File format of an analysis report (TG_SYNC_REPORT_FORMATS).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// The Markdown digest.
+    Markdown,
+    /// The analysis result with the chat title and message count, for other tools.
+    Json,
+    /// The Markdown digest rendered as a standalone HTML page.
+    Html,
+}
+
+impl ReportFormat {
+    /// Name in TG_SYNC_REPORT_FORMATS, also the file extension.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportFormat::Markdown => "md",
+            ReportFormat::Json => "json",
+            ReportFormat::Html => "html",
+        }
+    }
+
+    /// Inverse of [`ReportFormat::as_str`] (case-insensitive; "markdown" is accepted too).
+    /// None for unknown formats.
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "md" | "markdown" => Some(ReportFormat::Markdown),
+            "json" => Some(ReportFormat::Json),
+            "html" => Some(ReportFormat::Html),
+            _ => None,
+        }
+    }
+}
+
+/// Chat-level data passed to an [`crate::ports::ExporterPort`] when a chat export starts.
+#[derive(Debug, Clone)]
+pub struct ExportChat {
+    pub id: i64,
+    pub title: String,
+    pub kind: ChatType,
+    /// Date filter of the export; None exports the whole chat.
+    pub range: Option<TimeRange>,
+    /// Unix timestamp of the export.
+    pub exported_at: i64,
+}
+
+/// One message prepared for a rendered chat export: sender resolved, media file located and
+/// the replied-to message looked up.
+#[derive(Debug, Clone)]
+pub struct ExportMessage {
+    pub message: Message,
+    /// Sender username from the entity registry (pseudonym when anonymized).
+    pub sender_name: Option<String>,
+    /// Sent by the account owner.
+    pub is_outgoing: bool,
+    /// Downloaded media file, relative to the export file (`/`-separated). None when the
+    /// message has no media or it was not downloaded.
+    pub media_path: Option<String>,
+    /// The message this one replies to, when it is in the archive.
+    pub reply_to: Option<ReplyQuote>,
+    /// Archived comments of a channel post (from its discussion group), oldest first. Empty
+    /// for other messages.
+    pub comments: Vec<ExportMessage>,
+}
+
+/// A service event prepared for a rendered chat export.
+#[derive(Debug, Clone)]
+pub struct ExportEvent {
+    pub event: ChatEvent,
+    /// Actor name from the entity registry (pseudonym when anonymized).
+    pub actor_name: Option<String>,
+    /// [`ChatEvent::describe`] with the same names.
+    pub text: String,
+}
+
+/// Quoted context of a reply.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplyQuote {
+    pub message_id: i32,
+    pub sender_name: Option<String>,
+    pub text: String,
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Ingest
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// One message read from a chat fragment file, before it is given archive ids.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FragmentMessage {
+    /// Unix timestamp (UTC).
+    pub date: i64,
+    /// Display name of the author as written in the fragment. None for unattributed lines.
+    pub sender: Option<String>,
+    pub text: String,
+}
+
+/// Parser output: messages in file order plus the lines that could not be parsed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParsedFragment {
+    pub messages: Vec<FragmentMessage>,
+    /// (1-based line or entry number, reason).
+    pub skipped: Vec<(usize, String)>,
+}
+
+/// A whole chat history export read for `tg-sync import`. Unlike a fragment it keeps
+/// Telegram's ids, so it goes into the chat it was exported from.
+#[derive(Debug, Clone)]
+pub struct ImportedHistory {
+    /// Bot API style chat id (users as is, basic groups negated, channels `-100<id>`). None
+    /// when the export has no id.
+    pub chat_id: Option<i64>,
+    pub title: String,
+    pub kind: ChatType,
+    /// Messages in file order. Their `chat_id` (and their media's) is set by the import.
+    pub messages: Vec<ImportedMessage>,
+    /// Service entries, `chat_id` likewise set by the import.
+    pub events: Vec<ChatEvent>,
+    /// Senders with the name the export shows.
+    pub users: Vec<User>,
+    /// (1-based entry number, reason).
+    pub skipped: Vec<(usize, String)>,
+}
+
+/// One message of an [`ImportedHistory`]. Its media reference has no `opaque_ref`: it
+/// cannot be downloaded, only copied from the export.
+#[derive(Debug, Clone)]
+pub struct ImportedMessage {
+    pub message: Message,
+    /// Exported media file, relative to the export's folder. None when the export left the
+    /// file out.
+    pub media_file: Option<String>,
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Sync Progress
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Snapshot of a running chat sync, reported after each saved batch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncProgress {
+    pub chat_id: i64,
+    /// Messages saved so far in this sync.
+    pub synced: u64,
+    /// Media downloads queued so far in this sync.
+    pub media_queued: u64,
+    /// Estimated messages still to fetch. None when the chat total is unknown.
+    pub remaining: Option<u64>,
+    /// Smoothed throughput (messages/sec, including rate-limit sleeps).
+    pub rate_per_sec: Option<f64>,
+    /// Rolling ETA. None when `remaining` or the rate is unknown.
+    pub eta: Option<std::time::Duration>,
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Activity Statistics
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Aggregation bucket for activity histograms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActivityBucket {
+    /// One bin per calendar day (local midnight to midnight).
+    Day,
+    /// One bin per hour of the day (0..=23), aggregated over the whole range.
+    HourOfDay,
+}
+
+/// Half-open time range `[from, to)` in Unix seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeRange {
+    pub from: i64,
+    pub to: i64,
+}
+
+/// One histogram row. For `Day`, `bucket_start` is the Unix timestamp of local midnight;
+/// for `HourOfDay`, it is the hour (0..=23).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActivityBin {
+    pub bucket_start: i64,
+    pub count: u64,
+}
+
+/// Stored totals of one chat (Statistics view).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChatStats {
+    pub chat_id: i64,
+    pub messages: u64,
+    /// Dates (Unix seconds) of the oldest and newest stored message. None for an empty chat.
+    pub oldest: Option<i64>,
+    pub newest: Option<i64>,
+    /// Messages carrying a media reference, per media type (most common first).
+    pub media_by_type: Vec<(MediaType, u64)>,
+    /// Media files downloaded for this chat, and their total size in bytes.
+    pub media_files: u64,
+    pub media_bytes: u64,
+}
+
+impl ChatStats {
+    /// Messages carrying a media reference, all types.
+    pub fn media_references(&self) -> u64 {
+        self.media_by_type.iter().map(|(_, n)| n).sum()
+    }
+}
+
+/// Totals of the whole archive: one entry per chat with stored messages, plus the size of the
+/// database files.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ArchiveStats {
+    pub chats: Vec<ChatStats>,
+    /// messages.db plus its WAL, in bytes.
+    pub db_size_bytes: u64,
+}
+
+impl ArchiveStats {
+    pub fn messages(&self) -> u64 {
+        self.chats.iter().map(|c| c.messages).sum()
+    }
+
+    pub fn media_files(&self) -> u64 {
+        self.chats.iter().map(|c| c.media_files).sum()
+    }
+
+    pub fn media_bytes(&self) -> u64 {
+        self.chats.iter().map(|c| c.media_bytes).sum()
+    }
+
+    /// Media references per type across all chats, most common first.
+    pub fn media_by_type(&self) -> Vec<(MediaType, u64)> {
+        let mut totals: Vec<(MediaType, u64)> = Vec::new();
+        for (kind, n) in self.chats.iter().flat_map(|c| &c.media_by_type) {
+            match totals.iter_mut().find(|(k, _)| k == kind) {
+                Some((_, total)) => *total += n,
+                None => totals.push((*kind, *n)),
+            }
+        }
+        totals.sort_by_key(|(_, n)| std::cmp::Reverse(*n));
+        totals
+    }
+}
+
+/// Outcome of a database maintenance pass (integrity check, WAL checkpoint, VACUUM).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DatabaseMaintenance {
+    /// Problems reported by `PRAGMA integrity_check`; empty when the database is intact.
+    pub integrity_errors: Vec<String>,
+    /// False when VACUUM was skipped because the integrity check failed.
+    pub vacuumed: bool,
+    /// messages.db plus its WAL before and after, in bytes.
+    pub size_before: u64,
+    pub size_after: u64,
+}
+
+impl DatabaseMaintenance {
+    pub fn reclaimed_bytes(&self) -> u64 {
+        self.size_before.saturating_sub(self.size_after)
+    }
+}
+
+/// What [`crate::ports::RepoPort::delete_chat_data`] removed for one chat.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChatPurge {
+    /// Deleted rows per table; tables without rows for the chat are left out.
+    pub rows: Vec<(&'static str, u64)>,
+    /// Media index paths (relative to the media directory) of the deleted rows.
+    pub media_paths: Vec<String>,
+    /// Files removed from disk and their size in bytes.
+    pub files: u64,
+    pub bytes: u64,
+}
+
+impl ChatPurge {
+    pub fn total_rows(&self) -> u64 {
+        self.rows.iter().map(|(_, n)| n).sum()
+    }
+}
+
+/// One full-text search match.
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub message: Message,
+    /// Title from the archive's chat table, when the chat is registered there.
+    pub chat_title: Option<String>,
+    /// Text excerpt around the match; matched terms are wrapped in `[` `]`.
+    pub snippet: String,
+}
+
+/// Keywords seeded as global watch rules into a new archive (the former built-in list).
+pub const DEFAULT_WATCH_KEYWORDS: &[&str] = &["Urgent", "Bug", "Error", "Production"];
+
+/// Watcher rule. A keyword rule matches text case-insensitively: a substring, or a regex when
+/// `is_regex`. Mention and reply rules have an empty pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchRule {
+    pub id: i64,
+    /// Chat the rule applies to; 0 = every watched chat.
+    pub chat_id: i64,
+    pub kind: WatchRuleKind,
+    pub pattern: String,
+    pub is_regex: bool,
+    pub enabled: bool,
+}
+
+/// What a watch rule alerts on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WatchRuleKind {
+    /// Messages whose text matches the pattern.
+    #[default]
+    Keyword,
+    /// Messages mentioning the signed-in account.
+    Mention,
+    /// Replies to messages of the signed-in account.
+    Reply,
+}
+
+impl WatchRuleKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WatchRuleKind::Keyword => "keyword",
+            WatchRuleKind::Mention => "mention",
+            WatchRuleKind::Reply => "reply",
+        }
+    }
+
+    /// Inverse of [`WatchRuleKind::as_str`]. Unknown values are treated as `Keyword`.
+    pub fn from_name(s: &str) -> Self {
+        match s {
+            "mention" => WatchRuleKind::Mention,
+            "reply" => WatchRuleKind::Reply,
+            _ => WatchRuleKind::Keyword,
+        }
+    }
+}
+
+/// How the watcher turns keyword matches into alerts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertMode {
+    /// One combined alert per chat and cycle, with match counts per keyword.
+    Digest,
+    /// One alert per matching message.
+    PerMessage,
+}
+
+/// Which chats the watcher's automatic backup syncs (blacklisted chats never are).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutoBackupScope {
+    /// Every dialog.
+    AllChats,
+    /// Only the watcher's target chats.
+    Targets,
+}
+
+impl WatchRule {
+    /// True for rules that apply to every watched chat.
+    pub fn is_global(&self) -> bool {
+        self.chat_id == 0
+    }
+}
+
 // ─────────────────────────────────────────────────────────────────────────────
 // AI Analysis Entities
 // ─────────────────────────────────────────────────────────────────────────────
 
-/// Weekly grouping key for analysis (e.g., "2024-05").
-/// Format: "YYYY-WW" where WW is ISO week number.
+/// Length of the period one analysis covers. Chosen per chat; weekly by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    Day,
+    #[default]
+    Week,
+    Month,
+}
+
+impl Granularity {
+    pub const ALL: [Granularity; 3] = [Granularity::Day, Granularity::Week, Granularity::Month];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Granularity::Day => "day",
+            Granularity::Week => "week",
+            Granularity::Month => "month",
+        }
+    }
+
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "day" | "daily" => Some(Granularity::Day),
+            "week" | "weekly" => Some(Granularity::Week),
+            "month" | "monthly" => Some(Granularity::Month),
+            _ => None,
+        }
+    }
+
+    /// SQLite strftime format of the period key: "YYYY-MM-DD", "YYYY-WW" or "YYYY-MM".
+    pub fn strftime_format(self) -> &'static str {
+        match self {
+            Granularity::Day => "%Y-%m-%d",
+            Granularity::Week => "%Y-%W",
+            Granularity::Month => "%Y-%m",
+        }
+    }
+
+    /// Capitalized period name, e.g. "Week".
+    pub fn label(self) -> &'static str {
+        match self {
+            Granularity::Day => "Day",
+            Granularity::Week => "Week",
+            Granularity::Month => "Month",
+        }
+    }
+
+    /// Report heading word, e.g. "Weekly".
+    pub fn adjective(self) -> &'static str {
+        match self {
+            Granularity::Day => "Daily",
+            Granularity::Week => "Weekly",
+            Granularity::Month => "Monthly",
+        }
+    }
+}
+
+/// Grouping key for analysis: one day, week or month (see [`Granularity::strftime_format`]).
+/// Keys of different granularities can look alike ("2024-05"), so storage pairs them with it.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
-pub struct WeekGroup(pub String);
+pub struct PeriodGroup(pub String);
+
+/// Weekly period key ("YYYY-WW"); the original name of [`PeriodGroup`].
+pub type WeekGroup = PeriodGroup;
 
-impl WeekGroup {
-    /// Create from SQLite strftime output: "YYYY-WW"
-    pub fn new(year_week: impl Into<String>) -> Self {
-        Self(year_week.into())
+impl PeriodGroup {
+    /// Create from SQLite strftime output, e.g. "YYYY-WW"
+    pub fn new(period: impl Into<String>) -> Self {
+        Self(period.into())
     }
 
     /// Get the inner string value.
@@ -105,7 +1141,7 @@ impl WeekGroup {
     }
 }
 
-impl std::fmt::Display for WeekGroup {
+impl std::fmt::Display for PeriodGroup {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.0)
     }
@@ -123,10 +1159,31 @@ pub struct ActionItem {
     pub priority: Option<String>,
 }
 
-/// Result of LLM analysis for a week's chat data.
+/// A task to create in the task tracker, built from an action item.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskSpec {
+    /// Short task title (e.g. card name).
+    pub title: String,
+    /// Longer description; may be empty.
+    pub description: String,
+    /// Due date string (format is adapter-specific, e.g. ISO date).
+    pub due: Option<String>,
+    /// Priority from the analysis ("high", "medium", "low").
+    #[serde(default)]
+    pub priority: Option<String>,
+}
+
+/// Chat id under which combined (cross-chat) digests are saved. Telegram never uses 0.
+pub const COMBINED_CHAT_ID: i64 = 0;
+
+/// Result of LLM analysis for a period's chat data.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AnalysisResult {
+    /// Period key; see `granularity` for its length.
     pub week_group: WeekGroup,
+    /// Results saved before granularities existed are weekly.
+    #[serde(default)]
+    pub granularity: Granularity,
     pub chat_id: i64,
     pub summary: String,
     pub key_topics: Vec<String>,
@@ -134,3 +1191,214 @@ pub struct AnalysisResult {
     /// Unix timestamp when analysis was performed.
     pub analyzed_at: i64,
 }
+
+/// A period with a stored analysis, as listed for browsing past reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnalyzedPeriod {
+    pub week_group: WeekGroup,
+    pub granularity: Granularity,
+    /// Unix timestamp when analysis was performed.
+    pub analyzed_at: i64,
+    pub action_items: usize,
+}
+
+/// Token usage of one LLM call, as reported by the provider.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AiUsage {
+    pub model: String,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl AiUsage {
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+/// What an LLM call returned, plus its token usage (None when the provider didn't report it).
+#[derive(Debug, Clone)]
+pub struct AiReply<T> {
+    pub value: T,
+    pub usage: Option<AiUsage>,
+}
+
+impl<T> AiReply<T> {
+    /// A reply without usage information (mock adapter, tests).
+    pub fn unmetered(value: T) -> Self {
+        Self { value, usage: None }
+    }
+}
+
+/// One LLM call made while analyzing a chat+period (ai_usage table).
+#[derive(Debug, Clone, PartialEq)]
+pub struct AiUsageRecord {
+    pub chat_id: i64,
+    pub week_group: PeriodGroup,
+    pub usage: AiUsage,
+    /// USD from the configured price table; None when the model has no price.
+    pub cost_estimate: Option<f64>,
+    /// Unix timestamp of the call.
+    pub called_at: i64,
+}
+
+/// Usage summed over several LLM calls (one analysis run, one month, ...).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UsageTotals {
+    pub calls: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Sum of the known cost estimates, USD.
+    pub cost_estimate: f64,
+    /// Calls whose model has no price; their cost is missing from `cost_estimate`.
+    pub unpriced_calls: u64,
+}
+
+impl UsageTotals {
+    pub fn add(&mut self, usage: &AiUsage, cost_estimate: Option<f64>) {
+        self.calls += 1;
+        self.prompt_tokens += usage.prompt_tokens;
+        self.completion_tokens += usage.completion_tokens;
+        match cost_estimate {
+            Some(cost) => self.cost_estimate += cost,
+            None => self.unpriced_calls += 1,
+        }
+    }
+
+    pub fn merge(&mut self, other: &UsageTotals) {
+        self.calls += other.calls;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.cost_estimate += other.cost_estimate;
+        self.unpriced_calls += other.unpriced_calls;
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    /// One-line summary, e.g. `12,345 tokens (12,000 prompt + 345 completion), ≈ $0.0021`.
+    pub fn describe(&self) -> String {
+        let mut text = format!(
+            "{} tokens ({} prompt + {} completion), ≈ ${:.4}",
+            self.total_tokens(),
+            self.prompt_tokens,
+            self.completion_tokens,
+            self.cost_estimate
+        );
+        if self.unpriced_calls > 0 {
+            text.push_str(&format!(
+                " (+{} call(s) with no price for the model)",
+                self.unpriced_calls
+            ));
+        }
+        text
+    }
+}
+
+/// Map-phase summary of one chunk of a week's CSV context. Kept until the week's analysis is
+/// saved, so a failed reduce step doesn't pay for the chunks again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkSummary {
+    pub chunk_index: usize,
+    /// Fingerprint of the chunk text; a summary is only reused for identical input.
+    pub chunk_hash: i64,
+    pub summary: String,
+}
+
+/// Something to tell the user about outside the app (e.g. by email or a webhook).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotificationEvent {
+    /// Watch keywords matched in a chat; `text` is the alert as sent to Telegram.
+    KeywordAlert {
+        chat_id: i64,
+        chat_title: String,
+        text: String,
+    },
+    /// A sync of several chats finished (e.g. the watcher's automatic backup).
+    SyncCompleted {
+        chats: usize,
+        messages: u64,
+        failed: usize,
+    },
+    /// AI analysis of a period finished; carries the Markdown report.
+    AnalysisCompleted {
+        chat_id: i64,
+        granularity: Granularity,
+        week_group: WeekGroup,
+        report_markdown: String,
+    },
+    /// Periodic activity summary (the watcher's daily digest), as Markdown.
+    Digest { title: String, markdown: String },
+    /// Something failed that the user should know about; `context` says what (e.g. "watcher").
+    Error { context: String, message: String },
+}
+
+impl NotificationEvent {
+    /// Machine-readable name of the kind of event (webhook payloads).
+    pub fn kind(&self) -> &'static str {
+        match self {
+            NotificationEvent::KeywordAlert { .. } => "keyword_alert",
+            NotificationEvent::SyncCompleted { .. } => "sync_completed",
+            NotificationEvent::AnalysisCompleted { .. } => "analysis_completed",
+            NotificationEvent::Digest { .. } => "digest",
+            NotificationEvent::Error { .. } => "error",
+        }
+    }
+
+    /// One-line subject (email subject, message title).
+    pub fn subject(&self) -> String {
+        match self {
+            NotificationEvent::KeywordAlert { chat_title, .. } => {
+                format!("Keyword alert in '{}'", chat_title)
+            }
+            NotificationEvent::SyncCompleted {
+                chats, messages, ..
+            } => format!(
+                "Sync completed: {} chat(s), {} new message(s)",
+                chats, messages
+            ),
+            NotificationEvent::AnalysisCompleted {
+                chat_id,
+                granularity,
+                week_group,
+                ..
+            } if *chat_id == COMBINED_CHAT_ID => {
+                format!("{} combined digest {}", granularity.adjective(), week_group)
+            }
+            NotificationEvent::AnalysisCompleted {
+                chat_id,
+                granularity,
+                week_group,
+                ..
+            } => format!(
+                "{} digest {} for chat {}",
+                granularity.adjective(),
+                week_group,
+                chat_id
+            ),
+            NotificationEvent::Digest { title, .. } => title.clone(),
+            NotificationEvent::Error { context, .. } => format!("tg-sync error: {}", context),
+        }
+    }
+
+    /// Body as Markdown.
+    pub fn markdown(&self) -> String {
+        match self {
+            NotificationEvent::KeywordAlert { text, .. } => text.clone(),
+            NotificationEvent::SyncCompleted {
+                chats,
+                messages,
+                failed,
+            } => format!(
+                "Synced {} chat(s): {} new message(s), {} chat(s) failed.\n",
+                chats, messages, failed
+            ),
+            NotificationEvent::AnalysisCompleted {
+                report_markdown, ..
+            } => report_markdown.clone(),
+            NotificationEvent::Digest { markdown, .. } => markdown.clone(),
+            NotificationEvent::Error { message, .. } => message.clone(),
+        }
+    }
+}
@@ -29,9 +29,36 @@ pub enum DomainError {
     #[error("FloodWait: retry after {seconds} seconds")]
     FloodWait { seconds: u64 },
 
+    /// TAKEOUT_INIT_DELAY: the takeout session must be confirmed in another Telegram client
+    /// and can only start after `seconds` seconds.
+    #[error(
+        "Takeout must be confirmed in another Telegram client; it can start in {seconds} seconds"
+    )]
+    TakeoutDelay { seconds: u64 },
+
     #[error("AI analysis failed: {0}")]
     Ai(String),
 
     #[error("Task tracker error: {0}")]
     TaskTracker(String),
+
+    #[error("Ingest failed: {0}")]
+    Ingest(String),
+
+    #[error("Notification failed: {0}")]
+    Notify(String),
+
+    #[error("Invalid watch rule: {0}")]
+    WatchRule(String),
+
+    /// Archive encryption: wrong or missing key, damaged sealed data.
+    #[error("Encryption error: {0}")]
+    Crypto(String),
+
+    #[error("Remote storage error: {0}")]
+    Storage(String),
+
+    /// The user backed out of an interactive prompt or action (Esc, Ctrl+C).
+    #[error("Cancelled")]
+    Cancelled,
 }
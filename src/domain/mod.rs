@@ -6,7 +6,15 @@ pub mod entities;
 pub mod errors;
 
 pub use entities::{
-    ActionItem, AnalysisResult, Chat, ChatType, MediaReference, MediaType, Message, MessageEdit,
-    SignInResult, WeekGroup,
+    ActionItem, ActivityBin, ActivityBucket, AiReply, AiUsage, AiUsageRecord, AlertMode,
+    AnalysisResult, AnalyzedPeriod, ArchiveStats, AutoBackupScope, COMBINED_CHAT_ID, Chat,
+    ChatEvent, ChatEventKind, ChatPurge, ChatSettings, ChatStats, ChatType, ChunkSummary,
+    DEFAULT_WATCH_KEYWORDS, DatabaseMaintenance, DiscussionLink, ExportChat, ExportEvent,
+    ExportFormat, ExportMessage, ForumTopic, FragmentMessage, GENERAL_TOPIC_ID, Granularity,
+    ImportedHistory, ImportedMessage, LoginMethod, MediaFile, MediaFilter, MediaReference,
+    MediaStatus, MediaType, Message, MessageEdit, NotificationEvent, ParsedFragment, PeriodGroup,
+    Poll, PollAnswer, QrLoginStatus, QrToken, ReplyQuote, ReportFormat, SearchHit, SignInResult,
+    SyncProgress, TaskSpec, TimeRange, TopicFilter, UsageTotals, User, WatchRule, WatchRuleKind, WeekGroup,
+    album_label,
 };
 pub use errors::DomainError;
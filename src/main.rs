@@ -2,59 +2,168 @@
 //! No business logic here; authentication is delegated to AuthService.
 
 use dotenv::dotenv;
-use std::path::PathBuf;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
-use tg_sync::adapters::ai::{MockAiAdapter, OpenAiAdapter};
+use tg_sync::adapters::ai::{
+    AnthropicAdapter, ChunkBudget, MockAiAdapter, OpenAiAdapter, PromptTemplates,
+};
+use tg_sync::adapters::cli::{self, CliCommand, ExportTarget, WatchMode};
+use tg_sync::adapters::export;
+use tg_sync::adapters::headless::EnvAuthPrompt;
+use tg_sync::adapters::ingest::{DesktopJsonParser, default_parsers};
+use tg_sync::adapters::integrations::dead_letter::{DEAD_LETTER_FILE, DeadLetterTracker};
 use tg_sync::adapters::integrations::trello::TrelloAdapter;
-use tg_sync::adapters::persistence::{sqlite_repo::SqliteRepo, state_json::StateJson};
+use tg_sync::adapters::notify::{HttpHeartbeat, notifiers_from_config};
+use tg_sync::adapters::persistence::mirror_repo::{MIRROR_DIR, MirrorRepo};
+use tg_sync::adapters::persistence::{
+    jsonl_repo, message_repo, sqlite_repo::SqliteRepo, state_store,
+};
+use tg_sync::adapters::recording::{RecordingTgGateway, ReplayMode, ReplayTgGateway};
+use tg_sync::adapters::storage::remote_storage_from_config;
 use tg_sync::adapters::telegram::{auth_adapter::GrammersAuthAdapter, client::GrammersTgGateway};
 use tg_sync::adapters::tools::chatpack::ChatpackProcessor;
-use tg_sync::adapters::ui::tui::TuiInputPort;
+use tg_sync::adapters::ui::auth_prompt::InquireAuthPrompt;
+use tg_sync::adapters::ui::progress::{IndicatifProgress, LogProgress};
+use tg_sync::adapters::ui::tui::{self, TuiInputPort};
+use tg_sync::domain::DomainError;
 use tg_sync::ports::{
-    AiPort, AnalysisLogPort, AuthPort, InputPort, RepoPort, StatePort, TaskTrackerPort, TgGateway,
+    AiPort, AnalysisLogPort, ArchiveAuditPort, AuthPort, AuthPromptPort, EntityRegistry,
+    HeartbeatPort, InputPort, MediaIndexPort, MediaQueuePort, NotifierPort, ProgressPort, RepoPort,
+    SettingsPort, StatePort, TaskTrackerPort, TgGateway, WatchRulePort,
+};
+use tg_sync::shared::activity;
+use tg_sync::shared::activity_flag::ActivityFlag;
+use tg_sync::shared::anonymize::Anonymizer;
+use tg_sync::shared::config::{AiProvider, AppConfig, DEFAULT_DATA_DIR, DEFAULT_MEDIA_QUEUE_SIZE};
+use tg_sync::shared::lock::{DataDirLock, pid_alive};
+use tg_sync::shared::logging;
+use tg_sync::shared::pidfile::{PidFile, WATCHER_PID_FILE, running_pid};
+use tg_sync::shared::rate_limiter::RateLimiter;
+use tg_sync::shared::systemd;
+use tg_sync::testing::bench::{self, BenchBounds, BenchConfig};
+use tg_sync::testing::synthetic::SyntheticSpec;
+use tg_sync::usecases::media_layout;
+use tg_sync::usecases::recovery_service::DEFAULT_PENDING_MEDIA_AGE;
+use tg_sync::usecases::watcher_service::{AutoBackup, Heartbeat};
+use tg_sync::usecases::{
+    AnalysisService, AuditService, AuthService, BackupService, DaemonController, ExportOptions,
+    ExportService, ImportOptions, ImportService, IngestService, MaintenanceService,
+    MediaManifestService, MediaWorker, PurgeService, RecoveryService, RecoveryStep,
+    ReplayTrackerDeadLetters, RequeuePendingMedia, SweepTempFiles, SyncService, UploadWorker,
+    WatcherService, WatcherStatus,
 };
-use tg_sync::shared::config::DEFAULT_MEDIA_QUEUE_SIZE;
-use tg_sync::usecases::{AnalysisService, AuthService, MediaWorker, SyncService, WatcherService};
 use tokio::sync::mpsc;
 use tracing::{info, warn};
 
 /// Bounded channel capacity for media refs. Producer (sync) blocks on send().await when full (backpressure).
 const CHANNEL_CAPACITY: usize = DEFAULT_MEDIA_QUEUE_SIZE;
+
+/// How long the SOCKS5 proxy (TG_SYNC_PROXY_URL) may take to accept a connection at startup.
+const PROXY_CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 use tracing_subscriber::{EnvFilter, layer::SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
     let env_loaded = dotenv();
+    let (globals, args) = match cli::split_global_flags(std::env::args().skip(1)) {
+        Ok(split) => split,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    let cli_command = match cli::parse_args(args) {
+        Ok(cmd) => cmd,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    // Progress bars only on a terminal; log lines are written around them, not over them.
+    let progress_bars = std::io::stderr()
+        .is_terminal()
+        .then(|| Arc::new(IndicatifProgress::new()));
+    let log_writer = match &progress_bars {
+        Some(bars) => BoxMakeWriter::new(bars.log_writer()),
+        None => BoxMakeWriter::new(std::io::stdout),
+    };
+    // A value that doesn't parse stops here, before it could silently become a default.
+    let mut cfg = match AppConfig::load(globals.profile.as_deref()) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            eprintln!("Configuration error: {}", e);
+            std::process::exit(2);
+        }
+    };
+    let log_format = cfg.log_format();
+    let mut log_layers = vec![logging::fmt_layer(log_format, log_writer, true)];
+    // The file layer is optional: an unusable path is reported once logging is up.
+    let log_file_error = match cfg.log_file() {
+        Some(path) => match logging::rolling_file(&path, cfg.log_keep_or_default()) {
+            Ok(appender) => {
+                log_layers.push(logging::fmt_layer(log_format, appender, false));
+                None
+            }
+            Err(e) => Some((path, e)),
+        },
+        None => None,
+    };
     tracing_subscriber::registry()
+        .with(log_layers)
         .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()))
-        .with(tracing_subscriber::fmt::layer())
         .init();
+    if let Some((path, e)) = log_file_error {
+        warn!(path = %path.display(), error = %e, "cannot open log file; logging to console only");
+    }
 
     match &env_loaded {
         Ok(path) => info!(path = %path.display(), "loaded .env"),
         Err(_) => info!(cwd = %cwd.display(), "no .env found (check CWD)"),
     }
+    for key in &cfg.unknown_keys {
+        warn!(key = %key, "unknown configuration key, ignored (see `tg-sync config check`)");
+    }
 
-    tg_sync::adapters::ui::init_ui();
+    // --- Non-interactive commands: no banner, no Telegram login (but `watch`, which runs the
+    // Watcher without the menu) ---
+    let watch_only = match cli_command {
+        Some(CliCommand::Watch {
+            mode: WatchMode::Foreground,
+        }) => true,
+        Some(cmd) => return run_cli(cmd, &cfg, globals.force).await,
+        None => false,
+    };
+
+    if !watch_only {
+        tg_sync::adapters::ui::init_ui();
+        // Named profiles exist but none was chosen: ask. Logging keeps the settings it was
+        // started with.
+        if cfg.profile.is_none() && !cfg.profiles.is_empty() && std::io::stdin().is_terminal() {
+            let picked = tui::pick_profile(&cfg.profiles).map_err(|e| anyhow::anyhow!("{}", e))?;
+            if let Some(name) = picked {
+                cfg = AppConfig::load(Some(&name))
+                    .map_err(|e| anyhow::anyhow!("configuration error: {}", e))?;
+            }
+        }
+    }
+    if let Some(name) = &cfg.profile {
+        info!(profile = %name, "using profile {}", name);
+    }
 
-    let cfg = tg_sync::shared::config::AppConfig::load().unwrap_or_default();
     if std::env::var("TG_SYNC_AI_API_KEY").is_ok() {
         info!("TG_SYNC_AI_API_KEY is set (env)");
     } else {
         info!("TG_SYNC_AI_API_KEY is not set in env");
     }
-    let api_hash = cfg
-        .api_hash
-        .clone()
-        .or_else(|| std::env::var("TG_SYNC_API_HASH").ok())
-        .unwrap_or_default();
-    if api_hash.is_empty() {
-        anyhow::bail!("Set TG_SYNC_API_HASH (env or .env). Get from https://my.telegram.org");
-    }
-
-    let data_dir = cfg.data_dir.as_deref().unwrap_or("./data").to_string();
+    let data_dir = cfg
+        .data_dir
+        .as_deref()
+        .unwrap_or(DEFAULT_DATA_DIR)
+        .to_string();
     let data_path = PathBuf::from(&data_dir);
     let data_dir_abs = data_path
         .canonicalize()
@@ -64,42 +173,127 @@ async fn main() -> anyhow::Result<()> {
         "data directory: {}",
         data_dir_abs.display()
     );
-    let state_path = data_path.join("state.json");
+    // --- One instance per data directory (data/.lock); released on graceful shutdown ---
+    std::fs::create_dir_all(&data_path).map_err(|e| anyhow::anyhow!("create data dir: {}", e))?;
+    let data_lock =
+        DataDirLock::acquire(&data_path, globals.force).map_err(|e| anyhow::anyhow!("{}", e))?;
     let session_path = cfg
         .session_path
         .as_deref()
         .map(PathBuf::from)
         .unwrap_or_else(|| PathBuf::from("./session.db"));
 
-    // --- Telegram client (cloned for auth and gateway; same session, no global lock) ---
-    let tg_client = create_telegram_client(&cfg, &session_path).await?;
-
-    // --- Auth: adapter + service, then run flow ---
-    let auth_adapter: Arc<dyn AuthPort> = Arc::new(GrammersAuthAdapter::new(tg_client.clone()));
-    let auth_service = AuthService::new(auth_adapter, api_hash);
-    auth_service
-        .run_auth_flow()
-        .await
-        .map_err(|e| anyhow::anyhow!("{}", e))?;
-
-    // --- Gateway (clone of same client; fetch_messages and download_media can run concurrently) ---
-    let tg: Arc<dyn TgGateway> = Arc::new(GrammersTgGateway::new(tg_client, cfg.export_delay_ms));
-
     // Audit §2.4: Use SqliteRepo for ACID compliance, WAL mode, and EntityRegistry support.
+    // Connected before the gateway, which resolves peers through the entity registry.
     let sqlite_repo = Arc::new(
-        SqliteRepo::connect(&data_path)
+        open_archive(&data_path, &cfg)
             .await
             .map_err(|e| anyhow::anyhow!("SQLite connect failed: {}", e))?,
     );
-    let repo: Arc<dyn RepoPort> = Arc::clone(&sqlite_repo) as Arc<dyn RepoPort>;
+    // --- Message repository: SQLite, or JSONL files (TG_SYNC_REPO_BACKEND) ---
+    let jsonl = jsonl_repo(&data_path, &cfg);
+    let registry: Arc<dyn EntityRegistry> = match &jsonl {
+        Some(jsonl) => {
+            warn!(
+                "JSONL repository: AI analysis, the archive audit and media retries read messages.db and see none of its messages"
+            );
+            Arc::clone(jsonl) as Arc<dyn EntityRegistry>
+        }
+        None => Arc::clone(&sqlite_repo) as Arc<dyn EntityRegistry>,
+    };
+
+    let tg: Arc<dyn TgGateway> = if let Some(replay_dir) = cfg.replay_dir.as_deref() {
+        // --- Replay: recorded gateway calls, no login and no network (TG_SYNC_REPLAY_DIR) ---
+        info!(
+            dir = replay_dir,
+            "replaying recorded Telegram calls (offline)"
+        );
+        Arc::new(
+            ReplayTgGateway::open(replay_dir, ReplayMode::Keyed)
+                .map_err(|e| anyhow::anyhow!("{}", e))?,
+        )
+    } else {
+        let api_hash = cfg
+            .api_hash
+            .clone()
+            .or_else(|| std::env::var("TG_SYNC_API_HASH").ok())
+            .unwrap_or_default();
+        if api_hash.is_empty() {
+            anyhow::bail!("Set TG_SYNC_API_HASH (env or .env). Get from https://my.telegram.org");
+        }
+
+        // --- Telegram client (cloned for auth and gateway; same session, no global lock) ---
+        let tg_client = create_telegram_client(&cfg, &session_path).await?;
+
+        // --- Auth: adapter + service, then run flow ---
+        let auth_adapter: Arc<dyn AuthPort> = Arc::new(GrammersAuthAdapter::new(tg_client.clone()));
+        // Headless login when TG_SYNC_PHONE is set or there is no terminal to prompt on.
+        let auth_prompt: Arc<dyn AuthPromptPort> =
+            if cfg.phone.is_some() || !std::io::stdin().is_terminal() {
+                Arc::new(EnvAuthPrompt::new(
+                    cfg.phone.clone(),
+                    cfg.password.clone(),
+                    cfg.login_method(),
+                    data_path.clone(),
+                    cfg.login_http_addr(),
+                ))
+            } else {
+                Arc::new(InquireAuthPrompt::new())
+            };
+        let api_id = cfg.telegram_api_id().unwrap_or(0);
+        // Bot account (TG_SYNC_BOT_TOKEN): signs in without prompts.
+        let bot_token = cfg.bot_token();
+        let mut auth_service = AuthService::new(auth_adapter, auth_prompt, api_id, api_hash);
+        if let Some(token) = bot_token.clone() {
+            auth_service = auth_service.with_bot_token(token);
+        }
+        auth_service
+            .run_auth_flow()
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+
+        // --- Gateway (clone of same client; fetch_messages and download_media can run concurrently) ---
+        // --- Rate limits (TG_SYNC_RATE_HISTORY_PER_MIN / TG_SYNC_RATE_MEDIA_PER_MIN) ---
+        let deprecated = cfg.deprecated_delay_keys();
+        if !deprecated.is_empty() {
+            warn!(
+                keys = ?deprecated,
+                "{} deprecated; use TG_SYNC_RATE_HISTORY_PER_MIN instead",
+                deprecated.join(", ")
+            );
+        }
+        let history_per_min = cfg.rate_history_per_min_or_default();
+        let media_per_min = cfg.rate_media_per_min_or_default();
+        info!(
+            history_per_min,
+            media_per_min,
+            "Telegram rate limit: {} history / {} media requests per minute (0 = unlimited)",
+            history_per_min,
+            media_per_min
+        );
+        let limiter = RateLimiter::per_minute(history_per_min, media_per_min);
+        let mut gateway = GrammersTgGateway::new(tg_client, limiter, Arc::clone(&registry));
+        // A bot has no dialog list: its chats come from TG_SYNC_BOT_CHATS.
+        if bot_token.is_some() {
+            gateway = gateway.with_bot_chats(cfg.bot_chats());
+        }
+        let live: Arc<dyn TgGateway> = Arc::new(gateway);
+        // --- Optional fixture recording (TG_SYNC_RECORD_DIR) ---
+        match cfg.record_dir.as_deref() {
+            Some(dir) => {
+                Arc::new(RecordingTgGateway::new(live, dir).map_err(|e| anyhow::anyhow!("{}", e))?)
+            }
+            None => live,
+        }
+    };
+
+    let repo: Arc<dyn RepoPort> = message_repo(Arc::clone(&sqlite_repo), jsonl, &data_path, &cfg);
     let analysis_log: Arc<dyn AnalysisLogPort> =
         Arc::clone(&sqlite_repo) as Arc<dyn AnalysisLogPort>;
-    let state_impl = StateJson::new(&state_path);
-    state_impl
-        .load()
+    // --- Sync checkpoints: state.json or the sync_state table (TG_SYNC_STATE_BACKEND) ---
+    let state: Arc<dyn StatePort> = state_store(Arc::clone(&sqlite_repo), &data_path, &cfg)
         .await
         .map_err(|e| anyhow::anyhow!("{}", e))?;
-    let state: Arc<dyn StatePort> = Arc::new(state_impl);
 
     let _processor = Arc::new(ChatpackProcessor::new(None::<&str>));
 
@@ -114,104 +308,971 @@ async fn main() -> anyhow::Result<()> {
     tokio::fs::create_dir_all(&media_dir)
         .await
         .map_err(|e| anyhow::anyhow!("create media dir: {}", e))?;
-    let media_worker = MediaWorker::new(Arc::clone(&tg), media_rx, media_dir);
+    let media_index: Arc<dyn MediaIndexPort> = Arc::clone(&sqlite_repo) as Arc<dyn MediaIndexPort>;
+    // One-time move of flat media files into per-chat folders, before any worker touches them.
+    if let Err(e) = media_layout::migrate_flat_layout(media_index.as_ref(), &media_dir).await {
+        warn!(error = %e, "failed to move media files into per-chat folders");
+    }
+
+    // --- Remote storage (TG_SYNC_S3_*): media uploads on their own bounded queue ---
+    let remote_storage = remote_storage_from_config(&cfg).map_err(|e| anyhow::anyhow!("{}", e))?;
+    if remote_storage.is_none() && (cfg.media_upload_enabled() || cfg.db_backup_upload_enabled()) {
+        anyhow::bail!(
+            "TG_SYNC_MEDIA_UPLOAD/TG_SYNC_DB_BACKUP_UPLOAD need remote storage (set TG_SYNC_S3_BUCKET)"
+        );
+    }
+    let upload_tx = match remote_storage
+        .as_ref()
+        .filter(|_| cfg.media_upload_enabled())
+    {
+        Some(remote) => {
+            let (upload_tx, upload_rx) = mpsc::channel(media_queue_size);
+            let upload_worker = UploadWorker::new(
+                Arc::clone(remote),
+                Arc::clone(&media_index),
+                upload_rx,
+                media_dir.clone(),
+                cfg.media_delete_local_enabled(),
+            );
+            info!(
+                storage = remote.name(),
+                delete_local = cfg.media_delete_local_enabled(),
+                "media uploads enabled"
+            );
+            tokio::spawn(async move {
+                upload_worker.run().await;
+            });
+            Some(upload_tx)
+        }
+        None => None,
+    };
+    let media_worker = MediaWorker::new(
+        Arc::clone(&tg),
+        media_rx,
+        media_dir.clone(),
+        Arc::clone(&media_index),
+        Arc::clone(&sqlite_repo) as Arc<dyn MediaQueuePort>,
+        sqlite_repo.cipher(),
+        upload_tx,
+    )
+    .with_hashes(cfg.media_hash_enabled());
     tokio::spawn(async move {
         media_worker.run().await;
     });
 
-    // --- Sync rate limit (SYNC_DELAY_MS, default 500ms) ---
-    let sync_delay_ms = cfg.sync_delay_ms_or_default();
-    let sync_delay = Duration::from_millis(sync_delay_ms);
-    info!(
-        sync_delay_ms,
-        "sync rate limit: {} ms between batches", sync_delay_ms
+    // --- Services ---
+    let progress: Arc<dyn ProgressPort> = match progress_bars {
+        Some(bars) => bars,
+        None => Arc::new(LogProgress::new()),
+    };
+    // Running syncs and the watcher keep maintenance (VACUUM, orphan deletion) out.
+    let activity = ActivityFlag::new();
+    let sync_service = Arc::new(
+        SyncService::new(
+            Arc::clone(&tg),
+            Arc::clone(&repo),
+            Arc::clone(&state),
+            media_tx.clone(),
+            Arc::clone(&sqlite_repo) as Arc<dyn MediaQueuePort>,
+            Arc::clone(&sqlite_repo) as Arc<dyn SettingsPort>,
+            cfg.edit_rescan_window_or_default(),
+            data_path.join("reports"),
+            Some(progress),
+            activity.clone(),
+        )
+        .with_media_index(Arc::clone(&media_index), media_dir.clone()),
     );
 
-    // --- Services ---
-    let sync_service = Arc::new(SyncService::new(
-        Arc::clone(&tg),
-        Arc::clone(&repo),
-        Arc::clone(&state),
-        media_tx,
-        sync_delay,
-    ));
+    // --- Notifiers (email via TG_SYNC_SMTP_URL, webhook via TG_SYNC_WEBHOOK_URL); misconfiguration is fatal ---
+    let notifiers = notifiers_from_config(&cfg).map_err(|e| anyhow::anyhow!("{}", e))?;
+    for notifier in &notifiers {
+        info!(notifier = notifier.name(), "notifications enabled");
+    }
 
     let watcher_cycle_secs = cfg.watcher_cycle_secs_or_default();
+    // --- Automatic backups from the watcher loop (TG_SYNC_AUTO_BACKUP_*) ---
+    let auto_backup = cfg.auto_backup_interval().map(|every| AutoBackup {
+        every,
+        scope: cfg.auto_backup_scope(),
+        media: cfg.auto_backup_media_filter(),
+    });
+    if let Some(auto) = &auto_backup {
+        info!(
+            every_hours = auto.every.as_secs() / 3600,
+            scope = ?auto.scope,
+            "automatic backups from the watcher enabled"
+        );
+    }
+    // --- Watcher liveness: URL ping per cycle, daily summary (TG_SYNC_HEARTBEAT_*) ---
+    let heartbeat = Heartbeat {
+        pinger: HttpHeartbeat::from_config(&cfg)
+            .map_err(|e| anyhow::anyhow!("{}", e))?
+            .map(|h| Arc::new(h) as Arc<dyn HeartbeatPort>),
+        daily_hour: cfg.heartbeat_daily_hour(),
+        utc_offset_secs: cfg.utc_offset_secs(),
+    };
+    if let Some(pinger) = &heartbeat.pinger {
+        info!(heartbeat = pinger.name(), "watcher heartbeat enabled");
+    }
     let watcher_service = Arc::new(WatcherService::new(
         Arc::clone(&tg),
         Arc::clone(&repo),
+        Arc::clone(&sqlite_repo) as Arc<dyn WatchRulePort>,
+        Arc::clone(&sqlite_repo) as Arc<dyn SettingsPort>,
         Arc::clone(&sync_service),
         Duration::from_secs(watcher_cycle_secs),
+        systemd::watchdog_interval(),
+        notifiers.clone(),
+        cfg.alert_chat_id(),
+        cfg.alert_mode(),
+        cfg.alert_cooldown_secs_or_default(),
+        auto_backup,
+        heartbeat,
+        activity.clone(),
     ));
+    let watcher_daemon = Arc::new(DaemonController::new(Arc::clone(&watcher_service)));
 
     // --- AI Analysis Service ---
+    // Failed task creations are parked in data/tracker_dead_letters.jsonl and replayed on start.
+    let dead_letter_tracker = dead_letter_tracker(&cfg, &data_path).await?;
+    let analysis_service = Arc::new(analysis_service(
+        &cfg,
+        &data_path,
+        &sqlite_repo,
+        Arc::clone(&repo),
+        dead_letter_tracker
+            .clone()
+            .map(|t| t as Arc<dyn TaskTrackerPort>),
+        notifiers,
+        Some(Arc::clone(&tg)),
+    )?);
+
+    let export_service = Arc::new(ExportService::new(
+        Arc::clone(&repo),
+        registry,
+        Arc::clone(&analysis_log),
+        Arc::clone(&media_index),
+        media_dir.clone(),
+        data_path.join("exports"),
+        cfg.anonymize_map.as_deref().map(PathBuf::from),
+    ));
+
+    let audit_service = Arc::new(AuditService::new(
+        Arc::clone(&sqlite_repo) as Arc<dyn ArchiveAuditPort>,
+        Arc::clone(&media_index),
+        Arc::clone(&state),
+        media_dir.clone(),
+    ));
+    let maintenance_service = Arc::new(MaintenanceService::new(
+        Arc::clone(&sqlite_repo) as Arc<dyn ArchiveAuditPort>,
+        media_index,
+        Arc::clone(&sqlite_repo) as Arc<dyn MediaQueuePort>,
+        activity.clone(),
+        media_dir.clone(),
+    ));
+    let purge_service = Arc::new(PurgeService::new(
+        Arc::clone(&repo),
+        Arc::clone(&state),
+        activity,
+        media_dir.clone(),
+    ));
+    let backup_service = Arc::new(BackupService::new(
+        Arc::clone(&sqlite_repo) as Arc<dyn ArchiveAuditPort>,
+        data_path.join("backups"),
+        cfg.db_backup_gzip_enabled(),
+        cfg.db_backup_keep_or_default(),
+        remote_storage.filter(|_| cfg.db_backup_upload_enabled()),
+    ));
+    if let Some(every) = cfg.db_backup_interval() {
+        info!(
+            every_hours = every.as_secs() / 3600,
+            keep = cfg.db_backup_keep_or_default(),
+            "periodic database snapshots enabled"
+        );
+        let backups = Arc::clone(&backup_service);
+        tokio::spawn(async move {
+            backups.run_every(every).await;
+        });
+    }
+
+    let input_port: Arc<dyn InputPort> = Arc::new(TuiInputPort::new(
+        Arc::clone(&tg),
+        Arc::clone(&repo),
+        Arc::clone(&sync_service),
+        Arc::clone(&watcher_service),
+        Arc::clone(&watcher_daemon),
+        Arc::clone(&analysis_service),
+        Arc::clone(&export_service),
+        export::default_exporters(cfg.utc_offset_secs()),
+        Arc::new(import_service(
+            Arc::clone(&repo),
+            &sqlite_repo,
+            Arc::clone(&state),
+            &data_path,
+            &cfg,
+        )),
+        audit_service,
+        maintenance_service,
+        purge_service,
+        backup_service,
+        cfg.utc_offset_secs(),
+        cfg.media_filter(),
+        cfg.parallel_chats_or_default(),
+        config_report(&cfg),
+    ));
+
+    // --- Startup recovery: finish what a crash or kill left behind, before any new work ---
+    let mut recovery_steps: Vec<Box<dyn RecoveryStep>> = vec![
+        Box::new(
+            SweepTempFiles::new(vec![
+                media_dir.clone(),
+                data_path.clone(),
+                data_path.join("exports"),
+                data_path.join(MIRROR_DIR),
+            ])
+            .with_nested(vec![media_dir]),
+        ),
+        Box::new(RequeuePendingMedia::new(
+            Arc::clone(&sqlite_repo) as Arc<dyn MediaIndexPort>,
+            media_tx,
+            DEFAULT_PENDING_MEDIA_AGE,
+        )),
+    ];
+    if let Some(dead_letters) = dead_letter_tracker {
+        recovery_steps.push(Box::new(ReplayTrackerDeadLetters::new(dead_letters)));
+    }
+    RecoveryService::new(recovery_steps).run().await;
+
+    // --- Auth done, services up: tell systemd (Type=notify) we're ready; no-op otherwise ---
+    systemd::notify_ready();
+
+    let result = if watch_only {
+        // --- `tg-sync watch`: the Watcher alone until Ctrl+C or SIGTERM ---
+        let _pid_file = PidFile::create(&data_path.join(WATCHER_PID_FILE))
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let shutdown = async {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate_signal() => {}
+            }
+        };
+        watcher_daemon.run_until(shutdown).await
+    } else {
+        // --- Run the main menu until Quit or SIGTERM (Ctrl+C only stops the running action) ---
+        let result = tokio::select! {
+            result = input_port.run() => result,
+            _ = terminate_signal() => {
+                info!("shutdown signal received; stopping");
+                Ok(())
+            }
+        };
+        // A watcher started from the menu finishes the chat it is syncing first.
+        if let Err(e) = watcher_daemon.stop().await {
+            warn!(error = %e, "watcher ended with an error");
+        }
+        result
+    };
+    systemd::notify_stopping();
+    drop(data_lock);
+
+    result.map_err(|e| anyhow::anyhow!("{}", e))
+}
+
+/// Resolves on SIGTERM on Unix (what `systemctl stop` sends); never elsewhere. Ctrl+C is
+/// handled by the menu, which stops the running action and shows the menu again.
+async fn terminate_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                term.recv().await;
+            }
+            Err(e) => {
+                warn!(error = %e, "SIGTERM handler unavailable; quit from the menu");
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Run a single non-interactive command and exit. Offline commands only need the data directory.
+async fn run_cli(cmd: CliCommand, cfg: &AppConfig, force: bool) -> anyhow::Result<()> {
+    let data_path = PathBuf::from(cfg.data_dir.as_deref().unwrap_or(DEFAULT_DATA_DIR));
+    let _lock = if cmd.needs_lock() {
+        std::fs::create_dir_all(&data_path)
+            .map_err(|e| anyhow::anyhow!("create data dir: {}", e))?;
+        Some(DataDirLock::acquire(&data_path, force).map_err(|e| anyhow::anyhow!("{}", e))?)
+    } else {
+        None
+    };
+    match cmd {
+        CliCommand::Help => print!("{}", cli::USAGE),
+        CliCommand::Bench {
+            messages,
+            max_text_len,
+            media_percent,
+            dir,
+        } => {
+            let config = BenchConfig {
+                spec: SyntheticSpec {
+                    count: messages,
+                    max_text_len,
+                    media_ratio: f64::from(media_percent) / 100.0,
+                    ..SyntheticSpec::default()
+                },
+                ..BenchConfig::default()
+            };
+            // Never the real archive: a scratch dir, removed afterwards unless given explicitly.
+            let scratch = dir.is_none();
+            let dir = dir.unwrap_or_else(|| {
+                std::env::temp_dir().join(format!("tg-sync-bench-{}", std::process::id()))
+            });
+            if dir.join("messages.db").exists() {
+                anyhow::bail!(
+                    "{} already contains a database; pick an empty --dir",
+                    dir.display()
+                );
+            }
+            println!(
+                "Benchmarking {} synthetic messages in {} ...",
+                messages,
+                dir.display()
+            );
+            let result = bench::run(&dir, &config).await;
+            if scratch {
+                let _ = std::fs::remove_dir_all(&dir);
+            }
+            let report = result.map_err(|e| anyhow::anyhow!("{}", e))?;
+            print!("{}", report);
+            let problems = report.check(&BenchBounds::default());
+            if !problems.is_empty() {
+                for problem in &problems {
+                    eprintln!("  - {}", problem);
+                }
+                anyhow::bail!("{} bench bound(s) exceeded", problems.len());
+            }
+        }
+        CliCommand::MirrorRebuild { chat_id } => {
+            let repo = Arc::new(
+                open_archive(&data_path, cfg)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("SQLite connect failed: {}", e))?,
+            );
+            let mirror_dir = data_path.join(MIRROR_DIR);
+            let mirror = MirrorRepo::new(repo.clone(), &mirror_dir);
+            let rebuilt = match chat_id {
+                Some(chat_id) => {
+                    let written = mirror
+                        .rebuild_chat(chat_id)
+                        .await
+                        .map_err(|e| anyhow::anyhow!("{}", e))?;
+                    vec![(chat_id, written)]
+                }
+                None => {
+                    let chat_ids: Vec<i64> = repo
+                        .max_message_ids()
+                        .await
+                        .map_err(|e| anyhow::anyhow!("{}", e))?
+                        .into_iter()
+                        .map(|(chat_id, _)| chat_id)
+                        .collect();
+                    mirror
+                        .rebuild_missing(&chat_ids)
+                        .await
+                        .map_err(|e| anyhow::anyhow!("{}", e))?
+                }
+            };
+            for (chat_id, written) in &rebuilt {
+                println!("  chat {}: {} message(s)", chat_id, written);
+            }
+            println!(
+                "Rebuilt {} mirror file(s) in {}",
+                rebuilt.len(),
+                mirror_dir.display()
+            );
+        }
+        CliCommand::ConfigValidate | CliCommand::ConfigCheck => {
+            if cmd == CliCommand::ConfigCheck {
+                println!("{}", cfg.describe());
+            }
+            // Load errors already stopped main(); this checks values that did parse.
+            let mut problems = cfg.validate();
+            match notifiers_from_config(cfg) {
+                Ok(notifiers) => {
+                    for notifier in &notifiers {
+                        println!("{} notifier: ok", notifier.name());
+                    }
+                }
+                Err(e) => problems.push(e.to_string()),
+            }
+            match HttpHeartbeat::from_config(cfg) {
+                Ok(Some(heartbeat)) => println!("{} heartbeat: ok", heartbeat.name()),
+                Ok(None) => {}
+                Err(e) => problems.push(e.to_string()),
+            }
+            match remote_storage_from_config(cfg) {
+                Ok(Some(remote)) => println!("{} remote storage: ok", remote.name()),
+                Ok(None) => {}
+                Err(e) => problems.push(e.to_string()),
+            }
+            if !problems.is_empty() {
+                for problem in &problems {
+                    eprintln!("  - {}", problem);
+                }
+                anyhow::bail!("{} configuration problem(s)", problems.len());
+            }
+            println!("Configuration OK");
+        }
+        CliCommand::MediaManifest { only_chat } => {
+            let repo = Arc::new(
+                open_archive(&data_path, cfg)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("SQLite connect failed: {}", e))?,
+            );
+            let service = MediaManifestService::new(repo, data_path.join("media"));
+            let count = service
+                .write_manifest(only_chat)
+                .await
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
+            println!(
+                "Wrote {} entries to {}",
+                count,
+                service.manifest_path().display()
+            );
+        }
+        CliCommand::Export {
+            target,
+            out,
+            anonymize,
+        } => {
+            let repo = Arc::new(
+                open_archive(&data_path, cfg)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("SQLite connect failed: {}", e))?,
+            );
+            let jsonl = jsonl_repo(&data_path, cfg);
+            let registry: Arc<dyn EntityRegistry> = match &jsonl {
+                Some(jsonl) => jsonl.clone(),
+                None => repo.clone(),
+            };
+            let service = ExportService::new(
+                message_repo(repo.clone(), jsonl, &data_path, cfg),
+                registry,
+                repo.clone(),
+                repo.clone(),
+                data_path.join("media"),
+                data_path.join("exports"),
+                cfg.anonymize_map.as_deref().map(PathBuf::from),
+            );
+            // Offline: own user id is unknown, so is_outgoing stays empty.
+            let opts = ExportOptions {
+                out,
+                me_id: None,
+                anonymize,
+                range: None,
+            };
+            let report = match target {
+                ExportTarget::Messages { chat_id, format } => {
+                    service.export_messages(chat_id, format, &opts).await
+                }
+                ExportTarget::Analysis { chat_id, format } => {
+                    service.export_analysis(chat_id, format, &opts).await
+                }
+                ExportTarget::Chat {
+                    chat_id,
+                    format,
+                    from,
+                    to,
+                } => {
+                    let offset = cfg.utc_offset_secs();
+                    let exporter = export::default_exporters(offset)
+                        .into_iter()
+                        .find(|e| e.extension() == format)
+                        .ok_or_else(|| anyhow::anyhow!("unsupported export format: {}", format))?;
+                    let chat = repo
+                        .get_known_chats()
+                        .await
+                        .map_err(|e| anyhow::anyhow!("{}", e))?
+                        .into_iter()
+                        .find(|c| c.id == chat_id)
+                        .ok_or_else(|| anyhow::anyhow!("chat {} is not in the archive", chat_id))?;
+                    let opts = ExportOptions {
+                        range: (from.is_some() || to.is_some())
+                            .then(|| activity::date_range(from, to, offset)),
+                        ..opts
+                    };
+                    service.export_chat(&chat, exporter.as_ref(), &opts).await
+                }
+            }
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+            println!("Wrote {} rows to {}", report.rows, report.path.display());
+            if let Some(mapping) = report.mapping_path {
+                println!("Pseudonym mapping (keep private): {}", mapping.display());
+            }
+        }
+        CliCommand::Ingest { file, chat_name } => {
+            let repo = Arc::new(
+                open_archive(&data_path, cfg)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("SQLite connect failed: {}", e))?,
+            );
+            let jsonl = jsonl_repo(&data_path, cfg);
+            let registry: Arc<dyn EntityRegistry> = match &jsonl {
+                Some(jsonl) => jsonl.clone(),
+                None => repo.clone(),
+            };
+            let service = IngestService::new(
+                message_repo(repo.clone(), jsonl, &data_path, cfg),
+                registry,
+                default_parsers(cfg.utc_offset_secs()),
+            );
+            let report = service
+                .ingest_file(&file, &chat_name)
+                .await
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
+            println!(
+                "Ingested {} message(s) into chat {} ({}); {} duplicate(s), {} skipped line(s)",
+                report.imported,
+                report.chat_id,
+                report.parser,
+                report.duplicates,
+                report.skipped.len()
+            );
+            for (line, reason) in report.skipped.iter().take(10) {
+                println!("  line {}: {}", line, reason);
+            }
+        }
+        CliCommand::Import {
+            file,
+            chat_id,
+            media,
+        } => {
+            let repo = Arc::new(
+                open_archive(&data_path, cfg)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("SQLite connect failed: {}", e))?,
+            );
+            let state = state_store(repo.clone(), &data_path, cfg)
+                .await
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
+            let service = import_service(
+                message_repo(repo.clone(), jsonl_repo(&data_path, cfg), &data_path, cfg),
+                &repo,
+                state,
+                &data_path,
+                cfg,
+            );
+            if media && !service.can_copy_media() {
+                println!("The archive is encrypted: exported media files are not copied.");
+            }
+            let report = service
+                .import_file(
+                    &file,
+                    &ImportOptions {
+                        chat_id,
+                        copy_media: media,
+                    },
+                )
+                .await
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
+            println!(
+                "Imported {} message(s) into '{}' ({}); {} already in the archive, {} event(s), {} skipped entr(ies)",
+                report.imported,
+                report.title,
+                report.chat_id,
+                report.duplicates,
+                report.events,
+                report.skipped.len()
+            );
+            if media {
+                println!(
+                    "Media: {} file(s) copied, {} not in the export",
+                    report.media_copied, report.media_missing
+                );
+            }
+            for (entry, reason) in report.skipped.iter().take(10) {
+                println!("  entry {}: {}", entry, reason);
+            }
+            println!(
+                "Next sync of this chat continues after message {}.",
+                report.last_message_id
+            );
+        }
+        CliCommand::Analyze {
+            chat_id,
+            latest,
+            auto_push,
+        } => {
+            let repo = Arc::new(
+                open_archive(&data_path, cfg)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("SQLite connect failed: {}", e))?,
+            );
+            let notifiers = notifiers_from_config(cfg).map_err(|e| anyhow::anyhow!("{}", e))?;
+            // Offline: no Telegram client, so digests are not posted there.
+            let service = analysis_service(
+                cfg,
+                &data_path,
+                &repo,
+                repo.clone(),
+                dead_letter_tracker(cfg, &data_path)
+                    .await?
+                    .map(|t| t as Arc<dyn TaskTrackerPort>),
+                notifiers,
+                None,
+            )?;
+            let reports = service
+                .analyze_chat(chat_id, latest)
+                .await
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
+            for report in &reports {
+                let items = report.result.action_items.len();
+                println!(
+                    "{} ({}, {} action item(s))",
+                    report.files_display(),
+                    report.result.week_group,
+                    items
+                );
+                if auto_push {
+                    let all: Vec<usize> = (0..items).collect();
+                    let push = service.push_action_items(&report.result, &all).await;
+                    println!(
+                        "  {} task(s) created, {} already in the tracker, {} failed",
+                        push.created,
+                        push.skipped,
+                        push.failed.len()
+                    );
+                    for (title, error) in &push.failed {
+                        println!("  failed: {} ({})", title, error);
+                    }
+                }
+            }
+            println!("Generated {} report(s) for chat {}", reports.len(), chat_id);
+        }
+        CliCommand::Rekey { batch } => {
+            let Some(secret) = archive_secret(&data_path, cfg, true)
+                .await
+                .map_err(|e| anyhow::anyhow!("{}", e))?
+            else {
+                anyhow::bail!(
+                    "rekey needs TG_SYNC_ENCRYPTION_KEY (or a terminal to ask for a passphrase)"
+                );
+            };
+            let repo = SqliteRepo::connect_encrypted(&data_path, &secret)
+                .await
+                .map_err(|e| anyhow::anyhow!("SQLite connect failed: {}", e))?;
+            let messages = repo
+                .rekey(batch)
+                .await
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
+            let cipher = repo
+                .cipher()
+                .ok_or_else(|| anyhow::anyhow!("archive opened without its key"))?;
+            let media = MediaWorker::encrypt_existing(&repo, &data_path.join("media"), &cipher)
+                .await
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
+            println!(
+                "Encrypted {} message(s) and {} media file(s)",
+                messages, media
+            );
+            if data_path.join(MIRROR_DIR).exists() {
+                println!(
+                    "Note: {} still holds plaintext JSONL mirror files; delete it to finish",
+                    data_path.join(MIRROR_DIR).display()
+                );
+            }
+        }
+        CliCommand::Watch {
+            mode: WatchMode::Foreground,
+        } => unreachable!("`watch` runs the Watcher from main"),
+        CliCommand::Watch {
+            mode: WatchMode::Detach,
+        } => {
+            let pid_path = data_path.join(WATCHER_PID_FILE);
+            if let Some(pid) = running_pid(&pid_path) {
+                anyhow::bail!("the watcher is already running (pid {})", pid);
+            }
+            std::fs::create_dir_all(&data_path)
+                .map_err(|e| anyhow::anyhow!("create data dir: {}", e))?;
+            let log_path = data_path.join("watcher.log");
+            let log = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&log_path)
+                .map_err(|e| anyhow::anyhow!("open {}: {}", log_path.display(), e))?;
+            let exe = std::env::current_exe()
+                .map_err(|e| anyhow::anyhow!("locate tg-sync executable: {}", e))?;
+            let mut command = std::process::Command::new(exe);
+            command
+                .arg("watch")
+                .stdin(std::process::Stdio::null())
+                .stdout(log.try_clone()?)
+                .stderr(log);
+            if force {
+                command.arg("--force");
+            }
+            if let Some(name) = &cfg.profile {
+                command.arg("--profile").arg(name);
+            }
+            // Own process group: Ctrl+C in this terminal doesn't reach it.
+            #[cfg(unix)]
+            {
+                use std::os::unix::process::CommandExt;
+                command.process_group(0);
+            }
+            let child = command
+                .spawn()
+                .map_err(|e| anyhow::anyhow!("start background watcher: {}", e))?;
+            println!(
+                "Watcher started in the background (pid {}); log: {}",
+                child.id(),
+                log_path.display()
+            );
+            println!(
+                "Check it with `tg-sync watch --status`; stop it with `kill {}`.",
+                child.id()
+            );
+        }
+        CliCommand::Watch {
+            mode: WatchMode::Status,
+        } => {
+            let repo = open_archive(&data_path, cfg)
+                .await
+                .map_err(|e| anyhow::anyhow!("SQLite connect failed: {}", e))?;
+            let mut status = WatcherStatus::load(&repo)
+                .await
+                .map_err(|e| anyhow::anyhow!("{}", e))?
+                .unwrap_or_default();
+            // The stored flag outlives a killed process: trust live processes only (the PID
+            // file of `tg-sync watch`, or the menu instance that stored the status).
+            let pid = running_pid(&data_path.join(WATCHER_PID_FILE))
+                .or(Some(status.pid).filter(|&pid| status.running && pid_alive(pid)));
+            status.running = pid.is_some();
+            status.pid = pid.unwrap_or(status.pid);
+            println!("{}", status);
+        }
+        CliCommand::Audit { fix } => {
+            let repo = Arc::new(
+                open_archive(&data_path, cfg)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("SQLite connect failed: {}", e))?,
+            );
+            let state = state_store(repo.clone(), &data_path, cfg)
+                .await
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
+            let service = AuditService::new(repo.clone(), repo, state, data_path.join("media"));
+            let report = service
+                .run(fix)
+                .await
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
+            println!("{}", report);
+            if report.unresolved() > 0 {
+                anyhow::bail!(
+                    "archive audit found {} unresolved problem(s)",
+                    report.unresolved()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Import of Telegram Desktop exports through `repo`. Media files are only copied into a
+/// plaintext archive: an encrypted one keeps its media encrypted.
+fn import_service(
+    repo: Arc<dyn RepoPort>,
+    sqlite: &Arc<SqliteRepo>,
+    state: Arc<dyn StatePort>,
+    data_path: &Path,
+    cfg: &AppConfig,
+) -> ImportService {
+    let media_dir = sqlite.cipher().is_none().then(|| data_path.join("media"));
+    ImportService::new(
+        repo,
+        state,
+        sqlite.clone(),
+        media_dir,
+        Box::new(DesktopJsonParser::new(cfg.utc_offset_secs())),
+    )
+}
+
+/// Open the archive, with its key when TG_SYNC_ENCRYPTION_KEY is set. An encrypted archive
+/// without the variable asks for the passphrase on a terminal.
+async fn open_archive(data_path: &Path, cfg: &AppConfig) -> Result<SqliteRepo, DomainError> {
+    match archive_secret(data_path, cfg, false).await? {
+        Some(secret) => SqliteRepo::connect_encrypted(data_path, &secret).await,
+        None => SqliteRepo::connect(data_path).await,
+    }
+}
+
+/// Archive key: TG_SYNC_ENCRYPTION_KEY, else a passphrase prompt when the archive is encrypted
+/// (or `encrypting` it) and stdin is a terminal. A new passphrase is asked for twice.
+async fn archive_secret(
+    data_path: &Path,
+    cfg: &AppConfig,
+    encrypting: bool,
+) -> Result<Option<String>, DomainError> {
+    if let Some(key) = cfg.encryption_key() {
+        return Ok(Some(key));
+    }
+    let encrypted = SqliteRepo::is_encrypted(data_path).await?;
+    if !(encrypted || encrypting) || !std::io::stdin().is_terminal() {
+        return Ok(None);
+    }
+    let mut prompt = inquire::Password::new("Archive passphrase:");
+    if encrypted {
+        prompt = prompt.without_confirmation();
+    }
+    prompt
+        .prompt()
+        .map(Some)
+        .map_err(|e| DomainError::Crypto(format!("input: {}", e)))
+}
+
+/// Effective configuration plus the problems `config validate` would report, for the menu.
+fn config_report(cfg: &AppConfig) -> String {
+    let mut report = cfg.describe();
+    for problem in cfg.validate() {
+        report.push_str(&format!("⚠️  {}\n", problem));
+    }
+    report
+}
+
+/// Trello behind the dead-letter queue, when TRELLO_KEY, TRELLO_TOKEN and TRELLO_LIST_ID are set.
+/// Bad list, board or label ids fail here, at startup.
+async fn dead_letter_tracker(
+    cfg: &AppConfig,
+    data_path: &Path,
+) -> anyhow::Result<Option<Arc<DeadLetterTracker>>> {
+    if !cfg.is_trello_configured() {
+        return Ok(None);
+    }
+    info!("Trello task tracker enabled (TRELLO_KEY, TRELLO_TOKEN, TRELLO_LIST_ID)");
+    let mut trello = TrelloAdapter::new(
+        cfg.trello_key().unwrap_or_default(),
+        cfg.trello_token().unwrap_or_default(),
+        cfg.trello_board_id(),
+        cfg.trello_list_id().unwrap_or_default(),
+    );
+    if let Some(proxy) = cfg.http_proxy().map_err(|e| anyhow::anyhow!("{}", e))? {
+        trello = trello.with_proxy(proxy);
+    }
+    if let Some(label_id) = cfg.trello_label_id() {
+        trello = trello.with_label(label_id);
+    }
+    if let Some(ms) = cfg.trello_request_delay_ms {
+        trello = trello.with_request_delay(Duration::from_millis(ms));
+    }
+    trello
+        .validate()
+        .await
+        .map_err(|e| anyhow::anyhow!("Trello check failed: {}", e))?;
+    let trello: Arc<dyn TaskTrackerPort> = Arc::new(trello);
+    Ok(Some(Arc::new(DeadLetterTracker::new(
+        trello,
+        data_path.join(DEAD_LETTER_FILE),
+    ))))
+}
+
+/// AI analysis wiring shared by the menu and the `analyze` command. Without `tg`, digests are
+/// never posted to Telegram.
+fn analysis_service(
+    cfg: &AppConfig,
+    data_path: &Path,
+    sqlite_repo: &Arc<SqliteRepo>,
+    chats: Arc<dyn RepoPort>,
+    task_tracker: Option<Arc<dyn TaskTrackerPort>>,
+    notifiers: Vec<Arc<dyn NotifierPort>>,
+    tg: Option<Arc<dyn TgGateway>>,
+) -> anyhow::Result<AnalysisService> {
     let ai_adapter: Arc<dyn AiPort> = if cfg.is_ai_configured() {
+        let provider = cfg.ai_provider();
         info!(
+            ?provider,
             model = %cfg.ai_model_or_default(),
             url = %cfg.ai_api_url_or_default(),
-            "AI analysis enabled with OpenAI adapter"
+            "AI analysis enabled"
         );
-        Arc::new(OpenAiAdapter::new(
+        // Custom prompts from data/prompts/*.md; a malformed template stops startup here.
+        let prompts = PromptTemplates::load(&data_path.join("prompts"), cfg.ai_language())
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let (url, key, model, retries) = (
             cfg.ai_api_url_or_default(),
             cfg.ai_api_key().unwrap_or_default(),
             cfg.ai_model_or_default(),
-        ))
+            cfg.ai_max_retries_or_default(),
+        );
+        let proxy = cfg.http_proxy().map_err(|e| anyhow::anyhow!("{}", e))?;
+        match provider {
+            AiProvider::OpenAi => {
+                let adapter = OpenAiAdapter::new(url, key, model, retries, prompts);
+                Arc::new(match proxy {
+                    Some(proxy) => adapter.with_proxy(proxy),
+                    None => adapter,
+                })
+            }
+            AiProvider::Anthropic => {
+                let adapter = AnthropicAdapter::new(url, key, model, retries, prompts);
+                Arc::new(match proxy {
+                    Some(proxy) => adapter.with_proxy(proxy),
+                    None => adapter,
+                })
+            }
+        }
     } else {
         warn!("TG_SYNC_AI_API_KEY not set, using mock AI adapter");
         Arc::new(MockAiAdapter::new())
     };
 
-    let reports_dir = data_path.join("reports");
-    let task_tracker: Option<Arc<dyn TaskTrackerPort>> = if cfg.is_trello_configured() {
-        info!("Trello task tracker enabled (TRELLO_KEY, TRELLO_TOKEN, TRELLO_LIST_ID)");
-        Some(Arc::new(TrelloAdapter::new(
-            cfg.trello_key().unwrap_or_default(),
-            cfg.trello_token().unwrap_or_default(),
-            cfg.trello_board_id().unwrap_or_default(),
-            cfg.trello_list_id().unwrap_or_default(),
-        )))
+    // --- Optional anonymization of LLM context (TG_SYNC_AI_ANONYMIZE, TG_SYNC_ANONYMIZE_MAP) ---
+    let anonymizer = if cfg.ai_anonymize_enabled() {
+        let anonymizer = match cfg.anonymize_map.as_deref() {
+            Some(path) => Anonymizer::open(path).map_err(|e| anyhow::anyhow!("{}", e))?,
+            None => Anonymizer::new(),
+        };
+        info!("AI context anonymization enabled");
+        Some(Arc::new(std::sync::Mutex::new(anonymizer)))
     } else {
         None
     };
-    let analysis_service = Arc::new(AnalysisService::new(
+    Ok(AnalysisService::new(
         ai_adapter,
-        analysis_log,
-        reports_dir,
+        Arc::clone(sqlite_repo) as Arc<dyn AnalysisLogPort>,
+        chats,
+        Arc::clone(sqlite_repo) as Arc<dyn SettingsPort>,
+        data_path.join("reports"),
         task_tracker,
-    ));
-
-    let input_port: Arc<dyn InputPort> = Arc::new(TuiInputPort::new(
-        Arc::clone(&tg),
-        Arc::clone(&repo),
-        Arc::clone(&sync_service),
-        Arc::clone(&watcher_service),
-        Arc::clone(&analysis_service),
-    ));
-
-    // --- Run (main menu -> Full Backup / Watcher / AI Analysis) ---
-    input_port
-        .run()
-        .await
-        .map_err(|e| anyhow::anyhow!("{}", e))?;
-
-    Ok(())
+        cfg.utc_offset_secs(),
+        anonymizer,
+        notifiers,
+        cfg.ai_concurrency_or_default(),
+        ChunkBudget::new(
+            cfg.ai_max_context_tokens_or_default(),
+            cfg.ai_model_or_default(),
+        ),
+        cfg.ai_price_table(),
+        tg,
+        cfg.digest_to_telegram_enabled(),
+        cfg.alert_chat_id(),
+    )
+    .with_report_formats(cfg.report_formats().map_err(|e| anyhow::anyhow!("{}", e))?)
+    .with_reply_context(cfg.ai_reply_context_enabled()))
 }
 
 /// Create grammers Client with persistent session storage.
 /// Loads existing session from `session_path` if present; otherwise a new session is created
 /// and will be saved after login. Requires TG_SYNC_API_ID (and TG_SYNC_API_HASH for login).
+/// With TG_SYNC_PROXY_URL, connects through that SOCKS5 proxy after checking it answers.
 async fn create_telegram_client(
-    cfg: &tg_sync::shared::config::AppConfig,
+    cfg: &AppConfig,
     session_path: &std::path::Path,
 ) -> anyhow::Result<grammers_client::Client> {
-    let api_id = cfg
-        .api_id
-        .or_else(|| {
-            std::env::var("TG_SYNC_API_ID")
-                .ok()
-                .and_then(|s| s.parse().ok())
-        })
-        .unwrap_or(0);
+    let api_id = cfg.telegram_api_id().unwrap_or(0);
 
     if api_id == 0 {
         anyhow::bail!(
@@ -221,7 +1282,25 @@ async fn create_telegram_client(
 
     let session = tg_sync::adapters::telegram::session::open_file_session(session_path).await?;
     let session = Arc::new(session);
-    let pool = grammers_client::SenderPool::new(session, api_id);
+    let proxy = cfg.telegram_proxy().map_err(|e| anyhow::anyhow!("{}", e))?;
+    let pool = match proxy {
+        Some(proxy) => {
+            proxy
+                .check_reachable(PROXY_CONNECT_TIMEOUT)
+                .await
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
+            info!(proxy = %proxy.address(), "connecting to Telegram through SOCKS5 proxy");
+            grammers_client::SenderPool::with_configuration(
+                session,
+                api_id,
+                grammers_mtsender::ConnectionParams {
+                    proxy_url: Some(proxy.url().to_string()),
+                    ..Default::default()
+                },
+            )
+        }
+        None => grammers_client::SenderPool::new(session, api_id),
+    };
     let handle = pool.handle.clone();
     tokio::spawn(async move {
         pool.runner.run().await;
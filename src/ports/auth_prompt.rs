@@ -0,0 +1,26 @@
+//! Auth prompt outbound port. Collect login input (phone, code, 2FA password) from the user.
+
+use crate::domain::{DomainError, LoginMethod, QrToken};
+
+/// Port for asking the user for login credentials.
+///
+/// Implemented by the interactive TUI prompt and by a headless adapter (environment variables
+/// plus a code file or one-shot HTTP endpoint) for servers without a terminal.
+#[async_trait::async_trait]
+pub trait AuthPromptPort: Send + Sync {
+    /// Phone + code or QR code. Asked once, before anything else.
+    async fn login_method(&self) -> Result<LoginMethod, DomainError>;
+
+    /// Show a QR login token; `qr` is the token URL rendered for the terminal. Called again
+    /// with a new token when the previous one expired.
+    async fn show_qr(&self, token: &QrToken, qr: &str) -> Result<(), DomainError>;
+
+    /// Phone number in international format (e.g. "+1234567890").
+    async fn phone(&self) -> Result<String, DomainError>;
+
+    /// Login code Telegram sent to the app or by SMS. Called after the code was requested.
+    async fn login_code(&self) -> Result<String, DomainError>;
+
+    /// Two-step verification password. `hint` is the hint set on the account.
+    async fn password(&self, hint: Option<&str>) -> Result<String, DomainError>;
+}
@@ -0,0 +1,39 @@
+//! Chat exporter outbound port. Render an archived chat into a document format (HTML, ...).
+
+use crate::domain::{DomainError, ExportChat, ExportEvent, ExportMessage};
+use std::io::Write;
+
+/// Port for rendering a chat export.
+///
+/// The export use case streams the chat from the repository and hands prepared messages to
+/// the writer in batches, oldest first, so exporters never hold a whole chat in memory.
+pub trait ExporterPort: Send + Sync {
+    /// Format name for menus and logs (e.g. "HTML").
+    fn name(&self) -> &'static str;
+
+    /// File extension of the output, without the dot.
+    fn extension(&self) -> &'static str;
+
+    /// Start an export of `chat` into `out` (writes any preamble).
+    ///
+    /// # Errors
+    /// Returns `DomainError::Repo` if the output cannot be written.
+    fn begin(
+        &self,
+        chat: &ExportChat,
+        out: Box<dyn Write + Send>,
+    ) -> Result<Box<dyn ChatExportWriter>, DomainError>;
+}
+
+/// One export in progress, created by [`ExporterPort::begin`].
+pub trait ChatExportWriter: Send {
+    /// Write the next messages (oldest first).
+    fn write_batch(&mut self, messages: &[ExportMessage]) -> Result<(), DomainError>;
+
+    /// Write a service event (join, leave, title change, ...) between the messages written
+    /// before and after it.
+    fn write_event(&mut self, event: &ExportEvent) -> Result<(), DomainError>;
+
+    /// Write the closing part and flush the output.
+    fn finish(self: Box<Self>) -> Result<(), DomainError>;
+}
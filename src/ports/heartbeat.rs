@@ -0,0 +1,20 @@
+//! Heartbeat outbound port. Tells an outside monitor that the watcher is alive.
+
+use crate::domain::DomainError;
+
+/// Port for liveness pings of the watcher (e.g. a healthchecks.io URL).
+///
+/// Pinged once per watcher cycle in which no chat failed; a monitor that stops hearing from
+/// it raises the alarm. Callers treat pings as best effort: a failed ping is logged and never
+/// affects syncing.
+#[async_trait::async_trait]
+pub trait HeartbeatPort: Send + Sync {
+    /// Short name for logs (e.g. "http").
+    fn name(&self) -> &'static str;
+
+    /// Report a completed cycle. `summary` is one line about it (chats watched, ...).
+    ///
+    /// # Errors
+    /// Returns `DomainError::Notify` if the monitor could not be reached.
+    async fn ping(&self, summary: &str) -> Result<(), DomainError>;
+}
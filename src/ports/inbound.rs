@@ -5,7 +5,8 @@ use crate::domain::DomainError;
 /// Input port: UI/CLI invokes application use cases.
 #[async_trait::async_trait]
 pub trait InputPort: Send + Sync {
-    /// Run the main menu and dispatch to the selected mode (Full Backup, Watcher, AI Analysis).
+    /// Run the main menu and dispatch to the selected mode (Full Backup, Watcher, AI Analysis)
+    /// until the user quits.
     async fn run(&self) -> Result<(), DomainError>;
 
     /// Run interactive sync flow (select chats, sync, process). Used internally by Full Backup.
@@ -3,13 +3,26 @@
 //! - Inbound: Called by UI/adapter into the application
 //! - Outbound: Called by application into infrastructure
 
+pub mod auth_prompt;
+pub mod exporter;
+pub mod heartbeat;
 pub mod inbound;
+pub mod notifier;
 pub mod outbound;
+pub mod progress;
+pub mod remote_storage;
 pub mod task_tracker;
 
+pub use auth_prompt::AuthPromptPort;
+pub use exporter::{ChatExportWriter, ExporterPort};
+pub use heartbeat::HeartbeatPort;
 pub use inbound::InputPort;
+pub use notifier::NotifierPort;
 pub use outbound::{
-    AiPort, AnalysisLogPort, AuthPort, EntityRegistry, ProcessorPort, RepoPort, StatePort,
-    TgGateway,
+    AiPort, AnalysisLogPort, ArchiveAuditPort, AuthPort, EntityRegistry, FragmentParser,
+    HistoryParser, MediaIndexPort, MediaQueuePort, ProcessorPort, RepoPort, SettingsPort,
+    StatePort, TgGateway, WatchRulePort,
 };
-pub use task_tracker::TaskTrackerPort;
+pub use progress::ProgressPort;
+pub use remote_storage::RemoteStoragePort;
+pub use task_tracker::{TaskDeadLetterPort, TaskTrackerPort};
@@ -0,0 +1,26 @@
+//! Notifier outbound port. Deliver digests and events outside the app (e.g. email, webhooks).
+
+use crate::domain::{DomainError, NotificationEvent};
+
+/// Port for delivering notifications to the user.
+///
+/// Implemented by adapters (e.g. SMTP email, webhooks, Telegram). Callers treat delivery as
+/// best effort: a failed notification is retried once, then logged, and never fails the
+/// analysis or sync that caused it.
+#[async_trait::async_trait]
+pub trait NotifierPort: Send + Sync {
+    /// Short name for logs (e.g. "email").
+    fn name(&self) -> &'static str;
+
+    /// Whether this notifier wants `event`. Default: every event.
+    fn accepts(&self, event: &NotificationEvent) -> bool {
+        let _ = event;
+        true
+    }
+
+    /// Deliver one event.
+    ///
+    /// # Errors
+    /// Returns `DomainError::Notify` if the event could not be delivered.
+    async fn notify(&self, event: &NotificationEvent) -> Result<(), DomainError>;
+}
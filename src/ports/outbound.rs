@@ -2,8 +2,14 @@
 //!
 //! Implemented by adapters.
 
-use crate::domain::{Chat, DomainError, MediaReference, Message, SignInResult};
-use std::collections::HashSet;
+use crate::domain::{
+    ActivityBin, ActivityBucket, ArchiveStats, Chat, ChatEvent, ChatPurge, ChatSettings, ChatStats,
+    DatabaseMaintenance, DiscussionLink, DomainError, ForumTopic, ImportedHistory, MediaFile,
+    MediaReference, MediaStatus, Message, ParsedFragment, QrLoginStatus, QrToken, SearchHit,
+    SignInResult, TimeRange, User, WatchRule, WatchRuleKind,
+};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
 
 /// Telegram API gateway. Fetch dialogs, messages, media.
 #[async_trait::async_trait]
@@ -37,6 +43,63 @@ pub trait TgGateway: Send + Sync {
 
     /// Send a text message to a chat (e.g. Saved Messages for alerts). `chat_id` is the dialog id (e.g. own user id for Saved Messages).
     async fn send_message(&self, chat_id: i64, text: &str) -> Result<(), DomainError>;
+
+    /// Users that came with the message batches fetched since the last call (the call drains
+    /// them). Used by sync to keep sender names. Gateways without user data return none.
+    async fn get_users_from_batch(&self) -> Result<Vec<User>, DomainError> {
+        Ok(Vec::new())
+    }
+
+    /// Service messages (joins, leaves, title changes, ...) from the message batches fetched
+    /// since the last call, which drains them. `get_messages` never returns them as messages.
+    async fn get_events_from_batch(&self) -> Result<Vec<ChatEvent>, DomainError> {
+        Ok(Vec::new())
+    }
+
+    /// Topics of a forum supergroup (`Chat::is_forum`). Chats without topics, and gateways
+    /// that can't list them, return none.
+    async fn get_forum_topics(&self, _chat_id: i64) -> Result<Vec<ForumTopic>, DomainError> {
+        Ok(Vec::new())
+    }
+
+    /// Start a takeout session: until [`Self::end_takeout`], history requests and media
+    /// downloads go through it and get Telegram's relaxed export flood limits. The user may
+    /// have to confirm it in another client first ([`DomainError::TakeoutDelay`]).
+    async fn begin_takeout(&self) -> Result<(), DomainError> {
+        Err(DomainError::TgGateway(
+            "takeout sessions are not supported by this gateway".to_string(),
+        ))
+    }
+
+    /// Where channel post `msg_id` is discussed: its copy in the channel's discussion group.
+    /// None when the channel has no discussion group or the post has no comment thread.
+    async fn get_discussion_message(
+        &self,
+        _channel_id: i64,
+        _msg_id: i32,
+    ) -> Result<Option<DiscussionLink>, DomainError> {
+        Ok(None)
+    }
+
+    /// Finish the takeout session (`success` = the export completed). No-op without one.
+    async fn end_takeout(&self, _success: bool) -> Result<(), DomainError> {
+        Ok(())
+    }
+
+    /// Look up one chat by `@username`, t.me link or Bot API id (see
+    /// [`ChatReference`](crate::shared::chat_link::ChatReference)), joined or not. Bots can't
+    /// list their dialogs, so their chats are configured and resolved one by one.
+    async fn resolve_chat(&self, reference: &str) -> Result<Chat, DomainError> {
+        Err(DomainError::TgGateway(format!(
+            "cannot resolve chat '{}': not supported by this gateway",
+            reference
+        )))
+    }
+
+    /// True when signed in as a bot: no dialog list of its own and no Saved Messages.
+    fn is_bot(&self) -> bool {
+        false
+    }
 }
 
 /// Repository port. Persist and load chat messages.
@@ -45,6 +108,9 @@ pub trait RepoPort: Send + Sync {
     /// Save messages (append/merge). Implementations use INSERT OR IGNORE / dedupe by message id.
     async fn save_messages(&self, chat_id: i64, messages: &[Message]) -> Result<(), DomainError>;
 
+    /// Insert or refresh message senders. Reads fill `Message::sender_name` from them.
+    async fn save_users(&self, users: &[User]) -> Result<(), DomainError>;
+
     /// Load messages for a chat, newest first. Use limit/offset for pagination.
     async fn get_messages(
         &self,
@@ -53,6 +119,66 @@ pub trait RepoPort: Send + Sync {
         offset: u32,
     ) -> Result<Vec<Message>, DomainError>;
 
+    /// Load messages with id > `after_id`, oldest first (keyset page). Used to stream whole
+    /// chats in bounded batches; an empty page means the end.
+    async fn get_messages_after(
+        &self,
+        chat_id: i64,
+        after_id: i32,
+        limit: u32,
+    ) -> Result<Vec<Message>, DomainError>;
+
+    /// Load messages dated within `range` (`from` included, `to` excluded), oldest first: by
+    /// date, then id. `limit`/`offset` page through them; pages stay aligned while no message
+    /// is added inside the range (use [`Self::get_messages_after`] for a growing chat).
+    async fn get_messages_in_range(
+        &self,
+        chat_id: i64,
+        range: TimeRange,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<Message>, DomainError>;
+
+    /// Load the stored messages of a chat with the given ids, oldest first. Ids that are not
+    /// stored are skipped. Used to compare re-fetched messages against the archive (edits).
+    async fn get_messages_by_ids(
+        &self,
+        chat_id: i64,
+        ids: &[i32],
+    ) -> Result<Vec<Message>, DomainError>;
+
+    /// Stored messages of a chat by id, for resolving reply targets of a batch at once. `ids`
+    /// may repeat; ids not stored (deleted, or older than the archive) are absent.
+    async fn get_messages_map(
+        &self,
+        chat_id: i64,
+        ids: &[i32],
+    ) -> Result<HashMap<i32, Message>, DomainError> {
+        let mut ids = ids.to_vec();
+        ids.sort_unstable();
+        ids.dedup();
+        if ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        Ok(self
+            .get_messages_by_ids(chat_id, &ids)
+            .await?
+            .into_iter()
+            .map(|m| (m.id, m))
+            .collect())
+    }
+
+    /// Full-text search over message text, best match first. Words in `query` must all
+    /// match (a trailing `*` matches a prefix); `chat_id` narrows to one chat. `offset` pages
+    /// through the ranked results. An empty query returns nothing.
+    async fn search_messages(
+        &self,
+        query: &str,
+        chat_id: Option<i64>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<SearchHit>, DomainError>;
+
     /// Get the set of chat IDs that are blacklisted (excluded from backup).
     async fn get_blacklisted_ids(&self) -> Result<HashSet<i64>, DomainError>;
 
@@ -64,6 +190,79 @@ pub trait RepoPort: Send + Sync {
 
     /// Sync the target list with the given set. Replaces the stored targets with `ids`.
     async fn update_targets(&self, ids: HashSet<i64>) -> Result<(), DomainError>;
+
+    /// Per-chat sync overrides of `chat_id`; no overrides when none are stored.
+    async fn get_chat_settings(&self, chat_id: i64) -> Result<ChatSettings, DomainError>;
+
+    /// Every chat with stored overrides, by chat id.
+    async fn list_chat_settings(&self) -> Result<Vec<ChatSettings>, DomainError>;
+
+    /// Store the overrides of `settings.chat_id`. Settings without overrides remove the entry.
+    async fn set_chat_settings(&self, settings: &ChatSettings) -> Result<(), DomainError>;
+
+    /// Message counts per bucket within `range`, ordered by bucket. Aggregated in SQL.
+    ///
+    /// `utc_offset_secs` shifts bucket boundaries to the configured timezone (local midnight
+    /// for `Day`, local hour for `HourOfDay`). Empty buckets are omitted.
+    async fn get_activity_histogram(
+        &self,
+        chat_id: i64,
+        bucket: ActivityBucket,
+        range: TimeRange,
+        utc_offset_secs: i32,
+    ) -> Result<Vec<ActivityBin>, DomainError>;
+
+    /// Record a chat that exists only in the archive (e.g. an ingested fragment), so it has a
+    /// title even though no Telegram dialog backs it. Re-registering updates the title.
+    async fn register_synthetic_chat(&self, chat: &Chat) -> Result<(), DomainError>;
+
+    /// Record dialogs as listed by Telegram: inserts new chats and refreshes title, username,
+    /// kind and approximate size of known ones. A changed title is kept as a previous title.
+    async fn upsert_chats(&self, chats: &[Chat]) -> Result<(), DomainError>;
+
+    /// Replace the stored topic titles of a forum chat (used to label messages by topic).
+    async fn save_forum_topics(
+        &self,
+        chat_id: i64,
+        topics: &[ForumTopic],
+    ) -> Result<(), DomainError>;
+
+    /// Insert or refresh service events (keyed by chat and service message id).
+    async fn save_events(&self, events: &[ChatEvent]) -> Result<(), DomainError>;
+
+    /// Stored service events of a chat, oldest first.
+    async fn get_events(&self, chat_id: i64) -> Result<Vec<ChatEvent>, DomainError>;
+
+    /// In a discussion group, give replies (and replies to replies) the channel post of the
+    /// message they answer, starting from the post copies. Returns the number of messages
+    /// linked.
+    async fn link_channel_comments(&self, chat_id: i64) -> Result<u64, DomainError>;
+
+    /// Archived comments on posts `post_ids` of channel `channel_id`, from any synced
+    /// discussion group of it: by post, then oldest first. The post copies are not included.
+    async fn get_channel_comments(
+        &self,
+        channel_id: i64,
+        post_ids: &[i32],
+    ) -> Result<Vec<Message>, DomainError>;
+
+    /// Every chat the archive has a title for (listed dialogs and synthetic chats), by title.
+    /// Still available after the dialog is gone from Telegram.
+    async fn get_known_chats(&self) -> Result<Vec<Chat>, DomainError>;
+
+    /// Stored totals of one chat: message count, oldest/newest date, media references per
+    /// type and downloaded media files. Answered by indexed aggregates, not by loading rows.
+    async fn get_chat_stats(&self, chat_id: i64) -> Result<ChatStats, DomainError>;
+
+    /// [`Self::get_chat_stats`] for every chat with stored messages (by chat id), plus the
+    /// database size.
+    async fn get_global_stats(&self) -> Result<ArchiveStats, DomainError>;
+
+    /// Delete everything stored for one chat in a single transaction: messages, events,
+    /// topics, analysis log, chunk summaries, tracker tasks, media index and queue rows, watch
+    /// settings and the chat row. AI usage rows are kept (cost history). Media files on disk
+    /// are not touched; their paths are returned for the caller to remove.
+    async fn delete_chat_data(&self, chat_id: i64) -> Result<ChatPurge, DomainError>;
 }
 
 /// State port. Track last synced message ID per chat for incremental sync.
@@ -74,6 +273,23 @@ pub trait StatePort: Send + Sync {
 
     /// Update last message ID after successful save.
     async fn set_last_message_id(&self, chat_id: i64, message_id: i32) -> Result<(), DomainError>;
+
+    /// Lowest message ID stored by a downward pass that has not reached the bottom yet
+    /// (older history below it is still missing). Returns 0 if none.
+    async fn get_backfill_low_id(&self, chat_id: i64) -> Result<i32, DomainError>;
+
+    /// Record how far down the running pass got; 0 once it is complete.
+    async fn set_backfill_low_id(&self, chat_id: i64, message_id: i32) -> Result<(), DomainError>;
+
+    /// True when a bounded sync (date range or topic selection) stored messages above the
+    /// checkpoint but left some out, so the checkpoint is deliberately behind them.
+    async fn has_sync_gap(&self, chat_id: i64) -> Result<bool, DomainError>;
+
+    /// Record the gap, or clear it once an unbounded sync has paged down to the checkpoint.
+    async fn set_sync_gap(&self, chat_id: i64, gap: bool) -> Result<(), DomainError>;
+
+    /// Forget the checkpoint (and low-water mark, gap) of a chat; the next sync starts from scratch.
+    async fn clear(&self, chat_id: i64) -> Result<(), DomainError>;
 }
 
 /// Authentication port. Check auth state and perform login/2FA via Telegram.
@@ -90,6 +306,17 @@ pub trait AuthPort: Send + Sync {
 
     /// Complete 2FA after sign_in returned PasswordRequired. Call once per flow.
     async fn check_password(&self, password: &[u8]) -> Result<(), DomainError>;
+
+    /// Start a QR login. The returned URL must be shown as a QR code and scanned from a
+    /// logged-in Telegram app; call again once the token expires.
+    async fn request_qr_login(&self, api_id: i32, api_hash: &str) -> Result<QrToken, DomainError>;
+
+    /// Check whether the QR token was scanned. `Accepted(PasswordRequired)` must be followed
+    /// by check_password.
+    async fn poll_qr_login(&self) -> Result<QrLoginStatus, DomainError>;
+
+    /// Sign in as a bot with the token from @BotFather. Bots have no 2FA.
+    async fn sign_in_bot(&self, token: &str, api_hash: &str) -> Result<(), DomainError>;
 }
 
 /// Processor port. Invoke external tool (e.g. Chatpack) on archived data.
@@ -103,6 +330,27 @@ pub trait ProcessorPort: Send + Sync {
     ) -> Result<(), DomainError>;
 }
 
+/// Chat fragment parser for `tg-sync ingest`. One implementation per file format; the first
+/// parser whose [`FragmentParser::detect`] accepts the content is used.
+pub trait FragmentParser: Send + Sync {
+    /// Short format name for reports (e.g. `desktop-json`).
+    fn name(&self) -> &'static str;
+
+    /// Cheap sniff of the file content.
+    fn detect(&self, content: &str) -> bool;
+
+    /// Parse the whole fragment. Malformed lines are reported in `skipped`, not as errors;
+    /// `Err` means the file as a whole is unusable.
+    fn parse(&self, content: &str) -> Result<ParsedFragment, DomainError>;
+}
+
+/// Chat history export parser for `tg-sync import` (Telegram Desktop's `result.json`).
+pub trait HistoryParser: Send + Sync {
+    /// Parse the whole export. Unusable entries are reported in `skipped`; `Err` means the
+    /// file as a whole is unusable.
+    fn parse_history(&self, content: &str) -> Result<ImportedHistory, DomainError>;
+}
+
 /// Audit §6.2: Persistent entity registry for access_hash caching.
 /// Stores (peer_id, access_hash) to avoid re-iterating dialogs (FLOOD_WAIT risk).
 #[async_trait::async_trait]
@@ -110,6 +358,9 @@ pub trait EntityRegistry: Send + Sync {
     /// Get cached access_hash for a peer. Returns None if not cached.
     async fn get_access_hash(&self, peer_id: i64) -> Result<Option<i64>, DomainError>;
 
+    /// Get the cached (access_hash, peer_type) of a peer. Returns None if not cached.
+    async fn get_entity(&self, peer_id: i64) -> Result<Option<(i64, String)>, DomainError>;
+
     /// Save or update an entity's access_hash in the registry.
     async fn save_entity(
         &self,
@@ -118,21 +369,200 @@ pub trait EntityRegistry: Send + Sync {
         peer_type: &str,
         username: Option<&str>,
     ) -> Result<(), DomainError>;
+
+    /// Get the cached username for a peer. Returns None if unknown or the peer has none.
+    async fn get_username(&self, peer_id: i64) -> Result<Option<String>, DomainError>;
+}
+
+/// Watcher keyword rules (watch_rules table). Implemented by `SqliteRepo`.
+#[async_trait::async_trait]
+pub trait WatchRulePort: Send + Sync {
+    /// All rules, global ones first, then by chat and insertion order.
+    async fn list_watch_rules(&self) -> Result<Vec<WatchRule>, DomainError>;
+
+    /// Add an enabled rule (re-enables an identical one). Returns the rule id.
+    async fn add_watch_rule(
+        &self,
+        chat_id: i64,
+        pattern: &str,
+        is_regex: bool,
+    ) -> Result<i64, DomainError>;
+
+    /// Delete a rule. Returns false when no rule has this id.
+    async fn remove_watch_rule(&self, id: i64) -> Result<bool, DomainError>;
+
+    /// Enable or disable a rule without deleting it.
+    async fn set_watch_rule_enabled(&self, id: i64, enabled: bool) -> Result<(), DomainError>;
+
+    /// Turn a mention or reply rule of `chat_id` (0 = global) on or off, adding it when
+    /// missing. Returns the rule id.
+    async fn set_watch_trigger(
+        &self,
+        chat_id: i64,
+        kind: WatchRuleKind,
+        enabled: bool,
+    ) -> Result<i64, DomainError>;
+}
+
+/// Persistent key/value settings (settings table). Implemented by `SqliteRepo`.
+#[async_trait::async_trait]
+pub trait SettingsPort: Send + Sync {
+    /// Stored value for `key`, or None when unset.
+    async fn get_setting(&self, key: &str) -> Result<Option<String>, DomainError>;
+
+    /// Store `value` under `key`; `None` removes the key.
+    async fn set_setting(&self, key: &str, value: Option<&str>) -> Result<(), DomainError>;
+}
+
+/// Media index: one row per media file (media_files table) with path, size, hash and status.
+/// Written by the media worker; read by manifest export and maintenance tools.
+#[async_trait::async_trait]
+pub trait MediaIndexPort: Send + Sync {
+    /// Insert or replace the entry for (chat_id, message_id). `message_date` is ignored (derived on read);
+    /// a `remote_key` of None keeps the stored one.
+    async fn upsert_media_file(&self, file: &MediaFile) -> Result<(), DomainError>;
+
+    /// Page through entries ordered by (chat_id, message_id), starting after `after` (keyset).
+    /// Filters by `chat_id` and `status` when given. An empty page means the end.
+    async fn list_media_files(
+        &self,
+        chat_id: Option<i64>,
+        status: Option<MediaStatus>,
+        after: Option<(i64, i32)>,
+        limit: u32,
+    ) -> Result<Vec<MediaFile>, DomainError>;
+
+    /// Page through `done` entries without a remote key (downloaded, not yet uploaded to
+    /// remote storage), ordered by (chat_id, message_id) after `after`. An empty page means the end.
+    async fn list_unuploaded_media(
+        &self,
+        after: Option<(i64, i32)>,
+        limit: u32,
+    ) -> Result<Vec<MediaFile>, DomainError>;
+
+    /// Media references of the stored messages of `chat_id`, each with its index entry if it
+    /// has one, ordered by message id after `after` (keyset). References that can't be read
+    /// come back with an empty `opaque_ref`. An empty page means the end.
+    async fn list_chat_media(
+        &self,
+        chat_id: i64,
+        after: Option<i32>,
+        limit: u32,
+    ) -> Result<Vec<(MediaReference, Option<MediaFile>)>, DomainError>;
+
+    /// Media references of `pending` entries last updated at or before `updated_before` (unix
+    /// seconds), i.e. downloads interrupted by a crash. Ordered by (chat_id, message_id),
+    /// starting after `after` (keyset); an empty page means the end. Entries whose message is
+    /// stored without a readable media reference come back with an empty `opaque_ref`.
+    async fn find_stale_pending_media(
+        &self,
+        updated_before: i64,
+        after: Option<(i64, i32)>,
+        limit: u32,
+    ) -> Result<Vec<MediaReference>, DomainError>;
+}
+
+/// Durable media download queue (media_queue table): one row per media reference with status
+/// queued -> in_progress -> done/failed, attempt count and last error, so queued downloads
+/// survive a crash or restart. Implemented by `SqliteRepo`.
+#[async_trait::async_trait]
+pub trait MediaQueuePort: Send + Sync {
+    /// Add `queued` rows for `refs`; refs already in the queue keep their row. Returns rows added.
+    async fn enqueue_media(&self, refs: &[MediaReference]) -> Result<u64, DomainError>;
+
+    /// Queue `refs` for download again: new rows are added and `done` rows (whose file has
+    /// since gone) go back to `queued`; rows in progress or failed keep their state. Returns
+    /// the rows queued.
+    async fn requeue_media(&self, refs: &[MediaReference]) -> Result<u64, DomainError>;
+
+    /// Claim one reference for download: a new, `queued` or `done` row (an explicit re-queue,
+    /// e.g. after the audit found the file missing) becomes `in_progress`. Returns false when
+    /// it is in progress or failed (retried by [`MediaQueuePort::claim_due_media`]).
+    async fn claim_media(&self, media_ref: &MediaReference) -> Result<bool, DomainError>;
+
+    /// Claim up to `limit` due rows: `queued` ones, then `failed` ones with fewer than
+    /// `max_attempts` attempts whose backoff (`base_backoff_secs * 2^(attempts - 1)` since the
+    /// last failure) has passed at `now`. Rows whose message has no readable media reference
+    /// are marked failed for good instead of returned.
+    async fn claim_due_media(
+        &self,
+        now: i64,
+        base_backoff_secs: i64,
+        max_attempts: u32,
+        limit: u32,
+    ) -> Result<Vec<MediaReference>, DomainError>;
+
+    /// Mark a claimed row done (`error` None) or failed (attempts + 1, `last_error` set).
+    async fn finish_media(
+        &self,
+        chat_id: i64,
+        message_id: i32,
+        error: Option<&str>,
+    ) -> Result<(), DomainError>;
+
+    /// Put rows left `in_progress` by an unclean exit back to `queued`. Returns the count.
+    async fn requeue_interrupted_media(&self) -> Result<u64, DomainError>;
+
+    /// Reset every `failed` row to `queued` with zero attempts. Returns the count.
+    async fn retry_failed_media(&self) -> Result<u64, DomainError>;
+}
+
+/// Archive integrity queries for `tg-sync audit`. Cross-table checks run in SQL so whole
+/// tables are never loaded into memory. Read-only except [`ArchiveAuditPort::rebuild_fts`].
+#[async_trait::async_trait]
+pub trait ArchiveAuditPort: Send + Sync {
+    /// Number of stored messages that carry a media reference.
+    async fn count_media_references(&self) -> Result<u64, DomainError>;
+
+    /// Media references of stored messages that have no media index row, ordered by
+    /// (chat_id, message_id), starting after `after` (keyset). An empty page means the end.
+    async fn find_unindexed_media(
+        &self,
+        after: Option<(i64, i32)>,
+        limit: u32,
+    ) -> Result<Vec<MediaReference>, DomainError>;
+
+    /// Highest stored message id per chat, ordered by chat_id.
+    async fn max_message_ids(&self) -> Result<Vec<(i64, i32)>, DomainError>;
+
+    /// Total analysis log entries and those whose period contains no stored messages.
+    async fn find_orphan_analyses(
+        &self,
+    ) -> Result<(u64, Vec<(i64, Granularity, PeriodGroup)>), DomainError>;
+
+    /// Row counts of (messages, full-text index). `None` when the archive has no FTS index.
+    async fn fts_row_counts(&self) -> Result<Option<(u64, u64)>, DomainError>;
+
+    /// Rebuild the full-text index from `messages`. No-op without an FTS index.
+    async fn rebuild_fts(&self) -> Result<(), DomainError>;
+
+    /// Check integrity, checkpoint the WAL and VACUUM (only when the check passed), reporting
+    /// the database size before and after.
+    async fn maintenance(&self) -> Result<DatabaseMaintenance, DomainError>;
+
+    /// Write a consistent copy of the database to `dest` (which must not exist yet) while
+    /// writers keep running, then run an integrity check on the copy. Returns its size in
+    /// bytes. A copy that fails the check is removed and reported as an error.
+    async fn snapshot(&self, dest: &Path) -> Result<u64, DomainError>;
 }
 
 // ─────────────────────────────────────────────────────────────────────────────
 // AI Analysis Ports
 // ─────────────────────────────────────────────────────────────────────────────
 
-use crate::domain::{AnalysisResult, WeekGroup};
+use crate::domain::{
+    AiReply, AiUsageRecord, AnalysisResult, AnalyzedPeriod, ChunkSummary, Granularity, PeriodGroup,
+    UsageTotals, WeekGroup,
+};
 
 /// AI Analysis port. Send context to LLM, receive structured analysis.
 ///
 /// Implementations may use OpenAI, Ollama, Anthropic, or any compatible API.
-/// The adapter handles prompt construction and response parsing.
+/// The adapter handles prompt construction and response parsing. Replies carry the token
+/// usage the provider reported, for cost tracking.
 #[async_trait::async_trait]
 pub trait AiPort: Send + Sync {
-    /// Analyze chat context (CSV format). Returns structured analysis result.
+    /// Analyze chat context (CSV format). Returns structured analysis result and usage.
     ///
     /// # Arguments
     /// * `chat_id` - The chat being analyzed (for result metadata)
@@ -146,7 +576,7 @@ pub trait AiPort: Send + Sync {
         chat_id: i64,
         week_group: &WeekGroup,
         context_csv: &str,
-    ) -> Result<AnalysisResult, DomainError>;
+    ) -> Result<AiReply<AnalysisResult>, DomainError>;
 
     /// Summarize chat logs (Map phase). Returns plain-text intermediate summary.
     ///
@@ -158,43 +588,128 @@ pub trait AiPort: Send + Sync {
     ///
     /// # Errors
     /// Returns `DomainError::Ai` if the LLM API fails.
-    async fn summarize(&self, context: &str) -> Result<String, DomainError>;
+    async fn summarize(&self, context: &str) -> Result<AiReply<String>, DomainError>;
 }
 
-/// Analysis log persistence. Track which weeks have been analyzed.
+/// Analysis log persistence. Track which periods (days, weeks or months) have been analyzed.
 ///
-/// Implemented by `SqliteRepo` to persist analysis state and results.
+/// Implemented by `SqliteRepo` to persist analysis state and results. Period keys are only
+/// unique together with their [`Granularity`].
 #[async_trait::async_trait]
 pub trait AnalysisLogPort: Send + Sync {
-    /// Get all week groups for a chat that have NOT been analyzed yet.
+    /// Get all periods of `granularity` for a chat that have NOT been analyzed yet.
     ///
-    /// Returns weeks in chronological order (oldest first).
-    async fn get_unanalyzed_weeks(&self, chat_id: i64) -> Result<Vec<WeekGroup>, DomainError>;
+    /// Returns periods in chronological order (oldest first).
+    async fn get_unanalyzed_weeks(
+        &self,
+        chat_id: i64,
+        granularity: Granularity,
+    ) -> Result<Vec<PeriodGroup>, DomainError>;
+
+    /// All periods of `granularity` with messages to analyze, analyzed or not, oldest first.
+    /// Only the keys are read.
+    async fn get_periods(
+        &self,
+        chat_id: i64,
+        granularity: Granularity,
+    ) -> Result<Vec<PeriodGroup>, DomainError>;
 
-    /// Get messages grouped by week for CSV export.
+    /// Messages of one period for CSV export, oldest first. Only that period is loaded, so
+    /// memory is bounded by the largest period rather than the chat.
     ///
     /// Filters out:
-    /// - Empty messages
-    /// - Service messages (joins/leaves)
+    /// - Empty messages, except the parts of an album with a caption (the CSV collapses
+    ///   the album into one row)
+    /// - Service messages (by the structural `is_service` flag, never by text)
     /// - Stickers without captions
-    ///
-    /// Returns: Vec<(WeekGroup, Vec<Message>)> sorted chronologically.
-    async fn get_messages_by_week(
+    async fn get_messages_for_week(
         &self,
         chat_id: i64,
-    ) -> Result<Vec<(WeekGroup, Vec<Message>)>, DomainError>;
+        granularity: Granularity,
+        week_group: &PeriodGroup,
+    ) -> Result<Vec<Message>, DomainError>;
 
-    /// Save analysis result after LLM processing.
+    /// Save analysis result after LLM processing, keyed by chat, granularity and period.
     ///
-    /// Uses UPSERT semantics: if the week was already analyzed, the result is replaced.
+    /// Uses UPSERT semantics: if the period was already analyzed, the result is replaced.
     async fn save_analysis(&self, result: &AnalysisResult) -> Result<(), DomainError>;
 
-    /// Get previously saved analysis for a chat+week.
+    /// Get previously saved analysis for a chat+period.
     ///
-    /// Returns `None` if the week has not been analyzed.
+    /// Returns `None` if the period has not been analyzed.
     async fn get_analysis(
         &self,
         chat_id: i64,
-        week_group: &WeekGroup,
+        granularity: Granularity,
+        week_group: &PeriodGroup,
     ) -> Result<Option<AnalysisResult>, DomainError>;
+
+    /// Periods of a chat with a saved analysis, in every granularity, newest first (without
+    /// loading the results).
+    async fn get_analyzed_weeks(&self, chat_id: i64) -> Result<Vec<AnalyzedPeriod>, DomainError>;
+
+    /// Delete the saved analysis of a chat+period, so it counts as unanalyzed again. Returns
+    /// whether there was one.
+    async fn delete_analysis(
+        &self,
+        chat_id: i64,
+        granularity: Granularity,
+        week_group: &PeriodGroup,
+    ) -> Result<bool, DomainError>;
+
+    /// List saved analyses ordered by chat, then granularity and period. All chats when
+    /// `chat_id` is None.
+    async fn list_analyses(&self, chat_id: Option<i64>)
+    -> Result<Vec<AnalysisResult>, DomainError>;
+
+    /// Chunk summaries left by an unfinished Map-Reduce run of a chat+period, by chunk index.
+    async fn get_chunk_summaries(
+        &self,
+        chat_id: i64,
+        granularity: Granularity,
+        week_group: &PeriodGroup,
+    ) -> Result<Vec<ChunkSummary>, DomainError>;
+
+    /// Store (or replace) the summary of one chunk as soon as the Map phase produced it.
+    async fn save_chunk_summary(
+        &self,
+        chat_id: i64,
+        granularity: Granularity,
+        week_group: &PeriodGroup,
+        chunk: &ChunkSummary,
+    ) -> Result<(), DomainError>;
+
+    /// Drop the chunk summaries of a chat+period once its analysis is saved.
+    async fn delete_chunk_summaries(
+        &self,
+        chat_id: i64,
+        granularity: Granularity,
+        week_group: &PeriodGroup,
+    ) -> Result<(), DomainError>;
+
+    /// Append one LLM call to the usage log (ai_usage table).
+    async fn record_ai_usage(&self, record: &AiUsageRecord) -> Result<(), DomainError>;
+
+    /// Usage summed per calendar month ("YYYY-MM", UTC), newest first.
+    async fn get_ai_usage_by_month(&self) -> Result<Vec<(String, UsageTotals)>, DomainError>;
+
+    /// Whether a tracker task was already created for this action item of a chat+period.
+    /// `description_hash` identifies the item by its normalized description.
+    async fn has_tracker_task(
+        &self,
+        chat_id: i64,
+        granularity: Granularity,
+        week_group: &PeriodGroup,
+        description_hash: i64,
+    ) -> Result<bool, DomainError>;
+
+    /// Remember the tracker task created for an action item (tracker_tasks table).
+    async fn save_tracker_task(
+        &self,
+        chat_id: i64,
+        granularity: Granularity,
+        week_group: &PeriodGroup,
+        description_hash: i64,
+        external_id: &str,
+    ) -> Result<(), DomainError>;
 }
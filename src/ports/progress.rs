@@ -0,0 +1,19 @@
+//! Progress outbound port. Report long-running sync progress to the UI.
+
+use crate::domain::SyncProgress;
+
+/// Port for reporting per-chat sync progress (e.g. a TUI progress bar).
+///
+/// Methods are synchronous and must be cheap: they are called once per batch from the
+/// sync loop. When not configured, the sync service only logs.
+pub trait ProgressPort: Send + Sync {
+    /// A chat sync started. `expected` is the estimated number of messages to fetch, if known.
+    fn chat_started(&self, chat_id: i64, title: &str, expected: Option<u64>);
+
+    /// A batch was saved. Carries the running count and the rolling ETA.
+    fn chat_progress(&self, progress: &SyncProgress);
+
+    /// The chat sync finished (successfully or not); `synced` and `media_queued` are the final
+    /// counts.
+    fn chat_finished(&self, chat_id: i64, synced: u64, media_queued: u64);
+}
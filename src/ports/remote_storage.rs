@@ -0,0 +1,26 @@
+//! Remote storage outbound port. Object storage for media and database snapshots (e.g. S3).
+
+use crate::domain::DomainError;
+use std::path::Path;
+
+/// Port for storing files as objects under string keys (`media/123_45.jpg`).
+///
+/// Implemented by adapters (e.g. S3-compatible storage). Keys are relative to whatever prefix
+/// the adapter is configured with. Callers retry failed calls with backoff.
+#[async_trait::async_trait]
+pub trait RemoteStoragePort: Send + Sync {
+    /// Short name for logs (e.g. "s3").
+    fn name(&self) -> &'static str;
+
+    /// Upload the file at `path` as `key`, replacing an existing object.
+    ///
+    /// # Errors
+    /// Returns `DomainError::Storage` if the file could not be read or stored.
+    async fn put_object(&self, key: &str, path: &Path) -> Result<(), DomainError>;
+
+    /// Whether an object is stored under `key`.
+    ///
+    /// # Errors
+    /// Returns `DomainError::Storage` if the storage could not be asked.
+    async fn exists(&self, key: &str) -> Result<bool, DomainError>;
+}
@@ -1,6 +1,6 @@
 //! Task tracker outbound port. Create tasks in external systems (e.g. Trello).
 
-use crate::domain::DomainError;
+use crate::domain::{DomainError, TaskSpec};
 
 /// Port for creating tasks in an external task tracker.
 ///
@@ -8,19 +8,28 @@ use crate::domain::DomainError;
 /// service skips sending action items but still generates the Markdown report.
 #[async_trait::async_trait]
 pub trait TaskTrackerPort: Send + Sync {
-    /// Create a single task in the tracker.
-    ///
-    /// # Arguments
-    /// * `title` - Short task title (e.g. card name)
-    /// * `description` - Optional longer description
-    /// * `due` - Optional due date string (format is adapter-specific, e.g. ISO date)
+    /// Create a single task in the tracker and return its id there (e.g. the Trello card id).
     ///
     /// # Errors
     /// Returns `DomainError` if the API call fails.
-    async fn create_task(
-        &self,
-        title: &str,
-        description: &str,
-        due: Option<String>,
-    ) -> Result<(), DomainError>;
+    async fn create_task(&self, task: &TaskSpec) -> Result<String, DomainError>;
+
+    /// Create several tasks; one result per task, in order. The default creates them one by
+    /// one; adapters with rate limits override it to pace the requests.
+    async fn create_tasks(&self, tasks: &[TaskSpec]) -> Vec<Result<String, DomainError>> {
+        let mut results = Vec::with_capacity(tasks.len());
+        for task in tasks {
+            results.push(self.create_task(task).await);
+        }
+        results
+    }
+}
+
+/// Task creations that failed and were parked for a later retry (dead letters).
+#[async_trait::async_trait]
+pub trait TaskDeadLetterPort: Send + Sync {
+    /// Retry every parked task once.
+    ///
+    /// Returns (delivered, still parked).
+    async fn replay_dead_letters(&self) -> Result<(u64, u64), DomainError>;
 }
@@ -0,0 +1,295 @@
+//! Activity histogram helpers: fill gaps, aggregate in memory, and render as text.
+//!
+//! Pure functions shared by the analysis report (`AnalysisService`) and the TUI statistics view.
+//! Queries that hit the database live in `RepoPort::get_activity_histogram`.
+
+use crate::domain::{ActivityBin, ActivityBucket, TimeRange};
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate};
+
+const SECS_PER_DAY: i64 = 86_400;
+
+/// Sparkline glyphs from lowest to highest. Zero always renders as the first glyph.
+const SPARK_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Full-block character used for horizontal bars.
+const BAR_CHAR: char = '█';
+
+/// Render counts as a one-line unicode sparkline. Non-zero counts never render as the
+/// zero glyph, so a quiet-but-active day stays distinguishable from a silent one.
+pub fn sparkline(counts: &[u64]) -> String {
+    let max = counts.iter().copied().max().unwrap_or(0);
+    counts
+        .iter()
+        .map(|&c| {
+            if c == 0 || max == 0 {
+                SPARK_LEVELS[0]
+            } else {
+                // Scale into 1..=7 so any non-zero count is at least one step up.
+                let top = (SPARK_LEVELS.len() - 1) as u64;
+                let level = (c * top).div_ceil(max).clamp(1, top);
+                SPARK_LEVELS[level as usize]
+            }
+        })
+        .collect()
+}
+
+/// Render labelled counts as aligned horizontal bars, e.g. `Mon 01-08 │██████ 42`.
+/// `width` is the length of the longest bar.
+pub fn bar_rows(rows: &[(String, u64)], width: usize) -> Vec<String> {
+    let max = rows.iter().map(|(_, c)| *c).max().unwrap_or(0);
+    let label_width = rows
+        .iter()
+        .map(|(l, _)| l.chars().count())
+        .max()
+        .unwrap_or(0);
+    rows.iter()
+        .map(|(label, count)| {
+            let len = if max == 0 {
+                0
+            } else {
+                ((*count as usize) * width).div_ceil(max as usize)
+            };
+            format!(
+                "{:<lw$} │{} {}",
+                label,
+                BAR_CHAR.to_string().repeat(len),
+                count,
+                lw = label_width
+            )
+        })
+        .collect()
+}
+
+/// Expand sparse `Day` bins into one entry per local day covering `range`, zero-filling gaps.
+pub fn daily_series(
+    bins: &[ActivityBin],
+    range: TimeRange,
+    utc_offset_secs: i32,
+) -> Vec<ActivityBin> {
+    let offset = utc_offset_secs as i64;
+    let first = local_day_start(range.from, offset);
+    let mut out = Vec::new();
+    let mut day = first;
+    while day < range.to {
+        let count = bins
+            .iter()
+            .find(|b| b.bucket_start == day)
+            .map(|b| b.count)
+            .unwrap_or(0);
+        out.push(ActivityBin {
+            bucket_start: day,
+            count,
+        });
+        day += SECS_PER_DAY;
+    }
+    out
+}
+
+/// Expand sparse `HourOfDay` bins into all 24 hours, zero-filling gaps.
+pub fn hourly_series(bins: &[ActivityBin]) -> [u64; 24] {
+    let mut hours = [0u64; 24];
+    for b in bins {
+        if (0..24).contains(&b.bucket_start) {
+            hours[b.bucket_start as usize] += b.count;
+        }
+    }
+    hours
+}
+
+/// Aggregate message timestamps in memory (same bucketing as the SQL query).
+/// Used when messages are already loaded, e.g. for a week being analyzed.
+pub fn histogram_from_dates(
+    dates: impl IntoIterator<Item = i64>,
+    bucket: ActivityBucket,
+    utc_offset_secs: i32,
+) -> Vec<ActivityBin> {
+    let offset = utc_offset_secs as i64;
+    let mut bins: Vec<ActivityBin> = Vec::new();
+    for date in dates {
+        let key = match bucket {
+            ActivityBucket::Day => local_day_start(date, offset),
+            ActivityBucket::HourOfDay => (date + offset).rem_euclid(SECS_PER_DAY) / 3600,
+        };
+        match bins.iter_mut().find(|b| b.bucket_start == key) {
+            Some(b) => b.count += 1,
+            None => bins.push(ActivityBin {
+                bucket_start: key,
+                count: 1,
+            }),
+        }
+    }
+    bins.sort_by_key(|b| b.bucket_start);
+    bins
+}
+
+/// Week (Monday..Sunday, local time) containing `ts`, as a half-open range.
+pub fn week_range_containing(ts: i64, utc_offset_secs: i32) -> TimeRange {
+    let offset = utc_offset_secs as i64;
+    let day = local_day_start(ts, offset);
+    let weekday = to_local(day, utc_offset_secs)
+        .map(|dt| dt.weekday().num_days_from_monday() as i64)
+        .unwrap_or(0);
+    let from = day - weekday * SECS_PER_DAY;
+    TimeRange {
+        from,
+        to: from + 7 * SECS_PER_DAY,
+    }
+}
+
+/// Whole local days from the one containing `first` through the one containing `last`,
+/// as a half-open range.
+pub fn day_span(first: i64, last: i64, utc_offset_secs: i32) -> TimeRange {
+    let offset = utc_offset_secs as i64;
+    TimeRange {
+        from: local_day_start(first, offset),
+        to: local_day_start(last, offset) + SECS_PER_DAY,
+    }
+}
+
+/// Label for a day bucket, e.g. `Mon 2024-01-08`.
+pub fn day_label(day_start: i64, utc_offset_secs: i32) -> String {
+    to_local(day_start, utc_offset_secs)
+        .map(|dt| dt.format("%a %Y-%m-%d").to_string())
+        .unwrap_or_else(|| day_start.to_string())
+}
+
+/// Local date and time of `ts`, e.g. `2024-05-01 14:30`.
+pub fn time_label(ts: i64, utc_offset_secs: i32) -> String {
+    to_local(ts, utc_offset_secs)
+        .map(|dt| dt.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| ts.to_string())
+}
+
+/// Range covering the whole local days `from..=to`. A missing bound is open
+/// (`i64::MIN` / `i64::MAX`).
+pub fn date_range(
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    utc_offset_secs: i32,
+) -> TimeRange {
+    let offset = utc_offset_secs as i64;
+    let midnight = |d: NaiveDate| d.and_time(chrono::NaiveTime::MIN).and_utc().timestamp() - offset;
+    TimeRange {
+        from: from.map(midnight).unwrap_or(i64::MIN),
+        to: to
+            .and_then(|d| d.succ_opt())
+            .map(midnight)
+            .unwrap_or(i64::MAX),
+    }
+}
+
+/// Unix timestamp of local midnight for the day containing `ts`.
+fn local_day_start(ts: i64, offset: i64) -> i64 {
+    (ts + offset).div_euclid(SECS_PER_DAY) * SECS_PER_DAY - offset
+}
+
+fn to_local(ts: i64, utc_offset_secs: i32) -> Option<DateTime<FixedOffset>> {
+    let tz = FixedOffset::east_opt(utc_offset_secs)?;
+    DateTime::from_timestamp(ts, 0).map(|dt| dt.with_timezone(&tz))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MON_2024_01_01: i64 = 1704067200; // 2024-01-01 00:00:00 UTC, Monday
+
+    #[test]
+    fn test_sparkline_levels() {
+        assert_eq!(sparkline(&[]), "");
+        assert_eq!(sparkline(&[0, 0, 0]), "▁▁▁");
+        assert_eq!(sparkline(&[0, 7]), "▁█");
+        assert_eq!(sparkline(&[1, 100]), "▂█");
+        assert_eq!(sparkline(&[0, 1, 2, 3, 4, 5, 6, 7]), "▁▂▃▄▅▆▇█");
+    }
+
+    #[test]
+    fn test_bar_rows_scaled_and_aligned() {
+        let rows = vec![
+            ("Mon".to_string(), 10),
+            ("Tuesday".to_string(), 5),
+            ("Wed".to_string(), 0),
+        ];
+        let out = bar_rows(&rows, 10);
+        assert_eq!(out[0], "Mon     │██████████ 10");
+        assert_eq!(out[1], "Tuesday │█████ 5");
+        assert_eq!(out[2], "Wed     │ 0");
+    }
+
+    #[test]
+    fn test_daily_series_zero_fills() {
+        let bins = vec![ActivityBin {
+            bucket_start: MON_2024_01_01 + 86_400,
+            count: 4,
+        }];
+        let range = TimeRange {
+            from: MON_2024_01_01,
+            to: MON_2024_01_01 + 3 * 86_400,
+        };
+        let series = daily_series(&bins, range, 0);
+        let counts: Vec<u64> = series.iter().map(|b| b.count).collect();
+        assert_eq!(counts, vec![0, 4, 0]);
+        assert_eq!(series[0].bucket_start, MON_2024_01_01);
+    }
+
+    #[test]
+    fn test_histogram_from_dates_respects_offset() {
+        // 23:30 UTC on Monday is 04:30 Tuesday at UTC+5.
+        let ts = MON_2024_01_01 + 23 * 3600 + 1800;
+        let utc = histogram_from_dates([ts], ActivityBucket::HourOfDay, 0);
+        assert_eq!(utc[0].bucket_start, 23);
+        let plus5 = histogram_from_dates([ts], ActivityBucket::HourOfDay, 5 * 3600);
+        assert_eq!(plus5[0].bucket_start, 4);
+
+        let days = histogram_from_dates([ts, ts + 60], ActivityBucket::Day, 5 * 3600);
+        assert_eq!(days.len(), 1);
+        assert_eq!(days[0].count, 2);
+        // Local midnight Tuesday at UTC+5 is 19:00 UTC Monday.
+        assert_eq!(days[0].bucket_start, MON_2024_01_01 + 19 * 3600);
+    }
+
+    #[test]
+    fn test_hourly_series_and_week_range() {
+        let bins = vec![
+            ActivityBin {
+                bucket_start: 9,
+                count: 3,
+            },
+            ActivityBin {
+                bucket_start: 23,
+                count: 1,
+            },
+        ];
+        let hours = hourly_series(&bins);
+        assert_eq!(hours[9], 3);
+        assert_eq!(hours[23], 1);
+        assert_eq!(hours.iter().sum::<u64>(), 4);
+
+        // Thursday 2024-01-04 belongs to the week starting Monday 2024-01-01.
+        let week = week_range_containing(MON_2024_01_01 + 3 * 86_400 + 5000, 0);
+        assert_eq!(week.from, MON_2024_01_01);
+        assert_eq!(week.to, MON_2024_01_01 + 7 * 86_400);
+        // A Monday noon..Wednesday morning span covers three whole days.
+        let span = day_span(
+            MON_2024_01_01 + 43_200,
+            MON_2024_01_01 + 2 * 86_400 + 100,
+            0,
+        );
+        assert_eq!(span.from, MON_2024_01_01);
+        assert_eq!(span.to, MON_2024_01_01 + 3 * 86_400);
+        assert_eq!(day_label(MON_2024_01_01, 0), "Mon 2024-01-01");
+    }
+
+    #[test]
+    fn test_date_range_covers_whole_local_days() {
+        let day = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok();
+        let range = date_range(day("2024-01-01"), day("2024-01-02"), 5 * 3600);
+        assert_eq!(range.from, MON_2024_01_01 - 5 * 3600);
+        assert_eq!(range.to, MON_2024_01_01 + 2 * 86_400 - 5 * 3600);
+
+        let open = date_range(None, day("2024-01-01"), 0);
+        assert_eq!(open.from, i64::MIN);
+        assert_eq!(open.to, MON_2024_01_01 + 86_400);
+        assert_eq!(date_range(day("2024-01-01"), None, 0).to, i64::MAX);
+    }
+}
@@ -0,0 +1,119 @@
+//! In-process activity flag: which archive writers (sync, watcher) are running right now.
+//!
+//! One flag is shared by the services that write to the archive and by maintenance, which
+//! must not VACUUM the database or delete media files under a running sync. Writers register
+//! with [`ActivityFlag::begin`]; maintenance takes [`ActivityFlag::exclusive`], which fails
+//! while any writer runs and keeps new writers out until its guard is dropped. Unlike
+//! [`crate::shared::lock::DataDirLock`] this only covers the current process.
+
+use crate::domain::DomainError;
+use std::sync::{Arc, Mutex};
+
+/// Shared flag; clones refer to the same state.
+#[derive(Debug, Clone, Default)]
+pub struct ActivityFlag {
+    state: Arc<Mutex<ActivityState>>,
+}
+
+#[derive(Debug, Default)]
+struct ActivityState {
+    /// Names of running writers, one entry per guard.
+    running: Vec<&'static str>,
+    /// Name of the exclusive task, if one holds the flag.
+    exclusive: Option<&'static str>,
+}
+
+impl ActivityFlag {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a running writer. Fails while an exclusive task (maintenance) holds the flag.
+    pub fn begin(&self, name: &'static str) -> Result<ActivityGuard, DomainError> {
+        let mut state = self.lock();
+        if let Some(exclusive) = state.exclusive {
+            return Err(DomainError::State(format!(
+                "{} cannot start while {} is running",
+                name, exclusive
+            )));
+        }
+        state.running.push(name);
+        Ok(ActivityGuard {
+            flag: self.clone(),
+            name,
+            exclusive: false,
+        })
+    }
+
+    /// Hold the flag alone. Fails while any writer or another exclusive task runs.
+    pub fn exclusive(&self, name: &'static str) -> Result<ActivityGuard, DomainError> {
+        let mut state = self.lock();
+        if let Some(busy) = state.exclusive.or_else(|| state.running.first().copied()) {
+            return Err(DomainError::State(format!(
+                "{} refused: {} is running",
+                name, busy
+            )));
+        }
+        state.exclusive = Some(name);
+        Ok(ActivityGuard {
+            flag: self.clone(),
+            name,
+            exclusive: true,
+        })
+    }
+
+    /// Names of the running writers (an exclusive task is not listed).
+    pub fn running(&self) -> Vec<&'static str> {
+        self.lock().running.clone()
+    }
+
+    /// The state stays consistent under panics (plain pushes and removals), so a poisoned
+    /// lock is still used.
+    fn lock(&self) -> std::sync::MutexGuard<'_, ActivityState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Registration on an [`ActivityFlag`]; released on drop.
+#[derive(Debug)]
+pub struct ActivityGuard {
+    flag: ActivityFlag,
+    name: &'static str,
+    exclusive: bool,
+}
+
+impl Drop for ActivityGuard {
+    fn drop(&mut self) {
+        let mut state = self.flag.lock();
+        if self.exclusive {
+            state.exclusive = None;
+        } else if let Some(i) = state.running.iter().position(|n| *n == self.name) {
+            state.running.remove(i);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exclusive_waits_for_writers_and_blocks_them() {
+        let flag = ActivityFlag::new();
+        let sync = flag.begin("sync").unwrap();
+        let watcher = flag.begin("watcher").unwrap();
+        assert_eq!(flag.running(), vec!["sync", "watcher"]);
+        assert!(matches!(
+            flag.exclusive("maintenance"),
+            Err(DomainError::State(_))
+        ));
+
+        drop(sync);
+        drop(watcher);
+        let maintenance = flag.exclusive("maintenance").unwrap();
+        assert!(flag.begin("sync").is_err());
+        assert!(flag.clone().exclusive("maintenance").is_err());
+        drop(maintenance);
+        assert!(flag.begin("sync").is_ok());
+    }
+}
@@ -0,0 +1,330 @@
+//! Pseudonymization and redaction for data that leaves the machine (cloud LLM context,
+//! shared exports).
+//!
+//! - Users: consistent pseudonyms (`User-A`, `User-B`, ..., `User-AA`) assigned in first-seen
+//!   order. A user id and its username share one pseudonym. The mapping can be loaded from
+//!   and persisted to a JSON file so pseudonyms stay stable across runs, and written next to
+//!   an export for authorized de-anonymization.
+//! - Text: emails, `@username` mentions, phone numbers and credit-card-like digit runs
+//!   (13–19 digits passing the Luhn check) are replaced.
+
+use crate::domain::DomainError;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+/// Replacement for email addresses.
+pub const EMAIL_PLACEHOLDER: &str = "[email]";
+/// Replacement for phone numbers.
+pub const PHONE_PLACEHOLDER: &str = "[phone]";
+/// Replacement for card numbers.
+pub const CARD_PLACEHOLDER: &str = "[card]";
+
+static EMAIL_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)[a-z0-9._%+-]+@[a-z0-9-]+(?:\.[a-z0-9-]+)*\.[a-z]{2,}").expect("email regex")
+});
+
+/// Telegram usernames: 5–32 chars, letter first.
+static MENTION_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"@([A-Za-z][A-Za-z0-9_]{4,31})").expect("mention regex"));
+
+/// Digit runs with common separators; classified by [`classify_number`].
+static NUMBER_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\+?\(?\d[\d ().-]{5,}\d").expect("number regex"));
+
+/// Things that look like long numbers but are not contact data: dates and IPv4 addresses.
+static NOT_CONTACT_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?:\d{4}[-./]\d{1,2}[-./]\d{1,2}|\d{1,2}[-./]\d{1,2}[-./]\d{4}|\d{1,3}(?:\.\d{1,3}){3}$)")
+        .expect("date/ip regex")
+});
+
+/// Persisted form of the mapping.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct MappingFile {
+    /// Number of pseudonyms handed out so far.
+    next: u32,
+    /// Key (`id:<user id>`, `@<username>`, `name:<display name>`) -> pseudonym.
+    pseudonyms: BTreeMap<String, String>,
+}
+
+/// Pseudonym mapping plus text redaction. Not thread-safe; wrap in a Mutex to share.
+#[derive(Debug, Default)]
+pub struct Anonymizer {
+    mapping: MappingFile,
+    /// Where [`Anonymizer::persist`] writes the mapping. None = in-memory only (per run).
+    path: Option<PathBuf>,
+}
+
+impl Anonymizer {
+    /// Fresh, in-memory mapping.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mapping backed by `path`: loaded if the file exists, written by [`Anonymizer::persist`].
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, DomainError> {
+        let path = path.as_ref().to_path_buf();
+        let mapping = match std::fs::read_to_string(&path) {
+            Ok(s) => serde_json::from_str(&s).map_err(|e| {
+                DomainError::State(format!("Invalid anonymization map {:?}: {}", path, e))
+            })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => MappingFile::default(),
+            Err(e) => return Err(DomainError::State(e.to_string())),
+        };
+        Ok(Self {
+            mapping,
+            path: Some(path),
+        })
+    }
+
+    /// Pseudonym for a user id. The username, when given, is linked to the same pseudonym.
+    pub fn user(&mut self, id: i64, username: Option<&str>) -> String {
+        let id_key = format!("id:{}", id);
+        let label = match self.mapping.pseudonyms.get(&id_key) {
+            Some(label) => label.clone(),
+            None => {
+                let label = username
+                    .and_then(|u| self.mapping.pseudonyms.get(&username_key(u)).cloned())
+                    .unwrap_or_else(|| self.next_label());
+                self.mapping.pseudonyms.insert(id_key, label.clone());
+                label
+            }
+        };
+        if let Some(u) = username {
+            self.mapping
+                .pseudonyms
+                .entry(username_key(u))
+                .or_insert_with(|| label.clone());
+        }
+        label
+    }
+
+    /// Pseudonym for a username (with or without the leading `@`, case-insensitive).
+    pub fn username(&mut self, username: &str) -> String {
+        self.label_for(username_key(username))
+    }
+
+    /// Pseudonym for a free-text person name (e.g. an action item owner), case-insensitive.
+    pub fn name(&mut self, name: &str) -> String {
+        self.label_for(format!("name:{}", name.trim().to_lowercase()))
+    }
+
+    /// Redact emails, phone numbers and card numbers; replace `@mentions` with pseudonyms.
+    pub fn redact_text(&mut self, text: &str) -> String {
+        let text = EMAIL_RE.replace_all(text, EMAIL_PLACEHOLDER);
+        let text =
+            MENTION_RE.replace_all(&text, |c: &Captures| format!("@{}", self.username(&c[1])));
+        NUMBER_RE
+            .replace_all(&text, |c: &Captures| {
+                classify_number(&c[0]).unwrap_or(&c[0]).to_string()
+            })
+            .into_owned()
+    }
+
+    /// Number of distinct keys mapped.
+    pub fn len(&self) -> usize {
+        self.mapping.pseudonyms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mapping.pseudonyms.is_empty()
+    }
+
+    /// Write the mapping to `path` (JSON, temp file + rename). Sensitive: it reverses the
+    /// pseudonyms.
+    pub fn save_to(&self, path: &Path) -> Result<(), DomainError> {
+        let json = serde_json::to_string_pretty(&self.mapping)
+            .map_err(|e| DomainError::State(e.to_string()))?;
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).map_err(|e| DomainError::State(e.to_string()))?;
+        }
+        let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(".tmp");
+        let tmp_path = path.with_file_name(tmp_name);
+        std::fs::write(&tmp_path, json)
+            .map_err(|e| DomainError::State(format!("write anonymization map: {}", e)))?;
+        std::fs::rename(&tmp_path, path)
+            .map_err(|e| DomainError::State(format!("move anonymization map: {}", e)))
+    }
+
+    /// Write the mapping back to the file it was opened from. No-op for in-memory mappings.
+    pub fn persist(&self) -> Result<(), DomainError> {
+        match &self.path {
+            Some(path) => self.save_to(path),
+            None => Ok(()),
+        }
+    }
+
+    fn label_for(&mut self, key: String) -> String {
+        if let Some(label) = self.mapping.pseudonyms.get(&key) {
+            return label.clone();
+        }
+        let label = self.next_label();
+        self.mapping.pseudonyms.insert(key, label.clone());
+        label
+    }
+
+    fn next_label(&mut self) -> String {
+        let label = pseudonym_label(self.mapping.next);
+        self.mapping.next += 1;
+        label
+    }
+}
+
+fn username_key(username: &str) -> String {
+    format!(
+        "@{}",
+        username.trim().trim_start_matches('@').to_lowercase()
+    )
+}
+
+/// `User-A` .. `User-Z`, `User-AA`, `User-AB`, ... (bijective base 26).
+pub fn pseudonym_label(index: u32) -> String {
+    let mut n = index as u64 + 1;
+    let mut letters = Vec::new();
+    while n > 0 {
+        n -= 1;
+        letters.push(b'A' + (n % 26) as u8);
+        n /= 26;
+    }
+    letters.reverse();
+    format!("User-{}", String::from_utf8(letters).unwrap_or_default())
+}
+
+/// Placeholder for a number-like match, or None to keep it.
+fn classify_number(candidate: &str) -> Option<&'static str> {
+    if NOT_CONTACT_RE.is_match(candidate) {
+        return None;
+    }
+    let digits: Vec<u32> = candidate.chars().filter_map(|c| c.to_digit(10)).collect();
+    let n = digits.len();
+    if (13..=19).contains(&n) && luhn_valid(&digits) {
+        return Some(CARD_PLACEHOLDER);
+    }
+    let international = candidate.starts_with('+');
+    if (international && (7..=15).contains(&n)) || (10..=15).contains(&n) {
+        return Some(PHONE_PLACEHOLDER);
+    }
+    None
+}
+
+fn luhn_valid(digits: &[u32]) -> bool {
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| {
+            if i % 2 == 1 {
+                let d2 = d * 2;
+                if d2 > 9 { d2 - 9 } else { d2 }
+            } else {
+                d
+            }
+        })
+        .sum();
+    sum % 10 == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pseudonym_labels() {
+        assert_eq!(pseudonym_label(0), "User-A");
+        assert_eq!(pseudonym_label(25), "User-Z");
+        assert_eq!(pseudonym_label(26), "User-AA");
+        assert_eq!(pseudonym_label(27), "User-AB");
+        assert_eq!(pseudonym_label(701), "User-ZZ");
+        assert_eq!(pseudonym_label(702), "User-AAA");
+    }
+
+    #[test]
+    fn test_user_pseudonyms_are_consistent() {
+        let mut a = Anonymizer::new();
+        assert_eq!(a.user(42, Some("Alice")), "User-A");
+        assert_eq!(a.user(7, None), "User-B");
+        assert_eq!(a.user(42, None), "User-A");
+        // Username linked to id 42, in any case and with or without '@'.
+        assert_eq!(a.username("@alice"), "User-A");
+        assert_eq!(a.username("ALICE"), "User-A");
+        // Username seen first, id later: same pseudonym.
+        assert_eq!(a.username("bob_the_builder"), "User-C");
+        assert_eq!(a.user(99, Some("bob_the_builder")), "User-C");
+        assert_eq!(a.name(" Carol "), "User-D");
+        assert_eq!(a.name("carol"), "User-D");
+    }
+
+    #[test]
+    fn test_redact_emails_and_mentions() {
+        let mut a = Anonymizer::new();
+        a.user(1, Some("alice_w"));
+        assert_eq!(
+            a.redact_text("mail john.doe+x@mail.example.co.uk or ping @alice_w and @someone"),
+            "mail [email] or ping @User-A and @User-B"
+        );
+        // Too short to be a Telegram username; left alone.
+        assert_eq!(a.redact_text("@bob hi"), "@bob hi");
+    }
+
+    #[test]
+    fn test_redact_phone_numbers() {
+        let mut a = Anonymizer::new();
+        let cases = [
+            ("call +7 701 123 45 67 now", "call [phone] now"),
+            ("call +1 (555) 123-4567", "call [phone]"),
+            ("tel 8-800-555-35-35.", "tel [phone]."),
+            ("mobile 5551234567", "mobile [phone]"),
+            ("+44 20 7946 0958", "[phone]"),
+            // Not phones: short numbers, dates, times, IPs, amounts.
+            ("order 123456 shipped", "order 123456 shipped"),
+            ("on 2024-01-15 10:30", "on 2024-01-15 10:30"),
+            ("on 15.01.2024 10:30", "on 15.01.2024 10:30"),
+            ("host 192.168.100.200 down", "host 192.168.100.200 down"),
+            ("costs 1 250 000", "costs 1 250 000"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(a.redact_text(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_redact_card_numbers() {
+        let mut a = Anonymizer::new();
+        let cases = [
+            ("card 4111 1111 1111 1111 ok", "card [card] ok"),
+            ("card 4111-1111-1111-1111", "card [card]"),
+            ("amex 378282246310005", "amex [card]"),
+            // 16 digits failing Luhn: not a card, too long for a phone.
+            ("ref 1234567812345678", "ref 1234567812345678"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(a.redact_text(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_mapping_persists_across_runs() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/test_anonymizer_map");
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("map.json");
+
+        let mut first = Anonymizer::open(&path).unwrap();
+        assert!(first.is_empty());
+        assert_eq!(first.user(10, Some("ten")), "User-A");
+        assert_eq!(first.user(20, None), "User-B");
+        first.persist().unwrap();
+
+        // Second run: same ids keep their pseudonyms, new ids continue the sequence.
+        let mut second = Anonymizer::open(&path).unwrap();
+        assert_eq!(second.user(20, None), "User-B");
+        assert_eq!(second.user(30, None), "User-C");
+        assert_eq!(second.username("ten"), "User-A");
+        assert_eq!(second.len(), 4);
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(Anonymizer::open(&path).is_err());
+    }
+}
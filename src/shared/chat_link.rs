@@ -0,0 +1,150 @@
+//! Chat references typed by the user: `@username`, a bare username, a chat id as shown in
+//! the TUI, or a t.me / tg:// link to a chat or one of its posts.
+//!
+//! Invite links (`t.me/+…`, `t.me/joinchat/…`) name no chat until the invite is accepted,
+//! so they are rejected with a hint instead of being sent to Telegram.
+
+use crate::domain::DomainError;
+
+/// Offset of channel and supergroup ids in Bot API form (`-100…`).
+const CHANNEL_ID_OFFSET: i64 = 1_000_000_000_000;
+
+/// What a reference points at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChatReference {
+    /// Chat id in Bot API form.
+    Id(i64),
+    /// Public username, without the `@`.
+    Username(String),
+}
+
+impl ChatReference {
+    /// Parse what the user typed. Errors are worded for the user.
+    pub fn parse(input: &str) -> Result<Self, DomainError> {
+        let input = input.trim();
+        if input.is_empty() {
+            return Err(invalid(input, "nothing entered"));
+        }
+        if let Ok(id) = input.parse::<i64>() {
+            return Ok(Self::Id(id));
+        }
+        if let Some(name) = input.strip_prefix('@') {
+            return username(input, name);
+        }
+        if let Some(query) = input.strip_prefix("tg://resolve?") {
+            let domain = query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("domain="))
+                .ok_or_else(|| invalid(input, "the link names no chat"))?;
+            return username(input, domain);
+        }
+        if input.starts_with("tg://join") {
+            return Err(private_invite(input));
+        }
+        let Some(path) = t_me_path(input) else {
+            return username(input, input);
+        };
+        let mut segments = path.split('/').filter(|s| !s.is_empty());
+        match segments.next() {
+            None => Err(invalid(input, "the link names no chat")),
+            Some(first) if first.starts_with('+') || first == "joinchat" => {
+                Err(private_invite(input))
+            }
+            // Link to a post of a private channel: t.me/c/<channel>/<post>.
+            Some("c") => segments
+                .next()
+                .and_then(|id| id.parse::<i64>().ok())
+                .filter(|&id| id > 0)
+                .map(|id| Self::Id(-(CHANNEL_ID_OFFSET + id)))
+                .ok_or_else(|| invalid(input, "no channel id after t.me/c/")),
+            // Web preview of a public channel: t.me/s/<username>.
+            Some("s") => match segments.next() {
+                Some(name) => username(input, name),
+                None => Err(invalid(input, "the link names no chat")),
+            },
+            Some(name) => username(input, name),
+        }
+    }
+}
+
+/// Path of a t.me (or telegram.me) link, without scheme, host, query or fragment.
+fn t_me_path(input: &str) -> Option<&str> {
+    let rest = input
+        .strip_prefix("https://")
+        .or_else(|| input.strip_prefix("http://"))
+        .unwrap_or(input);
+    let rest = rest.strip_prefix("www.").unwrap_or(rest);
+    let path = ["t.me/", "telegram.me/", "telegram.dog/"]
+        .iter()
+        .find_map(|host| rest.strip_prefix(host))
+        .or_else(|| ["t.me", "telegram.me"].contains(&rest).then_some(""))?;
+    Some(path.split(['?', '#']).next().unwrap_or_default())
+}
+
+/// A username Telegram could hold: 4 to 32 letters, digits and underscores, starting with
+/// a letter.
+fn username(input: &str, name: &str) -> Result<ChatReference, DomainError> {
+    let valid = (4..=32).contains(&name.len())
+        && name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid {
+        Ok(ChatReference::Username(name.to_string()))
+    } else {
+        Err(invalid(input, "not a username, chat id or t.me link"))
+    }
+}
+
+fn invalid(input: &str, why: &str) -> DomainError {
+    DomainError::TgGateway(format!("'{}': {}", input, why))
+}
+
+fn private_invite(input: &str) -> DomainError {
+    DomainError::TgGateway(format!(
+        "'{}' is a private invite link; join the chat in Telegram first, then pick it from the dialog list",
+        input
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_chat_references() {
+        let name = |s: &str| ChatReference::Username(s.to_string());
+        for (input, expected) in [
+            ("@durov", name("durov")),
+            (" telegram ", name("telegram")),
+            ("-1001234567890", ChatReference::Id(-1001234567890)),
+            ("https://t.me/rust_lang", name("rust_lang")),
+            ("t.me/rust_lang/1234?single", name("rust_lang")),
+            ("http://telegram.me/s/rust_lang", name("rust_lang")),
+            ("tg://resolve?domain=rust_lang&post=5", name("rust_lang")),
+            (
+                "https://t.me/c/1234567890/55",
+                ChatReference::Id(-1001234567890),
+            ),
+        ] {
+            assert_eq!(ChatReference::parse(input).unwrap(), expected, "{}", input);
+        }
+
+        for private in [
+            "https://t.me/+AbCdEf123",
+            "t.me/joinchat/AbCdEf123",
+            "tg://join?invite=AbCdEf123",
+        ] {
+            let err = ChatReference::parse(private).unwrap_err().to_string();
+            assert!(err.contains("private invite link"), "{}", err);
+        }
+        for bad in [
+            "",
+            "@ab",
+            "https://t.me/",
+            "t.me/c/abc",
+            "hello world",
+            "1abc_def",
+        ] {
+            assert!(ChatReference::parse(bad).is_err(), "{}", bad);
+        }
+    }
+}
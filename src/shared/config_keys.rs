@@ -0,0 +1,287 @@
+//! Where each configuration key lives: its `config.toml` section, its environment variable and
+//! whether it is a secret. Used to read the config file, to flag keys that configure nothing
+//! and to print the effective configuration (`tg-sync config check`).
+//!
+//! In a section, a key drops the section name from its field name: `ai_api_key` is `api_key`
+//! under `[ai]`, `media_types` is `types` under `[media]`, `api_id` stays `api_id` under
+//! `[telegram]`. The environment variable is the field name upper-cased with the `TG_SYNC_`
+//! prefix, except for the few read without it ([`ENV_ALIASES`]).
+
+/// `config.toml` sections and the `AppConfig` fields they hold, in the order they are printed.
+pub const SECTIONS: &[(&str, &[&str])] = &[
+    (
+        "telegram",
+        &[
+            "api_id",
+            "api_hash",
+            "session_path",
+            "phone",
+            "password",
+            "login_method",
+            "login_http",
+            "bot_token",
+            "bot_chats",
+            "proxy_url",
+        ],
+    ),
+    (
+        "sync",
+        &[
+            "data_dir",
+            "rate_history_per_min",
+            "rate_media_per_min",
+            "export_delay_ms",
+            "sync_delay_ms",
+            "edit_rescan_window",
+            "parallel_chats",
+            "timezone",
+            "state_backend",
+            "repo_backend",
+            "jsonl_mirror",
+            "encryption_key",
+            "record_dir",
+            "replay_dir",
+            "log_format",
+            "log_file",
+            "log_keep",
+            "db_backup_every_hours",
+            "db_backup_keep",
+            "db_backup_gzip",
+            "db_backup_upload",
+        ],
+    ),
+    (
+        "media",
+        &[
+            "media_types",
+            "media_max_size_mb",
+            "media_queue_size",
+            "media_upload",
+            "media_delete_local",
+            "media_hash",
+        ],
+    ),
+    (
+        "s3",
+        &[
+            "s3_endpoint",
+            "s3_bucket",
+            "s3_region",
+            "s3_access_key",
+            "s3_secret_key",
+            "s3_prefix",
+        ],
+    ),
+    (
+        "ai",
+        &[
+            "ai_provider",
+            "ai_api_key",
+            "ai_api_url",
+            "ai_model",
+            "ai_max_retries",
+            "ai_concurrency",
+            "ai_max_context_tokens",
+            "ai_prices",
+            "ai_language",
+            "ai_anonymize",
+            "anonymize_map",
+            "ai_reply_context",
+            "digest_to_telegram",
+            "report_formats",
+            "http_proxy",
+        ],
+    ),
+    (
+        "trello",
+        &[
+            "trello_key",
+            "trello_token",
+            "trello_board_id",
+            "trello_list_id",
+            "trello_label_id",
+            "trello_request_delay_ms",
+        ],
+    ),
+    (
+        "watcher",
+        &[
+            "watcher_cycle_secs",
+            "alert_chat_id",
+            "alert_mode",
+            "alert_cooldown_secs",
+            "auto_backup_every_hours",
+            "auto_backup_scope",
+            "auto_backup_media",
+            "heartbeat_url",
+            "heartbeat_method",
+            "heartbeat_daily_hour",
+        ],
+    ),
+    (
+        "notify",
+        &[
+            "webhook_url",
+            "webhook_format",
+            "smtp_url",
+            "smtp_from",
+            "smtp_to",
+        ],
+    ),
+];
+
+/// Fields masked when the configuration is printed. URLs are included: they may carry
+/// passwords or tokens.
+const SECRETS: &[&str] = &[
+    "api_hash",
+    "password",
+    "bot_token",
+    "encryption_key",
+    "ai_api_key",
+    "s3_access_key",
+    "s3_secret_key",
+    "trello_key",
+    "trello_token",
+    "smtp_url",
+    "webhook_url",
+    "heartbeat_url",
+    "proxy_url",
+    "http_proxy",
+];
+
+/// Variables read without the `TG_SYNC_` prefix and their fields. They win over the
+/// prefixed form.
+pub const ENV_ALIASES: &[(&str, &str)] = &[
+    ("EXPORT_DELAY_MS", "export_delay_ms"),
+    ("SYNC_DELAY_MS", "sync_delay_ms"),
+    ("TRELLO_KEY", "trello_key"),
+    ("TRELLO_TOKEN", "trello_token"),
+    ("TRELLO_BOARD_ID", "trello_board_id"),
+    ("TRELLO_LIST_ID", "trello_list_id"),
+    ("TRELLO_LABEL_ID", "trello_label_id"),
+];
+
+/// `TG_SYNC_` variables that are not configuration values.
+const ENV_NOT_FIELDS: &[&str] = &["TG_SYNC_CONFIG", "TG_SYNC_PROFILE"];
+
+const ENV_PREFIX: &str = "TG_SYNC_";
+
+/// The known field named `name`.
+fn field(name: &str) -> Option<&'static str> {
+    SECTIONS
+        .iter()
+        .flat_map(|(_, fields)| fields.iter())
+        .find(|f| **f == name)
+        .copied()
+}
+
+/// Section holding `field`.
+pub fn section_of(field: &str) -> Option<&'static str> {
+    SECTIONS
+        .iter()
+        .find(|(_, fields)| fields.contains(&field))
+        .map(|(section, _)| *section)
+}
+
+/// Key of `field` in its section (`api_key` for `ai_api_key`).
+pub fn key_in_section(field: &str) -> &str {
+    section_of(field)
+        .and_then(|section| field.strip_prefix(section)?.strip_prefix('_'))
+        .unwrap_or(field)
+}
+
+/// Dotted config file key of `field` (`ai.api_key`); None for unknown fields.
+pub fn file_key(field: &str) -> Option<String> {
+    section_of(field).map(|section| format!("{}.{}", section, key_in_section(field)))
+}
+
+/// Environment variable of `field` (an alias where there is one).
+pub fn env_var(field: &str) -> String {
+    ENV_ALIASES
+        .iter()
+        .find(|(_, f)| *f == field)
+        .map(|(name, _)| name.to_string())
+        .unwrap_or_else(|| format!("{}{}", ENV_PREFIX, field.to_uppercase()))
+}
+
+pub fn is_secret(field: &str) -> bool {
+    SECRETS.contains(&field)
+}
+
+/// Field of the config file entry `key` in `section`, or at the top level with `None` (a bare
+/// field name, as in config files written before the sections).
+pub fn field_for_file_key(section: Option<&str>, key: &str) -> Option<&'static str> {
+    match section {
+        None => field(key),
+        Some(section) => {
+            let (_, fields) = SECTIONS.iter().find(|(s, _)| *s == section)?;
+            fields.iter().find(|f| key_in_section(f) == key).copied()
+        }
+    }
+}
+
+/// Whether `name` is a config file section.
+pub fn is_section(name: &str) -> bool {
+    SECTIONS.iter().any(|(section, _)| *section == name)
+}
+
+/// Field set by the environment variable `name`; None for other variables.
+pub fn field_for_env(name: &str) -> Option<&'static str> {
+    if let Some((_, field)) = ENV_ALIASES.iter().find(|(alias, _)| *alias == name) {
+        return Some(field);
+    }
+    field(&name.strip_prefix(ENV_PREFIX)?.to_lowercase())
+}
+
+/// Whether `name` looks like a tg-sync variable but sets nothing (a typo).
+pub fn is_unknown_env(name: &str) -> bool {
+    name.starts_with(ENV_PREFIX) && field_for_env(name).is_none() && !ENV_NOT_FIELDS.contains(&name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_of_fields() {
+        assert_eq!(file_key("ai_api_key").as_deref(), Some("ai.api_key"));
+        assert_eq!(file_key("media_types").as_deref(), Some("media.types"));
+        assert_eq!(file_key("api_id").as_deref(), Some("telegram.api_id"));
+        assert_eq!(
+            file_key("anonymize_map").as_deref(),
+            Some("ai.anonymize_map")
+        );
+        assert_eq!(file_key("nope"), None);
+        assert_eq!(env_var("watcher_cycle_secs"), "TG_SYNC_WATCHER_CYCLE_SECS");
+        assert_eq!(env_var("trello_list_id"), "TRELLO_LIST_ID");
+
+        assert_eq!(field_for_file_key(Some("s3"), "bucket"), Some("s3_bucket"));
+        assert_eq!(field_for_file_key(Some("s3"), "s3_bucket"), None);
+        assert_eq!(field_for_file_key(Some("trello"), "api_id"), None);
+        assert_eq!(field_for_file_key(None, "ai_model"), Some("ai_model"));
+        assert_eq!(field_for_env("TG_SYNC_AI_MODEL"), Some("ai_model"));
+        assert_eq!(field_for_env("SYNC_DELAY_MS"), Some("sync_delay_ms"));
+        assert_eq!(field_for_env("PATH"), None);
+        assert!(is_unknown_env("TG_SYNC_AI_MODLE"));
+        assert!(!is_unknown_env("TG_SYNC_CONFIG"));
+        assert!(!is_unknown_env("HOME"));
+    }
+
+    #[test]
+    fn test_keys_are_unique_within_sections() {
+        for (section, fields) in SECTIONS {
+            for (i, field) in fields.iter().enumerate() {
+                assert_eq!(section_of(field), Some(*section), "{} listed twice", field);
+                assert!(
+                    fields[..i]
+                        .iter()
+                        .all(|f| key_in_section(f) != key_in_section(field)),
+                    "{}.{} is ambiguous",
+                    section,
+                    key_in_section(field)
+                );
+            }
+        }
+        assert!(SECRETS.iter().all(|f| field(f).is_some()));
+    }
+}
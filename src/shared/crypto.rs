@@ -0,0 +1,334 @@
+//! Archive encryption at rest (TG_SYNC_ENCRYPTION_KEY).
+//!
+//! AES-256-GCM under keys derived from one archive secret: 64 hex characters are used as the
+//! key itself, anything else is a passphrase stretched with PBKDF2-HMAC-SHA256 over a
+//! per-archive salt.
+//!
+//! - Database fields are sealed into `enc1:<base64(nonce || ciphertext)>` strings. The nonce is
+//!   an HMAC of the field's context and plaintext, so sealing the same value of the same field
+//!   twice gives the same string: the repository can still compare stored and new text (edit
+//!   detection) without decrypting. The context (`text:<chat_id>:<id>`) is also the associated
+//!   data, so a sealed value cannot be moved to another message. Empty strings stay empty.
+//! - Files are sealed in 64 KiB chunks under a random per-file nonce prefix stored in the
+//!   header; each chunk is authenticated with its index and a last-chunk flag, so reordered,
+//!   truncated or extended files fail to open.
+
+use crate::domain::DomainError;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// Prefix of sealed database values.
+pub const SEALED_PREFIX: &str = "enc1:";
+
+/// Extension appended to encrypted media files (`1_2.jpg.enc`).
+pub const ENCRYPTED_EXTENSION: &str = "enc";
+
+/// Length of the per-archive passphrase salt.
+pub const SALT_LEN: usize = 16;
+
+/// PBKDF2 rounds for passphrases (OWASP 2023 recommendation for HMAC-SHA256).
+const PBKDF2_ROUNDS: u32 = 600_000;
+
+/// Header of an encrypted file, followed by the nonce prefix.
+const FILE_MAGIC: &[u8; 8] = b"TGSENC01";
+/// Random part of the file nonces; the remaining 4 bytes count chunks.
+const FILE_NONCE_PREFIX: usize = 8;
+/// Plaintext bytes per sealed file chunk.
+const FILE_CHUNK: usize = 64 * 1024;
+/// GCM authentication tag per chunk.
+const TAG_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Encrypts and decrypts archive data with one key.
+pub struct ArchiveCipher {
+    aead: Aes256Gcm,
+    /// Keys the synthetic nonces of sealed database values.
+    nonce_key: [u8; 32],
+}
+
+impl ArchiveCipher {
+    /// Cipher for `secret`: 64 hex characters are the raw key, anything else is a passphrase
+    /// stretched over `salt`.
+    pub fn from_secret(secret: &str, salt: &[u8]) -> Result<Self, DomainError> {
+        let secret = secret.trim();
+        if secret.is_empty() {
+            return Err(DomainError::Crypto("empty encryption key".to_string()));
+        }
+        let master = match decode_hex_key(secret) {
+            Some(key) => key,
+            None => pbkdf2::pbkdf2_hmac_array::<Sha256, 32>(secret.as_bytes(), salt, PBKDF2_ROUNDS),
+        };
+        Ok(Self::from_master(&master))
+    }
+
+    fn from_master(master: &[u8; 32]) -> Self {
+        let aead = Aes256Gcm::new_from_slice(&subkey(master, b"tg-sync archive encryption"))
+            .expect("32-byte key");
+        Self {
+            aead,
+            nonce_key: subkey(master, b"tg-sync archive nonce"),
+        }
+    }
+
+    /// Fresh random salt for a new archive.
+    pub fn new_salt() -> [u8; SALT_LEN] {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        salt
+    }
+
+    /// True when `stored` carries the sealed prefix. Legacy plaintext may carry it too; only
+    /// [`Self::open`] tells for sure.
+    pub fn is_sealed(stored: &str) -> bool {
+        stored.starts_with(SEALED_PREFIX)
+    }
+
+    /// Seal `plaintext` bound to `context`. Deterministic for equal inputs.
+    pub fn seal(&self, context: &str, plaintext: &str) -> String {
+        if plaintext.is_empty() {
+            return String::new();
+        }
+        let mut mac = <HmacSha256 as Mac>::new_from_slice(&self.nonce_key).expect("any key length");
+        mac.update(context.as_bytes());
+        mac.update(&[0]);
+        mac.update(plaintext.as_bytes());
+        let digest = mac.finalize().into_bytes();
+        let nonce = Nonce::from_slice(&digest[..NONCE_LEN]);
+        let ciphertext = self
+            .aead
+            .encrypt(
+                nonce,
+                Payload {
+                    msg: plaintext.as_bytes(),
+                    aad: context.as_bytes(),
+                },
+            )
+            .expect("in-memory encryption");
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        format!("{}{}", SEALED_PREFIX, BASE64.encode(sealed))
+    }
+
+    /// Open a value sealed with `context`. Values that are not sealed (written before
+    /// encryption was enabled) are returned as they are.
+    pub fn open(&self, context: &str, stored: &str) -> Result<String, DomainError> {
+        let Some(encoded) = stored.strip_prefix(SEALED_PREFIX) else {
+            return Ok(stored.to_string());
+        };
+        let bytes = BASE64
+            .decode(encoded)
+            .map_err(|e| DomainError::Crypto(format!("malformed sealed value: {}", e)))?;
+        if bytes.len() < NONCE_LEN + TAG_LEN {
+            return Err(DomainError::Crypto("truncated sealed value".to_string()));
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let plaintext = self
+            .aead
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: context.as_bytes(),
+                },
+            )
+            .map_err(|_| DomainError::Crypto(format!("cannot decrypt {} (wrong key?)", context)))?;
+        String::from_utf8(plaintext).map_err(|e| DomainError::Crypto(e.to_string()))
+    }
+
+    /// Encrypt file `src` into `dst` (blocking I/O). Returns the size of `dst`.
+    pub fn seal_file(&self, src: &Path, dst: &Path) -> Result<u64, DomainError> {
+        let mut input = File::open(src).map_err(io_err)?;
+        let mut output = File::create(dst).map_err(io_err)?;
+        let mut prefix = [0u8; FILE_NONCE_PREFIX];
+        OsRng.fill_bytes(&mut prefix);
+        output.write_all(FILE_MAGIC).map_err(io_err)?;
+        output.write_all(&prefix).map_err(io_err)?;
+        let mut written = (FILE_MAGIC.len() + FILE_NONCE_PREFIX) as u64;
+
+        // One chunk of look-ahead tells whether the current chunk is the last one.
+        let mut current = read_chunk(&mut input, FILE_CHUNK)?;
+        let mut index = 0u32;
+        loop {
+            let next = read_chunk(&mut input, FILE_CHUNK)?;
+            let last = next.is_empty();
+            let sealed = self
+                .aead
+                .encrypt(
+                    &file_nonce(&prefix, index),
+                    Payload {
+                        msg: &current,
+                        aad: &[last as u8],
+                    },
+                )
+                .expect("in-memory encryption");
+            output.write_all(&sealed).map_err(io_err)?;
+            written += sealed.len() as u64;
+            if last {
+                break;
+            }
+            current = next;
+            index = index
+                .checked_add(1)
+                .ok_or_else(|| DomainError::Crypto("file too large".to_string()))?;
+        }
+        output.sync_all().map_err(io_err)?;
+        Ok(written)
+    }
+
+    /// Decrypt file `src` (written by [`Self::seal_file`]) into `dst` (blocking I/O).
+    pub fn open_file(&self, src: &Path, dst: &Path) -> Result<(), DomainError> {
+        let mut input = File::open(src).map_err(io_err)?;
+        let mut header = [0u8; FILE_MAGIC.len() + FILE_NONCE_PREFIX];
+        input.read_exact(&mut header).map_err(|_| {
+            DomainError::Crypto(format!("{}: not an encrypted file", src.display()))
+        })?;
+        let (magic, prefix) = header.split_at(FILE_MAGIC.len());
+        if magic != FILE_MAGIC {
+            return Err(DomainError::Crypto(format!(
+                "{}: not an encrypted file",
+                src.display()
+            )));
+        }
+        let mut output = File::create(dst).map_err(io_err)?;
+        let mut current = read_chunk(&mut input, FILE_CHUNK + TAG_LEN)?;
+        let mut index = 0u32;
+        loop {
+            let next = read_chunk(&mut input, FILE_CHUNK + TAG_LEN)?;
+            let last = next.is_empty();
+            let plain = self
+                .aead
+                .decrypt(
+                    &file_nonce(prefix, index),
+                    Payload {
+                        msg: &current,
+                        aad: &[last as u8],
+                    },
+                )
+                .map_err(|_| {
+                    DomainError::Crypto(format!(
+                        "{}: cannot decrypt chunk {} (wrong key or damaged file)",
+                        src.display(),
+                        index
+                    ))
+                })?;
+            output.write_all(&plain).map_err(io_err)?;
+            if last {
+                return Ok(());
+            }
+            current = next;
+            index = index.wrapping_add(1);
+        }
+    }
+}
+
+/// HMAC-SHA256 of `label` under the master key.
+fn subkey(master: &[u8; 32], label: &[u8]) -> [u8; 32] {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(master).expect("any key length");
+    mac.update(label);
+    mac.finalize().into_bytes().into()
+}
+
+/// Size of the file [`ArchiveCipher::seal_file`] writes for `plain_len` bytes: the header
+/// plus one tag per chunk (an empty file still has one chunk).
+pub fn sealed_file_len(plain_len: u64) -> u64 {
+    let chunks = plain_len.div_ceil(FILE_CHUNK as u64).max(1);
+    (FILE_MAGIC.len() + FILE_NONCE_PREFIX) as u64 + plain_len + chunks * TAG_LEN as u64
+}
+
+fn file_nonce(prefix: &[u8], index: u32) -> Nonce<aes_gcm::aead::consts::U12> {
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce[..FILE_NONCE_PREFIX].copy_from_slice(prefix);
+    nonce[FILE_NONCE_PREFIX..].copy_from_slice(&index.to_be_bytes());
+    nonce.into()
+}
+
+/// Up to `len` bytes; shorter only at the end of the input.
+fn read_chunk(input: &mut impl Read, len: usize) -> Result<Vec<u8>, DomainError> {
+    let mut chunk = Vec::with_capacity(len);
+    input
+        .take(len as u64)
+        .read_to_end(&mut chunk)
+        .map_err(io_err)?;
+    Ok(chunk)
+}
+
+/// A 64-character hex string as a raw 32-byte key.
+fn decode_hex_key(s: &str) -> Option<[u8; 32]> {
+    if s.len() != 64 || !s.is_ascii() {
+        return None;
+    }
+    let mut key = [0u8; 32];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&s[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(key)
+}
+
+fn io_err(e: std::io::Error) -> DomainError {
+    DomainError::Crypto(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    #[test]
+    fn test_seal_is_deterministic_and_bound_to_context() {
+        let cipher = ArchiveCipher::from_secret(KEY, &[]).unwrap();
+        let sealed = cipher.seal("text:1:2", "hello");
+        assert!(ArchiveCipher::is_sealed(&sealed));
+        assert!(!sealed.contains("hello"));
+        assert_eq!(sealed, cipher.seal("text:1:2", "hello"));
+        assert_ne!(sealed, cipher.seal("text:1:3", "hello"));
+        assert_eq!(cipher.open("text:1:2", &sealed).unwrap(), "hello");
+        // Moved to another message: rejected.
+        assert!(matches!(
+            cipher.open("text:1:3", &sealed),
+            Err(DomainError::Crypto(_))
+        ));
+        // Empty and legacy plaintext values pass through.
+        assert_eq!(cipher.seal("text:1:2", ""), "");
+        assert_eq!(cipher.open("text:1:2", "plain").unwrap(), "plain");
+
+        let other = ArchiveCipher::from_secret(&KEY.replace("1f", "ff"), &[]).unwrap();
+        assert!(other.open("text:1:2", &sealed).is_err());
+    }
+
+    #[test]
+    fn test_file_round_trip_and_tamper_detection() {
+        let dir = std::env::temp_dir().join(format!("tg-sync-crypto-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cipher = ArchiveCipher::from_secret(KEY, &[]).unwrap();
+        for len in [0, 10, FILE_CHUNK, 2 * FILE_CHUNK + 7] {
+            let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            let (plain, sealed, opened) = (dir.join("p"), dir.join("s"), dir.join("o"));
+            std::fs::write(&plain, &data).unwrap();
+            let size = cipher.seal_file(&plain, &sealed).unwrap();
+            assert_eq!(size, std::fs::metadata(&sealed).unwrap().len());
+            assert_eq!(size, sealed_file_len(len as u64), "len {}", len);
+            cipher.open_file(&sealed, &opened).unwrap();
+            assert_eq!(std::fs::read(&opened).unwrap(), data, "len {}", len);
+
+            // Dropping the last chunk must not go unnoticed.
+            if len > FILE_CHUNK {
+                let bytes = std::fs::read(&sealed).unwrap();
+                std::fs::write(&sealed, &bytes[..16 + FILE_CHUNK + TAG_LEN]).unwrap();
+                assert!(cipher.open_file(&sealed, &opened).is_err());
+            }
+        }
+        assert!(cipher.open_file(&dir.join("p"), &dir.join("o")).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,167 @@
+//! Rolling ETA for long backfills.
+//!
+//! [`EtaEstimator`] keeps an exponential moving average of per-batch throughput
+//! (messages/sec, measured over the whole batch including the rate-limit sleep).
+//! Averaging the *rate* rather than the batch duration keeps a single FloodWait
+//! spike from multiplying the ETA: a 60 s stall pulls the rate down by `alpha`,
+//! not by two orders of magnitude.
+
+use std::time::Duration;
+
+/// Default smoothing factor: weight of the newest batch in the moving average.
+pub const DEFAULT_ALPHA: f64 = 0.2;
+
+/// Pure throughput/ETA estimator. Feed it batch timings; ask for the rate and remaining time.
+#[derive(Debug, Clone)]
+pub struct EtaEstimator {
+    alpha: f64,
+    /// Smoothed messages per second. None until the first non-empty batch.
+    rate: Option<f64>,
+}
+
+impl Default for EtaEstimator {
+    fn default() -> Self {
+        Self::new(DEFAULT_ALPHA)
+    }
+}
+
+impl EtaEstimator {
+    /// `alpha` in (0, 1]: higher reacts faster, lower smooths more. Clamped into range.
+    pub fn new(alpha: f64) -> Self {
+        Self {
+            alpha: alpha.clamp(f64::EPSILON, 1.0),
+            rate: None,
+        }
+    }
+
+    /// Record one batch of `messages` that took `elapsed` (fetch + save + sleep).
+    /// Empty or zero-duration batches carry no throughput information and are ignored.
+    pub fn record_batch(&mut self, messages: u64, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        if messages == 0 || secs <= 0.0 {
+            return;
+        }
+        let sample = messages as f64 / secs;
+        self.rate = Some(match self.rate {
+            None => sample,
+            Some(prev) => self.alpha * sample + (1.0 - self.alpha) * prev,
+        });
+    }
+
+    /// Smoothed throughput in messages per second, if any batch has been recorded.
+    pub fn rate_per_sec(&self) -> Option<f64> {
+        self.rate
+    }
+
+    /// Estimated time to sync `remaining` messages. None when the total is unknown
+    /// or no throughput has been measured yet.
+    pub fn eta(&self, remaining: Option<u64>) -> Option<Duration> {
+        let remaining = remaining?;
+        let rate = self.rate.filter(|r| *r > 0.0)?;
+        Some(Duration::from_secs_f64(remaining as f64 / rate))
+    }
+}
+
+/// Human-friendly ETA, e.g. `approx 2h 40m remaining`, or `ETA unknown`.
+pub fn format_eta(eta: Option<Duration>) -> String {
+    let Some(eta) = eta else {
+        return "ETA unknown".to_string();
+    };
+    format!("approx {} remaining", format_duration(eta))
+}
+
+/// Coarse duration, e.g. `2h 40m`, `2m` or `42s`.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (h, m, s) = (secs / 3600, (secs % 3600) / 60, secs % 60);
+    if h > 0 {
+        format!("{}h {}m", h, m)
+    } else if m > 0 {
+        format!("{}m", m)
+    } else {
+        format!("{}s", s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn test_unknown_until_measured_or_without_total() {
+        let mut est = EtaEstimator::default();
+        assert_eq!(est.rate_per_sec(), None);
+        assert_eq!(est.eta(Some(1000)), None);
+
+        est.record_batch(100, ms(1000));
+        assert_eq!(est.eta(None), None, "unknown total stays unknown");
+        assert_eq!(est.eta(Some(1000)), Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn test_steady_batches_converge() {
+        let mut est = EtaEstimator::default();
+        // 100 messages every 500 ms fetch + 500 ms sleep = 100 msg/s.
+        for _ in 0..20 {
+            est.record_batch(100, ms(1000));
+        }
+        let rate = est.rate_per_sec().unwrap();
+        assert!((rate - 100.0).abs() < 1e-9);
+        // 300k messages at 100 msg/s = 50 minutes.
+        assert_eq!(est.eta(Some(300_000)), Some(Duration::from_secs(3000)));
+    }
+
+    #[test]
+    fn test_flood_wait_spike_is_smoothed() {
+        let mut est = EtaEstimator::default();
+        for _ in 0..10 {
+            est.record_batch(100, ms(1000));
+        }
+        let before = est.eta(Some(10_000)).unwrap();
+
+        // A single 60 s FloodWait batch.
+        est.record_batch(100, Duration::from_secs(60));
+        let after_spike = est.eta(Some(10_000)).unwrap();
+        // Naive per-batch estimate would be 60x; the EMA keeps it within ~1.25x.
+        assert!(after_spike > before);
+        assert!(after_spike.as_secs_f64() < before.as_secs_f64() * 1.3);
+
+        // Back to normal speed: the estimate recovers towards the steady state.
+        for _ in 0..20 {
+            est.record_batch(100, ms(1000));
+        }
+        let recovered = est.eta(Some(10_000)).unwrap();
+        assert!((recovered.as_secs_f64() - before.as_secs_f64()).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_empty_and_zero_duration_batches_ignored() {
+        let mut est = EtaEstimator::default();
+        est.record_batch(0, ms(1000));
+        est.record_batch(100, Duration::ZERO);
+        assert_eq!(est.rate_per_sec(), None);
+    }
+
+    #[test]
+    fn test_format_eta() {
+        let cases: &[(Option<u64>, &str)] = &[
+            (None, "ETA unknown"),
+            (Some(9600), "approx 2h 40m remaining"),
+            (Some(125), "approx 2m remaining"),
+            (Some(42), "approx 42s remaining"),
+            (Some(0), "approx 0s remaining"),
+        ];
+        for (secs, expected) in cases {
+            assert_eq!(
+                format_eta(secs.map(Duration::from_secs)),
+                *expected,
+                "{:?}",
+                secs
+            );
+        }
+    }
+}
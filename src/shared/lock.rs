@@ -0,0 +1,268 @@
+//! Advisory data-directory lock: `data/.lock` with the owner's PID, hostname and start time.
+//!
+//! Prevents two instances (e.g. a cron job and an interactive session) from syncing into the
+//! same archive. Acquired with an atomic create-exclusive; a lock left behind by a dead
+//! process on this host is broken automatically, anything else needs `--force`. The lock is
+//! removed when the guard is dropped.
+
+use crate::domain::DomainError;
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Lock file name inside the data directory.
+pub const LOCK_FILE: &str = ".lock";
+
+/// Contents of the lock file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockInfo {
+    pub pid: u32,
+    pub hostname: String,
+    /// Unix timestamp.
+    pub started_at: i64,
+}
+
+impl LockInfo {
+    /// Info describing this process.
+    pub fn current() -> Self {
+        Self {
+            pid: std::process::id(),
+            hostname: hostname(),
+            started_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs() as i64,
+        }
+    }
+}
+
+/// Held lock. Removes the lock file on drop.
+#[derive(Debug)]
+pub struct DataDirLock {
+    path: PathBuf,
+}
+
+impl DataDirLock {
+    /// Acquire the lock for `data_dir`. With `force`, a lock held by another process is broken.
+    pub fn acquire(data_dir: &Path, force: bool) -> Result<Self, DomainError> {
+        Self::acquire_as(data_dir, force, &LockInfo::current(), &pid_alive)
+    }
+
+    /// [`DataDirLock::acquire`] with the owner info and liveness check supplied (tests).
+    fn acquire_as(
+        data_dir: &Path,
+        force: bool,
+        me: &LockInfo,
+        is_alive: &dyn Fn(u32) -> bool,
+    ) -> Result<Self, DomainError> {
+        let path = data_dir.join(LOCK_FILE);
+        // Second attempt only after removing a stale or forced lock.
+        for _ in 0..2 {
+            match create_exclusive(&path, me) {
+                Ok(()) => {
+                    info!(path = %path.display(), pid = me.pid, "data directory locked");
+                    return Ok(Self { path });
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+                Err(e) => {
+                    return Err(DomainError::State(format!(
+                        "cannot create lock file {}: {}",
+                        path.display(),
+                        e
+                    )));
+                }
+            }
+
+            let holder = read_lock(&path);
+            match &holder {
+                Some(h) if !force && is_held(h, me, is_alive) => {
+                    return Err(DomainError::State(format!(
+                        "data directory {} is in use by tg-sync (pid {} on {}, started {}). \
+                         Stop that instance, or pass --force if it is not running.",
+                        data_dir.display(),
+                        h.pid,
+                        h.hostname,
+                        format_started(h.started_at)
+                    )));
+                }
+                Some(h) => warn!(
+                    pid = h.pid,
+                    host = %h.hostname,
+                    forced = force,
+                    "breaking data directory lock"
+                ),
+                None => warn!(path = %path.display(), "breaking unreadable data directory lock"),
+            }
+            if let Err(e) = std::fs::remove_file(&path) {
+                if e.kind() != ErrorKind::NotFound {
+                    return Err(DomainError::State(format!(
+                        "cannot remove stale lock {}: {}",
+                        path.display(),
+                        e
+                    )));
+                }
+            }
+        }
+        Err(DomainError::State(format!(
+            "data directory {} was locked again by another instance",
+            data_dir.display()
+        )))
+    }
+
+    /// Path of the lock file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for DataDirLock {
+    fn drop(&mut self) {
+        match std::fs::remove_file(&self.path) {
+            Ok(()) => info!(path = %self.path.display(), "data directory unlocked"),
+            Err(e) => warn!(path = %self.path.display(), error = %e, "failed to remove lock file"),
+        }
+    }
+}
+
+/// A lock is held unless its owner is a dead process on this host. Owners on other hosts
+/// (shared/network data dirs) cannot be checked and count as held. Our own PID on our host
+/// means a reused PID (e.g. PID 1 in a restarted container), so the lock is stale.
+fn is_held(holder: &LockInfo, me: &LockInfo, is_alive: &dyn Fn(u32) -> bool) -> bool {
+    holder.hostname != me.hostname || (holder.pid != me.pid && is_alive(holder.pid))
+}
+
+fn create_exclusive(path: &Path, me: &LockInfo) -> std::io::Result<()> {
+    let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    let json = serde_json::to_string(me).map_err(std::io::Error::other)?;
+    file.write_all(json.as_bytes())?;
+    file.sync_all()
+}
+
+fn read_lock(path: &Path) -> Option<LockInfo> {
+    let s = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&s).ok()
+}
+
+fn format_started(ts: i64) -> String {
+    DateTime::from_timestamp(ts, 0)
+        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_else(|| ts.to_string())
+}
+
+/// Best-effort hostname without extra dependencies.
+fn hostname() -> String {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "localhost".to_string())
+}
+
+/// Whether a process with `pid` exists on this host.
+#[cfg(target_os = "linux")]
+pub fn pid_alive(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+pub fn pid_alive(pid: u32) -> bool {
+    std::process::Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(true)
+}
+
+/// No portable check: assume alive (use --force to break).
+#[cfg(not(unix))]
+pub fn pid_alive(_pid: u32) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn info(pid: u32, host: &str) -> LockInfo {
+        LockInfo {
+            pid,
+            hostname: host.to_string(),
+            started_at: 1704067200,
+        }
+    }
+
+    #[test]
+    fn test_acquire_refuses_live_holder_and_releases_on_drop() {
+        let dir = temp_dir("test_lock_live");
+        let alive = |_: u32| true;
+
+        let lock = DataDirLock::acquire_as(&dir, false, &info(100, "box"), &alive).unwrap();
+        assert_eq!(read_lock(lock.path()), Some(info(100, "box")));
+
+        let err = DataDirLock::acquire_as(&dir, false, &info(200, "box"), &alive).unwrap_err();
+        let msg = err.to_string();
+        assert!(msg.contains("in use by tg-sync (pid 100 on box"), "{}", msg);
+        assert!(msg.contains("2024-01-01 00:00:00 UTC"), "{}", msg);
+
+        drop(lock);
+        assert!(!dir.join(LOCK_FILE).exists());
+        let _again = DataDirLock::acquire_as(&dir, false, &info(200, "box"), &alive).unwrap();
+    }
+
+    #[test]
+    fn test_stale_lock_is_broken() {
+        let dir = temp_dir("test_lock_stale");
+        std::fs::write(
+            dir.join(LOCK_FILE),
+            serde_json::to_string(&info(4_000_000, "box")).unwrap(),
+        )
+        .unwrap();
+
+        let lock = DataDirLock::acquire_as(&dir, false, &info(200, "box"), &|pid| pid != 4_000_000)
+            .unwrap();
+        assert_eq!(read_lock(lock.path()).unwrap().pid, 200);
+        drop(lock);
+
+        // Same PID as ours on the same host: a reused PID, not a live holder.
+        std::fs::write(
+            dir.join(LOCK_FILE),
+            serde_json::to_string(&info(1, "box")).unwrap(),
+        )
+        .unwrap();
+        let lock = DataDirLock::acquire_as(&dir, false, &info(1, "box"), &|_| true).unwrap();
+        drop(lock);
+
+        // Unreadable lock files count as stale too.
+        std::fs::write(dir.join(LOCK_FILE), "garbage").unwrap();
+        assert!(DataDirLock::acquire_as(&dir, false, &info(200, "box"), &|_| true).is_ok());
+    }
+
+    #[test]
+    fn test_other_host_needs_force() {
+        let dir = temp_dir("test_lock_force");
+        let dead = |_: u32| false;
+        let held = DataDirLock::acquire_as(&dir, false, &info(100, "nas"), &dead).unwrap();
+
+        // Dead on our host, but the holder is another machine: cannot tell, so refuse.
+        assert!(DataDirLock::acquire_as(&dir, false, &info(200, "box"), &dead).is_err());
+
+        let forced = DataDirLock::acquire_as(&dir, true, &info(200, "box"), &|_| true).unwrap();
+        assert_eq!(read_lock(forced.path()).unwrap().hostname, "box");
+        std::mem::forget(held);
+    }
+}
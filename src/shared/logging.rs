@@ -0,0 +1,110 @@
+//! Log output: the console in text or JSON (TG_SYNC_LOG_FORMAT) and optionally a log file
+//! rotated daily (TG_SYNC_LOG_FILE, the newest TG_SYNC_LOG_KEEP files are kept).
+//!
+//! Hot paths log structured fields rather than interpolated strings, under the same names
+//! everywhere so JSON lines can be filtered: `chat_id`, `msg_id`, `batch_size`, `wait_secs`
+//! and `error`.
+
+use crate::shared::config::LogFormat;
+use std::path::Path;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::Layer;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::registry::Registry;
+
+/// A formatting layer; the console and the file each get one.
+pub type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// Formatting layer writing `format` lines to `writer`. `ansi` enables colors in text lines.
+pub fn fmt_layer<W>(format: LogFormat, writer: W, ansi: bool) -> BoxedLayer
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer().with_writer(writer);
+    match format {
+        LogFormat::Text => layer.with_ansi(ansi).boxed(),
+        LogFormat::Json => layer.json().flatten_event(true).boxed(),
+    }
+}
+
+/// Daily-rotated appender for `path`: `tg-sync.log` becomes `tg-sync.YYYY-MM-DD.log` in the
+/// same directory, which is created if needed. Only the newest `keep` files are kept.
+pub fn rolling_file(path: &Path, keep: usize) -> std::io::Result<RollingFileAppender> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "tg-sync".to_string());
+    let mut builder = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(stem)
+        .max_log_files(keep.max(1));
+    if let Some(ext) = path.extension() {
+        builder = builder.filename_suffix(ext.to_string_lossy());
+    }
+    std::fs::create_dir_all(dir)?;
+    builder.build(dir).map_err(std::io::Error::other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_json_lines_carry_fields_at_top_level() {
+        let buf = Arc::new(Mutex::new(Vec::new()));
+        let writer = {
+            let buf = buf.clone();
+            move || SharedBuf(buf.clone())
+        };
+        let subscriber =
+            tracing_subscriber::registry().with(fmt_layer(LogFormat::Json, writer, false));
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::warn!(chat_id = -100i64, wait_secs = 30u64, "FloodWait");
+        });
+        let out = String::from_utf8(buf.lock().unwrap().clone()).unwrap();
+        let line: serde_json::Value = serde_json::from_str(out.trim()).unwrap();
+        assert_eq!(line["level"], "WARN");
+        assert_eq!(line["message"], "FloodWait");
+        assert_eq!(line["chat_id"], -100);
+        assert_eq!(line["wait_secs"], 30);
+    }
+
+    #[test]
+    fn test_rolling_file_is_dated_and_created() {
+        let base = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join("test_logging");
+        let _ = std::fs::remove_dir_all(&base);
+        let appender = rolling_file(&base.join("logs").join("tg-sync.log"), 3).unwrap();
+        let subscriber =
+            tracing_subscriber::registry().with(fmt_layer(LogFormat::Text, appender, false));
+        tracing::subscriber::with_default(subscriber, || tracing::info!(msg_id = 7, "saved"));
+        let names: Vec<String> = std::fs::read_dir(base.join("logs"))
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        let today = chrono::Utc::now().format("%Y-%m-%d");
+        assert_eq!(names, vec![format!("tg-sync.{}.log", today)]);
+        let text = std::fs::read_to_string(base.join("logs").join(&names[0])).unwrap();
+        assert!(text.contains("saved msg_id=7"));
+    }
+
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for SharedBuf {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(data);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+}
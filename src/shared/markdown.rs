@@ -0,0 +1,333 @@
+//! Minimal Markdown → HTML (digest emails) and → plain text (digests posted to Telegram)
+//! for the reports this app writes.
+//!
+//! Covers what `AnalysisService` and the watcher digest emit: `#`/`##`/`###` headings,
+//! `---` rules, `-` lists (incl. `- [ ]` task items), fenced code blocks, paragraphs,
+//! and inline `**bold**`, `*italic*` and `` `code` ``. Everything else passes through as
+//! escaped text; this is not a general CommonMark implementation.
+
+/// Render Markdown as a standalone HTML page titled `title`.
+pub fn to_html_document(title: &str, md: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n\
+         <body style=\"font-family: sans-serif; max-width: 42em; margin: auto; line-height: 1.4\">\n\
+         {}</body>\n</html>\n",
+        escape(title),
+        to_html(md)
+    )
+}
+
+/// Render Markdown to an HTML fragment (no `<html>`/`<body>` wrapper).
+pub fn to_html(md: &str) -> String {
+    let mut html = String::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut in_list = false;
+    let mut in_code = false;
+
+    for line in md.lines() {
+        if in_code {
+            if line.trim_start().starts_with("```") {
+                html.push_str("</code></pre>\n");
+                in_code = false;
+            } else {
+                html.push_str(&escape(line));
+                html.push('\n');
+            }
+            continue;
+        }
+
+        let trimmed = line.trim();
+        let item = trimmed
+            .strip_prefix("- ")
+            .or_else(|| trimmed.strip_prefix("* "));
+        if item.is_none() && in_list {
+            html.push_str("</ul>\n");
+            in_list = false;
+        }
+        if item.is_some() || trimmed.is_empty() || is_block_start(trimmed) {
+            flush_paragraph(&mut html, &mut paragraph);
+        }
+
+        if trimmed.starts_with("```") {
+            html.push_str("<pre><code>");
+            in_code = true;
+        } else if trimmed == "---" || trimmed == "***" {
+            html.push_str("<hr>\n");
+        } else if let Some((level, text)) = heading(trimmed) {
+            html.push_str(&format!("<h{0}>{1}</h{0}>\n", level, inline(text)));
+        } else if let Some(item) = item {
+            if !in_list {
+                html.push_str("<ul>\n");
+                in_list = true;
+            }
+            let (checkbox, text) = match item.strip_prefix("[ ] ") {
+                Some(rest) => ("&#9744; ", rest),
+                None => match item
+                    .strip_prefix("[x] ")
+                    .or_else(|| item.strip_prefix("[X] "))
+                {
+                    Some(rest) => ("&#9745; ", rest),
+                    None => ("", item),
+                },
+            };
+            html.push_str(&format!("<li>{}{}</li>\n", checkbox, inline(text)));
+        } else if !trimmed.is_empty() {
+            paragraph.push(trimmed);
+        }
+    }
+
+    flush_paragraph(&mut html, &mut paragraph);
+    if in_list {
+        html.push_str("</ul>\n");
+    }
+    if in_code {
+        html.push_str("</code></pre>\n");
+    }
+    html
+}
+
+fn is_block_start(line: &str) -> bool {
+    line.starts_with("```") || line == "---" || line == "***" || heading(line).is_some()
+}
+
+fn flush_paragraph(html: &mut String, paragraph: &mut Vec<&str>) {
+    if paragraph.is_empty() {
+        return;
+    }
+    let text: Vec<String> = paragraph.iter().map(|l| inline(l)).collect();
+    html.push_str(&format!("<p>{}</p>\n", text.join("<br>\n")));
+    paragraph.clear();
+}
+
+/// `# Title` → (1, "Title"). Levels 1..=3 only.
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.bytes().take_while(|&b| b == b'#').count();
+    if !(1..=3).contains(&level) {
+        return None;
+    }
+    line[level..].strip_prefix(' ').map(|t| (level, t.trim()))
+}
+
+/// Escape, then apply `code`, **bold** and *italic* spans. Unpaired markers stay literal.
+fn inline(text: &str) -> String {
+    let parts: Vec<&str> = text.split('`').collect();
+    // With an odd number of backticks the last one has no partner.
+    let paired = (parts.len() - 1) / 2 * 2;
+    let mut out = String::new();
+    for (i, part) in parts.iter().enumerate() {
+        if i > paired {
+            out.push('`');
+        }
+        if i % 2 == 1 && i <= paired {
+            out.push_str(&format!("<code>{}</code>", escape(part)));
+        } else {
+            let bold = spans(&escape(part), "**", "<strong>", "</strong>");
+            out.push_str(&spans(&bold, "*", "<em>", "</em>"));
+        }
+    }
+    out
+}
+
+/// Replace pairs of `marker` around text with `open`/`close`. Unpaired markers and empty spans
+/// stay literal.
+fn spans(text: &str, marker: &str, open: &str, close: &str) -> String {
+    let parts: Vec<&str> = text.split(marker).collect();
+    let mut out = parts[0].to_string();
+    let mut i = 1;
+    while i < parts.len() {
+        if i + 1 < parts.len() && !parts[i].is_empty() {
+            out.push_str(&format!("{}{}{}{}", open, parts[i], close, parts[i + 1]));
+            i += 2;
+        } else {
+            out.push_str(marker);
+            out.push_str(parts[i]);
+            i += 1;
+        }
+    }
+    out
+}
+
+/// Render Markdown as plain text for a chat message: headings on their own line (the top one
+/// upper-cased), rules as a line of box characters, bullets and task boxes as symbols, code
+/// fences dropped and inline markers removed. Runs of blank lines collapse to one.
+pub fn to_plain_text(md: &str) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut in_code = false;
+    for line in md.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("```") {
+            in_code = !in_code;
+            continue;
+        }
+        let text = if in_code {
+            line.to_string()
+        } else if trimmed == "---" || trimmed == "***" {
+            "──────────".to_string()
+        } else if let Some((level, text)) = heading(trimmed) {
+            match level {
+                1 => plain_inline(text).to_uppercase(),
+                2 => plain_inline(text),
+                _ => format!("▸ {}", plain_inline(text)),
+            }
+        } else if let Some(item) = trimmed
+            .strip_prefix("- ")
+            .or_else(|| trimmed.strip_prefix("* "))
+        {
+            match item.strip_prefix("[ ] ") {
+                Some(rest) => format!("☐ {}", plain_inline(rest)),
+                None => match item
+                    .strip_prefix("[x] ")
+                    .or_else(|| item.strip_prefix("[X] "))
+                {
+                    Some(rest) => format!("☑ {}", plain_inline(rest)),
+                    None => format!("• {}", plain_inline(item)),
+                },
+            }
+        } else {
+            plain_inline(trimmed)
+        };
+        if text.trim().is_empty() && lines.last().is_none_or(|l| l.is_empty()) {
+            continue;
+        }
+        lines.push(if text.trim().is_empty() {
+            String::new()
+        } else {
+            text
+        });
+    }
+    while lines.last().is_some_and(|l| l.is_empty()) {
+        lines.pop();
+    }
+    lines.join("\n")
+}
+
+/// Inline markup removed: `code` keeps its text, paired **bold** and *italic* markers go.
+fn plain_inline(text: &str) -> String {
+    let parts: Vec<&str> = text.split('`').collect();
+    let paired = (parts.len() - 1) / 2 * 2;
+    let mut out = String::new();
+    for (i, part) in parts.iter().enumerate() {
+        if i > paired {
+            out.push('`');
+        }
+        if i % 2 == 1 && i <= paired {
+            out.push_str(part);
+        } else {
+            out.push_str(&spans(&spans(part, "**", "", ""), "*", "", ""));
+        }
+    }
+    out
+}
+
+/// Split `text` into pieces of at most `limit` UTF-16 code units (how Telegram measures
+/// message length), breaking between lines where possible and inside a line only when the
+/// line alone is too long.
+pub fn split_text(text: &str, limit: usize) -> Vec<String> {
+    let limit = limit.max(1);
+    let units = |s: &str| s.encode_utf16().count();
+    let mut pieces = Vec::new();
+    let mut current = String::new();
+    for line in text.lines() {
+        let needed = if current.is_empty() { 0 } else { 1 } + units(line);
+        if !current.is_empty() && units(&current) + needed > limit {
+            pieces.push(std::mem::take(&mut current));
+        }
+        if units(line) <= limit {
+            if !current.is_empty() {
+                current.push('\n');
+            }
+            current.push_str(line);
+            continue;
+        }
+        // A single overlong line: cut it at character boundaries.
+        for c in line.chars() {
+            if units(&current) + c.len_utf16() > limit {
+                pieces.push(std::mem::take(&mut current));
+            }
+            current.push(c);
+        }
+    }
+    if !current.trim().is_empty() {
+        pieces.push(current);
+    }
+    pieces
+}
+
+/// Escape HTML special characters.
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_markdown_to_html() {
+        let md = "# Weekly Digest: 2024-05\n\n**Chat ID:** 1 | **Analyzed:** today\n\n---\n\n\
+                  ## Summary\n\nLine one\nline <two> & `x < y`\n\n\
+                  - [ ] **Ship it** (Owner: bob)\n- plain *item*\n\n\
+                  ```\nMon | ##\n```\n*Generated*\n";
+        let html = to_html(md);
+        assert_eq!(
+            html,
+            "<h1>Weekly Digest: 2024-05</h1>\n\
+             <p><strong>Chat ID:</strong> 1 | <strong>Analyzed:</strong> today</p>\n\
+             <hr>\n\
+             <h2>Summary</h2>\n\
+             <p>Line one<br>\nline &lt;two&gt; &amp; <code>x &lt; y</code></p>\n\
+             <ul>\n<li>&#9744; <strong>Ship it</strong> (Owner: bob)</li>\n<li>plain <em>item</em></li>\n</ul>\n\
+             <pre><code>Mon | ##\n</code></pre>\n\
+             <p><em>Generated</em></p>\n"
+        );
+    }
+
+    #[test]
+    fn test_unpaired_markers_stay_literal() {
+        assert_eq!(inline("2 * 3 = 6"), "2 * 3 = 6");
+        assert_eq!(inline("a **b** c**"), "a <strong>b</strong> c**");
+        assert_eq!(inline("it`s"), "it`s");
+        assert_eq!(heading("#hashtag"), None);
+        assert_eq!(to_html("#hashtag"), "<p>#hashtag</p>\n");
+    }
+
+    #[test]
+    fn test_report_markdown_to_plain_text() {
+        let md = "# Weekly Digest: 2024-05\n\n**Chat:** Team | **Analyzed:** today\n\n---\n\n\
+                  ## 🔑 Key Topics\n\n### Team\n\n- release `v2`\n\n\n\
+                  - [ ] **Ship it** (Owner: bob)\n\n```\nMon | ##\n```\n*Generated*\n";
+        assert_eq!(
+            to_plain_text(md),
+            "WEEKLY DIGEST: 2024-05\n\n\
+             Chat: Team | Analyzed: today\n\n\
+             ──────────\n\n\
+             🔑 Key Topics\n\n\
+             ▸ Team\n\n\
+             • release v2\n\n\
+             ☐ Ship it (Owner: bob)\n\n\
+             Mon | ##\n\
+             Generated"
+        );
+        assert_eq!(to_plain_text("2 * 3 = 6"), "2 * 3 = 6");
+    }
+
+    #[test]
+    fn test_split_text_respects_utf16_limit() {
+        assert_eq!(split_text("one\ntwo\nthree", 7), vec!["one\ntwo", "three"]);
+        // An emoji is two UTF-16 units: a 5-emoji line splits 2 + 2 + 1 at limit 4.
+        assert_eq!(split_text("😀😀😀😀😀", 4), vec!["😀😀", "😀😀", "😀"]);
+        assert_eq!(split_text("short", 4096), vec!["short"]);
+        assert!(split_text("", 4096).is_empty());
+    }
+}
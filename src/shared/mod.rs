@@ -1 +1,19 @@
+pub mod activity;
+pub mod activity_flag;
+pub mod anonymize;
+pub mod chat_link;
 pub mod config;
+pub mod config_keys;
+pub mod crypto;
+pub mod eta;
+pub mod lock;
+pub mod logging;
+pub mod markdown;
+pub mod paths;
+pub mod pidfile;
+pub mod pricing;
+pub mod profile;
+pub mod proxy;
+pub mod qr;
+pub mod rate_limiter;
+pub mod systemd;
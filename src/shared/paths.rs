@@ -0,0 +1,291 @@
+//! Cross-platform filename handling for media, exports, and reports.
+//!
+//! Archives synced on Linux are often opened on Windows, so every generated path
+//! component goes through [`sanitize_component`]: NFC-normalized, stripped of
+//! characters Windows rejects, never a reserved device name, and never ending in
+//! a dot or space. [`join_sanitized`] additionally keeps the full path under
+//! [`MAX_PATH_LEN`] by truncating the stem while preserving the extension.
+
+use std::path::{Path, PathBuf};
+use unicode_normalization::UnicodeNormalization;
+
+/// Maximum bytes for a single path component (ext4, NTFS, APFS all cap at 255).
+pub const MAX_COMPONENT_BYTES: usize = 255;
+
+/// Maximum total path length in characters (classic Windows MAX_PATH minus the terminator).
+pub const MAX_PATH_LEN: usize = 259;
+
+/// Replacement used when a component sanitizes down to nothing.
+const EMPTY_REPLACEMENT: &str = "_";
+
+/// Characters rejected by Windows in file names (also covers the POSIX separator).
+const FORBIDDEN_CHARS: &[char] = &['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+/// Windows reserved device names. Matched case-insensitively against the part before the first dot.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Sanitize a single path component (file or directory name).
+///
+/// - NFC-normalizes Unicode so the same name composed differently maps to one file
+/// - Strips `<>:"/\|?*` and control characters
+/// - Trims trailing dots and spaces (Windows silently drops them)
+/// - Prefixes reserved device names (`CON`, `nul.txt`, `COM1`) with `_`
+/// - Truncates to [`MAX_COMPONENT_BYTES`], preserving the extension
+///
+/// Never returns an empty string, `.` or `..`.
+pub fn sanitize_component(name: &str) -> String {
+    let normalized: String = name.nfc().collect();
+    let mut cleaned: String = normalized
+        .chars()
+        .filter(|c| !c.is_control() && !FORBIDDEN_CHARS.contains(c))
+        .collect();
+
+    let trimmed_len = cleaned.trim_end_matches(['.', ' ']).len();
+    cleaned.truncate(trimmed_len);
+    let cleaned = cleaned.trim_start_matches(' ').to_string();
+
+    if cleaned.is_empty() {
+        return EMPTY_REPLACEMENT.to_string();
+    }
+
+    let cleaned = if is_reserved_name(&cleaned) {
+        format!("_{}", cleaned)
+    } else {
+        cleaned
+    };
+
+    truncate_preserving_extension(&cleaned, MAX_COMPONENT_BYTES)
+}
+
+/// Join `name` (sanitized) onto `base`, truncating the stem so the full path stays
+/// within [`MAX_PATH_LEN`] characters. The extension is preserved when possible.
+pub fn join_sanitized(base: &Path, name: &str) -> PathBuf {
+    let name = sanitize_component(name);
+    let base_len = base.to_string_lossy().chars().count();
+    // +1 for the separator between base and name.
+    let budget = MAX_PATH_LEN.saturating_sub(base_len + 1);
+    let name = truncate_chars_preserving_extension(&name, budget.max(1));
+    base.join(name)
+}
+
+/// Returns true if `name` (or its stem before the first dot) is a Windows reserved device name.
+fn is_reserved_name(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or(name).trim_end_matches(' ');
+    RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(stem))
+}
+
+/// Split into (stem, extension-with-dot). Leading-dot names (".env") have no extension.
+fn split_extension(name: &str) -> (&str, &str) {
+    match name.rfind('.') {
+        Some(idx) if idx > 0 && idx < name.len() - 1 => (&name[..idx], &name[idx..]),
+        _ => (name, ""),
+    }
+}
+
+/// Truncate to at most `max_bytes` UTF-8 bytes without splitting a character,
+/// cutting the stem rather than the extension.
+fn truncate_preserving_extension(name: &str, max_bytes: usize) -> String {
+    if name.len() <= max_bytes {
+        return name.to_string();
+    }
+    let (stem, ext) = split_extension(name);
+    // Extensions longer than the budget are not real extensions; cut the whole name.
+    let (stem, ext) = if ext.len() >= max_bytes {
+        (name, "")
+    } else {
+        (stem, ext)
+    };
+    let stem_budget = max_bytes - ext.len();
+    let mut end = stem_budget.min(stem.len());
+    while end > 0 && !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+    finish_truncated(&stem[..end], ext)
+}
+
+/// Same as [`truncate_preserving_extension`] but measured in characters (for path-length limits).
+pub fn truncate_chars_preserving_extension(name: &str, max_chars: usize) -> String {
+    if name.chars().count() <= max_chars {
+        return name.to_string();
+    }
+    let (stem, ext) = split_extension(name);
+    let ext_chars = ext.chars().count();
+    let (stem, ext, ext_chars) = if ext_chars >= max_chars {
+        (name, "", 0)
+    } else {
+        (stem, ext, ext_chars)
+    };
+    let stem: String = stem.chars().take(max_chars - ext_chars).collect();
+    finish_truncated(&stem, ext)
+}
+
+/// Re-apply the trailing dot/space rule after a cut, and never return an empty stem.
+fn finish_truncated(stem: &str, ext: &str) -> String {
+    let stem = stem.trim_end_matches(['.', ' ']);
+    let stem = if stem.is_empty() {
+        EMPTY_REPLACEMENT
+    } else {
+        stem
+    };
+    format!("{}{}", stem, ext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_component_table() {
+        let cases: &[(&str, &str)] = &[
+            // Plain names pass through
+            ("photo.jpg", "photo.jpg"),
+            ("123_456.mp4", "123_456.mp4"),
+            ("report final.pdf", "report final.pdf"),
+            // Forbidden characters are stripped
+            ("a<b>c.txt", "abc.txt"),
+            ("what?.pdf", "what.pdf"),
+            ("dir/name.txt", "dirname.txt"),
+            ("back\\slash.txt", "backslash.txt"),
+            ("pipe|star*.txt", "pipestar.txt"),
+            ("quote\"colon:.txt", "quotecolon.txt"),
+            // Control characters are stripped
+            ("tab\there.txt", "tabhere.txt"),
+            ("nul\0byte.txt", "nulbyte.txt"),
+            ("line\nbreak.txt", "linebreak.txt"),
+            // Trailing dots and spaces are trimmed, leading spaces too
+            ("name.", "name"),
+            ("name...", "name"),
+            ("name . .", "name"),
+            ("  padded.txt", "padded.txt"),
+            // Windows reserved device names
+            ("CON", "_CON"),
+            ("con", "_con"),
+            ("NUL.txt", "_NUL.txt"),
+            ("nul.tar.gz", "_nul.tar.gz"),
+            ("COM1", "_COM1"),
+            ("lpt9.log", "_lpt9.log"),
+            ("AUX ", "_AUX"),
+            ("PRN.", "_PRN"),
+            // Not reserved: only exact stem matches
+            ("CONSOLE.txt", "CONSOLE.txt"),
+            ("COM10", "COM10"),
+            ("nullable.rs", "nullable.rs"),
+            // Degenerate input never yields empty, "." or ".."
+            ("", "_"),
+            ("...", "_"),
+            ("..", "_"),
+            (".", "_"),
+            ("???", "_"),
+            // Dotfiles keep their leading dot
+            (".env", ".env"),
+            // Unicode is kept
+            ("Отчёт.pdf", "Отчёт.pdf"),
+            ("日本語.txt", "日本語.txt"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                &sanitize_component(input),
+                expected,
+                "sanitize_component({:?})",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_sanitize_component_nfc_normalizes() {
+        // "é" as e + combining acute accent (NFD) becomes the single code point (NFC).
+        let decomposed = "caf\u{0065}\u{0301}.txt";
+        let composed = "caf\u{00e9}.txt";
+        assert_eq!(sanitize_component(decomposed), composed);
+        assert_eq!(sanitize_component(composed), composed);
+    }
+
+    #[test]
+    fn test_sanitize_component_truncates_preserving_extension() {
+        let cases: &[(String, usize, &str)] = &[
+            (
+                format!("{}.pdf", "a".repeat(300)),
+                MAX_COMPONENT_BYTES,
+                ".pdf",
+            ),
+            (
+                format!("{}.jpeg", "b".repeat(1000)),
+                MAX_COMPONENT_BYTES,
+                ".jpeg",
+            ),
+            ("c".repeat(400), MAX_COMPONENT_BYTES, "c"),
+        ];
+        for (input, max, suffix) in cases {
+            let out = sanitize_component(input);
+            assert!(out.len() <= *max, "{} bytes > {}", out.len(), max);
+            assert!(
+                out.ends_with(suffix),
+                "{:?} should end with {:?}",
+                out,
+                suffix
+            );
+        }
+    }
+
+    #[test]
+    fn test_sanitize_component_truncates_on_char_boundary() {
+        // Each "ж" is 2 bytes; the cut must not split one.
+        let input = format!("{}.txt", "ж".repeat(200));
+        let out = sanitize_component(&input);
+        assert!(out.len() <= MAX_COMPONENT_BYTES);
+        assert!(out.ends_with(".txt"));
+        assert!(out.trim_end_matches(".txt").chars().all(|c| c == 'ж'));
+    }
+
+    #[test]
+    fn test_sanitize_component_overlong_extension_is_cut() {
+        let input = format!("x.{}", "e".repeat(300));
+        let out = sanitize_component(&input);
+        assert!(out.len() <= MAX_COMPONENT_BYTES);
+        assert!(out.starts_with("x."));
+    }
+
+    #[test]
+    fn test_join_sanitized_enforces_path_length() {
+        let cases: &[(&str, String, &str)] = &[
+            (
+                "data/media",
+                "short.jpg".to_string(),
+                "data/media/short.jpg",
+            ),
+            ("data/media", "CON.jpg".to_string(), "data/media/_CON.jpg"),
+            (
+                "data/media",
+                "bad:name?.png".to_string(),
+                "data/media/badname.png",
+            ),
+        ];
+        for (base, name, expected) in cases {
+            assert_eq!(
+                join_sanitized(Path::new(base), name),
+                PathBuf::from(expected),
+                "join_sanitized({:?}, {:?})",
+                base,
+                name
+            );
+        }
+
+        let base = PathBuf::from("d".repeat(200));
+        let joined = join_sanitized(&base, &format!("{}.mp4", "v".repeat(200)));
+        assert!(joined.to_string_lossy().chars().count() <= MAX_PATH_LEN);
+        assert!(joined.to_string_lossy().ends_with(".mp4"));
+        assert!(joined.starts_with(&base));
+    }
+
+    #[test]
+    fn test_join_sanitized_tiny_budget_never_empty() {
+        let base = PathBuf::from("d".repeat(MAX_PATH_LEN + 10));
+        let joined = join_sanitized(&base, "file.txt");
+        let name = joined.file_name().unwrap().to_string_lossy().to_string();
+        assert!(!name.is_empty());
+    }
+}
@@ -0,0 +1,71 @@
+//! PID file of a background process (`data/watcher.pid` for `tg-sync watch`).
+//!
+//! Written by the process itself and removed when it exits; a file left behind by a process
+//! that died is ignored by [`running_pid`].
+
+use crate::domain::DomainError;
+use crate::shared::lock::pid_alive;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// PID file of the watcher inside the data directory.
+pub const WATCHER_PID_FILE: &str = "watcher.pid";
+
+/// Written PID file. Removes the file on drop.
+#[derive(Debug)]
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Write the current process id to `path`.
+    pub fn create(path: &Path) -> Result<Self, DomainError> {
+        std::fs::write(path, format!("{}\n", std::process::id()))
+            .map_err(|e| DomainError::State(format!("write {}: {}", path.display(), e)))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            warn!(path = %self.path.display(), error = %e, "failed to remove PID file");
+        }
+    }
+}
+
+/// PID in `path` if that process is alive; None when the file is missing, unreadable or stale.
+pub fn running_pid(path: &Path) -> Option<u32> {
+    std::fs::read_to_string(path)
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .filter(|&pid| pid_alive(pid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pid_file_is_read_back_and_removed() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join("test_pidfile");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(WATCHER_PID_FILE);
+        assert_eq!(running_pid(&path), None);
+
+        let pid_file = PidFile::create(&path).unwrap();
+        assert_eq!(running_pid(&path), Some(std::process::id()));
+        drop(pid_file);
+        assert!(!path.exists());
+
+        std::fs::write(&path, "not a pid").unwrap();
+        assert_eq!(running_pid(&path), None);
+    }
+}
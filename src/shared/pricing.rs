@@ -0,0 +1,146 @@
+//! Per-model LLM prices for usage cost estimates.
+//!
+//! Built-in list prices (USD per million tokens) for common models, overridable with
+//! TG_SYNC_AI_PRICES, e.g. `gpt-4o-mini=0.15/0.60,llama3.2=0/0`. A model matches the longest
+//! entry it starts with, so dated snapshots (`gpt-4o-mini-2024-07-18`) use the base price.
+
+use crate::domain::AiUsage;
+
+/// Price of one model, USD per million tokens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelPrice {
+    pub input_per_mtok: f64,
+    pub output_per_mtok: f64,
+}
+
+/// Built-in prices; estimates only, check the provider's pricing page.
+const BUILTIN_PRICES: &[(&str, f64, f64)] = &[
+    ("gpt-4o-mini", 0.15, 0.60),
+    ("gpt-4o", 2.50, 10.00),
+    ("gpt-4.1-nano", 0.10, 0.40),
+    ("gpt-4.1-mini", 0.40, 1.60),
+    ("gpt-4.1", 2.00, 8.00),
+    ("claude-3-5-haiku", 0.80, 4.00),
+    ("claude-3-5-sonnet", 3.00, 15.00),
+    ("claude-3-7-sonnet", 3.00, 15.00),
+    ("claude-sonnet-4", 3.00, 15.00),
+];
+
+/// Model name prefix -> price.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceTable {
+    entries: Vec<(String, ModelPrice)>,
+}
+
+impl Default for PriceTable {
+    fn default() -> Self {
+        Self {
+            entries: BUILTIN_PRICES
+                .iter()
+                .map(|(model, input, output)| {
+                    (
+                        model.to_string(),
+                        ModelPrice {
+                            input_per_mtok: *input,
+                            output_per_mtok: *output,
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+impl PriceTable {
+    /// Built-in prices with `spec` (`model=input/output,...`) added or replacing entries.
+    pub fn with_overrides(mut self, spec: &str) -> Result<Self, String> {
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (model, price) = parse_entry(entry)?;
+            self.entries.retain(|(m, _)| *m != model);
+            self.entries.push((model, price));
+        }
+        Ok(self)
+    }
+
+    /// Price of `model`: the longest matching prefix.
+    pub fn price(&self, model: &str) -> Option<ModelPrice> {
+        self.entries
+            .iter()
+            .filter(|(prefix, _)| model.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, price)| *price)
+    }
+
+    /// Estimated USD cost of one call; None when the model has no price.
+    pub fn cost(&self, usage: &AiUsage) -> Option<f64> {
+        self.price(&usage.model).map(|p| {
+            (usage.prompt_tokens as f64 * p.input_per_mtok
+                + usage.completion_tokens as f64 * p.output_per_mtok)
+                / 1_000_000.0
+        })
+    }
+}
+
+/// `model=input/output` with non-negative prices.
+fn parse_entry(entry: &str) -> Result<(String, ModelPrice), String> {
+    let invalid = || format!("invalid price '{}' (expected model=input/output)", entry);
+    let (model, prices) = entry.split_once('=').ok_or_else(invalid)?;
+    let (input, output) = prices.split_once('/').ok_or_else(invalid)?;
+    let parse = |s: &str| {
+        s.trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|p| p.is_finite() && *p >= 0.0)
+    };
+    match (model.trim(), parse(input), parse(output)) {
+        (model, Some(input), Some(output)) if !model.is_empty() => Ok((
+            model.to_string(),
+            ModelPrice {
+                input_per_mtok: input,
+                output_per_mtok: output,
+            },
+        )),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(model: &str, prompt: u64, completion: u64) -> AiUsage {
+        AiUsage {
+            model: model.to_string(),
+            prompt_tokens: prompt,
+            completion_tokens: completion,
+        }
+    }
+
+    #[test]
+    fn test_longest_prefix_wins_and_cost() {
+        let table = PriceTable::default();
+        let mini = table.price("gpt-4o-mini-2024-07-18").unwrap();
+        assert_eq!(mini.input_per_mtok, 0.15);
+        assert_eq!(table.price("gpt-4o").unwrap().input_per_mtok, 2.50);
+        assert!(table.price("llama3.2").is_none());
+
+        let cost = table
+            .cost(&usage("gpt-4o-mini", 1_000_000, 500_000))
+            .unwrap();
+        assert!((cost - 0.45).abs() < 1e-9);
+        assert_eq!(table.cost(&usage("llama3.2", 10, 10)), None);
+    }
+
+    #[test]
+    fn test_overrides() {
+        let table = PriceTable::default()
+            .with_overrides("llama3.2=0/0, gpt-4o-mini=1/2")
+            .unwrap();
+        assert_eq!(table.cost(&usage("llama3.2", 100, 100)), Some(0.0));
+        assert_eq!(table.price("gpt-4o-mini").unwrap().output_per_mtok, 2.0);
+
+        assert!(PriceTable::default().with_overrides("gpt-4o=1").is_err());
+        assert!(PriceTable::default().with_overrides("=1/2").is_err());
+        assert!(PriceTable::default().with_overrides("x=-1/2").is_err());
+    }
+}
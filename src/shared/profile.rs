@@ -0,0 +1,56 @@
+//! Profiles: separate accounts in one installation (`--profile <name>`, TG_SYNC_PROFILE).
+//!
+//! The default profile keeps the plain layout (`data/`, `./session.db`). A named profile lives
+//! in `data/profiles/<name>/` with its own session.db, messages.db (and so its own entity
+//! registry), state, media and reports; `[profiles.<name>]` in the config file overrides any
+//! setting for it. A named profile never falls back to the default profile's session.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+/// Directory under the base data directory holding the named profiles.
+pub const PROFILES_DIR: &str = "profiles";
+
+/// Name shown for the default profile in the picker; not usable as a profile name.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Session file of a named profile, in its data directory.
+pub const SESSION_FILE: &str = "session.db";
+
+/// Check a profile name: lowercase letters, digits, `-` and `_` (it becomes a directory name
+/// and a config file key).
+pub fn validate_name(name: &str) -> Result<(), String> {
+    if name.is_empty()
+        || name == DEFAULT_PROFILE
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+    {
+        return Err(format!(
+            "invalid profile name '{}' (lowercase letters, digits, - and _; not '{}')",
+            name, DEFAULT_PROFILE
+        ));
+    }
+    Ok(())
+}
+
+/// Data directory of profile `name` under the base data directory.
+pub fn profile_dir(base_data_dir: &Path, name: &str) -> PathBuf {
+    base_data_dir.join(PROFILES_DIR).join(name)
+}
+
+/// Named profiles: those configured in the config file plus those with a directory under
+/// `base_data_dir`, sorted.
+pub fn list(base_data_dir: &Path, configured: &[String]) -> Vec<String> {
+    let mut names: BTreeSet<String> = configured.iter().cloned().collect();
+    if let Ok(entries) = std::fs::read_dir(base_data_dir.join(PROFILES_DIR)) {
+        names.extend(
+            entries
+                .flatten()
+                .filter(|entry| entry.path().is_dir())
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .filter(|name| validate_name(name).is_ok()),
+        );
+    }
+    names.into_iter().collect()
+}
@@ -0,0 +1,21 @@
+//! Terminal QR codes (QR login).
+//!
+//! Renders with half-block characters, two modules per character cell, so a login token
+//! fits in a normal terminal. Colors are inverted for dark terminal themes, with the quiet
+//! zone the scanners need around the code.
+
+use crate::domain::DomainError;
+use qrcode::QrCode;
+use qrcode::render::unicode::Dense1x2;
+
+/// Render `data` as a QR code made of Unicode block characters, one string with newlines.
+pub fn render_terminal(data: &str) -> Result<String, DomainError> {
+    let code =
+        QrCode::new(data.as_bytes()).map_err(|e| DomainError::Auth(format!("qr code: {}", e)))?;
+    Ok(code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .quiet_zone(true)
+        .build())
+}
@@ -0,0 +1,231 @@
+//! Global request rate limiter for Telegram calls.
+//!
+//! Two token buckets of size one: history requests (dialogs, GetHistory, peer lookups,
+//! sends) and media downloads, each refilled at its own configured rate per minute. However
+//! many tasks share the limiter, requests of one kind start at least one refill interval
+//! apart. A FloodWait seen by any caller pauses both buckets for the wait and halves their
+//! refill rate for [`SLOWDOWN_WINDOW`]; after that the rate doubles back once per
+//! [`RECOVERY_STEP`] until it is at the configured value again.
+
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::debug;
+
+/// How long the halved rate holds after a FloodWait (counted from the end of the wait).
+pub const SLOWDOWN_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+/// After the slowdown window, the rate doubles once per step until fully recovered.
+pub const RECOVERY_STEP: Duration = Duration::from_secs(60);
+
+/// Repeated FloodWaits slow a bucket down to at most 1/16 of its configured rate.
+const MAX_SLOWDOWN: u32 = 16;
+
+/// Effective rates are logged (debug) at most this often while requests flow.
+const REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Which budget a request draws from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestKind {
+    History,
+    Media,
+}
+
+/// History and media budgets shared by every caller of the Telegram gateway.
+pub struct RateLimiter {
+    history: RateBudget,
+    media: RateBudget,
+}
+
+impl RateLimiter {
+    /// Budgets in requests per minute; 0 means unlimited.
+    pub fn per_minute(history: u32, media: u32) -> Self {
+        Self {
+            history: RateBudget::per_minute("history", history),
+            media: RateBudget::per_minute("media", media),
+        }
+    }
+
+    /// Wait for the next slot of `kind`.
+    pub async fn acquire(&self, kind: RequestKind) {
+        self.budget(kind).acquire().await;
+    }
+
+    /// A FloodWait: hold back every request for `wait` and slow both budgets down.
+    pub fn flood_wait(&self, wait: Duration) {
+        self.history.slow_down(wait);
+        self.media.slow_down(wait);
+    }
+
+    /// Current requests per minute of `kind` (None = unlimited).
+    pub fn effective_per_minute(&self, kind: RequestKind) -> Option<f64> {
+        self.budget(kind).effective_per_minute()
+    }
+
+    fn budget(&self, kind: RequestKind) -> &RateBudget {
+        match kind {
+            RequestKind::History => &self.history,
+            RequestKind::Media => &self.media,
+        }
+    }
+}
+
+/// One token bucket of size one, refilled every `interval` (times the current slowdown).
+pub struct RateBudget {
+    name: &'static str,
+    interval: Duration,
+    slowdown_window: Duration,
+    recovery_step: Duration,
+    state: Mutex<BudgetState>,
+}
+
+struct BudgetState {
+    /// Earliest instant the next request may start.
+    next_slot: Instant,
+    /// Refill interval multiplier after FloodWaits (1 = configured rate).
+    slowdown: u32,
+    /// When `slowdown` next halves.
+    recover_at: Instant,
+    last_report: Instant,
+}
+
+impl RateBudget {
+    pub fn new(name: &'static str, interval: Duration) -> Self {
+        let now = Instant::now();
+        Self {
+            name,
+            interval,
+            slowdown_window: SLOWDOWN_WINDOW,
+            recovery_step: RECOVERY_STEP,
+            state: Mutex::new(BudgetState {
+                next_slot: now,
+                slowdown: 1,
+                recover_at: now,
+                last_report: now,
+            }),
+        }
+    }
+
+    /// Budget of `per_minute` requests per minute; 0 means unlimited.
+    pub fn per_minute(name: &'static str, per_minute: u32) -> Self {
+        let interval = match per_minute {
+            0 => Duration::ZERO,
+            n => Duration::from_secs(60) / n,
+        };
+        Self::new(name, interval)
+    }
+
+    /// Wait for the next request slot. Slots are handed out in call order.
+    pub async fn acquire(&self) {
+        let slot = {
+            let Ok(mut state) = self.state.lock() else {
+                return;
+            };
+            let now = Instant::now();
+            let interval = self.current_interval(&mut state, now);
+            if now >= state.last_report + REPORT_INTERVAL {
+                state.last_report = now;
+                debug!(
+                    budget = self.name,
+                    per_min = ?per_minute(interval),
+                    slowdown = state.slowdown,
+                    "effective Telegram request rate"
+                );
+            }
+            let slot = state.next_slot.max(now);
+            state.next_slot = slot + interval;
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+
+    /// Hold back every request for at least `wait` (FloodWait) and halve the refill rate
+    /// until the slowdown window has passed. Slots already handed out are not revoked;
+    /// callers that hit the wait retry through [`Self::acquire`].
+    pub fn slow_down(&self, wait: Duration) {
+        if let Ok(mut state) = self.state.lock() {
+            let resume = Instant::now() + wait;
+            state.next_slot = state.next_slot.max(resume);
+            state.slowdown = (state.slowdown * 2).min(MAX_SLOWDOWN);
+            state.recover_at = resume + self.slowdown_window;
+        }
+    }
+
+    /// Current requests per minute (None = unlimited).
+    pub fn effective_per_minute(&self) -> Option<f64> {
+        let mut state = self.state.lock().ok()?;
+        per_minute(self.current_interval(&mut state, Instant::now()))
+    }
+
+    /// Configured interval times the slowdown, after applying any recovery steps due by `now`.
+    fn current_interval(&self, state: &mut BudgetState, now: Instant) -> Duration {
+        while state.slowdown > 1 && now >= state.recover_at {
+            state.slowdown /= 2;
+            state.recover_at += self.recovery_step;
+        }
+        self.interval * state.slowdown
+    }
+}
+
+fn per_minute(interval: Duration) -> Option<f64> {
+    (!interval.is_zero()).then(|| 60.0 / interval.as_secs_f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_requests_are_spaced_by_interval() {
+        let budget = RateBudget::new("test", Duration::from_millis(20));
+        let started = Instant::now();
+        for _ in 0..4 {
+            budget.acquire().await;
+        }
+        // First slot is immediate, the other three wait one interval each.
+        assert!(started.elapsed() >= Duration::from_millis(60));
+    }
+
+    #[tokio::test]
+    async fn test_flood_wait_delays_next_slot() {
+        let budget = RateBudget::new("test", Duration::ZERO);
+        budget.acquire().await;
+        let started = Instant::now();
+        budget.slow_down(Duration::from_millis(50));
+        budget.acquire().await;
+        assert!(started.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn test_flood_wait_halves_rate_then_recovers_stepwise() {
+        let mut budget = RateBudget::per_minute("test", 60);
+        budget.slowdown_window = Duration::from_millis(40);
+        budget.recovery_step = Duration::from_millis(60);
+        assert_eq!(budget.effective_per_minute(), Some(60.0));
+
+        budget.slow_down(Duration::ZERO);
+        budget.slow_down(Duration::ZERO);
+        assert_eq!(budget.effective_per_minute(), Some(15.0));
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(budget.effective_per_minute(), Some(30.0));
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(budget.effective_per_minute(), Some(60.0));
+    }
+
+    #[test]
+    fn test_limiter_budgets_are_separate() {
+        let limiter = RateLimiter::per_minute(120, 0);
+        assert_eq!(
+            limiter.effective_per_minute(RequestKind::History),
+            Some(120.0)
+        );
+        assert_eq!(limiter.effective_per_minute(RequestKind::Media), None);
+
+        limiter.flood_wait(Duration::ZERO);
+        assert_eq!(
+            limiter.effective_per_minute(RequestKind::History),
+            Some(60.0)
+        );
+    }
+}
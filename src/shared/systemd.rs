@@ -0,0 +1,142 @@
+//! Minimal systemd `sd_notify` client for `Type=notify` units with `WatchdogSec`.
+//!
+//! Writes state datagrams to `$NOTIFY_SOCKET` directly (no libsystemd). Every call is a no-op
+//! when the variable is absent (not started by systemd) and on non-Unix targets.
+
+use std::ffi::OsStr;
+use std::time::Duration;
+use tracing::debug;
+
+/// Startup finished; systemd marks the unit active.
+pub const READY: &str = "READY=1";
+/// Watchdog keep-alive.
+pub const WATCHDOG: &str = "WATCHDOG=1";
+/// Graceful shutdown has begun.
+pub const STOPPING: &str = "STOPPING=1";
+
+/// Send `READY=1`.
+pub fn notify_ready() {
+    notify(READY);
+}
+
+/// Send `WATCHDOG=1`.
+pub fn notify_watchdog() {
+    notify(WATCHDOG);
+}
+
+/// Send `STOPPING=1`.
+pub fn notify_stopping() {
+    notify(STOPPING);
+}
+
+/// Send `state` to `$NOTIFY_SOCKET` if set. Failures are logged and otherwise ignored.
+pub fn notify(state: &str) {
+    let Some(socket) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    if let Err(e) = notify_socket(&socket, state) {
+        debug!(error = %e, state, "sd_notify failed");
+    }
+}
+
+/// How often to ping the watchdog: half of `$WATCHDOG_USEC`. `None` when the watchdog is not
+/// enabled for this process.
+pub fn watchdog_interval() -> Option<Duration> {
+    let usec = std::env::var("WATCHDOG_USEC").ok();
+    let pid = std::env::var("WATCHDOG_PID").ok();
+    parse_watchdog_interval(usec.as_deref(), pid.as_deref(), std::process::id())
+}
+
+/// `WATCHDOG_PID`, when present, must name this process (the variables may leak to children).
+fn parse_watchdog_interval(
+    usec: Option<&str>,
+    pid: Option<&str>,
+    own_pid: u32,
+) -> Option<Duration> {
+    if let Some(pid) = pid {
+        if pid.trim().parse::<u32>().ok() != Some(own_pid) {
+            return None;
+        }
+    }
+    let usec = usec?.trim().parse::<u64>().ok().filter(|&u| u > 0)?;
+    Some(Duration::from_micros(usec / 2))
+}
+
+/// Write one datagram to the notify socket. A leading `@` selects the Linux abstract namespace.
+#[cfg(unix)]
+pub fn notify_socket(socket: &OsStr, state: &str) -> std::io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::UnixDatagram;
+
+    let sock = UnixDatagram::unbound()?;
+    if let Some(name) = socket.as_bytes().strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            sock.send_to_addr(state.as_bytes(), &addr)?;
+            return Ok(());
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = name;
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "abstract notify socket requires Linux",
+            ));
+        }
+    }
+    sock.send_to(state.as_bytes(), std::path::Path::new(socket))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn notify_socket(_socket: &OsStr, _state: &str) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_watchdog_interval() {
+        assert_eq!(
+            parse_watchdog_interval(Some("30000000"), None, 7),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(
+            parse_watchdog_interval(Some("30000000"), Some("7"), 7),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(
+            parse_watchdog_interval(Some("30000000"), Some("8"), 7),
+            None
+        );
+        assert_eq!(parse_watchdog_interval(Some("0"), None, 7), None);
+        assert_eq!(parse_watchdog_interval(Some("abc"), None, 7), None);
+        assert_eq!(parse_watchdog_interval(None, None, 7), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_notify_socket_writes_datagrams() {
+        use std::os::unix::net::UnixDatagram;
+
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("target/test_sd_notify");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notify.sock");
+        let server = UnixDatagram::bind(&path).unwrap();
+
+        for state in [READY, WATCHDOG, STOPPING] {
+            notify_socket(path.as_os_str(), state).unwrap();
+            let mut buf = [0u8; 64];
+            let n = server.recv(&mut buf).unwrap();
+            assert_eq!(&buf[..n], state.as_bytes());
+        }
+
+        let missing = dir.join("missing.sock");
+        assert!(notify_socket(missing.as_os_str(), READY).is_err());
+    }
+}
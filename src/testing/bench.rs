@@ -0,0 +1,341 @@
+//! Persistence load benchmark (`tg-sync bench`).
+//!
+//! Fills a scratch `SqliteRepo` with synthetic history and times the operations that matter
+//! for large accounts: single vs batched saves, paged reads at growing offsets, FTS rebuild
+//! and weekly grouping over the whole span. [`BenchReport::check`] holds the results against
+//! generous [`BenchBounds`], so an order-of-magnitude regression fails the run.
+
+use super::synthetic::{SyntheticMessages, SyntheticSpec};
+use crate::adapters::persistence::sqlite_repo::SqliteRepo;
+use crate::domain::{DomainError, Granularity, Message};
+use crate::ports::{AnalysisLogPort, ArchiveAuditPort, RepoPort};
+use std::fmt;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Page size of the paged-read measurements (same as a TUI page).
+const PAGE: u32 = 100;
+
+/// Reads per offset; the mean is reported.
+const READ_REPEATS: u32 = 5;
+
+/// What to generate and how to write it.
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    /// Synthetic history for the batched run (chat id and span come from here).
+    pub spec: SyntheticSpec,
+    /// Messages saved one call each (the single-insert baseline). Capped at `spec.count`.
+    pub single_inserts: u32,
+    /// Messages per `save_messages` call in the batched run.
+    pub batch_size: u32,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            spec: SyntheticSpec {
+                count: 100_000,
+                ..SyntheticSpec::default()
+            },
+            single_inserts: 2_000,
+            batch_size: 1_000,
+        }
+    }
+}
+
+/// Sanity limits. Defaults are an order of magnitude below what a laptop does, so they
+/// only trip on real regressions.
+#[derive(Debug, Clone)]
+pub struct BenchBounds {
+    /// Minimum batched save throughput, messages per second.
+    pub min_batched_per_sec: f64,
+    /// Maximum mean latency of one page read at any offset.
+    pub max_page_latency: Duration,
+    /// Maximum time to read the whole chat week by week (`get_messages_for_week`), per 100k
+    /// messages.
+    pub max_by_week_per_100k: Duration,
+}
+
+impl Default for BenchBounds {
+    fn default() -> Self {
+        Self {
+            min_batched_per_sec: 5_000.0,
+            max_page_latency: Duration::from_millis(250),
+            max_by_week_per_100k: Duration::from_secs(20),
+        }
+    }
+}
+
+/// One measured operation.
+#[derive(Debug, Clone)]
+pub struct BenchRow {
+    pub name: String,
+    /// Units of work (messages saved, pages read, ...).
+    pub ops: u64,
+    pub elapsed: Duration,
+    pub note: Option<String>,
+}
+
+impl BenchRow {
+    fn new(name: impl Into<String>, ops: u64, elapsed: Duration) -> Self {
+        Self {
+            name: name.into(),
+            ops,
+            elapsed,
+            note: None,
+        }
+    }
+
+    pub fn per_sec(&self) -> f64 {
+        self.ops as f64 / self.elapsed.as_secs_f64().max(1e-9)
+    }
+
+    pub fn per_op(&self) -> Duration {
+        self.elapsed / (self.ops.max(1) as u32)
+    }
+}
+
+/// Results of a bench run, in measurement order.
+#[derive(Debug, Clone, Default)]
+pub struct BenchReport {
+    pub messages: u64,
+    pub single_save: Option<BenchRow>,
+    pub batched_save: Option<BenchRow>,
+    pub page_reads: Vec<BenchRow>,
+    /// None when the database has no FTS index.
+    pub fts_rebuild: Option<BenchRow>,
+    pub by_week: Option<BenchRow>,
+    /// Week groups returned by `get_periods`.
+    pub weeks: usize,
+}
+
+impl BenchReport {
+    fn rows(&self) -> impl Iterator<Item = &BenchRow> {
+        self.single_save
+            .iter()
+            .chain(&self.batched_save)
+            .chain(&self.page_reads)
+            .chain(&self.fts_rebuild)
+            .chain(&self.by_week)
+    }
+
+    /// Bound violations, empty when the run is within limits.
+    pub fn check(&self, bounds: &BenchBounds) -> Vec<String> {
+        let mut problems = Vec::new();
+        if let Some(row) = self
+            .batched_save
+            .as_ref()
+            .filter(|row| row.per_sec() < bounds.min_batched_per_sec)
+        {
+            problems.push(format!(
+                "batched save: {:.0} msg/s, expected at least {:.0}",
+                row.per_sec(),
+                bounds.min_batched_per_sec
+            ));
+        }
+        for row in &self.page_reads {
+            if row.per_op() > bounds.max_page_latency {
+                problems.push(format!(
+                    "{}: {:?} per page, expected at most {:?}",
+                    row.name,
+                    row.per_op(),
+                    bounds.max_page_latency
+                ));
+            }
+        }
+        if let Some(row) = &self.by_week {
+            let limit = bounds
+                .max_by_week_per_100k
+                .mul_f64((self.messages as f64 / 100_000.0).max(0.01));
+            if row.elapsed > limit {
+                problems.push(format!(
+                    "messages by week: {:?}, expected at most {:?}",
+                    row.elapsed, limit
+                ));
+            }
+        }
+        problems
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<32} {:>10} {:>12} {:>14} {:>12}",
+            "operation", "ops", "total", "per op", "ops/s"
+        )?;
+        for row in self.rows() {
+            writeln!(
+                f,
+                "{:<32} {:>10} {:>12} {:>14} {:>12.0}{}",
+                row.name,
+                row.ops,
+                format!("{:.2?}", row.elapsed),
+                format!("{:.2?}", row.per_op()),
+                row.per_sec(),
+                row.note
+                    .as_deref()
+                    .map(|n| format!("  ({})", n))
+                    .unwrap_or_default()
+            )?;
+        }
+        if self.fts_rebuild.is_none() {
+            writeln!(f, "{:<32} skipped (no full-text index)", "fts rebuild")?;
+        }
+        if let (Some(single), Some(batched)) = (&self.single_save, &self.batched_save) {
+            writeln!(
+                f,
+                "batched saves are {:.1}x faster than single saves",
+                batched.per_sec() / single.per_sec().max(1e-9)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Run the benchmark against a fresh database in `dir` (must not hold a real archive).
+pub async fn run(dir: &Path, config: &BenchConfig) -> Result<BenchReport, DomainError> {
+    let repo = SqliteRepo::connect(dir).await?;
+    let spec = &config.spec;
+    let mut report = BenchReport {
+        messages: u64::from(spec.count),
+        ..BenchReport::default()
+    };
+
+    // Single saves go to a separate chat so the batched chat starts empty.
+    let single_count = config.single_inserts.min(spec.count);
+    if single_count > 0 {
+        let single_chat = spec.chat_id + 1;
+        let messages = SyntheticMessages::new(SyntheticSpec {
+            chat_id: single_chat,
+            count: single_count,
+            ..spec.clone()
+        });
+        let start = Instant::now();
+        for m in messages {
+            repo.save_messages(single_chat, std::slice::from_ref(&m))
+                .await?;
+        }
+        report.single_save = Some(BenchRow::new(
+            "save (1 per call)",
+            u64::from(single_count),
+            start.elapsed(),
+        ));
+    }
+
+    let batch_size = config.batch_size.max(1) as usize;
+    let mut messages = SyntheticMessages::new(spec.clone());
+    let mut batch: Vec<Message> = Vec::with_capacity(batch_size);
+    let mut saving = Duration::ZERO;
+    loop {
+        batch.clear();
+        batch.extend(messages.by_ref().take(batch_size));
+        if batch.is_empty() {
+            break;
+        }
+        // Only the save is timed, not the generation.
+        let start = Instant::now();
+        repo.save_messages(spec.chat_id, &batch).await?;
+        saving += start.elapsed();
+    }
+    report.batched_save = Some(BenchRow::new(
+        format!("save ({} per call)", batch_size),
+        u64::from(spec.count),
+        saving,
+    ));
+
+    let last_page = spec.count.saturating_sub(PAGE);
+    let mut offsets = vec![0, spec.count / 4, spec.count / 2, last_page];
+    offsets.dedup();
+    for offset in offsets {
+        let start = Instant::now();
+        for _ in 0..READ_REPEATS {
+            let page = repo.get_messages(spec.chat_id, PAGE, offset).await?;
+            if page.len() as u32 != PAGE.min(spec.count - offset) {
+                return Err(DomainError::Repo(format!(
+                    "bench: page at offset {} has {} messages",
+                    offset,
+                    page.len()
+                )));
+            }
+        }
+        report.page_reads.push(BenchRow::new(
+            format!("get_messages offset {}", offset),
+            u64::from(READ_REPEATS),
+            start.elapsed(),
+        ));
+    }
+
+    if repo.fts_row_counts().await?.is_some() {
+        let start = Instant::now();
+        repo.rebuild_fts().await?;
+        report.fts_rebuild = Some(BenchRow::new(
+            "fts rebuild",
+            report.messages + u64::from(single_count),
+            start.elapsed(),
+        ));
+    }
+
+    let start = Instant::now();
+    let weeks = repo.get_periods(spec.chat_id, Granularity::Week).await?;
+    let mut grouped = 0;
+    for week in &weeks {
+        grouped += repo
+            .get_messages_for_week(spec.chat_id, Granularity::Week, week)
+            .await?
+            .len();
+    }
+    let mut row = BenchRow::new("get_messages_for_week", weeks.len() as u64, start.elapsed());
+    row.note = Some(format!("{} weeks, {} messages", weeks.len(), grouped));
+    report.weeks = weeks.len();
+    report.by_week = Some(row);
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_small_bench_run_and_bounds() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join("test_persistence_bench");
+        let _ = std::fs::remove_dir_all(&dir);
+        let config = BenchConfig {
+            spec: SyntheticSpec {
+                count: 3_000,
+                ..SyntheticSpec::default()
+            },
+            single_inserts: 50,
+            batch_size: 500,
+        };
+        let report = run(&dir, &config).await.unwrap();
+
+        assert_eq!(report.single_save.as_ref().unwrap().ops, 50);
+        assert_eq!(report.batched_save.as_ref().unwrap().ops, 3_000);
+        assert_eq!(report.page_reads.len(), 4);
+        // A year of messages spreads over every ISO week.
+        assert!((52..=54).contains(&report.weeks), "weeks {}", report.weeks);
+        let table = report.to_string();
+        assert!(table.contains("save (500 per call)"));
+        assert!(table.contains("get_messages offset 2900"));
+
+        // Unreachable limits are reported; unoptimized test builds are too slow for the
+        // defaults, so those are exercised by `tg-sync bench` on release builds.
+        let strict = BenchBounds {
+            min_batched_per_sec: f64::MAX,
+            max_page_latency: Duration::ZERO,
+            max_by_week_per_100k: Duration::ZERO,
+        };
+        assert_eq!(report.check(&strict).len(), 6);
+        let lax = BenchBounds {
+            min_batched_per_sec: 1.0,
+            max_page_latency: Duration::from_secs(60),
+            max_by_week_per_100k: Duration::from_secs(3600),
+        };
+        assert!(report.check(&lax).is_empty());
+    }
+}
@@ -0,0 +1,285 @@
+//! In-memory Telegram for use-case tests: serves chat histories the way `GetHistory` pages
+//! them, fails chosen requests with FloodWait and records what was sent or downloaded.
+//!
+//! Paging follows the real gateway: `max_id` is also the request's `offset_id`, so a page
+//! holds the `limit` newest messages below it. Telegram does not honour `min_id` once an
+//! offset is given, and neither does this fake: pages below the first one may reach under
+//! `min_id`, which sync must cut off client-side.
+
+use crate::domain::{Chat, DomainError, MediaReference, Message};
+use crate::ports::TgGateway;
+use crate::shared::chat_link::ChatReference;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+/// One history request received by a [`FakeTgGateway`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryRequest {
+    pub chat_id: i64,
+    pub min_id: i32,
+    pub max_id: i32,
+    pub limit: i32,
+}
+
+/// Telegram with chat histories held in memory.
+#[derive(Default)]
+pub struct FakeTgGateway {
+    /// Own user id (Saved Messages).
+    me_id: i64,
+    /// Signed in as a bot: no Saved Messages.
+    bot: bool,
+    dialogs: Mutex<Vec<Chat>>,
+    /// Public chats not among the dialogs, resolvable by username only.
+    public: Mutex<Vec<Chat>>,
+    /// Messages per chat, in any order.
+    history: Mutex<HashMap<i64, Vec<Message>>>,
+    /// FloodWait seconds returned by history request number N (1-based).
+    flood_waits: Mutex<HashMap<usize, u64>>,
+    requests: Mutex<Vec<HistoryRequest>>,
+    /// Media downloaded and where to.
+    downloads: Mutex<Vec<(MediaReference, PathBuf)>>,
+    /// Messages sent: chat and text.
+    sent: Mutex<Vec<(i64, String)>>,
+}
+
+/// Lock that survives a test panicking while holding it.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A plain text message with only the essentials set.
+pub fn text_message(chat_id: i64, id: i32, date: i64, text: &str) -> Message {
+    Message {
+        id,
+        chat_id,
+        date,
+        text: text.to_string(),
+        media: None,
+        from_user_id: None,
+        reply_to_msg_id: None,
+        edit_history: None,
+        sender_name: None,
+        topic_id: None,
+        topic_title: None,
+        forwarded_from_name: None,
+        forwarded_from_id: None,
+        forwarded_date: None,
+        poll: None,
+        linked_channel_post: None,
+        grouped_id: None,
+        mentioned: false,
+        mentioned_user_ids: Vec::new(),
+    }
+}
+
+impl FakeTgGateway {
+    /// Empty Telegram for the user `me_id`.
+    pub fn new(me_id: i64) -> Self {
+        Self {
+            me_id,
+            ..Self::default()
+        }
+    }
+
+    /// Play a bot account: dialogs stay resolvable but there is no Saved Messages.
+    pub fn as_bot(mut self) -> Self {
+        self.bot = true;
+        self
+    }
+
+    /// List `chat` among the dialogs.
+    pub fn add_dialog(&self, chat: Chat) {
+        lock(&self.dialogs).push(chat);
+    }
+
+    /// Make `chat` resolvable by its username without listing it among the dialogs, like a
+    /// public channel not joined.
+    pub fn add_public_chat(&self, chat: Chat) {
+        lock(&self.public).push(chat);
+    }
+
+    /// Post `messages` to their chats. A message with a known id replaces it (an edit).
+    pub fn post(&self, messages: impl IntoIterator<Item = Message>) {
+        let mut history = lock(&self.history);
+        for message in messages {
+            let chat = history.entry(message.chat_id).or_default();
+            chat.retain(|m| m.id != message.id);
+            chat.push(message);
+        }
+    }
+
+    /// Answer history request number `request` (1-based, counting every chat) with a
+    /// FloodWait of `seconds`.
+    pub fn flood_wait_at(&self, request: usize, seconds: u64) {
+        lock(&self.flood_waits).insert(request, seconds);
+    }
+
+    /// History requests received so far, in order.
+    pub fn history_requests(&self) -> Vec<HistoryRequest> {
+        lock(&self.requests).clone()
+    }
+
+    /// Media downloaded so far and the paths they were written to.
+    pub fn downloads(&self) -> Vec<(MediaReference, PathBuf)> {
+        lock(&self.downloads).clone()
+    }
+
+    /// Messages sent so far: chat and text.
+    pub fn sent_messages(&self) -> Vec<(i64, String)> {
+        lock(&self.sent).clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl TgGateway for FakeTgGateway {
+    async fn get_dialogs(&self) -> Result<Vec<Chat>, DomainError> {
+        Ok(lock(&self.dialogs).clone())
+    }
+
+    async fn get_messages(
+        &self,
+        chat_id: i64,
+        min_id: i32,
+        max_id: i32,
+        limit: i32,
+    ) -> Result<Vec<Message>, DomainError> {
+        let number = {
+            let mut requests = lock(&self.requests);
+            requests.push(HistoryRequest {
+                chat_id,
+                min_id,
+                max_id,
+                limit,
+            });
+            requests.len()
+        };
+        if let Some(seconds) = lock(&self.flood_waits).remove(&number) {
+            return Err(DomainError::FloodWait { seconds });
+        }
+        let mut page: Vec<Message> = lock(&self.history)
+            .get(&chat_id)
+            .into_iter()
+            .flatten()
+            .filter(|m| match max_id {
+                0 => m.id > min_id,
+                // min_id is ignored once an offset is given.
+                offset => m.id < offset,
+            })
+            .cloned()
+            .collect();
+        page.sort_by_key(|m| std::cmp::Reverse(m.id));
+        page.truncate(limit.max(0) as usize);
+        Ok(page)
+    }
+
+    async fn download_media(
+        &self,
+        media_ref: &MediaReference,
+        dest_path: &Path,
+    ) -> Result<(), DomainError> {
+        if let Some(parent) = dest_path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| DomainError::Media(e.to_string()))?;
+        }
+        tokio::fs::write(dest_path, media_ref.opaque_ref.as_bytes())
+            .await
+            .map_err(|e| DomainError::Media(e.to_string()))?;
+        lock(&self.downloads).push((media_ref.clone(), dest_path.to_path_buf()));
+        Ok(())
+    }
+
+    async fn get_me_id(&self) -> Result<i64, DomainError> {
+        Ok(self.me_id)
+    }
+
+    async fn send_message(&self, chat_id: i64, text: &str) -> Result<(), DomainError> {
+        lock(&self.sent).push((chat_id, text.to_string()));
+        Ok(())
+    }
+
+    /// Finds a dialog by id, a dialog or public chat by username or link.
+    async fn resolve_chat(&self, reference: &str) -> Result<Chat, DomainError> {
+        let found = match ChatReference::parse(reference)? {
+            ChatReference::Id(id) => lock(&self.dialogs).iter().find(|c| c.id == id).cloned(),
+            ChatReference::Username(name) => {
+                let named = |c: &&Chat| {
+                    c.username
+                        .as_deref()
+                        .is_some_and(|u| u.eq_ignore_ascii_case(&name))
+                };
+                let dialog = lock(&self.dialogs).iter().find(named).cloned();
+                dialog.or_else(|| lock(&self.public).iter().find(named).cloned())
+            }
+        };
+        found.ok_or_else(|| DomainError::TgGateway(format!("chat '{}' not found", reference)))
+    }
+
+    fn is_bot(&self) -> bool {
+        self.bot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pages_newest_first_and_ignores_min_id_below_an_offset() {
+        let tg = FakeTgGateway::new(1);
+        tg.post((1..=5).map(|id| text_message(9, id, 1704067200 + i64::from(id), "m")));
+        let ids = |page: Vec<Message>| page.iter().map(|m| m.id).collect::<Vec<_>>();
+
+        assert_eq!(
+            ids(tg.get_messages(9, 2, 0, 10).await.unwrap()),
+            vec![5, 4, 3]
+        );
+        assert_eq!(ids(tg.get_messages(9, 0, 0, 2).await.unwrap()), vec![5, 4]);
+        assert_eq!(
+            ids(tg.get_messages(9, 3, 4, 10).await.unwrap()),
+            vec![3, 2, 1]
+        );
+        assert!(tg.get_messages(8, 0, 0, 10).await.unwrap().is_empty());
+
+        tg.flood_wait_at(5, 30);
+        assert!(matches!(
+            tg.get_messages(9, 0, 0, 10).await,
+            Err(DomainError::FloodWait { seconds: 30 })
+        ));
+        assert_eq!(tg.history_requests().len(), 5);
+
+        let dest = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join("test_fake_tg")
+            .join("photo.jpg");
+        let media = MediaReference {
+            message_id: 1,
+            chat_id: 9,
+            media_type: crate::domain::MediaType::Photo,
+            opaque_ref: "9:1".to_string(),
+            size_bytes: None,
+            filename: None,
+            mime_type: None,
+        };
+        tg.download_media(&media, &dest).await.unwrap();
+        let downloads = tg.downloads();
+        assert_eq!(downloads.len(), 1);
+        assert_eq!(
+            (downloads[0].0.opaque_ref.as_str(), &downloads[0].1),
+            ("9:1", &dest)
+        );
+        assert!(dest.exists());
+        tg.send_message(1, "hi").await.unwrap();
+        assert_eq!(tg.sent_messages(), vec![(1, "hi".to_string())]);
+        assert_eq!(
+            tg.history_requests()[2],
+            HistoryRequest {
+                chat_id: 9,
+                min_id: 3,
+                max_id: 4,
+                limit: 10
+            }
+        );
+    }
+}
@@ -0,0 +1,60 @@
+//! Local HTTP server with canned replies, for tests of adapters that call HTTP APIs (AI
+//! providers, Trello, webhooks). Answers one request per connection, in order, and keeps
+//! what it received so tests can check paths, headers and bodies.
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+/// Serve `replies` on 127.0.0.1, one `(status, body)` per connection, then stop listening.
+/// `status` follows "HTTP/1.1 " ("200 OK") and may carry extra header lines
+/// ("429 Too Many Requests\r\nretry-after: 0"); bodies are sent as JSON. Returns the base
+/// URL (`http://127.0.0.1:<port>`) and a handle yielding the raw requests received.
+pub async fn serve(
+    replies: Vec<(&'static str, &'static str)>,
+) -> (String, JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let server = tokio::spawn(async move {
+        let mut requests = Vec::new();
+        for (status, body) in replies {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            requests.push(read_request(&mut stream).await);
+            let reply = format!(
+                "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            stream.write_all(reply.as_bytes()).await.unwrap();
+        }
+        requests
+    });
+    (url, server)
+}
+
+/// Read one HTTP request: headers, then `content-length` bytes of body.
+async fn read_request(stream: &mut TcpStream) -> String {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = stream.read(&mut buf).await.unwrap();
+        data.extend_from_slice(&buf[..n]);
+        let text = String::from_utf8_lossy(&data).to_string();
+        if let Some(end) = text.find("\r\n\r\n") {
+            let length = text[..end]
+                .lines()
+                .find_map(|l| {
+                    l.to_ascii_lowercase()
+                        .strip_prefix("content-length:")
+                        .map(|v| v.trim().parse::<usize>().unwrap())
+                })
+                .unwrap_or(0);
+            if n == 0 || data.len() >= end + 4 + length {
+                return text;
+            }
+        } else if n == 0 {
+            return text;
+        }
+    }
+}
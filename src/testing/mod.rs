@@ -0,0 +1,10 @@
+//! Test and benchmark support: deterministic synthetic data, an in-memory Telegram, a canned
+//! HTTP server and the persistence bench.
+//!
+//! Public so integration tests under `tests/` and the hidden `tg-sync bench` command can
+//! share the same generator.
+
+pub mod bench;
+pub mod fake_tg;
+pub mod http_stub;
+pub mod synthetic;
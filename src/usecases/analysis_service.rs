@@ -3,34 +3,344 @@
 //! Coordinates between repository (data), AI adapter (analysis), and filesystem (reports).
 //!
 //! Implements Map-Reduce pattern for large chats: chunks are summarized separately,
-//! then combined for final analysis (avoids OOM and token limit exceeded).
+//! then combined for final analysis (avoids OOM and token limit exceeded). Chunk summaries
+//! are stored until the period's analysis is saved, so re-running after a failed reduce step
+//! only pays for the chunks that are missing.
+//!
+//! Messages are grouped into days, weeks or months per chat ([`Granularity`], stored as a
+//! setting; weekly by default).
+//!
+//! Every LLM call's token usage is logged with a cost estimate from the [`PriceTable`]; the
+//! report footer shows what the period cost.
+//!
+//! Finished reports can also be posted to Telegram as plain text (TG_SYNC_DIGEST_TO_TELEGRAM),
+//! split to fit Telegram's message length limit.
+//!
+//! A combined digest analyzes one week of several chats together and is saved under
+//! [`COMBINED_CHAT_ID`]; the model tags topics and action items with their source chat so the
+//! report can group them.
 
-use crate::adapters::ai::messages_to_csv_chunked;
-use crate::domain::{AnalysisResult, DomainError, Message, WeekGroup};
-use crate::ports::{AiPort, AnalysisLogPort, TaskTrackerPort};
+use crate::adapters::ai::{
+    ChunkBudget, ReplyTargets, messages_to_csv_chunked, messages_to_csv_chunked_anonymized,
+};
+use crate::adapters::notify::TelegramNotifier;
+use crate::domain::{
+    ActionItem, ActivityBin, ActivityBucket, AiUsage, AiUsageRecord, AnalysisResult,
+    AnalyzedPeriod, COMBINED_CHAT_ID, ChunkSummary, DomainError, Granularity, Message,
+    NotificationEvent, PeriodGroup, ReportFormat, TaskSpec, UsageTotals,
+};
+use crate::ports::{
+    AiPort, AnalysisLogPort, NotifierPort, RepoPort, SettingsPort, TaskTrackerPort, TgGateway,
+};
+use crate::shared::activity;
+use crate::shared::anonymize::Anonymizer;
+use crate::shared::markdown;
+use crate::shared::paths::join_sanitized;
+use crate::shared::pricing::PriceTable;
+use crate::usecases::notify::notify_all;
+use crate::usecases::watcher_service::stored_alert_chat;
 use chrono::{DateTime, Utc};
-use std::path::PathBuf;
-use std::sync::Arc;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use tokio::fs;
-use tracing::{info, warn};
+use tracing::{debug, info, warn};
+
+/// One generated report: where it was written and what the LLM found.
+#[derive(Debug, Clone)]
+pub struct AnalysisReport {
+    /// The Markdown report; the first of `files` when Markdown is not written.
+    pub path: PathBuf,
+    /// Every file written for the report, one per format of TG_SYNC_REPORT_FORMATS.
+    pub files: Vec<PathBuf>,
+    pub result: AnalysisResult,
+    /// LLM calls made for this report in this run (stored chunk summaries cost nothing).
+    pub usage: UsageTotals,
+}
+
+/// What pushing action items to the task tracker did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrackerPushReport {
+    /// Tasks created.
+    pub created: usize,
+    /// Items that already had a task from an earlier run of the same period.
+    pub skipped: usize,
+    /// Items whose task could not be created: title and error. The tracker keeps them for a
+    /// retry on the next start (dead-letter file).
+    pub failed: Vec<(String, String)>,
+}
+
+impl AnalysisReport {
+    /// The report files, comma-separated, for printing.
+    pub fn files_display(&self) -> String {
+        self.files
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// The JSON report: the analysis result with the chat it covers, for other tools.
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    #[serde(flatten)]
+    result: &'a AnalysisResult,
+    /// Chat title; the titles joined with ", " for a combined digest.
+    chat_title: Option<&'a str>,
+    message_count: usize,
+}
+
+/// Put in front of a combined digest's context so the model attributes what it finds.
+const COMBINED_INSTRUCTIONS: &str = "The context below combines several chats; each section \
+starts with a \"### Chat: <name>\" line. Start every key topic and every action item \
+description with the name of the chat it comes from in square brackets, e.g. \"[Team] Ship v2\".";
+
+/// One chat of a combined digest.
+#[derive(Debug, Clone)]
+struct CombinedSource {
+    /// Name the model sees and tags items with (a pseudonym when anonymizing).
+    label: String,
+    /// Stored chat title shown in the report.
+    title: String,
+}
+
+/// Heading line of one chat's section in a combined digest context.
+fn source_header(label: &str) -> String {
+    format!("### Chat: {}", label)
+}
+
+/// Split a "[label] text" item into the index of its source and the text without the tag.
+/// Items without a known tag return None and are left unchanged.
+fn split_source<'a>(text: &'a str, sources: &[CombinedSource]) -> (Option<usize>, &'a str) {
+    let trimmed = text.trim_start();
+    let Some((tag, rest)) = trimmed.strip_prefix('[').and_then(|t| t.split_once(']')) else {
+        return (None, text);
+    };
+    let tag = tag.trim();
+    match sources
+        .iter()
+        .position(|s| s.label.eq_ignore_ascii_case(tag))
+    {
+        Some(index) => (Some(index), rest.trim_start()),
+        None => (None, text),
+    }
+}
+
+/// Items of one source chat (None: untagged), each with its text without the tag.
+type SourceGroup<'a, T> = (Option<usize>, Vec<(&'a T, &'a str)>);
+
+/// Group items by their source tag, in source order; untagged items come last.
+fn group_by_source<'a, T>(
+    items: &'a [T],
+    text: impl Fn(&'a T) -> &'a str,
+    sources: &[CombinedSource],
+) -> Vec<SourceGroup<'a, T>> {
+    let mut groups: Vec<SourceGroup<'a, T>> = (0..sources.len())
+        .map(|i| (Some(i), Vec::new()))
+        .chain(std::iter::once((None, Vec::new())))
+        .collect();
+    for item in items {
+        let (source, stripped) = split_source(text(item), sources);
+        let slot = source.unwrap_or(sources.len());
+        groups[slot].1.push((item, stripped));
+    }
+    groups.retain(|(_, items)| !items.is_empty());
+    groups
+}
+
+/// Markdown list line of an action item with `description` in bold.
+fn action_item_line(item: &ActionItem, description: &str) -> String {
+    let mut line = format!("- [ ] **{}**", description);
+    let mut meta = Vec::new();
+    if let Some(owner) = &item.owner {
+        meta.push(format!("Owner: {}", owner));
+    }
+    if let Some(deadline) = &item.deadline {
+        meta.push(format!("Due: {}", deadline));
+    }
+    if let Some(priority) = &item.priority {
+        meta.push(format!("Priority: {}", priority));
+    }
+    if !meta.is_empty() {
+        line.push_str(&format!(" ({})", meta.join(", ")));
+    }
+    line.push('\n');
+    line
+}
+
+/// Map phase: summarize `chunks` with at most `concurrency` LLM requests in flight.
+///
+/// Summaries stored by an earlier, unfinished run are reused when the chunk is unchanged; new
+/// ones are stored as soon as they arrive. Summaries are returned in chunk order. The first
+/// failure is returned at once and the requests still in flight are dropped, so no tokens are
+/// spent on a period that can't finish. Usage of every call is recorded as it completes and
+/// returned summed up.
+#[allow(clippy::too_many_arguments)]
+async fn summarize_chunks(
+    ai: &dyn AiPort,
+    log: &dyn AnalysisLogPort,
+    prices: &PriceTable,
+    chat_id: i64,
+    granularity: Granularity,
+    week: &PeriodGroup,
+    chunks: &[String],
+    concurrency: usize,
+) -> Result<(Vec<String>, UsageTotals), DomainError> {
+    let total = chunks.len();
+    let stored: HashMap<usize, ChunkSummary> = log
+        .get_chunk_summaries(chat_id, granularity, week)
+        .await?
+        .into_iter()
+        .map(|c| (c.chunk_index, c))
+        .collect();
+    // Futures are built up front: a closure inside the stream trips the Send check on callers.
+    let requests: Vec<_> = chunks
+        .iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let chunk_hash = chunk_fingerprint(chunk);
+            let reusable = stored
+                .get(&i)
+                .filter(|c| c.chunk_hash == chunk_hash)
+                .map(|c| c.summary.clone());
+            async move {
+                if let Some(summary) = reusable {
+                    info!(chat_id, week = %week, chunk = i + 1, total, "map: reusing stored chunk summary");
+                    return Ok((i, summary, UsageTotals::default()));
+                }
+                info!(chat_id, week = %week, chunk = i + 1, total, "map: summarizing chunk");
+                let reply = ai.summarize(chunk).await?;
+                let mut usage = UsageTotals::default();
+                if let Some(call) = &reply.usage {
+                    let cost = record_usage(log, prices, chat_id, week, call).await;
+                    usage.add(call, cost);
+                }
+                let stored = ChunkSummary {
+                    chunk_index: i,
+                    chunk_hash,
+                    summary: reply.value,
+                };
+                if let Err(e) = log
+                    .save_chunk_summary(chat_id, granularity, week, &stored)
+                    .await
+                {
+                    warn!(chat_id, week = %week, chunk = i + 1, error = %e, "failed to store chunk summary");
+                }
+                Ok::<_, DomainError>((i, stored.summary, usage))
+            }
+        })
+        .collect();
+    let mut summaries: Vec<(usize, String, UsageTotals)> = stream::iter(requests)
+        .buffer_unordered(concurrency.max(1))
+        .try_collect()
+        .await?;
+    summaries.sort_unstable_by_key(|(i, _, _)| *i);
+    let mut totals = UsageTotals::default();
+    for (_, _, usage) in &summaries {
+        totals.merge(usage);
+    }
+    Ok((
+        summaries
+            .into_iter()
+            .map(|(_, summary, _)| summary)
+            .collect(),
+        totals,
+    ))
+}
+
+/// Log one LLM call in the usage table and return its cost estimate. A failed write is only
+/// logged: losing a usage row must not cost the analysis.
+async fn record_usage(
+    log: &dyn AnalysisLogPort,
+    prices: &PriceTable,
+    chat_id: i64,
+    week: &PeriodGroup,
+    usage: &AiUsage,
+) -> Option<f64> {
+    let cost_estimate = prices.cost(usage);
+    let record = AiUsageRecord {
+        chat_id,
+        week_group: week.clone(),
+        usage: usage.clone(),
+        cost_estimate,
+        called_at: Utc::now().timestamp(),
+    };
+    if let Err(e) = log.record_ai_usage(&record).await {
+        warn!(chat_id, week = %week, error = %e, "failed to record AI usage");
+    }
+    cost_estimate
+}
+
+/// Settings key of a chat's analysis granularity.
+fn granularity_key(chat_id: i64) -> String {
+    format!("analysis.granularity.{}", chat_id)
+}
 
-/// Maximum characters per chunk. Conservative for LLM token limits (~15k tokens).
-const MAX_CHUNK_SIZE: usize = 50_000;
+/// FNV-1a over the chunk text: stable across runs and builds, unlike `DefaultHasher`.
+fn chunk_fingerprint(chunk: &str) -> i64 {
+    let hash = chunk.bytes().fold(0xcbf2_9ce4_8422_2325u64, |h, b| {
+        (h ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    });
+    hash as i64
+}
+
+/// Fingerprint of an action item for tracker deduplication: case and whitespace differences
+/// between runs don't make it a new item.
+fn description_fingerprint(description: &str) -> i64 {
+    let normalized = description
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+    chunk_fingerprint(&normalized)
+}
 
 /// Service for AI-powered chat analysis.
 ///
 /// Orchestrates the flow:
-/// 1. Fetch unanalyzed weeks from repository
-/// 2. Generate CSV context for each week
+/// 1. Fetch unanalyzed periods (days, weeks or months) from repository
+/// 2. Generate CSV context for each period
 /// 3. Send to AI for analysis
 /// 4. Save results and generate Markdown reports
-/// 5. Optionally push action items to a task tracker (e.g. Trello)
+/// 5. Optionally push action items to a task tracker (e.g. Trello), once reviewed by the caller
+/// 6. Deliver the report through the configured notifiers (e.g. email) and, when enabled,
+///    post it to Telegram
 pub struct AnalysisService {
     ai: Arc<dyn AiPort>,
     repo: Arc<dyn AnalysisLogPort>,
+    /// Stored chat titles for the report header.
+    chats: Arc<dyn RepoPort>,
+    /// Per-chat analysis granularity.
+    settings: Arc<dyn SettingsPort>,
     reports_dir: PathBuf,
     /// Optional task tracker. When None, action items are only written to the report.
     task_tracker: Option<Arc<dyn TaskTrackerPort>>,
+    /// Configured UTC offset for the report's per-day activity section.
+    utc_offset_secs: i32,
+    /// When set, LLM context uses pseudonyms and redacted text (TG_SYNC_AI_ANONYMIZE).
+    anonymizer: Option<Arc<Mutex<Anonymizer>>>,
+    /// Report delivery (e.g. email digest). Empty: reports are only written to disk.
+    notifiers: Vec<Arc<dyn NotifierPort>>,
+    /// Chunk summaries requested at once in the Map phase (TG_SYNC_AI_CONCURRENCY).
+    ai_concurrency: usize,
+    /// Token limit per CSV chunk for the configured model (TG_SYNC_AI_MAX_CONTEXT_TOKENS).
+    chunk_budget: ChunkBudget,
+    /// Per-model prices for usage cost estimates (TG_SYNC_AI_PRICES).
+    prices: PriceTable,
+    /// Telegram client for posting digests. None: digests are never posted.
+    tg: Option<Arc<dyn TgGateway>>,
+    /// Post digests to Telegram (TG_SYNC_DIGEST_TO_TELEGRAM; switchable in the TUI).
+    digest_to_telegram: AtomicBool,
+    /// Alert chat from config, used when none is stored in settings. Neither: Saved Messages.
+    default_alert_chat: Option<i64>,
+    /// Files written per report (TG_SYNC_REPORT_FORMATS).
+    report_formats: Vec<ReportFormat>,
+    /// Quote the message each CSV row replies to (TG_SYNC_AI_REPLY_CONTEXT).
+    reply_context: bool,
 }
 
 impl AnalysisService {
@@ -39,42 +349,136 @@ impl AnalysisService {
     /// # Arguments
     /// * `ai` - AI port implementation (OpenAI, Mock, etc.)
     /// * `repo` - Repository implementing AnalysisLogPort
+    /// * `chats` - Message repository; supplies the stored chat title for report headers
+    /// * `settings` - Settings store holding each chat's analysis granularity
     /// * `reports_dir` - Directory to save generated reports
     /// * `task_tracker` - Optional task tracker; when None, action items are only in the report
+    /// * `utc_offset_secs` - Timezone offset used to bucket messages per day in reports
+    /// * `anonymizer` - Optional pseudonymizer applied to the CSV context sent to the LLM
+    /// * `notifiers` - Where finished reports are delivered; may be empty
+    /// * `ai_concurrency` - Max chunk summaries in flight during the Map phase
+    /// * `chunk_budget` - Token limit per chunk and the model whose tokenizer counts it
+    /// * `prices` - Per-model prices used to estimate the cost of each LLM call
+    /// * `tg` - Telegram client for posting digests; None disables posting
+    /// * `digest_to_telegram` - Whether digests are posted to Telegram initially
+    /// * `default_alert_chat` - Chat digests go to when no alert chat is stored; None = Saved Messages
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         ai: Arc<dyn AiPort>,
         repo: Arc<dyn AnalysisLogPort>,
+        chats: Arc<dyn RepoPort>,
+        settings: Arc<dyn SettingsPort>,
         reports_dir: PathBuf,
         task_tracker: Option<Arc<dyn TaskTrackerPort>>,
+        utc_offset_secs: i32,
+        anonymizer: Option<Arc<Mutex<Anonymizer>>>,
+        notifiers: Vec<Arc<dyn NotifierPort>>,
+        ai_concurrency: usize,
+        chunk_budget: ChunkBudget,
+        prices: PriceTable,
+        tg: Option<Arc<dyn TgGateway>>,
+        digest_to_telegram: bool,
+        default_alert_chat: Option<i64>,
     ) -> Self {
         Self {
             ai,
             repo,
+            chats,
+            settings,
             reports_dir,
             task_tracker,
+            utc_offset_secs,
+            anonymizer,
+            notifiers,
+            ai_concurrency: ai_concurrency.max(1),
+            chunk_budget,
+            prices,
+            tg,
+            digest_to_telegram: AtomicBool::new(digest_to_telegram),
+            default_alert_chat,
+            report_formats: vec![ReportFormat::Markdown],
+            reply_context: false,
         }
     }
 
-    /// Analyze unprocessed weeks for a chat.
+    /// Write each report in `formats` instead of Markdown only. Empty keeps Markdown.
+    pub fn with_report_formats(mut self, formats: Vec<ReportFormat>) -> Self {
+        if !formats.is_empty() {
+            self.report_formats = formats;
+        }
+        self
+    }
+
+    /// Give the CSV a `ReplyTo` column quoting the message each row replies to, so the model
+    /// sees which questions were answered. Costs tokens for every reply.
+    pub fn with_reply_context(mut self, enabled: bool) -> Self {
+        self.reply_context = enabled;
+        self
+    }
+
+    /// Whether finished digests are posted to Telegram.
+    pub fn digest_to_telegram(&self) -> bool {
+        self.digest_to_telegram.load(Ordering::Relaxed)
+    }
+
+    /// Turn posting digests to Telegram on or off for the following runs.
+    pub fn set_digest_to_telegram(&self, enabled: bool) {
+        self.digest_to_telegram.store(enabled, Ordering::Relaxed);
+    }
+
+    /// AI usage of all analysis runs summed per month, newest first.
+    pub async fn usage_by_month(&self) -> Result<Vec<(String, UsageTotals)>, DomainError> {
+        self.repo.get_ai_usage_by_month().await
+    }
+
+    /// Analysis grouping chosen for `chat_id`; weekly when never set.
+    pub async fn granularity(&self, chat_id: i64) -> Result<Granularity, DomainError> {
+        let stored = self.settings.get_setting(&granularity_key(chat_id)).await?;
+        Ok(stored
+            .as_deref()
+            .and_then(Granularity::from_name)
+            .unwrap_or_default())
+    }
+
+    /// Remember the analysis grouping for `chat_id`.
+    pub async fn set_granularity(
+        &self,
+        chat_id: i64,
+        granularity: Granularity,
+    ) -> Result<(), DomainError> {
+        self.settings
+            .set_setting(&granularity_key(chat_id), Some(granularity.as_str()))
+            .await
+    }
+
+    /// Periods of `chat_id` (in its granularity) with stored messages and no analysis yet,
+    /// oldest first.
+    pub async fn pending_weeks(&self, chat_id: i64) -> Result<Vec<PeriodGroup>, DomainError> {
+        let granularity = self.granularity(chat_id).await?;
+        self.repo.get_unanalyzed_weeks(chat_id, granularity).await
+    }
+
+    /// Analyze unprocessed periods for a chat, in the chat's granularity.
     ///
-    /// Returns paths to generated Markdown reports.
-    /// Skips already-analyzed weeks (idempotent).
+    /// Returns the generated Markdown reports with their analysis results.
+    /// Skips already-analyzed periods (idempotent).
     ///
     /// # Arguments
     /// * `chat_id` - The chat to analyze
-    /// * `single_week` - If true, only the most recent unanalyzed week is processed; older weeks are ignored
+    /// * `single_week` - If true, only the most recent unanalyzed period is processed; older ones are ignored
     pub async fn analyze_chat(
         &self,
         chat_id: i64,
         single_week: bool,
-    ) -> Result<Vec<PathBuf>, DomainError> {
+    ) -> Result<Vec<AnalysisReport>, DomainError> {
         // Ensure reports directory exists
         fs::create_dir_all(&self.reports_dir)
             .await
             .map_err(|e| DomainError::Repo(format!("Failed to create reports dir: {}", e)))?;
 
-        // Get weeks that haven't been analyzed yet (chronological order, oldest first)
-        let mut unanalyzed_weeks = self.repo.get_unanalyzed_weeks(chat_id).await?;
+        // Get periods that haven't been analyzed yet (chronological order, oldest first)
+        let granularity = self.granularity(chat_id).await?;
+        let mut unanalyzed_weeks = self.repo.get_unanalyzed_weeks(chat_id, granularity).await?;
         if unanalyzed_weeks.is_empty() {
             info!(chat_id, "no unanalyzed weeks found");
             return Ok(Vec::new());
@@ -92,78 +496,369 @@ impl AnalysisService {
 
         info!(
             chat_id,
+            granularity = granularity.as_str(),
             weeks = unanalyzed_weeks.len(),
-            "found unanalyzed weeks"
+            "found unanalyzed periods"
         );
 
-        // Get all messages grouped by week
-        let weeks_data = self.repo.get_messages_by_week(chat_id).await?;
+        let title = self.chat_title(chat_id).await;
 
         let mut reports = Vec::new();
 
-        for (week, messages) in weeks_data {
-            // Skip if not in our unanalyzed set
-            if !unanalyzed_weeks.contains(&week) {
-                continue;
+        // One period in memory at a time; analyzed periods are never loaded.
+        for week in unanalyzed_weeks {
+            if let Some(report) = self
+                .analyze_period(chat_id, granularity, &week, title.as_deref())
+                .await?
+            {
+                reports.push(report);
             }
+        }
 
-            if messages.is_empty() {
-                warn!(chat_id, week = %week, "week has no messages after filtering");
-                continue;
-            }
+        info!(
+            chat_id,
+            reports_generated = reports.len(),
+            "analysis complete"
+        );
 
-            info!(
-                chat_id,
-                week = %week,
-                messages = messages.len(),
-                "analyzing week"
-            );
+        Ok(reports)
+    }
 
-            // Generate CSV chunks (avoids memory bomb for large weeks)
-            let chunks = self.messages_to_csv_chunked(&messages, MAX_CHUNK_SIZE)?;
+    /// Analyze one period of a chat: save the result, write and deliver the report. None when
+    /// the period has no messages left after filtering.
+    async fn analyze_period(
+        &self,
+        chat_id: i64,
+        granularity: Granularity,
+        week: &PeriodGroup,
+        title: Option<&str>,
+    ) -> Result<Option<AnalysisReport>, DomainError> {
+        let messages = self
+            .repo
+            .get_messages_for_week(chat_id, granularity, week)
+            .await?;
+        if messages.is_empty() {
+            warn!(chat_id, week = %week, "week has no messages after filtering");
+            return Ok(None);
+        }
 
-            // Map-Reduce: single chunk -> direct analyze; multiple chunks -> summarize then analyze
-            let result = self.analyze_week_chunks(chat_id, &week, &chunks).await?;
+        info!(
+            chat_id,
+            week = %week,
+            messages = messages.len(),
+            "analyzing week"
+        );
 
-            // Persist result
-            self.repo.save_analysis(&result).await?;
+        // Generate CSV chunks (avoids memory bomb for large weeks)
+        let replies = self.reply_targets(chat_id, &messages).await?;
+        let chunks = self.messages_to_csv_chunked(&messages, replies.as_ref())?;
+        self.log_chunk_tokens(chat_id, week, &chunks);
 
-            // Push action items to task tracker if configured
-            self.send_action_items_to_tracker(&result).await;
+        // Map-Reduce: single chunk -> direct analyze; multiple chunks -> summarize then analyze
+        let (mut result, usage) = self
+            .analyze_week_chunks(chat_id, granularity, week, &chunks)
+            .await?;
+        result.granularity = granularity;
 
-            // Generate and save report
-            let report_path = self.generate_report(&result).await?;
-            reports.push(report_path);
+        // Persist result; the Map-phase summaries of this period are no longer needed
+        self.repo.save_analysis(&result).await?;
+        if let Err(e) = self
+            .repo
+            .delete_chunk_summaries(chat_id, granularity, week)
+            .await
+        {
+            warn!(chat_id, week = %week, error = %e, "failed to delete chunk summaries");
         }
 
-        info!(
+        // Messages per day of the period, for the report's Activity section
+        let activity = self.period_activity(&messages, granularity);
+
+        // Generate and save report
+        let report = self.render_report(&result, title, &[], &activity, &usage);
+        let files = self
+            .write_reports(&result, &report, title, messages.len())
+            .await?;
+
+        // Deliver (email digest etc.) if configured
+        self.deliver_report(&result, report).await;
+        Ok(Some(AnalysisReport {
+            path: files[0].clone(),
+            files,
+            result,
+            usage,
+        }))
+    }
+
+    /// Periods of `chat_id` with a stored analysis, in any grouping.
+    pub async fn analyzed_periods(&self, chat_id: i64) -> Result<Vec<AnalyzedPeriod>, DomainError> {
+        self.repo.get_analyzed_weeks(chat_id).await
+    }
+
+    /// Stored analysis of one period of a chat; None when it was not analyzed.
+    pub async fn stored_analysis(
+        &self,
+        chat_id: i64,
+        period: &AnalyzedPeriod,
+    ) -> Result<Option<AnalysisResult>, DomainError> {
+        self.repo
+            .get_analysis(chat_id, period.granularity, &period.week_group)
+            .await
+    }
+
+    /// Markdown report of a stored analysis. Written again when the file is gone, then
+    /// without the activity and AI usage sections (they are not stored).
+    pub async fn report_file(&self, result: &AnalysisResult) -> Result<PathBuf, DomainError> {
+        let path = self.report_path(result, ReportFormat::Markdown);
+        if fs::try_exists(&path).await.unwrap_or(false) {
+            return Ok(path);
+        }
+        fs::create_dir_all(&self.reports_dir)
+            .await
+            .map_err(|e| DomainError::Repo(format!("Failed to create reports dir: {}", e)))?;
+        let title = self.chat_title(result.chat_id).await;
+        let report =
+            self.render_report(result, title.as_deref(), &[], &[], &UsageTotals::default());
+        write_file(&path, &report).await?;
+        Ok(path)
+    }
+
+    /// Post a stored analysis to Telegram again, whatever TG_SYNC_DIGEST_TO_TELEGRAM says.
+    /// Returns the chat it went to.
+    pub async fn resend_to_telegram(&self, result: &AnalysisResult) -> Result<i64, DomainError> {
+        let title = self.chat_title(result.chat_id).await;
+        let event = NotificationEvent::AnalysisCompleted {
+            chat_id: result.chat_id,
+            granularity: result.granularity,
+            week_group: result.week_group.clone(),
+            report_markdown: self.render_report(
+                result,
+                title.as_deref(),
+                &[],
+                &[],
+                &UsageTotals::default(),
+            ),
+        };
+        self.send_digest(&event).await
+    }
+
+    /// Forget the stored analysis of one period and analyze it again, in the period's own
+    /// grouping. Tasks already created for its action items are kept (and not created twice).
+    pub async fn reanalyze_period(
+        &self,
+        chat_id: i64,
+        period: &AnalyzedPeriod,
+    ) -> Result<Option<AnalysisReport>, DomainError> {
+        fs::create_dir_all(&self.reports_dir)
+            .await
+            .map_err(|e| DomainError::Repo(format!("Failed to create reports dir: {}", e)))?;
+        self.repo
+            .delete_analysis(chat_id, period.granularity, &period.week_group)
+            .await?;
+        info!(chat_id, week = %period.week_group, "stored analysis deleted; analyzing again");
+        let title = self.chat_title(chat_id).await;
+        self.analyze_period(
             chat_id,
-            reports_generated = reports.len(),
-            "analysis complete"
-        );
+            period.granularity,
+            &period.week_group,
+            title.as_deref(),
+        )
+        .await
+    }
 
-        Ok(reports)
+    /// Get list of periods available for analysis (both analyzed and unanalyzed).
+    pub async fn get_available_weeks(&self, chat_id: i64) -> Result<Vec<PeriodGroup>, DomainError> {
+        let granularity = self.granularity(chat_id).await?;
+        self.repo.get_periods(chat_id, granularity).await
     }
 
-    /// Get list of weeks available for analysis (both analyzed and unanalyzed).
-    pub async fn get_available_weeks(&self, chat_id: i64) -> Result<Vec<WeekGroup>, DomainError> {
-        let weeks_data = self.repo.get_messages_by_week(chat_id).await?;
-        Ok(weeks_data.into_iter().map(|(week, _)| week).collect())
+    /// Weeks in which at least one of `chat_ids` has stored messages, oldest first.
+    pub async fn combined_weeks(&self, chat_ids: &[i64]) -> Result<Vec<PeriodGroup>, DomainError> {
+        let mut weeks = Vec::new();
+        for &chat_id in chat_ids {
+            for week in self.repo.get_periods(chat_id, Granularity::Week).await? {
+                if !weeks.contains(&week) {
+                    weeks.push(week);
+                }
+            }
+        }
+        weeks.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        Ok(weeks)
+    }
+
+    /// Analyze one week of several chats as a single digest.
+    ///
+    /// Each chat's CSV is prefixed with a "### Chat:" heading and goes through the same
+    /// Map-Reduce pipeline as a single chat. The result is saved under [`COMBINED_CHAT_ID`]
+    /// (re-running a week replaces it), and the report groups topics and action items by
+    /// source chat. Chats without messages that week are left out.
+    pub async fn analyze_week_across_chats(
+        &self,
+        chat_ids: &[i64],
+        week: &PeriodGroup,
+    ) -> Result<AnalysisReport, DomainError> {
+        fs::create_dir_all(&self.reports_dir)
+            .await
+            .map_err(|e| DomainError::Repo(format!("Failed to create reports dir: {}", e)))?;
+
+        let granularity = Granularity::Week;
+        let mut sources = Vec::new();
+        let mut messages = Vec::new();
+        // CSV chunks of all chats, each headed by its chat, and the source of each chunk
+        let mut chunks = Vec::new();
+        let mut chunk_sources = Vec::new();
+        for &chat_id in chat_ids {
+            let chat_messages = self
+                .repo
+                .get_messages_for_week(chat_id, granularity, week)
+                .await?;
+            if chat_messages.is_empty() {
+                info!(chat_id, week = %week, "combined digest: no messages this week, skipping chat");
+                continue;
+            }
+            let title = self
+                .chat_title(chat_id)
+                .await
+                .unwrap_or_else(|| chat_id.to_string());
+            // Chat titles stay out of the LLM context when anonymizing.
+            let label = match self.anonymizer {
+                Some(_) => format!("Chat {}", sources.len() + 1),
+                None => title.clone(),
+            };
+            let header = source_header(&label);
+            let replies = self.reply_targets(chat_id, &chat_messages).await?;
+            for chunk in self.messages_to_csv_chunked(&chat_messages, replies.as_ref())? {
+                chunks.push(format!("{}\n{}", header, chunk));
+                chunk_sources.push(sources.len());
+            }
+            sources.push(CombinedSource { label, title });
+            messages.extend(chat_messages);
+        }
+        if sources.is_empty() {
+            return Err(DomainError::Ai(format!(
+                "No messages in week {} for the selected chats",
+                week
+            )));
+        }
+        info!(week = %week, chats = sources.len(), messages = messages.len(), "analyzing combined week");
+        self.log_chunk_tokens(COMBINED_CHAT_ID, week, &chunks);
+
+        // Small weeks go to the model in one piece; otherwise Map-Reduce, where each summary
+        // gets its chat heading back so the reduce step can still attribute it.
+        let joined = chunks.join("\n\n");
+        let (context, usage) =
+            if self.chunk_budget.counter().count(&joined) <= self.chunk_budget.max_tokens {
+                (joined, UsageTotals::default())
+            } else {
+                let (summaries, usage) = summarize_chunks(
+                    self.ai.as_ref(),
+                    self.repo.as_ref(),
+                    &self.prices,
+                    COMBINED_CHAT_ID,
+                    granularity,
+                    week,
+                    &chunks,
+                    self.ai_concurrency,
+                )
+                .await?;
+                let meta_context = summaries
+                    .iter()
+                    .zip(&chunk_sources)
+                    .map(|(summary, &source)| {
+                        format!("{}\n{}", source_header(&sources[source].label), summary)
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n\n");
+                (meta_context, usage)
+            };
+        let context = format!("{}\n\n{}", COMBINED_INSTRUCTIONS, context);
+        let (mut result, usage) = self
+            .analyze_context(COMBINED_CHAT_ID, week, &context, usage)
+            .await?;
+        result.chat_id = COMBINED_CHAT_ID;
+        result.granularity = granularity;
+
+        self.repo.save_analysis(&result).await?;
+        if let Err(e) = self
+            .repo
+            .delete_chunk_summaries(COMBINED_CHAT_ID, granularity, week)
+            .await
+        {
+            warn!(week = %week, error = %e, "failed to delete chunk summaries");
+        }
+        let activity = self.period_activity(&messages, granularity);
+        let report = self.render_report(&result, None, &sources, &activity, &usage);
+        let titles = sources
+            .iter()
+            .map(|s| s.title.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let files = self
+            .write_reports(&result, &report, Some(&titles), messages.len())
+            .await?;
+        self.deliver_report(&result, report).await;
+        Ok(AnalysisReport {
+            path: files[0].clone(),
+            files,
+            result,
+            usage,
+        })
+    }
+
+    /// Whether a task tracker is configured, i.e. whether action items are worth reviewing.
+    pub fn has_task_tracker(&self) -> bool {
+        self.task_tracker.is_some()
+    }
+
+    /// Push the action items at `selected` (indices into `result.action_items`) to the task
+    /// tracker. Analysis never pushes on its own: callers review the items first, or pass every
+    /// index to push them all.
+    pub async fn push_action_items(
+        &self,
+        result: &AnalysisResult,
+        selected: &[usize],
+    ) -> TrackerPushReport {
+        let mut approved = result.clone();
+        approved.action_items = selected
+            .iter()
+            .filter_map(|&i| result.action_items.get(i).cloned())
+            .collect();
+        self.send_action_items_to_tracker(&approved).await
     }
 
-    /// Send action items to the task tracker (if configured). Logs warnings on failure but does not fail the analysis.
-    async fn send_action_items_to_tracker(&self, result: &AnalysisResult) {
+    /// Send action items to the task tracker (if configured) as one batch. Items that already
+    /// got a task in an earlier run of the same period are skipped. Failures are reported, not
+    /// returned: they never fail the analysis.
+    async fn send_action_items_to_tracker(&self, result: &AnalysisResult) -> TrackerPushReport {
+        let mut report = TrackerPushReport::default();
         if result.action_items.is_empty() {
-            return;
+            return report;
         }
         let Some(tracker) = &self.task_tracker else {
             warn!(
                 "Trello not configured (TRELLO_KEY, TRELLO_TOKEN, TRELLO_LIST_ID); skipping task sync"
             );
-            return;
+            return report;
         };
+        let (chat_id, granularity, week) = (result.chat_id, result.granularity, &result.week_group);
+        let mut hashes = Vec::new();
+        let mut tasks = Vec::new();
         for item in &result.action_items {
             let title = item.description.as_str();
+            let hash = description_fingerprint(title);
+            match self
+                .repo
+                .has_tracker_task(chat_id, granularity, week, hash)
+                .await
+            {
+                Ok(true) => {
+                    debug!(chat_id, week = %week, title, "task already in tracker; skipping");
+                    report.skipped += 1;
+                    continue;
+                }
+                Ok(false) => {}
+                // Better a possible duplicate than a lost action item.
+                Err(e) => warn!(chat_id, week = %week, error = %e, "failed to check tracker tasks"),
+            }
             let desc_parts: Vec<String> = [
                 item.owner.as_ref().map(|o| format!("Owner: {}", o)),
                 item.priority.as_ref().map(|p| format!("Priority: {}", p)),
@@ -174,59 +869,260 @@ impl AnalysisService {
             let description = if desc_parts.is_empty() {
                 String::new()
             } else {
-                format!("{}\n\nWeek: {}", desc_parts.join("\n"), result.week_group)
+                format!(
+                    "{}\n\n{}: {}",
+                    desc_parts.join("\n"),
+                    result.granularity.label(),
+                    result.week_group
+                )
             };
-            let due = item.deadline.clone();
-            if let Err(e) = tracker.create_task(title, &description, due).await {
-                warn!(chat_id = result.chat_id, week = %result.week_group, title, error = %e, "failed to create task in tracker");
+            hashes.push(hash);
+            tasks.push(TaskSpec {
+                title: item.description.clone(),
+                description,
+                due: item.deadline.clone(),
+                priority: item.priority.clone(),
+            });
+        }
+        if tasks.is_empty() {
+            return report;
+        }
+
+        let results = tracker.create_tasks(&tasks).await;
+        for ((task, hash), created) in tasks.iter().zip(hashes).zip(results) {
+            let title = task.title.as_str();
+            match created {
+                Ok(external_id) => {
+                    report.created += 1;
+                    if let Err(e) = self
+                        .repo
+                        .save_tracker_task(chat_id, granularity, week, hash, &external_id)
+                        .await
+                    {
+                        warn!(chat_id, week = %week, title, error = %e, "failed to record tracker task");
+                    }
+                }
+                Err(e) => {
+                    warn!(chat_id, week = %week, title, error = %e, "failed to create task in tracker");
+                    report.failed.push((task.title.clone(), e.to_string()));
+                }
+            }
+        }
+        report
+    }
+
+    /// Post the report to Telegram (if enabled) and send it to every notifier. Logs warnings
+    /// on failure but does not fail the analysis.
+    async fn deliver_report(&self, result: &AnalysisResult, report_markdown: String) {
+        let event = NotificationEvent::AnalysisCompleted {
+            chat_id: result.chat_id,
+            granularity: result.granularity,
+            week_group: result.week_group.clone(),
+            report_markdown,
+        };
+        self.post_to_telegram(result, &event).await;
+        notify_all(&self.notifiers, &event).await;
+    }
+
+    /// Post the report as plain text to the alert chat (Saved Messages when none is set), in as
+    /// many messages as Telegram's length limit needs. Stops at the first failed message.
+    async fn post_to_telegram(&self, result: &AnalysisResult, event: &NotificationEvent) {
+        if !self.digest_to_telegram() {
+            return;
+        }
+        if self.tg.is_none() {
+            warn!("Telegram client not available; skipping digest post");
+            return;
+        }
+        match self.send_digest(event).await {
+            Ok(chat_id) => info!(chat_id, week = %result.week_group, "digest posted to Telegram"),
+            Err(e) => {
+                warn!(week = %result.week_group, error = %e, "failed to post digest to Telegram")
             }
         }
     }
 
-    /// Generate CSV chunks, each under MAX_CHUNK_SIZE characters.
+    /// Send `event` to the digest chat (see [`Self::post_to_telegram`]); returns the chat id.
+    async fn send_digest(&self, event: &NotificationEvent) -> Result<i64, DomainError> {
+        let Some(tg) = &self.tg else {
+            return Err(DomainError::TgGateway(
+                "Telegram client not available".to_string(),
+            ));
+        };
+        let chat_id =
+            match stored_alert_chat(self.settings.as_ref(), self.default_alert_chat).await? {
+                Some(chat_id) => chat_id,
+                None if tg.is_bot() => {
+                    return Err(DomainError::Notify(
+                        "a bot account has no Saved Messages; choose an alert chat".to_string(),
+                    ));
+                }
+                None => tg.get_me_id().await?,
+            };
+        TelegramNotifier::new(Arc::clone(tg), chat_id)
+            .notify(event)
+            .await?;
+        Ok(chat_id)
+    }
+
+    /// Messages that `messages` reply to, when reply context is on. Replies to messages
+    /// outside the period are looked up too.
+    async fn reply_targets(
+        &self,
+        chat_id: i64,
+        messages: &[Message],
+    ) -> Result<Option<ReplyTargets>, DomainError> {
+        if !self.reply_context {
+            return Ok(None);
+        }
+        let ids: Vec<i32> = messages.iter().filter_map(|m| m.reply_to_msg_id).collect();
+        self.chats.get_messages_map(chat_id, &ids).await.map(Some)
+    }
+
+    /// Generate CSV chunks, each within the token budget.
     fn messages_to_csv_chunked(
         &self,
         messages: &[Message],
-        max_size: usize,
+        replies: Option<&ReplyTargets>,
     ) -> Result<Vec<String>, DomainError> {
-        messages_to_csv_chunked(messages, max_size)
-            .map_err(|e| DomainError::Ai(format!("Failed to generate CSV chunks: {}", e)))
+        let chunks = match &self.anonymizer {
+            Some(anonymizer) => {
+                let mut anonymizer = anonymizer
+                    .lock()
+                    .map_err(|_| DomainError::Ai("anonymizer lock poisoned".to_string()))?;
+                let chunks = messages_to_csv_chunked_anonymized(
+                    messages,
+                    &self.chunk_budget,
+                    replies,
+                    &mut anonymizer,
+                );
+                if let Err(e) = anonymizer.persist() {
+                    warn!(error = %e, "failed to persist anonymization map");
+                }
+                chunks
+            }
+            None => messages_to_csv_chunked(messages, &self.chunk_budget, replies),
+        };
+        chunks.map_err(|e| DomainError::Ai(format!("Failed to generate CSV chunks: {}", e)))
     }
 
-    /// Analyze week data: single chunk -> direct analyze; multiple chunks -> Map-Reduce.
+    /// Log the estimated token count of every chunk (exact for OpenAI models, else chars/4).
+    fn log_chunk_tokens(&self, chat_id: i64, week: &PeriodGroup, chunks: &[String]) {
+        let counter = self.chunk_budget.counter();
+        let tokens: Vec<usize> = chunks.iter().map(|c| counter.count(c)).collect();
+        for (index, count) in tokens.iter().enumerate() {
+            debug!(chat_id, week = %week, chunk = index, tokens = count, "chunk token estimate");
+        }
+        info!(
+            chat_id,
+            week = %week,
+            chunks = chunks.len(),
+            tokens = tokens.iter().sum::<usize>(),
+            max_tokens = self.chunk_budget.max_tokens,
+            exact = counter.is_exact(),
+            "estimated context tokens"
+        );
+    }
+
+    /// Analyze period data: single chunk -> direct analyze; multiple chunks -> Map-Reduce.
+    /// Returns the analysis and the usage of all LLM calls it took.
     async fn analyze_week_chunks(
         &self,
         chat_id: i64,
-        week: &WeekGroup,
+        granularity: Granularity,
+        week: &PeriodGroup,
         chunks: &[String],
-    ) -> Result<AnalysisResult, DomainError> {
+    ) -> Result<(AnalysisResult, UsageTotals), DomainError> {
         if chunks.is_empty() {
             return Err(DomainError::Ai("No chunks to analyze".to_string()));
         }
 
-        if chunks.len() == 1 {
+        let (context, usage) = if chunks.len() == 1 {
             // Case A (Small): Single chunk, call analyze directly
-            self.ai.analyze(chat_id, week, &chunks[0]).await
+            (chunks[0].clone(), UsageTotals::default())
         } else {
             // Case B (Large): Map each chunk to summary, Reduce to final analysis
-            let mut summaries = Vec::with_capacity(chunks.len());
-            for (i, chunk) in chunks.iter().enumerate() {
-                info!(chat_id, week = %week, chunk = i + 1, total = chunks.len(), "map: summarizing chunk");
-                let summary = self.ai.summarize(chunk).await?;
-                summaries.push(summary);
-            }
+            let (summaries, usage) = summarize_chunks(
+                self.ai.as_ref(),
+                self.repo.as_ref(),
+                &self.prices,
+                chat_id,
+                granularity,
+                week,
+                chunks,
+                self.ai_concurrency,
+            )
+            .await?;
 
             let meta_context = summaries.join("\n\n");
             info!(chat_id, week = %week, summaries_len = meta_context.len(), "reduce: analyzing combined summaries");
-            self.ai.analyze(chat_id, week, &meta_context).await
+            (meta_context, usage)
+        };
+
+        self.analyze_context(chat_id, week, &context, usage).await
+    }
+
+    /// Final analysis call on a prepared context; its usage is recorded and added to `usage`.
+    async fn analyze_context(
+        &self,
+        chat_id: i64,
+        week: &PeriodGroup,
+        context: &str,
+        mut usage: UsageTotals,
+    ) -> Result<(AnalysisResult, UsageTotals), DomainError> {
+        let reply = self.ai.analyze(chat_id, week, context).await?;
+        if let Some(call) = &reply.usage {
+            let cost = record_usage(self.repo.as_ref(), &self.prices, chat_id, week, call).await;
+            usage.add(call, cost);
         }
+        Ok((reply.value, usage))
+    }
+
+    /// Per-day message counts for the period the messages belong to, zero-filled: Monday..Sunday
+    /// for weeks, otherwise the days from the first to the last message.
+    fn period_activity(&self, messages: &[Message], granularity: Granularity) -> Vec<ActivityBin> {
+        let (Some(first), Some(last)) = (
+            messages.iter().map(|m| m.date).min(),
+            messages.iter().map(|m| m.date).max(),
+        ) else {
+            return Vec::new();
+        };
+        let range = match granularity {
+            Granularity::Week => activity::week_range_containing(first, self.utc_offset_secs),
+            Granularity::Day | Granularity::Month => {
+                activity::day_span(first, last, self.utc_offset_secs)
+            }
+        };
+        let bins = activity::histogram_from_dates(
+            messages.iter().map(|m| m.date),
+            ActivityBucket::Day,
+            self.utc_offset_secs,
+        );
+        activity::daily_series(&bins, range, self.utc_offset_secs)
     }
 
-    /// Generate a Markdown report from analysis result.
-    async fn generate_report(&self, result: &AnalysisResult) -> Result<PathBuf, DomainError> {
-        let filename = format!("analysis_{}_{}.md", result.chat_id, result.week_group);
-        let path = self.reports_dir.join(&filename);
+    /// Stored title of `chat_id`; None when the archive has none (lookup errors are logged).
+    async fn chat_title(&self, chat_id: i64) -> Option<String> {
+        match self.chats.get_known_chats().await {
+            Ok(chats) => chats.into_iter().find(|c| c.id == chat_id).map(|c| c.title),
+            Err(e) => {
+                warn!(chat_id, error = %e, "failed to load chat title");
+                None
+            }
+        }
+    }
 
+    /// Render the Markdown report for an analysis result. `sources` lists the chats of a
+    /// combined digest (empty for a single chat); topics and action items are then grouped
+    /// by chat.
+    fn render_report(
+        &self,
+        result: &AnalysisResult,
+        title: Option<&str>,
+        sources: &[CombinedSource],
+        activity_days: &[ActivityBin],
+        usage: &UsageTotals,
+    ) -> String {
         let timestamp = DateTime::<Utc>::from_timestamp(result.analyzed_at, 0)
             .map(|dt| dt.format("%Y-%m-%d %H:%M UTC").to_string())
             .unwrap_or_else(|| "Unknown".to_string());
@@ -234,11 +1130,29 @@ impl AnalysisService {
         let mut md = String::new();
 
         // Header
-        md.push_str(&format!("# Weekly Digest: {}\n\n", result.week_group));
         md.push_str(&format!(
-            "**Chat ID:** {} | **Analyzed:** {}\n\n",
-            result.chat_id, timestamp
+            "# {} Digest: {}\n\n",
+            result.granularity.adjective(),
+            result.week_group
         ));
+        match title {
+            _ if !sources.is_empty() => {
+                let titles: Vec<&str> = sources.iter().map(|s| s.title.as_str()).collect();
+                md.push_str(&format!(
+                    "**Chats:** {} | **Analyzed:** {}\n\n",
+                    titles.join(", "),
+                    timestamp
+                ))
+            }
+            Some(title) => md.push_str(&format!(
+                "**Chat:** {} ({}) | **Analyzed:** {}\n\n",
+                title, result.chat_id, timestamp
+            )),
+            None => md.push_str(&format!(
+                "**Chat ID:** {} | **Analyzed:** {}\n\n",
+                result.chat_id, timestamp
+            )),
+        }
         md.push_str("---\n\n");
 
         // Summary
@@ -246,51 +1160,688 @@ impl AnalysisService {
         md.push_str(&result.summary);
         md.push_str("\n\n");
 
+        // Heading of a source chat's group in a combined digest
+        let group_heading = |source: Option<usize>| match source {
+            Some(i) => format!("### {}\n\n", sources[i].title),
+            None => "### Other\n\n".to_string(),
+        };
+
         // Key Topics
         if !result.key_topics.is_empty() {
             md.push_str("## 🔑 Key Topics\n\n");
-            for topic in &result.key_topics {
-                md.push_str(&format!("- {}\n", topic));
+            if sources.is_empty() {
+                for topic in &result.key_topics {
+                    md.push_str(&format!("- {}\n", topic));
+                }
+                md.push_str("\n");
+            } else {
+                for (source, topics) in group_by_source(&result.key_topics, |t| t.as_str(), sources)
+                {
+                    md.push_str(&group_heading(source));
+                    for (_, topic) in topics {
+                        md.push_str(&format!("- {}\n", topic));
+                    }
+                    md.push('\n');
+                }
             }
-            md.push_str("\n");
         }
 
         // Action Items
         if !result.action_items.is_empty() {
             md.push_str("## 🚀 Action Items\n\n");
-            for item in &result.action_items {
-                md.push_str(&format!("- [ ] **{}**", item.description));
-
-                let mut meta = Vec::new();
-                if let Some(owner) = &item.owner {
-                    meta.push(format!("Owner: {}", owner));
-                }
-                if let Some(deadline) = &item.deadline {
-                    meta.push(format!("Due: {}", deadline));
+            if sources.is_empty() {
+                for item in &result.action_items {
+                    md.push_str(&action_item_line(item, &item.description));
                 }
-                if let Some(priority) = &item.priority {
-                    meta.push(format!("Priority: {}", priority));
+                md.push('\n');
+            } else {
+                let groups =
+                    group_by_source(&result.action_items, |i| i.description.as_str(), sources);
+                for (source, items) in groups {
+                    md.push_str(&group_heading(source));
+                    for (item, description) in items {
+                        md.push_str(&action_item_line(item, description));
+                    }
+                    md.push('\n');
                 }
+            }
+        }
 
-                if !meta.is_empty() {
-                    md.push_str(&format!(" ({})", meta.join(", ")));
-                }
+        // Activity (messages per day)
+        if !activity_days.is_empty() {
+            md.push_str("## 📈 Activity\n\n");
+            let counts: Vec<u64> = activity_days.iter().map(|b| b.count).collect();
+            let rows: Vec<(String, u64)> = activity_days
+                .iter()
+                .map(|b| {
+                    (
+                        activity::day_label(b.bucket_start, self.utc_offset_secs),
+                        b.count,
+                    )
+                })
+                .collect();
+            md.push_str(&format!("`{}`\n\n", activity::sparkline(&counts)));
+            md.push_str("```\n");
+            for row in activity::bar_rows(&rows, 30) {
+                md.push_str(&row);
                 md.push('\n');
             }
-            md.push('\n');
+            md.push_str("```\n\n");
         }
 
         // Footer
         md.push_str("---\n");
+        if usage.calls > 0 {
+            md.push_str(&format!(
+                "*AI usage: {} in {} call(s)*\n\n",
+                usage.describe(),
+                usage.calls
+            ));
+        }
         md.push_str("*Generated by tg-sync AI Analysis*\n");
 
-        // Write to file
-        fs::write(&path, md)
+        md
+    }
+
+    /// Write the report in every configured format to the reports directory, Markdown first.
+    /// `md` is the rendered Markdown report; `title` and `message_count` go into the JSON.
+    async fn write_reports(
+        &self,
+        result: &AnalysisResult,
+        md: &str,
+        title: Option<&str>,
+        message_count: usize,
+    ) -> Result<Vec<PathBuf>, DomainError> {
+        let mut formats = self.report_formats.clone();
+        formats.sort_by_key(|format| *format != ReportFormat::Markdown);
+        let mut files = Vec::with_capacity(formats.len());
+        for format in formats {
+            let contents = match format {
+                ReportFormat::Markdown => md.to_string(),
+                ReportFormat::Json => serde_json::to_string_pretty(&JsonReport {
+                    result,
+                    chat_title: title,
+                    message_count,
+                })
+                .map_err(|e| DomainError::Repo(format!("Failed to encode JSON report: {}", e)))?,
+                ReportFormat::Html => {
+                    let page_title = format!(
+                        "{} Digest: {}",
+                        result.granularity.adjective(),
+                        result.week_group
+                    );
+                    markdown::to_html_document(&page_title, md)
+                }
+            };
+            let path = self.report_path(result, format);
+            write_file(&path, &contents).await?;
+            files.push(path);
+        }
+        Ok(files)
+    }
+
+    /// Where the report of `result` is written in `format`.
+    fn report_path(&self, result: &AnalysisResult, format: ReportFormat) -> PathBuf {
+        let scope = match result.chat_id {
+            COMBINED_CHAT_ID => "combined".to_string(),
+            chat_id => chat_id.to_string(),
+        };
+        let filename = format!(
+            "analysis_{}_{}_{}.{}",
+            scope,
+            result.granularity.as_str(),
+            result.week_group,
+            format.as_str()
+        );
+        join_sanitized(&self.reports_dir, &filename)
+    }
+}
+
+/// Write one report file.
+async fn write_file(path: &Path, contents: &str) -> Result<(), DomainError> {
+    fs::write(path, contents)
+        .await
+        .map_err(|e| DomainError::Repo(format!("Failed to write report: {}", e)))?;
+    info!(path = %path.display(), "report generated");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::ai::MockAiAdapter;
+    use crate::adapters::persistence::sqlite_repo::SqliteRepo;
+    use crate::domain::AiReply;
+    use crate::testing::fake_tg::text_message;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// Summarizer that fails chunks starting with "bad" at once and echoes the others after
+    /// `delay`, counting the requests it served, the most it had in flight at once and the
+    /// ones that ran to the end. Reports 1M prompt and 100k completion tokens of gpt-4o-mini
+    /// per call ($0.21).
+    struct Scripted {
+        delay: Duration,
+        calls: AtomicUsize,
+        in_flight: AtomicUsize,
+        peak_in_flight: AtomicUsize,
+        finished: AtomicUsize,
+    }
+
+    impl Scripted {
+        fn new(delay: Duration) -> Self {
+            Self {
+                delay,
+                calls: AtomicUsize::new(0),
+                in_flight: AtomicUsize::new(0),
+                peak_in_flight: AtomicUsize::new(0),
+                finished: AtomicUsize::new(0),
+            }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl AiPort for Scripted {
+        async fn analyze(
+            &self,
+            _chat_id: i64,
+            _week_group: &PeriodGroup,
+            _context_csv: &str,
+        ) -> Result<AiReply<AnalysisResult>, DomainError> {
+            unreachable!("map phase only")
+        }
+
+        async fn summarize(&self, context: &str) -> Result<AiReply<String>, DomainError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if context.starts_with("bad") {
+                return Err(DomainError::Ai("API error 400".to_string()));
+            }
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            tokio::time::sleep(self.delay).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            self.finished.fetch_add(1, Ordering::SeqCst);
+            Ok(AiReply {
+                value: format!("sum:{}", context),
+                usage: Some(AiUsage {
+                    model: "gpt-4o-mini".to_string(),
+                    prompt_tokens: 1_000_000,
+                    completion_tokens: 100_000,
+                }),
+            })
+        }
+    }
+
+    /// The mock adapter, keeping every analysis context it was sent.
+    #[derive(Default)]
+    struct Recording {
+        contexts: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait::async_trait]
+    impl AiPort for Recording {
+        async fn analyze(
+            &self,
+            chat_id: i64,
+            week_group: &PeriodGroup,
+            context_csv: &str,
+        ) -> Result<AiReply<AnalysisResult>, DomainError> {
+            self.contexts.lock().unwrap().push(context_csv.to_string());
+            MockAiAdapter::with_delay(0)
+                .analyze(chat_id, week_group, context_csv)
+                .await
+        }
+
+        async fn summarize(&self, context: &str) -> Result<AiReply<String>, DomainError> {
+            MockAiAdapter::with_delay(0).summarize(context).await
+        }
+    }
+
+    async fn test_repo(name: &str) -> SqliteRepo {
+        let base_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("target")
+            .join(name);
+        let _ = std::fs::remove_dir_all(&base_dir);
+        SqliteRepo::connect(&base_dir).await.expect("connect")
+    }
+
+    /// Chunk `i` has `i` data lines, so the mock's "summary of N lines" identifies it.
+    fn chunks(n: usize) -> Vec<String> {
+        (0..n)
+            .map(|i| format!("Date;User;Message{}", "\nrow".repeat(i)))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_map_phase_runs_concurrently_in_chunk_order() {
+        let repo = test_repo("test_map_phase_order_db").await;
+        let ai = Scripted::new(Duration::from_millis(50));
+        let week = PeriodGroup::new("2025-07");
+        let input = chunks(6);
+        let (summaries, usage) = summarize_chunks(
+            &ai,
+            &repo,
+            &PriceTable::default(),
+            1,
+            Granularity::Week,
+            &week,
+            &input,
+            3,
+        )
+        .await
+        .unwrap();
+        assert_eq!(usage.calls, 6);
+        // Never more than the limit in flight, and the limit is actually reached.
+        assert_eq!(ai.peak_in_flight.load(Ordering::SeqCst), 3);
+        let expected: Vec<String> = input.iter().map(|c| format!("sum:{}", c)).collect();
+        assert_eq!(summaries, expected);
+    }
+
+    #[tokio::test]
+    async fn test_map_phase_failure_cancels_in_flight_chunks() {
+        let repo = test_repo("test_map_phase_cancel_db").await;
+        let ai = Scripted::new(Duration::from_secs(5));
+        let week = PeriodGroup::new("2025-07");
+        let mut input = chunks(4);
+        input[0] = "bad chunk".to_string();
+        let err = summarize_chunks(
+            &ai,
+            &repo,
+            &PriceTable::default(),
+            1,
+            Granularity::Week,
+            &week,
+            &input,
+            4,
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, DomainError::Ai(_)));
+        // The other chunks were dropped instead of being waited for.
+        assert_eq!(ai.finished.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_map_phase_resumes_from_stored_summaries() {
+        let repo = test_repo("test_map_phase_resume_db").await;
+        let week = PeriodGroup::new("2025-07");
+        let mut input = vec!["a".to_string(), "b".to_string(), "bad".to_string()];
+
+        // First run: chunks 0 and 1 are summarized and stored before chunk 2 fails.
+        let prices = PriceTable::default();
+        let ai = Scripted::new(Duration::ZERO);
+        assert!(
+            summarize_chunks(&ai, &repo, &prices, 1, Granularity::Week, &week, &input, 1)
+                .await
+                .is_err()
+        );
+        assert_eq!(
+            repo.get_chunk_summaries(1, Granularity::Week, &week)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+
+        // Re-run after the failure is fixed and chunk 1 changed: only chunks 1 and 2 are paid for.
+        input[1] = "b2".to_string();
+        input[2] = "c".to_string();
+        let ai = Scripted::new(Duration::ZERO);
+        let (summaries, usage) =
+            summarize_chunks(&ai, &repo, &prices, 1, Granularity::Week, &week, &input, 1)
+                .await
+                .unwrap();
+        assert_eq!(summaries, vec!["sum:a", "sum:b2", "sum:c"]);
+        assert_eq!(ai.calls.load(Ordering::SeqCst), 2);
+        assert_eq!(usage.calls, 2);
+        assert!((usage.cost_estimate - 0.42).abs() < 1e-9);
+
+        // Every successful call of both runs is in the usage log.
+        let months = repo.get_ai_usage_by_month().await.unwrap();
+        assert_eq!(months.len(), 1);
+        assert_eq!(months[0].1.calls, 4);
+        assert_eq!(months[0].1.prompt_tokens, 4_000_000);
+        assert!((months[0].1.cost_estimate - 0.84).abs() < 1e-9);
+
+        repo.delete_chunk_summaries(1, Granularity::Week, &week)
             .await
-            .map_err(|e| DomainError::Repo(format!("Failed to write report: {}", e)))?;
+            .unwrap();
+        assert!(
+            repo.get_chunk_summaries(1, Granularity::Week, &week)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_combined_items_are_grouped_by_source_chat() {
+        let sources: Vec<CombinedSource> = ["Team", "Family"]
+            .iter()
+            .map(|t| CombinedSource {
+                label: t.to_string(),
+                title: t.to_string(),
+            })
+            .collect();
+        let topics: Vec<String> = [
+            "[family] Trip to Almaty",
+            "No tag at all",
+            "[Team] Release v2",
+            "[Unknown] Something",
+            " [Team]Budget",
+        ]
+        .iter()
+        .map(|t| t.to_string())
+        .collect();
+
+        let groups = group_by_source(&topics, |t| t.as_str(), &sources);
+        let flat: Vec<(Option<usize>, Vec<&str>)> = groups
+            .into_iter()
+            .map(|(source, items)| (source, items.into_iter().map(|(_, t)| t).collect()))
+            .collect();
+        assert_eq!(
+            flat,
+            vec![
+                (Some(0), vec!["Release v2", "Budget"]),
+                (Some(1), vec!["Trip to Almaty"]),
+                (None, vec!["No tag at all", "[Unknown] Something"]),
+            ]
+        );
+    }
 
-        info!(path = %path.display(), "report generated");
+    /// Tracker that records created titles and returns their position as the card id; titles
+    /// starting with "fail" are rejected.
+    #[derive(Default)]
+    struct RecordingTracker {
+        created: Mutex<Vec<String>>,
+    }
 
-        Ok(path)
+    #[async_trait::async_trait]
+    impl TaskTrackerPort for RecordingTracker {
+        async fn create_task(&self, task: &TaskSpec) -> Result<String, DomainError> {
+            if task.title.starts_with("fail") {
+                return Err(DomainError::TaskTracker("400 invalid name".into()));
+            }
+            let mut created = self.created.lock().unwrap();
+            created.push(task.title.clone());
+            Ok(format!("card-{}", created.len()))
+        }
+    }
+
+    /// Service with the mock AI and `tracker`; no notifiers, no Telegram.
+    fn tracker_service(repo: Arc<SqliteRepo>, tracker: Arc<RecordingTracker>) -> AnalysisService {
+        AnalysisService::new(
+            Arc::new(MockAiAdapter::new()),
+            repo.clone(),
+            repo.clone(),
+            repo,
+            PathBuf::from("unused"),
+            Some(tracker),
+            0,
+            None,
+            Vec::new(),
+            1,
+            ChunkBudget::new(12_000, "unknown"),
+            PriceTable::default(),
+            None,
+            false,
+            None,
+        )
+    }
+
+    fn item(description: &str) -> ActionItem {
+        ActionItem {
+            description: description.to_string(),
+            owner: None,
+            deadline: None,
+            priority: None,
+        }
+    }
+
+    fn week_result(action_items: Vec<ActionItem>) -> AnalysisResult {
+        AnalysisResult {
+            week_group: PeriodGroup::new("2025-07"),
+            granularity: Granularity::Week,
+            chat_id: 1,
+            summary: String::new(),
+            key_topics: Vec::new(),
+            action_items,
+            analyzed_at: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_only_selected_action_items_are_pushed() {
+        let repo = Arc::new(test_repo("test_tracker_selection_db").await);
+        let tracker = Arc::new(RecordingTracker::default());
+        let service = tracker_service(repo, tracker.clone());
+        let result = week_result(vec![item("Real task"), item("Hallucinated"), item("Other")]);
+        // Out-of-range indices are ignored.
+        let report = service.push_action_items(&result, &[0, 2, 7]).await;
+        assert_eq!(*tracker.created.lock().unwrap(), vec!["Real task", "Other"]);
+        assert_eq!(report.created, 2);
+    }
+
+    #[tokio::test]
+    async fn test_reanalysis_only_creates_cards_for_new_items() {
+        let repo = Arc::new(test_repo("test_tracker_dedup_db").await);
+        let tracker = Arc::new(RecordingTracker::default());
+        let service = tracker_service(repo, tracker.clone());
+        let mut result = week_result(vec![
+            item("Reply to Alex"),
+            item("Book the venue"),
+            item("fail: too long"),
+        ]);
+        let report = service.send_action_items_to_tracker(&result).await;
+        assert_eq!((report.created, report.skipped), (2, 0));
+        assert_eq!(
+            report.failed,
+            vec![(
+                "fail: too long".to_string(),
+                "Task tracker error: 400 invalid name".to_string()
+            )]
+        );
+
+        // Re-run: same items (one re-worded only in case and spacing) plus a new one; the
+        // failed item is tried again.
+        result.action_items = vec![
+            item("reply  to ALEX"),
+            item("Book the venue"),
+            item("Send the invoice"),
+            item("fail: too long"),
+        ];
+        let report = service.send_action_items_to_tracker(&result).await;
+        assert_eq!(
+            (report.created, report.skipped, report.failed.len()),
+            (1, 2, 1)
+        );
+        // The same item in another granularity's period with the same key is a separate card.
+        result.granularity = Granularity::Month;
+        result.action_items.truncate(1);
+        service.send_action_items_to_tracker(&result).await;
+
+        assert_eq!(
+            *tracker.created.lock().unwrap(),
+            vec![
+                "Reply to Alex",
+                "Book the venue",
+                "Send the invoice",
+                "reply  to ALEX"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_past_analysis_is_reopened_and_reanalyzed() {
+        let dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("target")
+            .join("test_reanalysis_reports");
+        let _ = std::fs::remove_dir_all(&dir);
+        let repo = Arc::new(test_repo("test_reanalysis_db").await);
+        // Monday 2024-01-01
+        let message =
+            |id: i32, text: &str| text_message(1, id, 1704067200 + i64::from(id) * 60, text);
+        repo.save_messages(1, &[message(1, "ship v2 on friday"), message(2, "ok")])
+            .await
+            .unwrap();
+        let service = AnalysisService::new(
+            Arc::new(MockAiAdapter::with_delay(0)),
+            repo.clone(),
+            repo.clone(),
+            repo.clone(),
+            dir.clone(),
+            None,
+            0,
+            None,
+            Vec::new(),
+            1,
+            ChunkBudget::new(12_000, "unknown"),
+            PriceTable::default(),
+            None,
+            false,
+            None,
+        );
+        let reports = service.analyze_chat(1, false).await.unwrap();
+        assert_eq!(reports.len(), 1);
+
+        let periods = service.analyzed_periods(1).await.unwrap();
+        assert_eq!(periods.len(), 1);
+        let period = &periods[0];
+        assert_eq!(period.week_group, reports[0].result.week_group);
+        assert_eq!(period.action_items, reports[0].result.action_items.len());
+        let stored = service.stored_analysis(1, period).await.unwrap().unwrap();
+
+        // A deleted report file is written again from the stored result.
+        std::fs::remove_file(&reports[0].path).unwrap();
+        assert_eq!(service.report_file(&stored).await.unwrap(), reports[0].path);
+        assert!(reports[0].path.exists());
+        // No Telegram client in this service.
+        assert!(service.resend_to_telegram(&stored).await.is_err());
+
+        let again = service.reanalyze_period(1, period).await.unwrap().unwrap();
+        assert_eq!(again.result.week_group, period.week_group);
+        assert_eq!(service.analyzed_periods(1).await.unwrap().len(), 1);
+        assert!(service.pending_weeks(1).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reply_context_quotes_targets_or_their_id() {
+        let repo = Arc::new(test_repo("test_reply_context_db").await);
+        let message = |id: i32, reply_to: Option<i32>, text: &str| Message {
+            from_user_id: Some(7),
+            reply_to_msg_id: reply_to,
+            // Monday 2024-01-01
+            ..text_message(1, id, 1704067200 + i64::from(id) * 60, text)
+        };
+        repo.save_messages(
+            1,
+            &[
+                message(1, None, "when do we ship?"),
+                message(2, Some(1), "friday"),
+                message(3, Some(99), "agreed"),
+            ],
+        )
+        .await
+        .unwrap();
+        let ai = Arc::new(Recording::default());
+        let service = |reply_context: bool| {
+            AnalysisService::new(
+                ai.clone(),
+                repo.clone(),
+                repo.clone(),
+                repo.clone(),
+                PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+                    .join("target")
+                    .join("test_reply_context"),
+                None,
+                0,
+                None,
+                Vec::new(),
+                1,
+                ChunkBudget::new(12_000, "unknown"),
+                PriceTable::default(),
+                None,
+                false,
+                None,
+            )
+            .with_reply_context(reply_context)
+        };
+
+        service(true).analyze_chat(1, true).await.unwrap();
+        let periods = service(false).analyzed_periods(1).await.unwrap();
+        service(false)
+            .reanalyze_period(1, &periods[0])
+            .await
+            .unwrap();
+        let contexts = ai.contexts.lock().unwrap().clone();
+        assert_eq!(contexts.len(), 2);
+        let rows: Vec<&str> = contexts[0].lines().collect();
+        assert_eq!(rows[0], "Date;User;Message;ReplyTo");
+        assert!(rows[1].ends_with(";"), "{}", rows[1]);
+        assert!(rows[2].ends_with(";7: when do we ship?"), "{}", rows[2]);
+        assert!(rows[3].ends_with(";#99"), "{}", rows[3]);
+        assert!(contexts[1].starts_with("Date;User;Message\n"));
+        assert!(!contexts[1].contains("#99"));
+    }
+
+    #[tokio::test]
+    async fn test_reports_are_written_in_every_format() {
+        let dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("target")
+            .join("test_report_formats");
+        let _ = std::fs::remove_dir_all(&dir);
+        let repo = Arc::new(test_repo("test_report_formats_db").await);
+        // Monday 2024-01-01
+        let message =
+            |id: i32, text: &str| text_message(1, id, 1704067200 + i64::from(id) * 60, text);
+        repo.save_messages(1, &[message(1, "ship v2 on friday"), message(2, "ok")])
+            .await
+            .unwrap();
+        let service = AnalysisService::new(
+            Arc::new(MockAiAdapter::with_delay(0)),
+            repo.clone(),
+            repo.clone(),
+            repo.clone(),
+            dir.clone(),
+            None,
+            0,
+            None,
+            Vec::new(),
+            1,
+            ChunkBudget::new(12_000, "unknown"),
+            PriceTable::default(),
+            None,
+            false,
+            None,
+        )
+        .with_report_formats(vec![
+            ReportFormat::Json,
+            ReportFormat::Markdown,
+            ReportFormat::Html,
+        ]);
+        let reports = service.analyze_chat(1, false).await.unwrap();
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+
+        let extensions: Vec<_> = report
+            .files
+            .iter()
+            .map(|path| path.extension().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(extensions, vec!["md", "json", "html"]);
+        assert_eq!(report.path, report.files[0]);
+        assert!(report.files.iter().all(|path| path.exists()));
+
+        let json = std::fs::read_to_string(&report.files[1]).unwrap();
+        let parsed: AnalysisResult = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.week_group, report.result.week_group);
+        assert_eq!(parsed.summary, report.result.summary);
+        assert_eq!(parsed.action_items.len(), report.result.action_items.len());
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["message_count"], 2);
+
+        let html = std::fs::read_to_string(&report.files[2]).unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<h1>"));
     }
 }
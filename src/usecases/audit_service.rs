@@ -0,0 +1,550 @@
+//! Archive integrity audit: cross-checks messages, the media index, files on disk, state
+//! checkpoints, the analysis log and the full-text index.
+//!
+//! Each check is an independent method returning one [`AuditCheck`]. With `fix` enabled,
+//! repairable problems are repaired in place: media is re-queued (index row reset to
+//! `pending`), checkpoints are clamped up to the newest stored message (except where a
+//! bounded sync left a gap below it), and the FTS index is rebuilt. Orphaned analyses are
+//! only reported.
+
+use crate::domain::{DomainError, MediaFile, MediaStatus};
+use crate::ports::{ArchiveAuditPort, MediaIndexPort, StatePort};
+use crate::usecases::media_layout::media_rel_path;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tracing::info;
+
+/// Rows fetched per page when walking the media index.
+const PAGE_SIZE: u32 = 500;
+
+/// Problem examples kept per check for the report.
+const MAX_SAMPLES: usize = 5;
+
+/// What a check covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditCategory {
+    /// Stored messages with media but no media index row.
+    UnindexedMedia,
+    /// `done` index rows whose file is missing or has a different size.
+    MissingMediaFiles,
+    /// State checkpoints below the newest stored message id.
+    StaleCheckpoints,
+    /// Analysis log weeks that contain no stored messages.
+    OrphanAnalyses,
+    /// Full-text index row count differs from `messages`.
+    FtsIndex,
+}
+
+impl AuditCategory {
+    pub fn label(self) -> &'static str {
+        match self {
+            AuditCategory::UnindexedMedia => "media references without index row",
+            AuditCategory::MissingMediaFiles => "downloaded media missing or wrong size",
+            AuditCategory::StaleCheckpoints => "state checkpoints behind stored messages",
+            AuditCategory::OrphanAnalyses => "analyses for weeks without messages",
+            AuditCategory::FtsIndex => "full-text index out of sync",
+        }
+    }
+}
+
+/// Result of one check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditCheck {
+    pub category: AuditCategory,
+    /// Items examined.
+    pub checked: u64,
+    /// Inconsistencies found.
+    pub problems: u64,
+    /// Inconsistencies repaired (only with `fix`).
+    pub fixed: u64,
+    /// Up to [`MAX_SAMPLES`] human-readable examples.
+    pub samples: Vec<String>,
+    /// Why the check did not run, if it was skipped.
+    pub skipped: Option<&'static str>,
+}
+
+impl AuditCheck {
+    fn new(category: AuditCategory) -> Self {
+        Self {
+            category,
+            checked: 0,
+            problems: 0,
+            fixed: 0,
+            samples: Vec::new(),
+            skipped: None,
+        }
+    }
+
+    fn problem(&mut self, sample: impl FnOnce() -> String) {
+        self.problems += 1;
+        if self.samples.len() < MAX_SAMPLES {
+            self.samples.push(sample());
+        }
+    }
+}
+
+/// All checks of one audit run, in execution order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditReport {
+    pub checks: Vec<AuditCheck>,
+}
+
+impl AuditReport {
+    /// Problems found across all checks.
+    pub fn problems(&self) -> u64 {
+        self.checks.iter().map(|c| c.problems).sum()
+    }
+
+    /// Problems still present after fixes.
+    pub fn unresolved(&self) -> u64 {
+        self.checks
+            .iter()
+            .map(|c| c.problems.saturating_sub(c.fixed))
+            .sum()
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.problems() == 0
+    }
+}
+
+impl fmt::Display for AuditReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            let mark = if check.skipped.is_some() {
+                "-"
+            } else if check.problems == 0 {
+                "ok"
+            } else if check.fixed >= check.problems {
+                "fixed"
+            } else {
+                "FAIL"
+            };
+            write!(f, "[{:>5}] {}: ", mark, check.category.label())?;
+            match check.skipped {
+                Some(reason) => writeln!(f, "skipped ({})", reason)?,
+                None => writeln!(
+                    f,
+                    "{} checked, {} problem(s), {} fixed",
+                    check.checked, check.problems, check.fixed
+                )?,
+            }
+            for sample in &check.samples {
+                writeln!(f, "          {}", sample)?;
+            }
+        }
+        write!(
+            f,
+            "{} problem(s), {} unresolved",
+            self.problems(),
+            self.unresolved()
+        )
+    }
+}
+
+/// Runs the archive integrity checks.
+pub struct AuditService {
+    audit: Arc<dyn ArchiveAuditPort>,
+    index: Arc<dyn MediaIndexPort>,
+    state: Arc<dyn StatePort>,
+    media_dir: PathBuf,
+}
+
+impl AuditService {
+    pub fn new(
+        audit: Arc<dyn ArchiveAuditPort>,
+        index: Arc<dyn MediaIndexPort>,
+        state: Arc<dyn StatePort>,
+        media_dir: PathBuf,
+    ) -> Self {
+        Self {
+            audit,
+            index,
+            state,
+            media_dir,
+        }
+    }
+
+    /// Run every check. With `fix`, repairable problems are repaired as they are found.
+    pub async fn run(&self, fix: bool) -> Result<AuditReport, DomainError> {
+        let checks = vec![
+            self.check_unindexed_media(fix).await?,
+            self.check_media_files(fix).await?,
+            self.check_checkpoints(fix).await?,
+            self.check_analyses().await?,
+            self.check_fts(fix).await?,
+        ];
+        let report = AuditReport { checks };
+        info!(
+            problems = report.problems(),
+            unresolved = report.unresolved(),
+            fix,
+            "archive audit finished"
+        );
+        Ok(report)
+    }
+
+    /// Every stored media reference has a media index row (any status).
+    /// Fix: insert a `pending` row so the reference is downloaded again.
+    pub async fn check_unindexed_media(&self, fix: bool) -> Result<AuditCheck, DomainError> {
+        let mut check = AuditCheck::new(AuditCategory::UnindexedMedia);
+        check.checked = self.audit.count_media_references().await?;
+
+        let mut after = None;
+        loop {
+            let page = self.audit.find_unindexed_media(after, PAGE_SIZE).await?;
+            let Some(last) = page.last() else {
+                break;
+            };
+            after = Some((last.chat_id, last.message_id));
+
+            for media_ref in &page {
+                check.problem(|| {
+                    format!(
+                        "chat {} message {} ({})",
+                        media_ref.chat_id,
+                        media_ref.message_id,
+                        media_ref.media_type.as_str()
+                    )
+                });
+                if fix {
+                    let file = MediaFile {
+                        chat_id: media_ref.chat_id,
+                        message_id: media_ref.message_id,
+                        media_type: media_ref.media_type,
+                        rel_path: media_rel_path(media_ref),
+                        size_bytes: None,
+                        expected_size: media_ref.expected_size(),
+                        sha256: None,
+                        status: MediaStatus::Pending,
+                        message_date: None,
+                        remote_key: None,
+                    };
+                    self.index.upsert_media_file(&file).await?;
+                    check.fixed += 1;
+                }
+            }
+            if (page.len() as u32) < PAGE_SIZE {
+                break;
+            }
+        }
+        Ok(check)
+    }
+
+    /// Every `done` index row points to an existing file of the recorded size. A file that
+    /// was uploaded to remote storage may be gone locally.
+    /// Fix: reset the row to `pending` so the file is downloaded again.
+    pub async fn check_media_files(&self, fix: bool) -> Result<AuditCheck, DomainError> {
+        let mut check = AuditCheck::new(AuditCategory::MissingMediaFiles);
+
+        let mut after = None;
+        loop {
+            let page = self
+                .index
+                .list_media_files(None, Some(MediaStatus::Done), after, PAGE_SIZE)
+                .await?;
+            let Some(last) = page.last() else {
+                break;
+            };
+            after = Some((last.chat_id, last.message_id));
+
+            for file in &page {
+                check.checked += 1;
+                let path = self.media_dir.join(&file.rel_path);
+                let problem = match tokio::fs::metadata(&path).await {
+                    Err(_) if file.remote_key.is_some() => None,
+                    Err(_) => Some("missing".to_string()),
+                    Ok(meta) => match file.size_bytes {
+                        Some(expected) if expected != meta.len() => {
+                            Some(format!("size {} (index says {})", meta.len(), expected))
+                        }
+                        _ => None,
+                    },
+                };
+                let Some(problem) = problem else {
+                    continue;
+                };
+                check.problem(|| format!("{}: {}", file.rel_path, problem));
+                if fix {
+                    let requeued = MediaFile {
+                        size_bytes: None,
+                        status: MediaStatus::Pending,
+                        ..file.clone()
+                    };
+                    self.index.upsert_media_file(&requeued).await?;
+                    check.fixed += 1;
+                }
+            }
+            if (page.len() as u32) < PAGE_SIZE {
+                break;
+            }
+        }
+        Ok(check)
+    }
+
+    /// Every chat's state checkpoint is at least its newest stored message id.
+    /// Fix: clamp the checkpoint up to that id. Chats where a date range or topic selection
+    /// left the checkpoint behind on purpose are skipped: clamping would make the next sync
+    /// jump over the messages it left out.
+    pub async fn check_checkpoints(&self, fix: bool) -> Result<AuditCheck, DomainError> {
+        let mut check = AuditCheck::new(AuditCategory::StaleCheckpoints);
+        for (chat_id, max_id) in self.audit.max_message_ids().await? {
+            check.checked += 1;
+            let checkpoint = self.state.get_last_message_id(chat_id).await?;
+            if checkpoint >= max_id || self.state.has_sync_gap(chat_id).await? {
+                continue;
+            }
+            check.problem(|| {
+                format!(
+                    "chat {}: checkpoint {} < stored {}",
+                    chat_id, checkpoint, max_id
+                )
+            });
+            if fix {
+                self.state.set_last_message_id(chat_id, max_id).await?;
+                check.fixed += 1;
+            }
+        }
+        Ok(check)
+    }
+
+    /// Every analysis log entry covers a period that has stored messages. Report only.
+    pub async fn check_analyses(&self) -> Result<AuditCheck, DomainError> {
+        let mut check = AuditCheck::new(AuditCategory::OrphanAnalyses);
+        let (total, orphans) = self.audit.find_orphan_analyses().await?;
+        check.checked = total;
+        for (chat_id, granularity, period) in orphans {
+            check.problem(|| format!("chat {} {} {}", chat_id, granularity.as_str(), period));
+        }
+        Ok(check)
+    }
+
+    /// The full-text index has one row per message. Fix: rebuild it.
+    pub async fn check_fts(&self, fix: bool) -> Result<AuditCheck, DomainError> {
+        let mut check = AuditCheck::new(AuditCategory::FtsIndex);
+        let Some((messages, indexed)) = self.audit.fts_row_counts().await? else {
+            check.skipped = Some("no full-text index");
+            return Ok(check);
+        };
+        check.checked = messages;
+        if messages == indexed {
+            return Ok(check);
+        }
+        check.problems = messages.abs_diff(indexed);
+        check
+            .samples
+            .push(format!("{} messages, {} indexed", messages, indexed));
+        if fix {
+            self.audit.rebuild_fts().await?;
+            check.fixed = check.problems;
+        }
+        Ok(check)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::persistence::sqlite_repo::SqliteRepo;
+    use crate::adapters::persistence::state_json::StateJson;
+    use crate::domain::{
+        AnalysisResult, Granularity, MediaReference, MediaType, Message, WeekGroup,
+    };
+    use crate::ports::{AnalysisLogPort, RepoPort};
+    use crate::testing::fake_tg::text_message;
+    use std::path::Path;
+
+    const JAN_1_2024: i64 = 1704067200;
+
+    fn message(chat_id: i64, id: i32, media: Option<MediaType>) -> Message {
+        Message {
+            media: media.map(|media_type| MediaReference {
+                message_id: id,
+                chat_id,
+                media_type,
+                opaque_ref: "ref".to_string(),
+                size_bytes: None,
+                filename: None,
+                mime_type: None,
+            }),
+            ..text_message(chat_id, id, JAN_1_2024 + id as i64, &format!("m{}", id))
+        }
+    }
+
+    fn done_file(chat_id: i64, message_id: i32, rel_path: &str, size: u64) -> MediaFile {
+        MediaFile {
+            chat_id,
+            message_id,
+            media_type: MediaType::Photo,
+            rel_path: rel_path.to_string(),
+            size_bytes: Some(size),
+            expected_size: None,
+            sha256: None,
+            status: MediaStatus::Done,
+            message_date: None,
+            remote_key: None,
+        }
+    }
+
+    /// Fresh fixture archive: database, state file and media dir under target/<name>.
+    async fn fixture(name: &str) -> (Arc<SqliteRepo>, Arc<StateJson>, AuditService, PathBuf) {
+        let base = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join(name);
+        let _ = std::fs::remove_dir_all(&base);
+        let repo = Arc::new(SqliteRepo::connect(&base).await.unwrap());
+        let state = Arc::new(StateJson::new(base.join("state.json")));
+        let media_dir = base.join("media");
+        std::fs::create_dir_all(&media_dir).unwrap();
+        let service =
+            AuditService::new(repo.clone(), repo.clone(), state.clone(), media_dir.clone());
+        (repo, state, service, media_dir)
+    }
+
+    #[tokio::test]
+    async fn test_unindexed_media_requeued() {
+        let (repo, _, service, _) = fixture("test_audit_unindexed").await;
+        repo.save_messages(
+            1,
+            &[
+                message(1, 1, Some(MediaType::Photo)),
+                message(1, 2, None),
+                message(1, 3, Some(MediaType::Video)),
+            ],
+        )
+        .await
+        .unwrap();
+        repo.upsert_media_file(&done_file(1, 1, "1_1.jpg", 3))
+            .await
+            .unwrap();
+
+        let check = service.check_unindexed_media(false).await.unwrap();
+        assert_eq!((check.checked, check.problems, check.fixed), (2, 1, 0));
+        assert_eq!(check.samples, vec!["chat 1 message 3 (video)".to_string()]);
+
+        let check = service.check_unindexed_media(true).await.unwrap();
+        assert_eq!((check.problems, check.fixed), (1, 1));
+        let pending = repo
+            .list_media_files(Some(1), Some(MediaStatus::Pending), None, 10)
+            .await
+            .unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].rel_path, "1/3.mp4");
+
+        let check = service.check_unindexed_media(false).await.unwrap();
+        assert_eq!(check.problems, 0);
+    }
+
+    #[tokio::test]
+    async fn test_missing_and_truncated_media_files() {
+        let (repo, _, service, media_dir) = fixture("test_audit_media_files").await;
+        std::fs::write(media_dir.join("1_1.jpg"), b"abc").unwrap();
+        std::fs::write(media_dir.join("1_2.jpg"), b"ab").unwrap();
+        for file in [
+            done_file(1, 1, "1_1.jpg", 3),
+            done_file(1, 2, "1_2.jpg", 3),
+            done_file(1, 3, "1_3.jpg", 3),
+            // Uploaded to remote storage and deleted locally.
+            MediaFile {
+                remote_key: Some("media/1_4.jpg".to_string()),
+                ..done_file(1, 4, "1_4.jpg", 3)
+            },
+        ] {
+            repo.upsert_media_file(&file).await.unwrap();
+        }
+
+        let check = service.check_media_files(false).await.unwrap();
+        assert_eq!((check.checked, check.problems), (4, 2));
+        assert_eq!(
+            check.samples,
+            vec![
+                "1_2.jpg: size 2 (index says 3)".to_string(),
+                "1_3.jpg: missing".to_string()
+            ]
+        );
+
+        let check = service.check_media_files(true).await.unwrap();
+        assert_eq!(check.fixed, 2);
+        let done = repo
+            .list_media_files(None, Some(MediaStatus::Done), None, 10)
+            .await
+            .unwrap();
+        assert_eq!(done.len(), 2);
+        assert_eq!((done[0].message_id, done[1].message_id), (1, 4));
+    }
+
+    #[tokio::test]
+    async fn test_stale_checkpoints_clamped() {
+        let (repo, state, service, _) = fixture("test_audit_checkpoints").await;
+        repo.save_messages(1, &[message(1, 5, None), message(1, 9, None)])
+            .await
+            .unwrap();
+        repo.save_messages(2, &[message(2, 4, None)]).await.unwrap();
+        state.set_last_message_id(1, 7).await.unwrap();
+        state.set_last_message_id(2, 4).await.unwrap();
+
+        let check = service.check_checkpoints(false).await.unwrap();
+        assert_eq!((check.checked, check.problems), (2, 1));
+        assert_eq!(check.samples, vec!["chat 1: checkpoint 7 < stored 9"]);
+
+        let check = service.check_checkpoints(true).await.unwrap();
+        assert_eq!(check.fixed, 1);
+        assert_eq!(state.get_last_message_id(1).await.unwrap(), 9);
+        assert_eq!(service.check_checkpoints(false).await.unwrap().problems, 0);
+    }
+
+    #[tokio::test]
+    async fn test_orphan_analyses_reported() {
+        let (repo, _, service, _) = fixture("test_audit_analyses").await;
+        repo.save_messages(1, &[message(1, 1, None)]).await.unwrap();
+        // Jan 1 2024 is a Monday: week "2024-01".
+        for week in ["2024-01", "2019-10"] {
+            let result = AnalysisResult {
+                week_group: WeekGroup::new(week),
+                granularity: Granularity::Week,
+                chat_id: 1,
+                summary: String::new(),
+                key_topics: Vec::new(),
+                action_items: Vec::new(),
+                analyzed_at: 0,
+            };
+            repo.save_analysis(&result).await.unwrap();
+        }
+
+        let check = service.check_analyses().await.unwrap();
+        assert_eq!((check.checked, check.problems), (2, 1));
+        assert_eq!(check.samples, vec!["chat 1 week 2019-10"]);
+    }
+
+    #[tokio::test]
+    async fn test_fts_drift_detected_and_rebuilt() {
+        let (repo, _, service, _) = fixture("test_audit_fts").await;
+        let report = service.run(false).await.unwrap();
+        assert!(report.is_clean());
+        assert_eq!(report.checks.len(), 5);
+
+        repo.save_messages(1, &[message(1, 1, None), message(1, 2, None)])
+            .await
+            .unwrap();
+        let check = service.check_fts(false).await.unwrap();
+        assert_eq!((check.skipped, check.checked, check.problems), (None, 2, 0));
+
+        // Emptied index (e.g. a crash mid-rebuild): every message is missing from it.
+        repo.connection()
+            .unwrap()
+            .execute(
+                "INSERT INTO messages_fts (messages_fts) VALUES ('delete-all')",
+                (),
+            )
+            .await
+            .unwrap();
+        let check = service.check_fts(true).await.unwrap();
+        assert_eq!((check.problems, check.fixed), (2, 2));
+        assert_eq!(service.check_fts(false).await.unwrap().problems, 0);
+        assert_eq!(
+            repo.search_messages("m2", None, 10, 0).await.unwrap().len(),
+            1
+        );
+    }
+}
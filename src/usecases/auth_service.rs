@@ -1,67 +1,308 @@
-//! Handle login / 2FA flow. Delegates to AuthPort; collects user input (phone, code, 2FA) here.
+//! Handle login / 2FA flow. Delegates to AuthPort; asks for input through AuthPromptPort.
 //!
 //! Keeps authentication workflow in the use-case layer; main.rs only bootstraps and calls run_auth_flow.
+//! Where the input comes from (terminal prompts, environment and a code file) is the prompt
+//! adapter's business. With a bot token the session signs in as that bot and nothing is asked.
 
-use crate::domain::{DomainError, SignInResult};
-use crate::ports::AuthPort;
+use crate::domain::{DomainError, LoginMethod, QrLoginStatus, SignInResult};
+use crate::ports::{AuthPort, AuthPromptPort};
+use crate::shared::qr;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
 use tracing::{info, warn};
 
+/// How long to wait for the QR code to be scanned.
+const QR_TIMEOUT: Duration = Duration::from_secs(3 * 60);
+
+/// Delay between QR login status checks.
+const QR_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
 pub struct AuthService {
     auth_port: Arc<dyn AuthPort>,
+    prompt: Arc<dyn AuthPromptPort>,
+    api_id: i32,
     api_hash: String,
+    /// Sign in as this bot instead of a user account (TG_SYNC_BOT_TOKEN).
+    bot_token: Option<String>,
+    qr_timeout: Duration,
+    qr_poll_interval: Duration,
 }
 
 impl AuthService {
-    pub fn new(auth_port: Arc<dyn AuthPort>, api_hash: String) -> Self {
+    pub fn new(
+        auth_port: Arc<dyn AuthPort>,
+        prompt: Arc<dyn AuthPromptPort>,
+        api_id: i32,
+        api_hash: String,
+    ) -> Self {
         Self {
             auth_port,
+            prompt,
+            api_id,
             api_hash,
+            bot_token: None,
+            qr_timeout: QR_TIMEOUT,
+            qr_poll_interval: QR_POLL_INTERVAL,
         }
     }
 
+    /// Log in as the bot with `token` when the session is not authorized yet.
+    pub fn with_bot_token(mut self, token: String) -> Self {
+        self.bot_token = Some(token);
+        self
+    }
+
     /// Check if we are already authenticated (delegates to auth port).
     pub async fn is_authenticated(&self) -> Result<bool, DomainError> {
         self.auth_port.is_authenticated().await
     }
 
-    /// Run full auth flow: check auth → if not, prompt phone → request code → prompt code →
-    /// sign in → if 2FA required, prompt password and check_password.
+    /// Run full auth flow: check auth → if not, sign in the bot, or ask for the login method →
+    /// phone + code or QR code → if 2FA required, ask for password and check_password.
     pub async fn run_auth_flow(&self) -> Result<(), DomainError> {
         if self.auth_port.is_authenticated().await? {
             info!("Already authorized");
             return Ok(());
         }
+        if let Some(token) = &self.bot_token {
+            warn!("Not authorized. Signing in with the bot token.");
+            self.auth_port
+                .sign_in_bot(token.trim(), &self.api_hash)
+                .await?;
+            info!("Signed in as a bot");
+            return Ok(());
+        }
 
-        warn!("Not authorized. Running login flow (phone + code from Telegram app/SMS).");
-
-        let phone = inquire::Text::new("Phone number (e.g. +1234567890):")
-            .prompt()
-            .map_err(|e| DomainError::Auth(format!("input: {}", e)))?;
-
-        self.auth_port
-            .request_login_code(phone.trim(), &self.api_hash)
-            .await?;
-
-        let code = inquire::Text::new("Login code from Telegram:")
-            .prompt()
-            .map_err(|e| DomainError::Auth(format!("input: {}", e)))?;
-
-        match self.auth_port.sign_in(code.trim()).await? {
+        let result = match self.prompt.login_method().await? {
+            LoginMethod::PhoneCode => {
+                warn!("Not authorized. Running login flow (phone + code from Telegram app/SMS).");
+                self.phone_login().await?
+            }
+            LoginMethod::Qr => {
+                warn!("Not authorized. Running login flow (QR code).");
+                self.qr_login().await?
+            }
+        };
+        match result {
             SignInResult::Success => {
                 info!("Signed in successfully");
                 Ok(())
             }
             SignInResult::PasswordRequired { hint } => {
-                let hint_str = hint.as_deref().unwrap_or("(no hint)");
-                let prompt = format!("2FA password (hint: {}):", hint_str);
-                let password = inquire::Password::new(&prompt)
-                    .prompt()
-                    .map_err(|e| DomainError::Auth(format!("input: {}", e)))?;
+                let password = self.prompt.password(hint.as_deref()).await?;
                 self.auth_port.check_password(password.as_bytes()).await?;
                 info!("Signed in (2FA completed)");
                 Ok(())
             }
         }
     }
+
+    /// Ask for phone → request code → ask for code → sign in.
+    async fn phone_login(&self) -> Result<SignInResult, DomainError> {
+        let phone = self.prompt.phone().await?;
+        self.auth_port
+            .request_login_code(phone.trim(), &self.api_hash)
+            .await?;
+
+        let code = self.prompt.login_code().await?;
+        self.auth_port.sign_in(code.trim()).await
+    }
+
+    /// Show a QR token and poll until it is scanned; expired tokens are replaced (and shown
+    /// again) until `qr_timeout` runs out.
+    async fn qr_login(&self) -> Result<SignInResult, DomainError> {
+        let deadline = Instant::now() + self.qr_timeout;
+        let mut token = self
+            .auth_port
+            .request_qr_login(self.api_id, &self.api_hash)
+            .await?;
+        self.prompt
+            .show_qr(&token, &qr::render_terminal(&token.url)?)
+            .await?;
+        loop {
+            tokio::time::sleep(self.qr_poll_interval).await;
+            if let QrLoginStatus::Accepted(result) = self.auth_port.poll_qr_login().await? {
+                return Ok(result);
+            }
+            if Instant::now() >= deadline {
+                return Err(DomainError::Auth(format!(
+                    "QR code was not scanned within {:?}",
+                    self.qr_timeout
+                )));
+            }
+            if chrono::Utc::now().timestamp() >= token.expires_at {
+                token = self
+                    .auth_port
+                    .request_qr_login(self.api_id, &self.api_hash)
+                    .await?;
+                info!("QR code expired; showing a new one");
+                self.prompt
+                    .show_qr(&token, &qr::render_terminal(&token.url)?)
+                    .await?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::QrToken;
+    use std::sync::Mutex;
+
+    /// Auth port that requires 2FA and records every call. QR polls answer from `qr_polls`
+    /// (front first); QR tokens are already expired, so each poll round refreshes one.
+    #[derive(Default)]
+    struct FakeAuth {
+        calls: Mutex<Vec<String>>,
+        qr_polls: Mutex<Vec<QrLoginStatus>>,
+    }
+
+    #[async_trait::async_trait]
+    impl AuthPort for FakeAuth {
+        async fn is_authenticated(&self) -> Result<bool, DomainError> {
+            Ok(false)
+        }
+        async fn request_login_code(&self, phone: &str, api_hash: &str) -> Result<(), DomainError> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("code {} {}", phone, api_hash));
+            Ok(())
+        }
+        async fn sign_in(&self, code: &str) -> Result<SignInResult, DomainError> {
+            self.calls.lock().unwrap().push(format!("sign_in {}", code));
+            Ok(SignInResult::PasswordRequired {
+                hint: Some("pet".into()),
+            })
+        }
+        async fn check_password(&self, password: &[u8]) -> Result<(), DomainError> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("password {}", String::from_utf8_lossy(password)));
+            Ok(())
+        }
+        async fn request_qr_login(
+            &self,
+            api_id: i32,
+            api_hash: &str,
+        ) -> Result<QrToken, DomainError> {
+            let mut calls = self.calls.lock().unwrap();
+            calls.push(format!("qr {} {}", api_id, api_hash));
+            Ok(QrToken {
+                url: format!("tg://login?token=t{}", calls.len()),
+                expires_at: 0,
+            })
+        }
+        async fn sign_in_bot(&self, token: &str, api_hash: &str) -> Result<(), DomainError> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("bot {} {}", token, api_hash));
+            Ok(())
+        }
+        async fn poll_qr_login(&self) -> Result<QrLoginStatus, DomainError> {
+            self.calls.lock().unwrap().push("poll".into());
+            let mut polls = self.qr_polls.lock().unwrap();
+            Ok(if polls.is_empty() {
+                QrLoginStatus::Pending
+            } else {
+                polls.remove(0)
+            })
+        }
+    }
+
+    struct FixedPrompt {
+        method: LoginMethod,
+        shown: Mutex<Vec<String>>,
+    }
+
+    impl FixedPrompt {
+        fn new(method: LoginMethod) -> Self {
+            Self {
+                method,
+                shown: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl AuthPromptPort for FixedPrompt {
+        async fn login_method(&self) -> Result<LoginMethod, DomainError> {
+            Ok(self.method)
+        }
+        async fn show_qr(&self, token: &QrToken, qr: &str) -> Result<(), DomainError> {
+            assert!(!qr.is_empty());
+            self.shown.lock().unwrap().push(token.url.clone());
+            Ok(())
+        }
+        async fn phone(&self) -> Result<String, DomainError> {
+            Ok(" +100 ".into())
+        }
+        async fn login_code(&self) -> Result<String, DomainError> {
+            Ok("12345\n".into())
+        }
+        async fn password(&self, hint: Option<&str>) -> Result<String, DomainError> {
+            Ok(format!("secret-{}", hint.unwrap_or_default()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_flow_uses_prompt_port() {
+        let auth = Arc::new(FakeAuth::default());
+        let prompt = Arc::new(FixedPrompt::new(LoginMethod::PhoneCode));
+        let service = AuthService::new(auth.clone(), prompt, 1, "hash".into());
+        service.run_auth_flow().await.unwrap();
+        assert_eq!(
+            *auth.calls.lock().unwrap(),
+            vec!["code +100 hash", "sign_in 12345", "password secret-pet"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bot_token_signs_in_without_prompts() {
+        let auth = Arc::new(FakeAuth::default());
+        let prompt = Arc::new(FixedPrompt::new(LoginMethod::Qr));
+        let service = AuthService::new(auth.clone(), prompt.clone(), 1, "hash".into())
+            .with_bot_token(" 123:abc ".into());
+        service.run_auth_flow().await.unwrap();
+        assert_eq!(*auth.calls.lock().unwrap(), vec!["bot 123:abc hash"]);
+        assert!(prompt.shown.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_qr_flow_refreshes_token_and_completes_2fa() {
+        let auth = Arc::new(FakeAuth::default());
+        auth.qr_polls.lock().unwrap().extend([
+            QrLoginStatus::Pending,
+            QrLoginStatus::Accepted(SignInResult::PasswordRequired { hint: None }),
+        ]);
+        let prompt = Arc::new(FixedPrompt::new(LoginMethod::Qr));
+        let mut service = AuthService::new(auth.clone(), prompt.clone(), 7, "hash".into());
+        service.qr_poll_interval = Duration::ZERO;
+        service.run_auth_flow().await.unwrap();
+        assert_eq!(
+            *auth.calls.lock().unwrap(),
+            vec!["qr 7 hash", "poll", "qr 7 hash", "poll", "password secret-"]
+        );
+        assert_eq!(
+            *prompt.shown.lock().unwrap(),
+            vec!["tg://login?token=t1", "tg://login?token=t3"]
+        );
+
+        // Never scanned: gives up at the deadline.
+        let auth = Arc::new(FakeAuth::default());
+        let mut service = AuthService::new(
+            auth.clone(),
+            Arc::new(FixedPrompt::new(LoginMethod::Qr)),
+            7,
+            "hash".into(),
+        );
+        service.qr_poll_interval = Duration::ZERO;
+        service.qr_timeout = Duration::ZERO;
+        assert!(service.run_auth_flow().await.is_err());
+        assert_eq!(*auth.calls.lock().unwrap(), vec!["qr 7 hash", "poll"]);
+    }
 }
@@ -0,0 +1,306 @@
+//! Snapshots of the archive database into `data/backups/`.
+//!
+//! A snapshot is a consistent copy of messages.db taken while syncs keep writing
+//! ([`ArchiveAuditPort::snapshot`], VACUUM INTO), verified with an integrity check and
+//! optionally gzipped. Snapshots are named `messages_YYYYMMDD_HHMMSS.db[.gz]` (UTC); after
+//! each one only the newest `keep` are left. Taken on demand (TUI) or every
+//! TG_SYNC_DB_BACKUP_EVERY_HOURS by [`BackupService::run_every`]. With remote storage
+//! (TG_SYNC_DB_BACKUP_UPLOAD) each snapshot is also uploaded as `backups/{name}`; retention
+//! applies to the local copies only. Restoring is manual: stop tg-sync, gunzip the snapshot
+//! and put it in place of messages.db.
+
+use crate::domain::DomainError;
+use crate::ports::{ArchiveAuditPort, RemoteStoragePort};
+use crate::usecases::upload_worker::{BASE_BACKOFF, upload_with_retry};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{error, info, warn};
+
+/// File name prefix of snapshots; files without it are never pruned.
+const SNAPSHOT_PREFIX: &str = "messages_";
+
+/// Result of one snapshot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupReport {
+    pub path: PathBuf,
+    /// Size of the snapshot file (compressed when gzipped), in bytes.
+    pub size_bytes: u64,
+    pub duration: Duration,
+    /// Older snapshots deleted by retention.
+    pub pruned: Vec<PathBuf>,
+    /// Object key of the uploaded copy, when uploading is enabled.
+    pub remote_key: Option<String>,
+}
+
+impl fmt::Display for BackupReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Snapshot {}: {} bytes in {:.1}s, integrity ok",
+            self.path.display(),
+            self.size_bytes,
+            self.duration.as_secs_f64()
+        )?;
+        if let Some(key) = &self.remote_key {
+            write!(f, "\nUploaded as {}", key)?;
+        }
+        if !self.pruned.is_empty() {
+            write!(f, "\n{} older snapshot(s) deleted", self.pruned.len())?;
+        }
+        Ok(())
+    }
+}
+
+/// Takes and prunes database snapshots.
+pub struct BackupService {
+    audit: Arc<dyn ArchiveAuditPort>,
+    backup_dir: PathBuf,
+    gzip: bool,
+    keep: usize,
+    /// Where snapshots are uploaded, if anywhere.
+    remote: Option<Arc<dyn RemoteStoragePort>>,
+}
+
+impl BackupService {
+    pub fn new(
+        audit: Arc<dyn ArchiveAuditPort>,
+        backup_dir: PathBuf,
+        gzip: bool,
+        keep: usize,
+        remote: Option<Arc<dyn RemoteStoragePort>>,
+    ) -> Self {
+        Self {
+            audit,
+            backup_dir,
+            gzip,
+            keep: keep.max(1),
+            remote,
+        }
+    }
+
+    /// Take a snapshot now, upload it when configured, then prune old ones. A failed upload
+    /// fails the backup but keeps the local snapshot.
+    pub async fn backup_now(&self) -> Result<BackupReport, DomainError> {
+        let started = Instant::now();
+        tokio::fs::create_dir_all(&self.backup_dir)
+            .await
+            .map_err(|e| {
+                DomainError::Repo(format!("create {}: {}", self.backup_dir.display(), e))
+            })?;
+        let db_path = self.next_path();
+        let mut size_bytes = self.audit.snapshot(&db_path).await?;
+        let path = if self.gzip {
+            let gz_path = PathBuf::from(format!("{}.gz", db_path.display()));
+            size_bytes = gzip_file(&db_path, &gz_path).await?;
+            gz_path
+        } else {
+            db_path
+        };
+        let remote_key = match &self.remote {
+            Some(remote) => {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                let key = format!("backups/{}", name);
+                upload_with_retry(&**remote, &key, &path, BASE_BACKOFF)
+                    .await
+                    .map_err(|e| {
+                        DomainError::Storage(format!(
+                            "upload of {} failed (snapshot kept locally): {}",
+                            path.display(),
+                            e
+                        ))
+                    })?;
+                Some(key)
+            }
+            None => None,
+        };
+        let pruned = self.prune().await?;
+        let report = BackupReport {
+            path,
+            size_bytes,
+            duration: started.elapsed(),
+            pruned,
+            remote_key,
+        };
+        info!(
+            path = %report.path.display(),
+            size_bytes = report.size_bytes,
+            elapsed_ms = report.duration.as_millis() as u64,
+            pruned = report.pruned.len(),
+            "database snapshot written"
+        );
+        Ok(report)
+    }
+
+    /// Take a snapshot whenever the newest one is `every` old, forever. Failures are logged
+    /// and retried at the next interval.
+    pub async fn run_every(&self, every: Duration) {
+        loop {
+            let age = match self.snapshots().await {
+                Ok(snapshots) => match snapshots.last() {
+                    Some(newest) => modified_age(newest).await,
+                    None => every,
+                },
+                Err(_) => every,
+            };
+            tokio::time::sleep(every.saturating_sub(age)).await;
+            if let Err(e) = self.backup_now().await {
+                error!(error = %e, "scheduled database snapshot failed");
+                tokio::time::sleep(every).await;
+            }
+        }
+    }
+
+    /// Unused snapshot path for the current time (a numeric suffix within the same second).
+    fn next_path(&self) -> PathBuf {
+        let stamp = chrono::Utc::now().format("%Y%m%d_%H%M%S").to_string();
+        let mut n = 1;
+        loop {
+            let name = match n {
+                1 => format!("{}{}.db", SNAPSHOT_PREFIX, stamp),
+                _ => format!("{}{}_{}.db", SNAPSHOT_PREFIX, stamp, n),
+            };
+            let path = self.backup_dir.join(name);
+            let gz = PathBuf::from(format!("{}.gz", path.display()));
+            if !path.exists() && !gz.exists() {
+                return path;
+            }
+            n += 1;
+        }
+    }
+
+    /// Snapshot files, oldest first (the names sort by time).
+    async fn snapshots(&self) -> Result<Vec<PathBuf>, DomainError> {
+        let mut entries = match tokio::fs::read_dir(&self.backup_dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(DomainError::Repo(format!(
+                    "read {}: {}",
+                    self.backup_dir.display(),
+                    e
+                )));
+            }
+        };
+        let mut paths = Vec::new();
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|e| DomainError::Repo(e.to_string()))?
+        {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with(SNAPSHOT_PREFIX)
+                && (name.ends_with(".db") || name.ends_with(".db.gz"))
+            {
+                paths.push(entry.path());
+            }
+        }
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// Delete all but the newest `keep` snapshots. Returns the deleted paths.
+    async fn prune(&self) -> Result<Vec<PathBuf>, DomainError> {
+        let snapshots = self.snapshots().await?;
+        let excess = snapshots.len().saturating_sub(self.keep);
+        let mut pruned = Vec::new();
+        for path in snapshots.into_iter().take(excess) {
+            match tokio::fs::remove_file(&path).await {
+                Ok(()) => pruned.push(path),
+                Err(e) => {
+                    warn!(path = %path.display(), error = %e, "failed to delete old snapshot")
+                }
+            }
+        }
+        Ok(pruned)
+    }
+}
+
+/// Time since `path` was last modified; zero when unknown.
+async fn modified_age(path: &Path) -> Duration {
+    tokio::fs::metadata(path)
+        .await
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| SystemTime::now().duration_since(t).ok())
+        .unwrap_or_default()
+}
+
+/// Compress `src` into `dest` and remove `src`. Returns the compressed size.
+async fn gzip_file(src: &Path, dest: &Path) -> Result<u64, DomainError> {
+    let (src, dest) = (src.to_path_buf(), dest.to_path_buf());
+    tokio::task::spawn_blocking(move || -> std::io::Result<u64> {
+        let mut input = std::fs::File::open(&src)?;
+        let output = std::fs::File::create(&dest)?;
+        let mut encoder = GzEncoder::new(output, Compression::default());
+        std::io::copy(&mut input, &mut encoder)?;
+        let output = encoder.finish()?;
+        output.sync_all()?;
+        std::fs::remove_file(&src)?;
+        Ok(output.metadata()?.len())
+    })
+    .await
+    .map_err(|e| DomainError::Repo(e.to_string()))?
+    .map_err(|e| DomainError::Repo(format!("gzip snapshot: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::persistence::sqlite_repo::SqliteRepo;
+    use crate::domain::Message;
+    use crate::ports::RepoPort;
+    use crate::testing::fake_tg::text_message;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    fn message(id: i32) -> Message {
+        text_message(1, id, 1704067200 + id as i64, &format!("m{}", id))
+    }
+
+    #[tokio::test]
+    async fn test_snapshots_are_readable_gzipped_and_pruned() {
+        let base = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join("test_backup");
+        let _ = std::fs::remove_dir_all(&base);
+        let repo = Arc::new(SqliteRepo::connect(&base).await.unwrap());
+        repo.save_messages(1, &[message(1), message(2)])
+            .await
+            .unwrap();
+        let backups = base.join("backups");
+        std::fs::create_dir_all(&backups).unwrap();
+        std::fs::write(backups.join("notes.txt"), b"keep me").unwrap();
+
+        let plain = BackupService::new(repo.clone(), backups.clone(), false, 2, None);
+        let first = plain.backup_now().await.unwrap();
+        assert!(first.size_bytes > 0);
+        assert!(first.to_string().contains("integrity ok"));
+        // The snapshot is a complete database of its own.
+        let restore = base.join("restore");
+        std::fs::create_dir_all(&restore).unwrap();
+        std::fs::copy(&first.path, restore.join("messages.db")).unwrap();
+        let copy = SqliteRepo::connect(&restore).await.unwrap();
+        assert_eq!(copy.get_messages(1, 10, 0).await.unwrap().len(), 2);
+
+        let gzip = BackupService::new(repo.clone(), backups.clone(), true, 2, None);
+        let second = gzip.backup_now().await.unwrap();
+        assert!(second.path.to_string_lossy().ends_with(".db.gz"));
+        let mut header = Vec::new();
+        GzDecoder::new(std::fs::File::open(&second.path).unwrap())
+            .take(16)
+            .read_to_end(&mut header)
+            .unwrap();
+        assert_eq!(&header, b"SQLite format 3\0");
+        assert!(second.pruned.is_empty());
+
+        let third = gzip.backup_now().await.unwrap();
+        assert_eq!(third.pruned, vec![first.path.clone()]);
+        assert!(!first.path.exists());
+        assert!(second.path.exists() && third.path.exists());
+        assert!(backups.join("notes.txt").exists());
+    }
+}
@@ -0,0 +1,1453 @@
+//! Export use case: flat files for spreadsheets and external tools, and rendered chats.
+//!
+//! - Messages: one row per message of a chat, oldest first. Streamed from the repository in
+//!   keyset batches and flushed after each batch, so memory stays bounded for any chat size.
+//! - Analysis: one row per action item of every saved weekly analysis (weeks without action
+//!   items get a single row with empty action columns).
+//! - Chat: a readable document rendered by an [`ExporterPort`] (e.g. HTML). Streamed the same
+//!   way; each batch gets sender names, reply quotes and downloaded media paths resolved.
+//!   Service events (joins, leaves, title changes, ...) are written between the messages
+//!   around them.
+//!
+//! Files are written to a temp path and renamed into place. With `anonymize`, users become
+//! pseudonyms, contact data in text is redacted, and the pseudonym mapping is written next
+//! to the export as `<file>.mapping.json`.
+
+use crate::domain::{
+    AnalysisResult, COMBINED_CHAT_ID, Chat, ChatEvent, ChatType, DomainError, ExportChat,
+    ExportEvent, ExportFormat, ExportMessage, MediaStatus, Message, ReplyQuote, TimeRange,
+};
+use crate::ports::{
+    AnalysisLogPort, ChatExportWriter, EntityRegistry, ExporterPort, MediaIndexPort, RepoPort,
+};
+use crate::shared::anonymize::Anonymizer;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::info;
+
+/// Messages fetched from the repository per batch.
+const BATCH_SIZE: u32 = 1000;
+
+/// Column order of the message export.
+const MESSAGE_HEADER: [&str; 9] = [
+    "id",
+    "date",
+    "sender_id",
+    "sender_name",
+    "text",
+    "media_type",
+    "reply_to",
+    "is_outgoing",
+    "topic",
+];
+
+/// Column order of the analysis export.
+const ANALYSIS_HEADER: [&str; 10] = [
+    "chat_id",
+    "chat_title",
+    "week",
+    "summary",
+    "topics",
+    "action_item",
+    "owner",
+    "deadline",
+    "priority",
+    "status",
+];
+
+/// Status written for action items. Items are not tracked after the analysis yet.
+const ACTION_STATUS_OPEN: &str = "open";
+
+/// Per-export options.
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    /// Output file. Defaults to a name under the export directory.
+    pub out: Option<PathBuf>,
+    /// Own user id; fills `is_outgoing` in message exports.
+    pub me_id: Option<i64>,
+    /// Pseudonymize users and redact contact data; writes the mapping alongside.
+    pub anonymize: bool,
+    /// Only messages dated within this range (chat exports). None exports everything.
+    pub range: Option<TimeRange>,
+}
+
+/// Where an export was written and how many data rows it contains.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportReport {
+    pub path: PathBuf,
+    pub rows: u64,
+    /// Pseudonym mapping written next to an anonymized export.
+    pub mapping_path: Option<PathBuf>,
+}
+
+/// Writes message and analysis exports.
+pub struct ExportService {
+    repo: Arc<dyn RepoPort>,
+    registry: Arc<dyn EntityRegistry>,
+    analysis_log: Arc<dyn AnalysisLogPort>,
+    /// Locates downloaded media for chat exports.
+    media_index: Arc<dyn MediaIndexPort>,
+    /// Directory the media index paths are relative to (data/media).
+    media_dir: PathBuf,
+    /// Default output directory (data/exports).
+    export_dir: PathBuf,
+    /// Persistent pseudonym mapping (TG_SYNC_ANONYMIZE_MAP); fresh per export when None.
+    anonymize_map: Option<PathBuf>,
+    batch_size: u32,
+}
+
+impl ExportService {
+    pub fn new(
+        repo: Arc<dyn RepoPort>,
+        registry: Arc<dyn EntityRegistry>,
+        analysis_log: Arc<dyn AnalysisLogPort>,
+        media_index: Arc<dyn MediaIndexPort>,
+        media_dir: PathBuf,
+        export_dir: PathBuf,
+        anonymize_map: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            repo,
+            registry,
+            analysis_log,
+            media_index,
+            media_dir,
+            export_dir,
+            anonymize_map,
+            batch_size: BATCH_SIZE,
+        }
+    }
+
+    /// Default path of a chat's message export, e.g. `exports/messages_-100123.csv`.
+    pub fn messages_path(&self, chat_id: i64, format: ExportFormat) -> PathBuf {
+        self.export_dir
+            .join(format!("messages_{}.{}", chat_id, format.as_str()))
+    }
+
+    /// Default path of a rendered chat export, e.g. `exports/chat_-100123.html`.
+    pub fn chat_path(&self, chat_id: i64, extension: &str) -> PathBuf {
+        self.export_dir
+            .join(format!("chat_{}.{}", chat_id, extension))
+    }
+
+    /// Default path of the analysis export (`analysis.csv` or `analysis_<chat>.csv`).
+    pub fn analysis_path(&self, chat_id: Option<i64>, format: ExportFormat) -> PathBuf {
+        let name = match chat_id {
+            Some(id) => format!("analysis_{}.{}", id, format.as_str()),
+            None => format!("analysis.{}", format.as_str()),
+        };
+        self.export_dir.join(name)
+    }
+
+    /// Export all stored messages of `chat_id`, oldest first. `is_outgoing` is left empty
+    /// when `opts.me_id` is unknown (offline export).
+    pub async fn export_messages(
+        &self,
+        chat_id: i64,
+        format: ExportFormat,
+        opts: &ExportOptions,
+    ) -> Result<ExportReport, DomainError> {
+        let path = opts
+            .out
+            .clone()
+            .unwrap_or_else(|| self.messages_path(chat_id, format));
+        let mut anonymizer = self.anonymizer(opts)?;
+        let (tmp_path, file) = create_temp(&path).await?;
+        let written = match format {
+            ExportFormat::Csv => {
+                let mut wtr = csv::Writer::from_writer(file);
+                self.write_messages_csv(chat_id, opts.me_id, anonymizer.as_mut(), &mut wtr)
+                    .await
+            }
+        };
+        let rows = commit_temp(&tmp_path, &path, written).await?;
+        let mapping_path = save_mapping(anonymizer.as_ref(), &path)?;
+        info!(chat_id, path = %path.display(), rows, "messages exported");
+        Ok(ExportReport {
+            path,
+            rows,
+            mapping_path,
+        })
+    }
+
+    /// Export saved analyses (all chats when `chat_id` is None), one row per action item.
+    pub async fn export_analysis(
+        &self,
+        chat_id: Option<i64>,
+        format: ExportFormat,
+        opts: &ExportOptions,
+    ) -> Result<ExportReport, DomainError> {
+        let path = opts
+            .out
+            .clone()
+            .unwrap_or_else(|| self.analysis_path(chat_id, format));
+        let mut analyses = self.analysis_log.list_analyses(chat_id).await?;
+        let mut titles: HashMap<i64, String> = self
+            .repo
+            .get_known_chats()
+            .await?
+            .into_iter()
+            .map(|c| (c.id, c.title))
+            .collect();
+        titles.insert(COMBINED_CHAT_ID, "Combined digest".to_string());
+        let mut anonymizer = self.anonymizer(opts)?;
+        if let Some(a) = anonymizer.as_mut() {
+            analyses.iter_mut().for_each(|r| anonymize_analysis(r, a));
+        }
+        let (tmp_path, file) = create_temp(&path).await?;
+        let written = match format {
+            ExportFormat::Csv => {
+                let mut wtr = csv::Writer::from_writer(file);
+                write_analysis_csv(&analyses, &titles, &mut wtr)
+                    .and_then(|rows| wtr.flush().map(|_| rows).map_err(csv_err))
+            }
+        };
+        let rows = commit_temp(&tmp_path, &path, written).await?;
+        let mapping_path = save_mapping(anonymizer.as_ref(), &path)?;
+        info!(path = %path.display(), rows, "analysis exported");
+        Ok(ExportReport {
+            path,
+            rows,
+            mapping_path,
+        })
+    }
+
+    /// Render `chat` with `exporter`, oldest first, limited to `opts.range`. Media links are
+    /// relative to the output file, so the export works next to the archive's media folder.
+    pub async fn export_chat(
+        &self,
+        chat: &Chat,
+        exporter: &dyn ExporterPort,
+        opts: &ExportOptions,
+    ) -> Result<ExportReport, DomainError> {
+        let path = opts
+            .out
+            .clone()
+            .unwrap_or_else(|| self.chat_path(chat.id, exporter.extension()));
+        let mut anonymizer = self.anonymizer(opts)?;
+        let out_dir = path
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let media_base = relative_path(out_dir, &self.media_dir);
+        let header = ExportChat {
+            id: chat.id,
+            title: chat.title.clone(),
+            kind: chat.kind,
+            range: opts.range,
+            exported_at: Utc::now().timestamp(),
+        };
+        let (tmp_path, file) = create_temp(&path).await?;
+        let written = match exporter.begin(&header, Box::new(file)) {
+            Ok(writer) => {
+                self.write_chat(writer, chat, opts, anonymizer.as_mut(), &media_base)
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        let rows = commit_temp(&tmp_path, &path, written).await?;
+        let mapping_path = save_mapping(anonymizer.as_ref(), &path)?;
+        info!(chat_id = chat.id, format = exporter.name(), path = %path.display(), rows, "chat exported");
+        Ok(ExportReport {
+            path,
+            rows,
+            mapping_path,
+        })
+    }
+
+    /// Anonymizer for one export: the persistent mapping when configured, else a fresh one.
+    fn anonymizer(&self, opts: &ExportOptions) -> Result<Option<Anonymizer>, DomainError> {
+        if !opts.anonymize {
+            return Ok(None);
+        }
+        match &self.anonymize_map {
+            Some(path) => Anonymizer::open(path).map(Some),
+            None => Ok(Some(Anonymizer::new())),
+        }
+    }
+
+    /// Stream a chat's messages as CSV rows (with header). Flushes after every batch.
+    async fn write_messages_csv<W: Write>(
+        &self,
+        chat_id: i64,
+        me_id: Option<i64>,
+        mut anonymizer: Option<&mut Anonymizer>,
+        wtr: &mut csv::Writer<W>,
+    ) -> Result<u64, DomainError> {
+        wtr.write_record(MESSAGE_HEADER).map_err(csv_err)?;
+        let mut names: HashMap<i64, Option<String>> = HashMap::new();
+        // Ingested fragments use negative message ids; start below them.
+        let mut after_id = i32::MIN;
+        let mut rows = 0u64;
+
+        loop {
+            let batch = self
+                .repo
+                .get_messages_after(chat_id, after_id, self.batch_size)
+                .await?;
+            let Some(last) = batch.last() else {
+                break;
+            };
+            after_id = last.id;
+
+            for msg in &batch {
+                let sender_name = self.sender_name(&mut names, msg).await?;
+                let mut record = message_record(msg, sender_name.as_deref(), me_id);
+                if let Some(a) = anonymizer.as_deref_mut() {
+                    anonymize_record(&mut record, msg, sender_name.as_deref(), a);
+                }
+                wtr.write_record(record).map_err(csv_err)?;
+                rows += 1;
+            }
+            wtr.flush().map_err(csv_err)?;
+        }
+        wtr.flush().map_err(csv_err)?;
+        Ok(rows)
+    }
+
+    /// Stream a chat into `writer` in keyset batches, with its service events interleaved by
+    /// message id (both share the chat's id sequence). Channel posts carry their archived
+    /// comments. Returns the number of messages written.
+    async fn write_chat(
+        &self,
+        mut writer: Box<dyn ChatExportWriter>,
+        chat: &Chat,
+        opts: &ExportOptions,
+        mut anonymizer: Option<&mut Anonymizer>,
+        media_base: &str,
+    ) -> Result<u64, DomainError> {
+        let chat_id = chat.id;
+        let mut names: HashMap<i64, Option<String>> = HashMap::new();
+        let mut events = self
+            .export_events(chat_id, opts, &mut names, anonymizer.as_deref_mut())
+            .await?
+            .into_iter()
+            .peekable();
+        let mut after_id = i32::MIN;
+        let mut rows = 0u64;
+
+        loop {
+            let batch = self
+                .repo
+                .get_messages_after(chat_id, after_id, self.batch_size)
+                .await?;
+            let Some(last) = batch.last() else {
+                break;
+            };
+            after_id = last.id;
+            let batch: Vec<Message> = batch
+                .into_iter()
+                .filter(|m| opts.range.is_none_or(|r| r.from <= m.date && m.date < r.to))
+                .collect();
+            if batch.is_empty() {
+                continue;
+            }
+
+            let media = self.media_paths(chat_id, &batch, media_base).await?;
+            let reply_ids: Vec<i32> = batch.iter().filter_map(|m| m.reply_to_msg_id).collect();
+            let replied = self.repo.get_messages_map(chat_id, &reply_ids).await?;
+
+            let mut prepared = self
+                .prepare(
+                    batch,
+                    &replied,
+                    &media,
+                    opts,
+                    &mut names,
+                    anonymizer.as_deref_mut(),
+                )
+                .await?;
+            if chat.kind == ChatType::Channel {
+                self.attach_comments(
+                    chat_id,
+                    &mut prepared,
+                    opts,
+                    &mut names,
+                    anonymizer.as_deref_mut(),
+                    media_base,
+                )
+                .await?;
+            }
+            // Split the batch wherever an event falls between two messages.
+            let mut start = 0;
+            for (i, m) in prepared.iter().enumerate() {
+                while let Some(event) = events.next_if(|e| e.event.id < m.message.id) {
+                    if start < i {
+                        writer.write_batch(&prepared[start..i])?;
+                        start = i;
+                    }
+                    writer.write_event(&event)?;
+                }
+            }
+            writer.write_batch(&prepared[start..])?;
+            rows += prepared.len() as u64;
+        }
+        for event in events {
+            writer.write_event(&event)?;
+        }
+        writer.finish()?;
+        Ok(rows)
+    }
+
+    /// Export form of `batch`: sender names, reply quotes (from `replied`), media paths and
+    /// anonymization applied.
+    async fn prepare(
+        &self,
+        batch: Vec<Message>,
+        replied: &HashMap<i32, Message>,
+        media: &HashMap<i32, String>,
+        opts: &ExportOptions,
+        names: &mut HashMap<i64, Option<String>>,
+        mut anonymizer: Option<&mut Anonymizer>,
+    ) -> Result<Vec<ExportMessage>, DomainError> {
+        let mut prepared = Vec::with_capacity(batch.len());
+        for mut message in batch {
+            let mut sender_name = self.sender_name(names, &message).await?;
+            let quoted = message.reply_to_msg_id.and_then(|id| replied.get(&id));
+            let mut reply_to = match quoted {
+                Some(q) => Some(ReplyQuote {
+                    message_id: q.id,
+                    sender_name: self.sender_name(names, q).await?,
+                    text: q.text.clone(),
+                }),
+                None => None,
+            };
+            if let Some(a) = anonymizer.as_deref_mut() {
+                sender_name = message
+                    .from_user_id
+                    .map(|id| a.user(id, sender_name.as_deref()));
+                message.text = a.redact_text(&message.text);
+                if let (Some(quote), Some(q)) = (reply_to.as_mut(), quoted) {
+                    quote.sender_name = q
+                        .from_user_id
+                        .map(|id| a.user(id, quote.sender_name.as_deref()));
+                    quote.text = a.redact_text(&quote.text);
+                }
+            }
+            prepared.push(ExportMessage {
+                is_outgoing: opts.me_id.is_some() && message.from_user_id == opts.me_id,
+                media_path: media.get(&message.id).cloned(),
+                message,
+                sender_name,
+                reply_to,
+                comments: Vec::new(),
+            });
+        }
+        Ok(prepared)
+    }
+
+    /// Attach to each post of `posts` the comments archived from the channel's discussion
+    /// groups. Replies between comments are quoted; the post copy a comment answers is not.
+    async fn attach_comments(
+        &self,
+        channel_id: i64,
+        posts: &mut [ExportMessage],
+        opts: &ExportOptions,
+        names: &mut HashMap<i64, Option<String>>,
+        mut anonymizer: Option<&mut Anonymizer>,
+        media_base: &str,
+    ) -> Result<(), DomainError> {
+        let post_ids: Vec<i32> = posts.iter().map(|p| p.message.id).collect();
+        let comments = self
+            .repo
+            .get_channel_comments(channel_id, &post_ids)
+            .await?;
+        if comments.is_empty() {
+            return Ok(());
+        }
+        let mut by_group: HashMap<i64, Vec<Message>> = HashMap::new();
+        for comment in comments {
+            by_group.entry(comment.chat_id).or_default().push(comment);
+        }
+        let mut groups: Vec<(i64, Vec<Message>)> = by_group.into_iter().collect();
+        groups.sort_unstable_by_key(|(id, _)| *id);
+        let mut by_post: HashMap<i32, Vec<ExportMessage>> = HashMap::new();
+        for (group_id, mut comments) in groups {
+            comments.sort_unstable_by_key(|m| m.id);
+            let media = self.media_paths(group_id, &comments, media_base).await?;
+            let replied: HashMap<i32, Message> =
+                comments.iter().map(|m| (m.id, m.clone())).collect();
+            for comment in self
+                .prepare(
+                    comments,
+                    &replied,
+                    &media,
+                    opts,
+                    names,
+                    anonymizer.as_deref_mut(),
+                )
+                .await?
+            {
+                if let Some(post_id) = comment.message.linked_channel_post {
+                    by_post.entry(post_id).or_default().push(comment);
+                }
+            }
+        }
+        for post in posts {
+            if let Some(comments) = by_post.remove(&post.message.id) {
+                post.comments = comments;
+            }
+        }
+        Ok(())
+    }
+
+    /// Stored events of `chat_id` within `opts.range`, oldest first, with the actor and any
+    /// added or removed users named (pseudonyms when anonymizing).
+    async fn export_events(
+        &self,
+        chat_id: i64,
+        opts: &ExportOptions,
+        names: &mut HashMap<i64, Option<String>>,
+        mut anonymizer: Option<&mut Anonymizer>,
+    ) -> Result<Vec<ExportEvent>, DomainError> {
+        let events: Vec<ChatEvent> = self
+            .repo
+            .get_events(chat_id)
+            .await?
+            .into_iter()
+            .filter(|e| opts.range.is_none_or(|r| r.from <= e.date && e.date < r.to))
+            .collect();
+        let mut labels: HashMap<i64, String> = HashMap::new();
+        for user_id in events
+            .iter()
+            .flat_map(|e| e.actor_id.into_iter().chain(e.payload_user_ids()))
+        {
+            if labels.contains_key(&user_id) {
+                continue;
+            }
+            let name = self.user_name(names, user_id).await?;
+            let label = match anonymizer.as_deref_mut() {
+                Some(a) => a.user(user_id, name.as_deref()),
+                None => name.unwrap_or_else(|| format!("user {}", user_id)),
+            };
+            labels.insert(user_id, label);
+        }
+        Ok(events
+            .into_iter()
+            .map(|event| {
+                let label = |id: Option<i64>| {
+                    id.and_then(|id| labels.get(&id).cloned())
+                        .unwrap_or_else(|| "Someone".to_string())
+                };
+                ExportEvent {
+                    actor_name: event.actor_id.and_then(|id| labels.get(&id).cloned()),
+                    text: event.describe(label),
+                    event,
+                }
+            })
+            .collect())
+    }
+
+    /// Paths (prefixed with `media_base`) of downloaded media in the id span of `batch`.
+    async fn media_paths(
+        &self,
+        chat_id: i64,
+        batch: &[Message],
+        media_base: &str,
+    ) -> Result<HashMap<i32, String>, DomainError> {
+        let mut paths = HashMap::new();
+        let (Some(first), Some(last)) = (batch.first(), batch.last()) else {
+            return Ok(paths);
+        };
+        if !batch.iter().any(|m| m.media.is_some()) {
+            return Ok(paths);
+        }
+        let mut after = Some((chat_id, first.id.saturating_sub(1)));
+        loop {
+            let page = self
+                .media_index
+                .list_media_files(
+                    Some(chat_id),
+                    Some(MediaStatus::Done),
+                    after,
+                    self.batch_size,
+                )
+                .await?;
+            let Some(tail) = page.last() else {
+                break;
+            };
+            after = Some((chat_id, tail.message_id));
+            let past_end = tail.message_id >= last.id;
+            for file in page.into_iter().take_while(|f| f.message_id <= last.id) {
+                let path = if media_base.is_empty() {
+                    file.rel_path
+                } else {
+                    format!("{}/{}", media_base, file.rel_path)
+                };
+                paths.insert(file.message_id, path);
+            }
+            if past_end {
+                break;
+            }
+        }
+        Ok(paths)
+    }
+
+    /// Sender display name joined by the repo, else the cached registry username (one
+    /// registry query per distinct sender).
+    async fn sender_name(
+        &self,
+        cache: &mut HashMap<i64, Option<String>>,
+        msg: &Message,
+    ) -> Result<Option<String>, DomainError> {
+        if msg.sender_name.is_some() {
+            return Ok(msg.sender_name.clone());
+        }
+        let Some(user_id) = msg.from_user_id else {
+            return Ok(None);
+        };
+        self.user_name(cache, user_id).await
+    }
+
+    /// Username of `user_id` from the entity registry, cached per export.
+    async fn user_name(
+        &self,
+        cache: &mut HashMap<i64, Option<String>>,
+        user_id: i64,
+    ) -> Result<Option<String>, DomainError> {
+        if let Some(name) = cache.get(&user_id) {
+            return Ok(name.clone());
+        }
+        let name = self.registry.get_username(user_id).await?;
+        cache.insert(user_id, name.clone());
+        Ok(name)
+    }
+}
+
+/// One message row in [`MESSAGE_HEADER`] order. Text is written verbatim (newlines and
+/// quotes are escaped by the CSV writer).
+fn message_record(msg: &Message, sender_name: Option<&str>, me_id: Option<i64>) -> [String; 9] {
+    let date = DateTime::<Utc>::from_timestamp(msg.date, 0)
+        .map(|dt| dt.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+        .unwrap_or_else(|| msg.date.to_string());
+    let is_outgoing = match (me_id, msg.from_user_id) {
+        (Some(me), Some(from)) => (me == from).to_string(),
+        (Some(_), None) => "false".to_string(),
+        (None, _) => String::new(),
+    };
+    [
+        msg.id.to_string(),
+        date,
+        opt_to_string(msg.from_user_id),
+        sender_name.unwrap_or_default().to_string(),
+        msg.text.clone(),
+        msg.media
+            .as_ref()
+            .map(|m| m.media_type.as_str().to_string())
+            .unwrap_or_default(),
+        opt_to_string(msg.reply_to_msg_id),
+        is_outgoing,
+        msg.topic_title.clone().unwrap_or_default(),
+    ]
+}
+
+/// Replace sender id/name with the sender's pseudonym and redact the text column.
+fn anonymize_record(
+    record: &mut [String; 9],
+    msg: &Message,
+    sender_name: Option<&str>,
+    anonymizer: &mut Anonymizer,
+) {
+    let pseudonym = msg.from_user_id.map(|id| anonymizer.user(id, sender_name));
+    record[2] = pseudonym.clone().unwrap_or_default();
+    record[3] = sender_name.and(pseudonym).unwrap_or_default();
+    record[4] = anonymizer.redact_text(&msg.text);
+}
+
+/// Pseudonymize owners and redact free text of one analysis.
+fn anonymize_analysis(result: &mut AnalysisResult, anonymizer: &mut Anonymizer) {
+    result.summary = anonymizer.redact_text(&result.summary);
+    for topic in &mut result.key_topics {
+        *topic = anonymizer.redact_text(topic);
+    }
+    for item in &mut result.action_items {
+        item.description = anonymizer.redact_text(&item.description);
+        if let Some(owner) = item.owner.as_mut() {
+            *owner = anonymizer.name(owner);
+        }
+    }
+}
+
+/// Write the pseudonym mapping next to `export_path` and persist the shared mapping.
+fn save_mapping(
+    anonymizer: Option<&Anonymizer>,
+    export_path: &Path,
+) -> Result<Option<PathBuf>, DomainError> {
+    let Some(anonymizer) = anonymizer else {
+        return Ok(None);
+    };
+    let mut name = export_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".mapping.json");
+    let mapping_path = export_path.with_file_name(name);
+    anonymizer.save_to(&mapping_path)?;
+    anonymizer.persist()?;
+    Ok(Some(mapping_path))
+}
+
+/// Analysis rows in [`ANALYSIS_HEADER`] order. `titles` holds the stored chat titles; chats
+/// without one get an empty title. Returns the number of data rows.
+fn write_analysis_csv<W: Write>(
+    analyses: &[AnalysisResult],
+    titles: &HashMap<i64, String>,
+    wtr: &mut csv::Writer<W>,
+) -> Result<u64, DomainError> {
+    wtr.write_record(ANALYSIS_HEADER).map_err(csv_err)?;
+    let mut rows = 0u64;
+    for a in analyses {
+        let chat_id = a.chat_id.to_string();
+        let title = titles.get(&a.chat_id).map_or("", String::as_str);
+        let topics = a.key_topics.join("; ");
+        let base = [
+            chat_id.as_str(),
+            title,
+            a.week_group.as_str(),
+            &a.summary,
+            &topics,
+        ];
+        if a.action_items.is_empty() {
+            wtr.write_record(base.iter().chain(&[""; 5]))
+                .map_err(csv_err)?;
+            rows += 1;
+        }
+        for item in &a.action_items {
+            let action = [
+                item.description.as_str(),
+                item.owner.as_deref().unwrap_or_default(),
+                item.deadline.as_deref().unwrap_or_default(),
+                item.priority.as_deref().unwrap_or_default(),
+                ACTION_STATUS_OPEN,
+            ];
+            wtr.write_record(base.iter().chain(&action))
+                .map_err(csv_err)?;
+            rows += 1;
+        }
+    }
+    Ok(rows)
+}
+
+/// Create the parent directory and `<path>.tmp`. Readers never see a partial export.
+async fn create_temp(path: &Path) -> Result<(PathBuf, BufWriter<File>), DomainError> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| DomainError::Repo(format!("Failed to create export dir: {}", e)))?;
+    }
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+    let file = File::create(&tmp_path)
+        .map_err(|e| DomainError::Repo(format!("Failed to create export file: {}", e)))?;
+    Ok((tmp_path, BufWriter::new(file)))
+}
+
+/// Rename the temp file into place on success; remove it on failure.
+async fn commit_temp(
+    tmp_path: &Path,
+    path: &Path,
+    written: Result<u64, DomainError>,
+) -> Result<u64, DomainError> {
+    let rows = match written {
+        Ok(rows) => rows,
+        Err(e) => {
+            let _ = tokio::fs::remove_file(tmp_path).await;
+            return Err(e);
+        }
+    };
+    tokio::fs::rename(tmp_path, path)
+        .await
+        .map_err(|e| DomainError::Repo(format!("Failed to move export into place: {}", e)))?;
+    Ok(rows)
+}
+
+/// `/`-separated path from directory `from` to `to` (e.g. `../media`). Both are made absolute
+/// against the working directory first; `..` components are not resolved.
+fn relative_path(from: &Path, to: &Path) -> String {
+    let from = std::path::absolute(from).unwrap_or_else(|_| from.to_path_buf());
+    let to = std::path::absolute(to).unwrap_or_else(|_| to.to_path_buf());
+    let common = from
+        .components()
+        .zip(to.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut parts: Vec<String> = from
+        .components()
+        .skip(common)
+        .map(|_| "..".to_string())
+        .collect();
+    parts.extend(
+        to.components()
+            .skip(common)
+            .map(|c| c.as_os_str().to_string_lossy().into_owned()),
+    );
+    parts.join("/")
+}
+
+fn opt_to_string<T: ToString>(v: Option<T>) -> String {
+    v.map(|v| v.to_string()).unwrap_or_default()
+}
+
+fn csv_err(e: impl std::fmt::Display) -> DomainError {
+    DomainError::Repo(format!("Failed to write CSV export: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::persistence::sqlite_repo::SqliteRepo;
+    use crate::domain::{
+        ActionItem, ActivityBin, ActivityBucket, ArchiveStats, ChatEventKind, ChatPurge,
+        ChatSettings, ChatStats, ChatType, ForumTopic, Granularity, MediaFile, MediaReference,
+        MediaType, SearchHit, User, WeekGroup,
+    };
+    use crate::testing::fake_tg::text_message;
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+
+    fn message(id: i32, text: &str, from: Option<i64>) -> Message {
+        Message {
+            from_user_id: from,
+            ..text_message(100, id, 1704067200 + id as i64, text)
+        }
+    }
+
+    async fn repo(name: &str) -> (Arc<SqliteRepo>, PathBuf) {
+        let base_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("target")
+            .join(name);
+        let _ = std::fs::remove_dir_all(&base_dir);
+        let repo = Arc::new(SqliteRepo::connect(&base_dir).await.expect("connect"));
+        (repo, base_dir)
+    }
+
+    /// RepoPort wrapper recording the size of every page read (streaming check).
+    struct PageSpy {
+        inner: Arc<SqliteRepo>,
+        pages: Mutex<Vec<(u32, usize)>>,
+    }
+
+    #[async_trait::async_trait]
+    impl RepoPort for PageSpy {
+        async fn save_messages(&self, chat_id: i64, m: &[Message]) -> Result<(), DomainError> {
+            self.inner.save_messages(chat_id, m).await
+        }
+        async fn save_users(&self, users: &[User]) -> Result<(), DomainError> {
+            self.inner.save_users(users).await
+        }
+        async fn get_messages(&self, _: i64, _: u32, _: u32) -> Result<Vec<Message>, DomainError> {
+            panic!("export must not use unbounded offset paging");
+        }
+        async fn get_messages_after(
+            &self,
+            chat_id: i64,
+            after_id: i32,
+            limit: u32,
+        ) -> Result<Vec<Message>, DomainError> {
+            let page = self
+                .inner
+                .get_messages_after(chat_id, after_id, limit)
+                .await?;
+            self.pages.lock().unwrap().push((limit, page.len()));
+            Ok(page)
+        }
+        async fn get_messages_in_range(
+            &self,
+            _: i64,
+            _: TimeRange,
+            _: u32,
+            _: u32,
+        ) -> Result<Vec<Message>, DomainError> {
+            panic!("export must not use unbounded offset paging");
+        }
+        async fn get_messages_by_ids(
+            &self,
+            chat_id: i64,
+            ids: &[i32],
+        ) -> Result<Vec<Message>, DomainError> {
+            self.inner.get_messages_by_ids(chat_id, ids).await
+        }
+        async fn search_messages(
+            &self,
+            query: &str,
+            chat_id: Option<i64>,
+            limit: u32,
+            offset: u32,
+        ) -> Result<Vec<SearchHit>, DomainError> {
+            self.inner
+                .search_messages(query, chat_id, limit, offset)
+                .await
+        }
+        async fn get_blacklisted_ids(&self) -> Result<HashSet<i64>, DomainError> {
+            self.inner.get_blacklisted_ids().await
+        }
+        async fn update_blacklist(&self, ids: HashSet<i64>) -> Result<(), DomainError> {
+            self.inner.update_blacklist(ids).await
+        }
+        async fn get_target_ids(&self) -> Result<HashSet<i64>, DomainError> {
+            self.inner.get_target_ids().await
+        }
+        async fn update_targets(&self, ids: HashSet<i64>) -> Result<(), DomainError> {
+            self.inner.update_targets(ids).await
+        }
+        async fn get_chat_settings(&self, chat_id: i64) -> Result<ChatSettings, DomainError> {
+            self.inner.get_chat_settings(chat_id).await
+        }
+        async fn list_chat_settings(&self) -> Result<Vec<ChatSettings>, DomainError> {
+            self.inner.list_chat_settings().await
+        }
+        async fn set_chat_settings(&self, settings: &ChatSettings) -> Result<(), DomainError> {
+            self.inner.set_chat_settings(settings).await
+        }
+        async fn get_activity_histogram(
+            &self,
+            chat_id: i64,
+            bucket: ActivityBucket,
+            range: TimeRange,
+            utc_offset_secs: i32,
+        ) -> Result<Vec<ActivityBin>, DomainError> {
+            self.inner
+                .get_activity_histogram(chat_id, bucket, range, utc_offset_secs)
+                .await
+        }
+        async fn register_synthetic_chat(&self, chat: &Chat) -> Result<(), DomainError> {
+            self.inner.register_synthetic_chat(chat).await
+        }
+        async fn upsert_chats(&self, chats: &[Chat]) -> Result<(), DomainError> {
+            self.inner.upsert_chats(chats).await
+        }
+        async fn save_forum_topics(
+            &self,
+            chat_id: i64,
+            topics: &[ForumTopic],
+        ) -> Result<(), DomainError> {
+            self.inner.save_forum_topics(chat_id, topics).await
+        }
+        async fn save_events(&self, events: &[ChatEvent]) -> Result<(), DomainError> {
+            self.inner.save_events(events).await
+        }
+        async fn get_events(&self, chat_id: i64) -> Result<Vec<ChatEvent>, DomainError> {
+            self.inner.get_events(chat_id).await
+        }
+        async fn link_channel_comments(&self, chat_id: i64) -> Result<u64, DomainError> {
+            self.inner.link_channel_comments(chat_id).await
+        }
+        async fn get_channel_comments(
+            &self,
+            channel_id: i64,
+            post_ids: &[i32],
+        ) -> Result<Vec<Message>, DomainError> {
+            self.inner.get_channel_comments(channel_id, post_ids).await
+        }
+        async fn get_known_chats(&self) -> Result<Vec<Chat>, DomainError> {
+            self.inner.get_known_chats().await
+        }
+        async fn get_chat_stats(&self, chat_id: i64) -> Result<ChatStats, DomainError> {
+            self.inner.get_chat_stats(chat_id).await
+        }
+        async fn get_global_stats(&self) -> Result<ArchiveStats, DomainError> {
+            self.inner.get_global_stats().await
+        }
+        async fn delete_chat_data(&self, chat_id: i64) -> Result<ChatPurge, DomainError> {
+            self.inner.delete_chat_data(chat_id).await
+        }
+    }
+
+    /// Exporter that keeps the prepared batches and writes one line of ids per batch.
+    #[derive(Default)]
+    struct RecordingExporter {
+        batches: Arc<Mutex<Vec<Vec<ExportMessage>>>>,
+    }
+
+    struct RecordingWriter {
+        out: Box<dyn Write + Send>,
+        batches: Arc<Mutex<Vec<Vec<ExportMessage>>>>,
+    }
+
+    impl ExporterPort for RecordingExporter {
+        fn name(&self) -> &'static str {
+            "recording"
+        }
+        fn extension(&self) -> &'static str {
+            "txt"
+        }
+        fn begin(
+            &self,
+            chat: &ExportChat,
+            mut out: Box<dyn Write + Send>,
+        ) -> Result<Box<dyn ChatExportWriter>, DomainError> {
+            writeln!(out, "{}", chat.title).unwrap();
+            Ok(Box::new(RecordingWriter {
+                out,
+                batches: self.batches.clone(),
+            }))
+        }
+    }
+
+    impl ChatExportWriter for RecordingWriter {
+        fn write_batch(&mut self, messages: &[ExportMessage]) -> Result<(), DomainError> {
+            let ids: Vec<String> = messages.iter().map(|m| m.message.id.to_string()).collect();
+            writeln!(self.out, "{}", ids.join(",")).unwrap();
+            self.batches.lock().unwrap().push(messages.to_vec());
+            Ok(())
+        }
+        fn write_event(&mut self, event: &ExportEvent) -> Result<(), DomainError> {
+            writeln!(self.out, "event {}: {}", event.event.id, event.text).unwrap();
+            Ok(())
+        }
+        fn finish(mut self: Box<Self>) -> Result<(), DomainError> {
+            self.out
+                .flush()
+                .map_err(|e| DomainError::Repo(e.to_string()))
+        }
+    }
+
+    #[test]
+    fn test_message_record_quoting() {
+        let mut msg = message(5, "He said \"hi\",\nthen left; ok", Some(42));
+        msg.reply_to_msg_id = Some(3);
+        msg.topic_title = Some("Releases".to_string());
+        msg.media = Some(MediaReference {
+            message_id: 5,
+            chat_id: 100,
+            media_type: MediaType::Photo,
+            opaque_ref: String::new(),
+            size_bytes: None,
+            filename: None,
+            mime_type: None,
+        });
+        let mut wtr = csv::Writer::from_writer(Vec::new());
+        wtr.write_record(message_record(&msg, Some("alice, b"), Some(42)))
+            .unwrap();
+        wtr.write_record(message_record(&message(6, "plain", None), None, None))
+            .unwrap();
+        let out = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            out,
+            "5,2024-01-01T00:00:05Z,42,\"alice, b\",\"He said \"\"hi\"\",\nthen left; ok\",photo,3,true,Releases\n\
+             6,2024-01-01T00:00:06Z,,,plain,,,,\n"
+        );
+
+        // Round trip through a CSV reader restores the original fields.
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(out.as_bytes());
+        let first = rdr.records().next().unwrap().unwrap();
+        assert_eq!(&first[3], "alice, b");
+        assert_eq!(&first[4], "He said \"hi\",\nthen left; ok");
+    }
+
+    #[test]
+    fn test_analysis_rows_one_per_action_item() {
+        let item = |d: &str, owner: Option<&str>| ActionItem {
+            description: d.to_string(),
+            owner: owner.map(String::from),
+            deadline: None,
+            priority: Some("high".to_string()),
+        };
+        let analyses = vec![
+            AnalysisResult {
+                week_group: WeekGroup::new("2024-01"),
+                granularity: Granularity::Week,
+                chat_id: 1,
+                summary: "Busy week".to_string(),
+                key_topics: vec!["release".to_string(), "bugs".to_string()],
+                action_items: vec![item("Fix login", Some("bob")), item("Ship v2", None)],
+                analyzed_at: 0,
+            },
+            AnalysisResult {
+                week_group: WeekGroup::new("2024-02"),
+                granularity: Granularity::Week,
+                chat_id: 1,
+                summary: "Quiet".to_string(),
+                key_topics: vec![],
+                action_items: vec![],
+                analyzed_at: 0,
+            },
+        ];
+        let titles = HashMap::from([(1, "Team".to_string())]);
+        let mut wtr = csv::Writer::from_writer(Vec::new());
+        assert_eq!(write_analysis_csv(&analyses, &titles, &mut wtr).unwrap(), 3);
+        let out = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            out,
+            "chat_id,chat_title,week,summary,topics,action_item,owner,deadline,priority,status\n\
+             1,Team,2024-01,Busy week,release; bugs,Fix login,bob,,high,open\n\
+             1,Team,2024-01,Busy week,release; bugs,Ship v2,,,high,open\n\
+             1,Team,2024-02,Quiet,,,,,,\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_export_messages_streams_in_batches() {
+        let (sqlite, base_dir) = repo("test_export_messages_csv").await;
+        let messages: Vec<Message> = (1..=25)
+            .map(|id| message(id, &format!("msg {}", id), Some(7)))
+            .collect();
+        sqlite.save_messages(100, &messages).await.unwrap();
+        sqlite
+            .save_entity(7, 1, "user", Some("alice"))
+            .await
+            .unwrap();
+
+        let spy = Arc::new(PageSpy {
+            inner: Arc::clone(&sqlite),
+            pages: Mutex::new(Vec::new()),
+        });
+        let out_dir = base_dir.join("exports");
+        let mut service = ExportService::new(
+            spy.clone(),
+            sqlite.clone(),
+            sqlite.clone(),
+            sqlite.clone(),
+            base_dir.join("media"),
+            out_dir.clone(),
+            None,
+        );
+        service.batch_size = 10;
+
+        let opts = ExportOptions {
+            me_id: Some(7),
+            ..Default::default()
+        };
+        let report = service
+            .export_messages(100, ExportFormat::Csv, &opts)
+            .await
+            .unwrap();
+        assert_eq!(report.rows, 25);
+        assert_eq!(report.path, out_dir.join("messages_100.csv"));
+
+        // Bounded pages: 10 + 10 + 5, then an empty page ends the stream.
+        let pages = spy.pages.lock().unwrap().clone();
+        assert_eq!(pages, vec![(10, 10), (10, 10), (10, 5), (10, 0)]);
+
+        let content = std::fs::read_to_string(&report.path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 26);
+        assert_eq!(lines[0], MESSAGE_HEADER.join(","));
+        assert_eq!(lines[1], "1,2024-01-01T00:00:01Z,7,alice,msg 1,,,true,");
+        assert_eq!(lines[25], "25,2024-01-01T00:00:25Z,7,alice,msg 25,,,true,");
+        assert!(!out_dir.join("messages_100.csv.tmp").exists());
+        assert_eq!(report.mapping_path, None);
+    }
+
+    #[tokio::test]
+    async fn test_export_chat_streams_with_replies_media_and_range() {
+        let (sqlite, base_dir) = repo("test_export_chat").await;
+        let mut messages: Vec<Message> = (1..=25)
+            .map(|id| message(id, &format!("msg {}", id), Some(7)))
+            .collect();
+        for (id, media_type) in [(3, MediaType::Photo), (4, MediaType::Video)] {
+            messages[id as usize - 1].media = Some(MediaReference {
+                message_id: id,
+                chat_id: 100,
+                media_type,
+                opaque_ref: String::new(),
+                size_bytes: None,
+                filename: None,
+                mime_type: None,
+            });
+        }
+        messages[1].from_user_id = Some(8);
+        messages[11].reply_to_msg_id = Some(3);
+        messages[12].reply_to_msg_id = Some(99);
+        sqlite.save_messages(100, &messages).await.unwrap();
+        sqlite
+            .save_entity(7, 1, "user", Some("alice"))
+            .await
+            .unwrap();
+        for (id, status) in [(3, MediaStatus::Done), (4, MediaStatus::Pending)] {
+            sqlite
+                .upsert_media_file(&MediaFile {
+                    chat_id: 100,
+                    message_id: id,
+                    media_type: MediaType::Photo,
+                    rel_path: format!("100_{}.jpg", id),
+                    size_bytes: None,
+                    expected_size: None,
+                    sha256: None,
+                    status,
+                    message_date: None,
+                    remote_key: None,
+                })
+                .await
+                .unwrap();
+        }
+
+        let spy = Arc::new(PageSpy {
+            inner: Arc::clone(&sqlite),
+            pages: Mutex::new(Vec::new()),
+        });
+        let mut service = ExportService::new(
+            spy.clone(),
+            sqlite.clone(),
+            sqlite.clone(),
+            sqlite.clone(),
+            base_dir.join("media"),
+            base_dir.join("exports"),
+            None,
+        );
+        service.batch_size = 10;
+
+        let chat = Chat {
+            id: 100,
+            title: "Team".to_string(),
+            username: None,
+            kind: ChatType::Group,
+            approx_message_count: None,
+            is_forum: false,
+            last_message_date: None,
+        };
+        let exporter = RecordingExporter::default();
+        // Messages 2..=20 (message N is dated start + N).
+        let opts = ExportOptions {
+            me_id: Some(7),
+            range: Some(TimeRange {
+                from: 1704067202,
+                to: 1704067221,
+            }),
+            ..Default::default()
+        };
+        let report = service.export_chat(&chat, &exporter, &opts).await.unwrap();
+        assert_eq!(report.rows, 19);
+        assert_eq!(report.path, base_dir.join("exports").join("chat_100.txt"));
+        assert_eq!(
+            spy.pages.lock().unwrap().clone(),
+            vec![(10, 10), (10, 10), (10, 5), (10, 0)]
+        );
+
+        let batches = exporter.batches.lock().unwrap().clone();
+        // The last page (21..=25) is outside the range and writes nothing.
+        assert_eq!(batches.len(), 2);
+        let all: Vec<&ExportMessage> = batches.iter().flatten().collect();
+        assert_eq!(all.first().unwrap().message.id, 2);
+        assert_eq!(all.last().unwrap().message.id, 20);
+
+        let by_id = |id: i32| all.iter().find(|m| m.message.id == id).unwrap();
+        assert_eq!(by_id(2).sender_name, None);
+        assert!(!by_id(2).is_outgoing);
+        assert_eq!(by_id(5).sender_name.as_deref(), Some("alice"));
+        assert!(by_id(5).is_outgoing);
+        assert_eq!(by_id(3).media_path.as_deref(), Some("../media/100_3.jpg"));
+        assert_eq!(by_id(4).media_path, None);
+        // Reply to a message of an earlier batch is quoted; a missing target is not.
+        assert_eq!(
+            by_id(12).reply_to,
+            Some(ReplyQuote {
+                message_id: 3,
+                sender_name: Some("alice".to_string()),
+                text: "msg 3".to_string(),
+            })
+        );
+        assert_eq!(by_id(13).reply_to, None);
+
+        let content = std::fs::read_to_string(&report.path).unwrap();
+        assert_eq!(content.lines().next(), Some("Team"));
+        assert_eq!(content.lines().count(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_export_chat_interleaves_events() {
+        let (sqlite, base_dir) = repo("test_export_chat_events").await;
+        let messages: Vec<Message> = [1, 3, 5]
+            .into_iter()
+            .map(|id| message(id, &format!("msg {}", id), Some(7)))
+            .collect();
+        sqlite.save_messages(100, &messages).await.unwrap();
+        sqlite
+            .save_entity(7, 1, "user", Some("alice"))
+            .await
+            .unwrap();
+        let event = |id: i32, kind, payload: Option<&str>| ChatEvent {
+            id,
+            chat_id: 100,
+            date: 1704067200 + id as i64,
+            kind,
+            actor_id: Some(7),
+            payload: payload.map(String::from),
+        };
+        sqlite
+            .save_events(&[
+                event(2, ChatEventKind::UserJoined, Some("8")),
+                event(4, ChatEventKind::TitleChanged, Some("Launch")),
+                event(9, ChatEventKind::MessagePinned, Some("5")),
+            ])
+            .await
+            .unwrap();
+
+        let service = ExportService::new(
+            sqlite.clone(),
+            sqlite.clone(),
+            sqlite.clone(),
+            sqlite.clone(),
+            base_dir.join("media"),
+            base_dir.join("exports"),
+            None,
+        );
+        let chat = Chat {
+            id: 100,
+            title: "Team".to_string(),
+            username: None,
+            kind: ChatType::Group,
+            approx_message_count: None,
+            is_forum: false,
+            last_message_date: None,
+        };
+        let exporter = RecordingExporter::default();
+        let report = service
+            .export_chat(&chat, &exporter, &ExportOptions::default())
+            .await
+            .unwrap();
+        // Events are written but not counted as rows.
+        assert_eq!(report.rows, 3);
+        let content = std::fs::read_to_string(&report.path).unwrap();
+        assert_eq!(
+            content.lines().collect::<Vec<_>>(),
+            vec![
+                "Team",
+                "1",
+                "event 2: alice added user 8",
+                "3",
+                "event 4: alice changed the title to \"Launch\"",
+                "5",
+                "event 9: alice pinned message #5",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_export_channel_posts_carry_comments() {
+        let (sqlite, base_dir) = repo("test_export_channel_comments").await;
+        let (channel, group) = (-1000000000042i64, -1000000000077i64);
+        let posts: Vec<Message> = [500, 501]
+            .into_iter()
+            .map(|id| Message {
+                chat_id: channel,
+                ..message(id, &format!("post {}", id), None)
+            })
+            .collect();
+        sqlite.save_messages(channel, &posts).await.unwrap();
+        let in_group = |id: i32, text: &str, from: Option<i64>, reply_to: Option<i32>| Message {
+            chat_id: group,
+            reply_to_msg_id: reply_to,
+            ..message(id, text, from)
+        };
+        let copy = Message {
+            forwarded_from_id: Some(channel),
+            forwarded_date: Some(1704067200),
+            linked_channel_post: Some(500),
+            ..in_group(20, "post 500", None, None)
+        };
+        sqlite
+            .save_messages(
+                group,
+                &[
+                    copy,
+                    in_group(21, "first!", Some(7), Some(20)),
+                    in_group(22, "welcome", Some(8), Some(21)),
+                ],
+            )
+            .await
+            .unwrap();
+        sqlite.link_channel_comments(group).await.unwrap();
+        sqlite
+            .save_entity(7, 1, "user", Some("alice"))
+            .await
+            .unwrap();
+
+        let service = ExportService::new(
+            sqlite.clone(),
+            sqlite.clone(),
+            sqlite.clone(),
+            sqlite.clone(),
+            base_dir.join("media"),
+            base_dir.join("exports"),
+            None,
+        );
+        let chat = Chat {
+            id: channel,
+            title: "News".to_string(),
+            username: None,
+            kind: ChatType::Channel,
+            approx_message_count: None,
+            is_forum: false,
+            last_message_date: None,
+        };
+        let exporter = RecordingExporter::default();
+        let report = service
+            .export_chat(&chat, &exporter, &ExportOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(report.rows, 2);
+        let batches = exporter.batches.lock().unwrap().clone();
+        let posts: Vec<&ExportMessage> = batches.iter().flatten().collect();
+        let comments: Vec<(i32, Option<&str>)> = posts[0]
+            .comments
+            .iter()
+            .map(|c| (c.message.id, c.sender_name.as_deref()))
+            .collect();
+        assert_eq!(comments, vec![(21, Some("alice")), (22, None)]);
+        // Replies between comments are quoted; the answer to the post copy is not.
+        assert!(posts[0].comments[0].reply_to.is_none());
+        let quote = posts[0].comments[1].reply_to.as_ref().unwrap();
+        assert_eq!((quote.message_id, quote.text.as_str()), (21, "first!"));
+        assert!(posts[1].comments.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_export_messages_anonymized() {
+        let (sqlite, base_dir) = repo("test_export_messages_anon").await;
+        let messages = vec![
+            message(1, "reach me at bob@example.com", Some(7)),
+            message(2, "my number is +1 (555) 123-4567", Some(8)),
+        ];
+        sqlite.save_messages(100, &messages).await.unwrap();
+        sqlite
+            .save_entity(7, 1, "user", Some("alice"))
+            .await
+            .unwrap();
+
+        let service = ExportService::new(
+            sqlite.clone(),
+            sqlite.clone(),
+            sqlite.clone(),
+            sqlite.clone(),
+            base_dir.join("media"),
+            base_dir.join("exports"),
+            None,
+        );
+        let opts = ExportOptions {
+            anonymize: true,
+            ..Default::default()
+        };
+        let report = service
+            .export_messages(100, ExportFormat::Csv, &opts)
+            .await
+            .unwrap();
+
+        let content = std::fs::read_to_string(&report.path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(
+            lines[1],
+            "1,2024-01-01T00:00:01Z,User-A,User-A,reach me at [email],,,,"
+        );
+        assert_eq!(
+            lines[2],
+            "2,2024-01-01T00:00:02Z,User-B,,my number is [phone],,,,"
+        );
+        assert!(!content.contains("alice"));
+
+        let mapping_path = report.mapping_path.expect("mapping written");
+        assert_eq!(
+            mapping_path,
+            base_dir
+                .join("exports")
+                .join("messages_100.csv.mapping.json")
+        );
+        let mapping = std::fs::read_to_string(mapping_path).unwrap();
+        assert!(mapping.contains("\"id:7\": \"User-A\""));
+        assert!(mapping.contains("\"@alice\": \"User-A\""));
+    }
+}
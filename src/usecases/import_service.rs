@@ -0,0 +1,432 @@
+//! Import a chat history exported by Telegram Desktop into the archive.
+//!
+//! Unlike an ingested fragment, an export keeps Telegram's message ids: its messages go into
+//! the chat they were exported from, as if synced, and messages already stored are left
+//! alone. The chat's sync checkpoint is raised to the newest imported id, so the next sync only
+//! fetches what came after the export. Exported media files can be copied into the media
+//! directory under their canonical names; their references cannot be downloaded again.
+
+use crate::domain::{Chat, DomainError, ImportedHistory, MediaFile, MediaStatus, Message};
+use crate::ports::{HistoryParser, MediaIndexPort, RepoPort, StatePort};
+use crate::usecases::media_layout::media_rel_path;
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Messages per duplicate check and save batch.
+const BATCH_SIZE: usize = 500;
+
+/// Per-import options.
+#[derive(Debug, Clone, Default)]
+pub struct ImportOptions {
+    /// Archive chat to import into, instead of the chat id in the export.
+    pub chat_id: Option<i64>,
+    /// Copy exported media files into the media directory.
+    pub copy_media: bool,
+}
+
+/// Outcome of one import.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportReport {
+    pub chat_id: i64,
+    pub title: String,
+    /// New messages saved.
+    pub imported: u64,
+    /// Messages whose id was already stored (or repeated in the file), not saved again.
+    pub duplicates: u64,
+    /// Service entries stored as chat events.
+    pub events: u64,
+    /// Media files copied into the media directory.
+    pub media_copied: u64,
+    /// Media of imported messages left out of the export, or not found next to it.
+    pub media_missing: u64,
+    /// Sync checkpoint after the import.
+    pub last_message_id: i32,
+    /// Entries that could not be used: (1-based number, reason).
+    pub skipped: Vec<(usize, String)>,
+}
+
+/// Reads Telegram Desktop exports and stores them in the archive.
+pub struct ImportService {
+    repo: Arc<dyn RepoPort>,
+    state: Arc<dyn StatePort>,
+    media_index: Arc<dyn MediaIndexPort>,
+    /// Where media files are copied to; None when the archive encrypts its media (files are
+    /// then never copied).
+    media_dir: Option<PathBuf>,
+    parser: Box<dyn HistoryParser>,
+}
+
+impl ImportService {
+    pub fn new(
+        repo: Arc<dyn RepoPort>,
+        state: Arc<dyn StatePort>,
+        media_index: Arc<dyn MediaIndexPort>,
+        media_dir: Option<PathBuf>,
+        parser: Box<dyn HistoryParser>,
+    ) -> Self {
+        Self {
+            repo,
+            state,
+            media_index,
+            media_dir,
+            parser,
+        }
+    }
+
+    /// Whether exported media files can be copied into the archive.
+    pub fn can_copy_media(&self) -> bool {
+        self.media_dir.is_some()
+    }
+
+    /// Read and parse an export file (`result.json`).
+    pub async fn read_file(&self, path: &Path) -> Result<ImportedHistory, DomainError> {
+        let content = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| DomainError::Ingest(format!("cannot read {}: {}", path.display(), e)))?;
+        self.parser.parse_history(&content)
+    }
+
+    /// Read an export file and import it; media paths are relative to its folder.
+    pub async fn import_file(
+        &self,
+        path: &Path,
+        opts: &ImportOptions,
+    ) -> Result<ImportReport, DomainError> {
+        let history = self.read_file(path).await?;
+        let export_dir = path.parent().unwrap_or(Path::new("."));
+        self.import(history, export_dir, opts).await
+    }
+
+    /// Store `history`, whose media files are relative to `export_dir`.
+    pub async fn import(
+        &self,
+        history: ImportedHistory,
+        export_dir: &Path,
+        opts: &ImportOptions,
+    ) -> Result<ImportReport, DomainError> {
+        let chat_id = opts.chat_id.or(history.chat_id).ok_or_else(|| {
+            DomainError::Ingest("the export has no chat id; choose the chat to import into".into())
+        })?;
+        let copy_to = match (&self.media_dir, opts.copy_media) {
+            (Some(dir), true) => Some(dir.as_path()),
+            _ => None,
+        };
+
+        let mut seen = HashSet::new();
+        let mut duplicates = 0u64;
+        let mut imported = 0u64;
+        let mut media_copied = 0u64;
+        let mut media_missing = 0u64;
+        let mut newest_id = 0;
+
+        self.repo.save_users(&history.users).await?;
+        for batch in history.messages.chunks(BATCH_SIZE) {
+            let ids: Vec<i32> = batch.iter().map(|m| m.message.id).collect();
+            let stored: HashSet<i32> = self
+                .repo
+                .get_messages_by_ids(chat_id, &ids)
+                .await?
+                .iter()
+                .map(|m| m.id)
+                .collect();
+            let mut fresh: Vec<Message> = Vec::new();
+            let mut files: Vec<(usize, &str)> = Vec::new();
+            for entry in batch {
+                newest_id = newest_id.max(entry.message.id);
+                if stored.contains(&entry.message.id) || !seen.insert(entry.message.id) {
+                    duplicates += 1;
+                    continue;
+                }
+                let mut message = entry.message.clone();
+                message.chat_id = chat_id;
+                if let Some(media) = &mut message.media {
+                    media.chat_id = chat_id;
+                    match &entry.media_file {
+                        Some(file) => files.push((fresh.len(), file.as_str())),
+                        None => media_missing += 1,
+                    }
+                }
+                fresh.push(message);
+            }
+            self.repo.save_messages(chat_id, &fresh).await?;
+            imported += fresh.len() as u64;
+
+            let Some(media_dir) = copy_to else {
+                continue;
+            };
+            for (index, file) in files {
+                let message = &fresh[index];
+                match self.copy_media(message, export_dir, file, media_dir).await {
+                    Ok(true) => media_copied += 1,
+                    Ok(false) => media_missing += 1,
+                    Err(e) => {
+                        warn!(chat_id, msg_id = message.id, error = %e, "failed to copy exported media");
+                        media_missing += 1;
+                    }
+                }
+            }
+        }
+
+        self.register_chat(chat_id, &history).await?;
+        let events: Vec<_> = history
+            .events
+            .into_iter()
+            .map(|mut event| {
+                event.chat_id = chat_id;
+                newest_id = newest_id.max(event.id);
+                event
+            })
+            .collect();
+        self.repo.save_events(&events).await?;
+
+        // Only ever raised: a newer checkpoint from an earlier sync stays.
+        let mut last_message_id = self.state.get_last_message_id(chat_id).await?;
+        if newest_id > last_message_id {
+            self.state.set_last_message_id(chat_id, newest_id).await?;
+            last_message_id = newest_id;
+        }
+
+        info!(
+            chat_id,
+            imported,
+            duplicates,
+            events = events.len(),
+            media_copied,
+            skipped = history.skipped.len(),
+            "history imported"
+        );
+        Ok(ImportReport {
+            chat_id,
+            title: history.title,
+            imported,
+            duplicates,
+            events: events.len() as u64,
+            media_copied,
+            media_missing,
+            last_message_id,
+            skipped: history.skipped,
+        })
+    }
+
+    /// Record the chat's title unless the archive already knows the chat (its stored title
+    /// is newer than the export's).
+    async fn register_chat(
+        &self,
+        chat_id: i64,
+        history: &ImportedHistory,
+    ) -> Result<(), DomainError> {
+        let known = self.repo.get_known_chats().await?;
+        if known.iter().any(|c| c.id == chat_id) {
+            return Ok(());
+        }
+        self.repo
+            .upsert_chats(&[Chat {
+                id: chat_id,
+                title: history.title.clone(),
+                username: None,
+                kind: history.kind,
+                approx_message_count: None,
+                is_forum: false,
+                last_message_date: history.messages.iter().map(|m| m.message.date).max(),
+            }])
+            .await
+    }
+
+    /// Copy the exported `file` of `message` to its canonical path in `media_dir` and index
+    /// it. Returns false when the file is not in the export folder.
+    async fn copy_media(
+        &self,
+        message: &Message,
+        export_dir: &Path,
+        file: &str,
+        media_dir: &Path,
+    ) -> Result<bool, DomainError> {
+        let Some(media) = &message.media else {
+            return Ok(false);
+        };
+        // Only plain relative paths: nothing outside the export folder is read.
+        let relative = Path::new(file);
+        if !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            return Ok(false);
+        }
+        let source = export_dir.join(relative);
+        if !tokio::fs::try_exists(&source).await.unwrap_or(false) {
+            return Ok(false);
+        }
+        let rel_path = media_rel_path(media);
+        let dest = media_dir.join(&rel_path);
+        let io_err = |e: std::io::Error| DomainError::Media(format!("{}: {}", dest.display(), e));
+        if !tokio::fs::try_exists(&dest).await.unwrap_or(false) {
+            if let Some(dir) = dest.parent() {
+                tokio::fs::create_dir_all(dir).await.map_err(io_err)?;
+            }
+            // Copied under a temporary name, so an interrupted copy is swept, not indexed.
+            let tmp = media_dir.join(format!("{}.tmp", rel_path));
+            tokio::fs::copy(&source, &tmp).await.map_err(io_err)?;
+            tokio::fs::rename(&tmp, &dest).await.map_err(io_err)?;
+        }
+        let size_bytes = tokio::fs::metadata(&dest).await.ok().map(|m| m.len());
+        self.media_index
+            .upsert_media_file(&MediaFile {
+                chat_id: message.chat_id,
+                message_id: message.id,
+                media_type: media.media_type,
+                rel_path,
+                size_bytes,
+                expected_size: media.expected_size(),
+                sha256: None,
+                status: MediaStatus::Done,
+                message_date: None,
+                remote_key: None,
+            })
+            .await?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::ingest::DesktopJsonParser;
+    use crate::adapters::persistence::sqlite_repo::SqliteRepo;
+    use crate::adapters::persistence::state_json::StateJson;
+    use crate::testing::fake_tg::text_message;
+
+    const CHAT: i64 = -1_000_000_001_234;
+
+    const EXPORT: &str = r#"{
+        "name": "Dev", "type": "private_supergroup", "id": 1234,
+        "messages": [
+            {"id": 3, "type": "message", "date": "2024-01-05T10:00:00", "from": "Alice", "from_id": "user7", "text": "first"},
+            {"id": 4, "type": "message", "date": "2024-01-05T10:01:00", "from": "Bob", "from_id": "user8",
+             "photo": "photos/photo_1.jpg", "text": ""},
+            {"id": 5, "type": "message", "date": "2024-01-05T10:02:00", "from": "Bob", "from_id": "user8",
+             "file": "(File not included. Change data exporting settings to download.)", "text": ""},
+            {"id": 5, "type": "message", "date": "2024-01-05T10:02:00", "from": "Bob", "from_id": "user8", "text": "repeated"},
+            {"id": 6, "type": "service", "date": "2024-01-05T10:03:00", "actor_id": "user7", "action": "pin_message", "message_id": 3}
+        ]
+    }"#;
+
+    struct Setup {
+        dir: PathBuf,
+        repo: Arc<SqliteRepo>,
+        state: Arc<StateJson>,
+        service: ImportService,
+    }
+
+    async fn setup(name: &str) -> Setup {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        let repo = Arc::new(SqliteRepo::connect(dir.join("db")).await.unwrap());
+        let state = Arc::new(StateJson::new(dir.join("state.json")));
+        let service = ImportService::new(
+            repo.clone(),
+            state.clone(),
+            repo.clone(),
+            Some(dir.join("media")),
+            Box::new(DesktopJsonParser::new(0)),
+        );
+        let export_dir = dir.join("export");
+        std::fs::create_dir_all(export_dir.join("photos")).unwrap();
+        std::fs::write(export_dir.join("result.json"), EXPORT).unwrap();
+        std::fs::write(export_dir.join("photos/photo_1.jpg"), b"jpeg").unwrap();
+        Setup {
+            dir,
+            repo,
+            state,
+            service,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_import_saves_history_and_raises_the_checkpoint() {
+        let s = setup("test_import_history").await;
+        // Already synced: kept as is, not counted as imported.
+        s.repo
+            .save_messages(CHAT, &[text_message(CHAT, 3, 1704448800, "synced")])
+            .await
+            .unwrap();
+        let opts = ImportOptions {
+            chat_id: None,
+            copy_media: true,
+        };
+
+        let report = s
+            .service
+            .import_file(&s.dir.join("export/result.json"), &opts)
+            .await
+            .unwrap();
+        assert_eq!(report.chat_id, CHAT);
+        assert_eq!((report.imported, report.duplicates), (2, 2));
+        assert_eq!(
+            (report.events, report.media_copied, report.media_missing),
+            (1, 1, 1)
+        );
+        assert_eq!(report.last_message_id, 6);
+        assert_eq!(s.state.get_last_message_id(CHAT).await.unwrap(), 6);
+
+        let stored = s.repo.get_messages_by_ids(CHAT, &[3, 4, 5]).await.unwrap();
+        let text = |id: i32| stored.iter().find(|m| m.id == id).unwrap().text.clone();
+        assert_eq!(text(3), "synced");
+        assert_eq!(stored.len(), 3);
+        let copied = s.dir.join("media").join(format!("{}/4.jpg", CHAT));
+        assert_eq!(std::fs::read(&copied).unwrap(), b"jpeg");
+        let chats = s.repo.get_known_chats().await.unwrap();
+        assert!(chats.iter().any(|c| c.id == CHAT && c.title == "Dev"));
+
+        // A second import finds everything in place; a newer checkpoint is kept.
+        s.state.set_last_message_id(CHAT, 40).await.unwrap();
+        let again = s
+            .service
+            .import_file(&s.dir.join("export/result.json"), &opts)
+            .await
+            .unwrap();
+        assert_eq!((again.imported, again.duplicates), (0, 4));
+        assert_eq!(again.last_message_id, 40);
+    }
+
+    #[tokio::test]
+    async fn test_import_needs_a_chat_and_stays_in_the_export_folder() {
+        let s = setup("test_import_chat_override").await;
+        let history = s
+            .service
+            .read_file(&s.dir.join("export/result.json"))
+            .await
+            .unwrap();
+        let mut no_id = history.clone();
+        no_id.chat_id = None;
+        let err = s
+            .service
+            .import(no_id, &s.dir.join("export"), &ImportOptions::default())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("no chat id"), "{}", err);
+
+        let mut escaping = history;
+        escaping.messages[1].media_file = Some("../export/photos/photo_1.jpg".into());
+        let report = s
+            .service
+            .import(
+                escaping,
+                &s.dir.join("export"),
+                &ImportOptions {
+                    chat_id: Some(77),
+                    copy_media: true,
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(report.chat_id, 77);
+        assert_eq!((report.imported, report.media_copied), (3, 0));
+        assert!(!s.dir.join("media").join("77/4.jpg").exists());
+        assert_eq!(s.state.get_last_message_id(CHAT).await.unwrap(), 0);
+        assert_eq!(s.state.get_last_message_id(77).await.unwrap(), 6);
+    }
+}
@@ -0,0 +1,331 @@
+//! Ingest chat fragments (files exported by someone else) into the archive.
+//!
+//! Each fragment is folded into a synthetic chat derived from its name, so re-ingesting
+//! under the same name merges into the same chat. Messages get negative ids (real Telegram
+//! ids are positive) and are saved through the normal repository path, so export, search
+//! and analysis treat them like synced chats. Senders become synthetic peers in the entity
+//! registry with their display name as username.
+
+use crate::domain::{Chat, ChatType, DomainError, FragmentMessage, Message, User};
+use crate::ports::{EntityRegistry, FragmentParser, RepoPort};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+use tracing::info;
+
+/// Synthetic chat ids live in `(BASE - SPAN, BASE]`, far below any Telegram peer id.
+const SYNTHETIC_CHAT_BASE: i64 = -9_000_000_000_000_000_000;
+/// Synthetic sender ids live in `(BASE - SPAN, BASE]`.
+const SYNTHETIC_USER_BASE: i64 = -8_000_000_000_000_000_000;
+const SYNTHETIC_SPAN: u64 = 100_000_000_000_000_000;
+
+/// Entity registry peer type of ingested senders.
+pub const SYNTHETIC_PEER_TYPE: &str = "synthetic";
+
+/// Messages per save batch / existing-message page.
+const BATCH_SIZE: usize = 500;
+
+/// Outcome of one ingest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IngestReport {
+    pub chat_id: i64,
+    /// Name of the parser that recognized the file.
+    pub parser: &'static str,
+    /// New messages saved.
+    pub imported: u64,
+    /// Messages already in the chat (same date, sender and text), not saved again.
+    pub duplicates: u64,
+    /// Lines or entries the parser could not use: (1-based number, reason).
+    pub skipped: Vec<(usize, String)>,
+}
+
+/// Stable FNV-1a hash of a case-folded, trimmed name (std's hasher is not stable across releases).
+fn name_hash(name: &str) -> u64 {
+    name.trim()
+        .to_lowercase()
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325u64, |h, b| {
+            (h ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+}
+
+/// Chat id for a fragment chat name. Case-insensitive and stable across runs.
+pub fn synthetic_chat_id(chat_name: &str) -> i64 {
+    SYNTHETIC_CHAT_BASE - (name_hash(chat_name) % SYNTHETIC_SPAN) as i64
+}
+
+/// Peer id for a sender display name. Case-insensitive and stable across runs.
+pub fn synthetic_user_id(sender: &str) -> i64 {
+    SYNTHETIC_USER_BASE - (name_hash(sender) % SYNTHETIC_SPAN) as i64
+}
+
+/// Detects the fragment format and saves the messages under a synthetic chat.
+pub struct IngestService {
+    repo: Arc<dyn RepoPort>,
+    registry: Arc<dyn EntityRegistry>,
+    parsers: Vec<Box<dyn FragmentParser>>,
+}
+
+impl IngestService {
+    /// `parsers` are tried in order; see `adapters::ingest::default_parsers`.
+    pub fn new(
+        repo: Arc<dyn RepoPort>,
+        registry: Arc<dyn EntityRegistry>,
+        parsers: Vec<Box<dyn FragmentParser>>,
+    ) -> Self {
+        Self {
+            repo,
+            registry,
+            parsers,
+        }
+    }
+
+    /// Read and ingest a fragment file.
+    pub async fn ingest_file(
+        &self,
+        path: &Path,
+        chat_name: &str,
+    ) -> Result<IngestReport, DomainError> {
+        let content = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| DomainError::Ingest(format!("cannot read {}: {}", path.display(), e)))?;
+        self.ingest(&content, chat_name).await
+    }
+
+    /// Parse `content` and save its messages into the chat named `chat_name`.
+    pub async fn ingest(
+        &self,
+        content: &str,
+        chat_name: &str,
+    ) -> Result<IngestReport, DomainError> {
+        let chat_name = chat_name.trim();
+        if chat_name.is_empty() {
+            return Err(DomainError::Ingest("chat name must not be empty".into()));
+        }
+        let parser = self
+            .parsers
+            .iter()
+            .find(|p| p.detect(content))
+            .ok_or_else(|| {
+                DomainError::Ingest(
+                    "unrecognized format (expected a Telegram Desktop JSON export or \"[date] Name: text\" lines)"
+                        .into(),
+                )
+            })?;
+        let parsed = parser.parse(content)?;
+        let chat_id = synthetic_chat_id(chat_name);
+
+        let (mut seen, min_id) = self.existing_messages(chat_id).await?;
+        let mut fresh: Vec<FragmentMessage> = Vec::new();
+        let mut duplicates = 0u64;
+        for msg in parsed.messages {
+            let from = msg.sender.as_deref().map(synthetic_user_id);
+            if seen.insert((msg.date, from, msg.text.clone())) {
+                fresh.push(msg);
+            } else {
+                duplicates += 1;
+            }
+        }
+        // Oldest first so ids ascend with time within this fragment.
+        fresh.sort_by_key(|m| m.date);
+
+        let first_id = i64::from(min_id.min(0)) - fresh.len() as i64;
+        if first_id <= i64::from(i32::MIN) {
+            return Err(DomainError::Ingest(format!(
+                "chat {} has no synthetic message ids left",
+                chat_id
+            )));
+        }
+
+        let mut senders: HashMap<i64, &str> = HashMap::new();
+        let messages: Vec<Message> = fresh
+            .iter()
+            .enumerate()
+            .map(|(i, m)| {
+                let from_user_id = m.sender.as_deref().map(|name| {
+                    let id = synthetic_user_id(name);
+                    senders.entry(id).or_insert(name);
+                    id
+                });
+                Message {
+                    id: (first_id + i as i64) as i32,
+                    chat_id,
+                    date: m.date,
+                    text: m.text.clone(),
+                    media: None,
+                    from_user_id,
+                    reply_to_msg_id: None,
+                    edit_history: None,
+                    sender_name: None,
+                    topic_id: None,
+                    topic_title: None,
+                    forwarded_from_name: None,
+                    forwarded_from_id: None,
+                    forwarded_date: None,
+                    poll: None,
+                    linked_channel_post: None,
+                    grouped_id: None,
+                    mentioned: false,
+                    mentioned_user_ids: Vec::new(),
+                }
+            })
+            .collect();
+
+        for (&id, name) in &senders {
+            self.registry
+                .save_entity(id, 0, SYNTHETIC_PEER_TYPE, Some(name))
+                .await?;
+        }
+        let users: Vec<User> = senders
+            .into_iter()
+            .map(|(id, name)| User {
+                id,
+                first_name: Some(name.to_string()),
+                last_name: None,
+                username: None,
+            })
+            .collect();
+        self.repo.save_users(&users).await?;
+        for batch in messages.chunks(BATCH_SIZE) {
+            self.repo.save_messages(chat_id, batch).await?;
+        }
+        self.repo
+            .register_synthetic_chat(&Chat {
+                id: chat_id,
+                title: chat_name.to_string(),
+                username: None,
+                kind: ChatType::Group,
+                approx_message_count: None,
+                is_forum: false,
+                last_message_date: None,
+            })
+            .await?;
+
+        info!(
+            chat_id,
+            parser = parser.name(),
+            imported = messages.len(),
+            duplicates,
+            skipped = parsed.skipped.len(),
+            "fragment ingested"
+        );
+        Ok(IngestReport {
+            chat_id,
+            parser: parser.name(),
+            imported: messages.len() as u64,
+            duplicates,
+            skipped: parsed.skipped,
+        })
+    }
+
+    /// Dedup keys (date, sender id, text) of the chat's stored messages and its lowest id.
+    async fn existing_messages(
+        &self,
+        chat_id: i64,
+    ) -> Result<(HashSet<(i64, Option<i64>, String)>, i32), DomainError> {
+        let mut seen = HashSet::new();
+        let mut min_id = 0;
+        let mut after_id = i32::MIN;
+        loop {
+            let page = self
+                .repo
+                .get_messages_after(chat_id, after_id, BATCH_SIZE as u32)
+                .await?;
+            let Some(last) = page.last() else {
+                break;
+            };
+            after_id = last.id;
+            for m in page {
+                min_id = min_id.min(m.id);
+                seen.insert((m.date, m.from_user_id, m.text));
+            }
+        }
+        Ok((seen, min_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::ingest::default_parsers;
+    use crate::adapters::persistence::sqlite_repo::SqliteRepo;
+
+    async fn service(name: &str) -> (Arc<SqliteRepo>, IngestService) {
+        let base = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join(name);
+        let _ = std::fs::remove_dir_all(&base);
+        let repo = Arc::new(SqliteRepo::connect(&base).await.unwrap());
+        let service = IngestService::new(repo.clone(), repo.clone(), default_parsers(0));
+        (repo, service)
+    }
+
+    #[test]
+    fn test_synthetic_ids_are_stable_and_out_of_range() {
+        assert_eq!(
+            synthetic_chat_id("Project X"),
+            synthetic_chat_id(" project x ")
+        );
+        assert_ne!(
+            synthetic_chat_id("Project X"),
+            synthetic_chat_id("Project Y")
+        );
+        assert!(synthetic_chat_id("Project X") < -8_999_000_000_000_000_000);
+        let alice = synthetic_user_id("Alice");
+        assert!(
+            alice <= SYNTHETIC_USER_BASE && alice > SYNTHETIC_USER_BASE - SYNTHETIC_SPAN as i64
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ingest_saves_and_deduplicates() {
+        let (repo, service) = service("test_ingest_fragments").await;
+        let log = "[2024-01-05 14:04] Bob: second\n[2024-01-05 14:03] Alice: first\n[bad] x: y\n";
+
+        let report = service.ingest(log, "Project X").await.unwrap();
+        let chat_id = synthetic_chat_id("Project X");
+        assert_eq!(report.chat_id, chat_id);
+        assert_eq!(report.parser, "plain-text");
+        assert_eq!((report.imported, report.duplicates), (2, 0));
+        assert_eq!(
+            report.skipped,
+            vec![(3, "unrecognized date 'bad'".to_string())]
+        );
+
+        let stored = repo
+            .get_messages_after(chat_id, i32::MIN, 10)
+            .await
+            .unwrap();
+        let got: Vec<(i32, &str)> = stored.iter().map(|m| (m.id, m.text.as_str())).collect();
+        assert_eq!(got, vec![(-2, "first"), (-1, "second")]);
+        assert_eq!(
+            repo.get_username(synthetic_user_id("alice")).await.unwrap(),
+            Some("Alice".to_string())
+        );
+
+        // Same lines again plus one new: only the new one is saved, below the existing ids.
+        let more = format!("{}[2024-01-05 14:05] Alice: third\n", log);
+        let report = service.ingest(&more, "project x").await.unwrap();
+        assert_eq!((report.imported, report.duplicates), (1, 2));
+        let stored = repo
+            .get_messages_after(chat_id, i32::MIN, 10)
+            .await
+            .unwrap();
+        assert_eq!(stored.len(), 3);
+        assert_eq!(stored[0].id, -3);
+        assert_eq!(stored[0].text, "third");
+    }
+
+    #[tokio::test]
+    async fn test_ingest_rejects_unknown_format() {
+        let (_, service) = service("test_ingest_unknown").await;
+        let err = service.ingest("just notes", "X").await.unwrap_err();
+        assert!(err.to_string().contains("unrecognized format"));
+        assert!(
+            service
+                .ingest("[2024-01-05 14:03] A: b", " ")
+                .await
+                .is_err()
+        );
+    }
+}
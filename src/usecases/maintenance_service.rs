@@ -0,0 +1,586 @@
+//! Archive maintenance: database integrity check, WAL checkpoint and VACUUM, plus orphaned
+//! media files.
+//!
+//! A file in the media directory is an orphan when neither the media index nor a stored
+//! message refers to it (e.g. after a chat was purged). Orphans are only listed by
+//! [`MaintenanceService::run`]; [`MaintenanceService::delete_orphans`] removes them after the
+//! caller confirmed. Both refuse to run while a sync or the watcher is active (see
+//! [`ActivityFlag`]) and keep them from starting until done.
+//!
+//! [`MaintenanceService::verify_media`] compares every downloaded file with the size
+//! Telegram reported (or the recorded size for older entries) and, if asked, with its
+//! recorded SHA-256; [`MaintenanceService::requeue_corrupt`] deletes the damaged files and
+//! queues them for download again.
+
+use crate::domain::{DatabaseMaintenance, DomainError, MediaFile, MediaReference, MediaStatus};
+use crate::ports::{ArchiveAuditPort, MediaIndexPort, MediaQueuePort};
+use crate::shared::activity_flag::ActivityFlag;
+use crate::shared::crypto::{ENCRYPTED_EXTENSION, sealed_file_len};
+use crate::usecases::media_layout::media_rel_path;
+use crate::usecases::media_manifest::MANIFEST_FILE;
+use crate::usecases::media_worker::file_sha256;
+use std::collections::HashSet;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Rows fetched per page when walking the media index.
+const PAGE_SIZE: u32 = 500;
+
+/// Orphans listed by name in the report; the rest are only counted.
+const MAX_LISTED: usize = 10;
+
+/// Name of the maintenance task in the activity flag.
+const TASK: &str = "maintenance";
+
+/// A file in the media directory that nothing refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrphanMedia {
+    /// Path relative to the media directory.
+    pub rel_path: String,
+    pub size_bytes: u64,
+}
+
+/// Result of one maintenance run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MaintenanceReport {
+    pub database: DatabaseMaintenance,
+    /// Files examined in the media directory.
+    pub media_files: u64,
+    pub orphans: Vec<OrphanMedia>,
+}
+
+impl MaintenanceReport {
+    pub fn orphan_bytes(&self) -> u64 {
+        self.orphans.iter().map(|o| o.size_bytes).sum()
+    }
+}
+
+impl fmt::Display for MaintenanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let db = &self.database;
+        if db.integrity_errors.is_empty() {
+            writeln!(f, "Integrity check: ok")?;
+        } else {
+            writeln!(
+                f,
+                "Integrity check: {} problem(s); VACUUM skipped",
+                db.integrity_errors.len()
+            )?;
+            for error in db.integrity_errors.iter().take(MAX_LISTED) {
+                writeln!(f, "  {}", error)?;
+            }
+        }
+        writeln!(
+            f,
+            "Database: {} -> {} bytes ({} reclaimed)",
+            db.size_before,
+            db.size_after,
+            db.reclaimed_bytes()
+        )?;
+        write!(
+            f,
+            "Media: {} file(s) checked, {} orphan(s), {} bytes",
+            self.media_files,
+            self.orphans.len(),
+            self.orphan_bytes()
+        )?;
+        for orphan in self.orphans.iter().take(MAX_LISTED) {
+            write!(f, "\n  {} ({} bytes)", orphan.rel_path, orphan.size_bytes)?;
+        }
+        if self.orphans.len() > MAX_LISTED {
+            write!(f, "\n  ... and {} more", self.orphans.len() - MAX_LISTED)?;
+        }
+        Ok(())
+    }
+}
+
+/// A downloaded file that is not what Telegram sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorruptMedia {
+    /// Its media index entry.
+    pub file: MediaFile,
+    /// What is wrong, for the report.
+    pub problem: String,
+}
+
+/// Result of [`MaintenanceService::verify_media`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MediaVerifyReport {
+    /// Downloaded files examined.
+    pub checked: u64,
+    /// Of those, files whose SHA-256 was compared too.
+    pub hashed: u64,
+    /// Entries only kept in remote storage, not checked.
+    pub remote_only: u64,
+    pub corrupt: Vec<CorruptMedia>,
+}
+
+impl fmt::Display for MediaVerifyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Media: {} file(s) verified ({} by checksum), {} damaged or incomplete",
+            self.checked,
+            self.hashed,
+            self.corrupt.len()
+        )?;
+        if self.remote_only > 0 {
+            write!(f, "; {} kept in remote storage only", self.remote_only)?;
+        }
+        for corrupt in self.corrupt.iter().take(MAX_LISTED) {
+            write!(f, "\n  {}: {}", corrupt.file.rel_path, corrupt.problem)?;
+        }
+        if self.corrupt.len() > MAX_LISTED {
+            write!(f, "\n  ... and {} more", self.corrupt.len() - MAX_LISTED)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs database maintenance, the orphan media scan and media verification.
+pub struct MaintenanceService {
+    audit: Arc<dyn ArchiveAuditPort>,
+    index: Arc<dyn MediaIndexPort>,
+    queue: Arc<dyn MediaQueuePort>,
+    activity: ActivityFlag,
+    media_dir: PathBuf,
+}
+
+impl MaintenanceService {
+    pub fn new(
+        audit: Arc<dyn ArchiveAuditPort>,
+        index: Arc<dyn MediaIndexPort>,
+        queue: Arc<dyn MediaQueuePort>,
+        activity: ActivityFlag,
+        media_dir: PathBuf,
+    ) -> Self {
+        Self {
+            audit,
+            index,
+            queue,
+            activity,
+            media_dir,
+        }
+    }
+
+    /// Integrity check, WAL checkpoint and VACUUM, then list orphaned media files.
+    /// Fails when a sync or the watcher is running.
+    pub async fn run(&self) -> Result<MaintenanceReport, DomainError> {
+        let _exclusive = self.activity.exclusive(TASK)?;
+        let database = self.audit.maintenance().await?;
+        let (media_files, orphans) = self.find_orphans().await?;
+        let report = MaintenanceReport {
+            database,
+            media_files,
+            orphans,
+        };
+        info!(
+            reclaimed = report.database.reclaimed_bytes(),
+            orphans = report.orphans.len(),
+            orphan_bytes = report.orphan_bytes(),
+            "maintenance finished"
+        );
+        Ok(report)
+    }
+
+    /// Delete `orphans` (from [`Self::run`]) that are still unreferenced. Returns the files
+    /// and bytes removed.
+    pub async fn delete_orphans(&self, orphans: &[OrphanMedia]) -> Result<(u64, u64), DomainError> {
+        let _exclusive = self.activity.exclusive(TASK)?;
+        // A sync between the scan and the confirmation may have claimed a file again.
+        let referenced = self.referenced_paths().await?;
+        let (mut files, mut bytes) = (0, 0);
+        for orphan in orphans {
+            if referenced.contains(&orphan.rel_path) {
+                continue;
+            }
+            let path = self.media_dir.join(&orphan.rel_path);
+            match tokio::fs::remove_file(&path).await {
+                Ok(()) => {
+                    files += 1;
+                    bytes += orphan.size_bytes;
+                }
+                Err(e) => {
+                    warn!(path = %path.display(), error = %e, "failed to delete orphaned media")
+                }
+            }
+        }
+        info!(files, bytes, "orphaned media deleted");
+        Ok((files, bytes))
+    }
+
+    /// Check every downloaded file against the size Telegram reported, or the size recorded
+    /// at download for entries from before sizes were kept. With `hashes`, files with a
+    /// recorded SHA-256 are also read and compared. Fails when a sync or the watcher is
+    /// running.
+    pub async fn verify_media(&self, hashes: bool) -> Result<MediaVerifyReport, DomainError> {
+        let _exclusive = self.activity.exclusive(TASK)?;
+        let mut report = MediaVerifyReport::default();
+        let mut after = None;
+        loop {
+            let page = self
+                .index
+                .list_media_files(None, Some(MediaStatus::Done), after, PAGE_SIZE)
+                .await?;
+            let Some(last) = page.last() else {
+                break;
+            };
+            after = Some((last.chat_id, last.message_id));
+            let full = page.len() as u32 == PAGE_SIZE;
+            for file in page {
+                let path = self.media_dir.join(&file.rel_path);
+                let len = match tokio::fs::metadata(&path).await {
+                    Ok(meta) => meta.len(),
+                    Err(_) if file.remote_key.is_some() => {
+                        report.remote_only += 1;
+                        continue;
+                    }
+                    Err(_) => {
+                        report.checked += 1;
+                        report.corrupt.push(CorruptMedia {
+                            file,
+                            problem: "missing".to_string(),
+                        });
+                        continue;
+                    }
+                };
+                report.checked += 1;
+                let encrypted = file
+                    .rel_path
+                    .ends_with(&format!(".{}", ENCRYPTED_EXTENSION));
+                let expected = match file.expected_size {
+                    Some(plain) if encrypted => Some(sealed_file_len(plain)),
+                    Some(plain) => Some(plain),
+                    None => file.size_bytes,
+                };
+                let mut problem = expected
+                    .filter(|&expected| expected != len)
+                    .map(|expected| format!("{} bytes, expected {}", len, expected));
+                let hash_check = problem.is_none() && hashes && !encrypted;
+                if let Some(recorded) = file.sha256.as_deref().filter(|_| hash_check) {
+                    report.hashed += 1;
+                    if !file_sha256(&path).await?.eq_ignore_ascii_case(recorded) {
+                        problem = Some("checksum mismatch".to_string());
+                    }
+                }
+                if let Some(problem) = problem {
+                    report.corrupt.push(CorruptMedia { file, problem });
+                }
+            }
+            if !full {
+                break;
+            }
+        }
+        info!(
+            checked = report.checked,
+            corrupt = report.corrupt.len(),
+            "media verified"
+        );
+        Ok(report)
+    }
+
+    /// Delete the files of `corrupt` (from [`Self::verify_media`]), mark their index entries
+    /// pending and queue them for download. Returns the files queued.
+    pub async fn requeue_corrupt(&self, corrupt: &[CorruptMedia]) -> Result<u64, DomainError> {
+        let _exclusive = self.activity.exclusive(TASK)?;
+        let mut refs = Vec::with_capacity(corrupt.len());
+        for CorruptMedia { file, .. } in corrupt {
+            let path = self.media_dir.join(&file.rel_path);
+            if let Err(e) = tokio::fs::remove_file(&path).await {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    warn!(path = %path.display(), error = %e, "failed to delete damaged media");
+                    continue;
+                }
+            }
+            self.index
+                .upsert_media_file(&MediaFile {
+                    size_bytes: None,
+                    status: MediaStatus::Pending,
+                    ..file.clone()
+                })
+                .await?;
+            // The queue reads the reference itself from the stored message.
+            refs.push(MediaReference {
+                message_id: file.message_id,
+                chat_id: file.chat_id,
+                media_type: file.media_type,
+                opaque_ref: String::new(),
+                size_bytes: None,
+                filename: None,
+                mime_type: None,
+            });
+        }
+        let queued = self.queue.requeue_media(&refs).await?;
+        info!(queued, "damaged media queued for download");
+        Ok(queued)
+    }
+
+    /// Files in the media directory and its chat folders, and those of them nothing refers
+    /// to, by relative path.
+    async fn find_orphans(&self) -> Result<(u64, Vec<OrphanMedia>), DomainError> {
+        let referenced = self.referenced_paths().await?;
+        let mut checked = 0;
+        let mut orphans = Vec::new();
+        // (directory, its path relative to the media directory); chat folders are one deep.
+        let mut dirs = vec![(self.media_dir.clone(), None::<String>)];
+        while let Some((dir, prefix)) = dirs.pop() {
+            let mut entries = match tokio::fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    return Err(DomainError::Media(format!("read {}: {}", dir.display(), e)));
+                }
+            };
+            while let Some(entry) = entries
+                .next_entry()
+                .await
+                .map_err(|e| DomainError::Media(e.to_string()))?
+            {
+                let Ok(meta) = entry.metadata().await else {
+                    continue;
+                };
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if meta.is_dir() && prefix.is_none() {
+                    dirs.push((entry.path(), Some(name)));
+                    continue;
+                }
+                // The manifest is ours; partial files belong to the startup recovery sweep.
+                if !meta.is_file()
+                    || (prefix.is_none() && name == MANIFEST_FILE)
+                    || name.ends_with(".part")
+                    || name.ends_with(".tmp")
+                {
+                    continue;
+                }
+                checked += 1;
+                let rel_path = match &prefix {
+                    Some(prefix) => format!("{}/{}", prefix, name),
+                    None => name,
+                };
+                if !referenced.contains(&rel_path) {
+                    orphans.push(OrphanMedia {
+                        rel_path,
+                        size_bytes: meta.len(),
+                    });
+                }
+            }
+        }
+        orphans.sort_by(|a, b| a.rel_path.cmp(&b.rel_path));
+        Ok((checked, orphans))
+    }
+
+    /// Paths (relative to the media directory) of every media index row, any status, and
+    /// the file names of stored media references that have no index row yet.
+    async fn referenced_paths(&self) -> Result<HashSet<String>, DomainError> {
+        let mut paths = HashSet::new();
+        let mut after = None;
+        loop {
+            let page = self
+                .index
+                .list_media_files(None, None, after, PAGE_SIZE)
+                .await?;
+            let Some(last) = page.last() else {
+                break;
+            };
+            after = Some((last.chat_id, last.message_id));
+            paths.extend(page.into_iter().map(|f| f.rel_path));
+        }
+        let mut after = None;
+        loop {
+            let page = self.audit.find_unindexed_media(after, PAGE_SIZE).await?;
+            let Some(last) = page.last() else {
+                break;
+            };
+            after = Some((last.chat_id, last.message_id));
+            for media_ref in &page {
+                let rel_path = media_rel_path(media_ref);
+                paths.insert(format!("{}.{}", rel_path, ENCRYPTED_EXTENSION));
+                paths.insert(rel_path);
+            }
+        }
+        Ok(paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::persistence::sqlite_repo::SqliteRepo;
+    use crate::domain::{MediaFile, MediaReference, MediaStatus, MediaType, Message};
+    use crate::ports::RepoPort;
+    use crate::testing::fake_tg::text_message;
+    use std::path::Path;
+
+    fn message(id: i32, media: bool) -> Message {
+        Message {
+            media: media.then(|| MediaReference {
+                message_id: id,
+                chat_id: 1,
+                media_type: MediaType::Photo,
+                opaque_ref: "ref".to_string(),
+                size_bytes: None,
+                filename: None,
+                mime_type: None,
+            }),
+            ..text_message(1, id, 1704067200 + id as i64, &format!("m{}", id))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_lists_and_deletes_orphans_only() {
+        let base = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join("test_maintenance");
+        let _ = std::fs::remove_dir_all(&base);
+        let repo = Arc::new(SqliteRepo::connect(&base).await.unwrap());
+        let media_dir = base.join("media");
+        std::fs::create_dir_all(media_dir.join("1")).unwrap();
+        // 1: indexed file, 2: referenced by a message without index row, 3 and a stray flat
+        // file: orphans.
+        repo.save_messages(1, &[message(1, true), message(2, true)])
+            .await
+            .unwrap();
+        repo.upsert_media_file(&MediaFile {
+            chat_id: 1,
+            message_id: 1,
+            media_type: MediaType::Photo,
+            rel_path: "1/1.jpg".to_string(),
+            size_bytes: Some(3),
+            expected_size: None,
+            sha256: None,
+            status: MediaStatus::Done,
+            message_date: None,
+            remote_key: None,
+        })
+        .await
+        .unwrap();
+        for name in [
+            "1/1.jpg",
+            "1/2.jpg",
+            "1/3.jpg",
+            "1_3.jpg",
+            MANIFEST_FILE,
+            "1/4.jpg.part",
+        ] {
+            std::fs::write(media_dir.join(name), b"abc").unwrap();
+        }
+        let activity = ActivityFlag::new();
+        let service = MaintenanceService::new(
+            repo.clone(),
+            repo.clone(),
+            repo.clone(),
+            activity.clone(),
+            media_dir.clone(),
+        );
+
+        let sync = activity.begin("sync").unwrap();
+        assert!(matches!(service.run().await, Err(DomainError::State(_))));
+        drop(sync);
+
+        let report = service.run().await.unwrap();
+        assert!(report.database.integrity_errors.is_empty());
+        assert!(report.database.vacuumed);
+        assert!(report.database.size_after > 0);
+        assert_eq!(report.media_files, 4);
+        let orphan = |rel_path: &str| OrphanMedia {
+            rel_path: rel_path.to_string(),
+            size_bytes: 3,
+        };
+        assert_eq!(report.orphans, vec![orphan("1/3.jpg"), orphan("1_3.jpg")]);
+        assert!(report.to_string().contains("2 orphan(s), 6 bytes"));
+
+        assert_eq!(
+            service.delete_orphans(&report.orphans).await.unwrap(),
+            (2, 6)
+        );
+        assert!(!media_dir.join("1/3.jpg").exists());
+        assert!(!media_dir.join("1_3.jpg").exists());
+        assert!(media_dir.join("1/1.jpg").exists());
+        assert!(media_dir.join("1/2.jpg").exists());
+        assert!(activity.begin("sync").is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_verify_media_requeues_damaged_files() {
+        let base = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join("test_maintenance_verify");
+        let _ = std::fs::remove_dir_all(&base);
+        let repo = Arc::new(SqliteRepo::connect(&base).await.unwrap());
+        let media_dir = base.join("media");
+        std::fs::create_dir_all(&media_dir).unwrap();
+        // 1: complete, 2: truncated, 3: same size but other content, 4: missing.
+        let abc_sha256 = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        for (id, expected_size, sha256) in [
+            (1, Some(3), Some(abc_sha256)),
+            (2, Some(5), None),
+            (3, None, Some("00")),
+            (4, Some(3), None),
+        ] {
+            repo.upsert_media_file(&MediaFile {
+                chat_id: 1,
+                message_id: id,
+                media_type: MediaType::Photo,
+                rel_path: format!("1_{}.jpg", id),
+                size_bytes: Some(3),
+                expected_size,
+                sha256: sha256.map(str::to_string),
+                status: MediaStatus::Done,
+                message_date: None,
+                remote_key: None,
+            })
+            .await
+            .unwrap();
+            if id != 4 {
+                std::fs::write(media_dir.join(format!("1_{}.jpg", id)), b"abc").unwrap();
+            }
+        }
+        let service = MaintenanceService::new(
+            repo.clone(),
+            repo.clone(),
+            repo.clone(),
+            ActivityFlag::new(),
+            media_dir.clone(),
+        );
+
+        let corrupt = |report: &MediaVerifyReport| {
+            report
+                .corrupt
+                .iter()
+                .map(|c| (c.file.message_id, c.problem.clone()))
+                .collect::<Vec<_>>()
+        };
+        let report = service.verify_media(false).await.unwrap();
+        assert_eq!((report.checked, report.hashed), (4, 0));
+        assert_eq!(
+            corrupt(&report),
+            vec![
+                (2, "3 bytes, expected 5".to_string()),
+                (4, "missing".to_string())
+            ]
+        );
+
+        let report = service.verify_media(true).await.unwrap();
+        assert_eq!(report.hashed, 2);
+        assert_eq!(
+            corrupt(&report).iter().map(|c| c.0).collect::<Vec<_>>(),
+            vec![2, 3, 4]
+        );
+        assert!(report.to_string().contains("1_3.jpg: checksum mismatch"));
+
+        assert_eq!(service.requeue_corrupt(&report.corrupt).await.unwrap(), 3);
+        assert!(media_dir.join("1_1.jpg").exists());
+        assert!(!media_dir.join("1_2.jpg").exists());
+        assert!(!media_dir.join("1_3.jpg").exists());
+        let pending = repo
+            .list_media_files(None, Some(MediaStatus::Pending), None, 10)
+            .await
+            .unwrap();
+        assert_eq!(
+            pending.iter().map(|f| f.message_id).collect::<Vec<_>>(),
+            vec![2, 3, 4]
+        );
+    }
+}
@@ -0,0 +1,404 @@
+//! Where downloaded media live inside the media directory.
+//!
+//! Each chat has its own folder: `{chat_id}/{message_id}_{original name}` for documents sent
+//! with a file name, `{chat_id}/{message_id}.{ext}` for everything else. The message id
+//! prefix keeps names unique within a chat. Stored paths (the media index) are what
+//! exports, uploads and maintenance use; nothing rebuilds a path from this scheme except
+//! for files that were never indexed.
+//!
+//! Archives from before this layout kept every file flat as `{chat_id}_{message_id}.{ext}`.
+//! [`migrate_flat_layout`] moves those into the chat folders once and updates the index.
+
+use crate::domain::{DomainError, MediaFile, MediaReference, MediaType};
+use crate::ports::MediaIndexPort;
+use crate::shared::crypto::ENCRYPTED_EXTENSION;
+use crate::shared::paths::{sanitize_component, truncate_chars_preserving_extension};
+use crate::usecases::media_manifest::MANIFEST_FILE;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Longest file name kept for a media file, in characters. Leaves room for the data
+/// directory within the Windows path limit.
+const MAX_NAME_CHARS: usize = 120;
+
+/// Rows fetched per page when walking the media index.
+const PAGE_SIZE: u32 = 500;
+
+/// Path of the media file of `media_ref` relative to the media directory, with `/` as the
+/// separator. The extension comes from the original name, else the MIME type, else the
+/// media type.
+pub(crate) fn media_rel_path(media_ref: &MediaReference) -> String {
+    let ext = media_ref
+        .mime_type
+        .as_deref()
+        .and_then(extension_for_mime)
+        .unwrap_or_else(|| extension_for_media_type(media_ref.media_type));
+    let original = media_ref
+        .filename
+        .as_deref()
+        .filter(|name| !name.trim().is_empty())
+        .map(sanitize_component);
+    let name = match original {
+        Some(original) if Path::new(&original).extension().is_some() => {
+            format!("{}_{}", media_ref.message_id, original)
+        }
+        Some(original) => format!("{}_{}.{}", media_ref.message_id, original, ext),
+        None => format!("{}.{}", media_ref.message_id, ext),
+    };
+    format!(
+        "{}/{}",
+        media_ref.chat_id,
+        truncate_chars_preserving_extension(&name, MAX_NAME_CHARS)
+    )
+}
+
+/// Flat file name the media of `media_ref` had before per-chat folders.
+fn legacy_file_name(media_ref: &MediaReference) -> String {
+    format!(
+        "{}_{}.{}",
+        media_ref.chat_id,
+        media_ref.message_id,
+        extension_for_media_type(media_ref.media_type)
+    )
+}
+
+/// `path` itself when free, else the first free `{stem}-{n}.{ext}`.
+async fn unique_path(path: &Path) -> PathBuf {
+    if !tokio::fs::try_exists(path).await.unwrap_or(false) {
+        return path.to_path_buf();
+    }
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let ext = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    let mut n = 1;
+    loop {
+        let candidate = path.with_file_name(format!("{}-{}{}", stem, n, ext));
+        if !tokio::fs::try_exists(&candidate).await.unwrap_or(false) {
+            return candidate;
+        }
+        n += 1;
+    }
+}
+
+/// Move the flat files of an archive from before per-chat folders into them and point
+/// their index entries at the new paths. A name already taken in the chat folder gets a
+/// numeric suffix. Files nothing refers to stay where they are (maintenance lists them as
+/// orphans). Returns the files moved; cheap once no flat media file is left.
+pub async fn migrate_flat_layout(
+    index: &dyn MediaIndexPort,
+    media_dir: &Path,
+) -> Result<u64, DomainError> {
+    let flat = flat_files(media_dir).await?;
+    if flat.is_empty() {
+        return Ok(0);
+    }
+    // Chats with flat index entries or flat files named after them.
+    let mut chats: BTreeSet<i64> = flat
+        .iter()
+        .filter_map(|name| name.split_once('_')?.0.parse().ok())
+        .collect();
+    let mut after = None;
+    loop {
+        let page = index.list_media_files(None, None, after, PAGE_SIZE).await?;
+        let Some(last) = page.last() else {
+            break;
+        };
+        after = Some((last.chat_id, last.message_id));
+        chats.extend(
+            page.iter()
+                .filter(|f| !f.rel_path.contains('/'))
+                .map(|f| f.chat_id),
+        );
+    }
+
+    let mut moved = 0;
+    for chat_id in chats {
+        let mut after = None;
+        loop {
+            let page = index.list_chat_media(chat_id, after, PAGE_SIZE).await?;
+            let Some((last, _)) = page.last() else {
+                break;
+            };
+            after = Some(last.message_id);
+            for (media_ref, file) in page {
+                let old = match &file {
+                    Some(file) if file.rel_path.contains('/') => continue,
+                    Some(file) => file.rel_path.clone(),
+                    None => {
+                        let legacy = legacy_file_name(&media_ref);
+                        let sealed = format!("{}.{}", legacy, ENCRYPTED_EXTENSION);
+                        match (flat.contains(&legacy), flat.contains(&sealed)) {
+                            (true, _) => legacy,
+                            (false, true) => sealed,
+                            (false, false) => continue,
+                        }
+                    }
+                };
+                let mut new = media_rel_path(&media_ref);
+                if old.ends_with(&format!(".{}", ENCRYPTED_EXTENSION)) {
+                    new = format!("{}.{}", new, ENCRYPTED_EXTENSION);
+                }
+                if flat.contains(&old) {
+                    let target = unique_path(&media_dir.join(&new)).await;
+                    if let Err(e) = move_file(&media_dir.join(&old), &target).await {
+                        warn!(file = %old, error = %e, "failed to move media file");
+                        continue;
+                    }
+                    new = format!(
+                        "{}/{}",
+                        chat_id,
+                        target.file_name().unwrap_or_default().to_string_lossy()
+                    );
+                    moved += 1;
+                }
+                // Entries whose file is gone move too: a new download uses the new path.
+                if let Some(file) = file {
+                    index
+                        .upsert_media_file(&MediaFile {
+                            rel_path: new,
+                            ..file
+                        })
+                        .await?;
+                }
+            }
+        }
+    }
+    info!(moved, "media moved into per-chat folders");
+    Ok(moved)
+}
+
+/// Names of the files directly in `media_dir`, leaving out the manifest and partial files.
+async fn flat_files(media_dir: &Path) -> Result<BTreeSet<String>, DomainError> {
+    let mut entries = match tokio::fs::read_dir(media_dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeSet::new()),
+        Err(e) => {
+            return Err(DomainError::Media(format!(
+                "read {}: {}",
+                media_dir.display(),
+                e
+            )));
+        }
+    };
+    let mut names = BTreeSet::new();
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .map_err(|e| DomainError::Media(e.to_string()))?
+    {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type().await.is_ok_and(|t| t.is_file())
+            && name != MANIFEST_FILE
+            && !name.ends_with(".part")
+            && !name.ends_with(".tmp")
+        {
+            names.insert(name);
+        }
+    }
+    Ok(names)
+}
+
+async fn move_file(from: &Path, to: &Path) -> Result<(), DomainError> {
+    let io_err = |e: std::io::Error| DomainError::Media(format!("{}: {}", to.display(), e));
+    if let Some(parent) = to.parent() {
+        tokio::fs::create_dir_all(parent).await.map_err(io_err)?;
+    }
+    tokio::fs::rename(from, to).await.map_err(io_err)
+}
+
+/// File extension for a MIME type Telegram commonly reports.
+fn extension_for_mime(mime: &str) -> Option<&'static str> {
+    let mime = mime.split(';').next().unwrap_or(mime).trim();
+    Some(match mime.to_ascii_lowercase().as_str() {
+        "image/jpeg" => "jpg",
+        "image/png" => "png",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "image/heic" => "heic",
+        "video/mp4" => "mp4",
+        "video/quicktime" => "mov",
+        "video/webm" => "webm",
+        "video/x-matroska" => "mkv",
+        "audio/mpeg" => "mp3",
+        "audio/ogg" => "ogg",
+        "audio/mp4" | "audio/x-m4a" => "m4a",
+        "audio/flac" => "flac",
+        "audio/wav" | "audio/x-wav" => "wav",
+        "application/pdf" => "pdf",
+        "application/zip" => "zip",
+        "application/x-rar-compressed" | "application/vnd.rar" => "rar",
+        "application/x-7z-compressed" => "7z",
+        "application/x-tgsticker" => "tgs",
+        "application/json" => "json",
+        "application/msword" => "doc",
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document" => "docx",
+        "application/vnd.ms-excel" => "xls",
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet" => "xlsx",
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation" => "pptx",
+        "application/vnd.android.package-archive" => "apk",
+        "text/plain" => "txt",
+        "text/csv" => "csv",
+        "text/html" => "html",
+        _ => return None,
+    })
+}
+
+/// Extension of media sent without a name or a known MIME type.
+fn extension_for_media_type(media_type: MediaType) -> &'static str {
+    match media_type {
+        MediaType::Photo => "jpg",
+        MediaType::Video => "mp4",
+        MediaType::Document => "bin",
+        MediaType::Audio => "ogg",
+        MediaType::Voice => "ogg",
+        MediaType::Sticker => "webp",
+        MediaType::Animation => "mp4",
+        MediaType::VideoNote => "mp4",
+        MediaType::Other => "bin",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::persistence::sqlite_repo::SqliteRepo;
+    use crate::domain::{MediaStatus, Message};
+    use crate::ports::RepoPort;
+    use crate::testing::fake_tg::text_message;
+
+    fn media(
+        message_id: i32,
+        media_type: MediaType,
+        filename: Option<&str>,
+        mime: Option<&str>,
+    ) -> MediaReference {
+        MediaReference {
+            message_id,
+            chat_id: -100123,
+            media_type,
+            opaque_ref: "ref".to_string(),
+            size_bytes: None,
+            filename: filename.map(String::from),
+            mime_type: mime.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_media_rel_path_keeps_original_names() {
+        for (media_ref, expected) in [
+            (
+                media(7, MediaType::Document, Some("report_final.pdf"), None),
+                "-100123/7_report_final.pdf",
+            ),
+            (
+                media(7, MediaType::Document, Some("notes"), Some("text/plain")),
+                "-100123/7_notes.txt",
+            ),
+            (
+                media(7, MediaType::Document, Some("a/b:c?.zip"), None),
+                "-100123/7_abc.zip",
+            ),
+            (
+                media(7, MediaType::Document, None, Some("application/pdf")),
+                "-100123/7.pdf",
+            ),
+            (media(7, MediaType::Photo, None, None), "-100123/7.jpg"),
+            (
+                media(7, MediaType::Document, Some("  "), None),
+                "-100123/7.bin",
+            ),
+        ] {
+            assert_eq!(media_rel_path(&media_ref), expected);
+        }
+        let long = format!("{}.pdf", "x".repeat(300));
+        let path = media_rel_path(&media(7, MediaType::Document, Some(&long), None));
+        assert!(path.ends_with("x.pdf"), "{}", path);
+        assert_eq!(path.chars().count(), "-100123/".len() + MAX_NAME_CHARS);
+    }
+
+    #[tokio::test]
+    async fn test_migrate_flat_layout_moves_files_and_index_entries() {
+        let base = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join("test_media_layout_migration");
+        let _ = std::fs::remove_dir_all(&base);
+        let repo = SqliteRepo::connect(&base).await.unwrap();
+        let media_dir = base.join("media");
+        std::fs::create_dir_all(media_dir.join("-100123")).unwrap();
+        let with_media = |id: i32, media_ref: MediaReference| Message {
+            media: Some(media_ref),
+            ..text_message(-100123, id, 1704067200, "m")
+        };
+        // 1: indexed, 2: named document indexed encrypted, 3: downloaded but never indexed,
+        // 4: its new path already taken.
+        repo.save_messages(
+            -100123,
+            &[
+                with_media(1, media(1, MediaType::Photo, None, None)),
+                with_media(2, media(2, MediaType::Document, Some("cv.pdf"), None)),
+                with_media(3, media(3, MediaType::Voice, None, None)),
+                with_media(4, media(4, MediaType::Photo, None, None)),
+            ],
+        )
+        .await
+        .unwrap();
+        for (id, rel_path) in [
+            (1, "-100123_1.jpg"),
+            (2, "-100123_2.bin.enc"),
+            (4, "-100123_4.jpg"),
+        ] {
+            repo.upsert_media_file(&MediaFile {
+                chat_id: -100123,
+                message_id: id,
+                media_type: MediaType::Photo,
+                rel_path: rel_path.to_string(),
+                size_bytes: Some(1),
+                expected_size: None,
+                sha256: None,
+                status: MediaStatus::Done,
+                message_date: None,
+                remote_key: None,
+            })
+            .await
+            .unwrap();
+        }
+        for name in [
+            "-100123_1.jpg",
+            "-100123_2.bin.enc",
+            "-100123_3.ogg",
+            "-100123_4.jpg",
+            "-100123/4.jpg",
+            "unrelated.txt",
+        ] {
+            std::fs::write(media_dir.join(name), name).unwrap();
+        }
+
+        assert_eq!(migrate_flat_layout(&repo, &media_dir).await.unwrap(), 4);
+        let read = |rel: &str| std::fs::read_to_string(media_dir.join(rel)).unwrap();
+        assert_eq!(read("-100123/1.jpg"), "-100123_1.jpg");
+        assert_eq!(read("-100123/2_cv.pdf.enc"), "-100123_2.bin.enc");
+        assert_eq!(read("-100123/3.ogg"), "-100123_3.ogg");
+        assert_eq!(read("-100123/4.jpg"), "-100123/4.jpg");
+        assert_eq!(read("-100123/4-1.jpg"), "-100123_4.jpg");
+        assert!(media_dir.join("unrelated.txt").exists());
+        let paths: Vec<String> = repo
+            .list_media_files(None, None, None, 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|f| f.rel_path)
+            .collect();
+        assert_eq!(
+            paths,
+            vec!["-100123/1.jpg", "-100123/2_cv.pdf.enc", "-100123/4-1.jpg"]
+        );
+
+        assert_eq!(migrate_flat_layout(&repo, &media_dir).await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,248 @@
+//! Media manifest export: `media/manifest.jsonl`, one JSON object per downloaded file.
+//!
+//! Lets external scripts see what media exists without opening SQLite. Generated from the
+//! media index page by page (never loads the whole table), written to a temp file and
+//! renamed into place so readers never see a half-written manifest.
+
+use crate::domain::{DomainError, MediaFile, MediaStatus};
+use crate::ports::MediaIndexPort;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tracing::info;
+
+/// Manifest file name inside the media directory.
+pub const MANIFEST_FILE: &str = "manifest.jsonl";
+
+/// Rows fetched from the media index per page.
+const PAGE_SIZE: u32 = 500;
+
+/// One manifest line. Field order is the serialized order.
+#[derive(Debug, Serialize)]
+struct ManifestEntry<'a> {
+    chat_id: i64,
+    message_id: i32,
+    media_type: &'static str,
+    /// Relative to the media directory.
+    path: &'a str,
+    size: Option<u64>,
+    sha256: Option<&'a str>,
+    /// Unix timestamp of the message, if the message is stored.
+    date: Option<i64>,
+}
+
+impl<'a> From<&'a MediaFile> for ManifestEntry<'a> {
+    fn from(f: &'a MediaFile) -> Self {
+        Self {
+            chat_id: f.chat_id,
+            message_id: f.message_id,
+            media_type: f.media_type.as_str(),
+            path: &f.rel_path,
+            size: f.size_bytes,
+            sha256: f.sha256.as_deref(),
+            date: f.message_date,
+        }
+    }
+}
+
+/// Writes the media manifest from the media index.
+pub struct MediaManifestService {
+    index: Arc<dyn MediaIndexPort>,
+    media_dir: PathBuf,
+    page_size: u32,
+}
+
+impl MediaManifestService {
+    pub fn new(index: Arc<dyn MediaIndexPort>, media_dir: PathBuf) -> Self {
+        Self {
+            index,
+            media_dir,
+            page_size: PAGE_SIZE,
+        }
+    }
+
+    /// Path of the manifest file.
+    pub fn manifest_path(&self) -> PathBuf {
+        self.media_dir.join(MANIFEST_FILE)
+    }
+
+    /// Regenerate the manifest with all `done` files (optionally only for `only_chat`).
+    /// Idempotent: the same index always yields the same bytes. Returns the number of entries.
+    pub async fn write_manifest(&self, only_chat: Option<i64>) -> Result<usize, DomainError> {
+        let final_path = self.manifest_path();
+        let tmp_path = self.media_dir.join(format!("{}.tmp", MANIFEST_FILE));
+        tokio::fs::create_dir_all(&self.media_dir)
+            .await
+            .map_err(|e| DomainError::Repo(format!("Failed to create media dir: {}", e)))?;
+
+        let result = self.write_entries(&tmp_path, only_chat).await;
+        let count = match result {
+            Ok(count) => count,
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&tmp_path).await;
+                return Err(e);
+            }
+        };
+
+        tokio::fs::rename(&tmp_path, &final_path)
+            .await
+            .map_err(|e| DomainError::Repo(format!("Failed to move manifest into place: {}", e)))?;
+        info!(path = %final_path.display(), entries = count, "media manifest written");
+        Ok(count)
+    }
+
+    async fn write_entries(
+        &self,
+        path: &Path,
+        only_chat: Option<i64>,
+    ) -> Result<usize, DomainError> {
+        let file = tokio::fs::File::create(path)
+            .await
+            .map_err(|e| DomainError::Repo(format!("Failed to create manifest: {}", e)))?;
+        let mut out = tokio::io::BufWriter::new(file);
+        let mut count = 0usize;
+        let mut after = None;
+
+        loop {
+            let page = self
+                .index
+                .list_media_files(only_chat, Some(MediaStatus::Done), after, self.page_size)
+                .await?;
+            let Some(last) = page.last() else {
+                break;
+            };
+            after = Some((last.chat_id, last.message_id));
+
+            for f in &page {
+                let mut line = serde_json::to_string(&ManifestEntry::from(f)).map_err(|e| {
+                    DomainError::Repo(format!("Failed to serialize manifest entry: {}", e))
+                })?;
+                line.push('\n');
+                out.write_all(line.as_bytes())
+                    .await
+                    .map_err(|e| DomainError::Repo(format!("Failed to write manifest: {}", e)))?;
+                count += 1;
+            }
+            if (page.len() as u32) < self.page_size {
+                break;
+            }
+        }
+
+        out.flush()
+            .await
+            .map_err(|e| DomainError::Repo(format!("Failed to write manifest: {}", e)))?;
+        out.get_ref()
+            .sync_all()
+            .await
+            .map_err(|e| DomainError::Repo(format!("Failed to sync manifest: {}", e)))?;
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::persistence::sqlite_repo::SqliteRepo;
+    use crate::domain::MediaType;
+    use crate::ports::RepoPort;
+    use crate::testing::fake_tg::text_message;
+
+    fn file(
+        chat_id: i64,
+        message_id: i32,
+        media_type: MediaType,
+        status: MediaStatus,
+    ) -> MediaFile {
+        MediaFile {
+            chat_id,
+            message_id,
+            media_type,
+            rel_path: format!("{}_{}.bin", chat_id, message_id),
+            size_bytes: None,
+            expected_size: None,
+            sha256: None,
+            status,
+            message_date: None,
+            remote_key: None,
+        }
+    }
+
+    async fn seeded_repo(name: &str) -> (Arc<SqliteRepo>, PathBuf) {
+        let base_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("target")
+            .join(name);
+        let _ = std::fs::remove_dir_all(&base_dir);
+        let repo = Arc::new(SqliteRepo::connect(&base_dir).await.expect("connect"));
+
+        let msg = text_message(100, 7, 1704067200, "photo caption");
+        repo.save_messages(100, &[msg]).await.unwrap();
+
+        let mut photo = file(100, 7, MediaType::Photo, MediaStatus::Done);
+        photo.size_bytes = Some(1234);
+        photo.sha256 = Some("ab12".to_string());
+        let mut video = file(200, 1, MediaType::Video, MediaStatus::Done);
+        video.size_bytes = Some(2048);
+        let mut sticker = file(100, 10, MediaType::Sticker, MediaStatus::Done);
+        sticker.size_bytes = Some(10);
+        // Inserted out of order; not-done rows must not appear in the manifest.
+        let files = [
+            video,
+            file(100, 9, MediaType::Document, MediaStatus::Pending),
+            photo,
+            file(100, 8, MediaType::Voice, MediaStatus::Failed),
+            sticker,
+        ];
+        for f in &files {
+            repo.upsert_media_file(f).await.unwrap();
+        }
+        (repo, base_dir.join("media"))
+    }
+
+    fn read_lines(path: &Path) -> Vec<String> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_manifest_matches_media_files() {
+        let (repo, media_dir) = seeded_repo("test_media_manifest_db").await;
+        let mut service = MediaManifestService::new(repo, media_dir.clone());
+        // Small pages exercise the keyset pagination.
+        service.page_size = 2;
+
+        let count = service.write_manifest(None).await.unwrap();
+        assert_eq!(count, 3, "only done files are listed");
+        let expected = vec![
+            r#"{"chat_id":100,"message_id":7,"media_type":"photo","path":"100_7.bin","size":1234,"sha256":"ab12","date":1704067200}"#,
+            r#"{"chat_id":100,"message_id":10,"media_type":"sticker","path":"100_10.bin","size":10,"sha256":null,"date":null}"#,
+            r#"{"chat_id":200,"message_id":1,"media_type":"video","path":"200_1.bin","size":2048,"sha256":null,"date":null}"#,
+        ];
+        let first = read_lines(&service.manifest_path());
+        assert_eq!(first, expected);
+
+        // Regeneration is idempotent and leaves no temp file behind.
+        service.write_manifest(None).await.unwrap();
+        assert_eq!(read_lines(&service.manifest_path()), expected);
+        assert!(!media_dir.join("manifest.jsonl.tmp").exists());
+    }
+
+    #[tokio::test]
+    async fn test_manifest_only_chat_filter() {
+        let (repo, media_dir) = seeded_repo("test_media_manifest_filter_db").await;
+        let service = MediaManifestService::new(repo, media_dir);
+
+        let count = service.write_manifest(Some(200)).await.unwrap();
+        assert_eq!(count, 1);
+        let lines = read_lines(&service.manifest_path());
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with(r#"{"chat_id":200,"message_id":1,"#));
+
+        let count = service.write_manifest(Some(999)).await.unwrap();
+        assert_eq!(count, 0);
+        assert!(read_lines(&service.manifest_path()).is_empty());
+    }
+}
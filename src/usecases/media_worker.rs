@@ -1,30 +1,57 @@
-//! Async task: reads MediaReference from mpsc channel and downloads files.
+//! Async task: downloads media from the persistent media queue, woken by the mpsc channel.
 //!
 //! Runs concurrently with text sync. Uses TgGateway and rate limiting.
+//! Every file is recorded in the media index (pending -> done/failed, with size).
+//! Refs arriving on the channel are claimed in the media queue (MediaQueuePort) before download,
+//! so a ref is never fetched twice; between channel refs the worker picks up queued rows left by
+//! an earlier run and failed rows whose exponential backoff has passed.
+//! Files go into per-chat folders (see `media_layout`). They are downloaded to `{name}.part`
+//! and renamed once their size matches the one Telegram reported, so a crash or a cut
+//! connection never leaves a file that looks complete; a stored file of the wrong size is
+//! downloaded again. With TG_SYNC_MEDIA_HASH the SHA-256 of each
+//! new file is recorded too, for "Verify media".
+//! With an archive cipher every file is stored encrypted as `{name}.enc` and the plaintext
+//! download is removed. With remote storage, finished files are handed to the upload worker
+//! without waiting: a full upload queue never slows downloads (see `UploadWorker`).
 
-use crate::domain::{DomainError, MediaReference};
-use crate::ports::TgGateway;
-use std::path::PathBuf;
+use crate::domain::{DomainError, MediaFile, MediaReference, MediaStatus};
+use crate::ports::{MediaIndexPort, MediaQueuePort, TgGateway};
+use crate::shared::crypto::{ArchiveCipher, ENCRYPTED_EXTENSION, sealed_file_len};
+use crate::usecases::media_layout::media_rel_path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::mpsc;
+use tokio::sync::mpsc::{self, error::TryRecvError, error::TrySendError};
 use tokio::time::sleep;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 /// Maximum concurrent media downloads.
 const MAX_CONCURRENT: usize = 3;
 
-/// Maximum retry attempts for a single media download.
-const MAX_RETRIES: u32 = 3;
+/// Download attempts per media before it stays failed (until "Retry failed media").
+pub const MAX_ATTEMPTS: u32 = 5;
 
-/// Base delay in seconds for linear backoff (sleep = retry_count * BASE_BACKOFF_SECS).
-const BASE_BACKOFF_SECS: u64 = 2;
+/// Backoff after the first failure; doubles with every further attempt (30 s, 1 min, 2 min, ...).
+const BASE_BACKOFF_SECS: i64 = 30;
 
-/// Media worker. Consumes channel and downloads via TgGateway.
+/// How often an idle worker looks for due rows in the media queue.
+const QUEUE_POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Media worker. Consumes channel and persistent queue, downloads via TgGateway.
 pub struct MediaWorker {
     tg: Arc<dyn TgGateway>,
     rx: mpsc::Receiver<MediaReference>,
     output_dir: PathBuf,
+    /// Media index (media_files table): status, relative path and size per file.
+    index: Arc<dyn MediaIndexPort>,
+    /// Persistent download queue (media_queue table): claims, attempts, last error.
+    queue: Arc<dyn MediaQueuePort>,
+    /// Encrypts downloaded files when the archive is encrypted.
+    cipher: Option<Arc<ArchiveCipher>>,
+    /// Upload queue of finished files (TG_SYNC_MEDIA_UPLOAD).
+    uploads: Option<mpsc::Sender<MediaFile>>,
+    /// Record the SHA-256 of every new plaintext file (TG_SYNC_MEDIA_HASH).
+    hashes: bool,
 }
 
 impl MediaWorker {
@@ -32,22 +59,128 @@ impl MediaWorker {
         tg: Arc<dyn TgGateway>,
         rx: mpsc::Receiver<MediaReference>,
         output_dir: PathBuf,
+        index: Arc<dyn MediaIndexPort>,
+        queue: Arc<dyn MediaQueuePort>,
+        cipher: Option<Arc<ArchiveCipher>>,
+        uploads: Option<mpsc::Sender<MediaFile>>,
     ) -> Self {
-        Self { tg, rx, output_dir }
+        Self {
+            tg,
+            rx,
+            output_dir,
+            index,
+            queue,
+            cipher,
+            uploads,
+            hashes: false,
+        }
+    }
+
+    /// Record the SHA-256 of every file downloaded (unencrypted archives only).
+    pub fn with_hashes(mut self, hashes: bool) -> Self {
+        self.hashes = hashes;
+        self
+    }
+
+    /// Encrypt every downloaded (`done`) file still stored in plaintext and point its index
+    /// entry at the `.enc` file. Returns the number of files encrypted.
+    pub async fn encrypt_existing(
+        index: &dyn MediaIndexPort,
+        base: &Path,
+        cipher: &Arc<ArchiveCipher>,
+    ) -> Result<u64, DomainError> {
+        let mut encrypted = 0u64;
+        let mut after = None;
+        loop {
+            let page = index
+                .list_media_files(None, Some(MediaStatus::Done), after, 500)
+                .await?;
+            let Some(last) = page.last() else {
+                break;
+            };
+            after = Some((last.chat_id, last.message_id));
+            for mut file in page {
+                if file
+                    .rel_path
+                    .ends_with(&format!(".{}", ENCRYPTED_EXTENSION))
+                {
+                    continue;
+                }
+                let plain = base.join(&file.rel_path);
+                if !tokio::fs::try_exists(&plain).await.unwrap_or(false) {
+                    continue;
+                }
+                let sealed = encrypted_path(&plain);
+                file.size_bytes = Some(Self::encrypt_file(cipher, &plain, &sealed).await?);
+                file.rel_path = format!("{}.{}", file.rel_path, ENCRYPTED_EXTENSION);
+                file.sha256 = None;
+                index.upsert_media_file(&file).await?;
+                encrypted += 1;
+            }
+        }
+        Ok(encrypted)
+    }
+
+    /// Encrypt `plain` into `sealed` and remove `plain`. Returns the encrypted size.
+    async fn encrypt_file(
+        cipher: &Arc<ArchiveCipher>,
+        plain: &Path,
+        sealed: &Path,
+    ) -> Result<u64, DomainError> {
+        let (cipher, src, dst) = (
+            Arc::clone(cipher),
+            plain.to_path_buf(),
+            sealed.to_path_buf(),
+        );
+        let size = tokio::task::spawn_blocking(move || cipher.seal_file(&src, &dst))
+            .await
+            .map_err(|e| DomainError::Crypto(e.to_string()))??;
+        tokio::fs::remove_file(plain).await.map_err(|e| {
+            DomainError::Media(format!("remove plaintext {}: {}", plain.display(), e))
+        })?;
+        Ok(size)
     }
 
     /// Run the worker. Processes until channel is closed.
     pub async fn run(mut self) {
+        match self.queue.requeue_interrupted_media().await {
+            Ok(0) => {}
+            Ok(n) => info!(count = n, "resuming interrupted media downloads"),
+            Err(e) => warn!(error = %e, "failed to re-queue interrupted media"),
+        }
         let semaphore = Arc::new(tokio::sync::Semaphore::new(MAX_CONCURRENT));
 
-        while let Some(media_ref) = self.rx.recv().await {
-            let sem = Arc::clone(&semaphore);
+        loop {
+            // Take a slot first so only running downloads are claimed in the queue.
+            let permit = Arc::clone(&semaphore)
+                .acquire_owned()
+                .await
+                .expect("semaphore closed");
+            let Some(media_ref) = self.next_claimed().await else {
+                break;
+            };
             let tg = Arc::clone(&self.tg);
+            let index = Arc::clone(&self.index);
+            let queue = Arc::clone(&self.queue);
             let output_dir = self.output_dir.clone();
+            let cipher = self.cipher.clone();
+            let uploads = self.uploads.clone();
+            let hashes = self.hashes;
 
             tokio::spawn(async move {
-                let _permit = sem.acquire().await.expect("semaphore closed");
-                if let Err(e) = Self::download_one(&*tg, &media_ref, &output_dir).await {
+                let _permit = permit;
+                let result = Self::download_one(
+                    &*tg,
+                    &*index,
+                    &media_ref,
+                    &output_dir,
+                    cipher.as_ref(),
+                    uploads.as_ref(),
+                    hashes,
+                )
+                .await;
+                let error = result.as_ref().err().map(|e| e.to_string());
+                if let Err(e) = &result {
                     error!(chat_id = media_ref.chat_id, msg_id = media_ref.message_id, error = %e, "media download failed");
                 } else {
                     debug!(
@@ -56,72 +189,375 @@ impl MediaWorker {
                         "media downloaded"
                     );
                 }
+                if let Err(e) = queue
+                    .finish_media(media_ref.chat_id, media_ref.message_id, error.as_deref())
+                    .await
+                {
+                    warn!(chat_id = media_ref.chat_id, msg_id = media_ref.message_id, error = %e, "failed to update media queue");
+                }
             });
         }
 
         info!("media worker finished (channel closed)");
     }
 
+    /// Next claimed download: refs from the channel first, else due rows from the media queue;
+    /// waits for either when both are empty. None once the channel is closed.
+    async fn next_claimed(&mut self) -> Option<MediaReference> {
+        loop {
+            let media_ref = match self.rx.try_recv() {
+                Ok(media_ref) => media_ref,
+                Err(TryRecvError::Disconnected) => return None,
+                Err(TryRecvError::Empty) => {
+                    if let Some(media_ref) = self.claim_due().await {
+                        return Some(media_ref);
+                    }
+                    tokio::select! {
+                        received = self.rx.recv() => received?,
+                        _ = sleep(QUEUE_POLL_INTERVAL) => continue,
+                    }
+                }
+            };
+            match self.queue.claim_media(&media_ref).await {
+                Ok(true) => return Some(media_ref),
+                // Already in progress, done, or failed and waiting for its backoff.
+                Ok(false) => continue,
+                Err(e) => {
+                    // Better a possible duplicate download than a lost one.
+                    warn!(chat_id = media_ref.chat_id, msg_id = media_ref.message_id, error = %e, "media queue claim failed; downloading anyway");
+                    return Some(media_ref);
+                }
+            }
+        }
+    }
+
+    /// Claim one due row from the media queue. Queue errors are logged and treated as empty.
+    async fn claim_due(&self) -> Option<MediaReference> {
+        let now = chrono::Utc::now().timestamp();
+        match self
+            .queue
+            .claim_due_media(now, BASE_BACKOFF_SECS, MAX_ATTEMPTS, 1)
+            .await
+        {
+            Ok(mut refs) => refs.pop(),
+            Err(e) => {
+                warn!(error = %e, "failed to read media queue");
+                None
+            }
+        }
+    }
+
     async fn download_one(
         tg: &dyn TgGateway,
+        index: &dyn MediaIndexPort,
         media_ref: &MediaReference,
         base: &std::path::Path,
+        cipher: Option<&Arc<ArchiveCipher>>,
+        uploads: Option<&mpsc::Sender<MediaFile>>,
+        hashes: bool,
     ) -> Result<(), DomainError> {
-        let ext = extension_for_media_type(media_ref.media_type);
-        let filename = format!("{}_{}.{}", media_ref.chat_id, media_ref.message_id, ext);
-        let dest = base.join(&filename);
+        let plain_rel_path = media_rel_path(media_ref);
+        let download = base.join(&plain_rel_path);
+        // Where the file is kept: the download itself, or its encrypted copy.
+        let (dest, rel_path) = match cipher {
+            Some(_) => (
+                encrypted_path(&download),
+                format!("{}.{}", plain_rel_path, ENCRYPTED_EXTENSION),
+            ),
+            None => (download.clone(), plain_rel_path),
+        };
 
         if tokio::fs::try_exists(&dest).await.unwrap_or(false) {
-            debug!(path = %dest.display(), "File already exists: skipping download");
-            return Ok(());
+            let expected = media_ref.expected_size().map(|n| {
+                if cipher.is_some() {
+                    sealed_file_len(n)
+                } else {
+                    n
+                }
+            });
+            match check_size(&dest, expected).await {
+                Ok(_) => {
+                    debug!(path = %dest.display(), "File already exists: skipping download");
+                    let file =
+                        Self::record(index, media_ref, &rel_path, &dest, MediaStatus::Done, None)
+                            .await;
+                    Self::hand_to_uploads(uploads, file);
+                    return Ok(());
+                }
+                Err(e) => {
+                    warn!(error = %e, "stored media file is incomplete; downloading it again");
+                    let _ = tokio::fs::remove_file(&dest).await;
+                }
+            }
         }
 
-        let mut last_error = None;
-        for attempt in 0..=MAX_RETRIES {
-            match tg.download_media(media_ref, &dest).await {
-                Ok(()) => return Ok(()),
+        Self::record(
+            index,
+            media_ref,
+            &rel_path,
+            &dest,
+            MediaStatus::Pending,
+            None,
+        )
+        .await;
+
+        // With a cipher, a complete plaintext file from before encryption was enabled only
+        // needs encrypting. Without one it is `dest`, which is gone by now.
+        let downloaded = match check_size(&download, media_ref.expected_size()).await {
+            Ok(_) if cipher.is_some() => Ok(()),
+            _ => Self::fetch(tg, media_ref, &download).await,
+        };
+        let sha256 = match (&downloaded, cipher) {
+            (Ok(()), None) if hashes => match file_sha256(&download).await {
+                Ok(hash) => Some(hash),
                 Err(e) => {
-                    last_error = Some(e);
-                    if attempt < MAX_RETRIES {
-                        let delay_secs = (attempt + 1) as u64 * BASE_BACKOFF_SECS;
-                        debug!(
-                            chat_id = media_ref.chat_id,
-                            msg_id = media_ref.message_id,
-                            attempt = attempt + 1,
-                            max_retries = MAX_RETRIES,
-                            delay_secs,
-                            error = %last_error.as_ref().unwrap(),
-                            "download failed, retrying after backoff"
-                        );
-                        sleep(Duration::from_secs(delay_secs)).await;
-                    }
+                    warn!(error = %e, "failed to hash media file");
+                    None
                 }
+            },
+            _ => None,
+        };
+        let stored = match (downloaded, cipher) {
+            (Ok(()), Some(cipher)) => Self::encrypt_file(cipher, &download, &dest)
+                .await
+                .map(|_| ()),
+            (result, _) => result,
+        };
+        match stored {
+            Ok(()) => {
+                let file = Self::record(
+                    index,
+                    media_ref,
+                    &rel_path,
+                    &dest,
+                    MediaStatus::Done,
+                    sha256,
+                )
+                .await;
+                Self::hand_to_uploads(uploads, file);
+                Ok(())
+            }
+            Err(e) => {
+                Self::record(
+                    index,
+                    media_ref,
+                    &rel_path,
+                    &dest,
+                    MediaStatus::Failed,
+                    None,
+                )
+                .await;
+                Err(e)
             }
         }
+    }
 
-        let err = last_error.expect("last_error set in loop");
-        error!(
-            chat_id = media_ref.chat_id,
-            msg_id = media_ref.message_id,
-            file = %filename,
-            error = %err,
-            "Max retries exceeded for {}",
-            filename
-        );
-        Err(err)
+    /// Download into `{dest}.part` and move it to `dest` once it has the size Telegram
+    /// reported, so an interrupted or truncated download never looks complete. One attempt
+    /// per claim; the media queue schedules retries with backoff.
+    async fn fetch(
+        tg: &dyn TgGateway,
+        media_ref: &MediaReference,
+        dest: &Path,
+    ) -> Result<(), DomainError> {
+        let part = part_path(dest);
+        if let Some(dir) = dest.parent() {
+            tokio::fs::create_dir_all(dir)
+                .await
+                .map_err(|e| DomainError::Media(format!("create {}: {}", dir.display(), e)))?;
+        }
+        let result = match tg.download_media(media_ref, &part).await {
+            Ok(()) => check_size(&part, media_ref.expected_size()).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            let _ = tokio::fs::remove_file(&part).await;
+            return Err(e);
+        }
+        tokio::fs::rename(&part, dest)
+            .await
+            .map_err(|e| DomainError::Media(format!("rename {}: {}", part.display(), e)))
+    }
+
+    /// Queue a finished file for upload without waiting. When the queue is full the upload
+    /// worker's index sweep picks the file up later.
+    fn hand_to_uploads(uploads: Option<&mpsc::Sender<MediaFile>>, file: MediaFile) {
+        let Some(uploads) = uploads else {
+            return;
+        };
+        match uploads.try_send(file) {
+            Ok(()) => {}
+            Err(TrySendError::Full(file)) => {
+                debug!(
+                    chat_id = file.chat_id,
+                    msg_id = file.message_id,
+                    "upload queue full: left for the upload sweep"
+                );
+            }
+            Err(TrySendError::Closed(_)) => warn!("upload queue closed"),
+        }
+    }
+
+    /// Record the file in the media index and return the entry. Index failures are logged,
+    /// never fatal for the download. A `sha256` of None keeps the stored hash.
+    async fn record(
+        index: &dyn MediaIndexPort,
+        media_ref: &MediaReference,
+        rel_path: &str,
+        dest: &std::path::Path,
+        status: MediaStatus,
+        sha256: Option<String>,
+    ) -> MediaFile {
+        let size_bytes = match status {
+            MediaStatus::Done => tokio::fs::metadata(dest).await.ok().map(|m| m.len()),
+            _ => None,
+        };
+        let file = MediaFile {
+            chat_id: media_ref.chat_id,
+            message_id: media_ref.message_id,
+            media_type: media_ref.media_type,
+            rel_path: rel_path.to_string(),
+            size_bytes,
+            expected_size: media_ref.expected_size(),
+            sha256,
+            status,
+            message_date: None,
+            remote_key: None,
+        };
+        if let Err(e) = index.upsert_media_file(&file).await {
+            warn!(chat_id = media_ref.chat_id, msg_id = media_ref.message_id, error = %e, "failed to update media index");
+        }
+        file
+    }
+}
+
+/// Error unless the file at `path` is `expected` bytes long (any length when None). Returns
+/// its length.
+pub(crate) async fn check_size(path: &Path, expected: Option<u64>) -> Result<u64, DomainError> {
+    let len = tokio::fs::metadata(path)
+        .await
+        .map_err(|e| DomainError::Media(format!("{}: {}", path.display(), e)))?
+        .len();
+    match expected {
+        Some(expected) if expected != len => Err(DomainError::Media(format!(
+            "{}: {} bytes, expected {} (incomplete or damaged)",
+            path.display(),
+            len,
+            expected
+        ))),
+        _ => Ok(len),
     }
 }
 
-fn extension_for_media_type(media_type: crate::domain::MediaType) -> &'static str {
+/// Hex SHA-256 of the file at `path`, read on the blocking pool.
+pub(crate) async fn file_sha256(path: &Path) -> Result<String, DomainError> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        use sha2::{Digest, Sha256};
+        let mut file = std::fs::File::open(&path)
+            .map_err(|e| DomainError::Media(format!("{}: {}", path.display(), e)))?;
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut file, &mut hasher)
+            .map_err(|e| DomainError::Media(format!("{}: {}", path.display(), e)))?;
+        Ok(hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect())
+    })
+    .await
+    .map_err(|e| DomainError::Media(e.to_string()))?
+}
+
+/// Partial download of `path`: the same name plus `.part` (swept at startup).
+fn part_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".part");
+    PathBuf::from(name)
+}
+
+/// Encrypted copy of `path`: the same name plus `.enc`.
+fn encrypted_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(ENCRYPTED_EXTENSION);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::persistence::sqlite_repo::SqliteRepo;
     use crate::domain::MediaType;
-    match media_type {
-        MediaType::Photo => "jpg",
-        MediaType::Video => "mp4",
-        MediaType::Document => "bin",
-        MediaType::Audio => "ogg",
-        MediaType::Voice => "ogg",
-        MediaType::Sticker => "webp",
-        MediaType::Animation => "mp4",
-        MediaType::Other => "bin",
+    use crate::testing::fake_tg::FakeTgGateway;
+
+    fn photo(message_id: i32, opaque_ref: &str, size: i64) -> MediaReference {
+        MediaReference {
+            message_id,
+            chat_id: 9,
+            media_type: MediaType::Photo,
+            opaque_ref: opaque_ref.to_string(),
+            size_bytes: Some(size),
+            filename: None,
+            mime_type: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_download_keeps_only_files_of_the_expected_size() {
+        let base = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join("test_media_worker_sizes");
+        let _ = std::fs::remove_dir_all(&base);
+        let repo = SqliteRepo::connect(&base).await.unwrap();
+        let media_dir = base.join("media");
+        let tg = FakeTgGateway::new(1);
+        // The fake writes the opaque ref as the file's content.
+        let complete = photo(1, "photo-1", 7);
+        let truncated = photo(2, "photo-2", 1024);
+        let download = |media_ref: MediaReference| {
+            let (tg, repo, media_dir) = (&tg, &repo, &media_dir);
+            async move {
+                MediaWorker::download_one(tg, repo, &media_ref, media_dir, None, None, true).await
+            }
+        };
+        let entry = |message_id: i32| {
+            let repo = &repo;
+            async move {
+                repo.list_media_files(Some(9), None, None, 10)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .find(|f| f.message_id == message_id)
+                    .unwrap()
+            }
+        };
+
+        download(complete.clone()).await.unwrap();
+        assert_eq!(
+            std::fs::read(media_dir.join("9/1.jpg")).unwrap(),
+            b"photo-1"
+        );
+        let file = entry(1).await;
+        assert_eq!(file.status, MediaStatus::Done);
+        assert_eq!((file.size_bytes, file.expected_size), (Some(7), Some(7)));
+        assert_eq!(file.sha256.map(|h| h.len()), Some(64));
+
+        let err = download(truncated).await.unwrap_err();
+        assert!(err.to_string().contains("expected 1024"), "{}", err);
+        assert!(!media_dir.join("9/2.jpg").exists());
+        assert!(!media_dir.join("9/2.jpg.part").exists());
+        assert_eq!(entry(2).await.status, MediaStatus::Failed);
+
+        // A damaged copy is replaced; a complete one is kept without downloading.
+        std::fs::write(media_dir.join("9/1.jpg"), b"pho").unwrap();
+        download(complete.clone()).await.unwrap();
+        assert_eq!(
+            std::fs::read(media_dir.join("9/1.jpg")).unwrap(),
+            b"photo-1"
+        );
+        assert_eq!(tg.downloads().len(), 3);
+        download(complete).await.unwrap();
+        assert_eq!(tg.downloads().len(), 3);
     }
 }
@@ -1,13 +1,42 @@
 //! Application use cases. Orchestrate domain logic via ports.
 
 pub mod analysis_service;
+pub mod audit_service;
 pub mod auth_service;
+pub mod backup_service;
+pub mod export_service;
+pub mod import_service;
+pub mod ingest_service;
+pub mod maintenance_service;
+pub mod media_layout;
+pub mod media_manifest;
 pub mod media_worker;
+pub mod notify;
+pub mod purge_service;
+pub mod recovery_service;
 pub mod sync_service;
+pub mod upload_worker;
+pub mod watcher_daemon;
 pub mod watcher_service;
 
-pub use analysis_service::AnalysisService;
+pub use analysis_service::{AnalysisReport, AnalysisService, TrackerPushReport};
+pub use audit_service::{AuditReport, AuditService};
 pub use auth_service::AuthService;
+pub use backup_service::{BackupReport, BackupService};
+pub use export_service::{ExportOptions, ExportReport, ExportService};
+pub use import_service::{ImportOptions, ImportReport, ImportService};
+pub use ingest_service::{IngestReport, IngestService};
+pub use maintenance_service::{
+    CorruptMedia, MaintenanceReport, MaintenanceService, MediaVerifyReport,
+};
+pub use media_manifest::MediaManifestService;
 pub use media_worker::MediaWorker;
-pub use sync_service::SyncService;
-pub use watcher_service::WatcherService;
+pub use purge_service::{PurgeReport, PurgeService};
+pub use recovery_service::{
+    RecoveryReport, RecoveryService, RecoveryStep, ReplayTrackerDeadLetters, RequeuePendingMedia,
+    SweepTempFiles,
+};
+pub use sync_service::{ChatSyncResult, MediaBackfill, SyncPlan, SyncService, SyncSkip, SyncStats};
+pub use upload_worker::UploadWorker;
+pub use watcher_daemon::DaemonController;
+pub use watcher_service::{WatcherService, WatcherStatus, validate_watch_pattern};
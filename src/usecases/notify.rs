@@ -0,0 +1,97 @@
+//! Delivery of notification events to several notifiers at once.
+//!
+//! Every notifier that accepts the event gets it; a failed delivery is retried once after a
+//! short pause, then logged. Delivery never fails the caller.
+
+use crate::domain::NotificationEvent;
+use crate::ports::NotifierPort;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Pause before the one retry of a failed delivery.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Send `event` to every notifier that accepts it. Returns how many delivered it.
+pub async fn notify_all(notifiers: &[Arc<dyn NotifierPort>], event: &NotificationEvent) -> usize {
+    let mut delivered = 0;
+    for notifier in notifiers.iter().filter(|n| n.accepts(event)) {
+        let mut result = notifier.notify(event).await;
+        if let Err(e) = &result {
+            debug!(notifier = notifier.name(), event = event.kind(), error = %e, "notification failed; retrying once");
+            tokio::time::sleep(RETRY_DELAY).await;
+            result = notifier.notify(event).await;
+        }
+        match result {
+            Ok(()) => delivered += 1,
+            Err(e) => {
+                warn!(notifier = notifier.name(), event = event.kind(), error = %e, "failed to deliver notification")
+            }
+        }
+    }
+    delivered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::DomainError;
+    use std::sync::Mutex;
+
+    /// Fails its first `failures` deliveries; accepts only errors when `errors_only`.
+    struct FlakyNotifier {
+        failures: Mutex<u32>,
+        calls: Mutex<u32>,
+        errors_only: bool,
+    }
+
+    impl FlakyNotifier {
+        fn new(failures: u32, errors_only: bool) -> Arc<Self> {
+            Arc::new(Self {
+                failures: Mutex::new(failures),
+                calls: Mutex::new(0),
+                errors_only,
+            })
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl NotifierPort for FlakyNotifier {
+        fn name(&self) -> &'static str {
+            "flaky"
+        }
+
+        fn accepts(&self, event: &NotificationEvent) -> bool {
+            !self.errors_only || matches!(event, NotificationEvent::Error { .. })
+        }
+
+        async fn notify(&self, _event: &NotificationEvent) -> Result<(), DomainError> {
+            *self.calls.lock().unwrap() += 1;
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                return Err(DomainError::Notify("503".to_string()));
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_notify_all_retries_once_and_filters() {
+        let flaky = FlakyNotifier::new(1, false);
+        let broken = FlakyNotifier::new(5, false);
+        let errors = FlakyNotifier::new(0, true);
+        let notifiers: Vec<Arc<dyn NotifierPort>> =
+            vec![flaky.clone(), broken.clone(), errors.clone()];
+        let event = NotificationEvent::SyncCompleted {
+            chats: 2,
+            messages: 10,
+            failed: 0,
+        };
+
+        assert_eq!(notify_all(&notifiers, &event).await, 1);
+        assert_eq!(*flaky.calls.lock().unwrap(), 2);
+        assert_eq!(*broken.calls.lock().unwrap(), 2);
+        assert_eq!(*errors.calls.lock().unwrap(), 0);
+    }
+}
@@ -0,0 +1,268 @@
+//! Purge one chat from the archive.
+//!
+//! Deletes the chat's database rows in one transaction ([`RepoPort::delete_chat_data`]),
+//! forgets its sync checkpoint, removes its media files (indexed ones plus any
+//! `{chat_id}_*` file left without an index row) and blacklists the chat so the next sync
+//! does not fetch it again. Like maintenance it refuses to run while a sync or the watcher
+//! is active (see [`ActivityFlag`]).
+
+use crate::domain::{ChatPurge, DomainError};
+use crate::ports::{RepoPort, StatePort};
+use crate::shared::activity_flag::ActivityFlag;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Name of the purge task in the activity flag.
+const TASK: &str = "purge";
+
+/// What a purge removed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PurgeReport {
+    pub chat_id: i64,
+    pub purge: ChatPurge,
+    /// False when the chat already was on the blacklist.
+    pub blacklisted: bool,
+}
+
+impl fmt::Display for PurgeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Chat {}: {} row(s) deleted",
+            self.chat_id,
+            self.purge.total_rows()
+        )?;
+        for (table, rows) in &self.purge.rows {
+            writeln!(f, "  {}: {}", table, rows)?;
+        }
+        write!(
+            f,
+            "Files: {} deleted, {} bytes",
+            self.purge.files, self.purge.bytes
+        )?;
+        if self.blacklisted {
+            write!(f, "\nAdded to the blacklist")?;
+        }
+        Ok(())
+    }
+}
+
+/// Removes a chat from the archive.
+pub struct PurgeService {
+    repo: Arc<dyn RepoPort>,
+    state: Arc<dyn StatePort>,
+    activity: ActivityFlag,
+    media_dir: PathBuf,
+}
+
+impl PurgeService {
+    pub fn new(
+        repo: Arc<dyn RepoPort>,
+        state: Arc<dyn StatePort>,
+        activity: ActivityFlag,
+        media_dir: PathBuf,
+    ) -> Self {
+        Self {
+            repo,
+            state,
+            activity,
+            media_dir,
+        }
+    }
+
+    /// Delete everything stored for `chat_id` and blacklist it. Fails when a sync or the
+    /// watcher is running.
+    pub async fn purge(&self, chat_id: i64) -> Result<PurgeReport, DomainError> {
+        let _exclusive = self.activity.exclusive(TASK)?;
+        let mut purge = self.repo.delete_chat_data(chat_id).await?;
+        self.state.clear(chat_id).await?;
+        let (files, bytes) = self.delete_media(chat_id, &purge.media_paths).await?;
+        purge.files += files;
+        purge.bytes += bytes;
+
+        let mut blacklist = self.repo.get_blacklisted_ids().await?;
+        let blacklisted = blacklist.insert(chat_id);
+        if blacklisted {
+            self.repo.update_blacklist(blacklist).await?;
+        }
+        info!(
+            chat_id,
+            rows = purge.total_rows(),
+            files = purge.files,
+            bytes = purge.bytes,
+            "chat purged"
+        );
+        Ok(PurgeReport {
+            chat_id,
+            purge,
+            blacklisted,
+        })
+    }
+
+    /// Remove the indexed media files, the chat's media folder and every flat file named
+    /// after the chat (from before per-chat folders). Returns the files and bytes removed.
+    async fn delete_media(
+        &self,
+        chat_id: i64,
+        indexed: &[String],
+    ) -> Result<(u64, u64), DomainError> {
+        let mut paths: BTreeSet<PathBuf> = indexed.iter().map(|p| self.media_dir.join(p)).collect();
+        let chat_dir = self.media_dir.join(chat_id.to_string());
+        let prefix = format!("{}_", chat_id);
+        for (dir, prefix) in [(&self.media_dir, Some(&prefix)), (&chat_dir, None)] {
+            match tokio::fs::read_dir(dir).await {
+                Ok(mut entries) => {
+                    while let Some(entry) = entries
+                        .next_entry()
+                        .await
+                        .map_err(|e| DomainError::Media(e.to_string()))?
+                    {
+                        if prefix.is_none_or(|p| entry.file_name().to_string_lossy().starts_with(p))
+                        {
+                            paths.insert(entry.path());
+                        }
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(DomainError::Media(format!("read {}: {}", dir.display(), e)));
+                }
+            }
+        }
+        let (mut files, mut bytes) = (0, 0);
+        for path in paths {
+            let Ok(meta) = tokio::fs::metadata(&path).await else {
+                continue;
+            };
+            if !meta.is_file() {
+                continue;
+            }
+            match tokio::fs::remove_file(&path).await {
+                Ok(()) => {
+                    files += 1;
+                    bytes += meta.len();
+                }
+                Err(e) => warn!(path = %path.display(), error = %e, "failed to delete media file"),
+            }
+        }
+        // Only succeeds once empty; anything left there is not ours to delete.
+        let _ = tokio::fs::remove_dir(&chat_dir).await;
+        Ok((files, bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::persistence::sqlite_repo::SqliteRepo;
+    use crate::adapters::persistence::state_sqlite::StateSqlite;
+    use crate::domain::{
+        AnalysisResult, Granularity, MediaFile, MediaReference, MediaStatus, MediaType, Message,
+        WeekGroup,
+    };
+    use crate::ports::{AnalysisLogPort, MediaIndexPort};
+    use crate::testing::fake_tg::text_message;
+    use std::path::Path;
+
+    fn message(chat_id: i64, id: i32) -> Message {
+        Message {
+            media: Some(MediaReference {
+                message_id: id,
+                chat_id,
+                media_type: MediaType::Photo,
+                opaque_ref: "ref".to_string(),
+                size_bytes: None,
+                filename: None,
+                mime_type: None,
+            }),
+            ..text_message(chat_id, id, 1704067200 + id as i64, &format!("m{}", id))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_purge_removes_rows_state_and_files_of_one_chat() {
+        let base = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join("test_purge");
+        let _ = std::fs::remove_dir_all(&base);
+        let repo = Arc::new(SqliteRepo::connect(&base).await.unwrap());
+        let state = Arc::new(StateSqlite::new(repo.clone()));
+        let media_dir = base.join("media");
+        std::fs::create_dir_all(&media_dir).unwrap();
+        for chat_id in [1, 11] {
+            repo.save_messages(chat_id, &[message(chat_id, 1), message(chat_id, 2)])
+                .await
+                .unwrap();
+            state.set_last_message_id(chat_id, 2).await.unwrap();
+            repo.upsert_media_file(&MediaFile {
+                chat_id,
+                message_id: 1,
+                media_type: MediaType::Photo,
+                rel_path: format!("{}/1.jpg", chat_id),
+                size_bytes: Some(3),
+                expected_size: None,
+                sha256: None,
+                status: MediaStatus::Done,
+                message_date: None,
+                remote_key: None,
+            })
+            .await
+            .unwrap();
+            // Indexed file, one only the message refers to and a flat one from before
+            // per-chat folders.
+            std::fs::create_dir_all(media_dir.join(chat_id.to_string())).unwrap();
+            for name in ["{}/1.jpg", "{}/2.jpg", "{}_3.jpg"] {
+                let name = name.replace("{}", &chat_id.to_string());
+                std::fs::write(media_dir.join(name), b"abc").unwrap();
+            }
+        }
+        repo.save_analysis(&AnalysisResult {
+            chat_id: 1,
+            week_group: WeekGroup::new("2024-01"),
+            granularity: Granularity::Week,
+            summary: "s".to_string(),
+            key_topics: Vec::new(),
+            action_items: Vec::new(),
+            analyzed_at: 1704067200,
+        })
+        .await
+        .unwrap();
+
+        let activity = ActivityFlag::new();
+        let service = PurgeService::new(
+            repo.clone(),
+            state.clone(),
+            activity.clone(),
+            media_dir.clone(),
+        );
+        let sync = activity.begin("sync").unwrap();
+        assert!(matches!(service.purge(1).await, Err(DomainError::State(_))));
+        drop(sync);
+
+        let report = service.purge(1).await.unwrap();
+        assert_eq!(
+            report.purge.rows,
+            vec![("messages", 2), ("analysis_log", 1), ("media_files", 1)]
+        );
+        assert_eq!((report.purge.files, report.purge.bytes), (3, 9));
+        assert!(report.blacklisted);
+        assert!(report.to_string().contains("Chat 1: 4 row(s) deleted"));
+
+        assert!(repo.get_messages(1, 10, 0).await.unwrap().is_empty());
+        assert_eq!(state.get_last_message_id(1).await.unwrap(), 0);
+        assert!(repo.get_blacklisted_ids().await.unwrap().contains(&1));
+        assert!(!media_dir.join("1").exists());
+        assert!(!media_dir.join("1_3.jpg").exists());
+        // Chat 11 shares the id prefix digits but is untouched.
+        assert_eq!(repo.get_messages(11, 10, 0).await.unwrap().len(), 2);
+        assert_eq!(state.get_last_message_id(11).await.unwrap(), 2);
+        assert!(media_dir.join("11/1.jpg").exists());
+        assert!(media_dir.join("11/2.jpg").exists());
+        assert!(media_dir.join("11_3.jpg").exists());
+
+        assert!(!service.purge(1).await.unwrap().blacklisted);
+    }
+}
@@ -0,0 +1,414 @@
+//! Startup recovery: finish work an unclean exit left behind.
+//!
+//! Runs once at startup, before the menu or daemon loop. Each [`RecoveryStep`] repairs one
+//! kind of leftover through existing ports (temp files, pending media, tracker dead letters);
+//! a failing step is logged and reported but never stops the others or the startup.
+
+use crate::domain::{DomainError, MediaReference};
+use crate::ports::{MediaIndexPort, TaskDeadLetterPort};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::fs;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+/// Pending media untouched for this long was interrupted, not in flight.
+pub const DEFAULT_PENDING_MEDIA_AGE: Duration = Duration::from_secs(10 * 60);
+
+/// Stale pending media rows fetched per page.
+const PENDING_PAGE: u32 = 500;
+
+/// One recovery task.
+#[async_trait::async_trait]
+pub trait RecoveryStep: Send + Sync {
+    /// Short name for logs and the failure summary.
+    fn name(&self) -> &'static str;
+
+    /// Run the step. Returns a summary fragment (e.g. "re-queued 12 media"), or `None` when
+    /// there was nothing to do.
+    async fn run(&self) -> Result<Option<String>, DomainError>;
+}
+
+/// Outcome of a recovery run, in step order.
+#[derive(Debug, Default)]
+pub struct RecoveryReport {
+    /// Summary fragments of steps that did something.
+    pub done: Vec<String>,
+    /// (step name, error) of steps that failed.
+    pub failed: Vec<(&'static str, String)>,
+}
+
+impl RecoveryReport {
+    /// One line, e.g. "re-queued 12 media, cleaned 2 partial files".
+    pub fn summary(&self) -> String {
+        let mut parts = self.done.clone();
+        parts.extend(
+            self.failed
+                .iter()
+                .map(|(name, e)| format!("{} failed: {}", name, e)),
+        );
+        if parts.is_empty() {
+            "nothing to recover".to_string()
+        } else {
+            parts.join(", ")
+        }
+    }
+}
+
+/// Runs the recovery steps in order.
+pub struct RecoveryService {
+    steps: Vec<Box<dyn RecoveryStep>>,
+}
+
+impl RecoveryService {
+    pub fn new(steps: Vec<Box<dyn RecoveryStep>>) -> Self {
+        Self { steps }
+    }
+
+    /// Run every step; failures are collected, not propagated.
+    pub async fn run(&self) -> RecoveryReport {
+        let mut report = RecoveryReport::default();
+        for step in &self.steps {
+            match step.run().await {
+                Ok(Some(done)) => report.done.push(done),
+                Ok(None) => {}
+                Err(e) => {
+                    warn!(step = step.name(), error = %e, "recovery step failed");
+                    report.failed.push((step.name(), e.to_string()));
+                }
+            }
+        }
+        info!(summary = %report.summary(), "startup recovery finished");
+        report
+    }
+}
+
+/// Delete leftover temp files (`*.part` partial downloads, `*.tmp` from interrupted atomic
+/// writes) directly inside the given directories. Missing directories are skipped.
+pub struct SweepTempFiles {
+    dirs: Vec<PathBuf>,
+    /// Directories whose subdirectories are swept too (the media directory's chat folders).
+    nested: Vec<PathBuf>,
+}
+
+impl SweepTempFiles {
+    pub fn new(dirs: Vec<PathBuf>) -> Self {
+        Self {
+            dirs,
+            nested: Vec::new(),
+        }
+    }
+
+    /// Also sweep the immediate subdirectories of `dirs`.
+    pub fn with_nested(mut self, dirs: Vec<PathBuf>) -> Self {
+        self.nested = dirs;
+        self
+    }
+
+    /// Subdirectories of the nested directories.
+    async fn subdirectories(&self) -> Vec<PathBuf> {
+        let mut subdirs = Vec::new();
+        for dir in &self.nested {
+            let Ok(mut entries) = fs::read_dir(dir).await else {
+                continue;
+            };
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                if entry.file_type().await.is_ok_and(|t| t.is_dir()) {
+                    subdirs.push(entry.path());
+                }
+            }
+        }
+        subdirs
+    }
+}
+
+#[async_trait::async_trait]
+impl RecoveryStep for SweepTempFiles {
+    fn name(&self) -> &'static str {
+        "temp file sweep"
+    }
+
+    async fn run(&self) -> Result<Option<String>, DomainError> {
+        let mut cleaned = 0u64;
+        let subdirs = self.subdirectories().await;
+        for dir in self.dirs.iter().chain(&subdirs) {
+            let mut entries = match fs::read_dir(dir).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    return Err(DomainError::State(format!("read {}: {}", dir.display(), e)));
+                }
+            };
+            while let Some(entry) = entries
+                .next_entry()
+                .await
+                .map_err(|e| DomainError::State(format!("read {}: {}", dir.display(), e)))?
+            {
+                let path = entry.path();
+                let is_temp = matches!(
+                    path.extension().and_then(|e| e.to_str()),
+                    Some("part" | "tmp")
+                );
+                if !is_temp || !entry.file_type().await.is_ok_and(|t| t.is_file()) {
+                    continue;
+                }
+                fs::remove_file(&path)
+                    .await
+                    .map_err(|e| DomainError::State(format!("remove {}: {}", path.display(), e)))?;
+                info!(path = %path.display(), "removed leftover temp file");
+                cleaned += 1;
+            }
+        }
+        Ok((cleaned > 0).then(|| format!("cleaned {} partial file(s)", cleaned)))
+    }
+}
+
+/// Send media still `pending` after `min_age` back to the media worker.
+pub struct RequeuePendingMedia {
+    index: Arc<dyn MediaIndexPort>,
+    media_tx: mpsc::Sender<MediaReference>,
+    min_age: Duration,
+}
+
+impl RequeuePendingMedia {
+    pub fn new(
+        index: Arc<dyn MediaIndexPort>,
+        media_tx: mpsc::Sender<MediaReference>,
+        min_age: Duration,
+    ) -> Self {
+        Self {
+            index,
+            media_tx,
+            min_age,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl RecoveryStep for RequeuePendingMedia {
+    fn name(&self) -> &'static str {
+        "pending media re-queue"
+    }
+
+    async fn run(&self) -> Result<Option<String>, DomainError> {
+        let cutoff = chrono::Utc::now().timestamp() - self.min_age.as_secs() as i64;
+        let mut requeued = 0u64;
+        let mut left = 0u64;
+        let mut after = None;
+        loop {
+            let page = self
+                .index
+                .find_stale_pending_media(cutoff, after, PENDING_PAGE)
+                .await?;
+            let Some(last) = page.last() else {
+                break;
+            };
+            after = Some((last.chat_id, last.message_id));
+            for media_ref in page {
+                if media_ref.opaque_ref.is_empty() {
+                    continue;
+                }
+                // Don't block startup on a full queue; the rest stays pending for next time.
+                if left > 0 || self.media_tx.try_send(media_ref).is_err() {
+                    left += 1;
+                } else {
+                    requeued += 1;
+                }
+            }
+        }
+        if left > 0 {
+            warn!(
+                left,
+                "media queue full; remaining pending media are re-queued on next start"
+            );
+        }
+        Ok((requeued > 0).then(|| format!("re-queued {} media", requeued)))
+    }
+}
+
+/// Retry task-tracker creations parked after a failure.
+pub struct ReplayTrackerDeadLetters {
+    dead_letters: Arc<dyn TaskDeadLetterPort>,
+}
+
+impl ReplayTrackerDeadLetters {
+    pub fn new(dead_letters: Arc<dyn TaskDeadLetterPort>) -> Self {
+        Self { dead_letters }
+    }
+}
+
+#[async_trait::async_trait]
+impl RecoveryStep for ReplayTrackerDeadLetters {
+    fn name(&self) -> &'static str {
+        "tracker dead-letter replay"
+    }
+
+    async fn run(&self) -> Result<Option<String>, DomainError> {
+        let (delivered, parked) = self.dead_letters.replay_dead_letters().await?;
+        Ok(match (delivered, parked) {
+            (0, 0) => None,
+            (d, 0) => Some(format!("delivered {} parked task(s)", d)),
+            (d, p) => Some(format!(
+                "delivered {} parked task(s) ({} still failing)",
+                d, p
+            )),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::persistence::sqlite_repo::SqliteRepo;
+    use crate::domain::{MediaFile, MediaStatus, MediaType, Message};
+    use crate::ports::RepoPort;
+    use crate::testing::fake_tg::text_message;
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    struct FakeStep {
+        name: &'static str,
+        result: Result<Option<&'static str>, &'static str>,
+        ran: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl RecoveryStep for FakeStep {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+        async fn run(&self) -> Result<Option<String>, DomainError> {
+            self.ran.lock().unwrap().push(self.name);
+            self.result
+                .map(|done| done.map(String::from))
+                .map_err(|e| DomainError::State(e.to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_all_steps_run_despite_failures() {
+        let ran = Arc::new(Mutex::new(Vec::new()));
+        let step = |name, result| -> Box<dyn RecoveryStep> {
+            Box::new(FakeStep {
+                name,
+                result,
+                ran: ran.clone(),
+            })
+        };
+        let service = RecoveryService::new(vec![
+            step("sweep", Ok(Some("cleaned 2 partial file(s)"))),
+            step("media", Err("db locked")),
+            step("idle", Ok(None)),
+            step("tracker", Ok(Some("delivered 1 parked task(s)"))),
+        ]);
+
+        let report = service.run().await;
+        assert_eq!(
+            *ran.lock().unwrap(),
+            vec!["sweep", "media", "idle", "tracker"]
+        );
+        assert_eq!(
+            report.summary(),
+            "cleaned 2 partial file(s), delivered 1 parked task(s), media failed: State error: db locked"
+        );
+        assert_eq!(
+            RecoveryService::new(Vec::new()).run().await.summary(),
+            "nothing to recover"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sweep_removes_only_temp_files() {
+        let dir = temp_dir("test_recovery_sweep");
+        for name in ["1_2.jpg", "1_3.jpg.part", "manifest.jsonl.tmp", "notes.txt"] {
+            std::fs::write(dir.join(name), "x").unwrap();
+        }
+        std::fs::create_dir(dir.join("keep.tmp")).unwrap();
+        std::fs::create_dir(dir.join("1")).unwrap();
+        for name in ["1/4.jpg", "1/5.pdf.part"] {
+            std::fs::write(dir.join(name), "x").unwrap();
+        }
+
+        let step = SweepTempFiles::new(vec![dir.clone(), dir.join("missing")])
+            .with_nested(vec![dir.clone()]);
+        assert_eq!(
+            step.run().await.unwrap().as_deref(),
+            Some("cleaned 3 partial file(s)")
+        );
+        assert!(dir.join("1/4.jpg").exists());
+        assert!(!dir.join("1/5.pdf.part").exists());
+        let mut left: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        left.sort();
+        assert_eq!(left, vec!["1", "1_2.jpg", "keep.tmp", "notes.txt"]);
+        assert_eq!(step.run().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_requeue_pending_media() {
+        let dir = temp_dir("test_recovery_requeue");
+        let repo = Arc::new(SqliteRepo::connect(&dir).await.unwrap());
+        let media = |id: i32| MediaReference {
+            message_id: id,
+            chat_id: 5,
+            media_type: MediaType::Photo,
+            opaque_ref: format!("ref-{}", id),
+            size_bytes: None,
+            filename: None,
+            mime_type: None,
+        };
+        let messages: Vec<Message> = (1..=3)
+            .map(|id| Message {
+                media: Some(media(id)),
+                ..text_message(5, id, 1704067200, "")
+            })
+            .collect();
+        repo.save_messages(5, &messages).await.unwrap();
+        for (id, status) in [
+            (1, MediaStatus::Pending),
+            (2, MediaStatus::Done),
+            (3, MediaStatus::Pending),
+        ] {
+            repo.upsert_media_file(&MediaFile {
+                chat_id: 5,
+                message_id: id,
+                media_type: MediaType::Photo,
+                rel_path: format!("5_{}.jpg", id),
+                size_bytes: None,
+                expected_size: None,
+                sha256: None,
+                status,
+                message_date: None,
+                remote_key: None,
+            })
+            .await
+            .unwrap();
+        }
+
+        // Just written: too recent to count as interrupted.
+        let (tx, mut rx) = mpsc::channel(10);
+        let recent = RequeuePendingMedia::new(repo.clone(), tx.clone(), DEFAULT_PENDING_MEDIA_AGE);
+        assert_eq!(recent.run().await.unwrap(), None);
+
+        let step = RequeuePendingMedia::new(repo.clone(), tx, Duration::ZERO);
+        assert_eq!(
+            step.run().await.unwrap().as_deref(),
+            Some("re-queued 2 media")
+        );
+        assert_eq!(rx.recv().await.unwrap().opaque_ref, "ref-1");
+        assert_eq!(rx.recv().await.unwrap().opaque_ref, "ref-3");
+        assert!(rx.try_recv().is_err());
+    }
+}
@@ -6,16 +6,54 @@
 //! - **Strict client-side boundary enforcement:** We do not trust the Telegram API to
 //!   honour min_id/max_id when offset_id is present. All boundary checks and loop
 //!   termination are performed client-side; batches are filtered before processing.
-//! - Sends media refs to bounded mpsc channel for async download; send().await provides backpressure when queue is full.
-//! - Updates state only after successful save
-//! - Configurable delay between batches (SYNC_DELAY_MS) to avoid FLOOD_WAIT
+//! - Records media refs in the persistent media queue (so a crash loses nothing), then sends them
+//!   to the bounded mpsc channel for async download; send().await provides backpressure when full.
+//! - Updates state only after successful save. An initial backfill (no checkpoint yet) moves
+//!   the checkpoint (last_message_id) to the newest stored id after every batch and keeps the
+//!   lowest stored id as the backfill low-water mark; when it is interrupted (crash, error),
+//!   the next unbounded sync first resumes paging down from the mark to the start of the chat,
+//!   then does the usual incremental pass. An incremental pass moves the checkpoint once it has
+//!   paged down to it, so an interrupted one is simply repeated
+//! - Request pacing (TG_SYNC_RATE_HISTORY_PER_MIN) and FloodWait slowdown live in the gateway's
+//!   rate limiter, shared by every sync this service runs (so concurrent chat syncs together
+//!   stay at the configured rate)
+//! - Rolling ETA per chat (EMA of batch throughput) reported through the ProgressPort
+//! - Optional date range: messages outside it are not saved, and pagination stops at the first
+//!   message older than its start (history comes newest-first). A bounded sync only moves the
+//!   checkpoint when it covered everything down to the old checkpoint, so a later unbounded
+//!   sync still fetches what the range left out. Until then the chat is flagged as having a
+//!   sync gap, which keeps the audit from clamping the checkpoint up over it.
+//! - Forum topics: syncing a forum chat refreshes its topic titles; a topic selection keeps only
+//!   those topics' messages and, like a date range, leaves the checkpoint in place when it skipped
+//!   any, so a later full sync still fetches the other topics
+//! - Service messages (joins, leaves, renames, pins, calls) come back from the gateway as chat
+//!   events and are stored separately from messages; a date range applies to them too
+//! - Edit rescan: the newest `edit_window` messages at or below the checkpoint are fetched
+//!   again; those whose text changed are re-saved so the repo records the old version in
+//!   `edit_history` (the forward pass alone never sees edits of already-synced messages);
+//!   polls in the window are re-saved with their latest results, without an edit
+//! - Dry run: [`SyncService::plan_sync`] estimates what a sync would fetch from the newest
+//!   message of each chat, saving nothing and leaving checkpoints alone
 
-use crate::domain::{DomainError, MediaReference};
-use crate::ports::{RepoPort, StatePort, TgGateway};
+use crate::domain::{
+    Chat, ChatEvent, ChatStats, DomainError, GENERAL_TOPIC_ID, MediaFilter, MediaReference,
+    MediaStatus, Message, SyncProgress, TimeRange, TopicFilter,
+};
+use crate::ports::{
+    MediaIndexPort, MediaQueuePort, ProgressPort, RepoPort, SettingsPort, StatePort, TgGateway,
+};
+use crate::shared::activity_flag::ActivityFlag;
+use crate::shared::eta::{EtaEstimator, format_eta};
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::mpsc;
-use tracing::{info, warn};
+use std::time::{Duration, Instant};
+use tokio::sync::{Semaphore, mpsc};
+use tokio::task::JoinSet;
+use tracing::{debug, info, warn};
 
 /// Sync service. Coordinates incremental text sync and media pipeline.
 pub struct SyncService {
@@ -23,58 +61,184 @@ pub struct SyncService {
     repo: Arc<dyn RepoPort>,
     state: Arc<dyn StatePort>,
     media_tx: mpsc::Sender<MediaReference>,
-    /// Delay between message batch requests to avoid FLOOD_WAIT.
-    delay: Duration,
+    /// Durable media download queue; rows are written before refs go to the channel.
+    media_queue: Arc<dyn MediaQueuePort>,
+    /// Remembers the chats picked for the last manual Full Backup.
+    settings: Arc<dyn SettingsPort>,
+    /// Already-synced messages re-fetched per sync to catch edits. 0 disables the rescan.
+    edit_window: u32,
+    /// Where multi-chat sync summaries are written (`data/reports`).
+    reports_dir: PathBuf,
+    /// Optional progress reporter (TUI progress bar). When None, progress is only logged.
+    progress: Option<Arc<dyn ProgressPort>>,
+    /// Marks a sync as running; maintenance refuses to start meanwhile.
+    activity: ActivityFlag,
+    /// Media index and directory, to find media never downloaded (see [`Self::backfill_media`]).
+    media_files: Option<(Arc<dyn MediaIndexPort>, PathBuf)>,
 }
 
 impl SyncService {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         tg: Arc<dyn TgGateway>,
         repo: Arc<dyn RepoPort>,
         state: Arc<dyn StatePort>,
         media_tx: mpsc::Sender<MediaReference>,
-        delay: Duration,
+        media_queue: Arc<dyn MediaQueuePort>,
+        settings: Arc<dyn SettingsPort>,
+        edit_window: u32,
+        reports_dir: PathBuf,
+        progress: Option<Arc<dyn ProgressPort>>,
+        activity: ActivityFlag,
     ) -> Self {
         Self {
             tg,
             repo,
             state,
             media_tx,
-            delay,
+            media_queue,
+            settings,
+            edit_window,
+            reports_dir,
+            progress,
+            activity,
+            media_files: None,
         }
     }
 
+    /// Enable [`Self::backfill_media`]: `index` lists downloaded files under `media_dir`.
+    pub fn with_media_index(mut self, index: Arc<dyn MediaIndexPort>, media_dir: PathBuf) -> Self {
+        self.media_files = Some((index, media_dir));
+        self
+    }
+
     /// Sync a single chat. Fetches all new messages (id > last_message_id) via pagination.
     /// Forward history filling: paginates from newest down to oldest. Loop termination
     /// is client-side: we break when we see any message with id <= min_id, not when the
     /// API returns empty (API may ignore min_id/max_id). Batches are filtered to the
-    /// requested range before processing. If `include_media` is false, message text is
-    /// saved but media files are not downloaded.
+    /// requested range before processing. Only media passing `media` is queued for download;
+    /// skipped media stays referenced in the saved message for a later sync. With `range`,
+    /// only messages dated inside it are saved. An unbounded sync first finishes an
+    /// interrupted initial backfill (see [`StatePort::get_backfill_low_id`]). The chat's
+    /// stored settings ([`RepoPort::get_chat_settings`]) override `limit` and `media`.
     pub async fn sync_chat(
         &self,
         chat_id: i64,
         limit: i32,
-        include_media: bool,
+        media: &MediaFilter,
+        range: Option<TimeRange>,
+    ) -> Result<SyncStats, DomainError> {
+        self.sync_chat_inner(chat_id, limit, media, range, None, None)
+            .await
+    }
+
+    /// Same as [`Self::sync_chat`], additionally reporting per-batch progress and a rolling
+    /// ETA through the ProgressPort. The remaining-message estimate is the dialog's
+    /// `approx_message_count` minus the last synced id; without it the ETA is unknown.
+    /// For a forum, topic titles are refreshed first and `topics` (when set) limits the sync
+    /// to those topics.
+    pub async fn sync_chat_with_progress(
+        &self,
+        chat: &Chat,
+        limit: i32,
+        media: &MediaFilter,
+        range: Option<TimeRange>,
+        topics: Option<&HashSet<i32>>,
+    ) -> Result<SyncStats, DomainError> {
+        if chat.is_forum {
+            self.refresh_forum_topics(chat.id).await;
+        }
+        let progress = self.progress.as_deref();
+        let result = self
+            .sync_chat_inner(
+                chat.id,
+                limit,
+                media,
+                range,
+                topics,
+                progress.map(|p| (p, chat)),
+            )
+            .await;
+        if let Some(p) = progress {
+            let (synced, media_queued) = result.as_ref().map_or((0, 0), |s| {
+                (s.messages_synced as u64, s.media_queued as u64)
+            });
+            p.chat_finished(chat.id, synced, media_queued);
+        }
+        result
+    }
+
+    async fn sync_chat_inner(
+        &self,
+        chat_id: i64,
+        limit: i32,
+        media: &MediaFilter,
+        range: Option<TimeRange>,
+        topics: Option<&HashSet<i32>>,
+        report: Option<(&dyn ProgressPort, &Chat)>,
     ) -> Result<SyncStats, DomainError> {
+        let _running = self.activity.begin("sync")?;
+        let started = Instant::now();
+        // Per-chat overrides win over the call-level choice.
+        let settings = self.repo.get_chat_settings(chat_id).await?;
+        let media = &settings.media_filter(media);
+        let limit = settings.limit(limit);
+        if !settings.is_default() {
+            debug!(chat_id, overrides = %settings.label(), "per-chat settings applied");
+        }
+        let bounded = range.is_some() || topics.is_some();
+        let mut total_synced = 0usize;
+        let mut total_media_queued = 0usize;
+        let mut total_media_skipped = 0usize;
+        let mut channel_closed = false;
+        // An earlier backfill stopped halfway down: fill in the history below it first.
+        let backfill_low = self.state.get_backfill_low_id(chat_id).await?;
+        if backfill_low > 0 && !bounded {
+            let resumed = self
+                .resume_backfill(chat_id, backfill_low, limit, media)
+                .await?;
+            total_synced += resumed.messages;
+            total_media_queued += resumed.media_queued;
+            total_media_skipped += resumed.media_skipped;
+            channel_closed = resumed.channel_closed;
+        }
         let last_known_id = self.state.get_last_message_id(chat_id).await?;
         let min_id = last_known_id;
         let mut max_id = 0i32; // 0 = no upper bound; we set max_id = batch_min to fetch older chunks
 
-        let mut total_synced = 0usize;
-        let mut total_media_queued = 0usize;
         let mut current_head_id = last_known_id;
-        let mut channel_closed = false;
+        let mut synced_ids: Option<(i32, i32)> = None;
+        // Bounded sync (date range or topic selection): a new message outside it was left out,
+        // so the checkpoint must not move past the gap.
+        let mut left_gap = false;
+        // Initial backfill: progress is kept per batch (checkpoint plus low-water mark).
+        let backfill = last_known_id == 0 && !bounded;
+        // The backfill stored messages but has not reached the start of the chat yet.
+        let mut low_pending = false;
+        // Paging got down to the checkpoint (or the start of the chat).
+        let mut reached_bottom = false;
+
+        // Message ids grow roughly one per message, so everything above the checkpoint is new.
+        let expected = report
+            .and_then(|(_, chat)| chat.approx_message_count)
+            .map(|n| (n as i64 - last_known_id as i64).max(0) as u64);
+        let mut eta = EtaEstimator::default();
+        if let Some((progress, chat)) = report {
+            progress.chat_started(chat_id, &chat.title, expected);
+        }
 
         loop {
             if channel_closed {
                 break;
             }
 
+            let batch_started = Instant::now();
             let raw = self.tg.get_messages(chat_id, min_id, max_id, limit).await?;
 
             // Do not use empty list as termination signal: API may ignore min_id/max_id and
             // return out-of-range messages; we enforce boundaries client-side.
             if raw.is_empty() {
+                reached_bottom = true;
                 break;
             }
 
@@ -82,7 +246,12 @@ impl SyncService {
             // messages with id <= min_id. We must stop as soon as we see one, even if the
             // batch is full, to avoid infinite re-fetching or corrupting state.
             let reached_min = raw.iter().any(|m| m.id <= min_id);
+            reached_bottom |= reached_min;
             let raw_min_id = raw.iter().map(|m| m.id).min();
+            // Newest-first: once a message predates the range, everything after it does too.
+            let reached_since = range.is_some_and(|r| raw.iter().any(|m| m.date < r.from));
+            left_gap |= range.is_some_and(|r| raw.iter().any(|m| m.id > min_id && m.date < r.from));
+            left_gap |= raw.iter().any(|m| m.id > min_id && !in_topics(topics, m));
 
             // Batch filtering: drop any message outside the requested range so we never
             // persist out-of-scope or duplicate data. Handles mixed batches where the
@@ -92,9 +261,20 @@ impl SyncService {
                 .filter(|m| {
                     let above_min = m.id > min_id;
                     let below_max = max_id == 0 || m.id < max_id;
-                    above_min && below_max
+                    let in_range = range.is_none_or(|r| r.from <= m.date && m.date < r.to);
+                    above_min && below_max && in_range && in_topics(topics, m)
                 })
                 .collect();
+            // Service messages fetched so far. They belong to the whole chat, so a topic
+            // selection keeps them; saving one twice only refreshes it.
+            let events: Vec<ChatEvent> = self
+                .tg
+                .get_events_from_batch()
+                .await?
+                .into_iter()
+                .filter(|e| range.is_none_or(|r| r.from <= e.date && e.date < r.to))
+                .collect();
+            self.repo.save_events(&events).await?;
 
             if !messages.is_empty() {
                 // Process in forward order (oldest -> newest) for consistent history filling
@@ -106,70 +286,120 @@ impl SyncService {
                     .map(|m| m.id)
                     .unwrap_or(0);
 
-                // Queue media refs for download. BACKPRESSURE: send().await yields here when the
-                // channel is full; the producer (sync) is thus rate-limited by the consumer (media
-                // worker / disk), preventing unbounded buffer growth and OOM.
-                if include_media {
-                    for msg in &messages {
-                        if let Some(ref m) = msg.media {
-                            match self.media_tx.send(m.clone()).await {
-                                Ok(()) => total_media_queued += 1,
-                                Err(_) => {
-                                    // Receiver dropped (e.g. media worker exited); exit loop cleanly.
-                                    warn!(
-                                        chat_id,
-                                        msg_id = msg.id,
-                                        "media channel closed, stopping media queue for this chat"
-                                    );
-                                    channel_closed = true;
-                                    break;
-                                }
-                            }
-                        }
-                    }
-                }
-                // When include_media is false, messages are saved but media is not queued for download
-
                 // Save batch (repo merges and sorts by id). Only in-range messages reach here.
-                self.repo.save_messages(chat_id, &messages).await?;
-
-                // Persist checkpoint immediately so interrupted syncs can resume from this batch
-                self.state.set_last_message_id(chat_id, batch_max).await?;
+                let stored = self.store_batch(chat_id, &messages, media).await?;
+                total_media_queued += stored.media_queued;
+                total_media_skipped += stored.media_skipped;
+                channel_closed = stored.channel_closed;
 
                 total_synced += messages.len();
                 current_head_id = current_head_id.max(batch_max);
 
+                // Backfill: persist progress immediately so an interrupted sync resumes below
+                // this batch: the low-water mark first (how far down it got), then the
+                // checkpoint (the newest id). Other syncs decide once at the end (see below).
+                if backfill {
+                    if batch_min > 1 {
+                        self.state.set_backfill_low_id(chat_id, batch_min).await?;
+                        low_pending = true;
+                    } else if low_pending {
+                        self.state.set_backfill_low_id(chat_id, 0).await?;
+                        low_pending = false;
+                    }
+                    self.state
+                        .set_last_message_id(chat_id, current_head_id)
+                        .await?;
+                }
+                synced_ids = Some(match synced_ids {
+                    Some((lo, hi)) => (lo.min(batch_min), hi.max(batch_max)),
+                    None => (batch_min, batch_max),
+                });
+
                 info!(
                     chat_id,
                     batch_size = messages.len(),
-                    batch_id_range = %format!("{}..{}", batch_min, batch_max),
-                    checkpoint = batch_max,
+                    first_msg_id = batch_min,
+                    last_msg_id = batch_max,
+                    checkpoint = current_head_id,
                     "batch saved, checkpoint advanced"
                 );
 
-                if reached_min {
+                if reached_min || reached_since {
                     // Client-side termination: we saw id <= min_id; stop even if we processed valid messages.
                     break;
                 }
                 max_id = batch_min;
             } else {
                 // Filtered to empty: either we crossed the lower bound or server sent only out-of-range ids.
-                if reached_min {
+                if reached_min || reached_since {
                     break;
                 }
                 // Avoid infinite loop: advance cursor past this page so next request differs.
                 max_id = raw_min_id.unwrap_or(max_id);
             }
 
-            // Rate limit: delay before next batch to avoid FLOOD_WAIT
-            tokio::time::sleep(self.delay).await;
+            // Throughput includes the rate limiter wait, the fetch (and any FloodWait inside it)
+            // and the save.
+            let batch_len = messages.len() as u64;
+            eta.record_batch(batch_len, batch_started.elapsed());
+            if batch_len > 0 {
+                let synced = total_synced as u64;
+                let remaining = expected.map(|e| e.saturating_sub(synced));
+                let update = SyncProgress {
+                    chat_id,
+                    synced,
+                    media_queued: total_media_queued as u64,
+                    remaining,
+                    rate_per_sec: eta.rate_per_sec(),
+                    eta: eta.eta(remaining),
+                };
+                debug!(chat_id, synced, eta = %format_eta(update.eta), "sync progress");
+                if let Some((progress, _)) = report {
+                    progress.chat_progress(&update);
+                }
+            }
+        }
+
+        // The backfill got down to the start of the chat: nothing is missing below.
+        if low_pending && reached_bottom {
+            self.state.set_backfill_low_id(chat_id, 0).await?;
+        }
+
+        // Incremental pass: paging got down to the old checkpoint, so everything up to the
+        // newest stored id is in the archive. Bounded sync: everything above the old checkpoint
+        // that is older than the range end is stored (and no other topic was skipped), so the
+        // newest stored id is safe too. Newer, out-of-range messages have higher ids and are
+        // fetched by the next sync.
+        if !backfill && reached_bottom && !left_gap && current_head_id > last_known_id {
+            self.state
+                .set_last_message_id(chat_id, current_head_id)
+                .await?;
+        } else if bounded && current_head_id > last_known_id {
+            // Stored above a checkpoint that stays put: the audit must not clamp it up.
+            self.state.set_sync_gap(chat_id, true).await?;
+        }
+        // An unbounded pass down to the checkpoint has filled whatever a bounded sync left out.
+        if !bounded && reached_bottom && self.state.has_sync_gap(chat_id).await? {
+            self.state.set_sync_gap(chat_id, false).await?;
         }
 
+        let edits_recorded = self.rescan_edits(chat_id, last_known_id).await?;
+        // Batches arrive newest-first, so replies are saved before the comments they answer;
+        // link comment threads to their channel posts once the whole range is stored.
         if total_synced > 0 {
+            let linked = self.repo.link_channel_comments(chat_id).await?;
+            if linked > 0 {
+                debug!(chat_id, linked, "comments linked to channel posts");
+            }
+        }
+
+        if total_synced > 0 || edits_recorded > 0 {
             info!(
                 chat_id,
                 count = total_synced,
                 media_queued = total_media_queued,
+                media_skipped = total_media_skipped,
+                edits_recorded,
                 last_id = current_head_id,
                 "sync completed"
             );
@@ -178,30 +408,1661 @@ impl SyncService {
         Ok(SyncStats {
             messages_synced: total_synced,
             media_queued: total_media_queued,
+            media_skipped: total_media_skipped,
+            edits_recorded,
+            synced_ids,
+            duration: started.elapsed(),
         })
     }
 
-    /// Sync multiple chats. Runs sequentially to respect rate limits.
+    /// Save one batch with its senders and queue its media. Media refs go to the persistent
+    /// queue first (the worker picks them up after a crash or restart), then on the channel.
+    /// BACKPRESSURE: send().await yields here when the channel is full; the producer (sync) is
+    /// thus rate-limited by the consumer (media worker / disk), preventing unbounded buffer
+    /// growth and OOM. Filtered-out media is not queued; its reference stays in the message.
+    async fn store_batch(
+        &self,
+        chat_id: i64,
+        messages: &[Message],
+        media: &MediaFilter,
+    ) -> Result<StoredBatch, DomainError> {
+        self.repo.save_messages(chat_id, messages).await?;
+        // Sender names for analysis CSVs, alerts and exports.
+        let users = self.tg.get_users_from_batch().await?;
+        self.repo.save_users(&users).await?;
+
+        let mut stored = StoredBatch {
+            media_queued: 0,
+            media_skipped: 0,
+            channel_closed: false,
+        };
+        if media.is_none() {
+            return Ok(stored);
+        }
+        let (refs, skipped): (Vec<MediaReference>, Vec<MediaReference>) = messages
+            .iter()
+            .filter_map(|m| m.media.clone())
+            .partition(|m| media.allows(m));
+        stored.media_skipped = skipped.len();
+        self.media_queue.enqueue_media(&refs).await?;
+        for m in refs {
+            let msg_id = m.message_id;
+            if self.media_tx.send(m).await.is_err() {
+                // Receiver dropped (e.g. media worker exited); the rows stay queued.
+                warn!(
+                    chat_id,
+                    msg_id, "media channel closed, stopping media queue for this chat"
+                );
+                stored.channel_closed = true;
+                break;
+            }
+            stored.media_queued += 1;
+        }
+        Ok(stored)
+    }
+
+    /// Continue an interrupted backfill: page down from the low-water mark `low` to the start
+    /// of the chat, storing every page. The mark follows every page and is cleared at the end;
+    /// it stays set when the media channel closes halfway.
+    async fn resume_backfill(
+        &self,
+        chat_id: i64,
+        low: i32,
+        limit: i32,
+        media: &MediaFilter,
+    ) -> Result<ResumedBackfill, DomainError> {
+        info!(chat_id, below_id = low, "resuming interrupted backfill");
+        let mut resumed = ResumedBackfill {
+            messages: 0,
+            media_queued: 0,
+            media_skipped: 0,
+            channel_closed: false,
+        };
+        let mut max_id = low;
+        loop {
+            // Boundaries are enforced client-side, as in the forward pass.
+            let mut page: Vec<Message> = self
+                .tg
+                .get_messages(chat_id, 0, max_id, limit)
+                .await?
+                .into_iter()
+                .filter(|m| m.id < max_id)
+                .collect();
+            let events = self.tg.get_events_from_batch().await?;
+            self.repo.save_events(&events).await?;
+            let Some(page_min) = page.iter().map(|m| m.id).min() else {
+                break;
+            };
+            page.sort_by_key(|m| m.id);
+            let stored = self.store_batch(chat_id, &page, media).await?;
+            resumed.messages += page.len();
+            resumed.media_queued += stored.media_queued;
+            resumed.media_skipped += stored.media_skipped;
+            if page_min <= 1 {
+                break;
+            }
+            self.state.set_backfill_low_id(chat_id, page_min).await?;
+            if stored.channel_closed {
+                resumed.channel_closed = true;
+                return Ok(resumed);
+            }
+            max_id = page_min;
+        }
+        self.state.set_backfill_low_id(chat_id, 0).await?;
+        info!(
+            chat_id,
+            count = resumed.messages,
+            "interrupted backfill completed"
+        );
+        Ok(resumed)
+    }
+
+    /// Re-fetch the newest `edit_window` messages with id <= `checkpoint` (one request) and
+    /// re-save those whose stored text differs. The repo upsert appends the stored version to
+    /// `edit_history`; unchanged messages are not written. Polls whose results changed are
+    /// re-saved too, without an edit. Returns the number of edits.
+    async fn rescan_edits(&self, chat_id: i64, checkpoint: i32) -> Result<usize, DomainError> {
+        if self.edit_window == 0 || checkpoint <= 0 {
+            return Ok(0);
+        }
+        let window = self.edit_window.min(i32::MAX as u32) as i32;
+        let floor = checkpoint.saturating_sub(window).max(0);
+        let fetched: Vec<_> = self
+            .tg
+            .get_messages(chat_id, floor, checkpoint.saturating_add(1), window)
+            .await?
+            .into_iter()
+            .filter(|m| m.id > floor && m.id <= checkpoint)
+            .collect();
+        if fetched.is_empty() {
+            return Ok(0);
+        }
+        let ids: Vec<i32> = fetched.iter().map(|m| m.id).collect();
+        let stored: HashMap<i32, Message> = self
+            .repo
+            .get_messages_by_ids(chat_id, &ids)
+            .await?
+            .into_iter()
+            .map(|m| (m.id, m))
+            .collect();
+        // Only messages already in the archive: a missing id is not an edit.
+        let mut changed: Vec<_> = fetched
+            .into_iter()
+            .filter(|m| {
+                stored
+                    .get(&m.id)
+                    .is_some_and(|s| s.text != m.text || s.poll != m.poll)
+            })
+            .collect();
+        if changed.is_empty() {
+            return Ok(0);
+        }
+        changed.sort_by_key(|m| m.id);
+        self.repo.save_messages(chat_id, &changed).await?;
+        let edited: Vec<i32> = changed
+            .iter()
+            .filter(|m| m.poll.is_none())
+            .map(|m| m.id)
+            .collect();
+        debug!(
+            chat_id,
+            ids = ?edited,
+            polls_refreshed = changed.len() - edited.len(),
+            "recorded edited messages"
+        );
+        Ok(edited.len())
+    }
+
+    /// Give failed media downloads a fresh set of attempts; the media worker picks them up
+    /// within its next queue poll. Returns the number of downloads re-queued.
+    pub async fn retry_failed_media(&self) -> Result<u64, DomainError> {
+        self.media_queue.retry_failed_media().await
+    }
+
+    /// Queue the media of `chat_id`'s stored messages that was never downloaded, e.g. after a
+    /// text-only backup, without fetching any history. References passing `media` that have no
+    /// index entry, or a finished one whose file is gone (and was never uploaded), go to the
+    /// persistent media queue. The media worker downloads them at the media rate limit and
+    /// backs off on FloodWait; queued rows survive restarts, and running this again only
+    /// queues what is still missing.
+    pub async fn backfill_media(
+        &self,
+        chat_id: i64,
+        media: &MediaFilter,
+    ) -> Result<MediaBackfill, DomainError> {
+        let Some((index, media_dir)) = &self.media_files else {
+            return Err(DomainError::Media(
+                "media backfill needs the media index".to_string(),
+            ));
+        };
+        let mut report = MediaBackfill::default();
+        let mut after = None;
+        let mut wake_worker = true;
+        loop {
+            let page = index
+                .list_chat_media(chat_id, after, BACKFILL_PAGE_SIZE)
+                .await?;
+            let Some((last, _)) = page.last() else {
+                break;
+            };
+            after = Some(last.message_id);
+            let full_page = page.len() as u32 == BACKFILL_PAGE_SIZE;
+            let mut missing = Vec::new();
+            for (reference, file) in page {
+                report.with_media += 1;
+                if reference.opaque_ref.is_empty() {
+                    continue;
+                }
+                if !media.allows(&reference) {
+                    report.filtered += 1;
+                    continue;
+                }
+                match file {
+                    Some(file) if file.status == MediaStatus::Done => {
+                        let path = media_dir.join(&file.rel_path);
+                        if file.remote_key.is_some()
+                            || tokio::fs::try_exists(&path).await.unwrap_or(false)
+                        {
+                            report.present += 1;
+                            continue;
+                        }
+                    }
+                    // Pending or failed: the media queue is already on it.
+                    Some(_) => {
+                        report.pending += 1;
+                        continue;
+                    }
+                    None => {}
+                }
+                missing.push(reference);
+            }
+            let queued = self.media_queue.requeue_media(&missing).await? as usize;
+            report.queued += queued;
+            report.pending += missing.len() - queued;
+            // Wake the worker; what does not fit in the channel it takes from the queue.
+            for reference in missing {
+                if wake_worker && self.media_tx.try_send(reference).is_err() {
+                    wake_worker = false;
+                }
+            }
+            if !full_page {
+                break;
+            }
+        }
+        info!(
+            chat_id,
+            queued = report.queued,
+            present = report.present,
+            pending = report.pending,
+            filtered = report.filtered,
+            "media backfill queued"
+        );
+        Ok(report)
+    }
+
+    /// Sync multiple chats with progress reporting. Runs sequentially to respect rate limits.
+    /// A failing chat is logged and recorded; the remaining chats still sync. A chat that hits
+    /// a FloodWait is deferred until the others are done and then resumed from its checkpoint
+    /// once the wait is over; a second FloodWait in a row skips it (its result is the
+    /// FloodWait error). `topics` limits forum chats to the selected topics. With `takeout`
+    /// the whole run goes through one takeout session, which is finished (even when chats
+    /// failed) before returning. Returns one result per chat, in input order; Err only when
+    /// the takeout session could not start (e.g. [`DomainError::TakeoutDelay`]).
     pub async fn sync_chats(
         &self,
-        chat_ids: &[i64],
+        chats: &[Chat],
+        limit_per_chat: i32,
+        media: &MediaFilter,
+        range: Option<TimeRange>,
+        topics: &TopicFilter,
+        takeout: bool,
+    ) -> Result<Vec<ChatSyncResult>, DomainError> {
+        if !takeout {
+            return Ok(self
+                .sync_each(chats, limit_per_chat, media, range, topics)
+                .await);
+        }
+        self.tg.begin_takeout().await?;
+        let results = self
+            .sync_each(chats, limit_per_chat, media, range, topics)
+            .await;
+        let success = results.iter().all(|(_, r)| r.is_ok());
+        if let Err(e) = self.tg.end_takeout(success).await {
+            warn!(error = %e, "failed to finish takeout session");
+        }
+        Ok(results)
+    }
+
+    /// [`Self::sync_chats`] without the takeout session.
+    async fn sync_each(
+        &self,
+        chats: &[Chat],
+        limit_per_chat: i32,
+        media: &MediaFilter,
+        range: Option<TimeRange>,
+        topics: &TopicFilter,
+    ) -> Vec<ChatSyncResult> {
+        log_media_filter(media);
+        let mut results = Vec::with_capacity(chats.len());
+        // (index into `chats`, when its FloodWait ends)
+        let mut deferred: Vec<(usize, Instant)> = Vec::new();
+        for (index, chat) in chats.iter().enumerate() {
+            let chat_topics = topics.topics(chat.id);
+            let result = self
+                .sync_chat_with_progress(chat, limit_per_chat, media, range, chat_topics)
+                .await;
+            if let Err(DomainError::FloodWait { seconds }) = &result {
+                let remaining = chats.len() - index - 1;
+                info!(
+                    chat_id = chat.id,
+                    wait_secs = seconds,
+                    remaining,
+                    "chat deferred due to FloodWait"
+                );
+                deferred.push((index, Instant::now() + Duration::from_secs(*seconds)));
+            } else if let Err(e) = &result {
+                warn!(chat_id = chat.id, error = %e, "chat sync failed");
+            }
+            results.push((chat.id, result));
+        }
+
+        // Retry deferred chats in the order their waits end, sleeping out what's left of each.
+        deferred.sort_by_key(|(_, resume_at)| *resume_at);
+        for (index, resume_at) in deferred {
+            let chat = &chats[index];
+            let wait = resume_at.saturating_duration_since(Instant::now());
+            if !wait.is_zero() {
+                info!(
+                    chat_id = chat.id,
+                    wait_secs = wait.as_secs(),
+                    "waiting out FloodWait before retrying deferred chat"
+                );
+                tokio::time::sleep(wait).await;
+            }
+            let chat_topics = topics.topics(chat.id);
+            let result = self
+                .sync_chat_with_progress(chat, limit_per_chat, media, range, chat_topics)
+                .await;
+            match &result {
+                Err(DomainError::FloodWait { seconds }) => {
+                    warn!(
+                        chat_id = chat.id,
+                        wait_secs = seconds,
+                        "FloodWait again after deferral; skipping chat"
+                    )
+                }
+                Err(e) => warn!(chat_id = chat.id, error = %e, "chat sync failed"),
+                Ok(_) => info!(chat_id = chat.id, "deferred chat synced"),
+            }
+            results[index].1 = result;
+        }
+        results
+    }
+
+    /// Sync up to `max_parallel` chats at once. All tasks share the gateway's rate limiter, so
+    /// the combined request rate stays that of [`Self::sync_chats`]. A chat that hits a
+    /// FloodWait resumes from its checkpoint once the limiter reopens; any other failure is recorded and
+    /// the remaining chats carry on. Returns one result per chat, in input order.
+    pub async fn sync_chats_concurrent(
+        self: &Arc<Self>,
+        chats: &[Chat],
         limit_per_chat: i32,
-        include_media: bool,
-    ) -> Result<(), DomainError> {
-        if !include_media {
-            info!("Skipping media download due to user preference (text-only mode)");
+        media: &MediaFilter,
+        range: Option<TimeRange>,
+        topics: &TopicFilter,
+        max_parallel: usize,
+    ) -> Vec<ChatSyncResult> {
+        log_media_filter(media);
+        let permits = Arc::new(Semaphore::new(max_parallel.max(1)));
+        let mut tasks = JoinSet::new();
+        for (index, chat) in chats.iter().cloned().enumerate() {
+            let service = Arc::clone(self);
+            let permits = Arc::clone(&permits);
+            let media = media.clone();
+            let chat_topics = topics.topics(chat.id).cloned();
+            tasks.spawn(async move {
+                // The semaphore is never closed, so this always holds a permit.
+                let _permit = permits.acquire_owned().await.ok();
+                let mut flood_retries = 0;
+                let result = loop {
+                    match service
+                        .sync_chat_with_progress(
+                            &chat,
+                            limit_per_chat,
+                            &media,
+                            range,
+                            chat_topics.as_ref(),
+                        )
+                        .await
+                    {
+                        Err(DomainError::FloodWait { seconds })
+                            if flood_retries < MAX_FLOOD_WAIT_RETRIES =>
+                        {
+                            flood_retries += 1;
+                            info!(
+                                chat_id = chat.id,
+                                wait_secs = seconds,
+                                "resuming chat after FloodWait"
+                            );
+                        }
+                        other => break other,
+                    }
+                };
+                (index, result)
+            });
         }
+
+        // A panicked task leaves its slot empty; it is reported as a failure of that chat.
+        let mut outcomes: Vec<Option<Result<SyncStats, DomainError>>> =
+            chats.iter().map(|_| None).collect();
+        while let Some(joined) = tasks.join_next().await {
+            match joined {
+                Ok((index, result)) => outcomes[index] = Some(result),
+                Err(e) => warn!(error = %e, "chat sync task panicked"),
+            }
+        }
+        chats
+            .iter()
+            .zip(outcomes)
+            .map(|(chat, result)| {
+                let result = result.unwrap_or_else(|| {
+                    Err(DomainError::TgGateway(
+                        "chat sync task panicked".to_string(),
+                    ))
+                });
+                if let Err(e) = &result {
+                    warn!(chat_id = chat.id, error = %e, "chat sync failed");
+                }
+                (chat.id, result)
+            })
+            .collect()
+    }
+
+    /// Estimate what syncing `chat_ids` with `media` would fetch, without saving anything or
+    /// moving checkpoints: one dialog listing, then one GetHistory request (limit 1) per chat
+    /// that would sync. Message ids grow about one per message, so the newest id above the
+    /// checkpoint bounds the new messages (capped by the dialog's `approx_message_count`); an
+    /// interrupted backfill adds what is left below its low-water mark. The share of stored
+    /// messages with media of the filter's types (the whole archive's for a chat with nothing
+    /// stored yet) is extrapolated to them. Blacklisted chats and chats whose settings turn
+    /// sync off are flagged and not read; a chat whose request fails keeps its error.
+    pub async fn plan_sync(
+        &self,
+        chat_ids: &[i64],
+        media: &MediaFilter,
+    ) -> Result<Vec<SyncPlan>, DomainError> {
+        let dialogs = self.tg.get_dialogs().await?;
+        let known = self.repo.get_known_chats().await?;
+        let blacklisted = self.repo.get_blacklisted_ids().await?;
+        // Read once, for the first chat with no stored messages of its own.
+        let mut archive: Option<Vec<ChatStats>> = None;
+        let mut plans = Vec::with_capacity(chat_ids.len());
         for &chat_id in chat_ids {
-            self.sync_chat(chat_id, limit_per_chat, include_media)
-                .await?;
+            let dialog = dialogs.iter().find(|c| c.id == chat_id);
+            let settings = self.repo.get_chat_settings(chat_id).await?;
+            let mut plan = SyncPlan {
+                chat_id,
+                title: dialog
+                    .or_else(|| known.iter().find(|c| c.id == chat_id))
+                    .map(|c| c.title.clone()),
+                last_message_id: self.state.get_last_message_id(chat_id).await?,
+                ..SyncPlan::default()
+            };
+            plan.skip = if blacklisted.contains(&chat_id) {
+                Some(SyncSkip::Blacklisted)
+            } else if !settings.sync_enabled {
+                Some(SyncSkip::SyncOff)
+            } else {
+                None
+            };
+            if plan.skip.is_none() {
+                match self.tg.get_messages(chat_id, 0, 0, PLAN_SAMPLE_SIZE).await {
+                    Ok(page) => {
+                        let media = settings.media_filter(media);
+                        let stats = self.repo.get_chat_stats(chat_id).await?;
+                        let share = match media_share(std::slice::from_ref(&stats), &media) {
+                            Some(share) => Some(share),
+                            None => {
+                                if archive.is_none() {
+                                    archive = Some(self.repo.get_global_stats().await?.chats);
+                                }
+                                archive
+                                    .as_deref()
+                                    .and_then(|chats| media_share(chats, &media))
+                            }
+                        };
+                        plan.estimate(
+                            &page,
+                            dialog.and_then(|c| c.approx_message_count),
+                            self.state.get_backfill_low_id(chat_id).await?,
+                            share,
+                        );
+                    }
+                    Err(e) => {
+                        warn!(chat_id, error = %e, "could not read chat for the sync plan");
+                        plan.error = Some(e.to_string());
+                    }
+                }
+            }
+            plans.push(plan);
         }
-        Ok(())
+        Ok(plans)
+    }
+
+    /// Chats picked for the last manual Full Backup; None when no selection was made yet.
+    pub async fn backup_selection(&self) -> Result<Option<HashSet<i64>>, DomainError> {
+        let stored = self.settings.get_setting(BACKUP_SELECTION_SETTING).await?;
+        Ok(stored.map(|ids| {
+            ids.split(',')
+                .filter_map(|id| id.trim().parse().ok())
+                .collect()
+        }))
+    }
+
+    /// Remember the chats picked for a manual Full Backup (offered as the default next time).
+    pub async fn set_backup_selection(&self, chat_ids: &HashSet<i64>) -> Result<(), DomainError> {
+        let mut ids: Vec<i64> = chat_ids.iter().copied().collect();
+        ids.sort_unstable();
+        let value = ids
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        self.settings
+            .set_setting(BACKUP_SELECTION_SETTING, Some(&value))
+            .await
+    }
+
+    /// Look up a chat by `@username`, t.me link or id, joined or not, and store its title so
+    /// exports and the archive lists can label it. Chats not among the dialogs sync like any
+    /// other once resolved.
+    pub async fn resolve_chat(&self, reference: &str) -> Result<Chat, DomainError> {
+        let chat = self.tg.resolve_chat(reference).await?;
+        self.repo.upsert_chats(std::slice::from_ref(&chat)).await?;
+        info!(chat_id = chat.id, title = %chat.title, "chat resolved");
+        Ok(chat)
     }
+
+    /// Write a multi-chat sync summary as JSON to `reports_dir/sync_YYYYMMDD_HHMMSS.json`
+    /// (UTC), for automation. `chats` supplies the titles.
+    pub async fn write_report(
+        &self,
+        chats: &[Chat],
+        results: &[ChatSyncResult],
+    ) -> Result<PathBuf, DomainError> {
+        let now = Utc::now();
+        let report = SyncReport {
+            finished_at: now.timestamp(),
+            chats: results
+                .iter()
+                .map(|(chat_id, result)| SyncReportEntry::new(*chat_id, chats, result))
+                .collect(),
+        };
+        let json = serde_json::to_string_pretty(&report)
+            .map_err(|e| DomainError::Repo(format!("Failed to encode sync report: {}", e)))?;
+        tokio::fs::create_dir_all(&self.reports_dir)
+            .await
+            .map_err(|e| DomainError::Repo(format!("Failed to create reports dir: {}", e)))?;
+        let path = self
+            .reports_dir
+            .join(format!("sync_{}.json", now.format("%Y%m%d_%H%M%S")));
+        tokio::fs::write(&path, json)
+            .await
+            .map_err(|e| DomainError::Repo(format!("Failed to write sync report: {}", e)))?;
+        info!(path = %path.display(), "sync report written");
+        Ok(path)
+    }
+
+    /// Store the forum's current topic titles for exports. Best effort: a failure only
+    /// leaves the previous titles in place.
+    async fn refresh_forum_topics(&self, chat_id: i64) {
+        let result = match self.tg.get_forum_topics(chat_id).await {
+            Ok(topics) => self.repo.save_forum_topics(chat_id, &topics).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            warn!(chat_id, error = %e, "failed to refresh forum topics");
+        }
+    }
+}
+
+/// Whether `m` belongs to one of the selected topics (always true without a selection).
+/// Messages without a topic id are in the General topic.
+fn in_topics(topics: Option<&HashSet<i32>>, m: &Message) -> bool {
+    topics.is_none_or(|t| t.contains(&m.topic_id.unwrap_or(GENERAL_TOPIC_ID)))
 }
 
+fn log_media_filter(media: &MediaFilter) {
+    if media.is_none() {
+        info!("Skipping media download due to user preference (text-only mode)");
+    } else if *media != MediaFilter::all() {
+        info!(filter = ?media, "Downloading only media passing the filter");
+    }
+}
+
+/// Settings key of the chats picked for the last manual Full Backup (comma-separated ids).
+const BACKUP_SELECTION_SETTING: &str = "sync.backup_selection";
+
+/// Stored messages read per page by [`SyncService::backfill_media`].
+const BACKFILL_PAGE_SIZE: u32 = 500;
+
+/// Times a chat sync is resumed after a FloodWait before it is reported as failed.
+const MAX_FLOOD_WAIT_RETRIES: u32 = 3;
+
+/// Newest messages read per chat by [`SyncService::plan_sync`]: only the top id is needed.
+const PLAN_SAMPLE_SIZE: i32 = 1;
+
 /// Result of a single chat sync.
 #[derive(Debug, Default)]
 pub struct SyncStats {
     pub messages_synced: usize,
     pub media_queued: usize,
+    /// Media left out by the media filter (still referenced in the stored messages).
+    pub media_skipped: usize,
+    /// Already-synced messages found edited (previous text kept in edit history).
+    pub edits_recorded: usize,
+    /// Lowest and highest id of the saved messages; None when nothing was saved. Without a
+    /// range or topic selection, every stored id in between was saved by this sync. Older
+    /// messages stored by a resumed backfill are counted in `messages_synced` only.
+    pub synced_ids: Option<(i32, i32)>,
+    /// Wall time of the chat sync, including rate-limit waits.
+    pub duration: Duration,
+}
+
+/// Outcome of [`SyncService::backfill_media`] for one chat.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MediaBackfill {
+    /// Stored messages with a media reference.
+    pub with_media: usize,
+    /// Downloads queued now.
+    pub queued: usize,
+    /// Already downloaded: on disk or in remote storage.
+    pub present: usize,
+    /// Already waiting in the media queue, or failed and retried by it.
+    pub pending: usize,
+    /// Left out by the media filter.
+    pub filtered: usize,
+}
+
+impl fmt::Display for MediaBackfill {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} media file(s) queued for download, {} already downloaded, {} already queued",
+            self.queued, self.present, self.pending
+        )?;
+        if self.filtered > 0 {
+            write!(f, ", {} left out by the filter", self.filtered)?;
+        }
+        Ok(())
+    }
+}
+
+/// What a sync of one chat would fetch, estimated by [`SyncService::plan_sync`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncPlan {
+    pub chat_id: i64,
+    /// Dialog or stored title; None for a chat never seen.
+    pub title: Option<String>,
+    /// Checkpoint: newest message id already synced (0 = never synced).
+    pub last_message_id: i32,
+    /// Newest message id in the chat; None when the chat is empty or was not read.
+    pub top_message_id: Option<i32>,
+    /// Messages the sync would fetch (an upper bound: deleted messages used up ids too).
+    pub estimated_messages: u64,
+    /// Media among them that would be downloaded.
+    pub estimated_media: u64,
+    /// Why the sync leaves the chat out; None when it syncs.
+    pub skip: Option<SyncSkip>,
+    /// The newest message could not be read; the estimates are then zero.
+    pub error: Option<String>,
+}
+
+impl SyncPlan {
+    /// Fill the estimates from the chat's newest `page` (newest first) and the
+    /// `(with media, messages)` share from [`media_share`].
+    fn estimate(
+        &mut self,
+        page: &[Message],
+        approx_count: Option<i32>,
+        backfill_low: i32,
+        share: Option<(u64, u64)>,
+    ) {
+        let last = self.last_message_id;
+        self.top_message_id = page.iter().map(|m| m.id).max();
+        let sampled: Vec<&Message> = page.iter().filter(|m| m.id > last).collect();
+        // The page reached the checkpoint or the start of the chat: nothing new is missing.
+        let complete = page.len() < PLAN_SAMPLE_SIZE as usize || page.iter().any(|m| m.id <= last);
+        let above = if complete {
+            sampled.len() as u64
+        } else {
+            let gap = (self.top_message_id.unwrap_or(last) - last).max(0) as u64;
+            approx_count.map_or(gap, |count| gap.min(count.max(0) as u64))
+        };
+        let below = (backfill_low - 1).max(0) as u64;
+        self.estimated_messages = above + below;
+        self.estimated_media = match share {
+            Some((with_media, n)) if n > 0 => (with_media * self.estimated_messages + n / 2) / n,
+            _ => 0,
+        };
+    }
+}
+
+/// Stored messages carrying media of a type `media` downloads, and all stored messages, over
+/// `chats`; None when nothing is stored. The size limit is not taken into account.
+fn media_share(chats: &[ChatStats], media: &MediaFilter) -> Option<(u64, u64)> {
+    let messages: u64 = chats.iter().map(|c| c.messages).sum();
+    if messages == 0 {
+        return None;
+    }
+    let with_media = chats
+        .iter()
+        .flat_map(|c| &c.media_by_type)
+        .filter(|(media_type, _)| media.types.as_ref().is_none_or(|t| t.contains(media_type)))
+        .map(|(_, n)| n)
+        .sum();
+    Some((with_media, messages))
+}
+
+/// Why [`SyncService::plan_sync`] expects a chat to be left out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncSkip {
+    /// On the backup blacklist.
+    Blacklisted,
+    /// Its per-chat settings turn sync off.
+    SyncOff,
+}
+
+impl fmt::Display for SyncSkip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SyncSkip::Blacklisted => "blacklisted",
+            SyncSkip::SyncOff => "sync turned off in its chat settings",
+        })
+    }
+}
+
+/// What [`SyncService::store_batch`] did with one batch.
+struct StoredBatch {
+    media_queued: usize,
+    media_skipped: usize,
+    /// The media channel is closed; no more media can be queued in this sync.
+    channel_closed: bool,
+}
+
+/// Messages stored by [`SyncService::resume_backfill`].
+struct ResumedBackfill {
+    messages: usize,
+    media_queued: usize,
+    media_skipped: usize,
+    channel_closed: bool,
+}
+
+/// Outcome of one chat in a multi-chat sync: the chat id and its stats or error.
+pub type ChatSyncResult = (i64, Result<SyncStats, DomainError>);
+
+/// JSON sync summary written by [`SyncService::write_report`].
+#[derive(Debug, Serialize)]
+struct SyncReport {
+    /// Unix seconds.
+    finished_at: i64,
+    chats: Vec<SyncReportEntry>,
+}
+
+#[derive(Debug, Serialize)]
+struct SyncReportEntry {
+    chat_id: i64,
+    title: Option<String>,
+    messages_synced: usize,
+    media_queued: usize,
+    media_skipped: usize,
+    edits_recorded: usize,
+    duration_ms: u64,
+    /// Set when the chat failed; counts are then zero.
+    error: Option<String>,
+}
+
+impl SyncReportEntry {
+    fn new(chat_id: i64, chats: &[Chat], result: &Result<SyncStats, DomainError>) -> Self {
+        let title = chats
+            .iter()
+            .find(|c| c.id == chat_id)
+            .map(|c| c.title.clone());
+        let default = SyncStats::default();
+        let (stats, error) = match result {
+            Ok(stats) => (stats, None),
+            Err(e) => (&default, Some(e.to_string())),
+        };
+        Self {
+            chat_id,
+            title,
+            messages_synced: stats.messages_synced,
+            media_queued: stats.media_queued,
+            media_skipped: stats.media_skipped,
+            edits_recorded: stats.edits_recorded,
+            duration_ms: stats.duration.as_millis() as u64,
+            error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::persistence::sqlite_repo::SqliteRepo;
+    use crate::adapters::persistence::state_json::StateJson;
+    use crate::domain::{
+        ChatEventKind, ChatSettings, ChatType, ForumTopic, MediaType, Poll, PollAnswer,
+    };
+    use crate::testing::fake_tg::text_message;
+    use crate::usecases::AuditService;
+    use std::path::{Path, PathBuf};
+    use std::sync::Mutex;
+
+    /// Chat history held in memory; honours min_id/max_id/limit, newest first.
+    #[derive(Default)]
+    struct FakeChat {
+        messages: Mutex<Vec<Message>>,
+        /// Chat whose history requests always fail.
+        failing_chat: Mutex<Option<i64>>,
+        /// FloodWait returned by the next history request.
+        flood_once: Mutex<Option<u64>>,
+        /// Chat whose history requests always hit a zero-second FloodWait.
+        flooding_chat: Mutex<Option<i64>>,
+        /// Chat whose history requests panic, like a bug in the gateway.
+        panicking_chat: Mutex<Option<i64>>,
+        /// Chat id of every history request, in order.
+        requests: Mutex<Vec<i64>>,
+        /// Page size (limit) of every history request, in order.
+        limits: Mutex<Vec<i32>>,
+        /// Service messages of the chat; those in a fetched page wait in `batch_events`.
+        events: Mutex<Vec<ChatEvent>>,
+        batch_events: Mutex<Vec<ChatEvent>>,
+        /// Takeout calls in order: "begin", then "end ok" or "end failed".
+        takeout_calls: Mutex<Vec<&'static str>>,
+        /// TAKEOUT_INIT_DELAY returned by begin_takeout.
+        takeout_delay: Mutex<Option<u64>>,
+        /// History requests after this many fail, like a connection lost mid-sync.
+        fail_after_requests: Mutex<Option<usize>>,
+        dialogs: Mutex<Vec<Chat>>,
+    }
+
+    impl FakeChat {
+        fn post(&self, id: i32, text: &str) {
+            let mut messages = self.messages.lock().unwrap();
+            messages.retain(|m| m.id != id);
+            messages.push(Message {
+                from_user_id: Some(1),
+                ..text_message(9, id, 1704067200 + id as i64, text)
+            });
+        }
+
+        fn post_media(&self, id: i32, media_type: MediaType, size_bytes: Option<i64>) {
+            self.post(id, "");
+            let mut messages = self.messages.lock().unwrap();
+            let msg = messages.iter_mut().find(|m| m.id == id).unwrap();
+            msg.media = Some(MediaReference {
+                message_id: id,
+                chat_id: 9,
+                media_type,
+                opaque_ref: format!("9:{}", id),
+                size_bytes,
+                filename: None,
+                mime_type: None,
+            });
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl TgGateway for FakeChat {
+        async fn get_dialogs(&self) -> Result<Vec<Chat>, DomainError> {
+            Ok(self.dialogs.lock().unwrap().clone())
+        }
+        async fn get_messages(
+            &self,
+            chat_id: i64,
+            min_id: i32,
+            max_id: i32,
+            limit: i32,
+        ) -> Result<Vec<Message>, DomainError> {
+            let made = {
+                let mut requests = self.requests.lock().unwrap();
+                requests.push(chat_id);
+                requests.len()
+            };
+            self.limits.lock().unwrap().push(limit);
+            if self
+                .fail_after_requests
+                .lock()
+                .unwrap()
+                .is_some_and(|n| made > n)
+            {
+                return Err(DomainError::TgGateway("connection lost".into()));
+            }
+            if *self.failing_chat.lock().unwrap() == Some(chat_id) {
+                return Err(DomainError::TgGateway("CHANNEL_PRIVATE".into()));
+            }
+            if *self.flooding_chat.lock().unwrap() == Some(chat_id) {
+                return Err(DomainError::FloodWait { seconds: 0 });
+            }
+            if *self.panicking_chat.lock().unwrap() == Some(chat_id) {
+                panic!("history request for chat {} panicked", chat_id);
+            }
+            if let Some(seconds) = self.flood_once.lock().unwrap().take() {
+                return Err(DomainError::FloodWait { seconds });
+            }
+            let mut page: Vec<Message> = self
+                .messages
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|m| m.id > min_id && (max_id == 0 || m.id < max_id))
+                .cloned()
+                .collect();
+            page.sort_by_key(|m| std::cmp::Reverse(m.id));
+            page.truncate(limit as usize);
+            let oldest = page.last().map_or(min_id, |m| m.id);
+            let events = self.events.lock().unwrap();
+            self.batch_events.lock().unwrap().extend(
+                events
+                    .iter()
+                    .filter(|e| e.id > oldest && (max_id == 0 || e.id < max_id))
+                    .cloned(),
+            );
+            Ok(page)
+        }
+        async fn get_events_from_batch(&self) -> Result<Vec<ChatEvent>, DomainError> {
+            Ok(std::mem::take(&mut *self.batch_events.lock().unwrap()))
+        }
+        async fn begin_takeout(&self) -> Result<(), DomainError> {
+            if let Some(seconds) = *self.takeout_delay.lock().unwrap() {
+                return Err(DomainError::TakeoutDelay { seconds });
+            }
+            self.takeout_calls.lock().unwrap().push("begin");
+            Ok(())
+        }
+        async fn end_takeout(&self, success: bool) -> Result<(), DomainError> {
+            let call = if success { "end ok" } else { "end failed" };
+            self.takeout_calls.lock().unwrap().push(call);
+            Ok(())
+        }
+        async fn download_media(&self, _: &MediaReference, _: &Path) -> Result<(), DomainError> {
+            Ok(())
+        }
+        async fn get_me_id(&self) -> Result<i64, DomainError> {
+            Ok(1)
+        }
+        async fn send_message(&self, _: i64, _: &str) -> Result<(), DomainError> {
+            Ok(())
+        }
+        async fn get_forum_topics(&self, _: i64) -> Result<Vec<ForumTopic>, DomainError> {
+            Ok(vec![ForumTopic {
+                id: 5,
+                title: "Releases".to_string(),
+            }])
+        }
+    }
+
+    async fn setup(
+        name: &str,
+    ) -> (
+        Arc<FakeChat>,
+        Arc<SqliteRepo>,
+        SyncService,
+        mpsc::Receiver<MediaReference>,
+    ) {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        let repo = Arc::new(SqliteRepo::connect(&dir).await.unwrap());
+        let state = Arc::new(StateJson::new(dir.join("state.json")));
+        let chat = Arc::new(FakeChat::default());
+        let (media_tx, media_rx) = mpsc::channel(16);
+        let service = SyncService::new(
+            chat.clone(),
+            repo.clone(),
+            state,
+            media_tx,
+            repo.clone(),
+            repo.clone(),
+            2,
+            dir.join("reports"),
+            None,
+            ActivityFlag::new(),
+        );
+        (chat, repo, service, media_rx)
+    }
+
+    #[tokio::test]
+    async fn test_edit_between_syncs_is_recorded_as_prior_version() {
+        let (chat, repo, service, _) = setup("test_sync_edit_history").await;
+        for id in 1..=3 {
+            chat.post(id, &format!("v1 of {}", id));
+        }
+        let stats = service
+            .sync_chat(9, 100, &MediaFilter::none(), None)
+            .await
+            .unwrap();
+        assert_eq!((stats.messages_synced, stats.edits_recorded), (3, 0));
+
+        // Between cycles: #3 and #1 edited, #4 posted. #1 is outside the rescan window (2).
+        chat.post(3, "v2 of 3");
+        chat.post(1, "v2 of 1");
+        chat.post(4, "v1 of 4");
+        let stats = service
+            .sync_chat(9, 100, &MediaFilter::none(), None)
+            .await
+            .unwrap();
+        assert_eq!((stats.messages_synced, stats.edits_recorded), (1, 1));
+
+        // Nothing changed: no new versions, no duplicate rows.
+        let stats = service
+            .sync_chat(9, 100, &MediaFilter::none(), None)
+            .await
+            .unwrap();
+        assert_eq!((stats.messages_synced, stats.edits_recorded), (0, 0));
+
+        let stored = repo.get_messages_by_ids(9, &[1, 2, 3, 4, 5]).await.unwrap();
+        assert_eq!(
+            stored.iter().map(|m| m.id).collect::<Vec<_>>(),
+            [1, 2, 3, 4]
+        );
+        let third = &stored[2];
+        assert_eq!(third.text, "v2 of 3");
+        let history = third.edit_history.as_deref().unwrap_or_default();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].text, "v1 of 3");
+        assert_eq!(stored[0].text, "v1 of 1");
+        assert!(
+            stored[1]
+                .edit_history
+                .as_deref()
+                .unwrap_or_default()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_service_messages_are_saved_as_events() {
+        let (chat, repo, service, _) = setup("test_sync_chat_events").await;
+        chat.post(1, "hello");
+        chat.post(3, "welcome");
+        chat.events.lock().unwrap().push(ChatEvent {
+            id: 2,
+            chat_id: 9,
+            date: 1704067202,
+            kind: ChatEventKind::UserJoined,
+            actor_id: Some(4),
+            payload: None,
+        });
+        let stats = service
+            .sync_chat(9, 100, &MediaFilter::none(), None)
+            .await
+            .unwrap();
+        assert_eq!(stats.messages_synced, 2);
+
+        let events = repo.get_events(9).await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, ChatEventKind::UserJoined);
+        let stored = repo.get_messages_by_ids(9, &[1, 2, 3]).await.unwrap();
+        assert_eq!(stored.iter().map(|m| m.id).collect::<Vec<_>>(), [1, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_rescan_refreshes_poll_results_without_edit() {
+        let (chat, repo, service, _) = setup("test_sync_poll_refresh").await;
+        let poll = |votes: i32| Poll {
+            question: "Lunch?".to_string(),
+            answers: vec![PollAnswer {
+                text: "Pizza".to_string(),
+                voters: Some(votes),
+            }],
+            quiz: false,
+            closed: false,
+            total_voters: Some(votes),
+        };
+        let set_poll = |votes: i32| {
+            let mut messages = chat.messages.lock().unwrap();
+            let msg = messages.iter_mut().find(|m| m.id == 1).unwrap();
+            msg.text = poll(votes).render();
+            msg.poll = Some(poll(votes));
+        };
+        chat.post(1, "");
+        set_poll(1);
+        let none = MediaFilter::none();
+        service.sync_chat(9, 100, &none, None).await.unwrap();
+
+        set_poll(4);
+        let stats = service.sync_chat(9, 100, &none, None).await.unwrap();
+        assert_eq!(stats.edits_recorded, 0);
+        let stored = repo.get_messages_by_ids(9, &[1]).await.unwrap();
+        assert_eq!(stored[0].poll, Some(poll(4)));
+        assert_eq!(stored[0].text, "[Poll] Lunch? — Pizza (4)");
+        assert!(stored[0].edit_history.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_media_filter_skips_but_keeps_references() {
+        let (chat, repo, service, mut media_rx) = setup("test_sync_media_filter").await;
+        chat.post_media(1, MediaType::Photo, Some(200_000));
+        chat.post_media(2, MediaType::Video, Some(2_000_000_000));
+        chat.post_media(3, MediaType::Photo, Some(80_000_000));
+        chat.post_media(4, MediaType::Voice, None);
+        let filter = MediaFilter {
+            types: Some(vec![MediaType::Photo, MediaType::Voice]),
+            max_size_bytes: Some(50 * 1024 * 1024),
+        };
+
+        let stats = service.sync_chat(9, 100, &filter, None).await.unwrap();
+        assert_eq!((stats.media_queued, stats.media_skipped), (2, 2));
+        let mut queued = Vec::new();
+        while let Ok(m) = media_rx.try_recv() {
+            queued.push(m.message_id);
+        }
+        assert_eq!(queued, vec![1, 4]);
+
+        // Skipped media stays in the stored rows for a later, looser sync.
+        let stored = repo.get_messages_by_ids(9, &[2, 3]).await.unwrap();
+        assert!(stored.iter().all(|m| m.media.is_some()));
+        assert_eq!(
+            stored[0].media.as_ref().unwrap().size_bytes,
+            Some(2_000_000_000)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chat_settings_override_media_and_batch_limit() {
+        let (chat, repo, service, mut media_rx) = setup("test_sync_chat_settings").await;
+        chat.post_media(1, MediaType::Photo, None);
+        chat.post_media(2, MediaType::Video, None);
+        chat.post_media(3, MediaType::Voice, None);
+        let mut settings = ChatSettings::new(9);
+        settings.include_media = Some(true);
+        settings.media_types = Some(vec![MediaType::Voice]);
+        settings.batch_limit = Some(1);
+        repo.set_chat_settings(&settings).await.unwrap();
+
+        // A text-only sync still fetches the voice message of this chat, one per request.
+        let stats = service
+            .sync_chat(9, 100, &MediaFilter::none(), None)
+            .await
+            .unwrap();
+        assert_eq!((stats.media_queued, stats.media_skipped), (1, 2));
+        assert_eq!(media_rx.try_recv().unwrap().message_id, 3);
+        assert_eq!(chat.requests.lock().unwrap().len(), 4);
+
+        settings.include_media = Some(false);
+        repo.set_chat_settings(&settings).await.unwrap();
+        chat.post_media(4, MediaType::Voice, None);
+        let stats = service
+            .sync_chat(9, 100, &MediaFilter::all(), None)
+            .await
+            .unwrap();
+        assert_eq!(stats.media_queued, 0);
+        assert_eq!(repo.get_messages_by_ids(9, &[4]).await.unwrap().len(), 1);
+        assert!(media_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_backfill_resumes_below_low_water_mark() {
+        let (chat, repo, service, _) = setup("test_sync_resume_backfill").await;
+        for id in 1..=10 {
+            chat.post(id, &format!("message {}", id));
+        }
+        let none = MediaFilter::none();
+
+        // The initial backfill dies after two pages (#10..#7): the checkpoint is the newest id,
+        // the mark says how far down it got.
+        *chat.fail_after_requests.lock().unwrap() = Some(2);
+        assert!(service.sync_chat(9, 2, &none, None).await.is_err());
+        assert_eq!(service.state.get_last_message_id(9).await.unwrap(), 10);
+        assert_eq!(service.state.get_backfill_low_id(9).await.unwrap(), 7);
+
+        // Next run: the rest of the history below #7 first, then the new #11.
+        *chat.fail_after_requests.lock().unwrap() = None;
+        chat.post(11, "message 11");
+        let stats = service.sync_chat(9, 2, &none, None).await.unwrap();
+        assert_eq!(stats.messages_synced, 7);
+        assert_eq!(stats.synced_ids, Some((11, 11)));
+        let stored = repo
+            .get_messages_by_ids(9, &(1..=11).collect::<Vec<_>>())
+            .await
+            .unwrap();
+        assert_eq!(stored.len(), 11);
+        assert_eq!(service.state.get_last_message_id(9).await.unwrap(), 11);
+        assert_eq!(service.state.get_backfill_low_id(9).await.unwrap(), 0);
+
+        // An interrupted incremental pass leaves the checkpoint, so it is simply repeated.
+        for id in 12..=15 {
+            chat.post(id, &format!("message {}", id));
+        }
+        let made = chat.requests.lock().unwrap().len();
+        *chat.fail_after_requests.lock().unwrap() = Some(made + 1);
+        assert!(service.sync_chat(9, 2, &none, None).await.is_err());
+        assert_eq!(service.state.get_last_message_id(9).await.unwrap(), 11);
+        *chat.fail_after_requests.lock().unwrap() = None;
+        service.sync_chat(9, 2, &none, None).await.unwrap();
+        assert_eq!(service.state.get_last_message_id(9).await.unwrap(), 15);
+        assert_eq!(
+            repo.get_messages_by_ids(9, &[12, 13, 14, 15])
+                .await
+                .unwrap()
+                .len(),
+            4
+        );
+    }
+
+    #[tokio::test]
+    async fn test_date_range_stops_early_and_keeps_checkpoint_safe() {
+        let (chat, repo, service, _) = setup("test_sync_date_range").await;
+        let date = |id: i64| 1704067200 + id;
+        for id in 1..=6 {
+            chat.post(id, &format!("message {}", id));
+        }
+        let none = MediaFilter::none();
+
+        // Since #4: pages newest-first (2 per page) and stops at #3; #1..#3 are a gap, so the
+        // checkpoint stays put.
+        let since = TimeRange {
+            from: date(4),
+            to: i64::MAX,
+        };
+        let stats = service.sync_chat(9, 2, &none, Some(since)).await.unwrap();
+        assert_eq!(stats.messages_synced, 3);
+        assert_eq!(service.state.get_last_message_id(9).await.unwrap(), 0);
+        assert!(repo.get_messages_by_ids(9, &[3]).await.unwrap().is_empty());
+
+        // A later unbounded sync still fills the gap.
+        service.sync_chat(9, 100, &none, None).await.unwrap();
+        assert_eq!(
+            repo.get_messages_by_ids(9, &[1, 2, 3]).await.unwrap().len(),
+            3
+        );
+        assert_eq!(service.state.get_last_message_id(9).await.unwrap(), 6);
+
+        // Until #8 (exclusive): #7 is saved and the checkpoint moves to it, #8 and #9 follow later.
+        for id in 7..=9 {
+            chat.post(id, &format!("message {}", id));
+        }
+        let until = TimeRange {
+            from: i64::MIN,
+            to: date(8),
+        };
+        let stats = service.sync_chat(9, 2, &none, Some(until)).await.unwrap();
+        assert_eq!(stats.messages_synced, 1);
+        assert_eq!(service.state.get_last_message_id(9).await.unwrap(), 7);
+        let stats = service.sync_chat(9, 100, &none, None).await.unwrap();
+        assert_eq!(stats.messages_synced, 2);
+    }
+
+    #[tokio::test]
+    async fn test_audit_leaves_checkpoint_behind_date_range_gap() {
+        let (chat, repo, service, _) = setup("test_sync_range_audit").await;
+        for id in 1..=6 {
+            chat.post(id, &format!("message {}", id));
+        }
+        let none = MediaFilter::none();
+        service.sync_chat(9, 100, &none, None).await.unwrap();
+        for id in 7..=10 {
+            chat.post(id, &format!("message {}", id));
+        }
+        let audit = AuditService::new(
+            repo.clone(),
+            repo.clone(),
+            service.state.clone(),
+            PathBuf::from("media"),
+        );
+
+        // Since #9: #7 and #8 are left out, so the checkpoint stays at 6 below stored #10.
+        let since = TimeRange {
+            from: 1704067200 + 9,
+            to: i64::MAX,
+        };
+        service.sync_chat(9, 2, &none, Some(since)).await.unwrap();
+        let check = audit.check_checkpoints(true).await.unwrap();
+        assert_eq!((check.problems, check.fixed), (0, 0));
+        assert_eq!(service.state.get_last_message_id(9).await.unwrap(), 6);
+
+        // The next unbounded sync fills the gap; the audit has nothing left to skip.
+        let stats = service.sync_chat(9, 100, &none, None).await.unwrap();
+        assert_eq!(stats.messages_synced, 4);
+        assert_eq!(service.state.get_last_message_id(9).await.unwrap(), 10);
+        assert!(!service.state.has_sync_gap(9).await.unwrap());
+        assert_eq!(audit.check_checkpoints(false).await.unwrap().problems, 0);
+    }
+
+    #[tokio::test]
+    async fn test_topic_selection_keeps_selected_topics_and_checkpoint() {
+        let (chat, repo, service, _) = setup("test_sync_forum_topics").await;
+        // #1 General (no topic id), #2 and #3 in topic 5, #4 in topic 7.
+        for (id, topic) in [(1, None), (2, Some(5)), (3, Some(5)), (4, Some(7))] {
+            chat.post(id, "hello");
+            chat.messages.lock().unwrap()[id as usize - 1].topic_id = topic;
+        }
+        let forum = Chat {
+            id: 9,
+            title: "forum".to_string(),
+            username: None,
+            kind: crate::domain::ChatType::Supergroup,
+            approx_message_count: None,
+            is_forum: true,
+            last_message_date: None,
+        };
+        let none = MediaFilter::none();
+
+        let selected = HashSet::from([5]);
+        let stats = service
+            .sync_chat_with_progress(&forum, 100, &none, None, Some(&selected))
+            .await
+            .unwrap();
+        assert_eq!(stats.messages_synced, 2);
+        assert_eq!(service.state.get_last_message_id(9).await.unwrap(), 0);
+        let stored = repo.get_messages_by_ids(9, &[1, 2, 3, 4]).await.unwrap();
+        assert_eq!(stored.iter().map(|m| m.id).collect::<Vec<_>>(), [2, 3]);
+        assert_eq!(stored[0].topic_title.as_deref(), Some("Releases"));
+
+        // A later full sync picks up General and the other topic.
+        service
+            .sync_chat_with_progress(&forum, 100, &none, None, None)
+            .await
+            .unwrap();
+        let stored = repo.get_messages_by_ids(9, &[1, 2, 3, 4]).await.unwrap();
+        assert_eq!(stored.len(), 4);
+        assert_eq!(stored[3].topic_id, Some(7));
+        assert_eq!(service.state.get_last_message_id(9).await.unwrap(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_audit_leaves_checkpoint_behind_skipped_topics() {
+        let (chat, repo, service, _) = setup("test_sync_topics_audit").await;
+        // #1 General, #2 in topic 5, #3 in topic 7.
+        for (id, topic) in [(1, None), (2, Some(5)), (3, Some(7))] {
+            chat.post(id, "hello");
+            chat.messages.lock().unwrap()[id as usize - 1].topic_id = topic;
+        }
+        let forum = Chat {
+            id: 9,
+            title: "forum".to_string(),
+            username: None,
+            kind: crate::domain::ChatType::Supergroup,
+            approx_message_count: None,
+            is_forum: true,
+            last_message_date: None,
+        };
+        let none = MediaFilter::none();
+        let audit = AuditService::new(
+            repo.clone(),
+            repo.clone(),
+            service.state.clone(),
+            PathBuf::from("media"),
+        );
+
+        // Topic 7 only: #3 is stored above a checkpoint that has to stay below #1 and #2.
+        let selected = HashSet::from([7]);
+        service
+            .sync_chat_with_progress(&forum, 100, &none, None, Some(&selected))
+            .await
+            .unwrap();
+        let check = audit.check_checkpoints(true).await.unwrap();
+        assert_eq!((check.problems, check.fixed), (0, 0));
+        assert_eq!(service.state.get_last_message_id(9).await.unwrap(), 0);
+
+        // The skipped topics are still fetched by the next full sync.
+        service
+            .sync_chat_with_progress(&forum, 100, &none, None, None)
+            .await
+            .unwrap();
+        let stored = repo.get_messages_by_ids(9, &[1, 2, 3]).await.unwrap();
+        assert_eq!(stored.len(), 3);
+        assert_eq!(service.state.get_last_message_id(9).await.unwrap(), 3);
+        assert!(!service.state.has_sync_gap(9).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_sync_plan_estimates_without_syncing_and_flags_skipped_chats() {
+        let (chat, repo, service, _) = setup("test_sync_plan").await;
+        for id in 1..=3 {
+            chat.post(id, "old");
+        }
+        chat.post_media(2, MediaType::Photo, None);
+        service
+            .sync_chat(9, 100, &MediaFilter::none(), None)
+            .await
+            .unwrap();
+        chat.post(4, "new");
+        chat.post_media(5, MediaType::Photo, None);
+        repo.update_blacklist(HashSet::from([10])).await.unwrap();
+        let mut off = ChatSettings::new(11);
+        off.sync_enabled = false;
+        repo.set_chat_settings(&off).await.unwrap();
+        chat.requests.lock().unwrap().clear();
+        chat.limits.lock().unwrap().clear();
+
+        // The id gap above the checkpoint, with the stored history's media share (one in three).
+        let plans = service
+            .plan_sync(&[9, 10, 11], &MediaFilter::all())
+            .await
+            .unwrap();
+        let outline: Vec<_> = plans
+            .iter()
+            .map(|p| {
+                (
+                    p.chat_id,
+                    p.last_message_id,
+                    p.top_message_id,
+                    p.estimated_messages,
+                    p.estimated_media,
+                    p.skip,
+                )
+            })
+            .collect();
+        assert_eq!(
+            outline,
+            vec![
+                (9, 3, Some(5), 2, 1, None),
+                (10, 0, None, 0, 0, Some(SyncSkip::Blacklisted)),
+                (11, 0, None, 0, 0, Some(SyncSkip::SyncOff)),
+            ]
+        );
+        // Skipped chats are not read, the others by their newest message only; the media filter
+        // applies.
+        assert_eq!(*chat.requests.lock().unwrap(), vec![9]);
+        assert_eq!(*chat.limits.lock().unwrap(), vec![1]);
+        let plans = service.plan_sync(&[9], &MediaFilter::none()).await.unwrap();
+        assert_eq!(plans[0].estimated_media, 0);
+
+        // Many new messages: the id gap is capped by the dialog's count. A chat with nothing
+        // stored takes the whole archive's media share.
+        for id in 6..=150 {
+            if id % 10 == 0 {
+                chat.post_media(id, MediaType::Photo, None);
+            } else {
+                chat.post(id, "new");
+            }
+        }
+        *chat.dialogs.lock().unwrap() = vec![Chat {
+            id: 9,
+            title: "Nine".to_string(),
+            username: None,
+            kind: ChatType::Group,
+            approx_message_count: Some(120),
+            is_forum: false,
+            last_message_date: None,
+        }];
+        let plans = service
+            .plan_sync(&[9, 12], &MediaFilter::all())
+            .await
+            .unwrap();
+        assert_eq!(plans[0].title.as_deref(), Some("Nine"));
+        assert_eq!(plans[0].top_message_id, Some(150));
+        assert_eq!(
+            (plans[0].estimated_messages, plans[0].estimated_media),
+            (120, 40)
+        );
+        assert_eq!(
+            (plans[1].estimated_messages, plans[1].estimated_media),
+            (150, 50)
+        );
+
+        // Nothing was saved or moved.
+        assert_eq!(repo.get_messages(9, 200, 0).await.unwrap().len(), 3);
+        let stats = service
+            .sync_chat(9, 100, &MediaFilter::none(), None)
+            .await
+            .unwrap();
+        assert_eq!(stats.messages_synced, 147);
+    }
+
+    #[tokio::test]
+    async fn test_backup_selection_is_remembered() {
+        let (_, _, service, _) = setup("test_sync_backup_selection").await;
+        assert_eq!(service.backup_selection().await.unwrap(), None);
+        service
+            .set_backup_selection(&HashSet::from([-1001, 42]))
+            .await
+            .unwrap();
+        assert_eq!(
+            service.backup_selection().await.unwrap(),
+            Some(HashSet::from([-1001, 42]))
+        );
+        service.set_backup_selection(&HashSet::new()).await.unwrap();
+        assert_eq!(
+            service.backup_selection().await.unwrap(),
+            Some(HashSet::new())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_sync_reports_failures_without_masking_successes() {
+        let (chat, repo, service, _) = setup("test_sync_concurrent").await;
+        let service = Arc::new(service);
+        for id in 1..=3 {
+            chat.post(id, "hello");
+        }
+        *chat.failing_chat.lock().unwrap() = Some(13);
+        *chat.flood_once.lock().unwrap() = Some(0);
+        let dialog = |id: i64| Chat {
+            id,
+            title: format!("chat {}", id),
+            username: None,
+            kind: crate::domain::ChatType::Private,
+            approx_message_count: None,
+            is_forum: false,
+            last_message_date: None,
+        };
+
+        let chats = [dialog(13), dialog(9)];
+        let results = service
+            .sync_chats_concurrent(
+                &chats,
+                100,
+                &MediaFilter::none(),
+                None,
+                &TopicFilter::all(),
+                4,
+            )
+            .await;
+
+        // Chat 13 fails outright; chat 9 is resumed after its FloodWait. Input order is kept.
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].0, 13);
+        assert!(results[0].1.is_err());
+        assert_eq!(results[1].0, 9);
+        assert_eq!(results[1].1.as_ref().unwrap().messages_synced, 3);
+
+        // The failure is reported next to the success, which stays persisted.
+        let path = service.write_report(&chats, &results).await.unwrap();
+        let report: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(report["chats"][0]["title"], "chat 13");
+        assert!(report["chats"][0]["error"].is_string());
+        assert_eq!(report["chats"][1]["messages_synced"], 3);
+        assert!(report["chats"][1]["error"].is_null());
+        assert_eq!(
+            repo.get_messages_by_ids(9, &[1, 2, 3]).await.unwrap().len(),
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_sync_reports_panicked_chat_in_place() {
+        let (chat, _repo, service, _) = setup("test_sync_concurrent_panic").await;
+        let service = Arc::new(service);
+        chat.post(1, "hello");
+        *chat.panicking_chat.lock().unwrap() = Some(13);
+        let dialog = |id: i64| Chat {
+            id,
+            title: format!("chat {}", id),
+            username: None,
+            kind: crate::domain::ChatType::Private,
+            approx_message_count: None,
+            is_forum: false,
+            last_message_date: None,
+        };
+
+        let chats = [dialog(13), dialog(9)];
+        let results = service
+            .sync_chats_concurrent(
+                &chats,
+                100,
+                &MediaFilter::none(),
+                None,
+                &TopicFilter::all(),
+                4,
+            )
+            .await;
+
+        // The panicked chat keeps its slot as a failure instead of vanishing from the summary.
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].0, 13);
+        assert!(results[0].1.is_err());
+        assert_eq!(results[1].0, 9);
+        assert_eq!(results[1].1.as_ref().unwrap().messages_synced, 1);
+    }
+
+    #[tokio::test]
+    async fn test_flood_waited_chat_is_deferred_then_skipped_on_repeat() {
+        let (chat, _repo, service, _) = setup("test_sync_flood_deferral").await;
+        for id in 1..=3 {
+            chat.post(id, "hello");
+        }
+        let dialog = |id: i64| Chat {
+            id,
+            title: format!("chat {}", id),
+            username: None,
+            kind: crate::domain::ChatType::Private,
+            approx_message_count: None,
+            is_forum: false,
+            last_message_date: None,
+        };
+        // Chat 9's first request flood-waits; chat 7 always does.
+        *chat.flood_once.lock().unwrap() = Some(0);
+        *chat.flooding_chat.lock().unwrap() = Some(7);
+
+        let chats = [dialog(9), dialog(7), dialog(10)];
+        let results = service
+            .sync_chats(
+                &chats,
+                100,
+                &MediaFilter::none(),
+                None,
+                &TopicFilter::all(),
+                false,
+            )
+            .await
+            .unwrap();
+
+        // Chat 9 waited while 7 and 10 went ahead, then synced; chat 7 was tried twice.
+        let requests = chat.requests.lock().unwrap().clone();
+        assert_eq!(requests[..3], [9, 7, 10]);
+        assert_eq!(requests.iter().filter(|&&id| id == 7).count(), 2);
+        assert_eq!(
+            results.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![9, 7, 10]
+        );
+        assert_eq!(results[0].1.as_ref().unwrap().messages_synced, 3);
+        assert!(matches!(
+            results[1].1,
+            Err(DomainError::FloodWait { seconds: 0 })
+        ));
+        assert_eq!(results[2].1.as_ref().unwrap().messages_synced, 3);
+    }
+
+    #[tokio::test]
+    async fn test_takeout_session_wraps_run_and_always_finishes() {
+        let (chat, _repo, service, _) = setup("test_sync_takeout").await;
+        chat.post(1, "hello");
+        let dialog = |id: i64| Chat {
+            id,
+            title: format!("chat {}", id),
+            username: None,
+            kind: crate::domain::ChatType::Private,
+            approx_message_count: None,
+            is_forum: false,
+            last_message_date: None,
+        };
+        *chat.failing_chat.lock().unwrap() = Some(7);
+        let chats = [dialog(9), dialog(7)];
+        let (media, topics) = (MediaFilter::none(), TopicFilter::all());
+        let sync = || service.sync_chats(&chats, 100, &media, None, &topics, true);
+
+        // Chat 7 fails midway; the session is still finished, as unsuccessful.
+        let results = sync().await.unwrap();
+        assert!(results[0].1.is_ok());
+        assert!(results[1].1.is_err());
+        assert_eq!(
+            chat.takeout_calls.lock().unwrap().clone(),
+            vec!["begin", "end failed"]
+        );
+
+        // Not confirmed in another client yet: nothing is synced and the wait is reported.
+        *chat.takeout_delay.lock().unwrap() = Some(3600);
+        let requests_before = chat.requests.lock().unwrap().len();
+        assert!(matches!(
+            sync().await,
+            Err(DomainError::TakeoutDelay { seconds: 3600 })
+        ));
+        assert_eq!(chat.requests.lock().unwrap().len(), requests_before);
+    }
 }
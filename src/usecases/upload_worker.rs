@@ -0,0 +1,324 @@
+//! Async task: uploads downloaded media to remote storage (TG_SYNC_MEDIA_UPLOAD).
+//!
+//! Fed by the media worker over a bounded channel of finished files. The media worker hands
+//! files over with `try_send`, so a slow upload never holds up downloads or message sync; a
+//! file that doesn't fit is picked up by the sweep instead. Between channel files the worker
+//! sweeps the media index for downloaded files without a remote key (channel full, failed
+//! earlier, downloaded before uploads were enabled). Every upload is retried with exponential
+//! backoff; once stored, the object key is recorded in the media index and, with
+//! TG_SYNC_MEDIA_DELETE_LOCAL, the local file is deleted.
+
+use crate::domain::{DomainError, MediaFile};
+use crate::ports::{MediaIndexPort, RemoteStoragePort};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, error::TryRecvError};
+use tokio::time::sleep;
+use tracing::{debug, error, info, warn};
+
+/// Attempts per upload before it is left for the next sweep.
+pub const MAX_UPLOAD_ATTEMPTS: u32 = 5;
+
+/// Wait after the first failed attempt; doubles with every further attempt.
+pub(crate) const BASE_BACKOFF: Duration = Duration::from_secs(2);
+
+/// No single wait between attempts is longer than this.
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Pause between sweeps of the media index once a sweep found nothing left to upload.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Index entries read per sweep page.
+const SWEEP_PAGE: u32 = 100;
+
+/// Object key of a media file (`media/{rel_path}`).
+pub fn media_key(rel_path: &str) -> String {
+    format!("media/{}", rel_path)
+}
+
+/// Upload worker. Consumes the upload channel and sweeps the media index.
+pub struct UploadWorker {
+    remote: Arc<dyn RemoteStoragePort>,
+    index: Arc<dyn MediaIndexPort>,
+    rx: mpsc::Receiver<MediaFile>,
+    media_dir: PathBuf,
+    delete_local: bool,
+    base_backoff: Duration,
+    /// Entries of the current sweep page not handled yet.
+    swept: VecDeque<MediaFile>,
+    /// Keyset cursor of the current sweep; None before the first page.
+    sweep_after: Option<(i64, i32)>,
+}
+
+impl UploadWorker {
+    pub fn new(
+        remote: Arc<dyn RemoteStoragePort>,
+        index: Arc<dyn MediaIndexPort>,
+        rx: mpsc::Receiver<MediaFile>,
+        media_dir: PathBuf,
+        delete_local: bool,
+    ) -> Self {
+        Self {
+            remote,
+            index,
+            rx,
+            media_dir,
+            delete_local,
+            base_backoff: BASE_BACKOFF,
+            swept: VecDeque::new(),
+            sweep_after: None,
+        }
+    }
+
+    /// Run the worker, one upload at a time. Processes until the channel is closed.
+    pub async fn run(mut self) {
+        while let Some(file) = self.next_file().await {
+            if let Err(e) = self.upload_one(&file).await {
+                error!(chat_id = file.chat_id, msg_id = file.message_id, error = %e, "media upload failed");
+            }
+        }
+        info!("upload worker finished (channel closed)");
+    }
+
+    /// Next file to upload: from the channel first, else from the sweep; waits for the
+    /// channel or the next sweep when both are empty. None once the channel is closed.
+    async fn next_file(&mut self) -> Option<MediaFile> {
+        loop {
+            match self.rx.try_recv() {
+                Ok(file) => return Some(file),
+                Err(TryRecvError::Disconnected) => return None,
+                Err(TryRecvError::Empty) => {}
+            }
+            if let Some(file) = self.next_swept().await {
+                return Some(file);
+            }
+            tokio::select! {
+                received = self.rx.recv() => return received,
+                _ = sleep(SWEEP_INTERVAL) => continue,
+            }
+        }
+    }
+
+    /// Next entry of the running sweep. None when the sweep is over (the next one starts from
+    /// the beginning); index errors are logged and end the sweep.
+    async fn next_swept(&mut self) -> Option<MediaFile> {
+        if self.swept.is_empty() {
+            let page = match self
+                .index
+                .list_unuploaded_media(self.sweep_after, SWEEP_PAGE)
+                .await
+            {
+                Ok(page) => page,
+                Err(e) => {
+                    warn!(error = %e, "failed to read media index for uploads");
+                    Vec::new()
+                }
+            };
+            let Some(last) = page.last() else {
+                self.sweep_after = None;
+                return None;
+            };
+            self.sweep_after = Some((last.chat_id, last.message_id));
+            self.swept.extend(page);
+        }
+        self.swept.pop_front()
+    }
+
+    /// Upload one file unless it is already stored, record its key and delete the local copy
+    /// when configured. A file missing locally is skipped.
+    async fn upload_one(&self, file: &MediaFile) -> Result<(), DomainError> {
+        let path = self.media_dir.join(&file.rel_path);
+        if !tokio::fs::try_exists(&path).await.unwrap_or(false) {
+            debug!(path = %path.display(), "local media file missing: not uploaded");
+            return Ok(());
+        }
+        let key = media_key(&file.rel_path);
+        upload_with_retry(&*self.remote, &key, &path, self.base_backoff).await?;
+        self.index
+            .upsert_media_file(&MediaFile {
+                remote_key: Some(key.clone()),
+                ..file.clone()
+            })
+            .await?;
+        debug!(key, "media uploaded");
+        if self.delete_local {
+            if let Err(e) = tokio::fs::remove_file(&path).await {
+                warn!(path = %path.display(), error = %e, "failed to delete uploaded media file");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Store `path` as `key` unless an object already exists there, retrying failures up to
+/// [`MAX_UPLOAD_ATTEMPTS`] times with exponential backoff from `base_backoff`.
+pub(crate) async fn upload_with_retry(
+    remote: &dyn RemoteStoragePort,
+    key: &str,
+    path: &Path,
+    base_backoff: Duration,
+) -> Result<(), DomainError> {
+    let mut attempt = 0u32;
+    loop {
+        attempt += 1;
+        let result = match remote.exists(key).await {
+            Ok(true) => Ok(()),
+            Ok(false) => remote.put_object(key, path).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt < MAX_UPLOAD_ATTEMPTS => {
+                let delay = base_backoff
+                    .saturating_mul(1 << (attempt - 1))
+                    .min(MAX_BACKOFF);
+                warn!(
+                    key,
+                    attempt,
+                    delay_ms = delay.as_millis() as u64,
+                    error = %e,
+                    "retrying upload"
+                );
+                sleep(delay).await;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::persistence::sqlite_repo::SqliteRepo;
+    use crate::domain::{MediaStatus, MediaType};
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    /// In-memory storage whose first `failures` puts fail.
+    #[derive(Default)]
+    struct FakeStorage {
+        objects: Mutex<HashMap<String, Vec<u8>>>,
+        failures: Mutex<u32>,
+        puts: Mutex<u32>,
+    }
+
+    #[async_trait::async_trait]
+    impl RemoteStoragePort for FakeStorage {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+
+        async fn put_object(&self, key: &str, path: &Path) -> Result<(), DomainError> {
+            *self.puts.lock().unwrap() += 1;
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                return Err(DomainError::Storage("503 Slow Down".to_string()));
+            }
+            let body = std::fs::read(path).map_err(|e| DomainError::Storage(e.to_string()))?;
+            self.objects.lock().unwrap().insert(key.to_string(), body);
+            Ok(())
+        }
+
+        async fn exists(&self, key: &str) -> Result<bool, DomainError> {
+            Ok(self.objects.lock().unwrap().contains_key(key))
+        }
+    }
+
+    fn done_file(message_id: i32) -> MediaFile {
+        MediaFile {
+            chat_id: 1,
+            message_id,
+            media_type: MediaType::Photo,
+            rel_path: format!("1_{}.jpg", message_id),
+            size_bytes: Some(3),
+            expected_size: None,
+            sha256: None,
+            status: MediaStatus::Done,
+            message_date: None,
+            remote_key: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_uploads_from_channel_and_sweep_with_retry() {
+        let base = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join("test_upload_worker");
+        let _ = std::fs::remove_dir_all(&base);
+        let media_dir = base.join("media");
+        std::fs::create_dir_all(&media_dir).unwrap();
+        let repo = Arc::new(SqliteRepo::connect(&base).await.unwrap());
+        for id in [1, 2] {
+            std::fs::write(media_dir.join(format!("1_{}.jpg", id)), b"img").unwrap();
+            repo.upsert_media_file(&done_file(id)).await.unwrap();
+        }
+        let storage = Arc::new(FakeStorage::default());
+        *storage.failures.lock().unwrap() = 2;
+
+        let (tx, rx) = mpsc::channel(4);
+        let mut worker =
+            UploadWorker::new(storage.clone(), repo.clone(), rx, media_dir.clone(), true);
+        worker.base_backoff = Duration::from_millis(1);
+        // File 2 arrives on the channel; file 1 is only found by the sweep.
+        tx.send(done_file(2)).await.unwrap();
+        let first = worker.next_file().await.unwrap();
+        assert_eq!(first.message_id, 2);
+        worker.upload_one(&first).await.unwrap();
+        let second = worker.next_file().await.unwrap();
+        assert_eq!(second.message_id, 1);
+        worker.upload_one(&second).await.unwrap();
+        drop(tx);
+        assert!(worker.next_file().await.is_none());
+
+        assert_eq!(*storage.puts.lock().unwrap(), 4);
+        assert_eq!(
+            storage.objects.lock().unwrap()["media/1_1.jpg"],
+            b"img".to_vec()
+        );
+        assert!(!media_dir.join("1_1.jpg").exists());
+        assert!(!media_dir.join("1_2.jpg").exists());
+        let files = repo
+            .list_media_files(Some(1), None, None, 10)
+            .await
+            .unwrap();
+        let keys: Vec<_> = files.iter().map(|f| f.remote_key.as_deref()).collect();
+        assert_eq!(keys, vec![Some("media/1_1.jpg"), Some("media/1_2.jpg")]);
+        assert!(
+            repo.list_unuploaded_media(None, 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        // A later re-download keeps the recorded key.
+        repo.upsert_media_file(&done_file(1)).await.unwrap();
+        assert!(
+            repo.list_unuploaded_media(None, 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_upload_gives_up_after_max_attempts() {
+        let storage = FakeStorage::default();
+        *storage.failures.lock().unwrap() = MAX_UPLOAD_ATTEMPTS;
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+        let err = upload_with_retry(&storage, "k", &path, Duration::from_millis(1))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Slow Down"));
+        assert_eq!(*storage.puts.lock().unwrap(), MAX_UPLOAD_ATTEMPTS);
+        // An object that is already stored is not uploaded again.
+        upload_with_retry(&storage, "k", &path, Duration::from_millis(1))
+            .await
+            .unwrap();
+        upload_with_retry(&storage, "k", &path, Duration::from_millis(1))
+            .await
+            .unwrap();
+        assert_eq!(*storage.puts.lock().unwrap(), MAX_UPLOAD_ATTEMPTS + 1);
+    }
+}
@@ -0,0 +1,216 @@
+//! Runs the watcher in the background: the menu stays usable while it watches.
+//!
+//! [`DaemonController`] owns at most one spawned [`WatcherService::run_loop`] task. Stopping
+//! raises the run's stop signal and waits for it, so the watcher finishes the chat it is
+//! syncing before it exits. The status of a run is stored by the watcher itself (settings
+//! table); the controller only corrects whether it is running in this process.
+
+use crate::domain::DomainError;
+use crate::usecases::watcher_service::{WatcherService, WatcherStatus};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, watch};
+use tokio::task::JoinHandle;
+use tracing::info;
+
+/// How often [`DaemonController::run_until`] checks whether the watcher ended on its own.
+const FINISHED_POLL: Duration = Duration::from_secs(1);
+
+/// Starts, stops and reports on the background watcher.
+pub struct DaemonController {
+    watcher: Arc<WatcherService>,
+    running: Mutex<Option<RunningWatcher>>,
+}
+
+/// A spawned watcher run.
+struct RunningWatcher {
+    stop: watch::Sender<bool>,
+    task: JoinHandle<Result<(), DomainError>>,
+}
+
+impl DaemonController {
+    pub fn new(watcher: Arc<WatcherService>) -> Self {
+        Self {
+            watcher,
+            running: Mutex::new(None),
+        }
+    }
+
+    /// Start the watcher in a background task. Fails while it is running.
+    pub async fn start(&self) -> Result<(), DomainError> {
+        let mut running = self.running.lock().await;
+        if running.as_ref().is_some_and(|r| !r.task.is_finished()) {
+            return Err(DomainError::State(
+                "the watcher is already running".to_string(),
+            ));
+        }
+        let (stop, stop_rx) = watch::channel(false);
+        let watcher = Arc::clone(&self.watcher);
+        let task = tokio::spawn(async move { watcher.run_loop(stop_rx).await });
+        *running = Some(RunningWatcher { stop, task });
+        info!("watcher started in the background");
+        Ok(())
+    }
+
+    /// Whether a watcher task of this process is running.
+    pub async fn is_running(&self) -> bool {
+        self.running
+            .lock()
+            .await
+            .as_ref()
+            .is_some_and(|r| !r.task.is_finished())
+    }
+
+    /// Stop the watcher after the chat it is syncing and wait for it. `Ok(false)` when no run
+    /// was started; the error a run ended with (also before this call) is returned.
+    pub async fn stop(&self) -> Result<bool, DomainError> {
+        let Some(running) = self.running.lock().await.take() else {
+            return Ok(false);
+        };
+        let _ = running.stop.send(true);
+        match running.task.await {
+            Ok(result) => result.map(|()| true),
+            Err(e) => Err(DomainError::State(format!("watcher task failed: {}", e))),
+        }
+    }
+
+    /// Stored status of the current or last run. A run stored as running but not running
+    /// here is reported as stopped (its process was killed).
+    pub async fn status(&self) -> Result<WatcherStatus, DomainError> {
+        let mut status = self.watcher.status().await?.unwrap_or_default();
+        status.running = self.is_running().await;
+        Ok(status)
+    }
+
+    /// Run the watcher until `shutdown` resolves or the run ends by itself, then stop it.
+    pub async fn run_until(&self, shutdown: impl Future<Output = ()>) -> Result<(), DomainError> {
+        self.start().await?;
+        let finished = async {
+            while self.is_running().await {
+                tokio::time::sleep(FINISHED_POLL).await;
+            }
+        };
+        tokio::select! {
+            _ = shutdown => info!("stopping the watcher"),
+            _ = finished => {}
+        }
+        self.stop().await.map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::persistence::sqlite_repo::SqliteRepo;
+    use crate::adapters::persistence::state_json::StateJson;
+    use crate::domain::{AlertMode, Chat, MediaReference, Message};
+    use crate::ports::{RepoPort, TgGateway};
+    use crate::shared::activity_flag::ActivityFlag;
+    use crate::usecases::SyncService;
+    use crate::usecases::watcher_service::Heartbeat;
+    use std::collections::HashSet;
+    use std::path::Path;
+    use tokio::sync::mpsc;
+
+    /// Telegram with no messages anywhere.
+    struct EmptyTg;
+
+    #[async_trait::async_trait]
+    impl TgGateway for EmptyTg {
+        async fn get_dialogs(&self) -> Result<Vec<Chat>, DomainError> {
+            Ok(Vec::new())
+        }
+
+        async fn get_messages(
+            &self,
+            _chat_id: i64,
+            _min_id: i32,
+            _max_id: i32,
+            _limit: i32,
+        ) -> Result<Vec<Message>, DomainError> {
+            Ok(Vec::new())
+        }
+
+        async fn download_media(
+            &self,
+            _media_ref: &MediaReference,
+            _dest_path: &Path,
+        ) -> Result<(), DomainError> {
+            Ok(())
+        }
+
+        async fn get_me_id(&self) -> Result<i64, DomainError> {
+            Ok(1)
+        }
+
+        async fn send_message(&self, _chat_id: i64, _text: &str) -> Result<(), DomainError> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_watcher_runs_in_background_and_stops_during_sleep() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join("test_watcher_daemon");
+        let _ = std::fs::remove_dir_all(&dir);
+        let repo = Arc::new(SqliteRepo::connect(&dir).await.unwrap());
+        repo.update_targets(HashSet::from([9])).await.unwrap();
+        let tg = Arc::new(EmptyTg);
+        let (media_tx, _media_rx) = mpsc::channel(4);
+        let sync = Arc::new(SyncService::new(
+            tg.clone(),
+            repo.clone(),
+            Arc::new(StateJson::new(dir.join("state.json"))),
+            media_tx,
+            repo.clone(),
+            repo.clone(),
+            0,
+            dir.join("reports"),
+            None,
+            ActivityFlag::new(),
+        ));
+        let watcher = Arc::new(WatcherService::new(
+            tg,
+            repo.clone(),
+            repo.clone(),
+            repo.clone(),
+            sync,
+            // Far longer than the test: stopping must interrupt the sleep.
+            Duration::from_secs(3600),
+            None,
+            Vec::new(),
+            None,
+            AlertMode::Digest,
+            0,
+            None,
+            Heartbeat::default(),
+            ActivityFlag::new(),
+        ));
+        let daemon = DaemonController::new(watcher.clone());
+        assert_eq!(daemon.status().await.unwrap(), WatcherStatus::default());
+
+        daemon.start().await.unwrap();
+        assert!(daemon.start().await.is_err());
+        let mut status = daemon.status().await.unwrap();
+        for _ in 0..200 {
+            if status.last_cycle_at.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            status = daemon.status().await.unwrap();
+        }
+        assert!(status.running);
+        assert_eq!(status.chats_watched, 1);
+        assert_eq!(status.pid, std::process::id());
+
+        assert!(daemon.stop().await.unwrap());
+        assert!(!daemon.stop().await.unwrap());
+        let stored = watcher.status().await.unwrap().unwrap();
+        assert!(!stored.running);
+        assert!(stored.stopped_at.is_some());
+        assert_eq!(stored.last_error, None);
+        assert!(stored.to_string().starts_with("Watcher: stopped at"));
+    }
+}
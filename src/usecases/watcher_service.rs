@@ -1,90 +1,872 @@
-//! Watcher (Daemon) use case: sync target chats periodically and notify via Saved Messages when keywords are found.
+//! Watcher (Daemon) use case: sync target chats periodically and send an alert when keywords are found.
+//!
+//! Alerts go to Saved Messages unless another destination chat is stored (settings table) or
+//! configured (TG_SYNC_ALERT_CHAT_ID); after two failed sends in a row the watcher falls back
+//! to Saved Messages for the rest of the run. Telegram is one notifier among the configured
+//! ones ([`NotifierPort`], e.g. a webhook): every alert goes to all that accept it, as does the
+//! end of an automatic backup and an error that ends the run.
 //!
 //! Orchestrates SyncService, RepoPort, and TgGateway. Does not block the main thread; uses tokio::time::sleep.
+//! With notifiers configured (e.g. email), also sends a daily digest of per-chat activity.
+//! Keywords are watch rules (WatchRulePort), global or per chat, re-read every cycle so edits
+//! from the TUI apply without a restart. Mention and reply rules, toggled the same way, alert
+//! on messages that mention the signed-in account or reply to one of its archived messages,
+//! whatever their text; the alert names the trigger ("mention", "reply to you" or the keyword).
+//!
+//! In digest mode (TG_SYNC_ALERT_MODE, default) all matches of one chat in a cycle become one
+//! alert with counts per keyword; in message mode every matching message is an alert. With
+//! TG_SYNC_ALERT_COOLDOWN_SECS, a keyword that was alerted for in a chat is only counted until
+//! the window is over; the held-back count is included in the next alert. Cooldowns are kept
+//! in the settings table, so a restart doesn't re-send them.
+//!
+//! Targets whose per-chat settings turn the watcher off (`sync_enabled = false`) are skipped.
+//!
+//! With an automatic backup configured (TG_SYNC_AUTO_BACKUP_*), a due backup of all allowed
+//! chats runs in the time the watcher would otherwise sleep, one chat at a time; the targets
+//! are still checked every cycle until it is done.
+//!
+//! With a heartbeat configured (TG_SYNC_HEARTBEAT_*), every cycle in which no chat failed is
+//! reported to a [`HeartbeatPort`], and a daily "still alive" summary goes to Saved Messages
+//! once the configured hour has come. The summary's day (messages archived, whether it was
+//! sent) is kept in the settings table, so a restart doesn't send it twice.
+//!
+//! A run stops when its stop signal is raised: before the next chat or during the cycle
+//! sleep, never in the middle of a chat's sync. Its progress ([`WatcherStatus`]) is kept in
+//! the settings table, so another process (`tg-sync watch --status`) can read it.
 
-use crate::domain::DomainError;
-use crate::ports::{RepoPort, TgGateway};
-use crate::usecases::sync_service::SyncService;
-use std::collections::HashMap;
+use crate::adapters::notify::TelegramNotifier;
+use crate::domain::{
+    AlertMode, AutoBackupScope, Chat, DomainError, MediaFilter, Message, NotificationEvent,
+    TopicFilter, WatchRule, WatchRuleKind,
+};
+use crate::ports::{HeartbeatPort, NotifierPort, RepoPort, SettingsPort, TgGateway, WatchRulePort};
+use crate::shared::activity_flag::ActivityFlag;
+use crate::shared::systemd;
+use crate::usecases::notify::notify_all;
+use crate::usecases::sync_service::{ChatSyncResult, SyncService};
+use chrono::{DateTime, Timelike, Utc};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt;
 use std::sync::Arc;
 use std::time::Duration;
-use tracing::{info, warn};
+use tokio::sync::watch;
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+
+/// Seconds between daily digests.
+const DIGEST_INTERVAL_SECS: i64 = 24 * 60 * 60;
+
+/// Settings key of the alert destination chat picked in the TUI.
+const ALERT_CHAT_SETTING: &str = "watcher.alert_chat_id";
+
+/// Settings key of the alert cooldown state (JSON).
+const ALERT_COOLDOWN_SETTING: &str = "watcher.alert_cooldowns";
+
+/// Settings key of the status of the current or last run (JSON).
+const STATUS_SETTING: &str = "watcher.status";
+
+/// Settings key of the time the last automatic backup finished (unix seconds).
+const AUTO_BACKUP_SETTING: &str = "watcher.last_auto_backup";
+
+/// Settings key of the heartbeat's current day (JSON).
+const HEARTBEAT_SETTING: &str = "watcher.heartbeat";
+
+/// Synced messages loaded per page when scanning for keywords.
+const SCAN_PAGE: u32 = 500;
+
+/// Alert destination stored in settings (picked in the TUI), else `default` from config.
+/// None = Saved Messages. Also where AI digests are posted.
+pub(crate) async fn stored_alert_chat(
+    settings: &dyn SettingsPort,
+    default: Option<i64>,
+) -> Result<Option<i64>, DomainError> {
+    let stored = settings.get_setting(ALERT_CHAT_SETTING).await?;
+    Ok(match stored.as_deref().map(str::parse::<i64>) {
+        Some(Ok(id)) => Some(id),
+        Some(Err(_)) => {
+            warn!(value = ?stored, "ignoring invalid stored alert chat id");
+            default
+        }
+        None => default,
+    })
+}
+
+/// Status of the current or last watcher run, as stored in settings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WatcherStatus {
+    /// Whether the run was going on when the status was stored. A run whose process died
+    /// stays "running" here; readers check the process.
+    pub running: bool,
+    /// Process that runs (or ran) the watcher.
+    pub pid: u32,
+    /// Unix timestamps.
+    pub started_at: Option<i64>,
+    pub last_cycle_at: Option<i64>,
+    pub stopped_at: Option<i64>,
+    /// Latest failure: a chat's sync or alert scan, or what ended the run.
+    pub last_error: Option<String>,
+    /// Target chats in the last cycle.
+    pub chats_watched: usize,
+    /// Alerts sent since the run started.
+    pub alerts_sent: u64,
+    /// When the last automatic backup finished.
+    #[serde(default)]
+    pub last_auto_backup_at: Option<i64>,
+    /// Chats the running automatic backup has yet to sync; 0 when none runs.
+    #[serde(default)]
+    pub auto_backup_left: usize,
+}
+
+impl WatcherStatus {
+    /// Status stored in settings; None when the watcher never ran.
+    pub async fn load(settings: &dyn SettingsPort) -> Result<Option<Self>, DomainError> {
+        match settings.get_setting(STATUS_SETTING).await? {
+            Some(json) => serde_json::from_str(&json)
+                .map(Some)
+                .map_err(|e| DomainError::Repo(format!("invalid watcher status: {}", e))),
+            None => Ok(None),
+        }
+    }
+
+    /// Store in settings. Failures are logged; the watcher keeps running.
+    async fn save(&self, settings: &dyn SettingsPort) {
+        let result = match serde_json::to_string(self) {
+            Ok(json) => settings.set_setting(STATUS_SETTING, Some(&json)).await,
+            Err(e) => Err(DomainError::Repo(e.to_string())),
+        };
+        if let Err(e) = result {
+            warn!(error = %e, "failed to store watcher status");
+        }
+    }
+}
+
+impl fmt::Display for WatcherStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = |ts: Option<i64>| {
+            ts.and_then(|ts| DateTime::<Utc>::from_timestamp(ts, 0))
+                .map(|dt| dt.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+                .unwrap_or_else(|| "never".to_string())
+        };
+        if self.started_at.is_none() {
+            return write!(f, "Watcher: never started");
+        }
+        if self.running {
+            writeln!(
+                f,
+                "Watcher: running (pid {}) since {}",
+                self.pid,
+                time(self.started_at)
+            )?;
+        } else {
+            writeln!(f, "Watcher: stopped at {}", time(self.stopped_at))?;
+        }
+        writeln!(f, "Last cycle: {}", time(self.last_cycle_at))?;
+        writeln!(f, "Chats watched: {}", self.chats_watched)?;
+        write!(f, "Alerts sent: {}", self.alerts_sent)?;
+        if self.last_auto_backup_at.is_some() || self.auto_backup_left > 0 {
+            write!(
+                f,
+                "\nLast automatic backup: {}",
+                time(self.last_auto_backup_at)
+            )?;
+        }
+        if self.auto_backup_left > 0 {
+            write!(
+                f,
+                " (one is running, {} chat(s) left)",
+                self.auto_backup_left
+            )?;
+        }
+        if let Some(error) = &self.last_error {
+            write!(f, "\nLast error: {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Automatic backup run by the watcher (TG_SYNC_AUTO_BACKUP_*).
+#[derive(Debug, Clone)]
+pub struct AutoBackup {
+    /// Time between backups, from the end of one to the start of the next.
+    pub every: Duration,
+    pub scope: AutoBackupScope,
+    /// Media downloaded by the backup ([`MediaFilter::none`]: text only).
+    pub media: MediaFilter,
+}
 
-/// Hardcoded keywords (case-insensitive match). Notify when any new message contains one of these.
-const KEYWORDS: &[&str] = &["Urgent", "Bug", "Error", "Production"];
+/// Liveness reporting of the watcher (TG_SYNC_HEARTBEAT_*). The default reports nothing.
+#[derive(Clone, Default)]
+pub struct Heartbeat {
+    /// Pinged after every cycle in which no chat failed.
+    pub pinger: Option<Arc<dyn HeartbeatPort>>,
+    /// Local hour (0-23) from which the daily summary is posted to Saved Messages; None = off.
+    pub daily_hour: Option<u32>,
+    /// UTC offset of `daily_hour` and of the summary's day, in seconds.
+    pub utc_offset_secs: i32,
+}
+
+impl Heartbeat {
+    fn is_enabled(&self) -> bool {
+        self.pinger.is_some() || self.daily_hour.is_some()
+    }
+
+    /// Local date (`YYYY-MM-DD`) and hour now.
+    fn local_now(&self) -> (String, u32) {
+        let local = Utc::now() + chrono::Duration::seconds(i64::from(self.utc_offset_secs));
+        (local.format("%Y-%m-%d").to_string(), local.hour())
+    }
+}
+
+/// The day of the daily summary, as stored in settings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct HeartbeatDay {
+    /// Local date, `YYYY-MM-DD`.
+    day: String,
+    /// Messages the watcher archived that day (targets and automatic backups).
+    archived: u64,
+    summary_sent: bool,
+}
 
-/// Watcher service. Runs a loop: sync target chats -> check new messages for keywords -> notify to Saved Messages -> sleep.
+impl HeartbeatDay {
+    /// Start a new day (nothing archived, no summary sent) unless it is already `today`.
+    fn roll(&mut self, today: &str) {
+        if self.day != today {
+            *self = Self {
+                day: today.to_string(),
+                ..Self::default()
+            };
+        }
+    }
+
+    /// Count `messages` archived on `today`.
+    fn record(&mut self, today: &str, messages: u64) {
+        self.roll(today);
+        self.archived += messages;
+    }
+
+    /// Stored day; a new one when none (or an unreadable one) is stored.
+    async fn load(settings: &dyn SettingsPort) -> Self {
+        match settings.get_setting(HEARTBEAT_SETTING).await {
+            Ok(Some(json)) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                warn!(error = %e, "ignoring invalid stored heartbeat state");
+                Self::default()
+            }),
+            Ok(None) => Self::default(),
+            Err(e) => {
+                warn!(error = %e, "failed to read heartbeat state");
+                Self::default()
+            }
+        }
+    }
+
+    /// Store in settings. Failures are logged; the watcher keeps running.
+    async fn save(&self, settings: &dyn SettingsPort) {
+        let result = match serde_json::to_string(self) {
+            Ok(json) => settings.set_setting(HEARTBEAT_SETTING, Some(&json)).await,
+            Err(e) => Err(DomainError::Repo(e.to_string())),
+        };
+        if let Err(e) = result {
+            warn!(error = %e, "failed to store heartbeat state");
+        }
+    }
+}
+
+/// An automatic backup in progress.
+struct AutoBackupRun {
+    chats: Vec<Chat>,
+    /// Chats not synced yet, in order.
+    queue: VecDeque<Chat>,
+    results: Vec<ChatSyncResult>,
+}
+
+/// Watcher service. Runs a loop: sync target chats -> check new messages for keywords -> send alerts -> sleep.
 pub struct WatcherService {
     tg: Arc<dyn TgGateway>,
     repo: Arc<dyn RepoPort>,
+    rules: Arc<dyn WatchRulePort>,
+    settings: Arc<dyn SettingsPort>,
     sync_service: Arc<SyncService>,
     /// Sleep duration between cycles.
     cycle_sleep: Duration,
+    /// systemd watchdog ping interval (`None` when not supervised).
+    watchdog: Option<Duration>,
+    /// Configured notifiers (email, webhook): daily digest, alerts, backup results, errors.
+    notifiers: Vec<Arc<dyn NotifierPort>>,
+    /// Alert destination from config, used when none is stored in settings.
+    default_alert_chat: Option<i64>,
+    /// Combined or per-message alerts.
+    alert_mode: AlertMode,
+    /// Seconds a keyword stays quiet in a chat after an alert; 0 = no cooldown.
+    alert_cooldown_secs: u64,
+    /// Periodic backup of all allowed chats; None = off.
+    auto_backup: Option<AutoBackup>,
+    /// Liveness pings and daily summary.
+    heartbeat: Heartbeat,
+    /// Marks the watcher as running; maintenance refuses to start meanwhile.
+    activity: ActivityFlag,
 }
 
 impl WatcherService {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         tg: Arc<dyn TgGateway>,
         repo: Arc<dyn RepoPort>,
+        rules: Arc<dyn WatchRulePort>,
+        settings: Arc<dyn SettingsPort>,
         sync_service: Arc<SyncService>,
         cycle_sleep: Duration,
+        watchdog: Option<Duration>,
+        notifiers: Vec<Arc<dyn NotifierPort>>,
+        default_alert_chat: Option<i64>,
+        alert_mode: AlertMode,
+        alert_cooldown_secs: u64,
+        auto_backup: Option<AutoBackup>,
+        heartbeat: Heartbeat,
+        activity: ActivityFlag,
     ) -> Self {
         Self {
             tg,
             repo,
+            rules,
+            settings,
             sync_service,
             cycle_sleep,
+            watchdog,
+            notifiers,
+            default_alert_chat,
+            alert_mode,
+            alert_cooldown_secs,
+            auto_backup,
+            heartbeat,
+            activity,
         }
     }
 
     /// Run the watcher loop. Iterates target chats, syncs, checks for keywords, notifies, then sleeps.
-    /// Call this from the Watcher menu branch; it runs until the user stops the process.
-    pub async fn run_loop(&self) -> Result<(), DomainError> {
+    /// Runs until `stop` is raised (or its sender dropped); the status is stored as it goes.
+    pub async fn run_loop(&self, mut stop: watch::Receiver<bool>) -> Result<(), DomainError> {
+        let mut status = WatcherStatus {
+            running: true,
+            pid: std::process::id(),
+            started_at: Some(Utc::now().timestamp()),
+            ..WatcherStatus::default()
+        };
+        status.save(self.settings.as_ref()).await;
+        let result = self.watch(&mut stop, &mut status).await;
+        status.running = false;
+        status.stopped_at = Some(Utc::now().timestamp());
+        if let Err(e) = &result {
+            status.last_error = Some(e.to_string());
+            let event = NotificationEvent::Error {
+                context: "watcher".to_string(),
+                message: format!("The watcher stopped: {}", e),
+            };
+            notify_all(&self.notifiers, &event).await;
+        }
+        status.save(self.settings.as_ref()).await;
+        info!(alerts_sent = status.alerts_sent, "Watcher stopped");
+        result
+    }
+
+    /// Status of the current or last run; None when the watcher never ran.
+    pub async fn status(&self) -> Result<Option<WatcherStatus>, DomainError> {
+        WatcherStatus::load(self.settings.as_ref()).await
+    }
+
+    async fn watch(
+        &self,
+        stop: &mut watch::Receiver<bool>,
+        status: &mut WatcherStatus,
+    ) -> Result<(), DomainError> {
+        let _running = self.activity.begin("watcher")?;
         let me_id = self.tg.get_me_id().await?;
-        info!(
-            me_id,
-            "Watcher started; notifications will go to Saved Messages"
-        );
+        let mut telegram =
+            TelegramNotifier::new(Arc::clone(&self.tg), self.resolve_alert_chat(me_id).await?);
+        // A bot has no Saved Messages to fall back to; its daily summary goes to the alert chat.
+        let summary_chat = if self.tg.is_bot() {
+            telegram.chat_id()
+        } else {
+            telegram = telegram.with_fallback(me_id);
+            me_id
+        };
+        info!(me_id, alert_chat_id = telegram.chat_id(), alert_mode = ?self.alert_mode, "Watcher started");
+        let mut alert = Alerts {
+            notifiers: std::iter::once(Arc::new(telegram) as Arc<dyn NotifierPort>)
+                .chain(self.notifiers.iter().cloned())
+                .collect(),
+            sent: 0,
+        };
+        let mut cooldowns =
+            AlertCooldowns::load(self.settings.as_ref(), self.alert_cooldown_secs).await;
 
+        let mut digest = DailyDigest::new(Utc::now().timestamp());
+        let mut backup: Option<AutoBackupRun> = None;
+        let mut day = HeartbeatDay::load(self.settings.as_ref()).await;
         loop {
-            let target_ids = self.repo.get_target_ids().await?;
-            if target_ids.is_empty() {
-                info!("No target chats; sleeping until next cycle");
-                tokio::time::sleep(self.cycle_sleep).await;
-                continue;
-            }
-
-            let chat_titles = self.chat_id_to_title_map(&target_ids).await?;
+            let mut chat_failed = false;
+            let target_ids = self.sync_targets().await?;
+            let chat_titles = if target_ids.is_empty() {
+                info!("No target chats");
+                HashMap::new()
+            } else {
+                self.chat_id_to_title_map(&target_ids).await?
+            };
+            let matcher = KeywordMatcher::new(&self.rules.list_watch_rules().await?);
 
             for &chat_id in &target_ids {
-                if let Err(e) = self
+                // Stop between chats, never during a chat's sync.
+                if *stop.borrow() {
+                    return Ok(());
+                }
+                let fallback = chat_id.to_string();
+                let title = chat_titles.get(&chat_id).unwrap_or(&fallback);
+                let before = cooldowns.clone();
+                match self
                     .sync_and_notify_keywords(
                         chat_id,
                         me_id,
-                        chat_titles.get(&chat_id).map(|s| s.as_str()),
+                        &mut alert,
+                        &mut cooldowns,
+                        title,
+                        &matcher,
                     )
                     .await
                 {
-                    warn!(chat_id, error = %e, "Watcher sync/notify failed for chat");
+                    Ok((synced, alerts)) => {
+                        digest.record(title, synced, alerts);
+                        day.record(&self.heartbeat.local_now().0, synced);
+                    }
+                    Err(e) => {
+                        chat_failed = true;
+                        warn!(chat_id, error = %e, "Watcher sync/notify failed for chat");
+                        status.last_error = Some(format!("chat {}: {}", chat_id, e));
+                    }
+                }
+                status.alerts_sent = alert.sent;
+                if cooldowns != before {
+                    cooldowns.save(self.settings.as_ref()).await;
                 }
+                self.ping_watchdog();
+            }
+
+            self.send_digest_if_due(&mut digest).await;
+            self.report_alive(&mut day, summary_chat, target_ids.len(), !chat_failed)
+                .await;
+            status.chats_watched = target_ids.len();
+            status.last_cycle_at = Some(Utc::now().timestamp());
+            status.save(self.settings.as_ref()).await;
+
+            // A due automatic backup uses the cycle's sleep, one chat at a time, so the
+            // targets are still checked every cycle while it runs.
+            let next_cycle = Instant::now() + self.cycle_sleep;
+            if let Some(auto) = &self.auto_backup {
+                if self
+                    .auto_backup_until(auto, &mut backup, &mut day, stop, next_cycle)
+                    .await
+                {
+                    return Ok(());
+                }
+                status.last_auto_backup_at = self.last_auto_backup().await;
+                status.auto_backup_left = backup.as_ref().map_or(0, |run| run.queue.len());
+                status.save(self.settings.as_ref()).await;
+            }
+            if backup.is_some() {
+                continue;
             }
 
             info!(
                 cycle_secs = self.cycle_sleep.as_secs(),
                 "Cycle complete; sleeping"
             );
-            tokio::time::sleep(self.cycle_sleep).await;
+            let left = next_cycle.saturating_duration_since(Instant::now());
+            if self.sleep_cycle(stop, left).await {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Work on the automatic backup until `until`: start one when it is due, then sync its
+    /// chats one at a time (a chat's sync is never cut short). Returns true when a stop was
+    /// requested. The finish time is stored in settings, so a restart doesn't run it again.
+    /// Synced messages are counted in `day`.
+    async fn auto_backup_until(
+        &self,
+        auto: &AutoBackup,
+        run: &mut Option<AutoBackupRun>,
+        day: &mut HeartbeatDay,
+        stop: &watch::Receiver<bool>,
+        until: Instant,
+    ) -> bool {
+        if run.is_none() && self.auto_backup_due(auto).await {
+            match self.auto_backup_chats(auto.scope).await {
+                Ok(chats) => {
+                    info!(chats = chats.len(), "automatic backup started");
+                    *run = Some(AutoBackupRun {
+                        queue: chats.iter().cloned().collect(),
+                        chats,
+                        results: Vec::new(),
+                    });
+                }
+                Err(e) => warn!(error = %e, "automatic backup could not list chats"),
+            }
+        }
+        let Some(current) = run.as_mut() else {
+            return false;
+        };
+        while Instant::now() < until {
+            if *stop.borrow() {
+                return true;
+            }
+            let Some(chat) = current.queue.pop_front() else {
+                break;
+            };
+            // Without takeout, sync_chats never fails as a whole.
+            let results = self
+                .sync_service
+                .sync_chats(
+                    std::slice::from_ref(&chat),
+                    100,
+                    &auto.media,
+                    None,
+                    &TopicFilter::default(),
+                    false,
+                )
+                .await
+                .unwrap_or_default();
+            for stats in results.iter().filter_map(|(_, r)| r.as_ref().ok()) {
+                day.record(&self.heartbeat.local_now().0, stats.messages_synced as u64);
+            }
+            current.results.extend(results);
+            self.ping_watchdog();
+        }
+        if current.queue.is_empty() {
+            let failed = current.results.iter().filter(|(_, r)| r.is_err()).count();
+            info!(
+                chats = current.chats.len(),
+                failed, "automatic backup finished"
+            );
+            let event = NotificationEvent::SyncCompleted {
+                chats: current.chats.len(),
+                messages: current
+                    .results
+                    .iter()
+                    .filter_map(|(_, r)| r.as_ref().ok())
+                    .map(|s| s.messages_synced as u64)
+                    .sum(),
+                failed,
+            };
+            notify_all(&self.notifiers, &event).await;
+            match self
+                .sync_service
+                .write_report(&current.chats, &current.results)
+                .await
+            {
+                Ok(path) => info!(path = %path.display(), "automatic backup summary saved"),
+                Err(e) => warn!(error = %e, "failed to save automatic backup summary"),
+            }
+            let now = Utc::now().timestamp().to_string();
+            if let Err(e) = self
+                .settings
+                .set_setting(AUTO_BACKUP_SETTING, Some(&now))
+                .await
+            {
+                warn!(error = %e, "failed to store automatic backup time");
+            }
+            *run = None;
+        }
+        false
+    }
+
+    /// Unix time the last automatic backup finished; None if never (or unreadable).
+    async fn last_auto_backup(&self) -> Option<i64> {
+        match self.settings.get_setting(AUTO_BACKUP_SETTING).await {
+            Ok(stored) => stored.and_then(|ts| ts.parse().ok()),
+            Err(e) => {
+                warn!(error = %e, "failed to read automatic backup time");
+                None
+            }
+        }
+    }
+
+    /// Whether `auto.every` has passed since the last automatic backup finished.
+    async fn auto_backup_due(&self, auto: &AutoBackup) -> bool {
+        let every = i64::try_from(auto.every.as_secs()).unwrap_or(i64::MAX);
+        self.last_auto_backup()
+            .await
+            .is_none_or(|last| Utc::now().timestamp().saturating_sub(last) >= every)
+    }
+
+    /// Chats of an automatic backup, most recently active first: the dialogs (or only the
+    /// targets) minus the blacklist and chats whose per-chat settings switch syncing off.
+    async fn auto_backup_chats(&self, scope: AutoBackupScope) -> Result<Vec<Chat>, DomainError> {
+        let blacklist = self.repo.get_blacklisted_ids().await?;
+        let targets = match scope {
+            AutoBackupScope::AllChats => None,
+            AutoBackupScope::Targets => Some(self.repo.get_target_ids().await?),
+        };
+        let disabled: HashSet<i64> = self
+            .repo
+            .list_chat_settings()
+            .await?
+            .into_iter()
+            .filter(|s| !s.sync_enabled)
+            .map(|s| s.chat_id)
+            .collect();
+        let mut chats: Vec<Chat> = self
+            .tg
+            .get_dialogs()
+            .await?
+            .into_iter()
+            .filter(|c| {
+                !blacklist.contains(&c.id)
+                    && !disabled.contains(&c.id)
+                    && targets.as_ref().is_none_or(|t| t.contains(&c.id))
+            })
+            .collect();
+        chats.sort_by_key(|c| std::cmp::Reverse(c.last_message_date));
+        Ok(chats)
+    }
+
+    /// Target chats, minus those whose per-chat settings switch syncing off.
+    async fn sync_targets(&self) -> Result<HashSet<i64>, DomainError> {
+        let mut targets = self.repo.get_target_ids().await?;
+        for settings in self.repo.list_chat_settings().await? {
+            if !settings.sync_enabled && targets.remove(&settings.chat_id) {
+                debug!(
+                    chat_id = settings.chat_id,
+                    "target skipped: sync disabled in chat settings"
+                );
+            }
+        }
+        Ok(targets)
+    }
+
+    /// Send the daily digest once a day has passed since the last one, then start a new one.
+    /// Failures are logged; the watcher keeps running.
+    async fn send_digest_if_due(&self, digest: &mut DailyDigest) {
+        let now = Utc::now().timestamp();
+        if self.notifiers.is_empty() || now - digest.since < DIGEST_INTERVAL_SECS {
+            return;
+        }
+        let delivered = notify_all(&self.notifiers, &digest.to_event(now)).await;
+        info!(delivered, "daily digest sent");
+        *digest = DailyDigest::new(now);
+    }
+
+    /// After a cycle: ping the heartbeat when no chat failed (`cycle_ok`), post the daily
+    /// summary to `summary_chat` (Saved Messages, or the alert chat of a bot) once its hour
+    /// has come, and store the day. Failures are logged and retried next cycle; the watcher
+    /// keeps running.
+    async fn report_alive(
+        &self,
+        day: &mut HeartbeatDay,
+        summary_chat: i64,
+        chats_watched: usize,
+        cycle_ok: bool,
+    ) {
+        if !self.heartbeat.is_enabled() {
+            return;
+        }
+        if let Some(pinger) = self.heartbeat.pinger.as_ref().filter(|_| cycle_ok) {
+            let summary = format!(
+                "tg-sync watcher: cycle ok, {} chat(s) watched",
+                chats_watched
+            );
+            if let Err(e) = pinger.ping(&summary).await {
+                warn!(heartbeat = pinger.name(), error = %e, "heartbeat ping failed");
+            }
+        }
+        let (today, hour) = self.heartbeat.local_now();
+        day.roll(&today);
+        if self.heartbeat.daily_hour.is_some_and(|at| hour >= at) && !day.summary_sent {
+            let text = format!(
+                "tg-sync watcher still alive: {} chat(s) watched, {} message(s) archived today",
+                chats_watched, day.archived
+            );
+            match self.tg.send_message(summary_chat, &text).await {
+                Ok(()) => {
+                    day.summary_sent = true;
+                    info!(archived = day.archived, "daily summary sent");
+                }
+                Err(e) => warn!(error = %e, "failed to send daily summary"),
+            }
+        }
+        day.save(self.settings.as_ref()).await;
+    }
+
+    fn ping_watchdog(&self) {
+        if self.watchdog.is_some() {
+            systemd::notify_watchdog();
         }
     }
 
-    /// Build a map chat_id -> title for the given ids (from get_dialogs).
+    /// Sleep for `duration` (the rest of a cycle), or until `stop` is raised. Returns true
+    /// when stopped.
+    async fn sleep_cycle(&self, stop: &mut watch::Receiver<bool>, duration: Duration) -> bool {
+        tokio::select! {
+            _ = self.sleep_watchdog(duration) => false,
+            // A dropped sender (Err) stops the run as well.
+            _ = stop.wait_for(|&stop| stop) => true,
+        }
+    }
+
+    /// Sleep for `duration`. Under a systemd watchdog, wake up every ping interval so the
+    /// cycle sleep (default 10 min) never outlasts `WatchdogSec`.
+    async fn sleep_watchdog(&self, duration: Duration) {
+        let Some(ping) = self.watchdog else {
+            tokio::time::sleep(duration).await;
+            return;
+        };
+        let deadline = Instant::now() + duration;
+        loop {
+            systemd::notify_watchdog();
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            tokio::time::sleep(left.min(ping)).await;
+        }
+    }
+
+    /// Alert destination chosen in the TUI, else from config. None = Saved Messages.
+    pub async fn alert_chat(&self) -> Result<Option<i64>, DomainError> {
+        stored_alert_chat(self.settings.as_ref(), self.default_alert_chat).await
+    }
+
+    /// Persist the alert destination. None = Saved Messages.
+    pub async fn set_alert_chat(&self, chat_id: Option<i64>) -> Result<(), DomainError> {
+        let value = chat_id.map(|id| id.to_string());
+        self.settings
+            .set_setting(ALERT_CHAT_SETTING, value.as_deref())
+            .await
+    }
+
+    /// Alert destination for this run: the chosen chat if it is one of the account's dialogs,
+    /// else Saved Messages (`me_id`) with a warning. A bot has no Saved Messages: its alert
+    /// chat is required and must resolve, or the watcher does not start.
+    async fn resolve_alert_chat(&self, me_id: i64) -> Result<i64, DomainError> {
+        let bot = self.tg.is_bot();
+        let Some(chat_id) = self.alert_chat().await?.filter(|&id| id != me_id) else {
+            if bot {
+                return Err(DomainError::Notify(
+                    "a bot account has no Saved Messages; choose an alert chat \
+                     (TG_SYNC_ALERT_CHAT_ID or the Watcher menu)"
+                        .to_string(),
+                ));
+            }
+            return Ok(me_id);
+        };
+        let found = match self
+            .tg
+            .get_dialogs()
+            .await?
+            .into_iter()
+            .find(|c| c.id == chat_id)
+        {
+            Some(chat) => Some(chat),
+            None if bot => self.tg.resolve_chat(&chat_id.to_string()).await.ok(),
+            None => None,
+        };
+        match found {
+            Some(chat) => {
+                info!(chat_id, title = %chat.title, "Keyword alerts go to chat");
+                Ok(chat_id)
+            }
+            None if bot => Err(DomainError::Notify(format!(
+                "alert chat {} not found; add the bot to it and list it in TG_SYNC_BOT_CHATS",
+                chat_id
+            ))),
+            None => {
+                warn!(
+                    chat_id,
+                    "Alert chat not found in dialogs; alerts go to Saved Messages"
+                );
+                Ok(me_id)
+            }
+        }
+    }
+
+    /// Send one alert to every alert notifier and log the outcome. Returns true when at least
+    /// one delivered it.
+    async fn deliver_alert(
+        &self,
+        alert: &mut Alerts,
+        chat_id: i64,
+        title: &str,
+        text: &str,
+    ) -> bool {
+        let event = NotificationEvent::KeywordAlert {
+            chat_id,
+            chat_title: title.to_string(),
+            text: text.to_string(),
+        };
+        if notify_all(&alert.notifiers, &event).await == 0 {
+            warn!(chat_id, "Failed to send alert");
+            return false;
+        }
+        info!(chat_id, "Alert sent");
+        alert.sent += 1;
+        true
+    }
+
+    /// All watch rules, global ones first.
+    pub async fn list_rules(&self) -> Result<Vec<WatchRule>, DomainError> {
+        self.rules.list_watch_rules().await
+    }
+
+    /// Validate and store a rule (`chat_id` 0 = global). Returns the rule id.
+    pub async fn add_rule(
+        &self,
+        chat_id: i64,
+        pattern: &str,
+        is_regex: bool,
+    ) -> Result<i64, DomainError> {
+        let pattern = pattern.trim();
+        validate_watch_pattern(pattern, is_regex)?;
+        self.rules.add_watch_rule(chat_id, pattern, is_regex).await
+    }
+
+    /// Delete a rule by id.
+    pub async fn remove_rule(&self, id: i64) -> Result<bool, DomainError> {
+        self.rules.remove_watch_rule(id).await
+    }
+
+    /// Turn alerts on mentions of me or replies to my messages on or off for `chat_id`
+    /// (0 = global). Returns the rule id.
+    pub async fn set_trigger(
+        &self,
+        chat_id: i64,
+        kind: WatchRuleKind,
+        enabled: bool,
+    ) -> Result<i64, DomainError> {
+        if kind == WatchRuleKind::Keyword {
+            return Err(DomainError::WatchRule(
+                "keywords are added with a pattern".into(),
+            ));
+        }
+        self.rules.set_watch_trigger(chat_id, kind, enabled).await
+    }
+
+    /// Build a map chat_id -> title for the given ids. Dialog titles are stored first (so
+    /// renames are recorded); targets no longer among the dialogs keep their stored title.
     async fn chat_id_to_title_map(
         &self,
-        target_ids: &std::collections::HashSet<i64>,
+        target_ids: &HashSet<i64>,
     ) -> Result<HashMap<i64, String>, DomainError> {
         let dialogs = self.tg.get_dialogs().await?;
+        if let Err(e) = self.repo.upsert_chats(&dialogs).await {
+            warn!(error = %e, "failed to store chat titles");
+        }
         let mut map = HashMap::new();
-        for chat in dialogs {
+        for chat in self
+            .repo
+            .get_known_chats()
+            .await?
+            .into_iter()
+            .chain(dialogs)
+        {
             if target_ids.contains(&chat.id) {
                 map.insert(chat.id, chat.title);
             }
@@ -92,54 +874,481 @@ impl WatcherService {
         Ok(map)
     }
 
-    /// Sync one chat (text-only), then load newly synced messages, check keywords, and send alerts to Saved Messages.
+    /// Sync one chat (text-only), then scan exactly the newly synced messages (by id, so
+    /// message dates don't matter) for keywords, mentions of `me_id` and replies to it, and
+    /// send alerts. Returns (messages synced, matches).
     async fn sync_and_notify_keywords(
         &self,
         chat_id: i64,
-        saved_messages_id: i64,
-        chat_title: Option<&str>,
-    ) -> Result<(), DomainError> {
-        let stats = self.sync_service.sync_chat(chat_id, 100, false).await?;
-
-        if stats.messages_synced == 0 {
-            return Ok(());
-        }
-
-        let new_messages = self
-            .repo
-            .get_messages(chat_id, stats.messages_synced as u32, 0)
+        me_id: i64,
+        alert_target: &mut Alerts,
+        cooldowns: &mut AlertCooldowns,
+        title: &str,
+        matcher: &KeywordMatcher,
+    ) -> Result<(u64, u64), DomainError> {
+        let stats = self
+            .sync_service
+            .sync_chat(chat_id, 100, &MediaFilter::none(), None)
             .await?;
 
-        let fallback = chat_id.to_string();
-        let title = chat_title.unwrap_or(&fallback);
+        let Some((first_id, last_id)) = stats.synced_ids else {
+            return Ok((0, 0));
+        };
 
-        for msg in &new_messages {
-            if let Some(keyword) = find_keyword(&msg.text) {
-                let alert = format!(
-                    "[ALERT] Keyword '{}' found in chat '{}': {}",
-                    keyword,
-                    title,
-                    truncate_message(&msg.text)
-                );
-                if let Err(e) = self.tg.send_message(saved_messages_id, &alert).await {
-                    warn!(chat_id, error = %e, "Failed to send alert to Saved Messages");
+        let now = Utc::now().timestamp();
+        let mut matches = 0;
+        let mut hits = Vec::new();
+        // Ascending ids: a combined alert quotes the earliest match.
+        let mut after = first_id.saturating_sub(1);
+        while after < last_id {
+            let page = self
+                .repo
+                .get_messages_after(chat_id, after, SCAN_PAGE)
+                .await?;
+            let Some(last) = page.last() else {
+                break;
+            };
+            after = last.id;
+            let replies = if matcher.watches(chat_id, WatchRuleKind::Reply)
+                || matcher.watches(chat_id, WatchRuleKind::Mention)
+            {
+                self.replies_to(chat_id, &page, me_id).await?
+            } else {
+                HashSet::new()
+            };
+            for msg in page.iter().filter(|m| m.id <= last_id) {
+                let mine = msg.from_user_id == Some(me_id);
+                let reply_to_me = !mine && replies.contains(&msg.id);
+                // Telegram also flags replies to me as mentions; those count as replies only.
+                let mentions_me = !mine && (msg.mentions(me_id) || (msg.mentioned && !reply_to_me));
+                let trigger = if reply_to_me && matcher.watches(chat_id, WatchRuleKind::Reply) {
+                    Some((WatchRuleKind::Reply, REPLY_LABEL))
+                } else if mentions_me && matcher.watches(chat_id, WatchRuleKind::Mention) {
+                    Some((WatchRuleKind::Mention, MENTION_LABEL))
                 } else {
-                    info!(chat_id, keyword, "Alert sent to Saved Messages");
+                    matcher
+                        .find(chat_id, &msg.text)
+                        .map(|keyword| (WatchRuleKind::Keyword, keyword))
+                };
+                let Some((kind, keyword)) = trigger else {
+                    continue;
+                };
+                matches += 1;
+                if cooldowns.is_cooling(chat_id, keyword, now) {
+                    cooldowns.suppress(chat_id, keyword);
+                    continue;
+                }
+                let hit = KeywordHit::new(kind, keyword, msg);
+                match self.alert_mode {
+                    AlertMode::PerMessage => {
+                        let text = hit.alert(title, cooldowns.suppressed(chat_id, keyword));
+                        if self
+                            .deliver_alert(alert_target, chat_id, title, &text)
+                            .await
+                        {
+                            cooldowns.mark_sent(chat_id, keyword, now);
+                        }
+                    }
+                    AlertMode::Digest => hits.push(hit),
                 }
             }
         }
 
-        Ok(())
+        if !hits.is_empty() {
+            let text = combined_alert(title, &hits, |keyword| {
+                cooldowns.suppressed(chat_id, keyword)
+            });
+            if self
+                .deliver_alert(alert_target, chat_id, title, &text)
+                .await
+            {
+                for hit in &hits {
+                    cooldowns.mark_sent(chat_id, &hit.keyword, now);
+                }
+            }
+        }
+
+        Ok((stats.messages_synced as u64, matches))
+    }
+
+    /// Ids of the messages in `page` that reply to an archived message sent by `me_id`.
+    async fn replies_to(
+        &self,
+        chat_id: i64,
+        page: &[Message],
+        me_id: i64,
+    ) -> Result<HashSet<i32>, DomainError> {
+        let reply_ids: Vec<i32> = page.iter().filter_map(|m| m.reply_to_msg_id).collect();
+        let targets = self.repo.get_messages_map(chat_id, &reply_ids).await?;
+        Ok(page
+            .iter()
+            .filter(|m| {
+                m.reply_to_msg_id
+                    .and_then(|id| targets.get(&id))
+                    .is_some_and(|target| target.from_user_id == Some(me_id))
+            })
+            .map(|m| m.id)
+            .collect())
+    }
+}
+
+/// Where alerts go during one watcher run: the Telegram alert chat, then the configured
+/// notifiers.
+struct Alerts {
+    notifiers: Vec<Arc<dyn NotifierPort>>,
+    /// Alerts delivered during the run.
+    sent: u64,
+}
+
+/// Trigger of mention alerts, as named in alerts and cooldowns.
+const MENTION_LABEL: &str = "mention";
+
+/// Trigger of reply alerts, as named in alerts and cooldowns.
+const REPLY_LABEL: &str = "reply to you";
+
+/// One message that matched a watch rule.
+#[derive(Debug, Clone)]
+struct KeywordHit {
+    kind: WatchRuleKind,
+    /// The keyword's pattern, or the trigger's label for mentions and replies.
+    keyword: String,
+    sender: Option<String>,
+    text: String,
+}
+
+impl KeywordHit {
+    fn new(kind: WatchRuleKind, keyword: &str, msg: &Message) -> Self {
+        Self {
+            kind,
+            keyword: keyword.to_string(),
+            sender: msg.sender_name.clone(),
+            text: truncate_message(&msg.text),
+        }
+    }
+
+    /// " from Ann: text"
+    fn quote(&self) -> String {
+        match &self.sender {
+            Some(name) => format!(" from {}: {}", name, self.text),
+            None => format!(": {}", self.text),
+        }
+    }
+
+    /// Alert for this message alone; `held_back` earlier matches of the keyword were only counted.
+    fn alert(&self, title: &str, held_back: u64) -> String {
+        let mut alert = match self.kind {
+            WatchRuleKind::Keyword => format!(
+                "[ALERT] Keyword '{}' found in chat '{}'{}",
+                self.keyword,
+                title,
+                self.quote()
+            ),
+            WatchRuleKind::Mention => {
+                format!("[ALERT] Mention in chat '{}'{}", title, self.quote())
+            }
+            WatchRuleKind::Reply => {
+                format!("[ALERT] Reply to you in chat '{}'{}", title, self.quote())
+            }
+        };
+        if held_back > 0 {
+            alert.push_str(&format!(" (+{} earlier during cooldown)", held_back));
+        }
+        alert
     }
 }
 
-/// Returns the first matching keyword (case-insensitive) in `text`, or None.
-fn find_keyword(text: &str) -> Option<&'static str> {
-    let lower = text.to_lowercase();
-    KEYWORDS
+/// One alert for all `hits` of a chat (oldest first): counts per keyword in order of first
+/// match, matches of each keyword held back by the cooldown, and the first message. A single
+/// hit reads like a per-message alert.
+fn combined_alert(title: &str, hits: &[KeywordHit], held_back: impl Fn(&str) -> u64) -> String {
+    let Some(first) = hits.first() else {
+        return String::new();
+    };
+    if hits.len() == 1 {
+        return first.alert(title, held_back(&first.keyword));
+    }
+    let mut counts: Vec<(&str, u64)> = Vec::new();
+    for hit in hits {
+        match counts.iter_mut().find(|(k, _)| *k == hit.keyword) {
+            Some((_, n)) => *n += 1,
+            None => counts.push((&hit.keyword, 1)),
+        }
+    }
+    let keywords = counts
         .iter()
-        .find(|k| lower.contains(&k.to_lowercase()))
-        .copied()
+        .map(|&(keyword, n)| match held_back(keyword) {
+            0 => format!("{}×{}", n, keyword),
+            earlier => format!("{}×{} (+{} earlier)", n, keyword, earlier),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "[ALERT] {} matches in '{}': {}, first{}",
+        hits.len(),
+        title,
+        keywords,
+        first.quote()
+    )
+}
+
+/// Alert cooldowns per (chat, keyword) (TG_SYNC_ALERT_COOLDOWN_SECS). Entries are stored in
+/// settings as JSON.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct AlertCooldowns {
+    /// Cooldown window in seconds; 0 = off (nothing is held back or recorded).
+    window_secs: i64,
+    entries: Vec<CooldownEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct CooldownEntry {
+    chat_id: i64,
+    keyword: String,
+    /// Unix timestamp of the last alert.
+    last_sent: i64,
+    /// Matches counted since the last alert.
+    suppressed: u64,
+}
+
+impl AlertCooldowns {
+    fn new(window_secs: u64) -> Self {
+        Self {
+            window_secs: i64::try_from(window_secs).unwrap_or(i64::MAX),
+            entries: Vec::new(),
+        }
+    }
+
+    fn entry(&self, chat_id: i64, keyword: &str) -> Option<&CooldownEntry> {
+        self.entries
+            .iter()
+            .find(|e| e.chat_id == chat_id && e.keyword == keyword)
+    }
+
+    fn entry_mut(&mut self, chat_id: i64, keyword: &str) -> &mut CooldownEntry {
+        let pos = match self
+            .entries
+            .iter()
+            .position(|e| e.chat_id == chat_id && e.keyword == keyword)
+        {
+            Some(pos) => pos,
+            None => {
+                self.entries.push(CooldownEntry {
+                    chat_id,
+                    keyword: keyword.to_string(),
+                    last_sent: 0,
+                    suppressed: 0,
+                });
+                self.entries.len() - 1
+            }
+        };
+        &mut self.entries[pos]
+    }
+
+    /// True while the last alert for `keyword` in `chat_id` is less than the window old.
+    fn is_cooling(&self, chat_id: i64, keyword: &str, now: i64) -> bool {
+        self.window_secs > 0
+            && self
+                .entry(chat_id, keyword)
+                .is_some_and(|e| now.saturating_sub(e.last_sent) < self.window_secs)
+    }
+
+    /// Count a match held back by the cooldown.
+    fn suppress(&mut self, chat_id: i64, keyword: &str) {
+        self.entry_mut(chat_id, keyword).suppressed += 1;
+    }
+
+    /// Matches held back since the last alert.
+    fn suppressed(&self, chat_id: i64, keyword: &str) -> u64 {
+        self.entry(chat_id, keyword).map_or(0, |e| e.suppressed)
+    }
+
+    /// Record an alert: starts a new window and clears the held-back count.
+    fn mark_sent(&mut self, chat_id: i64, keyword: &str, now: i64) {
+        if self.window_secs == 0 {
+            return;
+        }
+        let entry = self.entry_mut(chat_id, keyword);
+        entry.last_sent = now;
+        entry.suppressed = 0;
+    }
+
+    /// State stored in settings (none without a window). Unreadable state is logged and starts
+    /// empty.
+    async fn load(settings: &dyn SettingsPort, window_secs: u64) -> Self {
+        let mut cooldowns = Self::new(window_secs);
+        if window_secs == 0 {
+            return cooldowns;
+        }
+        match settings.get_setting(ALERT_COOLDOWN_SETTING).await {
+            Ok(Some(json)) => match serde_json::from_str(&json) {
+                Ok(entries) => cooldowns.entries = entries,
+                Err(e) => warn!(error = %e, "ignoring invalid stored alert cooldowns"),
+            },
+            Ok(None) => {}
+            Err(e) => warn!(error = %e, "failed to read alert cooldowns"),
+        }
+        cooldowns
+    }
+
+    /// Store the state in settings, minus entries that no longer matter. Failures are logged.
+    async fn save(&mut self, settings: &dyn SettingsPort) {
+        self.prune(Utc::now().timestamp());
+        let result = match serde_json::to_string(&self.entries) {
+            Ok(json) => {
+                settings
+                    .set_setting(ALERT_COOLDOWN_SETTING, Some(&json))
+                    .await
+            }
+            Err(e) => Err(DomainError::Repo(e.to_string())),
+        };
+        if let Err(e) = result {
+            warn!(error = %e, "failed to store alert cooldowns");
+        }
+    }
+
+    /// Drop entries whose window is over and that hold nothing back.
+    fn prune(&mut self, now: i64) {
+        let window = self.window_secs;
+        self.entries
+            .retain(|e| e.suppressed > 0 || now.saturating_sub(e.last_sent) < window);
+    }
+}
+
+/// Per-chat activity since the last daily digest.
+#[derive(Debug)]
+struct DailyDigest {
+    /// Unix timestamp the period started.
+    since: i64,
+    /// Chat title -> (new messages, keyword alerts).
+    chats: BTreeMap<String, (u64, u64)>,
+}
+
+impl DailyDigest {
+    fn new(since: i64) -> Self {
+        Self {
+            since,
+            chats: BTreeMap::new(),
+        }
+    }
+
+    fn record(&mut self, title: &str, messages: u64, alerts: u64) {
+        if messages == 0 {
+            return;
+        }
+        let entry = self.chats.entry(title.to_string()).or_default();
+        entry.0 += messages;
+        entry.1 += alerts;
+    }
+
+    /// Markdown digest for the period ending at `until`.
+    fn to_event(&self, until: i64) -> NotificationEvent {
+        let fmt = |ts: i64| {
+            DateTime::<Utc>::from_timestamp(ts, 0)
+                .map(|dt| dt.format("%Y-%m-%d %H:%M UTC").to_string())
+                .unwrap_or_else(|| ts.to_string())
+        };
+        let day = fmt(until).chars().take(10).collect::<String>();
+
+        let mut md = format!("# Daily Digest: {}\n\n", day);
+        md.push_str(&format!(
+            "**Period:** {} to {}\n\n",
+            fmt(self.since),
+            fmt(until)
+        ));
+        if self.chats.is_empty() {
+            md.push_str("No new messages in watched chats.\n\n");
+        } else {
+            for (title, (messages, alerts)) in &self.chats {
+                md.push_str(&format!(
+                    "- **{}**: {} new message(s), {} keyword alert(s)\n",
+                    title, messages, alerts
+                ));
+            }
+            md.push('\n');
+        }
+        md.push_str("---\n");
+        md.push_str("*Generated by tg-sync Watcher*\n");
+
+        NotificationEvent::Digest {
+            title: format!("tg-sync daily digest {}", day),
+            markdown: md,
+        }
+    }
+}
+
+/// Check a rule pattern before it is stored: non-empty, and a valid regex when `is_regex`.
+pub fn validate_watch_pattern(pattern: &str, is_regex: bool) -> Result<(), DomainError> {
+    compile(pattern, is_regex).map(|_| ())
+}
+
+enum Pattern {
+    /// Lowercased substring.
+    Substring(String),
+    Regex(Regex),
+}
+
+fn compile(pattern: &str, is_regex: bool) -> Result<Pattern, DomainError> {
+    if pattern.trim().is_empty() {
+        return Err(DomainError::WatchRule("empty pattern".into()));
+    }
+    if !is_regex {
+        return Ok(Pattern::Substring(pattern.to_lowercase()));
+    }
+    RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .build()
+        .map(Pattern::Regex)
+        .map_err(|e| DomainError::WatchRule(format!("invalid regex '{}': {}", pattern, e)))
+}
+
+/// Enabled watch rules, compiled once per cycle.
+pub struct KeywordMatcher {
+    rules: Vec<(i64, String, Pattern)>,
+    /// Mention and reply rules: chat (0 = global) and kind.
+    triggers: Vec<(i64, WatchRuleKind)>,
+}
+
+impl KeywordMatcher {
+    /// Compile the enabled rules. A stored rule that no longer compiles is logged and skipped.
+    pub fn new(rules: &[WatchRule]) -> Self {
+        let triggers = rules
+            .iter()
+            .filter(|r| r.enabled && r.kind != WatchRuleKind::Keyword)
+            .map(|r| (r.chat_id, r.kind))
+            .collect();
+        let rules = rules
+            .iter()
+            .filter(|r| r.enabled && r.kind == WatchRuleKind::Keyword)
+            .filter_map(|r| match compile(&r.pattern, r.is_regex) {
+                Ok(p) => Some((r.chat_id, r.pattern.clone(), p)),
+                Err(e) => {
+                    warn!(rule_id = r.id, error = %e, "skipping watch rule");
+                    None
+                }
+            })
+            .collect();
+        Self { rules, triggers }
+    }
+
+    /// Whether a mention or reply rule of `kind` is on for `chat_id` (globally or for it).
+    pub fn watches(&self, chat_id: i64, kind: WatchRuleKind) -> bool {
+        self.triggers
+            .iter()
+            .any(|&(rule_chat, k)| k == kind && (rule_chat == 0 || rule_chat == chat_id))
+    }
+
+    /// First rule (global or for `chat_id`) matching `text`, case-insensitive. Returns its pattern.
+    pub fn find(&self, chat_id: i64, text: &str) -> Option<&str> {
+        let lower = text.to_lowercase();
+        self.rules
+            .iter()
+            .filter(|(rule_chat, _, _)| *rule_chat == 0 || *rule_chat == chat_id)
+            .find(|(_, _, p)| match p {
+                Pattern::Substring(s) => lower.contains(s.as_str()),
+                Pattern::Regex(re) => re.is_match(text),
+            })
+            .map(|(_, pattern, _)| pattern.as_str())
+    }
 }
 
 /// Truncate message text for the alert to avoid overly long notifications.
@@ -152,3 +1361,588 @@ fn truncate_message(text: &str) -> String {
         format!("{}...", &t[..MAX])
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::persistence::sqlite_repo::SqliteRepo;
+    use crate::adapters::persistence::state_json::StateJson;
+    use crate::domain::{ChatSettings, ChatType, MediaReference};
+    use crate::ports::StatePort;
+    use crate::testing::fake_tg::text_message;
+    use std::path::Path;
+    use std::sync::Mutex;
+    use tokio::sync::mpsc;
+
+    fn message(id: i32, date: i64, text: &str) -> Message {
+        text_message(9, id, date, text)
+    }
+
+    /// Telegram with one chat history (served newest id first) that records sent alerts.
+    #[derive(Default)]
+    struct FakeTg {
+        history: Mutex<Vec<Message>>,
+        sent: Mutex<Vec<String>>,
+        dialogs: Mutex<Vec<Chat>>,
+    }
+
+    #[async_trait::async_trait]
+    impl TgGateway for FakeTg {
+        async fn get_dialogs(&self) -> Result<Vec<Chat>, DomainError> {
+            Ok(self.dialogs.lock().unwrap().clone())
+        }
+
+        async fn get_messages(
+            &self,
+            _chat_id: i64,
+            min_id: i32,
+            max_id: i32,
+            limit: i32,
+        ) -> Result<Vec<Message>, DomainError> {
+            let mut page: Vec<Message> = self
+                .history
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|m| m.id > min_id && (max_id == 0 || m.id < max_id))
+                .cloned()
+                .collect();
+            page.sort_by_key(|m| std::cmp::Reverse(m.id));
+            page.truncate(limit as usize);
+            Ok(page)
+        }
+
+        async fn download_media(
+            &self,
+            _media_ref: &MediaReference,
+            _dest_path: &Path,
+        ) -> Result<(), DomainError> {
+            Ok(())
+        }
+
+        async fn get_me_id(&self) -> Result<i64, DomainError> {
+            Ok(1)
+        }
+
+        async fn send_message(&self, _chat_id: i64, text: &str) -> Result<(), DomainError> {
+            self.sent.lock().unwrap().push(text.to_string());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_auto_backup_fills_cycle_sleep_and_is_recorded() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join("test_watcher_auto_backup");
+        let _ = std::fs::remove_dir_all(&dir);
+        let repo = Arc::new(SqliteRepo::connect(&dir).await.unwrap());
+        let tg = Arc::new(FakeTg::default());
+        tg.history
+            .lock()
+            .unwrap()
+            .extend([message(1, 1704067200, "a"), message(2, 1704067201, "b")]);
+        let chat = |id| Chat {
+            id,
+            title: format!("chat {}", id),
+            username: None,
+            kind: ChatType::Group,
+            approx_message_count: None,
+            is_forum: false,
+            last_message_date: None,
+        };
+        *tg.dialogs.lock().unwrap() = vec![chat(9), chat(10), chat(11)];
+        repo.update_blacklist(HashSet::from([10])).await.unwrap();
+        let mut off = ChatSettings::new(11);
+        off.sync_enabled = false;
+        repo.set_chat_settings(&off).await.unwrap();
+
+        let (media_tx, _media_rx) = mpsc::channel(4);
+        let sync = Arc::new(SyncService::new(
+            tg.clone(),
+            repo.clone(),
+            Arc::new(StateJson::new(dir.join("state.json"))),
+            media_tx,
+            repo.clone(),
+            repo.clone(),
+            0,
+            dir.join("reports"),
+            None,
+            ActivityFlag::new(),
+        ));
+        let auto = AutoBackup {
+            every: Duration::from_secs(3600),
+            scope: AutoBackupScope::AllChats,
+            media: MediaFilter::none(),
+        };
+        let watcher = WatcherService::new(
+            tg.clone(),
+            repo.clone(),
+            repo.clone(),
+            repo.clone(),
+            sync,
+            Duration::from_secs(600),
+            None,
+            Vec::new(),
+            None,
+            AlertMode::Digest,
+            0,
+            Some(auto.clone()),
+            Heartbeat::default(),
+            ActivityFlag::new(),
+        );
+        let (_stop_tx, stop) = watch::channel(false);
+        let mut run = None;
+        let mut day = HeartbeatDay::default();
+
+        // No time left in the cycle: the backup starts, but no chat is synced yet.
+        assert!(
+            !watcher
+                .auto_backup_until(&auto, &mut run, &mut day, &stop, Instant::now())
+                .await
+        );
+        let queued: Vec<i64> = run.as_ref().unwrap().queue.iter().map(|c| c.id).collect();
+        assert_eq!(queued, vec![9]);
+        assert!(repo.get_messages(9, 10, 0).await.unwrap().is_empty());
+
+        // The next cycle's sleep finishes it; the finish time is stored.
+        let later = Instant::now() + Duration::from_secs(60);
+        watcher
+            .auto_backup_until(&auto, &mut run, &mut day, &stop, later)
+            .await;
+        assert!(run.is_none());
+        assert_eq!(repo.get_messages(9, 10, 0).await.unwrap().len(), 2);
+        assert!(watcher.last_auto_backup().await.is_some());
+        assert_eq!(day.archived, 2);
+
+        // Not due again within the interval.
+        watcher
+            .auto_backup_until(&auto, &mut run, &mut day, &stop, later)
+            .await;
+        assert!(run.is_none());
+    }
+
+    /// Records pings.
+    #[derive(Default)]
+    struct FakeHeartbeat {
+        pings: Mutex<Vec<String>>,
+    }
+
+    #[async_trait::async_trait]
+    impl HeartbeatPort for FakeHeartbeat {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+
+        async fn ping(&self, summary: &str) -> Result<(), DomainError> {
+            self.pings.lock().unwrap().push(summary.to_string());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_pings_per_cycle_and_summary_once_a_day() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join("test_watcher_heartbeat");
+        let _ = std::fs::remove_dir_all(&dir);
+        let repo = Arc::new(SqliteRepo::connect(&dir).await.unwrap());
+        let tg = Arc::new(FakeTg::default());
+        let pinger = Arc::new(FakeHeartbeat::default());
+        let (media_tx, _media_rx) = mpsc::channel(4);
+        let sync = Arc::new(SyncService::new(
+            tg.clone(),
+            repo.clone(),
+            Arc::new(StateJson::new(dir.join("state.json"))),
+            media_tx,
+            repo.clone(),
+            repo.clone(),
+            0,
+            dir.join("reports"),
+            None,
+            ActivityFlag::new(),
+        ));
+        let watcher = WatcherService::new(
+            tg.clone(),
+            repo.clone(),
+            repo.clone(),
+            repo.clone(),
+            sync,
+            Duration::from_secs(600),
+            None,
+            Vec::new(),
+            None,
+            AlertMode::Digest,
+            0,
+            None,
+            Heartbeat {
+                pinger: Some(pinger.clone()),
+                // Always due: the summary goes out with the first cycle of a day.
+                daily_hour: Some(0),
+                utc_offset_secs: 0,
+            },
+            ActivityFlag::new(),
+        );
+        let today = watcher.heartbeat.local_now().0;
+
+        let mut day = HeartbeatDay::load(repo.as_ref()).await;
+        day.record(&today, 5);
+        watcher.report_alive(&mut day, 1, 3, true).await;
+        watcher.report_alive(&mut day, 1, 3, true).await;
+        // A cycle with a failed chat is not reported as alive.
+        watcher.report_alive(&mut day, 1, 3, false).await;
+        assert_eq!(pinger.pings.lock().unwrap().len(), 2);
+        assert_eq!(
+            *tg.sent.lock().unwrap(),
+            vec!["tg-sync watcher still alive: 3 chat(s) watched, 5 message(s) archived today"]
+        );
+
+        // After a restart the day is read back: no second summary today.
+        let mut day = HeartbeatDay::load(repo.as_ref()).await;
+        assert!(day.summary_sent);
+        assert_eq!(day.archived, 5);
+        watcher.report_alive(&mut day, 1, 3, true).await;
+        assert_eq!(tg.sent.lock().unwrap().len(), 1);
+        assert_eq!(pinger.pings.lock().unwrap().len(), 3);
+
+        // A new day starts from zero.
+        day.record("2000-01-01", 1);
+        assert_eq!((day.archived, day.summary_sent), (1, false));
+    }
+
+    #[tokio::test]
+    async fn test_alerts_scan_synced_messages_not_newest_dates() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join("test_watcher_synced_scan");
+        let _ = std::fs::remove_dir_all(&dir);
+        let repo = Arc::new(SqliteRepo::connect(&dir).await.unwrap());
+        let state = Arc::new(StateJson::new(dir.join("state.json")));
+        // Already archived, with the newest dates; one of them matches but was seen before.
+        let archived: Vec<_> = (1..=3)
+            .map(|id| message(id, 1704067200 + id as i64, "old error"))
+            .collect();
+        repo.save_messages(9, &archived).await.unwrap();
+        state.set_last_message_id(9, 3).await.unwrap();
+        // New messages carry older dates (e.g. imported history).
+        let tg = Arc::new(FakeTg::default());
+        tg.history.lock().unwrap().extend(archived);
+        tg.history.lock().unwrap().extend([
+            message(4, 1600000000, "deploy failed: error"),
+            message(5, 1600000001, "all good"),
+        ]);
+
+        let (media_tx, _media_rx) = mpsc::channel(4);
+        let sync = Arc::new(SyncService::new(
+            tg.clone(),
+            repo.clone(),
+            state,
+            media_tx,
+            repo.clone(),
+            repo.clone(),
+            0,
+            dir.join("reports"),
+            None,
+            ActivityFlag::new(),
+        ));
+        let watcher = WatcherService::new(
+            tg.clone(),
+            repo.clone(),
+            repo.clone(),
+            repo.clone(),
+            sync,
+            Duration::from_secs(600),
+            None,
+            Vec::new(),
+            None,
+            AlertMode::Digest,
+            0,
+            None,
+            Heartbeat::default(),
+            ActivityFlag::new(),
+        );
+        let matcher = KeywordMatcher::new(&[WatchRule {
+            id: 1,
+            chat_id: 0,
+            kind: WatchRuleKind::Keyword,
+            pattern: "error".to_string(),
+            is_regex: false,
+            enabled: true,
+        }]);
+
+        let result = watcher
+            .sync_and_notify_keywords(
+                9,
+                1,
+                &mut Alerts {
+                    notifiers: vec![Arc::new(TelegramNotifier::new(tg.clone(), 1))],
+                    sent: 0,
+                },
+                &mut AlertCooldowns::new(0),
+                "Ops",
+                &matcher,
+            )
+            .await
+            .unwrap();
+        assert_eq!(result, (2, 1));
+        assert_eq!(
+            *tg.sent.lock().unwrap(),
+            vec!["[ALERT] Keyword 'error' found in chat 'Ops': deploy failed: error"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mentions_and_replies_to_me_alert_with_their_trigger() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join("test_watcher_mentions");
+        let _ = std::fs::remove_dir_all(&dir);
+        let repo = Arc::new(SqliteRepo::connect(&dir).await.unwrap());
+        let state = Arc::new(StateJson::new(dir.join("state.json")));
+        let from = |id: i32, user: i64, reply_to: Option<i32>, text: &str| Message {
+            from_user_id: Some(user),
+            reply_to_msg_id: reply_to,
+            ..message(id, 1704067200 + i64::from(id), text)
+        };
+        // Mine (user 1) and someone else's, archived before.
+        let archived = vec![
+            from(1, 1, None, "can someone review?"),
+            from(2, 5, None, "lunch?"),
+        ];
+        repo.save_messages(9, &archived).await.unwrap();
+        state.set_last_message_id(9, 2).await.unwrap();
+        let tg = Arc::new(FakeTg::default());
+        tg.history.lock().unwrap().extend(archived);
+        tg.history.lock().unwrap().extend([
+            from(3, 5, Some(1), "looks good"),
+            Message {
+                mentioned: true,
+                ..from(4, 5, None, "@me check this")
+            },
+            Message {
+                mentioned_user_ids: vec![1],
+                ..from(5, 5, Some(2), "ping")
+            },
+            // My own reply to myself.
+            from(6, 1, Some(1), "bump"),
+            // Telegram flags a reply to me as a mention too; it alerts as a reply.
+            Message {
+                mentioned: true,
+                ..from(7, 5, Some(1), "error again")
+            },
+            from(8, 5, Some(2), "error"),
+        ]);
+
+        let (media_tx, _media_rx) = mpsc::channel(4);
+        let sync = Arc::new(SyncService::new(
+            tg.clone(),
+            repo.clone(),
+            state,
+            media_tx,
+            repo.clone(),
+            repo.clone(),
+            0,
+            dir.join("reports"),
+            None,
+            ActivityFlag::new(),
+        ));
+        let watcher = WatcherService::new(
+            tg.clone(),
+            repo.clone(),
+            repo.clone(),
+            repo.clone(),
+            sync,
+            Duration::from_secs(600),
+            None,
+            Vec::new(),
+            None,
+            AlertMode::PerMessage,
+            0,
+            None,
+            Heartbeat::default(),
+            ActivityFlag::new(),
+        );
+        watcher
+            .set_trigger(0, WatchRuleKind::Mention, true)
+            .await
+            .unwrap();
+        watcher
+            .set_trigger(0, WatchRuleKind::Reply, false)
+            .await
+            .unwrap();
+        watcher
+            .set_trigger(9, WatchRuleKind::Reply, true)
+            .await
+            .unwrap();
+        assert!(
+            watcher
+                .set_trigger(9, WatchRuleKind::Keyword, true)
+                .await
+                .is_err()
+        );
+        let matcher = KeywordMatcher::new(&watcher.list_rules().await.unwrap());
+        assert!(matcher.watches(9, WatchRuleKind::Reply));
+        assert!(!matcher.watches(8, WatchRuleKind::Reply));
+        assert!(matcher.watches(8, WatchRuleKind::Mention));
+
+        let result = watcher
+            .sync_and_notify_keywords(
+                9,
+                1,
+                &mut Alerts {
+                    notifiers: vec![Arc::new(TelegramNotifier::new(tg.clone(), 1))],
+                    sent: 0,
+                },
+                &mut AlertCooldowns::new(0),
+                "Ops",
+                &matcher,
+            )
+            .await
+            .unwrap();
+        assert_eq!(result, (6, 5));
+        assert_eq!(
+            *tg.sent.lock().unwrap(),
+            vec![
+                "[ALERT] Reply to you in chat 'Ops': looks good",
+                "[ALERT] Mention in chat 'Ops': @me check this",
+                "[ALERT] Mention in chat 'Ops': ping",
+                "[ALERT] Reply to you in chat 'Ops': error again",
+                "[ALERT] Keyword 'Error' found in chat 'Ops': error",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keyword_matcher_rules() {
+        let rule =
+            |id: i64, chat_id: i64, pattern: &str, is_regex: bool, enabled: bool| WatchRule {
+                id,
+                chat_id,
+                kind: WatchRuleKind::Keyword,
+                pattern: pattern.to_string(),
+                is_regex,
+                enabled,
+            };
+        let matcher = KeywordMatcher::new(&[
+            rule(1, 0, "Срочно", false, true),
+            rule(2, 42, r"\binv-\d{4}\b", true, true),
+            rule(3, 0, "deploy", false, false),
+            // Stored before validation existed: skipped, not fatal.
+            rule(4, 0, "([", true, true),
+        ]);
+        assert_eq!(matcher.find(7, "это СРОЧНО!"), Some("Срочно"));
+        assert_eq!(
+            matcher.find(42, "see INV-2024 please"),
+            Some(r"\binv-\d{4}\b")
+        );
+        assert_eq!(matcher.find(7, "see INV-2024 please"), None);
+        assert_eq!(matcher.find(7, "deploy now"), None);
+
+        assert!(validate_watch_pattern("([", true).is_err());
+        assert!(validate_watch_pattern("  ", false).is_err());
+        assert!(validate_watch_pattern("([", false).is_ok());
+    }
+
+    fn hit(keyword: &str, sender: Option<&str>, text: &str) -> KeywordHit {
+        KeywordHit {
+            kind: WatchRuleKind::Keyword,
+            keyword: keyword.to_string(),
+            sender: sender.map(str::to_string),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_combined_alert_counts_keywords() {
+        let hits = [
+            hit("error", Some("Ann"), "error in prod"),
+            hit("production", None, "production down"),
+            hit("error", None, "another error"),
+        ];
+        assert_eq!(
+            combined_alert("Ops", &hits, |_| 0),
+            "[ALERT] 3 matches in 'Ops': 2×error, 1×production, first from Ann: error in prod"
+        );
+        assert_eq!(
+            combined_alert("Ops", &hits, |k| if k == "error" { 4 } else { 0 }),
+            "[ALERT] 3 matches in 'Ops': 2×error (+4 earlier), 1×production, first from Ann: error in prod"
+        );
+        assert_eq!(
+            combined_alert("Ops", &hits[1..2], |_| 2),
+            "[ALERT] Keyword 'production' found in chat 'Ops': production down (+2 earlier during cooldown)"
+        );
+        assert_eq!(
+            hit("error", Some("Ann"), "boom").alert("Ops", 0),
+            "[ALERT] Keyword 'error' found in chat 'Ops' from Ann: boom"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_alert_cooldowns_hold_back_and_persist() {
+        let mut cooldowns = AlertCooldowns::new(600);
+        assert!(!cooldowns.is_cooling(1, "error", 1000));
+        cooldowns.mark_sent(1, "error", 1000);
+        assert!(cooldowns.is_cooling(1, "error", 1599));
+        assert!(!cooldowns.is_cooling(2, "error", 1599));
+        assert!(!cooldowns.is_cooling(1, "deploy", 1599));
+        cooldowns.suppress(1, "error");
+        cooldowns.suppress(1, "error");
+        assert_eq!(cooldowns.suppressed(1, "error"), 2);
+        assert!(!cooldowns.is_cooling(1, "error", 1600));
+        cooldowns.mark_sent(1, "error", 1600);
+        assert_eq!(cooldowns.suppressed(1, "error"), 0);
+        cooldowns.prune(2200);
+        assert!(cooldowns.entries.is_empty());
+
+        // No window: nothing is ever held back or recorded.
+        let mut off = AlertCooldowns::new(0);
+        off.mark_sent(1, "error", 1000);
+        assert!(!off.is_cooling(1, "error", 1000));
+        assert!(off.entries.is_empty());
+
+        // The state survives a restart through the settings table.
+        let base = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join("test_watcher_cooldowns");
+        let _ = std::fs::remove_dir_all(&base);
+        let repo = crate::adapters::persistence::sqlite_repo::SqliteRepo::connect(&base)
+            .await
+            .unwrap();
+        let now = Utc::now().timestamp();
+        let mut cooldowns = AlertCooldowns::load(&repo, 600).await;
+        cooldowns.mark_sent(1, "error", now);
+        cooldowns.suppress(1, "error");
+        cooldowns.save(&repo).await;
+        let restored = AlertCooldowns::load(&repo, 600).await;
+        assert!(restored.is_cooling(1, "error", now + 1));
+        assert_eq!(restored.suppressed(1, "error"), 1);
+        assert!(AlertCooldowns::load(&repo, 0).await.entries.is_empty());
+    }
+
+    #[test]
+    fn test_daily_digest_markdown() {
+        let mut digest = DailyDigest::new(1704067200);
+        digest.record("Team", 3, 1);
+        digest.record("Empty", 0, 0);
+        digest.record("Team", 2, 0);
+        digest.record("Alpha", 1, 0);
+
+        let event = digest.to_event(1704067200 + DIGEST_INTERVAL_SECS);
+        assert_eq!(event.subject(), "tg-sync daily digest 2024-01-02");
+        assert_eq!(
+            event.markdown(),
+            "# Daily Digest: 2024-01-02\n\n\
+             **Period:** 2024-01-01 00:00 UTC to 2024-01-02 00:00 UTC\n\n\
+             - **Alpha**: 1 new message(s), 0 keyword alert(s)\n\
+             - **Team**: 5 new message(s), 1 keyword alert(s)\n\n\
+             ---\n*Generated by tg-sync Watcher*\n"
+        );
+
+        let quiet = DailyDigest::new(0).to_event(DIGEST_INTERVAL_SECS);
+        assert!(
+            quiet
+                .markdown()
+                .contains("No new messages in watched chats.")
+        );
+    }
+}
@@ -0,0 +1,205 @@
+//! `AnalysisService::analyze_chat` against a SQLite archive and a scripted mock model:
+//! failures, resumed Map phases, malformed replies and custom results.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tg_sync::adapters::ai::{ChunkBudget, MockAiAdapter, MockCall};
+use tg_sync::adapters::persistence::sqlite_repo::SqliteRepo;
+use tg_sync::domain::{ActionItem, AnalysisResult, Granularity, Message, WeekGroup};
+use tg_sync::ports::{AnalysisLogPort, RepoPort};
+use tg_sync::shared::pricing::PriceTable;
+use tg_sync::testing::fake_tg::text_message;
+use tg_sync::usecases::AnalysisService;
+
+const CHAT: i64 = 7;
+
+/// Monday 2024-01-01 00:00 UTC.
+const MONDAY: i64 = 1704067200;
+
+fn base_dir(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("target")
+        .join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn message(id: i32, text: &str) -> Message {
+    text_message(CHAT, id, MONDAY + i64::from(id) * 60, text)
+}
+
+/// A repo holding `texts` as one week of [`CHAT`], and an analysis service over it whose
+/// chunks take `max_tokens` each.
+async fn setup(
+    name: &str,
+    ai: Arc<MockAiAdapter>,
+    texts: &[&str],
+    max_tokens: usize,
+) -> (Arc<SqliteRepo>, AnalysisService) {
+    let dir = base_dir(name);
+    let repo = Arc::new(SqliteRepo::connect(dir.join("db")).await.unwrap());
+    let messages: Vec<Message> = texts
+        .iter()
+        .enumerate()
+        .map(|(i, text)| message(i as i32 + 1, text))
+        .collect();
+    repo.save_messages(CHAT, &messages).await.unwrap();
+    let service = AnalysisService::new(
+        ai,
+        repo.clone(),
+        repo.clone(),
+        repo.clone(),
+        dir.join("reports"),
+        None,
+        0,
+        None,
+        Vec::new(),
+        1,
+        ChunkBudget::new(max_tokens, "unknown"),
+        PriceTable::default(),
+        None,
+        false,
+        None,
+    );
+    (repo, service)
+}
+
+/// Messages long enough that each fills a small chunk on its own.
+fn long_texts() -> Vec<String> {
+    ["alpha", "bravo", "charlie"]
+        .iter()
+        .map(|word| format!("{} {}", word, "lorem ipsum dolor sit amet ".repeat(8)))
+        .collect()
+}
+
+fn summaries(calls: &[MockCall]) -> Vec<&str> {
+    calls
+        .iter()
+        .filter_map(|call| match call {
+            MockCall::Summarize { context } => Some(context.as_str()),
+            MockCall::Analyze { .. } => None,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn failed_request_leaves_the_period_pending() {
+    let ai = Arc::new(MockAiAdapter::with_delay(0).fail_first_n(1).record_calls());
+    let (_repo, service) = setup(
+        "test_scenario_fail_first",
+        ai.clone(),
+        &["hi", "hello"],
+        12_000,
+    )
+    .await;
+
+    let err = service.analyze_chat(CHAT, false).await.unwrap_err();
+    assert!(err.to_string().contains("simulated failure"), "{}", err);
+    assert_eq!(service.pending_weeks(CHAT).await.unwrap().len(), 1);
+
+    let reports = service.analyze_chat(CHAT, false).await.unwrap();
+    assert_eq!(reports.len(), 1);
+    assert!(service.pending_weeks(CHAT).await.unwrap().is_empty());
+    // One small period: no Map phase, the same context sent twice.
+    let calls = ai.calls();
+    assert_eq!(calls.len(), 2);
+    assert!(summaries(&calls).is_empty());
+    assert_eq!(calls[0], calls[1]);
+    match &calls[1] {
+        MockCall::Analyze {
+            chat_id, context, ..
+        } => {
+            assert_eq!(*chat_id, CHAT);
+            assert!(context.contains("hello"), "{}", context);
+        }
+        other => panic!("expected an analysis request, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn failed_chunk_is_summarized_again_on_the_next_run() {
+    let ai = Arc::new(MockAiAdapter::with_delay(0).fail_on_chunk(1).record_calls());
+    let texts = long_texts();
+    let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+    let (_repo, service) = setup("test_scenario_fail_chunk", ai.clone(), &texts, 80).await;
+
+    assert!(service.analyze_chat(CHAT, false).await.is_err());
+    let first_run = ai.calls();
+    let first_summaries = summaries(&first_run);
+    assert_eq!(first_summaries.len(), 2);
+    assert!(first_summaries[0].contains("alpha"));
+    assert!(first_summaries[1].contains("bravo"));
+    assert_eq!(first_run.len(), 2, "no analysis after a failed chunk");
+
+    let reports = service.analyze_chat(CHAT, false).await.unwrap();
+    assert_eq!(reports.len(), 1);
+    let calls = ai.calls();
+    // The stored summary of the first chunk is reused.
+    let second_run = summaries(&calls[first_run.len()..]);
+    assert_eq!(second_run.len(), 2);
+    assert!(second_run[0].contains("bravo"));
+    assert!(second_run[1].contains("charlie"));
+    match calls.last().unwrap() {
+        MockCall::Analyze { context, .. } => {
+            assert_eq!(context.matches("[MOCK] Intermediate summary").count(), 3);
+        }
+        other => panic!("expected the reduce request last, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn malformed_reply_saves_nothing() {
+    let ai = Arc::new(MockAiAdapter::with_delay(0).return_malformed_json());
+    let (repo, service) = setup("test_scenario_malformed", ai, &["hi"], 12_000).await;
+
+    let err = service.analyze_chat(CHAT, false).await.unwrap_err();
+    assert!(
+        err.to_string().contains("Failed to parse LLM JSON"),
+        "{}",
+        err
+    );
+    let week = &service.pending_weeks(CHAT).await.unwrap()[0];
+    assert!(
+        repo.get_analysis(CHAT, Granularity::Week, week)
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[tokio::test]
+async fn scripted_result_is_stored_and_reported() {
+    let ai = Arc::new(MockAiAdapter::with_delay(0).respond_with(AnalysisResult {
+        week_group: WeekGroup::new("ignored"),
+        granularity: Granularity::Week,
+        chat_id: 0,
+        summary: "Release planning".to_string(),
+        key_topics: vec!["v2".to_string()],
+        action_items: vec![ActionItem {
+            description: "Tag the v2 release".to_string(),
+            owner: Some("Ann".to_string()),
+            deadline: Some("Friday".to_string()),
+            priority: Some("high".to_string()),
+        }],
+        analyzed_at: MONDAY,
+    }));
+    let (_repo, service) = setup("test_scenario_respond_with", ai, &["ship v2"], 12_000).await;
+
+    let reports = service.analyze_chat(CHAT, false).await.unwrap();
+    let report = &reports[0];
+    assert_eq!(report.result.chat_id, CHAT);
+    assert_ne!(report.result.week_group, WeekGroup::new("ignored"));
+    assert_eq!(report.result.summary, "Release planning");
+
+    let period = &service.analyzed_periods(CHAT).await.unwrap()[0];
+    assert_eq!(period.action_items, 1);
+    let stored = service
+        .stored_analysis(CHAT, period)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.action_items[0].description, "Tag the v2 release");
+    let markdown = std::fs::read_to_string(&report.path).unwrap();
+    assert!(markdown.contains("Release planning"));
+    assert!(markdown.contains("Tag the v2 release"));
+}
@@ -0,0 +1,500 @@
+//! `SyncService::sync_chat` and the watcher's keyword alerts against the in-memory Telegram:
+//! backfill, incremental passes, resumes after a FloodWait and alerts for new messages.
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tg_sync::adapters::persistence::sqlite_repo::SqliteRepo;
+use tg_sync::adapters::persistence::state_json::StateJson;
+use tg_sync::domain::{
+    AlertMode, Chat, ChatType, DomainError, MediaFile, MediaFilter, MediaReference, MediaStatus,
+    MediaType, Message,
+};
+use tg_sync::ports::{MediaIndexPort, RepoPort, StatePort};
+use tg_sync::shared::activity_flag::ActivityFlag;
+use tg_sync::testing::fake_tg::{FakeTgGateway, text_message};
+use tg_sync::usecases::watcher_service::Heartbeat;
+use tg_sync::usecases::{DaemonController, SyncService, WatcherService};
+use tokio::sync::mpsc;
+
+const CHAT: i64 = 9;
+const ME: i64 = 1;
+
+/// 2024-01-01 00:00 UTC.
+const DAY: i64 = 1704067200;
+
+struct Setup {
+    tg: Arc<FakeTgGateway>,
+    repo: Arc<SqliteRepo>,
+    state: Arc<StateJson>,
+    sync: Arc<SyncService>,
+    media_rx: mpsc::Receiver<MediaReference>,
+}
+
+async fn setup(name: &str) -> Setup {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("target")
+        .join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    let tg = Arc::new(FakeTgGateway::new(ME));
+    let repo = Arc::new(SqliteRepo::connect(&dir).await.unwrap());
+    let state = Arc::new(StateJson::new(dir.join("state.json")));
+    let (media_tx, media_rx) = mpsc::channel(64);
+    let sync = Arc::new(
+        SyncService::new(
+            tg.clone(),
+            repo.clone(),
+            state.clone(),
+            media_tx,
+            repo.clone(),
+            repo.clone(),
+            0,
+            dir.join("reports"),
+            None,
+            ActivityFlag::new(),
+        )
+        .with_media_index(repo.clone(), dir.join("media")),
+    );
+    Setup {
+        tg,
+        repo,
+        state,
+        sync,
+        media_rx,
+    }
+}
+
+fn messages(ids: impl IntoIterator<Item = i32>) -> Vec<Message> {
+    ids.into_iter()
+        .map(|id| {
+            text_message(
+                CHAT,
+                id,
+                DAY + i64::from(id) * 60,
+                &format!("message {}", id),
+            )
+        })
+        .collect()
+}
+
+async fn stored_ids(repo: &SqliteRepo) -> Vec<i32> {
+    let mut ids: Vec<i32> = repo
+        .get_messages(CHAT, 1000, 0)
+        .await
+        .unwrap()
+        .iter()
+        .map(|m| m.id)
+        .collect();
+    ids.sort_unstable();
+    ids
+}
+
+fn max_ids(tg: &FakeTgGateway) -> Vec<i32> {
+    tg.history_requests().iter().map(|r| r.max_id).collect()
+}
+
+#[tokio::test]
+async fn initial_backfill_pages_down_to_the_first_message() {
+    let mut s = setup("test_scenario_backfill").await;
+    let mut history = messages(1..=25);
+    history[4].media = Some(MediaReference {
+        message_id: 5,
+        chat_id: CHAT,
+        media_type: MediaType::Photo,
+        opaque_ref: "photo-5".to_string(),
+        size_bytes: Some(1024),
+        filename: None,
+        mime_type: None,
+    });
+    s.tg.post(history);
+
+    let stats = s
+        .sync
+        .sync_chat(CHAT, 10, &MediaFilter::photos_only(), None)
+        .await
+        .unwrap();
+
+    assert_eq!(stats.messages_synced, 25);
+    assert_eq!(stats.synced_ids, Some((1, 25)));
+    assert_eq!(stats.media_queued, 1);
+    assert_eq!(s.media_rx.try_recv().unwrap().opaque_ref, "photo-5");
+    assert_eq!(stored_ids(&s.repo).await, (1..=25).collect::<Vec<_>>());
+    // Pages below 16 and 6, then one below the first message that comes back empty.
+    assert_eq!(max_ids(&s.tg), vec![0, 16, 6, 1]);
+    assert_eq!(s.state.get_last_message_id(CHAT).await.unwrap(), 25);
+    assert_eq!(s.state.get_backfill_low_id(CHAT).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn media_backfill_queues_only_what_was_never_downloaded() {
+    let mut s = setup("test_scenario_media_backfill").await;
+    let mut history = messages(1..=4);
+    for (id, media_type) in [
+        (2, MediaType::Photo),
+        (3, MediaType::Video),
+        (4, MediaType::Photo),
+    ] {
+        history[id as usize - 1].media = Some(MediaReference {
+            message_id: id,
+            chat_id: CHAT,
+            media_type,
+            opaque_ref: format!("media-{}", id),
+            size_bytes: Some(1024),
+            filename: None,
+            mime_type: None,
+        });
+    }
+    s.tg.post(history);
+    s.sync
+        .sync_chat(CHAT, 10, &MediaFilter::none(), None)
+        .await
+        .unwrap();
+    assert!(s.media_rx.try_recv().is_err());
+
+    // Photo 2 was downloaded since.
+    let media_dir =
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("target/test_scenario_media_backfill/media");
+    std::fs::create_dir_all(media_dir.join("9")).unwrap();
+    std::fs::write(media_dir.join("9/2.jpg"), b"jpg").unwrap();
+    s.repo
+        .upsert_media_file(&MediaFile {
+            chat_id: CHAT,
+            message_id: 2,
+            media_type: MediaType::Photo,
+            rel_path: "9/2.jpg".to_string(),
+            size_bytes: Some(3),
+            expected_size: None,
+            sha256: None,
+            status: MediaStatus::Done,
+            message_date: None,
+            remote_key: None,
+        })
+        .await
+        .unwrap();
+
+    let report = s
+        .sync
+        .backfill_media(CHAT, &MediaFilter::photos_only())
+        .await
+        .unwrap();
+    assert_eq!(
+        (
+            report.with_media,
+            report.queued,
+            report.present,
+            report.pending,
+            report.filtered
+        ),
+        (3, 1, 1, 0, 1)
+    );
+    assert_eq!(s.media_rx.try_recv().unwrap().opaque_ref, "media-4");
+    assert_eq!(s.tg.history_requests().len(), 2, "no history fetched again");
+
+    // Queued but not downloaded yet: left to the queue; a lost file is queued again.
+    std::fs::remove_file(media_dir.join("9/2.jpg")).unwrap();
+    let report = s
+        .sync
+        .backfill_media(CHAT, &MediaFilter::photos_only())
+        .await
+        .unwrap();
+    assert_eq!((report.queued, report.present, report.pending), (1, 0, 1));
+    assert_eq!(s.media_rx.try_recv().unwrap().opaque_ref, "media-2");
+}
+
+#[tokio::test]
+async fn incremental_sync_stops_at_the_checkpoint_though_min_id_is_ignored() {
+    let s = setup("test_scenario_incremental").await;
+    s.tg.post(messages(1..=25));
+    s.sync
+        .sync_chat(CHAT, 10, &MediaFilter::none(), None)
+        .await
+        .unwrap();
+    let before = s.tg.history_requests().len();
+
+    s.tg.post(messages(26..=30));
+    let stats = s
+        .sync
+        .sync_chat(CHAT, 3, &MediaFilter::none(), None)
+        .await
+        .unwrap();
+
+    assert_eq!(stats.messages_synced, 5);
+    assert_eq!(stats.synced_ids, Some((26, 30)));
+    // The second page reaches below the checkpoint: it is cut there and paging stops.
+    let requests = &s.tg.history_requests()[before..];
+    assert_eq!(
+        requests
+            .iter()
+            .map(|r| (r.min_id, r.max_id))
+            .collect::<Vec<_>>(),
+        vec![(25, 0), (25, 28)]
+    );
+    assert_eq!(stored_ids(&s.repo).await, (1..=30).collect::<Vec<_>>());
+    assert_eq!(s.state.get_last_message_id(CHAT).await.unwrap(), 30);
+
+    // Nothing new: one request, nothing saved.
+    let before = s.tg.history_requests().len();
+    let stats = s
+        .sync
+        .sync_chat(CHAT, 10, &MediaFilter::none(), None)
+        .await
+        .unwrap();
+    assert_eq!(stats.messages_synced, 0);
+    assert_eq!(s.tg.history_requests().len(), before + 1);
+    assert_eq!(s.state.get_last_message_id(CHAT).await.unwrap(), 30);
+}
+
+#[tokio::test]
+async fn flood_wait_interrupts_backfill_and_the_next_sync_resumes_it() {
+    let s = setup("test_scenario_flood_backfill").await;
+    s.tg.post(messages(1..=25));
+    s.tg.flood_wait_at(2, 120);
+
+    let err = s
+        .sync
+        .sync_chat(CHAT, 10, &MediaFilter::none(), None)
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(err, DomainError::FloodWait { seconds: 120 }),
+        "{}",
+        err
+    );
+    // The first page is kept, with how far down the backfill got.
+    assert_eq!(stored_ids(&s.repo).await, (16..=25).collect::<Vec<_>>());
+    assert_eq!(s.state.get_last_message_id(CHAT).await.unwrap(), 25);
+    assert_eq!(s.state.get_backfill_low_id(CHAT).await.unwrap(), 16);
+
+    s.tg.post(messages(26..=27));
+    let before = s.tg.history_requests().len();
+    let stats = s
+        .sync
+        .sync_chat(CHAT, 10, &MediaFilter::none(), None)
+        .await
+        .unwrap();
+
+    assert_eq!(stats.messages_synced, 17);
+    // Resumed below the mark first, then the usual pass down to the checkpoint.
+    assert_eq!(max_ids(&s.tg)[before..], [16, 6, 0, 26]);
+    assert_eq!(stored_ids(&s.repo).await, (1..=27).collect::<Vec<_>>());
+    assert_eq!(s.state.get_backfill_low_id(CHAT).await.unwrap(), 0);
+    assert_eq!(s.state.get_last_message_id(CHAT).await.unwrap(), 27);
+}
+
+#[tokio::test]
+async fn flood_wait_in_incremental_sync_keeps_the_checkpoint() {
+    let s = setup("test_scenario_flood_incremental").await;
+    s.tg.post(messages(1..=5));
+    s.sync
+        .sync_chat(CHAT, 10, &MediaFilter::none(), None)
+        .await
+        .unwrap();
+    let requests = s.tg.history_requests().len();
+
+    s.tg.post(messages(6..=12));
+    s.tg.flood_wait_at(requests + 2, 300);
+    let err = s
+        .sync
+        .sync_chat(CHAT, 4, &MediaFilter::none(), None)
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(err, DomainError::FloodWait { seconds: 300 }),
+        "{}",
+        err
+    );
+    // The newest page is stored, but the checkpoint only moves once paging reached it.
+    assert_eq!(
+        stored_ids(&s.repo).await,
+        (1..=12)
+            .filter(|id| !(6..=8).contains(id))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(s.state.get_last_message_id(CHAT).await.unwrap(), 5);
+
+    let stats = s
+        .sync
+        .sync_chat(CHAT, 4, &MediaFilter::none(), None)
+        .await
+        .unwrap();
+    assert_eq!(stats.synced_ids, Some((6, 12)));
+    assert_eq!(stored_ids(&s.repo).await, (1..=12).collect::<Vec<_>>());
+    assert_eq!(s.state.get_last_message_id(CHAT).await.unwrap(), 12);
+}
+
+fn ops_chat() -> Chat {
+    Chat {
+        id: CHAT,
+        title: "Ops".to_string(),
+        username: None,
+        kind: ChatType::Group,
+        approx_message_count: None,
+        is_forum: false,
+        last_message_date: None,
+    }
+}
+
+/// A watcher over [`CHAT`] alerting on "deploy failed", to `alert_chat` when set.
+async fn watcher(s: &Setup, alert_chat: Option<i64>) -> Arc<WatcherService> {
+    s.repo.update_targets(HashSet::from([CHAT])).await.unwrap();
+    let watcher = Arc::new(WatcherService::new(
+        s.tg.clone(),
+        s.repo.clone(),
+        s.repo.clone(),
+        s.repo.clone(),
+        s.sync.clone(),
+        // Far longer than the test: each run does one cycle, then waits to be stopped.
+        Duration::from_secs(3600),
+        None,
+        Vec::new(),
+        alert_chat,
+        AlertMode::PerMessage,
+        0,
+        None,
+        Heartbeat::default(),
+        ActivityFlag::new(),
+    ));
+    watcher.add_rule(0, "deploy failed", false).await.unwrap();
+    watcher
+}
+
+/// One watcher cycle: start, wait for the cycle to be stored, stop.
+async fn cycle(watcher: &Arc<WatcherService>, daemon: &DaemonController) {
+    let cycles = watcher
+        .status()
+        .await
+        .unwrap()
+        .and_then(|s| s.last_cycle_at);
+    daemon.start().await.unwrap();
+    for _ in 0..500 {
+        let status = watcher.status().await.unwrap().unwrap_or_default();
+        if status.last_cycle_at.is_some() && status.last_cycle_at != cycles {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    daemon.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn watcher_alerts_on_new_messages_matching_a_rule() {
+    let s = setup("test_scenario_watcher_keywords").await;
+    s.tg.add_dialog(ops_chat());
+    s.tg.post(messages(1..=3));
+    let watcher = watcher(&s, None).await;
+    let daemon = DaemonController::new(watcher.clone());
+
+    cycle(&watcher, &daemon).await;
+    assert!(s.tg.sent_messages().is_empty());
+    assert_eq!(s.state.get_last_message_id(CHAT).await.unwrap(), 3);
+
+    s.tg.post([
+        text_message(CHAT, 4, DAY + 3600, "Deploy failed on prod"),
+        text_message(CHAT, 5, DAY + 3660, "all good"),
+    ]);
+    // Cycle timestamps have one-second resolution.
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    cycle(&watcher, &daemon).await;
+
+    assert_eq!(
+        s.tg.sent_messages(),
+        vec![(
+            ME,
+            "[ALERT] Keyword 'deploy failed' found in chat 'Ops': Deploy failed on prod"
+                .to_string()
+        )]
+    );
+    assert_eq!(stored_ids(&s.repo).await, vec![1, 2, 3, 4, 5]);
+}
+
+#[tokio::test]
+async fn bot_watcher_needs_an_alert_chat_and_alerts_there() {
+    const ALERTS: i64 = -1_000_000_000_042;
+    let mut s = setup("test_scenario_watcher_bot").await;
+    s.tg = Arc::new(FakeTgGateway::new(ME).as_bot());
+    s.sync = Arc::new(SyncService::new(
+        s.tg.clone(),
+        s.repo.clone(),
+        s.state.clone(),
+        mpsc::channel(8).0,
+        s.repo.clone(),
+        s.repo.clone(),
+        0,
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("target/test_scenario_watcher_bot/reports"),
+        None,
+        ActivityFlag::new(),
+    ));
+    s.tg.add_dialog(ops_chat());
+    s.tg.post(messages(1..=3));
+
+    // No Saved Messages to fall back to: the watcher refuses to start.
+    let (_stop, stop_rx) = tokio::sync::watch::channel(false);
+    let err = watcher(&s, None)
+        .await
+        .run_loop(stop_rx.clone())
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("alert chat"), "{}", err);
+    let err = watcher(&s, Some(ALERTS))
+        .await
+        .run_loop(stop_rx)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("not found"), "{}", err);
+
+    s.tg.add_dialog(Chat {
+        id: ALERTS,
+        title: "Alerts".to_string(),
+        username: Some("ops_alerts".to_string()),
+        kind: ChatType::Channel,
+        ..ops_chat()
+    });
+    let watcher = watcher(&s, Some(ALERTS)).await;
+    let daemon = DaemonController::new(watcher.clone());
+    cycle(&watcher, &daemon).await;
+    s.tg.post([text_message(CHAT, 4, DAY + 3600, "deploy failed again")]);
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    cycle(&watcher, &daemon).await;
+
+    assert_eq!(
+        s.tg.sent_messages(),
+        vec![(
+            ALERTS,
+            "[ALERT] Keyword 'deploy failed' found in chat 'Ops': deploy failed again".to_string()
+        )]
+    );
+}
+
+#[tokio::test]
+async fn chat_resolved_by_link_is_stored_and_synced() {
+    const NEWS: i64 = -1_000_000_000_077;
+    let s = setup("test_scenario_resolve_link").await;
+    s.tg.add_public_chat(Chat {
+        id: NEWS,
+        title: "Rust News".to_string(),
+        username: Some("rust_news".to_string()),
+        kind: ChatType::Channel,
+        ..ops_chat()
+    });
+    s.tg.post([text_message(NEWS, 1, DAY, "Rust 2.0 released")]);
+
+    let chat = s
+        .sync
+        .resolve_chat("https://t.me/rust_news/1")
+        .await
+        .unwrap();
+    assert_eq!(chat.id, NEWS);
+    let known = s.repo.get_known_chats().await.unwrap();
+    assert!(known.iter().any(|c| c.id == NEWS && c.title == "Rust News"));
+    let stats = s
+        .sync
+        .sync_chat(NEWS, 10, &MediaFilter::none(), None)
+        .await
+        .unwrap();
+    assert_eq!(stats.messages_synced, 1);
+
+    let err = s.sync.resolve_chat("t.me/+AbCdEf").await.unwrap_err();
+    assert!(err.to_string().contains("private invite link"), "{}", err);
+    assert!(s.sync.resolve_chat("@nobody_here").await.is_err());
+}
@@ -0,0 +1,50 @@
+//! A year of synthetic history through the SQLite repository.
+
+use std::path::Path;
+use tg_sync::adapters::persistence::sqlite_repo::SqliteRepo;
+use tg_sync::domain::Granularity;
+use tg_sync::ports::{AnalysisLogPort, ArchiveAuditPort, RepoPort};
+use tg_sync::testing::synthetic::{SyntheticMessages, SyntheticSpec};
+
+#[tokio::test]
+async fn year_of_messages_round_trips() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("target")
+        .join("test_synthetic_archive");
+    let _ = std::fs::remove_dir_all(&dir);
+    let repo = SqliteRepo::connect(&dir).await.unwrap();
+    let spec = SyntheticSpec {
+        count: 5_000,
+        media_ratio: 0.2,
+        ..SyntheticSpec::default()
+    };
+
+    let messages: Vec<_> = SyntheticMessages::new(spec.clone()).collect();
+    for batch in messages.chunks(1_000) {
+        repo.save_messages(spec.chat_id, batch).await.unwrap();
+    }
+
+    let newest = repo.get_messages(spec.chat_id, 10, 0).await.unwrap();
+    assert_eq!(newest[0].id, 5_000);
+    assert_eq!(newest[0].text, messages[4_999].text);
+    let oldest = repo.get_messages(spec.chat_id, 10, 4_995).await.unwrap();
+    assert_eq!(oldest.last().unwrap().id, 1);
+
+    let weeks = repo
+        .get_periods(spec.chat_id, Granularity::Week)
+        .await
+        .unwrap();
+    assert!((52..=54).contains(&weeks.len()), "weeks {}", weeks.len());
+    let mut grouped = 0;
+    for week in &weeks {
+        grouped += repo
+            .get_messages_for_week(spec.chat_id, Granularity::Week, week)
+            .await
+            .unwrap()
+            .len();
+    }
+    assert_eq!(grouped, 5_000);
+
+    let with_media = messages.iter().filter(|m| m.media.is_some()).count() as u64;
+    assert_eq!(repo.count_media_references().await.unwrap(), with_media);
+}